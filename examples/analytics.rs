@@ -0,0 +1,147 @@
+//! Mini analytics job over a `MineDb`: loads page view events into a table, analyzes it, and sums up
+//! views and revenue per page in one scan. There is no SQL layer yet, so the job evaluates its filter
+//! and aggregates with the same `Value` operations an expression would use.
+//!
+//! `cargo run --example analytics -- 5000`
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::path::Path;
+
+use minedb::catalog::TableOid;
+use minedb::db::MineDb;
+use minedb::storage::table::schema::{Column, DataType, Schema};
+use minedb::storage::table::value::{ArithmeticOp, CompareOp, Value};
+
+pub const PAGES: [&str; 5] = ["/", "/docs", "/pricing", "/blog", "/signup"];
+const DEFAULT_EVENTS: usize = 5000;
+
+/// Views and revenue of one page, revenue in cents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSummary {
+    pub views: u64,
+    pub revenue: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub events: u64,
+    /// Estimated by the statistics of the last analyze.
+    pub distinct_users: u64,
+    pub pages: BTreeMap<String, PageSummary>,
+}
+
+fn events_schema() -> Schema {
+    Schema::new(vec![
+        Column::new("user_id", DataType::Integer),
+        Column::new("page", DataType::Varchar(16)),
+        Column::new("amount", DataType::Decimal(2)),
+    ])
+}
+
+/// Event `i` of the synthetic load: every third one a purchase of up to 9.99.
+pub fn event(i: usize) -> (i32, &'static str, i64) {
+    let amount = if i % 3 == 0 { (i % 1000) as i64 } else { 0 };
+    ((i * 7919 % 97) as i32, PAGES[i % PAGES.len()], amount)
+}
+
+pub fn load_events(db: &mut MineDb, n: usize) -> io::Result<TableOid> {
+    let oid = db.catalog_mut().create_table("events", events_schema())?.oid();
+    for i in 0..n {
+        let (user_id, page, amount) = event(i);
+        db.catalog_mut().insert_values(oid, &["user_id", "page", "amount"],
+                                       &[Value::Integer(user_id), Value::Varchar(page.to_string()), Value::Decimal(amount, 2)])?;
+    }
+    db.flush()?;
+    Ok(oid)
+}
+
+/// Roughly `SELECT page, count(*), sum(amount) FILTER (WHERE amount > 0) FROM events GROUP BY page`.
+pub fn run_job(db: &mut MineDb) -> io::Result<Report> {
+    let statistics = db.catalog_mut().analyze("events")?;
+    let table = db.catalog().get_table("events")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No events table to analyze."))?;
+    let schema = table.schema();
+
+    let mut pages: BTreeMap<String, PageSummary> = BTreeMap::new();
+    for entry in table.scan() {
+        let (_, tuple) = entry?;
+        let page = match tuple.get_value(schema, 1)? {
+            Value::Varchar(page) => page,
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Page {} is no varchar.", other))),
+        };
+        let amount = tuple.get_value(schema, 2)?;
+        let summary = pages.entry(page).or_insert(PageSummary { views: 0, revenue: Value::Decimal(0, 2) });
+        summary.views += 1;
+        if amount.evaluate(CompareOp::Gt, &Value::Decimal(0, 2))?.is_true() {
+            summary.revenue = summary.revenue.arithmetic(ArithmeticOp::Add, &amount)?;
+        }
+    }
+
+    Ok(Report {
+        events: statistics.row_count(),
+        distinct_users: statistics.column(0).map(|column| column.distinct_count()).unwrap_or(0),
+        pages,
+    })
+}
+
+/// Loads `n` events into a new database at `path` and runs the job over it.
+pub fn run(path: &Path, n: usize) -> io::Result<Report> {
+    let mut db = MineDb::open(path)?;
+    load_events(&mut db, n)?;
+    run_job(&mut db)
+}
+
+fn main() -> io::Result<()> {
+    let n = match env::args().nth(1) {
+        Some(n) => n.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Number of events expected."))?,
+        None => DEFAULT_EVENTS,
+    };
+    let dir = tempfile::tempdir()?;
+    let report = run(&dir.path().join("analytics.db"), n)?;
+
+    println!("{} events from about {} users", report.events, report.distinct_users);
+    for (page, summary) in &report.pages {
+        println!("{:10} {:6} views {:>12}", page, summary.views, summary.revenue.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use minedb::db::MineDb;
+    use minedb::storage::table::value::Value;
+
+    use super::{event, load_events, run_job};
+
+    #[test]
+    fn should_sum_views_and_revenue_per_page_and_keep_them_when_reopened() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("analytics.db");
+        let mut expected: BTreeMap<String, (u64, i64)> = BTreeMap::new();
+        for i in 0..1200 {
+            let (_, page, amount) = event(i);
+            let (views, revenue) = expected.entry(page.to_string()).or_default();
+            *views += 1;
+            *revenue += amount;
+        }
+        load_events(&mut MineDb::open(&path).unwrap(), 1200).unwrap();
+
+        // when
+        let report = run_job(&mut MineDb::open(&path).unwrap()).unwrap();
+
+        // then
+        assert_eq!(report.events, 1200);
+        assert!((90..=104).contains(&report.distinct_users));
+        let summaries: BTreeMap<String, (u64, Value)> = report.pages.into_iter()
+            .map(|(page, summary)| (page, (summary.views, summary.revenue)))
+            .collect();
+        let expected: BTreeMap<String, (u64, Value)> = expected.into_iter()
+            .map(|(page, (views, revenue))| (page, (views, Value::Decimal(revenue, 2))))
+            .collect();
+        assert_eq!(summaries, expected);
+    }
+}
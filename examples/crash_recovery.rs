@@ -0,0 +1,149 @@
+//! Crash recovery demo: a writer process appends numbered rows to a `MineDb`, flushing after every
+//! batch, and is killed with SIGKILL partway through. Reopening the file finds every row of the
+//! batches reported flushed, and never part of a batch, as flushes are staged in the double write
+//! file first and put back in place when the file is opened.
+//!
+//! `cargo run --example crash_recovery -- 500`
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use minedb::db::{MineDb, MineDbOptions};
+use minedb::storage::disk::disk_manager::FileDiskOptions;
+use minedb::storage::table::schema::{Column, DataType, Schema};
+use minedb::storage::table::value::Value;
+
+/// Rows written between two flushes.
+pub const BATCH_ROWS: usize = 10;
+/// Few enough rows for the pool to hold every page, so no page is written before its batch is flushed.
+pub const MAX_ROWS: usize = 2000;
+const DEFAULT_KILL_AFTER: usize = 500;
+
+/// What the writer reported flushed before it was killed, and what the file held after.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    pub flushed: usize,
+    pub recovered: usize,
+}
+
+fn options() -> MineDbOptions {
+    MineDbOptions::default().with_disk(FileDiskOptions { double_write: true, ..FileDiskOptions::default() })
+}
+
+/// Appends rows numbered from 0 to a new database at `path`, printing `flushed <rows>` once a batch
+/// is durable, then `done` after `MAX_ROWS`, and waits to be killed.
+pub fn write_until_killed(path: &Path) -> io::Result<()> {
+    let mut db = MineDb::open_with_options(path, options())?;
+    let oid = db.catalog_mut().create_table("rows", Schema::new(vec![Column::new("id", DataType::BigInt)]))?.oid();
+    db.flush()?;
+
+    let stdout = io::stdout();
+    for batch_start in (0..MAX_ROWS).step_by(BATCH_ROWS) {
+        for id in batch_start..batch_start + BATCH_ROWS {
+            db.catalog_mut().insert_values(oid, &["id"], &[Value::BigInt(id as i64)])?;
+        }
+        db.flush()?;
+        writeln!(stdout.lock(), "flushed {}", batch_start + BATCH_ROWS)?;
+    }
+    writeln!(stdout.lock(), "done")?;
+    loop {
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Rows in the database at `path`, failing if they are not numbered from 0 without a gap or end in
+/// part of a batch.
+pub fn recover(path: &Path) -> io::Result<usize> {
+    let db = MineDb::open_with_options(path, options())?;
+    let table = match db.catalog().get_table("rows") {
+        Some(table) => table,
+        None => return Ok(0),
+    };
+    let mut ids = Vec::new();
+    for entry in table.scan() {
+        let (_, tuple) = entry?;
+        ids.push(tuple.get_value(table.schema(), 0)?);
+    }
+    ids.sort();
+
+    let numbered = ids.iter().enumerate().all(|(i, id)| *id == Value::BigInt(i as i64));
+    if !numbered || ids.len() % BATCH_ROWS != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} rows recovered, not whole batches numbered from 0.", ids.len())));
+    }
+    Ok(ids.len())
+}
+
+/// Spawns `writer`, which has to run `write_until_killed` on `path`, kills it with SIGKILL once it
+/// reported `kill_after` rows flushed, or all of them, and recovers the file.
+pub fn crash_and_recover(mut writer: Command, path: &Path, kill_after: usize) -> io::Result<Recovery> {
+    let mut child = writer.stdout(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Writer has no stdout."))?;
+
+    let mut flushed = 0;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if let Some(rows) = line.strip_prefix("flushed ") {
+            flushed = rows.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, line.clone()))?;
+        }
+        if flushed >= kill_after || line == "done" {
+            break;
+        }
+    }
+    child.kill()?;
+    child.wait()?;
+
+    Ok(Recovery { flushed, recovered: recover(path)? })
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 3 && args[1] == "write" {
+        return write_until_killed(Path::new(&args[2]));
+    }
+
+    let kill_after = match args.get(1) {
+        Some(rows) => rows.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Number of rows expected."))?,
+        None => DEFAULT_KILL_AFTER,
+    };
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("crash.db");
+    let mut writer = Command::new(env::current_exe()?);
+    writer.arg("write").arg(&path);
+
+    let recovery = crash_and_recover(writer, &path, kill_after)?;
+    println!("killed the writer after {} rows were flushed, {} rows recovered", recovery.flushed, recovery.recovered);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use minedb::db::MineDb;
+    use minedb::storage::table::schema::{Column, DataType, Schema};
+    use minedb::storage::table::value::Value;
+
+    use super::{options, recover};
+
+    #[test]
+    fn should_refuse_rows_ending_in_part_of_a_batch() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crash.db");
+        {
+            let mut db = MineDb::open_with_options(&path, options()).unwrap();
+            let oid = db.catalog_mut().create_table("rows", Schema::new(vec![Column::new("id", DataType::BigInt)])).unwrap().oid();
+            for id in 0..3 {
+                db.catalog_mut().insert_values(oid, &["id"], &[Value::BigInt(id)]).unwrap();
+            }
+        }
+
+        // when
+        let recovered = recover(&path);
+
+        // then
+        assert!(recovered.is_err());
+    }
+}
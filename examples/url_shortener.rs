@@ -0,0 +1,138 @@
+//! URL shortener keeping its codes in a `LinearProbeHashTable` over a data file.
+//!
+//! `cargo run --example url_shortener -- https://example.com/a https://example.com/b`
+use std::env;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use fasthash::xx;
+use serde::{Deserialize, Serialize};
+
+use minedb::buffer::buffer_pool_manager::BufferPoolManager;
+use minedb::buffer::replacer::ClockReplacer;
+use minedb::common::hash::hash;
+use minedb::common::ValueType;
+use minedb::container::hash::hash_table::HashTable;
+use minedb::container::hash::linear_probe_hash_table::LinearProbeHashTable;
+use minedb::storage::disk::disk_manager::FileDiskManager;
+
+/// Hash table entries have a fixed size, so longer URLs are refused.
+pub const MAX_URL_LEN: usize = 32;
+const CODE_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const CODE_LEN: u32 = 6;
+
+/// URL padded with zeroes to `MAX_URL_LEN` bytes.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Url([u8; MAX_URL_LEN]);
+
+impl ValueType for Url {}
+
+impl Url {
+    fn parse(url: &str) -> Option<Url> {
+        if url.is_empty() || url.len() > MAX_URL_LEN || url.contains('\0') {
+            return None;
+        }
+
+        let mut raw = [0u8; MAX_URL_LEN];
+        raw[..url.len()].copy_from_slice(url.as_bytes());
+        Some(Url(raw))
+    }
+
+    fn as_str(&self) -> &str {
+        let len = self.0.iter().position(|b| *b == 0).unwrap_or(MAX_URL_LEN);
+        std::str::from_utf8(&self.0[..len]).unwrap()
+    }
+}
+
+pub fn encode_code(mut code: u64) -> String {
+    (0..CODE_LEN)
+        .map(|_| {
+            let c = CODE_ALPHABET[(code % CODE_ALPHABET.len() as u64) as usize] as char;
+            code /= CODE_ALPHABET.len() as u64;
+            c
+        })
+        .collect()
+}
+
+pub fn decode_code(code: &str) -> Option<u64> {
+    code.bytes().rev().try_fold(0u64, |acc, c| {
+        CODE_ALPHABET.iter()
+            .position(|a| *a == c)
+            .map(|digit| acc * CODE_ALPHABET.len() as u64 + digit as u64)
+    })
+}
+
+pub struct Shortener {
+    codes: LinearProbeHashTable<u64, Url>,
+}
+
+impl Shortener {
+    pub fn new(bpm: Arc<Mutex<BufferPoolManager>>) -> io::Result<Shortener> {
+        Ok(Shortener { codes: LinearProbeHashTable::new(16, bpm, hash)? })
+    }
+
+    /// The same URL always gets the same code.
+    pub fn shorten(&mut self, url: &str) -> io::Result<String> {
+        let url = Url::parse(url)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URL is empty or too long."))?;
+        let code = xx::hash64(url.as_str()) % (CODE_ALPHABET.len() as u64).pow(CODE_LEN);
+        self.codes.insert(&code, &url)?;
+        Ok(encode_code(code))
+    }
+
+    /// Two URLs sharing a code both stay stored, and the first one inserted wins.
+    pub fn expand(&mut self, code: &str) -> io::Result<Option<String>> {
+        let code = match decode_code(code) {
+            Some(code) => code,
+            None => return Ok(None),
+        };
+        Ok(self.codes.get_value(&code)?.first().map(|url| url.as_str().to_string()))
+    }
+}
+
+fn main() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let disk_manager = FileDiskManager::new(&dir.path().join("urls.data"))?;
+    let bpm = BufferPoolManager::new(16, Box::new(ClockReplacer::new(16)), Box::new(disk_manager));
+    let mut shortener = Shortener::new(Arc::new(Mutex::new(bpm)))?;
+
+    for url in env::args().skip(1) {
+        let code = shortener.shorten(&url)?;
+        println!("{} -> {} -> {}", url, code, shortener.expand(&code)?.unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use minedb::buffer::buffer_pool_manager::BufferPoolManager;
+    use minedb::buffer::replacer::ClockReplacer;
+    use minedb::storage::disk::disk_manager::FileDiskManager;
+
+    use super::{decode_code, encode_code, Shortener, MAX_URL_LEN};
+
+    #[test]
+    fn should_expand_codes_of_shortened_urls() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let disk_manager = FileDiskManager::new(&dir.path().join("urls.data")).unwrap();
+        let bpm = BufferPoolManager::new(16, Box::new(ClockReplacer::new(16)), Box::new(disk_manager));
+        let mut shortener = Shortener::new(Arc::new(Mutex::new(bpm))).unwrap();
+        let urls: Vec<String> = (0..300).map(|i| format!("https://minedb.dev/{}", i)).collect();
+
+        // when
+        let codes: Vec<String> = urls.iter().map(|url| shortener.shorten(url).unwrap()).collect();
+
+        // then
+        for (url, code) in urls.iter().zip(codes.iter()) {
+            assert_eq!(shortener.expand(code).unwrap().as_ref(), Some(url));
+        }
+        assert_eq!(shortener.shorten(&urls[0]).unwrap(), codes[0]);
+        assert!(shortener.shorten(&"x".repeat(MAX_URL_LEN + 1)).is_err());
+        assert_eq!(shortener.expand("!!!!!!").unwrap(), None);
+        assert_eq!(decode_code(&encode_code(123_456_789)), Some(123_456_789));
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::common::error::cause_of;
+use crate::storage::page::page::PageId;
+
+/// Failures of the buffer pool, returned from every pool method so callers can match on the cause.
+#[derive(Debug)]
+pub enum BufferPoolError {
+    OutOfFrames,
+    PagePinned(PageId),
+    PageNotFound(PageId),
+    PageNotPinned(PageId),
+    Io(io::Error),
+}
+
+impl BufferPoolError {
+    pub fn from_io(err: &io::Error) -> Option<&BufferPoolError> {
+        cause_of(err)
+    }
+}
+
+impl Display for BufferPoolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferPoolError::OutOfFrames => write!(f, "Out of memory to allocate page."),
+            BufferPoolError::PagePinned(_) => write!(f, "Cannot delete page that is in use."),
+            BufferPoolError::PageNotFound(pid) => write!(f, "Page {} not found in buffer pool.", pid),
+            BufferPoolError::PageNotPinned(pid) => write!(f, "Page {} is not pinned.", pid),
+            BufferPoolError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for BufferPoolError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BufferPoolError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BufferPoolError {
+    fn from(e: io::Error) -> Self {
+        BufferPoolError::Io(e)
+    }
+}
+
+/// Disk failures are handed back as they were, so `DiskError::from_io` still works on them.
+impl From<BufferPoolError> for io::Error {
+    fn from(e: BufferPoolError) -> Self {
+        match e {
+            BufferPoolError::Io(inner) => inner,
+            _ => io::Error::other(e),
+        }
+    }
+}
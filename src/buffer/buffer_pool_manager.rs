@@ -1,14 +1,21 @@
-use std::collections::HashMap;
-use std::io;
-use std::io::{Error, ErrorKind};
-use std::sync::RwLock;
+use std::ops::Range;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
 
 use crossbeam::queue::ArrayQueue;
 use dashmap::DashMap;
 
+use crate::buffer::buffer_pool_error::BufferPoolError;
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::buffer::pin_diagnostics::{DroppedWriteBack, PinDiagnostics, PinHolder, PinnedPage, UNTAGGED_PIN};
 use crate::buffer::replacer::{ClockReplacer, Replacer};
+use crate::maintenance::activity::ActivityMonitor;
 use crate::storage::disk::disk_manager::*;
 use crate::storage::page::page::*;
+use crate::storage::page::page_serde::{upgrade_page, PageSerde};
+
+/// Pages a sequential scan reads ahead of the page it is on, until changed by `set_read_ahead_depth`.
+pub const DEFAULT_READ_AHEAD_DEPTH: usize = 8;
 
 type FrameId = usize;
 pub struct BufferPoolManager {
@@ -16,7 +23,13 @@ pub struct BufferPoolManager {
     free_list: ArrayQueue<FrameId>,
     buffer_pool: Vec<RwLock<Page>>,
     replacer: Box<dyn Replacer>,
-    disk_manager: Box<dyn DiskManager>
+    disk_manager: Box<dyn DiskManager>,
+    activity: Option<Arc<ActivityMonitor>>,
+    pin_holders: Vec<Vec<PinHolder>>,
+    pin_deadline: Option<Duration>,
+    read_ahead_depth: usize,
+    last_exhaustion: Option<PinDiagnostics>,
+    last_dropped_write_back: Option<DroppedWriteBack>
 }
 
 impl BufferPoolManager {
@@ -26,17 +39,40 @@ impl BufferPoolManager {
             free_list: BufferPoolManager::build_full_free_list(pool_size),
             buffer_pool: BufferPoolManager::build_empty_page_pool(pool_size),
             replacer: Box::new(ClockReplacer::new(pool_size)),
-            disk_manager: Box::new(FakeDiskManager::new())
+            disk_manager: Box::new(FakeDiskManager::new()),
+            activity: None,
+            pin_holders: vec![Vec::new(); pool_size],
+            pin_deadline: None,
+            read_ahead_depth: DEFAULT_READ_AHEAD_DEPTH,
+            last_exhaustion: None,
+            last_dropped_write_back: None
         }
     }
 
-    fn new(pool_size: usize, replacer: Box<dyn Replacer>, disk_manager: Box<dyn DiskManager>) -> BufferPoolManager {
+    pub fn new(pool_size: usize, replacer: Box<dyn Replacer>, disk_manager: Box<dyn DiskManager>) -> BufferPoolManager {
         BufferPoolManager {
             page_table: DashMap::new(),
             free_list: BufferPoolManager::build_full_free_list(pool_size),
             buffer_pool: BufferPoolManager::build_empty_page_pool(pool_size),
             replacer,
-            disk_manager
+            disk_manager,
+            activity: None,
+            pin_holders: vec![Vec::new(); pool_size],
+            pin_deadline: None,
+            read_ahead_depth: DEFAULT_READ_AHEAD_DEPTH,
+            last_exhaustion: None,
+            last_dropped_write_back: None
+        }
+    }
+
+    /// Page fetches and allocations are counted as foreground activity, e.g. for the maintenance scheduler.
+    pub fn set_activity_monitor(&mut self, monitor: Arc<ActivityMonitor>) {
+        self.activity = Some(monitor);
+    }
+
+    fn record_activity(&self) {
+        if let Some(monitor) = &self.activity {
+            monitor.record_op();
         }
     }
 
@@ -51,7 +87,7 @@ impl BufferPoolManager {
     fn build_empty_page_pool(pool_size: usize) -> Vec<RwLock<Page>> {
         let mut bf = Vec::with_capacity(pool_size);
         for _ in 0..pool_size {
-            bf.push(RwLock::new(EMPTY_PAGE));
+            bf.push(RwLock::new(Page::new(INVALID_PAGE_ID)));
         }
         bf
     }
@@ -63,10 +99,17 @@ impl BufferPoolManager {
     // 2.     If R is dirty, write it back to the disk.
     // 3.     Delete R from the page table and insert P.
     // 4.     Update P's metadata, read in the page content from disk, and then return a pointer to P.
-    pub fn fetch_page(&mut self, pid: PageId) -> io::Result<&RwLock<Page>> {
+    pub fn fetch_page(&mut self, pid: PageId) -> Result<&RwLock<Page>, BufferPoolError> {
+        self.fetch_page_tagged(pid, UNTAGGED_PIN)
+    }
+
+    /// Like `fetch_page`, recording `tag` as the holder of the pin for `pin_diagnostics`.
+    pub fn fetch_page_tagged(&mut self, pid: PageId, tag: &'static str) -> Result<&RwLock<Page>, BufferPoolError> {
+        self.record_activity();
         if self.page_table.contains_key(&pid) {
             let fid = self.get_exist_frame(pid);
             self.replacer.pin(fid);
+            self.pin_holders[fid].push(PinHolder { tag, since: Instant::now() });
             let p = &self.buffer_pool[fid];
             let mut guard = p.write().unwrap();
             guard.pin();
@@ -74,118 +117,370 @@ impl BufferPoolManager {
         }
 
         let fid = self.get_available_frame()?;
-        Ok(self.update_page(fid, pid, false))
+        self.update_page(fid, pid, false, tag)
     }
 
     fn get_exist_frame(&self, pid: PageId) -> FrameId {
         *self.page_table.get(&pid).unwrap()
     }
 
-    fn get_available_frame(&mut self) -> io::Result<FrameId> {
+    fn get_available_frame(&mut self) -> Result<FrameId, BufferPoolError> {
         match self.free_list.pop() {
             Some(frame_id) => Ok(frame_id),
-            None => {
-                return if let Some(vic_fid) = (&mut self.replacer).victim() {
-                    Ok(vic_fid)
-                } else {
-                    Err(Error::new(ErrorKind::Other, "Out of memory to allocate page."))
+            None => match self.replacer.victim() {
+                Some(vic_fid) => Ok(vic_fid),
+                None => {
+                    self.last_exhaustion = Some(self.pin_diagnostics());
+                    Err(BufferPoolError::OutOfFrames)
                 }
             }
         }
     }
 
-    fn update_page(&mut self, fid: FrameId, new_pid: PageId, new_page: bool) -> &RwLock<Page> {
-        self.replacer.pin(fid);
+    pub fn pin_diagnostics(&self) -> PinDiagnostics {
+        let mut pinned: Vec<PinnedPage> = self.buffer_pool.iter().enumerate()
+            .filter_map(|(fid, frame)| {
+                let page = frame.read().unwrap();
+                if page.get_pin_count() == 0 {
+                    return None
+                }
+                Some(PinnedPage {
+                    page_id: page.get_id(),
+                    frame_id: fid,
+                    pin_count: page.get_pin_count(),
+                    holders: self.pin_holders[fid].clone(),
+                })
+            })
+            .collect();
+        pinned.sort_by_key(|p| p.page_id);
+
+        PinDiagnostics {
+            captured_at: Instant::now(),
+            pool_size: self.buffer_pool.len(),
+            free_frames: self.free_list.len(),
+            evictable_frames: self.replacer.size(),
+            pinned,
+        }
+    }
+
+    /// Diagnostics captured the last time a page could not get a frame because every frame was pinned.
+    pub fn last_exhaustion_diagnostics(&self) -> Option<&PinDiagnostics> {
+        self.last_exhaustion.as_ref()
+    }
+
+    /// The last page guard dropped without `release` that failed to write its page back.
+    pub fn last_dropped_write_back(&self) -> Option<&DroppedWriteBack> {
+        self.last_dropped_write_back.as_ref()
+    }
+
+    pub(crate) fn record_dropped_write_back(&mut self, failure: DroppedWriteBack) {
+        self.last_dropped_write_back = Some(failure);
+    }
+
+    pub fn set_pin_deadline(&mut self, deadline: Option<Duration>) {
+        self.pin_deadline = deadline;
+    }
+
+    /// Pages pinned for longer than the pin deadline, likely leaked pins. Empty without a deadline.
+    pub fn pins_past_deadline(&self) -> Vec<PinnedPage> {
+        match self.pin_deadline {
+            Some(deadline) => {
+                let diagnostics = self.pin_diagnostics();
+                diagnostics.past_deadline(deadline).into_iter().cloned().collect()
+            },
+            None => Vec::new()
+        }
+    }
 
-        let page = &self.buffer_pool[fid];
-        let mut page_guard = page.write().unwrap();
+    // A failed write-back keeps the old page in its frame; a failed read releases the frame.
+    fn update_page(&mut self, fid: FrameId, new_pid: PageId, new_page: bool, tag: &'static str) -> Result<&RwLock<Page>, BufferPoolError> {
+        let mut page_guard = self.buffer_pool[fid].write().unwrap();
         if page_guard.is_dirty() {
-            self.disk_manager.write_page(page_guard.get_id(), page_guard.get_data()).unwrap();
+            let old_pid = page_guard.get_id();
+            let written = self.disk_manager.write_page(old_pid, page_guard.get_data())
+                .with_context(|| ErrorContext::new("evict_page").page(old_pid).frame(fid));
+            if let Err(e) = written {
+                drop(page_guard);
+                self.release_unused_frame(fid);
+                return Err(e.into())
+            }
             page_guard.set_dirty(false);
         }
 
         self.page_table.remove(&page_guard.get_id());
-        self.page_table.insert(new_pid, fid);
+        if !new_page {
+            let read = self.disk_manager.read_page(new_pid, page_guard.get_data_mut())
+                .with_context(|| ErrorContext::new("fetch_page").page(new_pid).frame(fid));
+            if let Err(e) = read {
+                page_guard.set_id(INVALID_PAGE_ID);
+                self.free_list.push(fid).unwrap();
+                return Err(e.into())
+            }
+        }
 
+        self.replacer.pin(fid);
+        self.page_table.insert(new_pid, fid);
+        self.pin_holders[fid].push(PinHolder { tag, since: Instant::now() });
         page_guard.set_id(new_pid);
         page_guard.pin();
 
-        if !new_page {
-            self.disk_manager.read_page(new_pid, page_guard.get_data_mut()).unwrap();
+        Ok(&self.buffer_pool[fid])
+    }
+
+    /// Dirty flag is sticky: unpinning clean never clears an earlier dirty unpin.
+    pub fn unpin_page(&mut self, pid: PageId, is_dirty: bool) -> Result<(), BufferPoolError> {
+        let fid = *self.page_table.get(&pid).ok_or(BufferPoolError::PageNotFound(pid))?;
+        let mut page_guard = self.buffer_pool[fid].write().unwrap();
+        if !page_guard.unpin() {
+            return Err(BufferPoolError::PageNotPinned(pid))
+        }
+        self.pin_holders[fid].pop();
+
+        if is_dirty {
+            page_guard.set_dirty(true);
+        }
+        if page_guard.get_pin_count() == 0 {
+            self.replacer.unpin(fid);
         }
+        Ok(())
+    }
 
-        page
+    /// Frame of a page already pinned by the caller, taking no pin of its own.
+    pub(crate) fn pinned_frame(&self, pid: PageId) -> Result<&RwLock<Page>, BufferPoolError> {
+        let fid = *self.page_table.get(&pid).ok_or(BufferPoolError::PageNotFound(pid))?;
+        Ok(&self.buffer_pool[fid])
     }
 
-    pub fn unpin_page(&mut self, pid: PageId, is_dirty: bool) -> bool {
-        match self.page_table.get(&pid) {
-            Some(fid) => {
-                let page = &self.buffer_pool[*fid];
-                let mut page_guard = page.write().unwrap();
-                page_guard.unpin();
-                page_guard.set_dirty(is_dirty);
-                self.replacer.unpin(*fid);
-                true
-            },
-            None => {false}
+    pub fn pin_count_of(&self, pid: PageId) -> Option<u64> {
+        self.page_table.get(&pid)
+            .map(|fid| self.buffer_pool[*fid].read().unwrap().get_pin_count())
+    }
+
+    /// Writes the page back and syncs the disk manager, so the page survives a crash once this returns.
+    pub fn flush_page(&mut self, pid: PageId) -> Result<(), BufferPoolError> {
+        let fid = *self.page_table.get(&pid).ok_or(BufferPoolError::PageNotFound(pid))?;
+        let mut page_guard = self.buffer_pool[fid].write().unwrap();
+        let disk_manager = &mut self.disk_manager;
+        disk_manager.write_page(pid, page_guard.get_data())
+            .and_then(|_| disk_manager.sync())
+            .with_context(|| ErrorContext::new("flush_page").page(pid).frame(fid))?;
+        page_guard.set_dirty(false);
+        Ok(())
+    }
+
+    /// Writes back every dirty page in page id order, so the disk manager can coalesce neighbours,
+    /// then syncs once. Returns how many pages were written; with none dirty nothing is synced.
+    pub fn flush_all_pages(&mut self) -> Result<usize, BufferPoolError> {
+        let mut dirty_pages: Vec<(PageId, RwLockWriteGuard<Page>)> = self.buffer_pool.iter()
+            .map(|frame| frame.write().unwrap())
+            .filter(|page| page.is_dirty() && page.get_id() != INVALID_PAGE_ID)
+            .map(|page| (page.get_id(), page))
+            .collect();
+        dirty_pages.sort_by_key(|(pid, _)| *pid);
+        if dirty_pages.is_empty() {
+            return Ok(0)
         }
+
+        let pages: Vec<(PageId, &[u8])> = dirty_pages.iter().map(|(pid, page)| (*pid, page.get_data())).collect();
+        let disk_manager = &mut self.disk_manager;
+        disk_manager.write_pages(&pages)
+            .and_then(|_| disk_manager.sync())
+            .with_context(|| ErrorContext::new("flush_all_pages"))?;
+        drop(pages);
+
+        for (_, page) in dirty_pages.iter_mut() {
+            page.set_dirty(false);
+        }
+        Ok(dirty_pages.len())
     }
 
-    fn flush_page(&mut self, pid: PageId) -> bool {
-        return match self.page_table.get(&pid) {
-            Some(fid) => {
-                let page = &self.buffer_pool[*fid];
-                let page_guard = page.write().unwrap();
-                self.disk_manager.write_page(page_guard.get_id(), page_guard.get_data()).unwrap();
-                true
-            },
-            None => {false}
+    /// Moves pages toward the start of the data file, see `DiskManager::defragment`. Cached pages follow
+    /// to their new ids. Refused while any page is pinned, as its holder would keep using the old id.
+    pub fn defragment(&mut self) -> Result<PageRelocations, BufferPoolError> {
+        let cached: Vec<PageId> = self.page_table.iter().map(|entry| *entry.key()).collect();
+        if let Some(pid) = cached.into_iter().find(|pid| self.pin_count_of(*pid).unwrap_or(0) > 0) {
+            return Err(BufferPoolError::PagePinned(pid))
         }
+
+        self.flush_all_pages()?;
+        let relocations = self.disk_manager.defragment()
+            .with_context(|| ErrorContext::new("defragment"))?;
+        let moved_frames: Vec<(FrameId, PageId)> = relocations.iter()
+            .filter_map(|(old_pid, new_pid)| self.page_table.remove(&old_pid).map(|(_, fid)| (fid, new_pid)))
+            .collect();
+        for (fid, new_pid) in moved_frames {
+            if let Some((_, stale_fid)) = self.page_table.remove(&new_pid) {
+                self.buffer_pool[stale_fid].write().unwrap().set_id(INVALID_PAGE_ID);
+            }
+            self.buffer_pool[fid].write().unwrap().set_id(new_pid);
+            self.page_table.insert(new_pid, fid);
+        }
+        Ok(relocations)
     }
 
-    pub fn new_page(&mut self) -> io::Result<&RwLock<Page>> {
-        let fid = self.get_available_frame()?;
-        let pid = self.disk_manager.allocate_page()?;
-        Ok(self.update_page(fid, pid, true))
+    /// Snapshot stays unchanged while the live frame is modified or evicted, and leaves the page unpinned.
+    pub fn snapshot_page(&mut self, pid: PageId) -> Result<PageSnapshot, BufferPoolError> {
+        let snapshot = self.fetch_page(pid)?.read().unwrap().snapshot();
+        self.unpin_page(pid, false)?;
+        Ok(snapshot)
     }
 
-    fn delete_page(&mut self, pid: PageId) -> io::Result<bool> {
-        match self.page_table.get(&pid) {
-            Some(fid) => {
-                let page = &self.buffer_pool[*fid];
-                let page_guard = page.write().unwrap();
-                if page_guard.get_pin_count() != 0 {
-                    return Err(Error::new(ErrorKind::Other, "Cannot delete page that is in use."))
-                }
+    /// Fetches the page and decodes it as `T`, leaving it unpinned.
+    pub fn fetch_decoded<T: PageSerde>(&mut self, pid: PageId) -> Result<T, BufferPoolError> {
+        let decoded = T::from_page(self.fetch_page_tagged(pid, "fetch_decoded")?.read().unwrap().get_data());
+        self.unpin_page(pid, false)?;
+        Ok(decoded.with_context(|| ErrorContext::new("fetch_decoded").page(pid))?)
+    }
 
-                if page_guard.is_dirty() {
-                    self.disk_manager.write_page(page_guard.get_id(), page_guard.get_data()).unwrap();
-                }
-                self.free_list.push(*fid).unwrap();
-            },
-            None => {}
+    /// Encodes `page` over page `pid`, or over a new page if `None`, leaving it unpinned and dirty.
+    /// Returns the id of the page written.
+    pub fn write_encoded<T: PageSerde>(&mut self, pid: Option<PageId>, page: &T) -> Result<PageId, BufferPoolError> {
+        let (pid, encoded) = {
+            let mut frame = match pid {
+                Some(pid) => self.fetch_page_tagged(pid, "write_encoded")?.write().unwrap(),
+                None => self.new_page_tagged("write_encoded")?.write().unwrap(),
+            };
+            let encoded = page.to_page(frame.get_data_mut());
+            (frame.get_id(), encoded)
         };
-        self.page_table.remove(&pid);
+        self.unpin_page(pid, encoded.is_ok())?;
+        encoded.with_context(|| ErrorContext::new("write_encoded").page(pid))?;
+        Ok(pid)
+    }
+
+    /// Rewrites page `pid` in the current layout of `T` if stored in an older one, see `upgrade_page`.
+    /// The page is left unpinned, and dirty only if it was rewritten.
+    pub fn upgrade_page<T: PageSerde>(&mut self, pid: PageId) -> Result<bool, BufferPoolError> {
+        let upgraded = upgrade_page::<T>(self.fetch_page_tagged(pid, "upgrade_page")?.write().unwrap().get_data_mut());
+        self.unpin_page(pid, matches!(upgraded, Ok(true)))?;
+        Ok(upgraded.with_context(|| ErrorContext::new("upgrade_page").page(pid))?)
+    }
+
+    /// Pages a sequential scan like `TableHeap::iter` keeps read ahead of the page it is on, none at 0.
+    pub fn read_ahead_depth(&self) -> usize {
+        self.read_ahead_depth
+    }
+
+    pub fn set_read_ahead_depth(&mut self, read_ahead_depth: usize) {
+        self.read_ahead_depth = read_ahead_depth;
+    }
+
+    /// Sequential-access hint: reads pages a scan is about to fetch into free frames or frames of
+    /// evictable pages, leaving them unpinned. Pages already in the pool are skipped. As only a hint it
+    /// stops quietly once no frame is left or a read fails, the fetch itself then reports the failure.
+    /// Returns how many pages were read.
+    pub fn read_ahead(&mut self, pids: &[PageId]) -> usize {
+        // Pages read stay pinned until the end, so later ones cannot evict them.
+        let mut read = Vec::new();
+        for pid in pids {
+            if self.page_table.contains_key(pid) {
+                continue
+            }
+            let fid = match self.free_list.pop().or_else(|| self.replacer.victim()) {
+                Some(fid) => fid,
+                None => break
+            };
+            if self.update_page(fid, *pid, false, "read_ahead").is_err() {
+                break
+            }
+            read.push(*pid);
+        }
+        for pid in &read {
+            let _ = self.unpin_page(*pid, false);
+        }
+        read.len()
+    }
+
+    pub fn new_page(&mut self) -> Result<&RwLock<Page>, BufferPoolError> {
+        self.new_page_tagged(UNTAGGED_PIN)
+    }
+
+    pub fn new_page_tagged(&mut self, tag: &'static str) -> Result<&RwLock<Page>, BufferPoolError> {
+        self.allocate_new_page(None, tag)
+    }
+
+    /// Places the new page by `strategy`, e.g. near a sibling page to keep related pages clustered.
+    pub fn new_page_with(&mut self, strategy: AllocationStrategy) -> Result<&RwLock<Page>, BufferPoolError> {
+        self.allocate_new_page(Some(strategy), UNTAGGED_PIN)
+    }
+
+    /// Allocates `n` pages with consecutive ids, see `DiskManager::allocate_extent`. The pages are not
+    /// brought into the pool, fetch them to fill them.
+    pub fn new_extent(&mut self, n: usize) -> Result<Range<PageId>, BufferPoolError> {
+        self.record_activity();
+        Ok(self.disk_manager.allocate_extent(n).with_context(|| ErrorContext::new("new_extent"))?)
+    }
+
+    fn allocate_new_page(&mut self, strategy: Option<AllocationStrategy>, tag: &'static str) -> Result<&RwLock<Page>, BufferPoolError> {
+        self.record_activity();
+        let fid = self.get_available_frame()?;
+        let allocated = match strategy {
+            Some(strategy) => self.disk_manager.allocate_page_with(strategy),
+            None => self.disk_manager.allocate_page()
+        };
+        let pid = match allocated.with_context(|| ErrorContext::new("new_page").frame(fid)) {
+            Ok(pid) => pid,
+            Err(e) => {
+                self.release_unused_frame(fid);
+                return Err(e.into())
+            }
+        };
+        self.update_page(fid, pid, true, tag)
+    }
 
-        let done = self.disk_manager.deallocate_page(pid)?;
-        if !done {
-            return Ok(false)
+    // Gives back a frame taken by `get_available_frame` that ended up unused:
+    // a victim still holding its page returns to the replacer, anything else to the free list.
+    fn release_unused_frame(&mut self, fid: FrameId) {
+        let pid = self.buffer_pool[fid].read().unwrap().get_id();
+        if self.page_table.get(&pid).map(|f| *f) == Some(fid) {
+            self.replacer.unpin(fid);
+        } else {
+            self.free_list.push(fid).unwrap();
         }
+    }
+
+    /// Drops the page from the pool and deallocates it on disk. Fails with `PagePinned` while it is in use.
+    pub fn delete_page(&mut self, pid: PageId) -> Result<bool, BufferPoolError> {
+        if let Some(fid) = self.page_table.get(&pid).map(|fid| *fid) {
+            let mut page_guard = self.buffer_pool[fid].write().unwrap();
+            if page_guard.get_pin_count() != 0 {
+                return Err(BufferPoolError::PagePinned(pid))
+            }
 
-        Ok(true)
+            if page_guard.is_dirty() {
+                self.disk_manager.write_page(pid, page_guard.get_data())
+                    .with_context(|| ErrorContext::new("delete_page").page(pid).frame(fid))?;
+            }
+            page_guard.set_id(INVALID_PAGE_ID);
+            page_guard.set_dirty(false);
+            self.replacer.pin(fid);
+            self.free_list.push(fid).unwrap();
+        };
+        self.page_table.remove(&pid);
+
+        Ok(self.disk_manager.deallocate_page(pid).with_context(|| ErrorContext::new("delete_page").page(pid))?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::io::*;
+    use std::sync::Arc;
+    use std::time::Duration;
 
     use crossbeam::queue::ArrayQueue;
 
+    use crate::buffer::buffer_pool_error::BufferPoolError;
     use crate::buffer::buffer_pool_manager::{BufferPoolManager, FrameId};
+    use crate::common::error::{DbError, ErrorContext};
     use crate::buffer::replacer::ClockReplacer;
+    use crate::maintenance::activity::ActivityMonitor;
     use crate::storage::disk::disk_manager::*;
-    use crate::storage::page::page::PageId;
+    use crate::storage::page::free_space_map_page::FreeSpaceMapPage;
+    use crate::storage::page::overflow_page::OverflowPage;
+    use crate::storage::page::page::{PageId, PAGE_SIZE};
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_version::PageType;
 
     fn contains<T: Eq + Clone>(queue: &ArrayQueue<T>, item: T) -> bool {
         let size = queue.len();
@@ -300,8 +595,8 @@ mod tests {
         bpm.fetch_page(fake_id5).unwrap();
 
         // unpin some
-        bpm.unpin_page(fake_id2, true);
-        bpm.unpin_page(fake_id3, false);
+        bpm.unpin_page(fake_id2, true).unwrap();
+        bpm.unpin_page(fake_id3, false).unwrap();
 
         {
             // when (victim frame[2] => page3)
@@ -354,10 +649,54 @@ mod tests {
         let result = bpm.fetch_page(fake_id6);
 
         // then
-        assert!(result.is_err());
-        let error = result.err().unwrap();
-        assert_eq!(error.kind(), ErrorKind::Other);
-        assert_eq!(error.to_string(), "Out of memory to allocate page.");
+        assert!(matches!(result.err().unwrap(), BufferPoolError::OutOfFrames));
+    }
+
+    #[test]
+    fn should_capture_pin_diagnostics_when_all_frames_pinned() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(2);
+        let p1 = bpm.new_page_tagged("scan").unwrap().read().unwrap().get_id();
+        let p2 = bpm.new_page().unwrap().read().unwrap().get_id();
+        bpm.fetch_page_tagged(p1, "index").unwrap();
+        assert!(bpm.last_exhaustion_diagnostics().is_none());
+
+        // when
+        let result = bpm.new_page();
+
+        // then
+        assert!(matches!(result.err().unwrap(), BufferPoolError::OutOfFrames));
+        let diagnostics = bpm.last_exhaustion_diagnostics().unwrap();
+        assert_eq!(diagnostics.pool_size, 2);
+        assert_eq!(diagnostics.free_frames, 0);
+        assert_eq!(diagnostics.evictable_frames, 0);
+        assert_eq!(diagnostics.pinned.len(), 2);
+        assert_eq!(diagnostics.pinned[0].page_id, p1);
+        assert_eq!(diagnostics.pinned[0].pin_count, 2);
+        let tags: Vec<&str> = diagnostics.pinned[0].holders.iter().map(|h| h.tag).collect();
+        assert_eq!(tags, vec!["scan", "index"]);
+        assert_eq!(diagnostics.pinned[1].page_id, p2);
+        assert!(diagnostics.to_string().contains("holders [scan, index]"));
+    }
+
+    #[test]
+    fn should_report_pins_held_past_deadline() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(TEST_POOL_SIZE);
+        let leaked = bpm.new_page_tagged("leaky").unwrap().read().unwrap().get_id();
+        let released = bpm.new_page().unwrap().read().unwrap().get_id();
+        bpm.unpin_page(released, false).unwrap();
+        assert!(bpm.pins_past_deadline().is_empty());
+
+        // when
+        bpm.set_pin_deadline(Some(Duration::ZERO));
+        std::thread::sleep(Duration::from_millis(1));
+
+        // then
+        let overdue = bpm.pins_past_deadline();
+        assert_eq!(overdue.len(), 1);
+        assert_eq!(overdue[0].page_id, leaked);
+        assert_eq!(overdue[0].holders[0].tag, "leaky");
     }
 
     #[test]
@@ -377,8 +716,8 @@ mod tests {
             assert_eq!(p2.write().unwrap().get_pin_count(), 1);
         }
 
-        bpm.unpin_page(fake_id_1, false);
-        bpm.unpin_page(fake_id_2, true);
+        bpm.unpin_page(fake_id_1, false).unwrap();
+        bpm.unpin_page(fake_id_2, true).unwrap();
 
         // then
         assert_eq!(*bpm.page_table.get(&fake_id_1).unwrap(), fid_to_p1);
@@ -394,6 +733,62 @@ mod tests {
         assert!(p2.is_dirty());
     }
 
+    #[test]
+    fn should_fail_to_unpin_page_not_pinned_or_not_found() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(TEST_POOL_SIZE);
+        let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+        bpm.unpin_page(pid, false).unwrap();
+
+        // when
+        let not_pinned = bpm.unpin_page(pid, false);
+        let not_found = bpm.unpin_page(pid + 1, false);
+
+        // then
+        assert!(matches!(not_pinned.err().unwrap(), BufferPoolError::PageNotPinned(p) if p == pid));
+        assert!(matches!(not_found.err().unwrap(), BufferPoolError::PageNotFound(p) if p == pid + 1));
+        assert_eq!(bpm.pin_count_of(pid), Some(0));
+        assert_eq!(bpm.pin_count_of(pid + 1), None);
+    }
+
+    #[test]
+    fn should_keep_page_in_use_and_dirty_until_last_unpin() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(1);
+        let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+        bpm.fetch_page(pid).unwrap();
+
+        // when
+        bpm.unpin_page(pid, true).unwrap();
+        let other = bpm.new_page();
+
+        // then
+        assert!(matches!(other.err().unwrap(), BufferPoolError::OutOfFrames));
+        assert_eq!(bpm.pin_count_of(pid), Some(1));
+
+        // when
+        bpm.unpin_page(pid, false).unwrap();
+
+        // then
+        assert!(bpm.buffer_pool[0].read().unwrap().is_dirty());
+        assert!(bpm.new_page().is_ok());
+    }
+
+    #[test]
+    fn should_record_page_accesses_as_activity() {
+        // given
+        let monitor = Arc::new(ActivityMonitor::new());
+        let mut bpm = BufferPoolManager::new_default(TEST_POOL_SIZE);
+        bpm.set_activity_monitor(monitor.clone());
+
+        // when
+        let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+        bpm.fetch_page(pid).unwrap();
+
+        // then
+        assert_eq!(monitor.total_ops(), 2);
+    }
+
     #[test]
     fn should_flush_page() {
         // given
@@ -413,6 +808,11 @@ mod tests {
                 && page_data[2] == 3
             })
             .returning(move |_, _| Ok(()));
+        dm_mock
+            // then
+            .expect_sync()
+            .times(1)
+            .returning(move || Ok(()));
 
         let mut bpm = BufferPoolManager::new(
             TEST_POOL_SIZE,
@@ -428,7 +828,57 @@ mod tests {
             page_data[2] = 3;
         }
 
-        bpm.flush_page(fake_id_1);
+        bpm.flush_page(fake_id_1).unwrap();
+    }
+
+    #[test]
+    fn should_flush_all_dirty_pages_in_one_batch() {
+        // given
+        let mut dm_mock = MockDiskManager::new();
+        dm_mock
+            .expect_read_page()
+            .returning(move |_, _| Ok(()));
+        dm_mock
+            // then
+            .expect_write_pages()
+            .withf(|pages: &[(PageId, &[u8])]| {
+                pages.iter().map(|(pid, _)| *pid).collect::<Vec<PageId>>() == vec![2, 3, 5]
+                && pages.iter().all(|(pid, page_data)| page_data[0] == *pid as u8)
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+        dm_mock
+            .expect_sync()
+            .times(1)
+            .returning(|| Ok(()));
+
+        let mut bpm = BufferPoolManager::new(
+            TEST_POOL_SIZE,
+            Box::new(ClockReplacer::new(TEST_POOL_SIZE)),
+            Box::new(dm_mock));
+        for pid in [5, 1, 3, 2] {
+            bpm.fetch_page(pid).unwrap().write().unwrap().get_data_mut()[0] = pid as u8;
+            bpm.unpin_page(pid, pid != 1).unwrap();
+        }
+
+        // when
+        let flushed = bpm.flush_all_pages().unwrap();
+
+        // then
+        assert_eq!(flushed, 3);
+        assert_eq!(bpm.flush_all_pages().unwrap(), 0);
+    }
+
+    #[test]
+    fn should_fail_to_flush_page_not_in_pool() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(TEST_POOL_SIZE);
+
+        // when
+        let result = bpm.flush_page(1);
+
+        // then
+        assert!(matches!(result.err().unwrap(), BufferPoolError::PageNotFound(1)));
     }
 
     #[test]
@@ -473,10 +923,38 @@ mod tests {
         let result = bpm.new_page();
 
         // then
-        assert!(result.is_err());
-        let error = result.err().unwrap();
-        assert_eq!(error.kind(), ErrorKind::Other);
-        assert_eq!(error.to_string(), "Exceeded max page.");
+        match result.err().unwrap() {
+            BufferPoolError::Io(error) => {
+                assert_eq!(error.kind(), ErrorKind::Other);
+                assert_eq!(error.to_string(), "Exceeded max page. (in new_page frame 4)");
+                assert_eq!(DbError::from_io(&error).unwrap().context(), &[ErrorContext::new("new_page").frame(4)]);
+            },
+            other => panic!("unexpected error: {:?}", other)
+        }
+        assert_eq!(bpm.free_list.len(), TEST_POOL_SIZE);
+    }
+
+    #[test]
+    fn should_release_frame_when_disk_manager_cannot_read_page() {
+        // given
+        let fake_id: PageId = 1;
+        let mut dm_mock = MockDiskManager::new();
+        dm_mock
+            .expect_read_page()
+            .returning(move |_, _| Err(Error::new(ErrorKind::UnexpectedEof, "short read")));
+
+        let mut bpm = BufferPoolManager::new(
+            TEST_POOL_SIZE,
+            Box::new(ClockReplacer::new(TEST_POOL_SIZE)),
+            Box::new(dm_mock));
+
+        // when
+        let result = bpm.fetch_page(fake_id);
+
+        // then
+        assert!(matches!(result.err().unwrap(), BufferPoolError::Io(e) if e.kind() == ErrorKind::UnexpectedEof));
+        assert!(!bpm.page_table.contains_key(&fake_id));
+        assert_eq!(bpm.free_list.len(), TEST_POOL_SIZE);
     }
 
     #[test]
@@ -499,7 +977,7 @@ mod tests {
 
         // when
         bpm.new_page().unwrap();
-        bpm.unpin_page(fake_id_1, false);
+        bpm.unpin_page(fake_id_1, false).unwrap();
         let deleted = bpm.delete_page(fake_id_1);
 
         // then
@@ -531,7 +1009,7 @@ mod tests {
         let deleted = bpm.delete_page(fake_id_1);
 
         // then
-        assert!(deleted.is_err());
+        assert!(matches!(deleted.err().unwrap(), BufferPoolError::PagePinned(pid) if pid == fake_id_1));
         assert!(bpm.page_table.contains_key(&fake_id_1));
         assert!(!contains(&bpm.free_list, fid_to_p1));
     }
@@ -557,4 +1035,123 @@ mod tests {
         assert!(!deleted.unwrap());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn should_keep_snapshot_unchanged_when_page_modified() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(TEST_POOL_SIZE);
+        let pid = {
+            let mut page = bpm.new_page().unwrap().write().unwrap();
+            page.get_data_mut()[0] = 1;
+            page.get_id()
+        };
+        bpm.unpin_page(pid, true).unwrap();
+
+        // when
+        let snapshot = bpm.snapshot_page(pid).unwrap();
+        bpm.fetch_page(pid).unwrap().write().unwrap().get_data_mut()[0] = 2;
+
+        // then
+        assert_eq!(snapshot.get_id(), pid);
+        assert_eq!(snapshot.get_data()[0], 1);
+        assert_eq!(bpm.fetch_page(pid).unwrap().read().unwrap().get_data()[0], 2);
+    }
+
+    #[test]
+    fn should_snapshot_page_without_leaving_it_pinned() {
+        // given
+        let fake_id: PageId = 1;
+        let mut dm_mock = MockDiskManager::new();
+        dm_mock
+            .expect_read_page()
+            .times(1)
+            .returning(move |_, page_data: &mut [u8]| {
+                page_data[0] = 7;
+                Ok(())
+            });
+
+        let mut bpm = BufferPoolManager::new(
+            TEST_POOL_SIZE,
+            Box::new(ClockReplacer::new(TEST_POOL_SIZE)),
+            Box::new(dm_mock));
+
+        // when
+        let snapshot = bpm.snapshot_page(fake_id).unwrap();
+
+        // then
+        assert_eq!(snapshot.get_data()[0], 7);
+        let fid = *bpm.page_table.get(&fake_id).unwrap();
+        let page = bpm.buffer_pool[fid].read().unwrap();
+        assert_eq!(page.get_pin_count(), 0);
+        assert!(!page.is_dirty());
+    }
+
+    #[test]
+    fn should_write_encoded_page_and_fetch_it_decoded() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(TEST_POOL_SIZE);
+        let overflow_page = OverflowPage::new(vec![7; 100], Some(3)).unwrap();
+
+        // when
+        let pid = bpm.write_encoded(None, &overflow_page).unwrap();
+        let decoded: OverflowPage = bpm.fetch_decoded(pid).unwrap();
+
+        // then
+        assert_eq!(decoded.get_data(), &[7; 100][..]);
+        assert_eq!(decoded.get_next_page_id(), Some(3));
+        assert_eq!(bpm.pin_count_of(pid), Some(0));
+        let err: Error = bpm.fetch_decoded::<FreeSpaceMapPage>(pid).err().unwrap().into();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::WrongPageType { expected: PageType::FreeSpaceMap, found: 4 })));
+        assert_eq!(bpm.pin_count_of(pid), Some(0));
+    }
+
+    #[test]
+    fn should_defragment_and_keep_cached_pages_under_new_ids() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut fdm = FileDiskManager::new(&dir.path().join("defrag.data")).unwrap();
+        let pids: Vec<PageId> = (0..12).map(|_| fdm.allocate_page().unwrap()).collect();
+        for pid in &pids {
+            fdm.write_page(*pid, &[*pid as u8; PAGE_SIZE]).unwrap();
+        }
+        for pid in &pids[..10] {
+            fdm.deallocate_page(*pid).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(TEST_POOL_SIZE, Box::new(ClockReplacer::new(TEST_POOL_SIZE)), Box::new(fdm));
+        bpm.fetch_page(pids[11]).unwrap().write().unwrap().get_data_mut()[1] = 0xff;
+        assert!(matches!(bpm.defragment(), Err(BufferPoolError::PagePinned(pid)) if pid == pids[11]));
+        bpm.unpin_page(pids[11], true).unwrap();
+
+        // when
+        let relocations = bpm.defragment().unwrap();
+
+        // then
+        assert_eq!(relocations.len(), 2);
+        let new_pid = relocations.resolve(pids[11]);
+        assert_eq!(bpm.pin_count_of(new_pid), Some(0));
+        assert_eq!(bpm.pin_count_of(pids[11]), None);
+        let page = bpm.fetch_page(new_pid).unwrap().read().unwrap();
+        assert_eq!(page.get_id(), new_pid);
+        assert_eq!(page.get_data()[..2], [pids[11] as u8, 0xff]);
+    }
+
+    #[test]
+    fn should_read_ahead_evicted_pages_without_pinning_them() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(2);
+        let pids: Vec<PageId> = (0..4).map(|_| {
+            let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+            bpm.unpin_page(pid, true).unwrap();
+            pid
+        }).collect();
+        bpm.fetch_page(pids[3]).unwrap();
+
+        // when
+        let read = bpm.read_ahead(&[pids[3], pids[0], pids[1]]);
+
+        // then
+        assert_eq!(read, 1);
+        assert_eq!(bpm.pin_count_of(pids[0]), Some(0));
+        assert_eq!(bpm.pin_count_of(pids[1]), None);
+        assert_eq!(bpm.pin_count_of(pids[3]), Some(1));
+    }
+}
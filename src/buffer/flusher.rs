@@ -0,0 +1,61 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::maintenance::scheduler::MaintenanceTask;
+
+pub const FLUSH_TASK: &str = "flush";
+
+/// Writes back the dirty pages of the pool whenever the scheduler runs it, so fewer are left to write
+/// when they are evicted or the database is closed. Its interval follows `RuntimeOptions::flusher_interval`
+/// once bound through `RuntimeConfig::bind_maintenance`.
+pub struct FlushTask {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+}
+
+impl FlushTask {
+    pub fn new(bpm: Arc<Mutex<BufferPoolManager>>) -> FlushTask {
+        FlushTask { buffer_pool_manager: bpm }
+    }
+}
+
+impl MaintenanceTask for FlushTask {
+    fn name(&self) -> &str {
+        FLUSH_TASK
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        self.buffer_pool_manager.lock().unwrap().flush_all_pages()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::flusher::FlushTask;
+    use crate::maintenance::scheduler::MaintenanceTask;
+
+    #[test]
+    fn should_write_back_dirty_pages_when_run() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let pid = {
+            let mut bpm = bpm.lock().unwrap();
+            let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+            bpm.unpin_page(pid, true).unwrap();
+            pid
+        };
+        let mut task = FlushTask::new(bpm.clone());
+
+        // when
+        task.run().unwrap();
+
+        // then
+        let mut bpm = bpm.lock().unwrap();
+        assert_eq!(bpm.flush_all_pages().unwrap(), 0);
+        assert_eq!(bpm.pin_count_of(pid), Some(0));
+    }
+}
@@ -1,2 +1,6 @@
 pub mod replacer;
-pub mod buffer_pool_manager;
\ No newline at end of file
+pub mod buffer_pool_manager;
+pub mod buffer_pool_error;
+pub mod pin_diagnostics;
+pub mod page_guard;
+pub mod flusher;
@@ -0,0 +1,178 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::buffer::buffer_pool_error::BufferPoolError;
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::buffer::pin_diagnostics::DroppedWriteBack;
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+use crate::storage::page::hash_table_header_page::HashTableHeaderPage;
+use crate::storage::page::page::PageId;
+use crate::storage::page::page_serde::PageSerde;
+
+pub type HeaderPageGuard<'a> = PageGuard<'a, HashTableHeaderPage>;
+pub type BlockPageGuard<'a, K, V> = PageGuard<'a, HashTableBlockPage<K, V>>;
+
+/// A page decoded when pinned and kept pinned until the guard goes away. Changes made through the
+/// guard are encoded back into the frame and the page unpinned dirty; an untouched page is unpinned clean.
+///
+/// Call `release` to learn whether the page was written back. Dropping the guard writes it back too,
+/// but can only record a failure in `BufferPoolManager::last_dropped_write_back`.
+#[must_use = "call `release` to find out whether the page was written back"]
+pub struct PageGuard<'a, T: PageSerde> {
+    bpm: &'a mut BufferPoolManager,
+    pid: PageId,
+    tag: &'static str,
+    page: T,
+    dirty: bool,
+    released: bool,
+}
+
+impl<'a, T: PageSerde> PageGuard<'a, T> {
+    /// Pins page `pid` and decodes it, dropping the pin again if it cannot be decoded.
+    pub fn fetch(bpm: &'a mut BufferPoolManager, pid: PageId, tag: &'static str) -> Result<PageGuard<'a, T>, BufferPoolError> {
+        let decoded = T::from_page(bpm.fetch_page_tagged(pid, tag)?.read().unwrap().get_data());
+        if decoded.is_err() {
+            bpm.unpin_page(pid, false)?;
+        }
+        let page = decoded.with_context(|| ErrorContext::new(tag).page(pid))?;
+        Ok(PageGuard { bpm, pid, tag, page, dirty: false, released: false })
+    }
+
+    /// Pins a new page to hold `page`, written there on release like any changed page.
+    pub fn create(bpm: &'a mut BufferPoolManager, page: T, tag: &'static str) -> Result<PageGuard<'a, T>, BufferPoolError> {
+        let pid = bpm.new_page_tagged(tag)?.read().unwrap().get_id();
+        Ok(PageGuard { bpm, pid, tag, page, dirty: true, released: false })
+    }
+
+    pub fn get_page_id(&self) -> PageId {
+        self.pid
+    }
+
+    /// Writes the page back if changed and unpins it.
+    pub fn release(mut self) -> Result<(), BufferPoolError> {
+        self.write_back()
+    }
+
+    fn write_back(&mut self) -> Result<(), BufferPoolError> {
+        self.released = true;
+        let encoded = if self.dirty {
+            let frame = self.bpm.pinned_frame(self.pid)?;
+            self.page.to_page(frame.write().unwrap().get_data_mut())
+        } else {
+            Ok(())
+        };
+        self.bpm.unpin_page(self.pid, self.dirty && encoded.is_ok())?;
+        Ok(encoded.with_context(|| ErrorContext::new("page_guard.write_back").page(self.pid))?)
+    }
+}
+
+impl<'a, T: PageSerde> Deref for PageGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.page
+    }
+}
+
+/// Taking the page mutably counts as changing it.
+impl<'a, T: PageSerde> DerefMut for PageGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.page
+    }
+}
+
+impl<'a, T: PageSerde> Drop for PageGuard<'a, T> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        if let Err(e) = self.write_back() {
+            let failure = DroppedWriteBack { page_id: self.pid, tag: self.tag, error: e.to_string() };
+            self.bpm.record_dropped_write_back(failure);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::page_guard::{BlockPageGuard, PageGuard};
+    use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+    use crate::storage::page::hash_table_header_page::HashTableHeaderPage;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_serde::PageSerde;
+    use crate::storage::page::page_version::PageVersion;
+
+    struct Unencodable;
+
+    impl PageSerde for Unencodable {
+        const VERSION: PageVersion = 1;
+
+        fn to_page(&self, _page_data: &mut [u8]) -> io::Result<()> {
+            Err(PageError::PageDataTooShort { expected: 1, actual: 0 }.into())
+        }
+
+        fn from_page(_page_data: &[u8]) -> io::Result<Unencodable> {
+            Ok(Unencodable)
+        }
+    }
+
+    #[test]
+    fn should_write_back_changed_page_when_guard_dropped() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(10);
+        let block_pid = {
+            let mut block = BlockPageGuard::<u64, u64>::create(&mut bpm, HashTableBlockPage::new(), "test").unwrap();
+            block.insert(3, 1, 42).unwrap();
+            block.get_page_id()
+        };
+
+        // when
+        {
+            let mut block = BlockPageGuard::<u64, u64>::fetch(&mut bpm, block_pid, "test").unwrap();
+            assert!(block.remove(3, &1, &42).unwrap());
+        }
+
+        // then
+        let block: HashTableBlockPage<u64, u64> = bpm.fetch_decoded(block_pid).unwrap();
+        assert!(block.is_occupied(3).unwrap());
+        assert!(!block.is_readable(3).unwrap());
+        assert_eq!(bpm.pin_count_of(block_pid), Some(0));
+    }
+
+    #[test]
+    fn should_unpin_page_that_cannot_be_decoded() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(10);
+        let header_pid = bpm.write_encoded(None, &HashTableHeaderPage::new(0, 0)).unwrap();
+
+        // when
+        let err: io::Error = BlockPageGuard::<u64, u64>::fetch(&mut bpm, header_pid, "test").err().unwrap().into();
+
+        // then
+        assert!(matches!(PageError::from_io(&err), Some(PageError::WrongPageType { .. })));
+        assert_eq!(bpm.pin_count_of(header_pid), Some(0));
+    }
+
+    #[test]
+    fn should_record_instead_of_panicking_when_dropped_page_cannot_be_written_back() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(10);
+
+        // when
+        let pid = {
+            let guard = PageGuard::create(&mut bpm, Unencodable, "unencodable").unwrap();
+            guard.get_page_id()
+        };
+
+        // then
+        let failure = bpm.last_dropped_write_back().unwrap();
+        assert_eq!(failure.page_id, pid);
+        assert_eq!(failure.tag, "unencodable");
+        assert_eq!(bpm.pin_count_of(pid), Some(0));
+    }
+}
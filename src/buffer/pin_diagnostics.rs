@@ -0,0 +1,73 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use crate::storage::page::page::PageId;
+
+/// Tag of pins taken through the untagged `fetch_page` / `new_page`.
+pub const UNTAGGED_PIN: &str = "untagged";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinHolder {
+    pub tag: &'static str,
+    pub since: Instant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedPage {
+    pub page_id: PageId,
+    pub frame_id: usize,
+    pub pin_count: u64,
+    pub holders: Vec<PinHolder>,
+}
+
+impl PinnedPage {
+    pub fn oldest_pin_age(&self, now: Instant) -> Duration {
+        self.holders.iter()
+            .map(|h| now.saturating_duration_since(h.since))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// A page guard dropped without `release` whose page could not be written back. The page was unpinned
+/// clean if at all, so its changes were lost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DroppedWriteBack {
+    pub page_id: PageId,
+    pub tag: &'static str,
+    pub error: String,
+}
+
+/// State of the pool when it was captured, e.g. when every frame was pinned.
+#[derive(Debug, Clone)]
+pub struct PinDiagnostics {
+    pub captured_at: Instant,
+    pub pool_size: usize,
+    pub free_frames: usize,
+    /// Frames the replacer may still evict.
+    pub evictable_frames: usize,
+    pub pinned: Vec<PinnedPage>,
+}
+
+impl PinDiagnostics {
+    pub fn past_deadline(&self, deadline: Duration) -> Vec<&PinnedPage> {
+        self.pinned.iter()
+            .filter(|p| p.oldest_pin_age(self.captured_at) > deadline)
+            .collect()
+    }
+}
+
+impl Display for PinDiagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "buffer pool: {} frames, {} free, {} evictable, {} pinned",
+                 self.pool_size, self.free_frames, self.evictable_frames, self.pinned.len())?;
+        for page in &self.pinned {
+            let tags: Vec<&str> = page.holders.iter().map(|h| h.tag).collect();
+            writeln!(f, "  page {} frame {} pins {} oldest {:?} holders [{}]",
+                     page.page_id, page.frame_id, page.pin_count,
+                     page.oldest_pin_age(self.captured_at), tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
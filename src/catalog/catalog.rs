@@ -0,0 +1,1331 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{io, iter, mem};
+use std::sync::{Arc, Mutex};
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::catalog::catalog_error::CatalogError;
+use crate::catalog::foreign_key::{ForeignKeyAction, ForeignKeyInfo, ForeignKeyOid};
+use crate::catalog::index::{IndexInfo, IndexKind, IndexOid};
+use crate::catalog::statistics::TableStatistics;
+use crate::common::throttle::WriteThrottle;
+use crate::storage::page::b_plus_tree_page::BPlusTreeLeafPage;
+use crate::storage::page::catalog_page::{CatalogEntry, CatalogPage, StatisticsEntry};
+use crate::storage::page::overflow_page::{read_chain, OverflowPage, OverflowRef};
+use crate::storage::page::page::PageId;
+use crate::storage::page::statistics_page::StatisticsPage;
+use crate::storage::table::rid::Rid;
+use crate::storage::table::schema::{Column, Schema};
+use crate::storage::table::table_error::TableError;
+use crate::storage::table::table_heap::{TableHeap, VacuumStats};
+use crate::storage::table::tuple::{Tuple, VarcharData};
+use crate::storage::table::value::Value;
+
+/// Id of a table, handed out by the catalog and never reused.
+pub type TableOid = u32;
+
+/// Table of the catalog: its name, schema and heap.
+///
+/// Adding a column starts a new schema version. Tuples already in the heap keep the version they were
+/// written with, and get the defaults of the columns added since when read through `get_tuple` or `scan`.
+///
+/// Varchars longer than `Tuple::MAX_INLINE_VARCHAR_SIZE` are stored out of line, each in its own chain of
+/// overflow pages, and put back in place by `get_tuple` and `scan`.
+pub struct TableInfo {
+    oid: TableOid,
+    name: String,
+    schema: Schema,
+    heap: TableHeap,
+    column_counts: Vec<usize>,
+    defaults: Vec<Value>,
+    statistics_page_id: Option<PageId>,
+}
+
+impl TableInfo {
+    pub fn oid(&self) -> TableOid {
+        self.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Starts at 0 and goes up by one with every column added.
+    pub fn schema_version(&self) -> u32 {
+        self.column_counts.len() as u32 - 1
+    }
+
+    /// Tuples are written through the catalog, so that the indexes of the table follow. Tuples read from
+    /// the heap are as written, encoded after the schema version they are tagged with.
+    pub fn heap(&self) -> &TableHeap {
+        &self.heap
+    }
+
+    /// Tuple encoded after the latest schema, `None` if it was deleted.
+    pub fn get_tuple(&self, rid: Rid) -> io::Result<Option<Tuple>> {
+        self.heap.get_versioned_tuple(rid)?
+            .map(|(schema_version, tuple)| self.materialize(schema_version, tuple))
+            .transpose()
+    }
+
+    /// Sequential scan of the tuples in rid order, each encoded after the latest schema.
+    pub fn scan(&self) -> impl Iterator<Item = io::Result<(Rid, Tuple)>> + '_ {
+        let mut heap_iter = self.heap.iter();
+        iter::from_fn(move || heap_iter.next_versioned())
+            .map(move |entry| entry.and_then(|(rid, schema_version, tuple)| Ok((rid, self.materialize(schema_version, tuple)?))))
+    }
+
+    fn column_indices(&self, columns: &[&str]) -> Result<Vec<usize>, CatalogError> {
+        columns.iter()
+            .map(|column| self.schema.index_of(column)
+                .ok_or(CatalogError::ColumnNotFound { table: self.name.clone(), column: column.to_string() }))
+            .collect()
+    }
+
+    fn materialize(&self, schema_version: u32, tuple: Tuple) -> io::Result<Tuple> {
+        let old_schema = self.schema_of(schema_version)?;
+        let mut tuple = tuple;
+        for (idx, overflow_ref) in TableInfo::out_of_line(&old_schema, &tuple)? {
+            tuple = tuple.with_varchar_data(&old_schema, idx, VarcharData::Inline(self.heap.read_overflow(&overflow_ref)?))?;
+        }
+        if old_schema.len() == self.schema.len() {
+            return Ok(tuple);
+        }
+
+        let mut values = tuple.values(&old_schema)?;
+        values.extend_from_slice(&self.defaults[old_schema.len() - self.column_counts[0]..]);
+        Tuple::new(&values, &self.schema)
+    }
+
+    fn schema_of(&self, schema_version: u32) -> io::Result<Cow<'_, Schema>> {
+        Ok(match self.column_counts.get(schema_version as usize) {
+            // columns are only ever appended, so the schema of an older version is a prefix of the latest
+            Some(num_columns) if *num_columns < self.schema.len() =>
+                Cow::Owned(self.schema.project(&(0..*num_columns).collect::<Vec<usize>>())?),
+            _ => Cow::Borrowed(&self.schema),
+        })
+    }
+
+    fn out_of_line(schema: &Schema, tuple: &Tuple) -> io::Result<Vec<(usize, OverflowRef)>> {
+        let mut refs = Vec::new();
+        for idx in 0..schema.len() {
+            if let Some(VarcharData::OutOfLine(overflow_ref)) = tuple.varchar_data(schema, idx)? {
+                refs.push((idx, overflow_ref));
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Tuple to write to the heap, with its long varchars moved to overflow pages.
+    fn store_out_of_line(&mut self, tuple: &Tuple) -> io::Result<Tuple> {
+        let mut stored = tuple.clone();
+        for idx in 0..self.schema.len() {
+            let bytes = match tuple.varchar_data(&self.schema, idx)? {
+                Some(VarcharData::Inline(bytes)) if bytes.len() > Tuple::MAX_INLINE_VARCHAR_SIZE => bytes,
+                // two tuples sharing a chain would free it twice
+                Some(VarcharData::OutOfLine(_)) =>
+                    return Err(TableError::VarcharOutOfLine { column: self.schema.columns()[idx].name().to_string() }.into()),
+                _ => continue,
+            };
+            let overflow_ref = self.heap.write_overflow(&bytes)
+                .and_then(|overflow_ref| stored.with_varchar_data(&self.schema, idx, VarcharData::OutOfLine(overflow_ref)));
+            match overflow_ref {
+                Ok(with_ref) => stored = with_ref,
+                Err(e) => {
+                    self.free_out_of_line(self.schema_version(), &stored)?;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(stored)
+    }
+
+    fn free_out_of_line(&mut self, schema_version: u32, stored: &Tuple) -> io::Result<()> {
+        let schema = self.schema_of(schema_version)?.into_owned();
+        for (_, overflow_ref) in TableInfo::out_of_line(&schema, stored)? {
+            self.heap.free_overflow(&overflow_ref)?;
+        }
+        Ok(())
+    }
+}
+
+/// Named tables of one data file, each owning its `TableHeap`, and the indexes over them.
+///
+/// The catalog is kept on a `CatalogPage`, rewritten whenever a table or index is created or an index
+/// moves to other pages, so its page id is all that is needed to open every table and index again,
+/// e.g. from the catalog root of the superblock.
+///
+/// Tuples written through `insert_tuple`, `delete_tuple` and `update_tuple` are added to and removed
+/// from every index of their table. Keys of unique indexes are checked before the heap is touched,
+/// so a duplicate key leaves the table and its indexes unchanged. So are the keys referenced through
+/// foreign keys, see `create_foreign_key`.
+///
+/// With a write throttle set, each of those writes first waits until the throttle lets it through.
+///
+/// Entries not fitting into the catalog page are written to a chain of overflow pages, replaced as a
+/// whole on every change of the catalog.
+pub struct Catalog {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    page_id: PageId,
+    overflow: Option<OverflowRef>,
+    next_oid: u32,
+    tables: BTreeMap<TableOid, TableInfo>,
+    oids_by_name: HashMap<String, TableOid>,
+    indexes: BTreeMap<IndexOid, IndexInfo>,
+    foreign_keys: BTreeMap<ForeignKeyOid, ForeignKeyInfo>,
+    throttle: Option<Arc<WriteThrottle>>,
+}
+
+impl Catalog {
+    /// Writes the page of an empty catalog.
+    pub fn new(bpm: Arc<Mutex<BufferPoolManager>>) -> io::Result<Catalog> {
+        let page_id = bpm.lock().unwrap().write_encoded(None, &CatalogPage::new(0, Vec::new(), Vec::new()))?;
+        Ok(Catalog {
+            buffer_pool_manager: bpm,
+            page_id,
+            overflow: None,
+            next_oid: 0,
+            tables: BTreeMap::new(),
+            oids_by_name: HashMap::new(),
+            indexes: BTreeMap::new(),
+            foreign_keys: BTreeMap::new(),
+            throttle: None,
+        })
+    }
+
+    /// Catalog created before with its page on page `page_id`, opening every table and index.
+    pub fn open(bpm: Arc<Mutex<BufferPoolManager>>, page_id: PageId) -> io::Result<Catalog> {
+        let mut page: CatalogPage = bpm.lock().unwrap().fetch_decoded(page_id)?;
+        if let Some(overflow_ref) = page.get_overflow() {
+            let encoded = {
+                let mut bpm = bpm.lock().unwrap();
+                read_chain(&overflow_ref, |page_id| Ok(bpm.fetch_decoded(page_id)?))?
+            };
+            page = page.with_overflow_entries(&encoded)?;
+        }
+        let mut catalog = Catalog {
+            buffer_pool_manager: bpm,
+            page_id,
+            overflow: page.get_overflow(),
+            next_oid: page.get_next_oid(),
+            tables: BTreeMap::new(),
+            oids_by_name: HashMap::new(),
+            indexes: BTreeMap::new(),
+            foreign_keys: BTreeMap::new(),
+            throttle: None,
+        };
+        let foreign_keys: Vec<ForeignKeyInfo> = page.get_foreign_keys().iter().map(ForeignKeyInfo::open).collect();
+        let statistics_pages: HashMap<TableOid, PageId> = page.get_statistics().iter()
+            .map(|entry| (entry.table_oid, entry.page_id))
+            .collect();
+        let (tables, indexes) = page.into_entries();
+        for entry in tables {
+            let mut heap = TableHeap::open(catalog.buffer_pool_manager.clone(), entry.first_page_id)?;
+            heap.set_schema_version(entry.column_counts.len() as u32 - 1);
+            catalog.add_table(TableInfo {
+                oid: entry.oid,
+                name: entry.name,
+                schema: entry.schema,
+                heap,
+                column_counts: entry.column_counts,
+                defaults: entry.defaults,
+                statistics_page_id: statistics_pages.get(&entry.oid).copied(),
+            });
+        }
+        for entry in indexes {
+            let table = catalog.tables.get(&entry.table_oid).ok_or(CatalogError::TableOidNotFound { oid: entry.table_oid })?;
+            let index = IndexInfo::open(catalog.buffer_pool_manager.clone(), &entry, &table.schema)?;
+            catalog.indexes.insert(index.oid(), index);
+        }
+        for foreign_key in foreign_keys {
+            catalog.foreign_keys.insert(foreign_key.oid(), foreign_key);
+        }
+        Ok(catalog)
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    /// Writes to a table count against the global limit of the throttle and the limit of the table
+    /// by its name, see `WriteThrottle::set_table_limit`.
+    pub fn set_write_throttle(&mut self, throttle: Arc<WriteThrottle>) {
+        self.throttle = Some(throttle);
+    }
+
+    fn throttle_write(&self, table_oid: TableOid, bytes: usize) -> io::Result<()> {
+        let table = self.tables.get(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        if let Some(throttle) = &self.throttle {
+            throttle.throttle(&table.name, bytes as u64);
+        }
+        Ok(())
+    }
+
+    /// Creates the table with an empty heap under the next oid. Fails with `CatalogError::TableExists`
+    /// if the name is taken, or with a `TableError` if a column default does not fit its column, see
+    /// `Schema::check_defaults`.
+    pub fn create_table(&mut self, name: &str, schema: Schema) -> io::Result<&TableInfo> {
+        if self.oids_by_name.contains_key(name) {
+            return Err(CatalogError::TableExists { name: name.to_string() }.into());
+        }
+        schema.check_defaults()?;
+
+        let heap = TableHeap::new(self.buffer_pool_manager.clone())?;
+        let oid = self.next_oid;
+        let first_page_id = heap.first_page_id();
+        let column_counts = vec![schema.len()];
+        self.add_table(TableInfo { oid, name: name.to_string(), schema, heap, column_counts, defaults: Vec::new(), statistics_page_id: None });
+        self.next_oid += 1;
+        if let Err(e) = self.write_page() {
+            self.next_oid -= 1;
+            self.oids_by_name.remove(name);
+            self.tables.remove(&oid);
+            self.buffer_pool_manager.lock().unwrap().delete_page(first_page_id)?;
+            return Err(e);
+        }
+        Ok(&self.tables[&oid])
+    }
+
+    /// Creates the table together with a unique index of `kind` over the `key_columns`, backing its
+    /// primary key: inserts and updates fail with `CatalogError::DuplicateKey` on a key already taken.
+    /// Fails with `CatalogError::NullablePrimaryKey` if a key column takes nulls, and otherwise as
+    /// `create_table` and `create_index` do, leaving no table behind.
+    pub fn create_table_with_primary_key(&mut self, name: &str, schema: Schema, key_columns: &[&str], kind: IndexKind) -> io::Result<&TableInfo> {
+        for column in key_columns {
+            let idx = schema.index_of(column).ok_or(CatalogError::ColumnNotFound { table: name.to_string(), column: column.to_string() })?;
+            if schema.columns()[idx].is_nullable() {
+                return Err(CatalogError::NullablePrimaryKey { table: name.to_string(), column: column.to_string() }.into());
+            }
+        }
+
+        let oid = self.create_table(name, schema)?.oid();
+        if let Err(e) = self.add_index(name, key_columns, kind, true) {
+            let table = self.tables.remove(&oid).unwrap();
+            self.oids_by_name.remove(name);
+            self.next_oid -= 1;
+            self.buffer_pool_manager.lock().unwrap().delete_page(table.heap.first_page_id())?;
+            self.write_page()?;
+            return Err(e);
+        }
+        Ok(&self.tables[&oid])
+    }
+
+    /// Adds the column to the table under a new schema version, without rewriting the tuples in its
+    /// heap: they read with `default`, cast to the type of the column, until updated. Fails with
+    /// `CatalogError::ColumnExists` if the table has a column of the name, or with a `TableError` if
+    /// the default does not cast or is null for a column not taking nulls.
+    pub fn alter_table_add_column(&mut self, table: &str, column: Column, default: Value) -> io::Result<&TableInfo> {
+        let oid = *self.oids_by_name.get(table).ok_or(CatalogError::TableNotFound { name: table.to_string() })?;
+        let table_info = self.tables.get_mut(&oid).unwrap();
+        if table_info.schema.index_of(column.name()).is_some() {
+            return Err(CatalogError::ColumnExists { table: table.to_string(), column: column.name().to_string() }.into());
+        }
+        let default = default.cast_to(column.data_type())?;
+        if default.is_null() && !column.is_nullable() {
+            return Err(TableError::NullNotAllowed { column: column.name().to_string() }.into());
+        }
+        Schema::new(vec![column.clone()]).check_defaults()?;
+
+        let old_schema = table_info.schema.clone();
+        let mut columns = old_schema.columns().to_vec();
+        columns.push(column);
+        table_info.schema = Schema::new(columns);
+        table_info.column_counts.push(table_info.schema.len());
+        table_info.defaults.push(default);
+        table_info.heap.set_schema_version(table_info.schema_version());
+        if let Err(e) = self.write_page() {
+            let table_info = self.tables.get_mut(&oid).unwrap();
+            table_info.schema = old_schema;
+            table_info.column_counts.pop();
+            table_info.defaults.pop();
+            table_info.heap.set_schema_version(table_info.schema_version());
+            return Err(e);
+        }
+        Ok(&self.tables[&oid])
+    }
+
+    pub fn get_table(&self, name: &str) -> Option<&TableInfo> {
+        self.oids_by_name.get(name).and_then(|oid| self.tables.get(oid))
+    }
+
+    pub fn get_table_by_oid(&self, oid: TableOid) -> Option<&TableInfo> {
+        self.tables.get(&oid)
+    }
+
+    /// Scans the table and records the statistics of its columns, replacing those of its last analyze,
+    /// on a statistics page of the table. Fails with `PageError::ValueTooLarge` if they do not fit the
+    /// page, e.g. for a column of long varchars.
+    pub fn analyze(&mut self, table: &str) -> io::Result<TableStatistics> {
+        let oid = *self.oids_by_name.get(table).ok_or(CatalogError::TableNotFound { name: table.to_string() })?;
+        let table_info = &self.tables[&oid];
+        let statistics = TableStatistics::collect(&table_info.schema, table_info.scan().map(|entry| entry.map(|(_, tuple)| tuple)))?;
+        let page = StatisticsPage::new(statistics);
+        // encoded first, so that no page is taken for statistics that do not fit
+        page.serialize()?;
+
+        let stored_page_id = table_info.statistics_page_id;
+        let page_id = self.buffer_pool_manager.lock().unwrap().write_encoded(stored_page_id, &page)?;
+        if stored_page_id.is_none() {
+            self.tables.get_mut(&oid).unwrap().statistics_page_id = Some(page_id);
+            if let Err(e) = self.write_page() {
+                self.tables.get_mut(&oid).unwrap().statistics_page_id = None;
+                self.buffer_pool_manager.lock().unwrap().delete_page(page_id)?;
+                return Err(e);
+            }
+        }
+        Ok(page.into_statistics())
+    }
+
+    /// Statistics of the table as of its last analyze, `None` if it was never analyzed.
+    pub fn table_statistics(&self, table_oid: TableOid) -> io::Result<Option<TableStatistics>> {
+        let table = self.tables.get(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        match table.statistics_page_id {
+            Some(page_id) => Ok(Some(self.buffer_pool_manager.lock().unwrap().fetch_decoded::<StatisticsPage>(page_id)?.into_statistics())),
+            None => Ok(None),
+        }
+    }
+
+    /// Vacuums the heap of the table, see `TableHeap::vacuum`.
+    pub fn vacuum(&mut self, table: &str) -> io::Result<VacuumStats> {
+        let oid = *self.oids_by_name.get(table).ok_or(CatalogError::TableNotFound { name: table.to_string() })?;
+        self.tables.get_mut(&oid).unwrap().heap.vacuum()
+    }
+
+    /// Rewrites the heap of the table in the key order of its B+ tree index `index_oid`, tuples with a
+    /// null in their key last, and rebuilds every index of the table over the new rids. The leaves of the
+    /// index hold the keys sorted already, so only the rids are kept in memory, not the tuples, which are
+    /// copied as stored, long varchars keeping their overflow chains. The catalog page is written before
+    /// the old heap and indexes are deleted, so a failure until then leaves the table as it was, with
+    /// the pages written so far unreferenced. Rids of the table are invalid afterwards.
+    ///
+    /// Fails with `CatalogError::IndexNotFound` if the index is not one of the table, or with
+    /// `CatalogError::IndexNotOrdered` if it is a hash index.
+    pub fn cluster(&mut self, table: &str, index_oid: IndexOid) -> io::Result<&TableInfo> {
+        let oid = *self.oids_by_name.get(table).ok_or(CatalogError::TableNotFound { name: table.to_string() })?;
+        let table_info = &self.tables[&oid];
+        let mut rids = self.indexes.get(&index_oid)
+            .filter(|index| index.table_oid() == oid)
+            .ok_or(CatalogError::IndexNotFound { table: table.to_string(), index: index_oid })?
+            .rids_in_key_order()?;
+        let indexed: HashSet<Rid> = rids.iter().copied().collect();
+        for entry in table_info.heap.iter() {
+            let (rid, _) = entry?;
+            if !indexed.contains(&rid) {
+                rids.push(rid);
+            }
+        }
+
+        let mut heap = TableHeap::new(self.buffer_pool_manager.clone())?;
+        let mut indexes = self.indexes.values()
+            .filter(|index| index.table_oid() == oid)
+            .map(|index| IndexInfo::create(self.buffer_pool_manager.clone(), index.oid(), oid, index.key_columns().to_vec(),
+                                           index.key_schema().clone(), index.kind(), index.is_primary_key()))
+            .collect::<io::Result<Vec<IndexInfo>>>()?;
+        for rid in rids {
+            let (schema_version, stored) = match table_info.heap.get_versioned_tuple(rid)? {
+                Some(versioned) => versioned,
+                None => continue,
+            };
+            let tuple = table_info.materialize(schema_version, stored.clone())?;
+            heap.set_schema_version(schema_version);
+            let new_rid = heap.insert_tuple(&stored)?;
+            for index in indexes.iter_mut() {
+                let key = index.key_of(&tuple, &table_info.schema)?;
+                index.insert_entry(&key, new_rid)?;
+            }
+        }
+        heap.set_schema_version(table_info.schema_version());
+
+        let old_heap = mem::replace(&mut self.tables.get_mut(&oid).unwrap().heap, heap);
+        let old_indexes: Vec<IndexInfo> = indexes.into_iter()
+            .filter_map(|index| self.indexes.insert(index.oid(), index))
+            .collect();
+        if let Err(e) = self.write_page() {
+            self.tables.get_mut(&oid).unwrap().heap = old_heap;
+            for index in old_indexes {
+                self.indexes.insert(index.oid(), index);
+            }
+            return Err(e);
+        }
+        old_heap.drop_heap()?;
+        for index in old_indexes {
+            index.drop_index()?;
+        }
+        Ok(&self.tables[&oid])
+    }
+
+    /// Tables in oid order, i.e. in the order they were created.
+    pub fn list_tables(&self) -> Vec<&TableInfo> {
+        self.tables.values().collect()
+    }
+
+    /// Creates an index of `kind` over the `columns` of the table, in key order, filled with the tuples
+    /// already in it. Fails with `CatalogError::KeyTooLarge` if the largest key of the columns does not
+    /// fit into the index, or with `CatalogError::DuplicateKey` if a unique index would hold a key
+    /// twice, leaving the pages written so far unreferenced.
+    pub fn create_index(&mut self, table: &str, columns: &[&str], kind: IndexKind) -> io::Result<&IndexInfo> {
+        self.add_index(table, columns, kind, false)
+    }
+
+    fn add_index(&mut self, table: &str, columns: &[&str], kind: IndexKind, primary_key: bool) -> io::Result<&IndexInfo> {
+        let table_info = self.get_table(table).ok_or(CatalogError::TableNotFound { name: table.to_string() })?;
+        let key_columns = table_info.column_indices(columns)?;
+        let key_schema = table_info.schema.project(&key_columns)?;
+        let max_key_size = match kind {
+            // bincode puts the length of the key ahead of it, the rid after it
+            IndexKind::BPlusTree => BPlusTreeLeafPage::<Tuple, Rid>::max_entry_size() - 8 - Rid::SIZE,
+            IndexKind::Hash => Tuple::MAX_HASH_KEY_SIZE,
+        };
+        if key_schema.max_tuple_size() > max_key_size {
+            return Err(CatalogError::KeyTooLarge { size: key_schema.max_tuple_size(), max: max_key_size }.into());
+        }
+
+        let oid = self.next_oid;
+        let mut index = IndexInfo::create(self.buffer_pool_manager.clone(), oid, table_info.oid, key_columns, key_schema, kind, primary_key)?;
+        for entry in table_info.scan() {
+            let (rid, tuple) = entry?;
+            let key = index.key_of(&tuple, &table_info.schema)?;
+            if (index.is_unique() && !index.scan_key(&key)?.is_empty()) || !index.insert_entry(&key, rid)? {
+                return Err(CatalogError::DuplicateKey { index: oid }.into());
+            }
+        }
+
+        self.indexes.insert(oid, index);
+        self.next_oid += 1;
+        if let Err(e) = self.write_page() {
+            self.next_oid -= 1;
+            self.indexes.remove(&oid);
+            return Err(e);
+        }
+        Ok(&self.indexes[&oid])
+    }
+
+    /// Declares a foreign key from the `columns` of the table to the `referenced_columns` of the referenced
+    /// table, which need a unique index keyed by exactly them, in that order, e.g. its primary key. Fails
+    /// with `CatalogError::ReferencedKeyNotUnique` if there is none, with `CatalogError::ForeignKeyMismatch`
+    /// if a column differs in type from the one it references, or with `CatalogError::ReferencedKeyNotFound`
+    /// if a tuple already in the table references a key not in the index.
+    ///
+    /// Tuples inserted into the table, or updated to another key, then fail with
+    /// `CatalogError::ReferencedKeyNotFound` unless their key is in the index. Deleting or updating a key
+    /// still referenced either fails with `CatalogError::KeyStillReferenced` or cascades to the referencing
+    /// tuples, as `on_delete` and `on_update` say. Referencing tuples are found through an index of the
+    /// table keyed by the referencing columns if there is one, and by a scan of the table if not.
+    pub fn create_foreign_key(&mut self, table: &str, columns: &[&str], referenced_table: &str, referenced_columns: &[&str],
+                              on_delete: ForeignKeyAction, on_update: ForeignKeyAction) -> io::Result<&ForeignKeyInfo> {
+        let table_info = self.get_table(table).ok_or(CatalogError::TableNotFound { name: table.to_string() })?;
+        let referenced_info = self.get_table(referenced_table).ok_or(CatalogError::TableNotFound { name: referenced_table.to_string() })?;
+        let columns = table_info.column_indices(columns)?;
+        let referenced_columns = referenced_info.column_indices(referenced_columns)?;
+        for (column, referenced) in columns.iter().zip(&referenced_columns) {
+            let column = &table_info.schema.columns()[*column];
+            if column.data_type() != referenced_info.schema.columns()[*referenced].data_type() {
+                return Err(CatalogError::ForeignKeyMismatch { column: column.name().to_string() }.into());
+            }
+        }
+        if columns.len() != referenced_columns.len() {
+            let column = match columns.get(referenced_columns.len()) {
+                Some(column) => table_info.schema.columns()[*column].name(),
+                None => referenced_info.schema.columns()[referenced_columns[columns.len()]].name(),
+            };
+            return Err(CatalogError::ForeignKeyMismatch { column: column.to_string() }.into());
+        }
+        let index = self.indexes.values()
+            .find(|index| index.table_oid() == referenced_info.oid && index.is_unique() && index.key_columns() == referenced_columns)
+            .ok_or(CatalogError::ReferencedKeyNotUnique { table: referenced_table.to_string() })?;
+
+        let oid = self.next_oid;
+        let foreign_key = ForeignKeyInfo::new(oid, table_info.oid, columns, referenced_info.oid, index.oid(), on_delete, on_update);
+        for entry in table_info.scan() {
+            self.check_referenced(&foreign_key, &entry?.1)?;
+        }
+
+        self.foreign_keys.insert(oid, foreign_key);
+        self.next_oid += 1;
+        if let Err(e) = self.write_page() {
+            self.next_oid -= 1;
+            self.foreign_keys.remove(&oid);
+            return Err(e);
+        }
+        Ok(&self.foreign_keys[&oid])
+    }
+
+    pub fn get_foreign_key(&self, oid: ForeignKeyOid) -> Option<&ForeignKeyInfo> {
+        self.foreign_keys.get(&oid)
+    }
+
+    /// Foreign keys of the table, referencing other tables, in oid order.
+    pub fn table_foreign_keys(&self, table_oid: TableOid) -> Vec<&ForeignKeyInfo> {
+        self.foreign_keys.values().filter(|foreign_key| foreign_key.table_oid() == table_oid).collect()
+    }
+
+    pub fn get_index(&self, oid: IndexOid) -> Option<&IndexInfo> {
+        self.indexes.get(&oid)
+    }
+
+    /// Index backing the primary key of the table, if it was created with one.
+    pub fn primary_key(&self, table_oid: TableOid) -> Option<&IndexInfo> {
+        self.indexes.values().find(|index| index.table_oid() == table_oid && index.is_primary_key())
+    }
+
+    /// Indexes of the table in oid order.
+    pub fn table_indexes(&self, table_oid: TableOid) -> Vec<&IndexInfo> {
+        self.indexes.values().filter(|index| index.table_oid() == table_oid).collect()
+    }
+
+    /// Inserts a tuple of the `values` of the named `columns`, in any order, see `insert_tuple`. Columns
+    /// left out take their default, or null, see `Schema::fill_defaults`.
+    pub fn insert_values(&mut self, table_oid: TableOid, columns: &[&str], values: &[Value]) -> io::Result<Rid> {
+        let table = self.tables.get(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        if columns.len() != values.len() {
+            return Err(TableError::ColumnCountMismatch { expected: columns.len(), found: values.len() }.into());
+        }
+        let mut given = vec![None; table.schema.len()];
+        for (idx, value) in table.column_indices(columns)?.into_iter().zip(values) {
+            given[idx] = Some(value.clone());
+        }
+        let tuple = Tuple::new(&table.schema.fill_defaults(given)?, &table.schema)?;
+        self.insert_tuple(table_oid, &tuple)
+    }
+
+    /// Inserts the tuple, encoded after the latest schema of the table, into its heap and its key into
+    /// every index of the table. Fails with a `TableError` if the tuple is not one of the schema, e.g.
+    /// with a null in a column not taking nulls, see `Tuple::check`, or with
+    /// `CatalogError::ReferencedKeyNotFound` if it references a key not there through a foreign key.
+    pub fn insert_tuple(&mut self, table_oid: TableOid, tuple: &Tuple) -> io::Result<Rid> {
+        self.throttle_write(table_oid, tuple.size())?;
+        tuple.check(&self.tables.get(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?.schema)?;
+        for foreign_key in self.foreign_keys.values().filter(|foreign_key| foreign_key.table_oid() == table_oid) {
+            self.check_referenced(foreign_key, tuple)?;
+        }
+        let table = self.tables.get_mut(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        let mut keys = Vec::new();
+        for index in self.indexes.values().filter(|index| index.table_oid() == table_oid) {
+            let key = index.key_of(tuple, &table.schema)?;
+            if index.is_unique() && !index.scan_key(&key)?.is_empty() {
+                return Err(CatalogError::DuplicateKey { index: index.oid() }.into());
+            }
+            keys.push((index.oid(), key));
+        }
+
+        let stored = table.store_out_of_line(tuple)?;
+        let rid = match table.heap.insert_tuple(&stored) {
+            Ok(rid) => rid,
+            Err(e) => {
+                table.free_out_of_line(table.schema_version(), &stored)?;
+                return Err(e);
+            }
+        };
+        for (index_oid, key) in keys {
+            self.indexes.get_mut(&index_oid).unwrap().insert_entry(&key, rid)?;
+        }
+        self.write_page_if_indexes_moved()?;
+        Ok(rid)
+    }
+
+    /// Deletes the tuple from the heap of the table and its key from every index of the table. Tuples
+    /// referencing it through a cascading foreign key are deleted along with it, and so on, while one
+    /// referencing it through a restricting foreign key fails the delete with
+    /// `CatalogError::KeyStillReferenced` before any tuple is deleted. Returns whether there was a tuple
+    /// to delete.
+    pub fn delete_tuple(&mut self, table_oid: TableOid, rid: Rid) -> io::Result<bool> {
+        self.throttle_write(table_oid, 0)?;
+        let table = self.tables.get(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        let tuple = match table.get_tuple(rid)? {
+            Some(tuple) => tuple,
+            None => return Ok(false),
+        };
+        let mut deleted = vec![(table_oid, rid)];
+        self.collect_cascaded_deletes(table_oid, &tuple, &mut deleted)?;
+        for (table_oid, rid) in deleted {
+            self.remove_tuple(table_oid, rid)?;
+        }
+        Ok(true)
+    }
+
+    /// Adds the tuples referencing the tuple of the table through a cascading foreign key, and the ones
+    /// referencing those in turn, to `deleted`.
+    fn collect_cascaded_deletes(&self, table_oid: TableOid, tuple: &Tuple, deleted: &mut Vec<(TableOid, Rid)>) -> io::Result<()> {
+        for foreign_key in self.foreign_keys.values().filter(|foreign_key| foreign_key.referenced_table_oid() == table_oid) {
+            let key_columns = self.indexes[&foreign_key.referenced_index_oid()].key_columns();
+            let values = Catalog::values_of(tuple, &self.tables[&table_oid].schema, key_columns)?;
+            if values.iter().any(Value::is_null) {
+                continue;
+            }
+            for rid in self.referencing_rids(foreign_key, &values)? {
+                let referencing = (foreign_key.table_oid(), rid);
+                if deleted.contains(&referencing) {
+                    continue;
+                }
+                if foreign_key.on_delete() == ForeignKeyAction::Restrict {
+                    return Err(CatalogError::KeyStillReferenced { foreign_key: foreign_key.oid() }.into());
+                }
+                deleted.push(referencing);
+                let referencing_tuple = self.tables[&foreign_key.table_oid()].get_tuple(rid)?.ok_or(TableError::TupleNotFound { rid })?;
+                self.collect_cascaded_deletes(foreign_key.table_oid(), &referencing_tuple, deleted)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_tuple(&mut self, table_oid: TableOid, rid: Rid) -> io::Result<bool> {
+        let table = self.tables.get_mut(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        let (schema_version, stored) = match table.heap.get_versioned_tuple(rid)? {
+            Some(versioned) => versioned,
+            None => return Ok(false),
+        };
+        let tuple = table.materialize(schema_version, stored.clone())?;
+
+        table.heap.delete_tuple(rid)?;
+        table.free_out_of_line(schema_version, &stored)?;
+        for index in self.indexes.values_mut().filter(|index| index.table_oid() == table_oid) {
+            let key = index.key_of(&tuple, &table.schema)?;
+            index.remove_entry(&key, rid)?;
+        }
+        self.write_page_if_indexes_moved()?;
+        Ok(true)
+    }
+
+    /// Updates the tuple in the heap, see `TableHeap::update_tuple`, and moves its entry in every index
+    /// of the table whose key changed, or every index if the tuple was relocated. Returns the rid the
+    /// tuple is at now.
+    ///
+    /// Foreign keys are checked as on insert and delete: a new key referenced by the tuple has to be in
+    /// the referenced table, and a key of the tuple still referenced either fails the update with
+    /// `CatalogError::KeyStillReferenced` or is updated in the referencing tuples afterwards. A cascade
+    /// failing part way leaves the tuples updated until then as they are. The tuple is checked as on
+    /// insert.
+    pub fn update_tuple(&mut self, table_oid: TableOid, rid: Rid, tuple: &Tuple) -> io::Result<Rid> {
+        self.throttle_write(table_oid, tuple.size())?;
+        let table = self.tables.get(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        tuple.check(&table.schema)?;
+        let old_tuple = table.get_tuple(rid)?.ok_or(TableError::TupleNotFound { rid })?;
+        for foreign_key in self.foreign_keys.values().filter(|foreign_key| foreign_key.table_oid() == table_oid) {
+            if Catalog::values_of(&old_tuple, &table.schema, foreign_key.columns())? != Catalog::values_of(tuple, &table.schema, foreign_key.columns())? {
+                self.check_referenced(foreign_key, tuple)?;
+            }
+        }
+        let mut cascaded = Vec::new();
+        for foreign_key in self.foreign_keys.values().filter(|foreign_key| foreign_key.referenced_table_oid() == table_oid) {
+            let key_columns = self.indexes[&foreign_key.referenced_index_oid()].key_columns();
+            let old_values = Catalog::values_of(&old_tuple, &table.schema, key_columns)?;
+            let new_values = Catalog::values_of(tuple, &table.schema, key_columns)?;
+            if old_values == new_values || old_values.iter().any(Value::is_null) {
+                continue;
+            }
+            let referencing: Vec<Rid> = self.referencing_rids(foreign_key, &old_values)?.into_iter()
+                .filter(|referencing| (foreign_key.table_oid(), *referencing) != (table_oid, rid))
+                .collect();
+            if referencing.is_empty() {
+                continue;
+            }
+            if foreign_key.on_update() == ForeignKeyAction::Restrict {
+                return Err(CatalogError::KeyStillReferenced { foreign_key: foreign_key.oid() }.into());
+            }
+            cascaded.push((foreign_key.clone(), referencing, new_values));
+        }
+
+        let new_rid = self.replace_tuple(table_oid, rid, tuple)?;
+        for (foreign_key, referencing, new_values) in cascaded {
+            for rid in referencing {
+                let table = &self.tables[&foreign_key.table_oid()];
+                let mut values = table.get_tuple(rid)?.ok_or(TableError::TupleNotFound { rid })?.values(&table.schema)?;
+                for (idx, value) in foreign_key.columns().iter().zip(&new_values) {
+                    values[*idx] = value.clone();
+                }
+                let referencing_tuple = Tuple::new(&values, &table.schema)?;
+                self.update_tuple(foreign_key.table_oid(), rid, &referencing_tuple)?;
+            }
+        }
+        Ok(new_rid)
+    }
+
+    fn replace_tuple(&mut self, table_oid: TableOid, rid: Rid, tuple: &Tuple) -> io::Result<Rid> {
+        let table = self.tables.get_mut(&table_oid).ok_or(CatalogError::TableOidNotFound { oid: table_oid })?;
+        let (old_schema_version, old_stored) = table.heap.get_versioned_tuple(rid)?.ok_or(TableError::TupleNotFound { rid })?;
+        let old_tuple = table.materialize(old_schema_version, old_stored.clone())?;
+        let mut keys = Vec::new();
+        for index in self.indexes.values().filter(|index| index.table_oid() == table_oid) {
+            let (old_key, new_key) = (index.key_of(&old_tuple, &table.schema)?, index.key_of(tuple, &table.schema)?);
+            if index.is_unique() && old_key != new_key && !index.scan_key(&new_key)?.is_empty() {
+                return Err(CatalogError::DuplicateKey { index: index.oid() }.into());
+            }
+            keys.push((index.oid(), old_key, new_key));
+        }
+
+        let stored = table.store_out_of_line(tuple)?;
+        let new_rid = match table.heap.update_tuple(rid, &stored) {
+            Ok(new_rid) => new_rid,
+            Err(e) => {
+                table.free_out_of_line(table.schema_version(), &stored)?;
+                return Err(e);
+            }
+        };
+        table.free_out_of_line(old_schema_version, &old_stored)?;
+        for (index_oid, old_key, new_key) in keys {
+            if old_key != new_key || new_rid != rid {
+                let index = self.indexes.get_mut(&index_oid).unwrap();
+                index.remove_entry(&old_key, rid)?;
+                index.insert_entry(&new_key, new_rid)?;
+            }
+        }
+        self.write_page_if_indexes_moved()?;
+        Ok(new_rid)
+    }
+
+    /// Fails with `CatalogError::ReferencedKeyNotFound` unless the key the tuple of the referencing table
+    /// holds is in the referenced index, or has a null.
+    fn check_referenced(&self, foreign_key: &ForeignKeyInfo, tuple: &Tuple) -> io::Result<()> {
+        let values = Catalog::values_of(tuple, &self.tables[&foreign_key.table_oid()].schema, foreign_key.columns())?;
+        if values.iter().any(Value::is_null) {
+            return Ok(());
+        }
+        let index = &self.indexes[&foreign_key.referenced_index_oid()];
+        if index.scan_key(&Tuple::new(&values, index.key_schema())?)?.is_empty() {
+            return Err(CatalogError::ReferencedKeyNotFound { foreign_key: foreign_key.oid() }.into());
+        }
+        Ok(())
+    }
+
+    /// Rids of the tuples referencing the key of `values` through the foreign key.
+    fn referencing_rids(&self, foreign_key: &ForeignKeyInfo, values: &[Value]) -> io::Result<Vec<Rid>> {
+        let index = self.indexes.values()
+            .find(|index| index.table_oid() == foreign_key.table_oid() && index.key_columns() == foreign_key.columns());
+        if let Some(index) = index {
+            return index.scan_key(&Tuple::new(values, index.key_schema())?);
+        }
+
+        let table = &self.tables[&foreign_key.table_oid()];
+        let mut rids = Vec::new();
+        for entry in table.scan() {
+            let (rid, tuple) = entry?;
+            if Catalog::values_of(&tuple, &table.schema, foreign_key.columns())? == values {
+                rids.push(rid);
+            }
+        }
+        Ok(rids)
+    }
+
+    fn values_of(tuple: &Tuple, schema: &Schema, columns: &[usize]) -> io::Result<Vec<Value>> {
+        columns.iter().map(|idx| tuple.get_value(schema, *idx)).collect()
+    }
+
+    fn add_table(&mut self, table: TableInfo) {
+        self.oids_by_name.insert(table.name.clone(), table.oid);
+        self.tables.insert(table.oid, table);
+    }
+
+    fn write_page(&mut self) -> io::Result<()> {
+        let tables = self.tables.values()
+            .map(|table| CatalogEntry {
+                oid: table.oid,
+                name: table.name.clone(),
+                schema: table.schema.clone(),
+                first_page_id: table.heap.first_page_id(),
+                column_counts: table.column_counts.clone(),
+                defaults: table.defaults.clone(),
+            })
+            .collect();
+        let indexes = self.indexes.values().map(IndexInfo::to_entry).collect();
+        let statistics = self.tables.values()
+            .filter_map(|table| table.statistics_page_id.map(|page_id| StatisticsEntry { table_oid: table.oid, page_id }))
+            .collect();
+        let foreign_keys = self.foreign_keys.values().map(ForeignKeyInfo::to_entry).collect();
+        let mut page = CatalogPage::new(self.next_oid, tables, indexes).with_statistics(statistics).with_foreign_keys(foreign_keys);
+        let encoded = page.encode_entries()?;
+        let overflow = match encoded.len() > CatalogPage::inline_capacity() {
+            true => Some(self.write_overflow(&encoded)?),
+            false => None,
+        };
+        if let Some(overflow_ref) = overflow {
+            page = page.with_overflow(overflow_ref);
+        }
+        let written = self.buffer_pool_manager.lock().unwrap().write_encoded(Some(self.page_id), &page);
+        if let Err(e) = written {
+            if let Some(overflow_ref) = overflow {
+                self.free_overflow(&overflow_ref)?;
+            }
+            return Err(e.into());
+        }
+        if let Some(old_overflow_ref) = mem::replace(&mut self.overflow, overflow) {
+            self.free_overflow(&old_overflow_ref)?;
+        }
+        for index in self.indexes.values_mut() {
+            index.stored_root = index.root_page_id();
+        }
+        Ok(())
+    }
+
+    /// Writes the chunks last to first, so each page knows the next one when written.
+    fn write_overflow(&self, data: &[u8]) -> io::Result<OverflowRef> {
+        let mut bpm = self.buffer_pool_manager.lock().unwrap();
+        let mut next_page_id = None;
+        for chunk in data.chunks(OverflowPage::capacity()).rev() {
+            next_page_id = Some(bpm.write_encoded(None, &OverflowPage::new(chunk.to_vec(), next_page_id)?)?);
+        }
+        Ok(OverflowRef { page_id: next_page_id.unwrap(), len: data.len() as u64 })
+    }
+
+    fn free_overflow(&self, overflow_ref: &OverflowRef) -> io::Result<()> {
+        let mut bpm = self.buffer_pool_manager.lock().unwrap();
+        read_chain(overflow_ref, |page_id| {
+            let overflow_page = bpm.fetch_decoded(page_id)?;
+            bpm.delete_page(page_id)?;
+            Ok(overflow_page)
+        })?;
+        Ok(())
+    }
+
+    /// A hash index moves to new header pages when it is resized.
+    fn write_page_if_indexes_moved(&mut self) -> io::Result<()> {
+        if self.indexes.values().any(|index| index.root_page_id() != index.stored_root) {
+            self.write_page()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::catalog::catalog::Catalog;
+    use crate::catalog::catalog_error::CatalogError;
+    use crate::catalog::foreign_key::ForeignKeyAction;
+    use crate::catalog::index::IndexKind;
+    use crate::common::throttle::{RateLimit, WriteThrottle};
+    use crate::storage::table::rid::Rid;
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::table_error::TableError;
+    use crate::storage::table::tuple::{Tuple, VarcharData};
+    use crate::storage::table::value::Value;
+
+    fn schema() -> Schema {
+        Schema::new(vec![Column::new("id", DataType::Integer), Column::new("name", DataType::Varchar(32))])
+    }
+
+    #[test]
+    fn should_create_tables_under_new_oids_and_open_them_again() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let tuple = Tuple::new(&[Value::Integer(1), Value::Varchar("one".to_string())], &schema()).unwrap();
+
+        // when
+        let users_oid = catalog.create_table("users", schema()).unwrap().oid();
+        let rid = catalog.insert_tuple(users_oid, &tuple).unwrap();
+        let orders_oid = catalog.create_table("orders", Schema::new(vec![Column::new("id", DataType::BigInt)])).unwrap().oid();
+        let err = catalog.create_table("users", schema()).err().unwrap();
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        assert!(matches!(CatalogError::from_io(&err), Some(CatalogError::TableExists { name }) if name == "users"));
+        let names: Vec<&str> = reopened.list_tables().iter().map(|table| table.name()).collect();
+        assert_eq!(names, ["users", "orders"]);
+        let users = reopened.get_table("users").unwrap();
+        assert_eq!((users.oid(), users.schema()), (0, &schema()));
+        assert_eq!(users.heap().get_tuple(rid).unwrap(), Some(tuple));
+        assert_eq!(reopened.get_table_by_oid(orders_oid).unwrap().name(), "orders");
+        assert!(reopened.get_table("missing").is_none());
+    }
+
+    #[test]
+    fn should_delay_writes_to_throttled_table() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut catalog = Catalog::new(bpm).unwrap();
+        let throttle = Arc::new(WriteThrottle::new());
+        throttle.set_table_limit("users", RateLimit { ops_per_sec: Some(10), bytes_per_sec: None }).unwrap();
+        catalog.set_write_throttle(throttle);
+        let users_oid = catalog.create_table("users", schema()).unwrap().oid();
+        let orders_oid = catalog.create_table("orders", schema()).unwrap().oid();
+        let tuple = Tuple::new(&[Value::Integer(1), Value::Varchar("one".to_string())], &schema()).unwrap();
+        for _ in 0..10 {
+            catalog.insert_tuple(users_oid, &tuple).unwrap();
+        }
+
+        // when
+        let unthrottled = Instant::now();
+        catalog.insert_tuple(orders_oid, &tuple).unwrap();
+        let unthrottled = unthrottled.elapsed();
+        let throttled = Instant::now();
+        let rid = catalog.insert_tuple(users_oid, &tuple).unwrap();
+        catalog.delete_tuple(users_oid, rid).unwrap();
+        let throttled = throttled.elapsed();
+
+        // then
+        assert!(unthrottled < Duration::from_millis(50));
+        assert!(throttled >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn should_keep_tables_past_catalog_page_in_overflow_chain_when_reopened() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let long_name = |i: usize| format!("{}{}", "x".repeat(1000), i);
+        for i in 0..20 {
+            catalog.create_table(&long_name(i), schema()).unwrap();
+        }
+
+        // when
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        assert_eq!(reopened.list_tables().len(), 20);
+        assert_eq!(reopened.get_table(&long_name(19)).unwrap().oid(), 19);
+    }
+
+    #[test]
+    fn should_keep_indexes_of_table_in_step_with_its_tuples() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let users = catalog.create_table("users", schema()).unwrap().oid();
+        let user = |id: i32, name: &str| Tuple::new(&[Value::Integer(id), Value::Varchar(name.to_string())], &schema()).unwrap();
+        let existing = catalog.insert_tuple(users, &user(1, "ann")).unwrap();
+        let by_id = catalog.create_index("users", &["id"], IndexKind::BPlusTree).unwrap().oid();
+        let by_name = catalog.create_index("users", &["name"], IndexKind::Hash).unwrap().oid();
+
+        // when
+        let bob = catalog.insert_tuple(users, &user(2, "bob")).unwrap();
+        let other_bob = catalog.insert_tuple(users, &user(3, "bob")).unwrap();
+        let duplicate = catalog.insert_tuple(users, &user(2, "eve")).err().unwrap();
+        let renamed = catalog.update_tuple(users, bob, &user(2, "robert")).unwrap();
+        catalog.delete_tuple(users, existing).unwrap();
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        assert!(matches!(CatalogError::from_io(&duplicate), Some(CatalogError::DuplicateKey { index }) if *index == by_id));
+        assert_eq!(reopened.get_table("users").unwrap().heap().iter().count(), 2);
+        let id_key = |id: i32| Tuple::new(&[Value::Integer(id)], &Schema::new(vec![Column::new("id", DataType::Integer)])).unwrap();
+        let name_key = |name: &str| Tuple::new(&[Value::Varchar(name.to_string())], &Schema::new(vec![Column::new("name", DataType::Varchar(32))])).unwrap();
+        let (by_id, by_name) = (reopened.get_index(by_id).unwrap(), reopened.get_index(by_name).unwrap());
+        assert_eq!(by_id.scan_key(&id_key(1)).unwrap(), Vec::<Rid>::new());
+        assert_eq!(by_id.scan_key(&id_key(2)).unwrap(), [renamed]);
+        assert_eq!(by_name.scan_key(&name_key("bob")).unwrap(), [other_bob]);
+        assert_eq!(by_name.scan_key(&name_key("robert")).unwrap(), [renamed]);
+        assert_eq!(reopened.table_indexes(users).len(), 2);
+        let err = catalog.create_index("users", &["age"], IndexKind::Hash).err().unwrap();
+        assert!(matches!(CatalogError::from_io(&err), Some(CatalogError::ColumnNotFound { column, .. }) if column == "age"));
+    }
+
+    #[test]
+    fn should_leave_keys_with_null_out_of_indexes() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm).unwrap();
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("email", DataType::Varchar(32)).with_nullable(true)]);
+        let users = catalog.create_table("users", schema.clone()).unwrap().oid();
+        let by_email = catalog.create_index("users", &["email"], IndexKind::BPlusTree).unwrap().oid();
+        let user = |id: i32, email: Value| Tuple::new(&[Value::Integer(id), email], &schema).unwrap();
+
+        // when
+        let ann = catalog.insert_tuple(users, &user(1, Value::Null)).unwrap();
+        catalog.insert_tuple(users, &user(2, Value::Null)).unwrap();
+        let ann = catalog.update_tuple(users, ann, &user(1, Value::Varchar("ann@mine.db".to_string()))).unwrap();
+
+        // then
+        let key_schema = Schema::new(vec![Column::new("email", DataType::Varchar(32)).with_nullable(true)]);
+        let email_key = |email: Value| Tuple::new(&[email], &key_schema).unwrap();
+        let index = catalog.get_index(by_email).unwrap();
+        assert_eq!(index.scan_key(&email_key(Value::Null)).unwrap(), Vec::<Rid>::new());
+        assert_eq!(index.scan_key(&email_key(Value::Varchar("ann@mine.db".to_string()))).unwrap(), [ann]);
+        assert_eq!(catalog.get_table("users").unwrap().heap().iter().count(), 2);
+    }
+
+    #[test]
+    fn should_rewrite_heap_in_index_order_and_rebuild_indexes_when_clustered() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(50)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer).with_nullable(true), Column::new("name", DataType::Varchar(32))]);
+        let users = catalog.create_table("users", schema.clone()).unwrap().oid();
+        let by_id = catalog.create_index("users", &["id"], IndexKind::BPlusTree).unwrap().oid();
+        let by_name = catalog.create_index("users", &["name"], IndexKind::Hash).unwrap().oid();
+        let user = |id: Value, name: &str| Tuple::new(&[id, Value::Varchar(name.to_string())], &schema).unwrap();
+        let mut ids: Vec<i32> = (0..300).collect();
+        ids.shuffle(&mut StdRng::seed_from_u64(17));
+        catalog.insert_tuple(users, &user(Value::Null, "nobody")).unwrap();
+        for id in &ids {
+            let rid = catalog.insert_tuple(users, &user(Value::Integer(*id), &format!("user{}", id))).unwrap();
+            if id % 10 == 0 {
+                catalog.delete_tuple(users, rid).unwrap();
+            }
+        }
+        let old_first_page_id = catalog.get_table("users").unwrap().heap().first_page_id();
+        let old_by_name_root = catalog.get_index(by_name).unwrap().root_page_id();
+
+        // when
+        let hash_err = catalog.cluster("users", by_name).err().unwrap();
+        let missing_err = catalog.cluster("users", 99).err().unwrap();
+        catalog.cluster("users", by_id).unwrap();
+        let reopened = Catalog::open(bpm.clone(), catalog.page_id()).unwrap();
+
+        // then
+        assert!(matches!(CatalogError::from_io(&hash_err), Some(CatalogError::IndexNotOrdered { index }) if *index == by_name));
+        assert!(matches!(CatalogError::from_io(&missing_err), Some(CatalogError::IndexNotFound { index: 99, .. })));
+        let table = reopened.get_table("users").unwrap();
+        let scanned: Vec<(Rid, Value)> = table.scan().map(|entry| {
+            let (rid, tuple) = entry.unwrap();
+            (rid, tuple.get_value(&schema, 0).unwrap())
+        }).collect();
+        let expected: Vec<Value> = (0..300).filter(|id| id % 10 != 0).map(Value::Integer).chain(iter::once(Value::Null)).collect();
+        assert_eq!(scanned.iter().map(|(_, id)| id.clone()).collect::<Vec<Value>>(), expected);
+        let id_key = |id: i32| Tuple::new(&[Value::Integer(id)], &Schema::new(vec![Column::new("id", DataType::Integer).with_nullable(true)])).unwrap();
+        let name_key = |name: &str| Tuple::new(&[Value::Varchar(name.to_string())], &Schema::new(vec![Column::new("name", DataType::Varchar(32))])).unwrap();
+        for (rid, id) in &scanned {
+            if let Value::Integer(id) = id {
+                assert_eq!(reopened.get_index(by_id).unwrap().scan_key(&id_key(*id)).unwrap(), [*rid]);
+                assert_eq!(reopened.get_index(by_name).unwrap().scan_key(&name_key(&format!("user{}", id))).unwrap(), [*rid]);
+            }
+        }
+        assert_eq!(reopened.get_index(by_id).unwrap().scan_key(&id_key(10)).unwrap(), Vec::<Rid>::new());
+        assert_ne!(table.heap().first_page_id(), old_first_page_id);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(old_first_page_id), None);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(old_by_name_root), None);
+    }
+
+    #[test]
+    fn should_enforce_primary_key_on_inserts_and_updates() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let users = catalog.create_table_with_primary_key("users", schema(), &["id"], IndexKind::Hash).unwrap().oid();
+        let user = |id: i32, name: &str| Tuple::new(&[Value::Integer(id), Value::Varchar(name.to_string())], &schema()).unwrap();
+        let ann = catalog.insert_tuple(users, &user(1, "ann")).unwrap();
+        let bob = catalog.insert_tuple(users, &user(2, "bob")).unwrap();
+
+        // when
+        let duplicate = catalog.insert_tuple(users, &user(1, "eve")).err().unwrap();
+        let taken = catalog.update_tuple(users, bob, &user(1, "bob")).err().unwrap();
+        let bob = catalog.update_tuple(users, bob, &user(3, "bob")).unwrap();
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        let pk = reopened.primary_key(users).unwrap();
+        assert!(pk.is_primary_key() && pk.is_unique());
+        assert!(matches!(CatalogError::from_io(&duplicate), Some(CatalogError::DuplicateKey { index }) if *index == pk.oid()));
+        assert!(matches!(CatalogError::from_io(&taken), Some(CatalogError::DuplicateKey { index }) if *index == pk.oid()));
+        let id_key = |id: i32| Tuple::new(&[Value::Integer(id)], pk.key_schema()).unwrap();
+        assert_eq!(pk.scan_key(&id_key(1)).unwrap(), [ann]);
+        assert_eq!(pk.scan_key(&id_key(2)).unwrap(), Vec::<Rid>::new());
+        assert_eq!(pk.scan_key(&id_key(3)).unwrap(), [bob]);
+        let nullable = Schema::new(vec![Column::new("email", DataType::Varchar(32)).with_nullable(true)]);
+        let err = catalog.create_table_with_primary_key("emails", nullable, &["email"], IndexKind::BPlusTree).err().unwrap();
+        assert!(matches!(CatalogError::from_io(&err), Some(CatalogError::NullablePrimaryKey { column, .. }) if column == "email"));
+        assert!(catalog.get_table("emails").is_none());
+    }
+
+    #[test]
+    fn should_check_referenced_keys_and_cascade_deletes_through_foreign_keys() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(30)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let users = catalog.create_table_with_primary_key("users", schema(), &["id"], IndexKind::BPlusTree).unwrap().oid();
+        let orders_schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("user_id", DataType::Integer).with_nullable(true)]);
+        let orders = catalog.create_table("orders", orders_schema.clone()).unwrap().oid();
+        let fk = catalog.create_foreign_key("orders", &["user_id"], "users", &["id"], ForeignKeyAction::Cascade, ForeignKeyAction::Restrict).unwrap().oid();
+        let user = |id: i32, name: &str| Tuple::new(&[Value::Integer(id), Value::Varchar(name.to_string())], &schema()).unwrap();
+        let order = |id: i32, user_id: Value| Tuple::new(&[Value::Integer(id), user_id], &orders_schema).unwrap();
+        let ann = catalog.insert_tuple(users, &user(1, "ann")).unwrap();
+        catalog.insert_tuple(users, &user(2, "bob")).unwrap();
+        catalog.insert_tuple(orders, &order(10, Value::Integer(1))).unwrap();
+        catalog.insert_tuple(orders, &order(11, Value::Integer(1))).unwrap();
+        catalog.insert_tuple(orders, &order(20, Value::Integer(2))).unwrap();
+        catalog.insert_tuple(orders, &order(30, Value::Null)).unwrap();
+
+        // when
+        let missing = catalog.insert_tuple(orders, &order(40, Value::Integer(4))).err().unwrap();
+        let restricted = catalog.update_tuple(users, ann, &user(5, "ann")).err().unwrap();
+        let renamed = catalog.update_tuple(users, ann, &user(1, "anna")).unwrap();
+        let deleted = catalog.delete_tuple(users, renamed).unwrap();
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        assert!(matches!(CatalogError::from_io(&missing), Some(CatalogError::ReferencedKeyNotFound { foreign_key }) if *foreign_key == fk));
+        assert!(matches!(CatalogError::from_io(&restricted), Some(CatalogError::KeyStillReferenced { foreign_key }) if *foreign_key == fk));
+        assert!(deleted);
+        let order_ids: Vec<Value> = reopened.get_table("orders").unwrap().scan()
+            .map(|entry| entry.unwrap().1.get_value(&orders_schema, 0).unwrap())
+            .collect();
+        assert_eq!(order_ids, [Value::Integer(20), Value::Integer(30)]);
+        assert_eq!(reopened.get_table("users").unwrap().heap().iter().count(), 1);
+        let foreign_key = reopened.get_foreign_key(fk).unwrap();
+        assert_eq!((foreign_key.table_oid(), foreign_key.columns(), foreign_key.referenced_table_oid()), (orders, &[1][..], users));
+        assert_eq!(reopened.table_foreign_keys(orders).len(), 1);
+        let err = catalog.create_foreign_key("orders", &["id"], "users", &["name"], ForeignKeyAction::Restrict, ForeignKeyAction::Restrict).err().unwrap();
+        assert!(matches!(CatalogError::from_io(&err), Some(CatalogError::ForeignKeyMismatch { column }) if column == "id"));
+    }
+
+    #[test]
+    fn should_cascade_key_updates_and_restrict_deletes_through_foreign_keys() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(30)));
+        let mut catalog = Catalog::new(bpm).unwrap();
+        let users = catalog.create_table("users", schema()).unwrap().oid();
+        let orders_schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("user_name", DataType::Varchar(32))]);
+        let orders = catalog.create_table("orders", orders_schema.clone()).unwrap().oid();
+        let user = |id: i32, name: &str| Tuple::new(&[Value::Integer(id), Value::Varchar(name.to_string())], &schema()).unwrap();
+        let order = |id: i32, user_name: &str| Tuple::new(&[Value::Integer(id), Value::Varchar(user_name.to_string())], &orders_schema).unwrap();
+        let ann = catalog.insert_tuple(users, &user(1, "ann")).unwrap();
+        catalog.insert_tuple(orders, &order(10, "ann")).unwrap();
+        let not_unique = catalog.create_foreign_key("orders", &["user_name"], "users", &["name"], ForeignKeyAction::Restrict, ForeignKeyAction::Cascade).err().unwrap();
+        catalog.create_index("users", &["name"], IndexKind::BPlusTree).unwrap();
+        catalog.create_index("orders", &["user_name"], IndexKind::Hash).unwrap();
+        let fk = catalog.create_foreign_key("orders", &["user_name"], "users", &["name"], ForeignKeyAction::Restrict, ForeignKeyAction::Cascade).unwrap().oid();
+
+        // when
+        let ann = catalog.update_tuple(users, ann, &user(1, "anna")).unwrap();
+        let restricted = catalog.delete_tuple(users, ann).err().unwrap();
+
+        // then
+        assert!(matches!(CatalogError::from_io(&not_unique), Some(CatalogError::ReferencedKeyNotUnique { table }) if table == "users"));
+        assert!(matches!(CatalogError::from_io(&restricted), Some(CatalogError::KeyStillReferenced { foreign_key }) if *foreign_key == fk));
+        let orders: Vec<Tuple> = catalog.get_table("orders").unwrap().scan().map(|entry| entry.unwrap().1).collect();
+        assert_eq!(orders, [order(10, "anna")]);
+        assert_eq!(catalog.get_table("users").unwrap().get_tuple(ann).unwrap(), Some(user(1, "anna")));
+    }
+
+    #[test]
+    fn should_fill_defaults_of_omitted_columns_and_refuse_nulls_where_not_allowed() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Integer),
+            Column::new("status", DataType::Varchar(8)).with_default(Value::Varchar("new".to_string())),
+            Column::new("note", DataType::Varchar(32)).with_nullable(true),
+        ]);
+        let tasks = catalog.create_table("tasks", schema.clone()).unwrap().oid();
+        let mut null_id = Tuple::new(&[Value::Integer(0), Value::Varchar("new".to_string()), Value::Null], &schema).unwrap().data().to_vec();
+        null_id[0] |= 0x01;
+
+        // when
+        let rid = catalog.insert_values(tasks, &["note", "id"], &[Value::Varchar("first".to_string()), Value::Integer(1)]).unwrap();
+        let missing = catalog.insert_values(tasks, &["status"], &[Value::Varchar("done".to_string())]).err().unwrap();
+        let null = catalog.insert_values(tasks, &["id", "status"], &[Value::Integer(2), Value::Null]).err().unwrap();
+        let raw_null = catalog.insert_tuple(tasks, &Tuple::from_bytes(null_id)).err().unwrap();
+        let bad_default = catalog.create_table("flags", Schema::new(vec![Column::new("flag", DataType::Boolean).with_default(Value::Null)])).err().unwrap();
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        let expected = Tuple::new(&[Value::Integer(1), Value::Varchar("new".to_string()), Value::Varchar("first".to_string())], &schema).unwrap();
+        assert_eq!(reopened.get_table("tasks").unwrap().get_tuple(rid).unwrap(), Some(expected));
+        assert_eq!(reopened.get_table("tasks").unwrap().schema(), &schema);
+        assert!(matches!(TableError::from_io(&missing), Some(TableError::MissingValue { column }) if column == "id"));
+        assert!(matches!(TableError::from_io(&null), Some(TableError::NullNotAllowed { column }) if column == "status"));
+        assert!(matches!(TableError::from_io(&raw_null), Some(TableError::NullNotAllowed { column }) if column == "id"));
+        assert!(matches!(TableError::from_io(&bad_default), Some(TableError::NullNotAllowed { column }) if column == "flag"));
+        assert_eq!(catalog.get_table("tasks").unwrap().heap().iter().count(), 1);
+    }
+
+    #[test]
+    fn should_add_column_and_read_older_tuples_with_its_default() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let users = catalog.create_table("users", schema()).unwrap().oid();
+        let ann = catalog.insert_tuple(users, &Tuple::new(&[Value::Integer(1), Value::Varchar("ann".to_string())], &schema()).unwrap()).unwrap();
+
+        // when
+        let altered = catalog.alter_table_add_column("users", Column::new("score", DataType::BigInt), Value::Integer(10)).unwrap().schema().clone();
+        let bob_tuple = Tuple::new(&[Value::Integer(2), Value::Varchar("bob".to_string()), Value::BigInt(20)], &altered).unwrap();
+        let bob = catalog.insert_tuple(users, &bob_tuple).unwrap();
+        let by_score = catalog.create_index("users", &["score"], IndexKind::Hash).unwrap().oid();
+        let errors = [
+            catalog.alter_table_add_column("users", Column::new("name", DataType::Integer), Value::Integer(0)).err().unwrap(),
+            catalog.alter_table_add_column("users", Column::new("email", DataType::Varchar(8)), Value::Null).err().unwrap(),
+        ];
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        let table = reopened.get_table("users").unwrap();
+        assert_eq!(table.schema_version(), 1);
+        assert_eq!(table.heap().get_versioned_tuple(ann).unwrap().unwrap().0, 0);
+        let ann_values = vec![Value::Integer(1), Value::Varchar("ann".to_string()), Value::BigInt(10)];
+        assert_eq!(table.get_tuple(ann).unwrap().unwrap().values(&altered).unwrap(), ann_values);
+        let scanned: Vec<Rid> = table.scan().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(scanned, [ann, bob]);
+        let score_key = Tuple::new(&[Value::BigInt(10)], &Schema::new(vec![Column::new("score", DataType::BigInt)])).unwrap();
+        assert_eq!(reopened.get_index(by_score).unwrap().scan_key(&score_key).unwrap(), [ann]);
+        assert!(matches!(CatalogError::from_io(&errors[0]), Some(CatalogError::ColumnExists { column, .. }) if column == "name"));
+        assert!(matches!(TableError::from_io(&errors[1]), Some(TableError::NullNotAllowed { column }) if column == "email"));
+        assert_eq!(catalog.get_table("users").unwrap().schema().len(), 3);
+    }
+
+    #[test]
+    fn should_store_long_varchars_out_of_line_and_free_them_with_the_tuple() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("body", DataType::Varchar(20000))]);
+        let posts = catalog.create_table("posts", schema.clone()).unwrap().oid();
+        let post = |body: String| Tuple::new(&[Value::Integer(1), Value::Varchar(body)], &schema).unwrap();
+        let long_body = "mine".repeat(2500);
+
+        // when
+        let rid = catalog.insert_tuple(posts, &post(long_body.clone())).unwrap();
+        let stored = catalog.get_table_by_oid(posts).unwrap().heap().get_tuple(rid).unwrap().unwrap();
+        let overflow_ref = match stored.varchar_data(&schema, 1).unwrap() {
+            Some(VarcharData::OutOfLine(overflow_ref)) => overflow_ref,
+            other => panic!("expected the body out of line, got {:?}", other),
+        };
+        let table = catalog.get_table_by_oid(posts).unwrap();
+        let read = table.get_tuple(rid).unwrap().unwrap();
+        let scanned: Vec<Tuple> = table.scan().map(|entry| entry.unwrap().1).collect();
+        let rid = catalog.update_tuple(posts, rid, &post("short".to_string())).unwrap();
+        let freed_on_update = bpm.lock().unwrap().pin_count_of(overflow_ref.page_id).is_none();
+        let rid = catalog.update_tuple(posts, rid, &post(long_body.clone())).unwrap();
+        catalog.delete_tuple(posts, rid).unwrap();
+
+        // then
+        assert_eq!(overflow_ref.len, 10000);
+        assert!(stored.size() < 300);
+        assert_eq!(read.values(&schema).unwrap(), vec![Value::Integer(1), Value::Varchar(long_body.clone())]);
+        assert_eq!(scanned, vec![post(long_body)]);
+        assert!(freed_on_update);
+        assert!(catalog.get_table_by_oid(posts).unwrap().scan().next().is_none());
+    }
+
+    #[test]
+    fn should_analyze_table_and_read_its_statistics_once_reopened() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let mut catalog = Catalog::new(bpm.clone()).unwrap();
+        let users = catalog.create_table("users", schema()).unwrap().oid();
+        for id in 1..=10 {
+            let tuple = Tuple::new(&[Value::Integer(id), Value::Varchar(format!("user_{}", id % 5))], &schema()).unwrap();
+            catalog.insert_tuple(users, &tuple).unwrap();
+        }
+        let never_analyzed = catalog.table_statistics(users).unwrap();
+
+        // when
+        catalog.analyze("users").unwrap();
+        catalog.insert_tuple(users, &Tuple::new(&[Value::Integer(11), Value::Varchar("user_1".to_string())], &schema()).unwrap()).unwrap();
+        let analyzed = catalog.analyze("users").unwrap();
+        let err = catalog.analyze("orders").err().unwrap();
+        let reopened = Catalog::open(bpm, catalog.page_id()).unwrap();
+
+        // then
+        assert_eq!(never_analyzed, None);
+        assert_eq!(reopened.table_statistics(users).unwrap(), Some(analyzed.clone()));
+        assert_eq!(analyzed.row_count(), 11);
+        let (id, name) = (analyzed.column(0).unwrap(), analyzed.column(1).unwrap());
+        assert_eq!((id.min(), id.max()), (Some(&Value::Integer(1)), Some(&Value::Integer(11))));
+        assert_eq!((name.distinct_count(), name.null_fraction()), (5, 0.0));
+        assert!(matches!(CatalogError::from_io(&err), Some(CatalogError::TableNotFound { name }) if name == "orders"));
+    }
+}
+
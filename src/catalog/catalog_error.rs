@@ -0,0 +1,80 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::catalog::catalog::TableOid;
+use crate::catalog::foreign_key::ForeignKeyOid;
+use crate::catalog::index::IndexOid;
+use crate::common::error::cause_of;
+
+/// Failures of changing the catalog or writing tuples through it, carried inside `io::Error` so
+/// callers can match on them.
+#[derive(Debug)]
+pub enum CatalogError {
+    TableExists { name: String },
+    TableNotFound { name: String },
+    TableOidNotFound { oid: TableOid },
+    ColumnNotFound { table: String, column: String },
+    ColumnExists { table: String, column: String },
+    /// The largest key of the index columns does not fit into the index.
+    KeyTooLarge { size: usize, max: usize },
+    /// A tuple with the key is in the unique index already.
+    DuplicateKey { index: IndexOid },
+    /// A primary key column takes nulls, which the key could not tell apart.
+    NullablePrimaryKey { table: String, column: String },
+    /// No unique index of the referenced table is keyed by exactly the referenced columns.
+    ReferencedKeyNotUnique { table: String },
+    /// The referencing and referenced columns differ in number or type, at `column`.
+    ForeignKeyMismatch { column: String },
+    /// A tuple references a key not in the referenced table.
+    ReferencedKeyNotFound { foreign_key: ForeignKeyOid },
+    /// A key restricted by a foreign key is referenced by a tuple and cannot be deleted or updated.
+    KeyStillReferenced { foreign_key: ForeignKeyOid },
+    IndexNotFound { table: String, index: IndexOid },
+    /// The index keeps no key order, as a hash index.
+    IndexNotOrdered { index: IndexOid },
+}
+
+impl CatalogError {
+    pub fn from_io(err: &io::Error) -> Option<&CatalogError> {
+        cause_of(err)
+    }
+}
+
+impl Display for CatalogError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogError::TableExists { name } => write!(f, "Table {} already exists.", name),
+            CatalogError::TableNotFound { name } => write!(f, "No table {}.", name),
+            CatalogError::TableOidNotFound { oid } => write!(f, "No table of oid {}.", oid),
+            CatalogError::ColumnNotFound { table, column } => write!(f, "Table {} has no column {}.", table, column),
+            CatalogError::ColumnExists { table, column } => write!(f, "Table {} already has a column {}.", table, column),
+            CatalogError::KeyTooLarge { size, max } =>
+                write!(f, "Keys of up to {} bytes too large for the index, at most {} allowed.", size, max),
+            CatalogError::DuplicateKey { index } => write!(f, "Key already in unique index {}.", index),
+            CatalogError::NullablePrimaryKey { table, column } =>
+                write!(f, "Column {} of table {} takes nulls and cannot be in its primary key.", column, table),
+            CatalogError::ReferencedKeyNotUnique { table } => write!(f, "No unique index of table {} over the referenced columns.", table),
+            CatalogError::ForeignKeyMismatch { column } => write!(f, "Column {} does not match the referenced column.", column),
+            CatalogError::ReferencedKeyNotFound { foreign_key } => write!(f, "Key referenced through foreign key {} not found.", foreign_key),
+            CatalogError::KeyStillReferenced { foreign_key } => write!(f, "Key still referenced through foreign key {}.", foreign_key),
+            CatalogError::IndexNotFound { table, index } => write!(f, "Table {} has no index {}.", table, index),
+            CatalogError::IndexNotOrdered { index } => write!(f, "Index {} keeps no key order.", index),
+        }
+    }
+}
+
+impl error::Error for CatalogError {}
+
+impl From<CatalogError> for io::Error {
+    fn from(e: CatalogError) -> Self {
+        let kind = match e {
+            CatalogError::TableExists { .. } | CatalogError::ColumnExists { .. } | CatalogError::DuplicateKey { .. } => io::ErrorKind::AlreadyExists,
+            CatalogError::TableNotFound { .. } | CatalogError::TableOidNotFound { .. } | CatalogError::ColumnNotFound { .. }
+            | CatalogError::ReferencedKeyNotFound { .. } | CatalogError::IndexNotFound { .. } => io::ErrorKind::NotFound,
+            CatalogError::KeyTooLarge { .. } | CatalogError::NullablePrimaryKey { .. } | CatalogError::ReferencedKeyNotUnique { .. }
+            | CatalogError::ForeignKeyMismatch { .. } | CatalogError::KeyStillReferenced { .. }
+            | CatalogError::IndexNotOrdered { .. } => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::catalog::TableOid;
+use crate::catalog::index::IndexOid;
+use crate::storage::page::catalog_page::ForeignKeyEntry;
+
+/// Id of a foreign key, handed out by the catalog from the same sequence as table oids.
+pub type ForeignKeyOid = u32;
+
+/// What becomes of the referencing tuples when the key they reference is deleted or updated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForeignKeyAction {
+    /// The key cannot change while a tuple references it.
+    Restrict,
+    /// Referencing tuples are deleted with the key, or take its new values.
+    Cascade,
+}
+
+/// Foreign key of a table: its `columns` hold a key of the unique index over the referenced columns of
+/// another table, or nulls. A key with a null references nothing and is not checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKeyInfo {
+    oid: ForeignKeyOid,
+    table_oid: TableOid,
+    columns: Vec<usize>,
+    referenced_table_oid: TableOid,
+    referenced_index_oid: IndexOid,
+    on_delete: ForeignKeyAction,
+    on_update: ForeignKeyAction,
+}
+
+impl ForeignKeyInfo {
+    pub(crate) fn new(oid: ForeignKeyOid, table_oid: TableOid, columns: Vec<usize>, referenced_table_oid: TableOid,
+                      referenced_index_oid: IndexOid, on_delete: ForeignKeyAction, on_update: ForeignKeyAction) -> ForeignKeyInfo {
+        ForeignKeyInfo { oid, table_oid, columns, referenced_table_oid, referenced_index_oid, on_delete, on_update }
+    }
+
+    pub(crate) fn open(entry: &ForeignKeyEntry) -> ForeignKeyInfo {
+        ForeignKeyInfo::new(entry.oid, entry.table_oid, entry.columns.clone(), entry.referenced_table_oid,
+                            entry.referenced_index_oid, entry.on_delete, entry.on_update)
+    }
+
+    pub fn oid(&self) -> ForeignKeyOid {
+        self.oid
+    }
+
+    /// Table holding the referencing tuples.
+    pub fn table_oid(&self) -> TableOid {
+        self.table_oid
+    }
+
+    /// Indices of the referencing columns, in the key order of the referenced index.
+    pub fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    pub fn referenced_table_oid(&self) -> TableOid {
+        self.referenced_table_oid
+    }
+
+    /// Unique index of the referenced table the keys are looked up in.
+    pub fn referenced_index_oid(&self) -> IndexOid {
+        self.referenced_index_oid
+    }
+
+    pub fn on_delete(&self) -> ForeignKeyAction {
+        self.on_delete
+    }
+
+    pub fn on_update(&self) -> ForeignKeyAction {
+        self.on_update
+    }
+
+    pub(crate) fn to_entry(&self) -> ForeignKeyEntry {
+        ForeignKeyEntry {
+            oid: self.oid,
+            table_oid: self.table_oid,
+            columns: self.columns.clone(),
+            referenced_table_oid: self.referenced_table_oid,
+            referenced_index_oid: self.referenced_index_oid,
+            on_delete: self.on_delete,
+            on_update: self.on_update,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::cmp::Ordering;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::catalog::catalog::TableOid;
+use crate::catalog::catalog_error::CatalogError;
+use crate::common::compare::KeyComparator;
+use crate::container::hash::hash_table::HashTable;
+use crate::container::hash::linear_probe_hash_table::LinearProbeHashTable;
+use crate::container::index::b_plus_tree::BPlusTree;
+use crate::storage::page::catalog_page::IndexEntry;
+use crate::storage::page::page::PageId;
+use crate::storage::table::rid::Rid;
+use crate::storage::table::schema::Schema;
+use crate::storage::table::tuple::Tuple;
+use crate::storage::table::value::Value;
+
+/// Id of an index, handed out by the catalog from the same sequence as table oids.
+pub type IndexOid = u32;
+
+/// Structure an index keeps its entries in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexKind {
+    /// Ordered and unique: a key points to one tuple at most.
+    BPlusTree,
+    /// Unordered, a key may point to several tuples.
+    Hash,
+}
+
+/// Orders the key tuples of a B+ tree index by their values, column by column.
+pub struct KeyTupleComparator {
+    key_schema: Schema,
+}
+
+impl KeyTupleComparator {
+    pub fn new(key_schema: Schema) -> KeyTupleComparator {
+        KeyTupleComparator { key_schema }
+    }
+}
+
+impl KeyComparator<Tuple> for KeyTupleComparator {
+    fn name(&self) -> String {
+        "key_tuple".to_string()
+    }
+
+    fn compare(&self, a: &Tuple, b: &Tuple) -> Ordering {
+        match (a.values(&self.key_schema), b.values(&self.key_schema)) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            // keys are only ever encoded by the index itself, an undecodable one just needs a stable place
+            _ => a.data().cmp(b.data()),
+        }
+    }
+}
+
+enum IndexStore {
+    BPlusTree(BPlusTree<Tuple, Rid>),
+    Hash(LinearProbeHashTable<Tuple, Rid>),
+}
+
+/// Index of a table, mapping the values of its key columns, encoded as a tuple of the key schema, to
+/// the rids of the tuples holding them. Kept up to date by the catalog as tuples are written.
+///
+/// Keys with a null are left out: a null equals no value, not even another null, so no lookup could
+/// find them, and a unique index takes any number of tuples with nulls in their key.
+pub struct IndexInfo {
+    oid: IndexOid,
+    table_oid: TableOid,
+    key_columns: Vec<usize>,
+    key_schema: Schema,
+    kind: IndexKind,
+    primary_key: bool,
+    store: IndexStore,
+    /// Root page as last written to the catalog page, which a hash index leaves when it is resized.
+    pub(crate) stored_root: PageId,
+}
+
+impl IndexInfo {
+    /// Writes the pages of a new, empty index, backing the primary key of the table if `primary_key`.
+    pub(crate) fn create(bpm: Arc<Mutex<BufferPoolManager>>, oid: IndexOid, table_oid: TableOid, key_columns: Vec<usize>, key_schema: Schema, kind: IndexKind, primary_key: bool) -> io::Result<IndexInfo> {
+        let store = match kind {
+            IndexKind::BPlusTree => IndexStore::BPlusTree(BPlusTree::new_with_comparator(bpm, KeyTupleComparator::new(key_schema.clone()))?),
+            IndexKind::Hash => IndexStore::Hash(LinearProbeHashTable::<Tuple, Rid>::builder().build(bpm)?),
+        };
+        Ok(IndexInfo::with_store(oid, table_oid, key_columns, key_schema, kind, primary_key, store))
+    }
+
+    pub(crate) fn open(bpm: Arc<Mutex<BufferPoolManager>>, entry: &IndexEntry, table_schema: &Schema) -> io::Result<IndexInfo> {
+        let key_schema = table_schema.project(&entry.key_columns)?;
+        let store = match entry.kind {
+            IndexKind::BPlusTree => IndexStore::BPlusTree(BPlusTree::open_with_comparator(bpm, entry.root_page_id, KeyTupleComparator::new(key_schema.clone()))?),
+            IndexKind::Hash => IndexStore::Hash(LinearProbeHashTable::<Tuple, Rid>::builder().open(bpm, entry.root_page_id)?),
+        };
+        Ok(IndexInfo::with_store(entry.oid, entry.table_oid, entry.key_columns.clone(), key_schema, entry.kind, entry.primary_key, store))
+    }
+
+    fn with_store(oid: IndexOid, table_oid: TableOid, key_columns: Vec<usize>, key_schema: Schema, kind: IndexKind, primary_key: bool, store: IndexStore) -> IndexInfo {
+        let mut index = IndexInfo { oid, table_oid, key_columns, key_schema, kind, primary_key, store, stored_root: 0 };
+        index.stored_root = index.root_page_id();
+        index
+    }
+
+    pub fn oid(&self) -> IndexOid {
+        self.oid
+    }
+
+    pub fn table_oid(&self) -> TableOid {
+        self.table_oid
+    }
+
+    /// Indices of the columns of the table the index is keyed by, in key order.
+    pub fn key_columns(&self) -> &[usize] {
+        &self.key_columns
+    }
+
+    pub fn key_schema(&self) -> &Schema {
+        &self.key_schema
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        self.kind
+    }
+
+    /// Whether a key points to one tuple at most: a B+ tree always, a hash index if it backs a primary key.
+    pub fn is_unique(&self) -> bool {
+        self.kind == IndexKind::BPlusTree || self.primary_key
+    }
+
+    pub fn is_primary_key(&self) -> bool {
+        self.primary_key
+    }
+
+    /// Page the index is opened from: the meta page of a B+ tree, the first header page of a hash table.
+    pub fn root_page_id(&self) -> PageId {
+        match &self.store {
+            IndexStore::BPlusTree(tree) => tree.meta_page_id(),
+            IndexStore::Hash(table) => table.get_header_pid(),
+        }
+    }
+
+    /// Key of a tuple of the table, whose schema is `table_schema`.
+    pub fn key_of(&self, tuple: &Tuple, table_schema: &Schema) -> io::Result<Tuple> {
+        let values = self.key_columns.iter()
+            .map(|idx| tuple.get_value(table_schema, *idx))
+            .collect::<io::Result<Vec<Value>>>()?;
+        Tuple::new(&values, &self.key_schema)
+    }
+
+    /// Rids of the tuples with `key`, in no particular order for a hash index. None for a key with a null.
+    pub fn scan_key(&self, key: &Tuple) -> io::Result<Vec<Rid>> {
+        if key.has_null(&self.key_schema) {
+            return Ok(Vec::new())
+        }
+
+        match &self.store {
+            IndexStore::BPlusTree(tree) => Ok(tree.get(key)?.into_iter().collect()),
+            IndexStore::Hash(table) => Ok(table.clone().get_value(key)?),
+        }
+    }
+
+    /// Returns false if the entry could not be added, as the key of a unique index is taken.
+    pub(crate) fn insert_entry(&mut self, key: &Tuple, rid: Rid) -> io::Result<bool> {
+        if key.has_null(&self.key_schema) {
+            return Ok(true)
+        }
+
+        match &mut self.store {
+            IndexStore::BPlusTree(tree) => tree.insert(key, &rid),
+            IndexStore::Hash(table) => Ok(table.insert(key, &rid)?),
+        }
+    }
+
+    /// Returns whether the index held the entry.
+    pub(crate) fn remove_entry(&mut self, key: &Tuple, rid: Rid) -> io::Result<bool> {
+        if key.has_null(&self.key_schema) {
+            return Ok(false)
+        }
+
+        match &mut self.store {
+            IndexStore::BPlusTree(tree) if tree.get(key)? == Some(rid) => tree.remove(key),
+            IndexStore::BPlusTree(_) => Ok(false),
+            IndexStore::Hash(table) => Ok(table.remove_kv(key, &rid)?),
+        }
+    }
+
+    /// Rids of the tuples in key order, tuples with a null in their key left out. Fails with
+    /// `CatalogError::IndexNotOrdered` for a hash index.
+    pub(crate) fn rids_in_key_order(&self) -> io::Result<Vec<Rid>> {
+        match &self.store {
+            IndexStore::BPlusTree(tree) => {
+                let mut rids = tree.range_rev(..)?.map(|entry| entry.map(|(_, rid)| rid)).collect::<io::Result<Vec<Rid>>>()?;
+                rids.reverse();
+                Ok(rids)
+            }
+            IndexStore::Hash(_) => Err(CatalogError::IndexNotOrdered { index: self.oid }.into()),
+        }
+    }
+
+    /// Deletes every page of the index.
+    pub(crate) fn drop_index(self) -> io::Result<()> {
+        match self.store {
+            IndexStore::BPlusTree(tree) => tree.drop_tree(),
+            IndexStore::Hash(table) => table.drop_table(),
+        }
+    }
+
+    pub(crate) fn to_entry(&self) -> IndexEntry {
+        IndexEntry {
+            oid: self.oid,
+            table_oid: self.table_oid,
+            key_columns: self.key_columns.clone(),
+            kind: self.kind,
+            root_page_id: self.root_page_id(),
+            primary_key: self.primary_key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::catalog::index::KeyTupleComparator;
+    use crate::common::compare::KeyComparator;
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::tuple::Tuple;
+    use crate::storage::table::value::Value;
+
+    #[test]
+    fn should_order_key_tuples_by_values_not_bytes() {
+        // given
+        let key_schema = Schema::new(vec![Column::new("age", DataType::Integer).with_nullable(true), Column::new("name", DataType::Varchar(8))]);
+        let key = |age: Value, name: &str| Tuple::new(&[age, Value::Varchar(name.to_string())], &key_schema).unwrap();
+        let comparator = KeyTupleComparator::new(key_schema.clone());
+
+        // when
+        let ordered = [comparator.compare(&key(Value::Integer(2), "b"), &key(Value::Integer(256), "a")),
+            comparator.compare(&key(Value::Integer(-1), "a"), &key(Value::Integer(1), "a")),
+            comparator.compare(&key(Value::Integer(7), "b"), &key(Value::Integer(7), "a")),
+            comparator.compare(&key(Value::Null, "z"), &key(Value::Integer(i32::MIN), "a"))];
+
+        // then
+        assert_eq!(ordered, [Ordering::Less, Ordering::Less, Ordering::Greater, Ordering::Less]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::catalog::catalog::Catalog;
+use crate::maintenance::scheduler::MaintenanceTask;
+
+pub const VACUUM_TASK: &str = "vacuum";
+pub const ANALYZE_TASK: &str = "analyze";
+
+fn table_names(catalog: &Catalog) -> Vec<String> {
+    catalog.list_tables().iter().map(|table| table.name().to_string()).collect()
+}
+
+/// Vacuums every table of the catalog whenever the scheduler runs it, see `Catalog::vacuum`.
+/// A table failing to vacuum fails the run, leaving the tables after it for the next one.
+pub struct VacuumTask {
+    catalog: Arc<Mutex<Catalog>>,
+}
+
+impl VacuumTask {
+    pub fn new(catalog: Arc<Mutex<Catalog>>) -> VacuumTask {
+        VacuumTask { catalog }
+    }
+}
+
+impl MaintenanceTask for VacuumTask {
+    fn name(&self) -> &str {
+        VACUUM_TASK
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let mut catalog = self.catalog.lock().unwrap();
+        for table in table_names(&catalog) {
+            catalog.vacuum(&table)?;
+        }
+        Ok(())
+    }
+}
+
+/// Refreshes the statistics of every table of the catalog whenever the scheduler runs it, see
+/// `Catalog::analyze`. Fails the run like `VacuumTask`.
+pub struct AnalyzeTask {
+    catalog: Arc<Mutex<Catalog>>,
+}
+
+impl AnalyzeTask {
+    pub fn new(catalog: Arc<Mutex<Catalog>>) -> AnalyzeTask {
+        AnalyzeTask { catalog }
+    }
+}
+
+impl MaintenanceTask for AnalyzeTask {
+    fn name(&self) -> &str {
+        ANALYZE_TASK
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let mut catalog = self.catalog.lock().unwrap();
+        for table in table_names(&catalog) {
+            catalog.analyze(&table)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::catalog::catalog::{Catalog, TableOid};
+    use crate::catalog::maintenance::{AnalyzeTask, VacuumTask};
+    use crate::maintenance::scheduler::MaintenanceTask;
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::tuple::Tuple;
+    use crate::storage::table::value::Value;
+
+    #[test]
+    fn should_vacuum_and_analyze_every_table() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("name", DataType::Varchar(1000))]);
+        let catalog = Arc::new(Mutex::new(Catalog::new(bpm).unwrap()));
+        let oids = {
+            let mut catalog = catalog.lock().unwrap();
+            let oids: Vec<TableOid> = ["users", "orders"].iter().map(|name| catalog.create_table(name, schema.clone()).unwrap().oid()).collect();
+            for oid in &oids {
+                for i in 0..10 {
+                    let tuple = Tuple::new(&[Value::Integer(i), Value::Varchar("x".repeat(900))], &schema).unwrap();
+                    let rid = catalog.insert_tuple(*oid, &tuple).unwrap();
+                    if i < 8 {
+                        catalog.delete_tuple(*oid, rid).unwrap();
+                    }
+                }
+            }
+            oids
+        };
+
+        // when
+        VacuumTask::new(catalog.clone()).run().unwrap();
+        AnalyzeTask::new(catalog.clone()).run().unwrap();
+
+        // then
+        let mut catalog = catalog.lock().unwrap();
+        for oid in oids {
+            let name = catalog.get_table_by_oid(oid).unwrap().name().to_string();
+            assert_eq!(catalog.table_statistics(oid).unwrap().unwrap().row_count(), 2);
+            assert_eq!(catalog.vacuum(&name).unwrap().pages_freed, 0);
+        }
+    }
+}
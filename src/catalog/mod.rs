@@ -0,0 +1,14 @@
+#[allow(clippy::module_inception)]
+pub mod catalog;
+pub mod catalog_error;
+pub mod foreign_key;
+pub mod index;
+pub mod maintenance;
+pub mod statistics;
+
+pub use catalog::{Catalog, TableInfo, TableOid};
+pub use catalog_error::CatalogError;
+pub use foreign_key::{ForeignKeyAction, ForeignKeyInfo, ForeignKeyOid};
+pub use index::{IndexInfo, IndexKind, IndexOid};
+pub use maintenance::{AnalyzeTask, VacuumTask};
+pub use statistics::{ColumnStatistics, TableStatistics};
@@ -0,0 +1,172 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+use fasthash::XXHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::common::hyper_log_log::HyperLogLog;
+use crate::storage::table::schema::Schema;
+use crate::storage::table::tuple::Tuple;
+use crate::storage::table::value::Value;
+
+/// Statistics of a table as of its last `Catalog::analyze`, for a planner to estimate the rows a plan
+/// reads. They are not kept up to date as tuples change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStatistics {
+    row_count: u64,
+    columns: Vec<ColumnStatistics>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStatistics {
+    min: Option<Value>,
+    max: Option<Value>,
+    null_fraction: f64,
+    distinct_count: u64,
+}
+
+impl TableStatistics {
+    /// Collects the statistics of tuples encoded after `schema` in one pass.
+    pub fn collect<I: Iterator<Item = io::Result<Tuple>>>(schema: &Schema, tuples: I) -> io::Result<TableStatistics> {
+        let mut row_count = 0;
+        let mut collectors: Vec<ColumnCollector> = (0..schema.len()).map(|_| ColumnCollector::default()).collect();
+        for tuple in tuples {
+            let values = tuple?.values(schema)?;
+            for (collector, value) in collectors.iter_mut().zip(values) {
+                collector.add(value);
+            }
+            row_count += 1;
+        }
+
+        let columns = collectors.into_iter().map(|collector| collector.finish(row_count)).collect();
+        Ok(TableStatistics { row_count, columns })
+    }
+
+    pub fn row_count(&self) -> u64 {
+        self.row_count
+    }
+
+    /// Statistics of column `idx` of the schema the table had when analyzed.
+    pub fn column(&self, idx: usize) -> Option<&ColumnStatistics> {
+        self.columns.get(idx)
+    }
+
+    pub fn columns(&self) -> &[ColumnStatistics] {
+        &self.columns
+    }
+}
+
+impl ColumnStatistics {
+    /// Smallest non-null value, `None` if there is none or the type has no order, e.g. a blob.
+    pub fn min(&self) -> Option<&Value> {
+        self.min.as_ref()
+    }
+
+    pub fn max(&self) -> Option<&Value> {
+        self.max.as_ref()
+    }
+
+    /// Share of the rows with a null, 0 for an empty table.
+    pub fn null_fraction(&self) -> f64 {
+        self.null_fraction
+    }
+
+    /// Estimate of the distinct non-null values, see `HyperLogLog`.
+    pub fn distinct_count(&self) -> u64 {
+        self.distinct_count
+    }
+}
+
+#[derive(Default)]
+struct ColumnCollector {
+    min: Option<Value>,
+    max: Option<Value>,
+    unordered: bool,
+    nulls: u64,
+    distinct: HyperLogLog,
+}
+
+impl ColumnCollector {
+    fn add(&mut self, value: Value) {
+        if value.is_null() {
+            self.nulls += 1;
+            return;
+        }
+
+        let mut hasher: XXHasher = Default::default();
+        value.hash(&mut hasher);
+        self.distinct.insert_hash(hasher.finish());
+        if self.unordered || value.compare(&value).is_err() {
+            // only values of a type without any order fail to compare, e.g. blobs
+            self.unordered = true;
+            return;
+        }
+        let below_min = match &self.min {
+            Some(min) => value.compare(min).ok() == Some(Ordering::Less),
+            None => true,
+        };
+        let above_max = match &self.max {
+            Some(max) => value.compare(max).ok() == Some(Ordering::Greater),
+            None => true,
+        };
+        if above_max {
+            self.max = Some(value.clone());
+        }
+        if below_min {
+            self.min = Some(value);
+        }
+    }
+
+    fn finish(self, row_count: u64) -> ColumnStatistics {
+        ColumnStatistics {
+            min: self.min,
+            max: self.max,
+            null_fraction: if row_count == 0 { 0.0 } else { self.nulls as f64 / row_count as f64 },
+            distinct_count: self.distinct.estimate(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::statistics::TableStatistics;
+    use crate::storage::page::overflow_page::OverflowRef;
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::tuple::Tuple;
+    use crate::storage::table::value::Value;
+
+    #[test]
+    fn should_collect_bounds_null_fraction_and_distinct_count_of_each_column() {
+        // given
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Integer),
+            Column::new("city", DataType::Varchar(16)).with_nullable(true),
+            Column::new("photo", DataType::Blob),
+        ]);
+        let cities = ["oslo", "lima", "rome"];
+        let tuples: Vec<Tuple> = (0..100)
+            .map(|i| {
+                let city = if i % 4 == 0 { Value::Null } else { Value::Varchar(cities[i as usize % 3].to_string()) };
+                Tuple::new(&[Value::Integer(50 - i), city, Value::Blob(OverflowRef { page_id: i as usize, len: 1 })], &schema).unwrap()
+            })
+            .collect();
+
+        // when
+        let statistics = TableStatistics::collect(&schema, tuples.into_iter().map(Ok)).unwrap();
+        let empty = TableStatistics::collect(&schema, Vec::new().into_iter()).unwrap();
+
+        // then
+        assert_eq!(statistics.row_count(), 100);
+        let (id, city, photo) = (statistics.column(0).unwrap(), statistics.column(1).unwrap(), statistics.column(2).unwrap());
+        assert_eq!((id.min(), id.max()), (Some(&Value::Integer(-49)), Some(&Value::Integer(50))));
+        assert_eq!(id.null_fraction(), 0.0);
+        assert!((98..=102).contains(&id.distinct_count()), "estimate {}", id.distinct_count());
+        assert_eq!((city.min(), city.max()), (Some(&Value::Varchar("lima".to_string())), Some(&Value::Varchar("rome".to_string()))));
+        assert_eq!((city.null_fraction(), city.distinct_count()), (0.25, 3));
+        assert_eq!((photo.min(), photo.max()), (None, None));
+        assert!((98..=102).contains(&photo.distinct_count()), "estimate {}", photo.distinct_count());
+        assert_eq!(empty.row_count(), 0);
+        assert_eq!((empty.column(1).unwrap().null_fraction(), empty.column(1).unwrap().min()), (0.0, None));
+    }
+}
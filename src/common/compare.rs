@@ -0,0 +1,74 @@
+use std::cmp::Ordering;
+
+/// Order an index keeps its keys in, independent of any `Ord` of the key type. Its `name` is stored
+/// with the index, which has to be opened with a comparator of the same name.
+pub trait KeyComparator<K>: Send + Sync {
+    fn name(&self) -> String;
+
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+}
+
+/// `Ord` of the key, the default comparator.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NaturalOrder;
+
+impl<K: Ord> KeyComparator<K> for NaturalOrder {
+    fn name(&self) -> String {
+        "natural".to_string()
+    }
+
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Reverses another comparator, e.g. for an index read newest first.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Descending<C>(pub C);
+
+impl<K, C: KeyComparator<K>> KeyComparator<K> for Descending<C> {
+    fn name(&self) -> String {
+        format!("desc({})", self.0.name())
+    }
+
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        self.0.compare(a, b).reverse()
+    }
+}
+
+/// Strings by their lowercase form, so keys differing in case only are the same key.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CaseInsensitive;
+
+impl KeyComparator<String> for CaseInsensitive {
+    fn name(&self) -> String {
+        "case_insensitive".to_string()
+    }
+
+    fn compare(&self, a: &String, b: &String) -> Ordering {
+        a.chars().flat_map(char::to_lowercase).cmp(b.chars().flat_map(char::to_lowercase))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::common::compare::{CaseInsensitive, Descending, KeyComparator, NaturalOrder};
+
+    #[test]
+    fn should_order_keys_by_comparator_and_name_nested_comparators() {
+        // given
+        let descending = Descending(CaseInsensitive);
+
+        // when
+        let ordered = [CaseInsensitive.compare(&"apple".to_string(), &"Banana".to_string()),
+            CaseInsensitive.compare(&"ABC".to_string(), &"abc".to_string()),
+            descending.compare(&"apple".to_string(), &"Banana".to_string()),
+            KeyComparator::<u64>::compare(&Descending(NaturalOrder), &1, &2)];
+
+        // then
+        assert_eq!(ordered, [Ordering::Less, Ordering::Equal, Ordering::Greater, Ordering::Greater]);
+        assert_eq!(descending.name(), "desc(case_insensitive)");
+    }
+}
@@ -0,0 +1,228 @@
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::buffer::buffer_pool_manager::{BufferPoolManager, DEFAULT_READ_AHEAD_DEPTH};
+use crate::buffer::flusher::FLUSH_TASK;
+use crate::common::throttle::{RateLimit, WriteThrottle};
+use crate::maintenance::scheduler::{MaintenanceControl, TaskOptions, CHECKPOINT_TASK};
+
+/// Settings of background workers that can change while the engine runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RuntimeOptions {
+    /// Interval of the `FlushTask` of a bound scheduler.
+    pub flusher_interval: Duration,
+    pub checkpoint_interval: Duration,
+    /// Pages read ahead of a sequential scan of a bound buffer pool, see `BufferPoolManager::read_ahead_depth`.
+    pub readahead_depth: usize,
+    pub global_write_limit: RateLimit,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        RuntimeOptions {
+            flusher_interval: Duration::from_secs(1),
+            checkpoint_interval: Duration::from_secs(300),
+            readahead_depth: DEFAULT_READ_AHEAD_DEPTH,
+            global_write_limit: RateLimit::default(),
+        }
+    }
+}
+
+/// Partial update of `RuntimeOptions`; `None` keeps the current value.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct OptionsDelta {
+    pub flusher_interval: Option<Duration>,
+    pub checkpoint_interval: Option<Duration>,
+    pub readahead_depth: Option<usize>,
+    pub global_write_limit: Option<RateLimit>,
+}
+
+impl OptionsDelta {
+    fn apply_to(&self, options: &RuntimeOptions) -> RuntimeOptions {
+        RuntimeOptions {
+            flusher_interval: self.flusher_interval.unwrap_or(options.flusher_interval),
+            checkpoint_interval: self.checkpoint_interval.unwrap_or(options.checkpoint_interval),
+            readahead_depth: self.readahead_depth.unwrap_or(options.readahead_depth),
+            global_write_limit: self.global_write_limit.unwrap_or(options.global_write_limit),
+        }
+    }
+}
+
+type Listener = Box<dyn Fn(&RuntimeOptions) + Send + Sync>;
+
+/// Current runtime options plus the workers to notify when they change.
+///
+/// Workers either poll `current()` on every tick or subscribe to be pushed the new options.
+pub struct RuntimeConfig {
+    options: RwLock<RuntimeOptions>,
+    listeners: Mutex<Vec<Listener>>,
+}
+
+impl RuntimeConfig {
+    pub fn new(options: RuntimeOptions) -> io::Result<RuntimeConfig> {
+        validate(&options)?;
+        Ok(RuntimeConfig {
+            options: RwLock::new(options),
+            listeners: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn current(&self) -> RuntimeOptions {
+        *self.options.read().unwrap()
+    }
+
+    /// Called with the current options right away, then after every successful `reconfigure`.
+    pub fn subscribe(&self, listener: Listener) {
+        listener(&self.current());
+        self.listeners.lock().unwrap().push(listener);
+    }
+
+    pub fn bind_throttle(&self, throttle: Arc<WriteThrottle>) {
+        self.subscribe(Box::new(move |options| {
+            if throttle.global_limit() != options.global_write_limit {
+                // validated along with the other options, so it cannot be refused
+                let _ = throttle.set_global_limit(options.global_write_limit);
+            }
+        }));
+    }
+
+    pub fn bind_buffer_pool(&self, bpm: Arc<Mutex<BufferPoolManager>>) {
+        self.subscribe(Box::new(move |options| {
+            bpm.lock().unwrap().set_read_ahead_depth(options.readahead_depth);
+        }));
+    }
+
+    /// Sets the intervals of the flush and checkpoint tasks, keeping the rest of their options.
+    pub fn bind_maintenance(&self, control: MaintenanceControl) {
+        self.subscribe(Box::new(move |options| {
+            let intervals = [(FLUSH_TASK, options.flusher_interval), (CHECKPOINT_TASK, options.checkpoint_interval)];
+            for (task, interval) in intervals {
+                let task_options = control.options().tasks
+                    .get(task)
+                    .cloned()
+                    .unwrap_or_default();
+                control.set_task_options(task, TaskOptions { interval, ..task_options });
+            }
+        }));
+    }
+
+    /// Applies the delta atomically: invalid options are rejected and nothing changes.
+    pub fn reconfigure(&self, delta: OptionsDelta) -> io::Result<RuntimeOptions> {
+        let listeners = self.listeners.lock().unwrap();
+        let updated = {
+            let mut options = self.options.write().unwrap();
+            let updated = delta.apply_to(&options);
+            validate(&updated)?;
+            *options = updated;
+            updated
+        };
+
+        for listener in listeners.iter() {
+            listener(&updated);
+        }
+        Ok(updated)
+    }
+}
+
+fn validate(options: &RuntimeOptions) -> io::Result<()> {
+    if options.flusher_interval.is_zero() || options.checkpoint_interval.is_zero() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Interval must be greater than zero."))
+    }
+
+    options.global_write_limit.validate()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::flusher::FLUSH_TASK;
+    use crate::common::config::*;
+    use crate::common::throttle::{RateLimit, WriteThrottle};
+    use crate::maintenance::activity::ActivityMonitor;
+    use crate::maintenance::scheduler::{MaintenanceOptions, MaintenanceScheduler, CHECKPOINT_TASK};
+
+    #[test]
+    fn should_apply_only_fields_set_in_delta() {
+        // given
+        let config = RuntimeConfig::new(RuntimeOptions::default()).unwrap();
+
+        // when
+        let updated = config.reconfigure(OptionsDelta { readahead_depth: Some(32), ..OptionsDelta::default() }).unwrap();
+
+        // then
+        assert_eq!(updated, RuntimeOptions { readahead_depth: 32, ..RuntimeOptions::default() });
+        assert_eq!(config.current(), updated);
+    }
+
+    #[test]
+    fn should_reject_invalid_delta_and_keep_options() {
+        // given
+        let config = RuntimeConfig::new(RuntimeOptions::default()).unwrap();
+
+        // when
+        let result = config.reconfigure(OptionsDelta {
+            readahead_depth: Some(32),
+            flusher_interval: Some(Duration::ZERO),
+            ..OptionsDelta::default()
+        });
+
+        // then
+        assert!(result.is_err());
+        assert_eq!(config.current(), RuntimeOptions::default());
+    }
+
+    #[test]
+    fn should_reject_zero_rate_limit() {
+        // given
+        let config = RuntimeConfig::new(RuntimeOptions::default()).unwrap();
+        let throttle = Arc::new(WriteThrottle::new());
+        config.bind_throttle(throttle.clone());
+
+        // when
+        let result = config.reconfigure(OptionsDelta {
+            global_write_limit: Some(RateLimit { ops_per_sec: None, bytes_per_sec: Some(0) }),
+            ..OptionsDelta::default()
+        });
+
+        // then
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(throttle.global_limit(), RateLimit::default());
+        assert!(RuntimeConfig::new(RuntimeOptions {
+            global_write_limit: RateLimit { ops_per_sec: Some(0), bytes_per_sec: None },
+            ..RuntimeOptions::default()
+        }).is_err());
+    }
+
+    #[test]
+    fn should_push_new_options_to_bound_workers() {
+        // given
+        let config = RuntimeConfig::new(RuntimeOptions::default()).unwrap();
+        let throttle = Arc::new(WriteThrottle::new());
+        let scheduler = MaintenanceScheduler::new(MaintenanceOptions::default(), Arc::new(ActivityMonitor::new()));
+        let handle = scheduler.start();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        config.bind_throttle(throttle.clone());
+        config.bind_maintenance(handle.control());
+        config.bind_buffer_pool(bpm.clone());
+
+        // when
+        let limit = RateLimit { ops_per_sec: Some(100), bytes_per_sec: None };
+        config.reconfigure(OptionsDelta {
+            global_write_limit: Some(limit),
+            checkpoint_interval: Some(Duration::from_secs(30)),
+            flusher_interval: Some(Duration::from_secs(5)),
+            readahead_depth: Some(2),
+        }).unwrap();
+
+        // then
+        assert_eq!(throttle.global_limit(), limit);
+        assert_eq!(handle.control().options().tasks[CHECKPOINT_TASK].interval, Duration::from_secs(30));
+        assert_eq!(handle.control().options().tasks[FLUSH_TASK].interval, Duration::from_secs(5));
+        assert_eq!(bpm.lock().unwrap().read_ahead_depth(), 2);
+        handle.stop();
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::storage::page::page::PageId;
+
+/// One breadcrumb describing what was being done when an error passed through a layer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub operation: &'static str,
+    pub page_id: Option<PageId>,
+    pub frame_id: Option<usize>,
+    pub table: Option<String>,
+}
+
+impl ErrorContext {
+    pub fn new(operation: &'static str) -> ErrorContext {
+        ErrorContext { operation, ..ErrorContext::default() }
+    }
+
+    pub fn page(mut self, page_id: PageId) -> ErrorContext {
+        self.page_id = Some(page_id);
+        self
+    }
+
+    pub fn frame(mut self, frame_id: usize) -> ErrorContext {
+        self.frame_id = Some(frame_id);
+        self
+    }
+
+    pub fn table(mut self, table: &str) -> ErrorContext {
+        self.table = Some(table.to_string());
+        self
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation)?;
+        if let Some(table) = &self.table {
+            write!(f, " table {}", table)?;
+        }
+        if let Some(pid) = self.page_id {
+            write!(f, " page {}", pid)?;
+        }
+        if let Some(fid) = self.frame_id {
+            write!(f, " frame {}", fid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Crate error: the original cause plus the context collected on the way up, innermost first.
+///
+/// It travels inside `io::Error` through layers that still return `io::Result`,
+/// so `DiskError::from_io` and friends keep finding the cause.
+#[derive(Debug)]
+pub struct DbError {
+    cause: io::Error,
+    context: Vec<ErrorContext>,
+}
+
+impl DbError {
+    pub fn new(cause: io::Error) -> DbError {
+        if cause.get_ref().is_some_and(|inner| inner.is::<DbError>()) {
+            return *cause.into_inner().unwrap().downcast::<DbError>().unwrap()
+        }
+        DbError { cause, context: Vec::new() }
+    }
+
+    pub fn from_io(err: &io::Error) -> Option<&DbError> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<DbError>())
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        self.cause.kind()
+    }
+
+    pub fn cause(&self) -> &io::Error {
+        &self.cause
+    }
+
+    pub fn context(&self) -> &[ErrorContext] {
+        &self.context
+    }
+
+    /// Innermost page id recorded, i.e. the page closest to the failure.
+    pub fn page_id(&self) -> Option<PageId> {
+        self.context.iter().find_map(|c| c.page_id)
+    }
+
+    pub fn table(&self) -> Option<&str> {
+        self.context.iter().find_map(|c| c.table.as_deref())
+    }
+
+    pub fn push_context(mut self, context: ErrorContext) -> DbError {
+        self.context.push(context);
+        self
+    }
+}
+
+impl Display for DbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.cause)?;
+        for (i, context) in self.context.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " (in " } else { " <- " }, context)?;
+        }
+        if !self.context.is_empty() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for DbError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::new(e)
+    }
+}
+
+impl From<DbError> for io::Error {
+    fn from(e: DbError) -> Self {
+        io::Error::new(e.kind(), e)
+    }
+}
+
+/// Finds a typed cause inside an `io::Error`, looking through any `DbError` context wrapper.
+pub fn cause_of<T: error::Error + 'static>(err: &io::Error) -> Option<&T> {
+    let inner = err.get_ref()?;
+    match inner.downcast_ref::<DbError>() {
+        Some(db_error) => cause_of(db_error.cause()),
+        None => inner.downcast_ref::<T>(),
+    }
+}
+
+pub trait ResultExt<T> {
+    /// Attaches a breadcrumb to the error, if any, keeping the error kind.
+    fn with_context<F: FnOnce() -> ErrorContext>(self, f: F) -> io::Result<T>;
+}
+
+impl<T> ResultExt<T> for io::Result<T> {
+    fn with_context<F: FnOnce() -> ErrorContext>(self, f: F) -> io::Result<T> {
+        self.map_err(|e| DbError::new(e).push_context(f()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::common::error::*;
+    use crate::storage::disk::disk_error::DiskError;
+
+    #[test]
+    fn should_collect_context_innermost_first_and_keep_cause() {
+        // given
+        let result: io::Result<()> = Err(DiskError::PageNotAllocated(3).into());
+
+        // when
+        let err = result
+            .with_context(|| ErrorContext::new("read_page").page(3))
+            .with_context(|| ErrorContext::new("fetch_page").page(3).frame(1))
+            .with_context(|| ErrorContext::new("insert").table("users"))
+            .err().unwrap();
+
+        // then
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::PageNotAllocated(3))));
+        let db_error = DbError::from_io(&err).unwrap();
+        assert_eq!(db_error.context().len(), 3);
+        assert_eq!(db_error.page_id(), Some(3));
+        assert_eq!(db_error.table(), Some("users"));
+        assert_eq!(err.to_string(),
+                   "Page id not allocate. (in read_page page 3 <- fetch_page page 3 frame 1 <- insert table users)");
+    }
+
+    #[test]
+    fn should_not_nest_db_errors_when_converting_back() {
+        // given
+        let err: io::Error = DbError::new(io::Error::other("boom"))
+            .push_context(ErrorContext::new("flush_page").page(1))
+            .into();
+
+        // when
+        let db_error = DbError::from(err);
+
+        // then
+        assert_eq!(db_error.context(), &[ErrorContext::new("flush_page").page(1)]);
+        assert_eq!(db_error.cause().to_string(), "boom");
+    }
+}
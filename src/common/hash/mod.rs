@@ -1,9 +1,16 @@
-use fasthash::XXHasher;
+use fasthash::{FastHasher, XXHasher};
 use std::hash::{Hash, Hasher};
+use std::mem;
 use crate::common::KeyType;
 
-pub trait HashKeyType: KeyType + Hash {}
+pub trait HashKeyType: KeyType + Hash {
+    /// Bytes a slot keeps for the key. The bincode encoding of a fixed-size type is no larger than
+    /// the type itself, the default. Types holding heap data, e.g. `String` or `Vec`, encode to a length
+    /// prefix and their contents instead, and have to give the largest encoding they expect.
+    const ENCODED_SIZE: usize = mem::size_of::<Self>();
+}
 impl<T: HashKeyType> KeyType for T {}
+impl HashKeyType for u64 {}
 
 pub fn hash<K: HashKeyType>(key: &K) -> u64 {
     let mut hasher: XXHasher = Default::default();
@@ -11,6 +18,44 @@ pub fn hash<K: HashKeyType>(key: &K) -> u64 {
     hasher.finish()
 }
 
+/// Hash a table places keys by. A table has to be opened with the hasher it was built with.
+///
+/// Plain functions and closures are hashers too, e.g. `hash` or `|k: &u64| *k`.
+pub trait KeyHasher<K>: Send + Sync {
+    fn hash(&self, key: &K) -> u64;
+}
+
+impl<K, F: Fn(&K) -> u64 + Send + Sync> KeyHasher<K> for F {
+    fn hash(&self, key: &K) -> u64 {
+        self(key)
+    }
+}
+
+/// xxHash of the key, the default hasher, seed 0 hashing like `hash`. Giving each table its own seed
+/// keeps keys crafted to collide in one table from colliding in the others.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct XxKeyHasher {
+    seed: u64,
+}
+
+impl XxKeyHasher {
+    pub fn with_seed(seed: u64) -> XxKeyHasher {
+        XxKeyHasher { seed }
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl<K: HashKeyType> KeyHasher<K> for XxKeyHasher {
+    fn hash(&self, key: &K) -> u64 {
+        let mut hasher = XXHasher::with_seed(self.seed);
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -46,4 +91,19 @@ mod tests {
         v.push(key.i);
         assert_eq!(hash64(v), actual);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_hash_like_default_hash_unless_seeded() {
+        // given
+        let key = TestHashKey { i: 23 };
+
+        // when
+        let unseeded = KeyHasher::hash(&XxKeyHasher::default(), &key);
+        let seeded = KeyHasher::hash(&XxKeyHasher::with_seed(42), &key);
+
+        // then
+        assert_eq!(unseeded, hash(&key));
+        assert_ne!(seeded, unseeded);
+        assert_eq!(seeded, KeyHasher::hash(&XxKeyHasher::with_seed(42), &key));
+    }
+}
@@ -1,6 +1,18 @@
+use std::mem;
+
 use serde::Serialize;
 
 pub mod hash;
+pub mod compare;
+pub mod error;
+pub mod throttle;
+pub mod config;
+pub mod hyper_log_log;
 
 pub trait KeyType: Default + Clone + Serialize + Eq {}
-pub trait ValueType: Default + Clone + Serialize + Eq {}
\ No newline at end of file
+pub trait ValueType: Default + Clone + Serialize + Eq {
+    /// Bytes a slot keeps for the value, see `HashKeyType::ENCODED_SIZE`. A larger value of a hash
+    /// table goes to an overflow chain.
+    const ENCODED_SIZE: usize = mem::size_of::<Self>();
+}
+impl ValueType for u64 {}
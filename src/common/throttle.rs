@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits for one scope; `None` means unlimited.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    pub ops_per_sec: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+}
+
+impl RateLimit {
+    /// A limit of zero would never let a write through: leave it unset to not limit instead.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.ops_per_sec == Some(0) || self.bytes_per_sec == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Rate limit must be greater than zero."))
+        }
+
+        Ok(())
+    }
+}
+
+/// Token bucket holding at most one second worth of tokens. Tokens may go negative,
+/// the debt is what the caller has to wait for. The rate is never zero, see `RateLimit::validate`.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> TokenBucket {
+        TokenBucket { rate: rate as f64, tokens: rate as f64, last: now }
+    }
+
+    fn reserve(&mut self, amount: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = self.last.max(now);
+        self.tokens -= amount as f64;
+        if self.tokens >= 0.0 {
+            return Duration::ZERO
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+}
+
+struct Buckets {
+    limit: RateLimit,
+    ops: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(limit: RateLimit, now: Instant) -> Buckets {
+        Buckets {
+            limit,
+            ops: limit.ops_per_sec.map(|rate| TokenBucket::new(rate, now)),
+            bytes: limit.bytes_per_sec.map(|rate| TokenBucket::new(rate, now)),
+        }
+    }
+
+    fn reserve(&mut self, bytes: u64, now: Instant) -> Duration {
+        let ops_delay = self.ops.as_mut().map_or(Duration::ZERO, |b| b.reserve(1, now));
+        let bytes_delay = self.bytes.as_mut().map_or(Duration::ZERO, |b| b.reserve(bytes, now));
+        ops_delay.max(bytes_delay)
+    }
+}
+
+/// Global and per-table write throttles, adjustable at runtime.
+///
+/// The write path calls `throttle` (or `reserve` to schedule the delay itself) before doing the work,
+/// so bulk jobs get slowed down instead of starving foreground writes.
+pub struct WriteThrottle {
+    global: Mutex<Buckets>,
+    tables: Mutex<HashMap<String, Buckets>>,
+}
+
+impl WriteThrottle {
+    pub fn new() -> WriteThrottle {
+        WriteThrottle {
+            global: Mutex::new(Buckets::new(RateLimit::default(), Instant::now())),
+            tables: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fails if the limit is zero, see `RateLimit::validate`, keeping the limit before.
+    pub fn set_global_limit(&self, limit: RateLimit) -> io::Result<()> {
+        limit.validate()?;
+        *self.global.lock().unwrap() = Buckets::new(limit, Instant::now());
+        Ok(())
+    }
+
+    pub fn global_limit(&self) -> RateLimit {
+        self.global.lock().unwrap().limit
+    }
+
+    pub fn set_table_limit(&self, table: &str, limit: RateLimit) -> io::Result<()> {
+        limit.validate()?;
+        self.tables.lock().unwrap().insert(table.to_string(), Buckets::new(limit, Instant::now()));
+        Ok(())
+    }
+
+    pub fn clear_table_limit(&self, table: &str) {
+        self.tables.lock().unwrap().remove(table);
+    }
+
+    pub fn table_limit(&self, table: &str) -> Option<RateLimit> {
+        self.tables.lock().unwrap().get(table).map(|b| b.limit)
+    }
+
+    /// Accounts one write of `bytes` to `table` and returns how long the caller should wait before doing it.
+    pub fn reserve(&self, table: &str, bytes: u64, now: Instant) -> Duration {
+        let table_delay = self.tables.lock().unwrap()
+            .get_mut(table)
+            .map_or(Duration::ZERO, |b| b.reserve(bytes, now));
+        let global_delay = self.global.lock().unwrap().reserve(bytes, now);
+        table_delay.max(global_delay)
+    }
+
+    /// Blocks the calling thread until one write of `bytes` to `table` is allowed.
+    pub fn throttle(&self, table: &str, bytes: u64) {
+        let delay = self.reserve(table, bytes, Instant::now());
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+impl Default for WriteThrottle {
+    fn default() -> Self {
+        WriteThrottle::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    use crate::common::throttle::{RateLimit, WriteThrottle};
+
+    #[test]
+    fn should_not_delay_writes_without_limits() {
+        let throttle = WriteThrottle::new();
+        let now = Instant::now();
+
+        for _ in 0..1000 {
+            assert_eq!(throttle.reserve("t", 4096, now), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn should_delay_table_writes_over_ops_limit() {
+        // given
+        let throttle = WriteThrottle::new();
+        throttle.set_table_limit("bulk", RateLimit { ops_per_sec: Some(10), bytes_per_sec: None }).unwrap();
+        let now = Instant::now() + Duration::from_secs(1);
+
+        // when
+        let within_burst: Vec<Duration> = (0..10).map(|_| throttle.reserve("bulk", 0, now)).collect();
+        let over = throttle.reserve("bulk", 0, now);
+        let other_table = throttle.reserve("users", 0, now);
+
+        // then
+        assert!(within_burst.iter().all(|d| d.is_zero()));
+        assert_eq!(over, Duration::from_millis(100));
+        assert_eq!(other_table, Duration::ZERO);
+    }
+
+    #[test]
+    fn should_take_longest_delay_of_global_and_table_bytes_limits() {
+        // given
+        let throttle = WriteThrottle::new();
+        throttle.set_global_limit(RateLimit { ops_per_sec: None, bytes_per_sec: Some(1000) }).unwrap();
+        throttle.set_table_limit("bulk", RateLimit { ops_per_sec: None, bytes_per_sec: Some(500) }).unwrap();
+        let now = Instant::now() + Duration::from_secs(1);
+
+        // when
+        let delay = throttle.reserve("bulk", 1000, now);
+
+        // then
+        assert_eq!(delay, Duration::from_secs(1));
+        assert_eq!(throttle.reserve("users", 0, now + Duration::from_secs(1)), Duration::ZERO);
+    }
+
+    #[test]
+    fn should_stop_delaying_when_table_limit_cleared() {
+        // given
+        let throttle = WriteThrottle::new();
+        throttle.set_table_limit("bulk", RateLimit { ops_per_sec: Some(1), bytes_per_sec: None }).unwrap();
+        let now = Instant::now() + Duration::from_secs(1);
+        throttle.reserve("bulk", 0, now);
+
+        // when
+        throttle.clear_table_limit("bulk");
+
+        // then
+        assert_eq!(throttle.table_limit("bulk"), None);
+        assert_eq!(throttle.reserve("bulk", 0, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn should_refuse_zero_limits_and_keep_limit_before() {
+        // given
+        let throttle = WriteThrottle::new();
+        let limit = RateLimit { ops_per_sec: Some(10), bytes_per_sec: None };
+        throttle.set_table_limit("bulk", limit).unwrap();
+
+        // when
+        let zero_ops = throttle.set_table_limit("bulk", RateLimit { ops_per_sec: Some(0), bytes_per_sec: None });
+        let zero_bytes = throttle.set_global_limit(RateLimit { ops_per_sec: None, bytes_per_sec: Some(0) });
+
+        // then
+        assert_eq!(zero_ops.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(zero_bytes.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(throttle.table_limit("bulk"), Some(limit));
+        assert_eq!(throttle.global_limit(), RateLimit::default());
+    }
+}
@@ -0,0 +1,92 @@
+use std::io;
+
+use serde::de::DeserializeOwned;
+
+use crate::common::hash::HashKeyType;
+use crate::common::ValueType;
+use crate::container::hash::linear_probe_hash_table::LinearProbeHashTable;
+use crate::maintenance::scheduler::MaintenanceTask;
+
+pub const HASH_TABLE_COMPACTION_TASK: &str = "hash_table_compaction";
+
+/// Share of the slots tombstones may take before the table is compacted.
+const DEFAULT_MAX_TOMBSTONE_RATIO: f64 = 0.1;
+
+/// Compacts a hash table whenever the scheduler runs it and tombstones have piled up, so probe
+/// sequences left long by removes get short again.
+pub struct HashTableCompactionTask<K: HashKeyType, V: ValueType> {
+    table: LinearProbeHashTable<K, V>,
+    max_tombstone_ratio: f64,
+}
+
+impl<K, V> HashTableCompactionTask<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    pub fn new(table: LinearProbeHashTable<K, V>) -> HashTableCompactionTask<K, V> {
+        HashTableCompactionTask { table, max_tombstone_ratio: DEFAULT_MAX_TOMBSTONE_RATIO }
+    }
+
+    pub fn with_max_tombstone_ratio(mut self, max_tombstone_ratio: f64) -> HashTableCompactionTask<K, V> {
+        self.max_tombstone_ratio = max_tombstone_ratio;
+        self
+    }
+}
+
+impl<K, V> MaintenanceTask for HashTableCompactionTask<K, V>
+    where
+        K: HashKeyType + DeserializeOwned + Send,
+        V: ValueType + DeserializeOwned + Send,
+{
+    fn name(&self) -> &str {
+        HASH_TABLE_COMPACTION_TASK
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let stats = self.table.stats()?;
+        if stats.tombstones() as f64 > self.max_tombstone_ratio * stats.slots as f64 {
+            self.table.compact()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::container::hash::compaction::HashTableCompactionTask;
+    use crate::container::hash::hash_table::HashTable;
+    use crate::container::hash::linear_probe_hash_table::LinearProbeHashTable;
+    use crate::maintenance::scheduler::MaintenanceTask;
+    use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+
+    #[test]
+    fn should_compact_only_once_tombstones_pass_ratio() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut table = LinearProbeHashTable::new(1, bpm, |k: &u64| *k).unwrap();
+        let slots = HashTableBlockPage::<u64, u64>::capacity_of_block() as f64;
+        let mut task = HashTableCompactionTask::new(table.clone()).with_max_tombstone_ratio(2.0 / slots);
+        for k in 0..10 {
+            table.insert(&k, &k).unwrap();
+        }
+        table.remove(&0).unwrap();
+
+        // when
+        task.run().unwrap();
+        let tombstones_below_ratio = table.stats().unwrap().tombstones();
+        for k in 1..5 {
+            table.remove(&k).unwrap();
+        }
+        task.run().unwrap();
+
+        // then
+        assert_eq!(tombstones_below_ratio, 1);
+        assert_eq!(table.stats().unwrap().tombstones(), 0);
+        assert_eq!(table.stats().unwrap().occupied, 5);
+        assert_eq!(table.get_value(&9).unwrap(), vec![9]);
+    }
+}
@@ -1,8 +1,19 @@
+use crate::common::error::DbError;
 use crate::common::hash::HashKeyType;
 use crate::common::ValueType;
 
+/// Failures of the pages underneath, e.g. a full buffer pool, a disk error or a corrupted page, are
+/// returned to the caller rather than panicking.
 pub trait HashTable<K: HashKeyType, V: ValueType> {
-    fn insert(&mut self, k: &K, v: &V) -> bool;
-    fn remove(&mut self, k: &K);
-    fn get_value(&mut self, k: &K) -> Vec<V>;
-}
\ No newline at end of file
+    fn insert(&mut self, k: &K, v: &V) -> Result<bool, DbError>;
+    /// Overwrites the value of an existing pair of `k` instead of adding another pair, inserting the
+    /// pair if `k` has none. Returns whether a value was overwritten.
+    fn upsert(&mut self, k: &K, v: &V) -> Result<bool, DbError>;
+    fn remove(&mut self, k: &K) -> Result<(), DbError>;
+    /// Removes the pair of `k` holding `v`, leaving the other values of `k` in place. Returns whether
+    /// there was such a pair.
+    fn remove_kv(&mut self, k: &K, v: &V) -> Result<bool, DbError>;
+    fn get_value(&mut self, k: &K) -> Result<Vec<V>, DbError>;
+    /// Whether `k` has any pair, found without decoding a value.
+    fn contains_key(&mut self, k: &K) -> Result<bool, DbError>;
+}
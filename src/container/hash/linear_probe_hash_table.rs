@@ -1,689 +1,2689 @@
+use std::io;
+use std::collections::{BTreeMap, HashMap};
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 
 use serde::de::DeserializeOwned;
 
 use crate::buffer::buffer_pool_manager::BufferPoolManager;
-use crate::common::hash::HashKeyType;
+use crate::buffer::page_guard::{BlockPageGuard, HeaderPageGuard};
+use crate::common::error::{DbError, ErrorContext, ResultExt};
+use crate::common::hash::{HashKeyType, KeyHasher, XxKeyHasher};
 use crate::common::ValueType;
-use crate::container::hash::FindSlotResult;
+use crate::container::hash::{create_header_chain, read_header_chain, FindSlotResult};
 use crate::container::hash::FindSlotResult::*;
 use crate::container::hash::hash_table::HashTable;
-use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+use crate::storage::page::hash_table_block_page::{HashTableBlockPage, HashTableBlockView, SlotValue};
+#[cfg(test)]
 use crate::storage::page::hash_table_header_page::HashTableHeaderPage;
-use crate::storage::page::page::PageId;
+use crate::storage::page::hash_table_header_page::{HashTableHeaderChain, HashTableHeaderView};
+use crate::storage::page::overflow_page::{read_chain, OverflowPage, OverflowRef};
+use crate::storage::page::page::{PageId, PageSnapshot, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_latch::{PageLatch, PageUpgradableLatch, PageWriteLatch};
+
+/// Buckets of `HashTableStats::probe_histogram`.
+pub const PROBE_HISTOGRAM_BUCKETS: usize = 16;
+
+/// Slot usage over all blocks of a hash table and how far pairs sit from their home slot, for
+/// deciding when to resize or compact it.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct HashTableStats {
+    /// Blocks allocated so far, out of the table size.
+    pub allocated_blocks: usize,
+    /// Slots of all blocks, allocated or not.
+    pub slots: usize,
+    pub occupied: usize,
+    pub readable: usize,
+    /// Slots between each live pair and its home slot, summed over all of them.
+    pub total_probe_length: usize,
+    pub max_probe_length: usize,
+    /// Live pairs by probe length: bucket 0 holds the pairs in their home slot, bucket `i` those
+    /// `2^(i-1)` up to `2^i` slots away, and the last bucket every pair further away.
+    pub probe_histogram: [usize; PROBE_HISTOGRAM_BUCKETS],
+}
 
-pub struct LinearProbeHashTable<'a, K: HashKeyType, V: ValueType> {
-    header_pid: PageId,
-    buffer_pool_manager: &'a mut BufferPoolManager,
-    hash_fn: fn(&K) -> u64,
-    phantom: PhantomData<V>,
+impl HashTableStats {
+    /// Share of the slots holding a live pair.
+    pub fn load_factor(&self) -> f64 {
+        if self.slots == 0 {
+            return 0.0;
+        }
+
+        self.readable as f64 / self.slots as f64
+    }
+
+    pub fn avg_probe_length(&self) -> f64 {
+        if self.readable == 0 {
+            return 0.0;
+        }
+
+        self.total_probe_length as f64 / self.readable as f64
+    }
+
+    fn add_probe_length(&mut self, probe_length: usize) {
+        let bucket = (usize::BITS - probe_length.leading_zeros()) as usize;
+        self.probe_histogram[bucket.min(PROBE_HISTOGRAM_BUCKETS - 1)] += 1;
+        self.total_probe_length += probe_length;
+        self.max_probe_length = self.max_probe_length.max(probe_length);
+    }
+
+    /// Share of the slots occupied, tombstones included.
+    pub fn fill_factor(&self) -> f64 {
+        if self.slots == 0 {
+            return 0.0;
+        }
+
+        self.occupied as f64 / self.slots as f64
+    }
+
+    /// Occupied slots holding a tombstone instead of a live pair.
+    pub fn tombstones(&self) -> usize {
+        self.occupied - self.readable
+    }
+}
+
+/// Which pairs an insert refuses as duplicates.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// A key may hold several values, each of them once.
+    #[default]
+    DistinctValues,
+    /// A key holds one value, inserting another one for it is refused. `upsert` replaces it.
+    UniqueKeys,
+}
+
+/// What a probe for a free slot stops at as already inserted.
+#[derive(Clone, Copy)]
+enum Duplicate<'a, V> {
+    Unchecked,
+    OfKey,
+    OfPair(&'a V),
+}
+
+/// Options of a new `LinearProbeHashTable`. Each has a default, so options added later do not break
+/// the tables built before.
+pub struct LinearProbeHashTableBuilder<K> {
+    num_buckets: usize,
+    hasher: Arc<dyn KeyHasher<K>>,
+    max_fill_factor: f64,
+    min_fill_factor: f64,
+    duplicates: DuplicatePolicy,
+}
+
+impl<K: HashKeyType> Default for LinearProbeHashTableBuilder<K> {
+    fn default() -> Self {
+        LinearProbeHashTableBuilder {
+            num_buckets: DEFAULT_NUM_BUCKETS,
+            hasher: Arc::new(XxKeyHasher::default()),
+            max_fill_factor: DEFAULT_MAX_FILL_FACTOR,
+            min_fill_factor: DEFAULT_MIN_FILL_FACTOR,
+            duplicates: DuplicatePolicy::default(),
+        }
+    }
+}
+
+impl<K> LinearProbeHashTableBuilder<K>
+    where
+        K: HashKeyType + DeserializeOwned,
+{
+    /// Blocks the table starts with.
+    pub fn num_buckets(mut self, num_buckets: usize) -> Self {
+        self.num_buckets = num_buckets;
+        self
+    }
+
+    /// `XxKeyHasher` with seed 0 unless set.
+    pub fn hasher<H: KeyHasher<K> + 'static>(mut self, hasher: H) -> Self {
+        self.hasher = Arc::new(hasher);
+        self
+    }
+
+    /// See `LinearProbeHashTable::set_max_fill_factor`.
+    pub fn max_fill_factor(mut self, max_fill_factor: f64) -> Self {
+        self.max_fill_factor = max_fill_factor;
+        self
+    }
+
+    /// See `LinearProbeHashTable::set_min_fill_factor`.
+    pub fn min_fill_factor(mut self, min_fill_factor: f64) -> Self {
+        self.min_fill_factor = min_fill_factor;
+        self
+    }
+
+    pub fn duplicates(mut self, duplicates: DuplicatePolicy) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// Writes the header pages of the new table.
+    pub fn build<V: ValueType + DeserializeOwned>(self, bpm: Arc<Mutex<BufferPoolManager>>) -> io::Result<LinearProbeHashTable<K, V>> {
+        let header_pid = create_header_chain(&mut bpm.lock().unwrap(), self.num_buckets)?;
+        Ok(self.with_header(bpm, header_pid))
+    }
+
+    /// Table built before with its first header page on page `header_pid`, see `get_header_pid`. The
+    /// hasher and duplicate policy have to be those it was built with, the number of buckets is read
+    /// from the header instead.
+    pub fn open<V: ValueType + DeserializeOwned>(self, bpm: Arc<Mutex<BufferPoolManager>>, header_pid: PageId) -> io::Result<LinearProbeHashTable<K, V>> {
+        let table = self.with_header(bpm, header_pid);
+        table.header_view_of(header_pid)?;
+        Ok(table)
+    }
+
+    fn with_header<V: ValueType + DeserializeOwned>(self, bpm: Arc<Mutex<BufferPoolManager>>, header_pid: PageId) -> LinearProbeHashTable<K, V> {
+        LinearProbeHashTable {
+            buffer_pool_manager: bpm,
+            hasher: self.hasher,
+            duplicates: self.duplicates,
+            state: Arc::new(RwLock::new(TableState {
+                header_pid,
+                max_fill_factor: self.max_fill_factor,
+                min_fill_factor: self.min_fill_factor,
+                occupied: Mutex::new(None),
+            })),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Pairs captured by `LinearProbeHashTable::iter_snapshot`, block by block. Holds no latch or pin,
+/// so it may be kept for as long as an export takes.
+pub struct SnapshotIter<K, V> {
+    blocks: Vec<HashTableBlockView<K, V, PageSnapshot>>,
+    overflowed: HashMap<(usize, usize), V>,
+    block_idx: usize,
+    offset: usize,
 }
 
-impl<'a, K, V> LinearProbeHashTable<'a, K, V>
+impl<K, V> SnapshotIter<K, V>
     where
         K: HashKeyType + DeserializeOwned,
         V: ValueType + DeserializeOwned,
 {
-    pub fn new(num_buckets: usize, bpm: &mut BufferPoolManager, hash_fn: fn(&K) -> u64) -> LinearProbeHashTable<K, V> {
-        let header_pid = {
-            let mut header_page = bpm.new_page().unwrap().write().unwrap();
+    fn read_pair(&mut self, block_idx: usize, offset: usize) -> io::Result<(K, V)> {
+        let (key, value) = self.blocks[block_idx].get(offset)?;
+        match value {
+            SlotValue::Inline(value) => Ok((key, value)),
+            SlotValue::Overflow(_) => match self.overflowed.remove(&(block_idx, offset)) {
+                Some(value) => Ok((key, value)),
+                None => Err(PageError::SlotChanged { slot_idx: offset }.into()),
+            },
+        }
+    }
+}
+
+impl<K, V> Iterator for SnapshotIter<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    type Item = io::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<io::Result<(K, V)>> {
+        while self.block_idx < self.blocks.len() {
+            let (block_idx, offset) = (self.block_idx, self.offset);
+            if offset == self.blocks[block_idx].capacity() {
+                self.block_idx += 1;
+                self.offset = 0;
+                continue;
+            }
 
-            let header = HashTableHeaderPage::new(header_page.get_id(), num_buckets);
-            let header_raw = header.serialize();
-            for i in 0..header_raw.len() {
-                header_page.get_data_mut()[i] = header_raw[i];
+            self.offset += 1;
+            match self.blocks[block_idx].is_readable(offset) {
+                Ok(false) => continue,
+                Ok(true) => return Some(self.read_pair(block_idx, offset)),
+                Err(e) => return Some(Err(e)),
             }
+        }
 
-            header_page.get_id()
-        };
+        None
+    }
+}
+
+/// Where `LinearProbeHashTable::get_value_limit` left off: the slots of the probe sequence searched.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ValueCursor {
+    probed: usize,
+}
+
+/// Share of occupied slots, tombstones included, an insert may bring the table to before it is resized.
+const DEFAULT_MAX_FILL_FACTOR: f64 = 0.75;
+
+const DEFAULT_NUM_BUCKETS: usize = 16;
+
+/// Share of live pairs a remove may bring the table below before it is halved, none by default.
+const DEFAULT_MIN_FILL_FACTOR: f64 = 0.0;
+
+/// Where a pair goes, see `find_insert_position`.
+#[derive(Copy, Clone)]
+enum InsertPosition {
+    Free(PageId, usize),
+    Tombstone(PageId, usize),
+    /// Block index and offset in a block not allocated yet.
+    NewBlock(usize, usize),
+}
+
+/// What `write_pair` did with the pair.
+enum WriteOutcome {
+    Inserted,
+    Replaced,
+    /// The same pair was there already.
+    Duplicated,
+}
+
+/// State shared by every handle of a table.
+struct TableState {
+    header_pid: PageId,
+    max_fill_factor: f64,
+    min_fill_factor: f64,
+    /// Occupied slots, counted from the blocks on the first insert and kept up to date after.
+    occupied: Mutex<Option<usize>>,
+}
+
+/// A page latch along with the pin keeping its frame on the page while the latch is held.
+struct PinnedLatch<L> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
+    pid: PageId,
+    latch: Option<L>,
+}
+
+impl<L> PinnedLatch<L> {
+    /// Waits for the latch without holding the buffer pool, which the current holder may need.
+    fn acquire<F>(bpm: &Arc<Mutex<BufferPoolManager>>, pid: PageId, take: F) -> io::Result<PinnedLatch<L>>
+        where F: FnOnce(&Arc<PageLatch>) -> L
+    {
+        let latch = bpm.lock().unwrap().fetch_page_tagged(pid, "hash_table.latch")?.read().unwrap().latch();
+        Ok(PinnedLatch { bpm: bpm.clone(), pid, latch: Some(take(&latch)) })
+    }
+}
+
+impl PinnedLatch<PageUpgradableLatch> {
+    fn upgrade(mut self) -> PinnedLatch<PageWriteLatch> {
+        let latch = self.latch.take().unwrap().upgrade();
+        PinnedLatch { bpm: self.bpm.clone(), pid: self.pid, latch: Some(latch) }
+    }
+}
+
+impl<L> Drop for PinnedLatch<L> {
+    fn drop(&mut self) {
+        // an upgraded latch handed its pin over along with the latch
+        if let Some(latch) = self.latch.take() {
+            drop(latch);
+            // the pin was taken along with the latch, so giving it back cannot fail
+            self.bpm.lock().unwrap().unpin_page(self.pid, false).unwrap();
+        }
+    }
+}
+
+/// A handle on a hash table kept in the buffer pool. Handles are cheap to clone and every clone works
+/// on the same table, so each thread can use its own.
+///
+/// Writers take the latch of the first header page in upgradable mode, which admits one of them at a
+/// time, and upgrade it to link a new block into the directory. Slots are changed under the write
+/// latch of their block and read under its read latch. Resizing moves the table to new pages, so it
+/// waits for every other operation instead.
+pub struct LinearProbeHashTable<K: HashKeyType, V: ValueType> {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    hasher: Arc<dyn KeyHasher<K>>,
+    duplicates: DuplicatePolicy,
+    state: Arc<RwLock<TableState>>,
+    phantom: PhantomData<V>,
+}
 
+impl<K: HashKeyType, V: ValueType> Clone for LinearProbeHashTable<K, V> {
+    fn clone(&self) -> Self {
         LinearProbeHashTable {
-            header_pid,
-            buffer_pool_manager: bpm,
-            hash_fn,
+            buffer_pool_manager: self.buffer_pool_manager.clone(),
+            hasher: self.hasher.clone(),
+            duplicates: self.duplicates,
+            state: self.state.clone(),
             phantom: PhantomData,
         }
     }
+}
 
-    fn get_header(&mut self) -> HashTableHeaderPage {
-        let header_page = self.buffer_pool_manager
-            .fetch_page(self.header_pid).unwrap()
-            .read().unwrap();
+impl<K, V> LinearProbeHashTable<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    /// Keys are placed by `hasher`, e.g. `XxKeyHasher::with_seed(seed)` or a plain function like `hash`.
+    /// Every other option keeps its default, see `builder` to set them.
+    ///
+    /// Fails like `LinearProbeHashTableBuilder::build` when the header pages cannot be written.
+    pub fn new<H: KeyHasher<K> + 'static>(num_buckets: usize, bpm: Arc<Mutex<BufferPoolManager>>, hasher: H) -> Result<LinearProbeHashTable<K, V>, DbError> {
+        Ok(Self::builder().num_buckets(num_buckets).hasher(hasher).build(bpm)?)
+    }
 
-        HashTableHeaderPage::deserialize(header_page.get_data()).unwrap()
+    pub fn builder() -> LinearProbeHashTableBuilder<K> {
+        LinearProbeHashTableBuilder::default()
     }
 
-    fn get_block(bpm: &mut BufferPoolManager, block_pid: usize) -> HashTableBlockPage<K, V> {
-        let block_page = bpm.fetch_page(block_pid).unwrap().read().unwrap();
-        HashTableBlockPage::deserialize(block_page.get_data()).unwrap()
+    /// First header page of the table. Changes when the table is resized.
+    pub fn get_header_pid(&self) -> PageId {
+        self.state.read().unwrap().header_pid
     }
 
-    fn insert_to_new_block(bpm: &mut BufferPoolManager,
-                           k: &K,
-                           v: &V,
-                           header: &mut HashTableHeaderPage,
-                           block_idx: usize,
-                           block_offset: usize) {
-        let mut new_block = HashTableBlockPage::<K, V>::new();
+    /// An insert that would occupy a larger share of the slots doubles the number of blocks first.
+    pub fn set_max_fill_factor(&self, max_fill_factor: f64) {
+        self.state.write().unwrap().max_fill_factor = max_fill_factor;
+    }
 
-        // collapse cannot happen in new block
-        assert!(new_block.insert(block_offset, k.clone(), v.clone()));
-        let block_pid = LinearProbeHashTable::<K, V>::update_page(bpm, None, new_block.serialize());
+    /// A remove leaving a smaller share of the slots live halves the number of blocks, freeing the
+    /// pages of the old ones. Keep it below half the maximum fill factor, or the halved table would
+    /// grow right back. Zero, the default, never shrinks the table.
+    pub fn set_min_fill_factor(&self, min_fill_factor: f64) {
+        self.state.write().unwrap().min_fill_factor = min_fill_factor;
+    }
 
-        header.set(block_pid, block_idx);
-        LinearProbeHashTable::<K, V>::update_page(bpm, Some(header.get_page_id()), header.serialize());
+    /// Rehashes every live pair into a new table of `num_buckets` blocks, then frees the pages of the
+    /// old one. Overflowed values stay where they are, only the slots referring to them move. The table
+    /// switches to its new header page once every pair is in, so a failure leaves it as it was.
+    ///
+    /// Fails with `PageError::HeaderFull` if the new table would be too small for the live pairs.
+    pub fn resize(&self, num_buckets: usize) -> io::Result<()> {
+        let mut state = self.state.write().unwrap();
+        self.resize_locked(&mut state, num_buckets)
     }
 
-    fn update_page(bpm: &mut BufferPoolManager, pid_option: Option<PageId>, page_data: Vec<u8>) -> PageId {
-        let pid_to_return = {
-            let mut page = match pid_option {
-                Some(pid) => bpm.fetch_page(pid).unwrap().write().unwrap(),
-                None => bpm.new_page().unwrap().write().unwrap()
+    fn resize_locked(&self, state: &mut TableState, num_buckets: usize) -> io::Result<()> {
+        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
+        if self.stats_of(state.header_pid)?.readable > num_buckets * slot_capacity {
+            return Err(PageError::HeaderFull.into());
+        }
+
+        let old_header = self.header_view_of(state.header_pid)?;
+        let new_header_pid = create_header_chain(&mut self.bpm(), num_buckets)?;
+        let mut old_block_pids = Vec::new();
+        let mut moved = 0;
+        for block_idx in 0..old_header.get_size() {
+            let block_pid = match old_header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => continue,
             };
-            let raw_data = page.get_data_mut();
-            for i in 0..page_data.len() {
-                raw_data[i] = page_data[i];
+            let block = LinearProbeHashTable::<K, V>::block_view(&mut self.bpm(), block_pid)?;
+            for slot_idx in 0..slot_capacity {
+                if !block.is_readable(slot_idx)? {
+                    continue;
+                }
+
+                let (k, value) = block.get(slot_idx)?;
+                // reloaded for every pair, so blocks allocated for the previous ones are seen
+                let new_header = self.header_view_of(new_header_pid)?;
+                let (home_block_idx, home_block_offset) = self.home_slot(&k, num_buckets);
+                match self.find_insert_position(&k, None, &new_header, home_block_idx, home_block_offset)? {
+                    Found(position) => self.insert_at(&new_header, position, &k, &value.as_ref())?,
+                    _ => return Err(PageError::HeaderFull.into()),
+                }
+                moved += 1;
+            }
+            old_block_pids.push(block_pid);
+        }
+
+        self.update_len(new_header_pid, |_| moved as u64)?;
+        state.header_pid = new_header_pid;
+        *state.occupied.lock().unwrap() = Some(moved);
+        let mut bpm = self.bpm();
+        for pid in old_header.page_ids().iter().chain(old_block_pids.iter()) {
+            bpm.delete_page(*pid)?;
+        }
+        Ok(())
+    }
+
+    /// Halves the blocks of the table if it became sparser than its minimum fill factor. Checked under
+    /// the shared state lock first, so removes leaving the table dense enough do not wait on others.
+    fn shrink_if_sparse(&self) -> io::Result<()> {
+        let size = {
+            let state = self.state.read().unwrap();
+            let size = self.header_view_of(state.header_pid)?.get_size();
+            if !self.is_sparse(&state, size)? {
+                return Ok(());
             }
-            page.get_id()
+            size
         };
 
-        {
-            bpm.unpin_page(pid_to_return, true);
+        let mut state = self.state.write().unwrap();
+        // another handle may have resized the table, or filled it, meanwhile
+        if self.header_view_of(state.header_pid)?.get_size() != size || !self.is_sparse(&state, size)? {
+            return Ok(());
+        }
+        self.resize_locked(&mut state, size / 2)
+    }
+
+    fn is_sparse(&self, state: &TableState, size: usize) -> io::Result<bool> {
+        if size < 2 || state.min_fill_factor <= 0.0 {
+            return Ok(false);
         }
 
-        pid_to_return
+        let slots = size * HashTableBlockPage::<K, V>::capacity_of_block();
+        Ok((self.len_of(state.header_pid)? as f64) < state.min_fill_factor * slots as f64)
     }
 
-    fn find_available_slot(bpm: &mut BufferPoolManager,
-                           key: &K,
-                           val: &V,
-                           block_pid: usize,
-                           block_offset: usize) -> FindSlotResult<(HashTableBlockPage<K, V>, usize)> {
-        let block = LinearProbeHashTable::<K, V>::get_block(bpm, block_pid);
-        for i in block_offset..HashTableBlockPage::<K, V>::capacity_of_block() {
-            if !block.is_occupied(i) {
-                return Found((block, i));
-            }
+    /// Doubles the blocks of a table of `size` blocks, unless another handle resized it meanwhile.
+    fn grow(&self, size: usize) -> io::Result<()> {
+        let mut state = self.state.write().unwrap();
+        if self.header_view_of(state.header_pid)?.get_size() != size {
+            return Ok(());
+        }
+
+        self.resize_locked(&mut state, 2 * size)
+    }
+
+    /// Unlinks every block page from the header, then deletes them along with the overflow pages of the
+    /// values left in them, so a failure part way only leaks pages. The table keeps its size and header
+    /// pages. Waits for every other operation.
+    pub fn clear(&self) -> io::Result<()> {
+        let state = self.state.write().unwrap();
+        let header = self.header_view_of(state.header_pid)?;
+        {
+            let mut bpm = self.bpm();
+            for block_idx in 0..header.get_size() {
+                if header.get_block_page_id(block_idx)?.is_none() {
+                    continue;
+                }
 
-            let (k, v) = block.get(i);
-            if key.eq(k) && val.eq(v) {
-                return Duplicated;
+                let (header_page_pid, slot_idx) = header.header_page_of(block_idx)?;
+                let mut header_page = HeaderPageGuard::fetch(&mut bpm, header_page_pid, "hash_table.clear")?;
+                header_page.set(INVALID_PAGE_ID, slot_idx)?;
+                header_page.release()?;
             }
         }
+        self.update_len(state.header_pid, |_| 0)?;
+        *state.occupied.lock().unwrap() = Some(0);
 
-        NotFound
+        self.release_blocks(&header)
     }
 
-    fn find_values_in_block(bpm: &mut BufferPoolManager,
-                            key: &K,
-                            block_pid: usize,
-                            block_offset: usize,
-                            res: &mut Vec<V>) -> bool {
-        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
-        let blk = LinearProbeHashTable::<K, V>::get_block(bpm, block_pid);
-        let mut curr_offset = block_offset;
-        for _ in block_offset..slot_capacity {
-            if !blk.is_occupied(curr_offset) {
-                return true;
+    /// Deletes every page of the table, header pages included. Other handles of the table must not be
+    /// used afterwards.
+    pub fn drop_table(self) -> io::Result<()> {
+        let state = self.state.write().unwrap();
+        let header = self.header_view_of(state.header_pid)?;
+        self.release_blocks(&header)?;
+
+        let mut bpm = self.bpm();
+        for header_page_pid in header.page_ids() {
+            bpm.delete_page(*header_page_pid)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the block pages and the overflow pages their live slots refer to.
+    fn release_blocks(&self, header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>) -> io::Result<()> {
+        let mut bpm = self.bpm();
+        for block_idx in 0..header.get_size() {
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => continue,
+            };
+
+            let block = LinearProbeHashTable::<K, V>::block_view(&mut bpm, block_pid)?;
+            for slot_idx in 0..block.capacity() {
+                if !block.is_readable(slot_idx)? || !block.is_overflowed(slot_idx)? {
+                    continue;
+                }
+                if let SlotValue::Overflow(overflow_ref) = block.get(slot_idx)?.1 {
+                    LinearProbeHashTable::<K, V>::free_overflow(&mut bpm, &overflow_ref)?;
+                }
             }
+            bpm.delete_page(block_pid)?;
+        }
+
+        Ok(())
+    }
 
-            let (k, v) = blk.get(curr_offset);
-            if k.eq(key) {
-                res.push((*v).clone());
-                curr_offset += 1;
-            } else {
-                return true;
+    /// Allocates every block page not allocated yet as one extent, so scanning the blocks in order
+    /// reads a contiguous region of the data file. Returns how many blocks were allocated.
+    pub fn preallocate_blocks(&self) -> io::Result<usize> {
+        let state = self.state.read().unwrap();
+        let _directory_latch = self.latch_page(state.header_pid, |latch| latch.write())?;
+        let mut bpm = self.bpm();
+        let mut header = read_header_chain(&mut bpm, state.header_pid)?;
+        let mut missing_blocks = Vec::new();
+        for block_idx in 0..header.get_size() {
+            if header.get_block_page_id(block_idx)?.is_none() {
+                missing_blocks.push(block_idx);
             }
         }
+        if missing_blocks.is_empty() {
+            return Ok(0);
+        }
+
+        let block_pids = bpm.new_extent(missing_blocks.len())?;
+        let empty_block = HashTableBlockPage::<K, V>::new();
+        for (block_idx, block_pid) in missing_blocks.iter().zip(block_pids) {
+            bpm.write_encoded(Some(block_pid), &empty_block)?;
+            header.set(block_pid, *block_idx)?;
+        }
+        for header_page in header.pages() {
+            bpm.write_encoded(Some(header_page.get_page_id()), header_page)?;
+        }
 
-        false
+        Ok(missing_blocks.len())
     }
 
-    fn try_insert_to_appropriate_slot(&mut self, k: &K, v: &V, mut header: &mut HashTableHeaderPage, block_idx: usize, init_block_offset: usize) -> bool {
-        let mut next_block_idx = block_idx;
-        let mut block_offset = init_block_offset;
-        loop {
-            let next_block_pid = header.get_block_page_id(next_block_idx);
-            if next_block_pid.is_none() {
-                LinearProbeHashTable::<K, V>::insert_to_new_block(self.buffer_pool_manager, k, v, &mut header, next_block_idx, block_offset);
-                return true;
+    /// Moves live pairs back over the tombstones on their probe sequences, so lookups reach the free
+    /// slot ending them sooner and inserts get free slots back. Returns how many slots became free.
+    /// Waits for every other operation, like `resize`.
+    ///
+    /// Pairs stay in their block, see `HashTableBlockPage::compact`, so a probe sequence still runs
+    /// into the next block only past a block with no free slot.
+    pub fn compact(&self) -> io::Result<usize> {
+        let state = self.state.write().unwrap();
+        let header = self.header_view_of(state.header_pid)?;
+        let mut freed = 0;
+        for block_idx in 0..header.get_size() {
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => continue,
+            };
+
+            let mut bpm = self.bpm();
+            let mut block = BlockPageGuard::<K, V>::fetch(&mut bpm, block_pid, "hash_table.compact")?;
+            // a block without tombstones is left clean
+            if block.num_occupied() == block.num_readable() {
+                continue;
             }
+            freed += block.compact(|k| match self.home_slot(k, header.get_size()) {
+                (home_block_idx, home_offset) if home_block_idx == block_idx => Some(home_offset),
+                _ => None,
+            });
+            block.release()?;
+        }
 
-            let block_and_offset = LinearProbeHashTable::<K, V>::find_available_slot(
-                self.buffer_pool_manager, k, v, next_block_pid.unwrap(), block_offset);
-            if block_and_offset.not_found() {
-                // temporary ignore hash table all fulled
-                if next_block_idx + 1 == header.get_size() {
-                    next_block_idx = 0;
-                } else {
-                    next_block_idx += 1;
+        let mut occupied = state.occupied.lock().unwrap();
+        *occupied = occupied.map(|occupied| occupied - freed);
+        Ok(freed)
+    }
+
+    /// Inserts the pairs not in the table yet, returning how many went in. Pairs are grouped by the
+    /// block their probe sequence starts in, and each block is read and written back once for all of
+    /// its pairs instead of once per pair. The few probing past their block are inserted one by one.
+    pub fn insert_batch(&self, pairs: &[(K, V)]) -> io::Result<usize> {
+        let mut leftovers = Vec::new();
+        let mut inserted = loop {
+            let size = {
+                let state = self.state.read().unwrap();
+                let directory_latch = self.latch_page(state.header_pid, |latch| latch.upgradable_read())?;
+                let header = self.header_view_of(state.header_pid)?;
+                if !self.needs_resize(&state, header.get_size(), pairs.len())? {
+                    break self.insert_into_home_blocks(&state, directory_latch, &header, pairs, &mut leftovers)?;
                 }
-                block_offset = 0;
+                header.get_size()
+            };
 
-                continue;
+            self.grow(size)?;
+        };
+
+        for (k, v) in leftovers {
+            if matches!(self.write_pair(k, v, false)?, WriteOutcome::Inserted) {
+                inserted += 1;
             }
+        }
+        Ok(inserted)
+    }
 
-            if block_and_offset.duplicated() {
-                return false;
+    /// Values of `k` in probe order like `get_value`, but at most `limit` of them, starting where
+    /// `cursor` left off or at the first value without one. Comes back with the cursor to pass on
+    /// for the rest, `None` once no value is left.
+    ///
+    /// Pairs inserted or removed between the calls may or may not be seen. A resize or compaction in
+    /// between moves pairs around, values may be missed or repeated then.
+    pub fn get_value_limit(&self, k: &K, limit: usize, cursor: Option<ValueCursor>) -> io::Result<(Vec<V>, Option<ValueCursor>)> {
+        let state = self.state.read().unwrap();
+        let header = self.latched_header_view(state.header_pid)?;
+
+        let mut values = Vec::new();
+        let mut next = None;
+        self.for_each_pair_from(&header, k, cursor.map_or(0, |cursor| cursor.probed), |bpm, _, block_offset, probed, block| {
+            if values.len() == limit {
+                next = Some(ValueCursor { probed });
+                return Ok(true);
             }
+            values.push(LinearProbeHashTable::<K, V>::read_value(bpm, block.get(block_offset)?.1)?);
+            Ok(false)
+        })?;
 
-            let (mut found_block, offset) = block_and_offset.unwrap();
-            assert!(found_block.insert(offset, k.clone(), v.clone()));
-            let vec = found_block.serialize();
-            LinearProbeHashTable::<K, V>::update_page(self.buffer_pool_manager, next_block_pid, vec);
+        Ok((values, next))
+    }
+
+    /// Replaces the first value of `k` in probe order by `merge` of it, or inserts `merge(None)` if
+    /// `k` has no pair, returning the value written. Other writers wait on the directory latch
+    /// meanwhile, so nothing changes the pair between reading and writing it, e.g. when counting.
+    pub fn merge<F: Fn(Option<&V>) -> V>(&self, k: &K, merge: F) -> io::Result<V> {
+        loop {
+            let size = {
+                let state = self.state.read().unwrap();
+                let directory_latch = self.latch_page(state.header_pid, |latch| latch.upgradable_read())?;
+                let header = self.header_view_of(state.header_pid)?;
+                let mut first = None;
+                self.for_each_pair(&header, k, |bpm, block_pid, block_offset, block| {
+                    let old_value = block.get(block_offset)?.1;
+                    let old = LinearProbeHashTable::<K, V>::read_value(bpm, old_value.clone())?;
+                    first = Some((block_pid, block_offset, old_value, old));
+                    Ok(true)
+                })?;
+                if let Some((block_pid, block_offset, old_value, old)) = first {
+                    let merged = merge(Some(&old));
+                    self.replace_at(block_pid, block_offset, k, &merged, old_value)?;
+                    return Ok(merged);
+                }
+
+                if self.needs_resize(&state, header.get_size(), 1)? {
+                    header.get_size()
+                } else {
+                    // `k` has no pair, so there is no duplicate to look for
+                    let (block_idx, block_offset) = self.home_slot(k, header.get_size());
+                    match self.find_insert_position(k, None, &header, block_idx, block_offset)? {
+                        Found(position) => {
+                            let merged = merge(None);
+                            self.insert_new(&state, directory_latch, &header, position, k, &merged)?;
+                            return Ok(merged);
+                        },
+                        _ => header.get_size(),
+                    }
+                }
+            };
 
-            return true;
+            self.grow(size)?;
         }
     }
-}
-
-impl<'a, K, V> HashTable<K, V> for LinearProbeHashTable<'a, K, V> where
-    K: HashKeyType + DeserializeOwned,
-    V: ValueType + DeserializeOwned,
-{
-    /// linear hash table insert:
-    /// 1. slot_index = hash(key) % size
-    /// 2. if slot not occupied, insert, done.
-    ///    else if
-    ///         1. can find next empty slot, insert, done
-    ///         2. find same k-v pair, cannot insert, do nothing
-    ///    else need resize
-    /// 3. if slot of page not exist, allocate one
-    fn insert(&mut self, k: &K, v: &V) -> bool {
-        let mut header = self.get_header();
 
-        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
-        let slot_idx = ((self.hash_fn)(k) % (header.get_size() * slot_capacity) as u64) as usize;
-        let block_idx = slot_idx / slot_capacity;
-        let block_offset = slot_idx - block_idx * slot_capacity;
+    /// Live pairs, counted on the first header page. Tables whose header keeps a layout from before the
+    /// count are scanned instead.
+    pub fn len(&self) -> io::Result<usize> {
+        let state = self.state.read().unwrap();
+        self.len_of(state.header_pid)
+    }
 
-        self.try_insert_to_appropriate_slot(k, v, &mut header, block_idx, block_offset)
+    fn len_of(&self, header_pid: PageId) -> io::Result<usize> {
+        match self.latched_header_view(header_pid)?.pages()[0].get_len() {
+            Some(len) => Ok(len as usize),
+            None => Ok(self.stats_of(header_pid)?.readable),
+        }
     }
 
-    fn remove(&mut self, _k: &K) {
-        todo!()
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
     }
 
-    fn get_value(&mut self, k: &K) -> Vec<V> {
-        let header = self.get_header();
+    /// Rewrites the count on the first header page in place, if its layout holds one. Writers keep the
+    /// count consistent by holding the directory latch.
+    fn update_len<F: FnOnce(u64) -> u64>(&self, header_pid: PageId, update: F) -> io::Result<()> {
+        let mut bpm = self.bpm();
+        let updated = {
+            let mut header_page = bpm.fetch_page_tagged(header_pid, "hash_table.update_len")?.write().unwrap();
+            HashTableHeaderView::from_page(header_page.get_data_mut()).map(|mut header| match header.get_len() {
+                Some(len) => header.set_len(update(len)),
+                None => false,
+            })
+        };
+        bpm.unpin_page(header_pid, matches!(updated, Ok(true)))?;
 
-        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
-        let slot_idx = ((self.hash_fn)(k) % (header.get_size() * slot_capacity) as u64) as usize;
-        let block_idx = slot_idx / slot_capacity;
-        let init_block_offset = slot_idx - block_idx * slot_capacity;
+        updated.map(|_| ())
+    }
 
-        let mut res = Vec::new();
-        let mut next_block_idx = block_idx;
-        let mut block_offset = init_block_offset;
-        loop {
-            let blk_pid = header.get_block_page_id(next_block_idx);
-            if blk_pid.is_none() {
-                return res;
+    /// Pairs of the table as of this call, however it changes while they are iterated. Every block
+    /// page is snapshotted at once while no operation runs, then writers go on: a page written to
+    /// later is copied first, leaving the snapshot as it was.
+    ///
+    /// Overflowed values are read right away, since a remove frees their pages.
+    pub fn iter_snapshot(&self) -> io::Result<SnapshotIter<K, V>> {
+        let state = self.state.write().unwrap();
+        let header = self.header_view_of(state.header_pid)?;
+        let mut bpm = self.bpm();
+        let mut blocks = Vec::new();
+        let mut overflowed = HashMap::new();
+        for block_idx in 0..header.get_size() {
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => continue,
+            };
+
+            let block = LinearProbeHashTable::<K, V>::block_view(&mut bpm, block_pid)?;
+            for offset in 0..block.capacity() {
+                if block.is_readable(offset)? && block.is_overflowed(offset)? {
+                    let value = LinearProbeHashTable::<K, V>::read_value(&mut bpm, block.get(offset)?.1)?;
+                    overflowed.insert((blocks.len(), offset), value);
+                }
             }
+            blocks.push(block);
+        }
 
-            let finished = LinearProbeHashTable::<K, V>::find_values_in_block(
-                self.buffer_pool_manager,
-                k,
-                blk_pid.unwrap(),
-                block_offset,
-                &mut res);
+        Ok(SnapshotIter { blocks, overflowed, block_idx: 0, offset: 0 })
+    }
 
-            if finished {
-                break;
-            }
+    /// Slot usage of every block as text, for following probe chains while debugging: the header pages
+    /// in chain order, then a line per block with its page and counts, followed by its slots, 64 a line.
+    /// A slot shows as `.` if free, `#` if live, `~` if live with its value in an overflow chain, and
+    /// `x` if a tombstone.
+    pub fn dump_occupancy(&self) -> io::Result<String> {
+        let state = self.state.read().unwrap();
+        let header = self.header_view_of(state.header_pid)?;
+        let header_pids: Vec<String> = header.page_ids().iter().map(PageId::to_string).collect();
+        let mut lines = vec![format!("header pages {}", header_pids.join(" -> "))];
+        for block_idx in 0..header.get_size() {
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => {
+                    lines.push(format!("block {} unallocated", block_idx));
+                    continue;
+                }
+            };
 
-            if next_block_idx + 1 == header.get_size() {
-                next_block_idx = 0;
-            } else {
-                next_block_idx += 1;
+            let block = LinearProbeHashTable::<K, V>::block_view(&mut self.bpm(), block_pid)?;
+            lines.push(format!("block {} page {}: {} occupied, {} live", block_idx, block_pid, block.num_occupied(), block.num_readable()));
+            let slots = (0..block.capacity())
+                .map(|i| Ok(match (block.is_occupied(i)?, block.is_readable(i)?, block.is_overflowed(i)?) {
+                    (false, _, _) => '.',
+                    (true, false, _) => 'x',
+                    (true, true, false) => '#',
+                    (true, true, true) => '~',
+                }))
+                .collect::<io::Result<Vec<char>>>()?;
+            lines.extend(slots.chunks(64).map(|row| format!("  {}", row.iter().collect::<String>())));
+        }
+
+        Ok(lines.join("\n") + "\n")
+    }
+
+    /// Reads every allocated block in place, decoding the keys of the live pairs for their probe lengths.
+    pub fn stats(&self) -> io::Result<HashTableStats> {
+        let state = self.state.read().unwrap();
+        let mut stats = self.stats_of(state.header_pid)?;
+        let header = self.header_view_of(state.header_pid)?;
+        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
+        for block_idx in 0..header.get_size() {
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => continue,
+            };
+
+            let block = LinearProbeHashTable::<K, V>::block_view(&mut self.bpm(), block_pid)?;
+            for offset in 0..slot_capacity {
+                if !block.is_readable(offset)? {
+                    continue;
+                }
+
+                let (home_block_idx, home_offset) = self.home_slot(&block.get_key(offset)?, header.get_size());
+                let (home, slot) = (home_block_idx * slot_capacity + home_offset, block_idx * slot_capacity + offset);
+                // a probe sequence wrapping around the table ends before its home slot
+                stats.add_probe_length((slot + stats.slots - home) % stats.slots);
             }
-            block_offset = 0;
         }
 
-        res
+        Ok(stats)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use serde::{Deserialize, Serialize};
+    /// Slot usage only, read off the bitmaps of every allocated block.
+    fn stats_of(&self, header_pid: PageId) -> io::Result<HashTableStats> {
+        let header = self.header_view_of(header_pid)?;
+        let mut stats = HashTableStats {
+            slots: header.get_size() * HashTableBlockPage::<K, V>::capacity_of_block(),
+            ..HashTableStats::default()
+        };
+        for block_idx in 0..header.get_size() {
+            if let Some(block_pid) = header.get_block_page_id(block_idx)? {
+                let block = LinearProbeHashTable::<K, V>::block_view(&mut self.bpm(), block_pid)?;
+                stats.allocated_blocks += 1;
+                stats.occupied += block.num_occupied();
+                stats.readable += block.num_readable();
+            }
+        }
 
-    use crate::common::hash::hash;
-    use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+        Ok(stats)
+    }
 
-    use super::*;
+    fn bpm(&self) -> MutexGuard<'_, BufferPoolManager> {
+        self.buffer_pool_manager.lock().unwrap()
+    }
 
-    #[derive(Hash, Default, Clone, Serialize, Deserialize)]
-    struct FakeKey {
-        data: [u8; 10],
+    fn latch_page<L, F>(&self, pid: PageId, take: F) -> io::Result<PinnedLatch<L>>
+        where F: FnOnce(&Arc<PageLatch>) -> L
+    {
+        PinnedLatch::acquire(&self.buffer_pool_manager, pid, take)
     }
 
-    impl HashKeyType for FakeKey {}
+    #[cfg(test)]
+    fn get_header(&self) -> io::Result<HashTableHeaderChain<HashTableHeaderPage>> {
+        read_header_chain(&mut self.bpm(), self.get_header_pid())
+    }
 
-    impl PartialEq<Self> for FakeKey {
-        fn eq(&self, other: &Self) -> bool {
-            self.data == other.data
-        }
+    #[cfg(test)]
+    fn header_view(&self) -> io::Result<HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>> {
+        self.header_view_of(self.get_header_pid())
     }
 
-    impl Eq for FakeKey {}
+    fn header_view_of(&self, header_pid: PageId) -> io::Result<HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>> {
+        let mut bpm = self.bpm();
+        HashTableHeaderChain::load(header_pid, |header_pid| {
+            let header_page = bpm.snapshot_page(header_pid)?;
+            HashTableHeaderView::from_page(header_page)
+                .with_context(|| ErrorContext::new("hash_table.get_header").page(header_pid))
+        })
+    }
 
-    #[derive(Default, Clone, Serialize, Deserialize)]
-    struct FakeValue {
-        data: [u8; 20],
+    /// Loads the directory under its read latch, so no block is seen half linked into it.
+    fn latched_header_view(&self, header_pid: PageId) -> io::Result<HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>> {
+        let _directory_latch = self.latch_page(header_pid, |latch| latch.read())?;
+        self.header_view_of(header_pid)
     }
 
-    impl Eq for FakeValue {}
+    /// Unpinned snapshot of the block, read in place.
+    fn block_view(bpm: &mut BufferPoolManager, block_pid: PageId) -> io::Result<HashTableBlockView<K, V, PageSnapshot>> {
+        let block_page = bpm.snapshot_page(block_pid)?;
+        HashTableBlockView::from_page(block_page)
+            .with_context(|| ErrorContext::new("hash_table.get_block").page(block_pid))
+    }
 
-    impl PartialEq<Self> for FakeValue {
-        fn eq(&self, other: &Self) -> bool {
-            self.data == other.data
-        }
+    /// Block and offset of the slot the probe sequence of `k` starts at, in a table of `size` blocks.
+    fn home_slot(&self, k: &K, size: usize) -> (usize, usize) {
+        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
+        let slot_idx = (self.hasher.hash(k) % (size * slot_capacity) as u64) as usize;
+        (slot_idx / slot_capacity, slot_idx % slot_capacity)
     }
 
-    impl ValueType for FakeValue {}
+    /// A new block is linked into the directory, which the caller must hold the latch for.
+    fn insert_at(&self,
+                 header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                 position: InsertPosition,
+                 k: &K,
+                 value: &SlotValue<&V>) -> io::Result<()> {
+        match position {
+            InsertPosition::Free(block_pid, block_offset) | InsertPosition::Tombstone(block_pid, block_offset) => {
+                let _block_latch = self.latch_page(block_pid, |latch| latch.write())?;
+                if !LinearProbeHashTable::<K, V>::insert_to_block(&mut self.bpm(), k, value, block_pid, block_offset)? {
+                    return Err(PageError::SlotChanged { slot_idx: block_offset }.into());
+                }
+            },
+            InsertPosition::NewBlock(block_idx, block_offset) =>
+                LinearProbeHashTable::<K, V>::insert_to_new_block(&mut self.bpm(), k, value, header, block_idx, block_offset)?,
+        }
 
-    const FAKE_HASH: fn(&FakeKey) -> u64 = |key: &FakeKey| { bincode::deserialize(&key.data).unwrap() };
+        Ok(())
+    }
 
-    fn build_kv(k: u64, v: u64) -> (FakeKey, FakeValue) {
+    fn insert_to_new_block(bpm: &mut BufferPoolManager,
+                           k: &K,
+                           value: &SlotValue<&V>,
+                           header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                           block_idx: usize,
+                           block_offset: usize) -> io::Result<()> {
+        let (block_pid, inserted) = {
+            let mut block_page = bpm.new_page_tagged("hash_table.new_block")?.write().unwrap();
+            let inserted = HashTableBlockView::<K, V, _>::init(block_page.get_data_mut())
+                .and_then(|mut block| LinearProbeHashTable::<K, V>::insert_pair(&mut block, block_offset, k, value));
+            (block_page.get_id(), inserted)
+        };
+        bpm.unpin_page(block_pid, true)?;
+
+        // collapse cannot happen in new block, the block is given up otherwise
+        if !matches!(inserted, Ok(true)) {
+            bpm.delete_page(block_pid)?;
+            return Err(inserted.err().unwrap_or_else(|| PageError::SlotChanged { slot_idx: block_offset }.into()));
+        }
+        let (header_pid, slot_idx) = header.header_page_of(block_idx)?;
+        let mut header_page = HeaderPageGuard::fetch(bpm, header_pid, "hash_table.new_block")?;
+        header_page.set(block_pid, slot_idx)?;
+        Ok(header_page.release()?)
+    }
+
+    fn insert_to_block(bpm: &mut BufferPoolManager, k: &K, value: &SlotValue<&V>, block_pid: PageId, block_offset: usize) -> io::Result<bool> {
+        let inserted = {
+            let mut block_page = bpm.fetch_page_tagged(block_pid, "hash_table.insert")?.write().unwrap();
+            HashTableBlockView::<K, V, _>::from_page(block_page.get_data_mut())
+                .and_then(|mut block| LinearProbeHashTable::<K, V>::insert_pair(&mut block, block_offset, k, value))
+        };
+        bpm.unpin_page(block_pid, true)?;
+
+        inserted
+    }
+
+    fn insert_pair<D: AsRef<[u8]> + AsMut<[u8]>>(block: &mut HashTableBlockView<K, V, D>,
+                                                 block_offset: usize,
+                                                 k: &K,
+                                                 value: &SlotValue<&V>) -> io::Result<bool> {
+        match value {
+            SlotValue::Overflow(overflow_ref) => block.insert_overflowed(block_offset, k, overflow_ref),
+            SlotValue::Inline(v) => block.insert(block_offset, k, v),
+        }
+    }
+
+    /// Writes the value to a new overflow chain if the pair does not fit into a slot, last page first
+    /// so each page is written once along with its successor.
+    fn write_value<'v>(bpm: &mut BufferPoolManager, k: &K, v: &'v V) -> io::Result<SlotValue<&'v V>> {
+        if HashTableBlockPage::<K, V>::fits_inline(k, v)? {
+            return Ok(SlotValue::Inline(v));
+        }
+
+        let raw = bincode::serialize(v).map_err(PageError::from)?;
+        let mut next_page_id = None;
+        for chunk in raw.chunks(OverflowPage::capacity()).rev() {
+            let overflow_page = OverflowPage::new(chunk.to_vec(), next_page_id)?;
+            next_page_id = Some(bpm.write_encoded(None, &overflow_page)?);
+        }
+
+        Ok(SlotValue::Overflow(OverflowRef { page_id: next_page_id.unwrap(), len: raw.len() as u64 }))
+    }
+
+    fn read_value(bpm: &mut BufferPoolManager, slot_value: SlotValue<V>) -> io::Result<V> {
+        let overflow_ref = match slot_value {
+            SlotValue::Inline(v) => return Ok(v),
+            SlotValue::Overflow(overflow_ref) => overflow_ref,
+        };
+
+        let raw = read_chain(&overflow_ref, |page_id| {
+            Ok(bpm.fetch_decoded(page_id)?)
+        })?;
+        Ok(bincode::deserialize(&raw).map_err(PageError::from)?)
+    }
+
+    /// Tombstones cannot end the search, a duplicate may sit behind them, so the first one seen is
+    /// recorded in `first_tombstone` for the pair to take instead of the free slot found. Without `val`
+    /// no duplicate is looked for.
+    fn find_available_slot(bpm: &mut BufferPoolManager,
+                           key: &K,
+                           duplicate: Duplicate<V>,
+                           block_pid: usize,
+                           block_offset: usize,
+                           first_tombstone: &mut Option<(PageId, usize)>) -> io::Result<FindSlotResult<usize>> {
+        let block = LinearProbeHashTable::<K, V>::block_view(bpm, block_pid)?;
+        LinearProbeHashTable::<K, V>::probe_block(bpm, &block, block_pid, key, duplicate, block_offset, first_tombstone)
+    }
+
+    fn duplicate_of<'a>(&self, v: Option<&'a V>) -> Duplicate<'a, V> {
+        match (v, self.duplicates) {
+            (None, _) => Duplicate::Unchecked,
+            (Some(_), DuplicatePolicy::UniqueKeys) => Duplicate::OfKey,
+            (Some(v), DuplicatePolicy::DistinctValues) => Duplicate::OfPair(v),
+        }
+    }
+
+    /// `find_available_slot` on a block already at hand.
+    fn probe_block<D: AsRef<[u8]>>(bpm: &mut BufferPoolManager,
+                                   block: &HashTableBlockView<K, V, D>,
+                                   block_pid: PageId,
+                                   key: &K,
+                                   duplicate: Duplicate<V>,
+                                   block_offset: usize,
+                                   first_tombstone: &mut Option<(PageId, usize)>) -> io::Result<FindSlotResult<usize>> {
+        // a block its filter rules the key out of has no duplicate, but is still searched for a free slot
+        let may_contain = block.may_contain(key);
+        for i in block_offset..HashTableBlockPage::<K, V>::capacity_of_block() {
+            if !block.is_occupied(i)? {
+                return Ok(Found(i));
+            }
+
+            if !block.is_readable(i)? {
+                first_tombstone.get_or_insert((block_pid, i));
+                continue;
+            }
+
+            let duplicated = may_contain && match duplicate {
+                Duplicate::Unchecked => false,
+                Duplicate::OfKey => key.eq(&block.get_key(i)?),
+                Duplicate::OfPair(val) => key.eq(&block.get_key(i)?)
+                    && val.eq(&LinearProbeHashTable::<K, V>::read_value(bpm, block.get(i)?.1)?),
+            };
+            if duplicated {
+                return Ok(Duplicated);
+            }
+        }
+
+        Ok(NotFound)
+    }
+
+    /// Leaves a tombstone in the slot. The caller holds the write latch of the block.
+    fn remove_from_block(bpm: &mut BufferPoolManager, block_pid: PageId, block_offset: usize) -> io::Result<bool> {
+        let removed = {
+            let mut block_page = bpm.fetch_page_tagged(block_pid, "hash_table.remove")?.write().unwrap();
+            HashTableBlockView::<K, V, _>::from_page(block_page.get_data_mut())
+                .and_then(|mut block| block.remove(block_offset))
+        };
+        bpm.unpin_page(block_pid, matches!(removed, Ok(true)))?;
+
+        removed
+    }
+
+    /// Deletes the pages of an overflow chain no slot refers to anymore.
+    fn free_overflow(bpm: &mut BufferPoolManager, overflow_ref: &OverflowRef) -> io::Result<()> {
+        read_chain(overflow_ref, |page_id| {
+            let overflow_page = bpm.fetch_decoded(page_id)?;
+            bpm.delete_page(page_id)?;
+            Ok(overflow_page)
+        })?;
+        Ok(())
+    }
+
+    /// Calls `visit` with the slot of every pair of `k`, in probe order, under the read latch of its
+    /// block, until it returns `true`. Reads the blocks in place, decoding only the keys probed and
+    /// leaving the values to `visit`.
+    fn for_each_pair<F>(&self,
+                        header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                        k: &K,
+                        mut visit: F) -> io::Result<()>
+        where F: FnMut(&mut BufferPoolManager, PageId, usize, &HashTableBlockView<K, V, PageSnapshot>) -> io::Result<bool>
+    {
+        self.for_each_pair_from(header, k, 0, |bpm, block_pid, block_offset, _, block| visit(bpm, block_pid, block_offset, block))
+    }
+
+    /// `for_each_pair` skipping the first `probed` slots of the probe sequence, handing `visit` the
+    /// slots searched before each pair too.
+    fn for_each_pair_from<F>(&self,
+                             header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                             k: &K,
+                             mut probed: usize,
+                             mut visit: F) -> io::Result<()>
+        where F: FnMut(&mut BufferPoolManager, PageId, usize, usize, &HashTableBlockView<K, V, PageSnapshot>) -> io::Result<bool>
+    {
+        let slot_capacity = HashTableBlockPage::<K, V>::capacity_of_block();
+        let num_slots = header.get_size() * slot_capacity;
+        let (home_block_idx, home_block_offset) = self.home_slot(k, header.get_size());
+        let home_slot_idx = home_block_idx * slot_capacity + home_block_offset;
+
+        // a probe sequence wrapping around every block ends in the home block, before the home slot
+        while probed < num_slots {
+            let slot_idx = (home_slot_idx + probed) % num_slots;
+            let (block_idx, block_offset) = (slot_idx / slot_capacity, slot_idx % slot_capacity);
+            let slots = block_offset..slot_capacity.min(block_offset + num_slots - probed);
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => break,
+            };
+
+            let _block_latch = self.latch_page(block_pid, |latch| latch.read())?;
+            let mut bpm = self.bpm();
+            let ended = LinearProbeHashTable::<K, V>::visit_block(&mut bpm, k, block_pid, slots.clone(), &mut |bpm, block_pid, offset, block| {
+                visit(bpm, block_pid, offset, probed + offset - block_offset, block)
+            })?;
+            if ended {
+                break;
+            }
+            probed += slots.len();
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a free slot among `slots` ended the probe sequence, or `visit` did.
+    fn visit_block<F>(bpm: &mut BufferPoolManager,
+                      key: &K,
+                      block_pid: PageId,
+                      slots: Range<usize>,
+                      visit: &mut F) -> io::Result<bool>
+        where F: FnMut(&mut BufferPoolManager, PageId, usize, &HashTableBlockView<K, V, PageSnapshot>) -> io::Result<bool>
+    {
+        let blk = LinearProbeHashTable::<K, V>::block_view(bpm, block_pid)?;
+        // a block its filter rules the key out of is only searched for a free slot, none of its keys is decoded
+        let may_contain = blk.may_contain(key);
+        // pairs of other keys hashed nearby and tombstones may sit in between, only a free slot ends the probe sequence
+        for offset in slots {
+            if !blk.is_occupied(offset)? {
+                return Ok(true);
+            }
+
+            if !may_contain || !blk.is_readable(offset)? {
+                continue;
+            }
+
+            if blk.get_key(offset)?.eq(key) && visit(bpm, block_pid, offset, &blk)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// The first tombstone on the probe sequence of the pair, or else the free slot ending it. Comes
+    /// back `NotFound` when every slot holds a live pair. Without `v` no duplicate is looked for, e.g.
+    /// when rehashing pairs known to be distinct.
+    fn find_insert_position(&self,
+                            k: &K,
+                            v: Option<&V>,
+                            header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                            block_idx: usize,
+                            init_block_offset: usize) -> io::Result<FindSlotResult<InsertPosition>> {
+        let mut next_block_idx = block_idx;
+        let mut block_offset = init_block_offset;
+        let mut first_tombstone = None;
+        let mut blocks_searched = 0;
+        loop {
+            let next_block_pid = match header.get_block_page_id(next_block_idx)? {
+                Some(block_pid) => block_pid,
+                None => return Ok(Found(match first_tombstone {
+                    Some((tombstone_pid, tombstone_offset)) => InsertPosition::Tombstone(tombstone_pid, tombstone_offset),
+                    None => InsertPosition::NewBlock(next_block_idx, block_offset),
+                })),
+            };
+
+            let slot = LinearProbeHashTable::<K, V>::find_available_slot(
+                &mut self.bpm(), k, self.duplicate_of(v), next_block_pid, block_offset, &mut first_tombstone)?;
+            match slot {
+                Duplicated => return Ok(Duplicated),
+                Found(free_offset) => return Ok(Found(match first_tombstone {
+                    Some((tombstone_pid, tombstone_offset)) => InsertPosition::Tombstone(tombstone_pid, tombstone_offset),
+                    None => InsertPosition::Free(next_block_pid, free_offset),
+                })),
+                NotFound => {}
+            }
+
+            blocks_searched += 1;
+            // every slot searched without a free one, a tombstone is the only place left
+            if blocks_searched > header.get_size() {
+                return Ok(match first_tombstone {
+                    Some((tombstone_pid, tombstone_offset)) => Found(InsertPosition::Tombstone(tombstone_pid, tombstone_offset)),
+                    None => NotFound,
+                });
+            }
+
+            next_block_idx = (next_block_idx + 1) % header.get_size();
+            block_offset = 0;
+        }
+    }
+
+    /// Places the pairs probing no further than their home block, grouped by that block: each block is
+    /// copied out once, filled with all of its pairs and written back once. The caller holds the
+    /// directory latch. Returns how many pairs went in, the pairs probing past their block are left in
+    /// `leftovers`.
+    fn insert_into_home_blocks<'p>(&self,
+                                   state: &TableState,
+                                   directory_latch: PinnedLatch<PageUpgradableLatch>,
+                                   header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                                   pairs: &'p [(K, V)],
+                                   leftovers: &mut Vec<&'p (K, V)>) -> io::Result<usize> {
+        let mut groups = BTreeMap::<usize, Vec<_>>::new();
+        for pair in pairs {
+            let (block_idx, block_offset) = self.home_slot(&pair.0, header.get_size());
+            groups.entry(block_idx).or_default().push((block_offset, pair));
+        }
+        let mut new_blocks = false;
+        for block_idx in groups.keys() {
+            new_blocks |= header.get_block_page_id(*block_idx)?.is_none();
+        }
+        // readers must not see the directory while blocks are linked into it
+        let _upgraded_latch = if new_blocks { Some(directory_latch.upgrade()) } else { None };
+
+        let (mut inserted, mut new_slots) = (0, 0);
+        for (block_idx, group) in groups {
+            let block_pid = header.get_block_page_id(block_idx)?;
+            let _block_latch = match block_pid {
+                Some(block_pid) => Some(self.latch_page(block_pid, |latch| latch.write())?),
+                None => None,
+            };
+            let mut data = match block_pid {
+                Some(block_pid) => self.bpm().snapshot_page(block_pid)?.get_data().to_vec(),
+                None => vec![0; PAGE_SIZE],
+            };
+            let mut block = match block_pid {
+                Some(_) => HashTableBlockView::<K, V, _>::from_page(&mut data[..])?,
+                None => HashTableBlockView::<K, V, _>::init(&mut data[..])?,
+            };
+
+            let mut placed = 0;
+            for (block_offset, pair) in group {
+                let (k, v) = pair;
+                let mut first_tombstone = None;
+                let slot = LinearProbeHashTable::<K, V>::probe_block(
+                    &mut self.bpm(), &block, block_pid.unwrap_or(INVALID_PAGE_ID), k, self.duplicate_of(Some(v)), block_offset, &mut first_tombstone)?;
+                let slot_idx = match (slot, first_tombstone) {
+                    (Duplicated, _) => continue,
+                    (NotFound, _) => {
+                        leftovers.push(pair);
+                        continue;
+                    },
+                    (Found(_), Some((_, tombstone_offset))) => tombstone_offset,
+                    (Found(free_offset), None) => {
+                        new_slots += 1;
+                        free_offset
+                    },
+                };
+                let value = LinearProbeHashTable::<K, V>::write_value(&mut self.bpm(), k, v)?;
+                if !LinearProbeHashTable::<K, V>::insert_pair(&mut block, slot_idx, k, &value)? {
+                    return Err(PageError::SlotChanged { slot_idx }.into());
+                }
+                placed += 1;
+            }
+            if placed == 0 {
+                continue;
+            }
+
+            let mut bpm = self.bpm();
+            match block_pid {
+                Some(block_pid) => {
+                    bpm.fetch_page_tagged(block_pid, "hash_table.insert_batch")?.write().unwrap().get_data_mut().copy_from_slice(&data);
+                    bpm.unpin_page(block_pid, true)?;
+                },
+                None => {
+                    let block_pid = {
+                        let mut block_page = bpm.new_page_tagged("hash_table.insert_batch")?.write().unwrap();
+                        block_page.get_data_mut().copy_from_slice(&data);
+                        block_page.get_id()
+                    };
+                    bpm.unpin_page(block_pid, true)?;
+                    let (header_pid, slot_idx) = header.header_page_of(block_idx)?;
+                    let mut header_page = HeaderPageGuard::fetch(&mut bpm, header_pid, "hash_table.insert_batch")?;
+                    header_page.set(block_pid, slot_idx)?;
+                    header_page.release()?;
+                },
+            }
+            inserted += placed;
+        }
+
+        if inserted > 0 {
+            self.update_len(state.header_pid, |len| len + inserted as u64)?;
+            let mut occupied = state.occupied.lock().unwrap();
+            *occupied = occupied.map(|occupied| occupied + new_slots);
+        }
+        Ok(inserted)
+    }
+
+    /// Inserts the pair, or with `overwrite` puts `v` into the slot of the first pair of `k` if any.
+    fn write_pair(&self, k: &K, v: &V, overwrite: bool) -> io::Result<WriteOutcome> {
+        loop {
+            let size = {
+                let state = self.state.read().unwrap();
+                let directory_latch = self.latch_page(state.header_pid, |latch| latch.upgradable_read())?;
+                let header = self.header_view_of(state.header_pid)?;
+                if overwrite && self.replace_first(&header, k, v)? {
+                    return Ok(WriteOutcome::Replaced);
+                }
+
+                if self.needs_resize(&state, header.get_size(), 1)? {
+                    header.get_size()
+                } else {
+                    let (block_idx, block_offset) = self.home_slot(k, header.get_size());
+                    match self.find_insert_position(k, Some(v), &header, block_idx, block_offset)? {
+                        Found(position) => {
+                            self.insert_new(&state, directory_latch, &header, position, k, v)?;
+                            return Ok(WriteOutcome::Inserted);
+                        },
+                        Duplicated => return Ok(WriteOutcome::Duplicated),
+                        NotFound => header.get_size(),
+                    }
+                }
+            };
+
+            // the state lock is given up first, resizing waits for every handle to do so
+            self.grow(size)?;
+        }
+    }
+
+    /// Inserts a pair found a place for under `directory_latch`, counting it in.
+    fn insert_new(&self,
+                  state: &TableState,
+                  directory_latch: PinnedLatch<PageUpgradableLatch>,
+                  header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                  position: InsertPosition,
+                  k: &K,
+                  v: &V) -> io::Result<()> {
+        let value = LinearProbeHashTable::<K, V>::write_value(&mut self.bpm(), k, v)?;
+        // readers must not see the directory while a block is linked into it
+        let _upgraded_latch = match position {
+            InsertPosition::NewBlock(..) => Some(directory_latch.upgrade()),
+            _ => None,
+        };
+        self.insert_at(header, position, k, &value)?;
+        self.update_len(state.header_pid, |len| len + 1)?;
+        if !matches!(position, InsertPosition::Tombstone(..)) {
+            let mut occupied = state.occupied.lock().unwrap();
+            *occupied = occupied.map(|occupied| occupied + 1);
+        }
+        Ok(())
+    }
+
+    /// Puts `v` into the slot of the first pair of `k`, then frees the overflow pages of the old value.
+    /// The caller holds the directory latch, keeping other writers off the slot.
+    fn replace_first(&self,
+                     header: &HashTableHeaderChain<HashTableHeaderView<PageSnapshot>>,
+                     k: &K,
+                     v: &V) -> io::Result<bool> {
+        let mut first = None;
+        self.for_each_pair(header, k, |_, block_pid, block_offset, block| {
+            first = Some((block_pid, block_offset, block.get(block_offset)?.1));
+            Ok(true)
+        })?;
+        match first {
+            Some((block_pid, block_offset, old_value)) => self.replace_at(block_pid, block_offset, k, v, old_value)?,
+            None => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Puts `v` into the slot holding `old_value`, then frees the overflow pages of the old value. The
+    /// caller holds the directory latch.
+    fn replace_at(&self, block_pid: PageId, block_offset: usize, k: &K, v: &V, old_value: SlotValue<V>) -> io::Result<()> {
+        let value = LinearProbeHashTable::<K, V>::write_value(&mut self.bpm(), k, v)?;
+        {
+            let _block_latch = self.latch_page(block_pid, |latch| latch.write())?;
+            let mut bpm = self.bpm();
+            let replaced = {
+                let mut block_page = bpm.fetch_page_tagged(block_pid, "hash_table.replace")?.write().unwrap();
+                HashTableBlockView::<K, V, _>::from_page(block_page.get_data_mut()).and_then(|mut block| {
+                    block.remove(block_offset)?;
+                    LinearProbeHashTable::<K, V>::insert_pair(&mut block, block_offset, k, &value)
+                })
+            };
+            bpm.unpin_page(block_pid, replaced.is_ok())?;
+            replaced?;
+        }
+        if let SlotValue::Overflow(overflow_ref) = old_value {
+            LinearProbeHashTable::<K, V>::free_overflow(&mut self.bpm(), &overflow_ref)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `additional` more occupied slots would take the table past its maximum fill factor.
+    fn needs_resize(&self, state: &TableState, size: usize, additional: usize) -> io::Result<bool> {
+        let mut occupied = state.occupied.lock().unwrap();
+        let occupied = match *occupied {
+            Some(occupied) => occupied,
+            None => *occupied.insert(self.stats_of(state.header_pid)?.occupied),
+        };
+
+        let slots = size * HashTableBlockPage::<K, V>::capacity_of_block();
+        Ok((occupied + additional) as f64 > state.max_fill_factor * slots as f64)
+    }
+}
+
+impl<K, V> HashTable<K, V> for LinearProbeHashTable<K, V> where
+    K: HashKeyType + DeserializeOwned,
+    V: ValueType + DeserializeOwned,
+{
+    /// linear hash table insert:
+    /// 1. slot_index = hash(key) % size
+    /// 2. if slot not occupied, insert, done.
+    ///    else if
+    ///         1. can find next empty slot, insert, done
+    ///         2. find same k-v pair, cannot insert, do nothing
+    ///    else need resize
+    /// 3. if slot of page not exist, allocate one
+    fn insert(&mut self, k: &K, v: &V) -> Result<bool, DbError> {
+        Ok(matches!(self.write_pair(k, v, false)?, WriteOutcome::Inserted))
+    }
+
+    /// The first pair of `k` in probe order is the one overwritten.
+    fn upsert(&mut self, k: &K, v: &V) -> Result<bool, DbError> {
+        Ok(matches!(self.write_pair(k, v, true)?, WriteOutcome::Replaced))
+    }
+
+    /// Leaves a tombstone in the slot of every pair of `k` and frees the overflow pages of their values.
+    fn remove(&mut self, k: &K) -> Result<(), DbError> {
+        let state = self.state.read().unwrap();
+        let directory_latch = self.latch_page(state.header_pid, |latch| latch.upgradable_read())?;
+        let header = self.header_view_of(state.header_pid)?;
+
+        let mut pairs = Vec::new();
+        self.for_each_pair(&header, k, |_, block_pid, block_offset, block| {
+            pairs.push((block_pid, block_offset, block.get(block_offset)?.1));
+            Ok(false)
+        })?;
+
+        // no other writer gets in while the directory latch is held, so the slots still hold the pairs
+        let removed = pairs.len() as u64;
+        for (block_pid, block_offset, value) in pairs {
+            {
+                let _block_latch = self.latch_page(block_pid, |latch| latch.write())?;
+                LinearProbeHashTable::<K, V>::remove_from_block(&mut self.bpm(), block_pid, block_offset)?;
+            }
+            // readers of the value held the block latch, none is left once it was taken
+            if let SlotValue::Overflow(overflow_ref) = value {
+                LinearProbeHashTable::<K, V>::free_overflow(&mut self.bpm(), &overflow_ref)?;
+            }
+        }
+        if removed > 0 {
+            self.update_len(state.header_pid, |len| len.saturating_sub(removed))?;
+            // resizing waits for the state lock to be given up, and frees the directory page
+            drop((directory_latch, state));
+            self.shrink_if_sparse()?;
+        }
+        Ok(())
+    }
+
+    /// Stops at the pair holding `v`, comparing the values of `k` on the way.
+    fn remove_kv(&mut self, k: &K, v: &V) -> Result<bool, DbError> {
+        let state = self.state.read().unwrap();
+        let directory_latch = self.latch_page(state.header_pid, |latch| latch.upgradable_read())?;
+        let header = self.header_view_of(state.header_pid)?;
+
+        let mut pair = None;
+        self.for_each_pair(&header, k, |bpm, block_pid, block_offset, block| {
+            let value = block.get(block_offset)?.1;
+            if !v.eq(&LinearProbeHashTable::<K, V>::read_value(bpm, value.clone())?) {
+                return Ok(false);
+            }
+            pair = Some((block_pid, block_offset, value));
+            Ok(true)
+        })?;
+        let (block_pid, block_offset, value) = match pair {
+            Some(pair) => pair,
+            None => return Ok(false),
+        };
+
+        {
+            let _block_latch = self.latch_page(block_pid, |latch| latch.write())?;
+            LinearProbeHashTable::<K, V>::remove_from_block(&mut self.bpm(), block_pid, block_offset)?;
+        }
+        if let SlotValue::Overflow(overflow_ref) = value {
+            LinearProbeHashTable::<K, V>::free_overflow(&mut self.bpm(), &overflow_ref)?;
+        }
+        self.update_len(state.header_pid, |len| len.saturating_sub(1))?;
+        drop((directory_latch, state));
+        self.shrink_if_sparse()?;
+        Ok(true)
+    }
+
+    fn get_value(&mut self, k: &K) -> Result<Vec<V>, DbError> {
+        let state = self.state.read().unwrap();
+        let header = self.latched_header_view(state.header_pid)?;
+
+        let mut res = Vec::new();
+        self.for_each_pair(&header, k, |bpm, _, block_offset, block| {
+            res.push(LinearProbeHashTable::<K, V>::read_value(bpm, block.get(block_offset)?.1)?);
+            Ok(false)
+        })?;
+
+        Ok(res)
+    }
+
+    /// Stops at the first pair of `k`.
+    fn contains_key(&mut self, k: &K) -> Result<bool, DbError> {
+        let state = self.state.read().unwrap();
+        let header = self.latched_header_view(state.header_pid)?;
+
+        let mut found = false;
+        self.for_each_pair(&header, k, |_, _, _, _| {
+            found = true;
+            Ok(true)
+        })?;
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use serde::{Deserialize, Serialize};
+
+    use crate::buffer::buffer_pool_error::BufferPoolError;
+    use crate::common::hash::{hash, XxKeyHasher};
+    use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+
+    use super::*;
+
+    #[derive(Hash, Default, Clone, Serialize, Deserialize)]
+    struct FakeKey {
+        data: [u8; 10],
+    }
+
+    impl HashKeyType for FakeKey {}
+
+    impl PartialEq<Self> for FakeKey {
+        fn eq(&self, other: &Self) -> bool {
+            self.data == other.data
+        }
+    }
+
+    impl Eq for FakeKey {}
+
+    #[derive(Default, Clone, Serialize, Deserialize)]
+    struct FakeValue {
+        data: [u8; 20],
+    }
+
+    impl Eq for FakeValue {}
+
+    impl PartialEq<Self> for FakeValue {
+        fn eq(&self, other: &Self) -> bool {
+            self.data == other.data
+        }
+    }
+
+    impl ValueType for FakeValue {}
+
+    const FAKE_HASH: fn(&FakeKey) -> u64 = |key: &FakeKey| { bincode::deserialize(&key.data).unwrap() };
+
+    fn build_kv(k: u64, v: u64) -> (FakeKey, FakeValue) {
         let k_vec = bincode::serialize(&k).unwrap();
         let mut key = FakeKey { data: [0; 10] };
         for i in 0..k_vec.len() {
             key.data[i] = k_vec[i]
         }
-
-        let v_vec = bincode::serialize(&v).unwrap();
-        let mut val = FakeValue { data: [0; 20] };
-        for i in 0..v_vec.len() {
-            val.data[i] = v_vec[i]
+
+        let v_vec = bincode::serialize(&v).unwrap();
+        let mut val = FakeValue { data: [0; 20] };
+        for i in 0..v_vec.len() {
+            val.data[i] = v_vec[i]
+        }
+
+        (key, val)
+    }
+
+    #[test]
+    fn should_build_new_linear_probe_hash_table() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let size: usize = 16;
+
+        // when
+        let header_pid = {
+            let lpht = LinearProbeHashTable::<FakeKey, FakeValue>::new(size, bpm.clone(), hash).unwrap();
+            lpht.get_header_pid()
+        };
+
+        // then
+        let mut bpm = bpm.lock().unwrap();
+        let page_with_lock = bpm.fetch_page(header_pid).unwrap();
+        let header_raw = page_with_lock.read().unwrap();
+        let header: HashTableHeaderPage = HashTableHeaderPage::deserialize(header_raw.get_data()).unwrap();
+
+        assert_eq!(header.get_size(), size);
+        assert_eq!(header.get_page_id(), header_raw.get_id());
+    }
+
+    #[test]
+    fn should_insert_kv_pair_to_new_block() {
+        // given
+        let bucket_size = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let (header_view, header_pid) = {
+            let table = LinearProbeHashTable::<FakeKey, FakeValue>::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+            (table.header_view().unwrap(), table.get_header_pid())
+        };
+
+        let new_block_pid = 1;
+        let slot_idx = 0;
+        let block_index = 0;
+        let block_offset = 21;
+
+        // when
+        let mut bpm = bpm.lock().unwrap();
+        let (key, val) = build_kv(21, 127);
+        LinearProbeHashTable::insert_to_new_block(&mut bpm, &key, &SlotValue::Inline(&val), &header_view, block_index, block_offset).unwrap();
+
+        // then
+        // get bucket page id
+        let header = read_header_chain(&mut bpm, header_pid).unwrap();
+        assert_eq!(bpm.pin_count_of(header_pid), Some(0));
+        assert_eq!(header.get_block_page_id(slot_idx).unwrap().unwrap(), new_block_pid);
+
+        // get value from bucket
+        let block_raw = bpm.fetch_page(new_block_pid).unwrap().read().unwrap();
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
+        let (k, v) = block.get(block_offset).unwrap();
+        assert_eq!(k.data[0], 21);
+        assert_eq!(v.data[0], 127);
+    }
+
+    #[test]
+    fn should_insert_one_kv_to_empty_hashtable() {
+        // given
+        let bucket_size = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), hash).unwrap();
+
+        // when
+        let (key, val) = build_kv(1, 127);
+        table.insert(&key, &val).unwrap();
+
+        // then
+        // calculate slot index and bucket index
+        let slot_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let slot_index = (hash(&key) % (bucket_size * slot_capacity) as u64) as usize;
+        let block_index = slot_index / slot_capacity;
+
+        // get bucket page id
+        let first_block_page_id = 1;
+        let header = table.get_header().unwrap();
+        assert_eq!(header.get_block_page_id(block_index).unwrap().unwrap(), first_block_page_id);
+
+        // get value from bucket
+        let mut bpm = bpm.lock().unwrap();
+        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
+        let (k, v) = block.get(slot_index - block_index * slot_capacity).unwrap();
+        assert_eq!(k.data[0], 1);
+        assert_eq!(v.data[0], 127);
+    }
+
+    #[test]
+    fn should_insert_one_kv_to_hashtable_with_same_block() {
+        // given
+        let bucket_size = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        let (key1, val) = build_kv(1, 127);
+        table.insert(&key1, &val).unwrap();
+
+        // when
+        let (key2, val) = build_kv(2, 127);
+        table.insert(&key2, &val).unwrap();
+
+        // then
+        // calculate slot index and bucket index
+        let slot_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let slot_index = (FAKE_HASH(&key2) % (bucket_size * slot_capacity) as u64) as usize;
+        let block_index = slot_index / slot_capacity;
+
+        // get bucket page id
+        let first_block_page_id = 1;
+        let header = table.get_header().unwrap();
+        assert_eq!(header.get_block_page_id(block_index).unwrap().unwrap(), first_block_page_id);
+
+        // get value from bucket
+        let mut bpm = bpm.lock().unwrap();
+        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
+        let (k, v) = block.get(slot_index - block_index * slot_capacity).unwrap();
+        assert_eq!(k.data[0], 2);
+        assert_eq!(v.data[0], 127);
+    }
+
+    #[test]
+    fn should_insert_one_kv_to_hashtable_with_same_block_meet_collapse() {
+        // given
+        let bucket_size = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        let (key1, val1) = build_kv(1, 127);
+        table.insert(&key1, &val1).unwrap();
+
+        // when
+        let (key2, val2) = build_kv(1, 126);
+        table.insert(&key2, &val2).unwrap();
+
+        // then
+        // calculate slot index and bucket index
+        let slot_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let slot_index = (FAKE_HASH(&key2) % (bucket_size * slot_capacity) as u64) as usize;
+        let block_index = slot_index / slot_capacity;
+
+        // get bucket page id
+        let first_block_page_id = 1;
+        let header = table.get_header().unwrap();
+        assert_eq!(header.get_block_page_id(block_index).unwrap().unwrap(), first_block_page_id);
+
+        // get value from bucket
+        let mut bpm = bpm.lock().unwrap();
+        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
+        let (k1, v1) = block.get(slot_index - block_index * slot_capacity).unwrap();
+        assert_eq!(k1.data[0], 1);
+        assert_eq!(v1.data[0], 127);
+        let (k2, v2) = block.get((slot_index - block_index * slot_capacity) + 1).unwrap();
+        assert_eq!(k2.data[0], 1);
+        assert_eq!(v2.data[0], 126);
+    }
+
+    #[test]
+    fn should_find_next_block_when_index_collapse() {
+        // given
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let mut bpm = BufferPoolManager::new_default(100);
+
+        // current block
+        let curr_block_pid =
+            {
+                let mut curr_block = HashTableBlockPage::<FakeKey, FakeValue>::new();
+                for i in 0..block_capacity {
+                    curr_block.insert(i, FakeKey { data: [0; 10] }, FakeValue { data: [0; 20] }).unwrap();
+                }
+                bpm.write_encoded(None, &curr_block).unwrap()
+            };
+
+        // next block
+        let next_block_pid = {
+            let mut next_block = HashTableBlockPage::<FakeKey, FakeValue>::new();
+            next_block.insert(0, FakeKey { data: [0; 10] }, FakeValue { data: [0; 20] }).unwrap();
+            next_block.insert(1, FakeKey { data: [0; 10] }, FakeValue { data: [0; 20] }).unwrap();
+            bpm.write_encoded(None, &next_block).unwrap()
+        };
+
+        // when
+        let no_available = LinearProbeHashTable::<FakeKey, FakeValue>::find_available_slot(
+            &mut bpm, &FakeKey { data: [1; 10] }, Duplicate::OfPair(&FakeValue { data: [0; 20] }), curr_block_pid, 0, &mut None).unwrap();
+        let duplicated = LinearProbeHashTable::<FakeKey, FakeValue>::find_available_slot(
+            &mut bpm, &FakeKey { data: [0; 10] }, Duplicate::OfPair(&FakeValue { data: [0; 20] }), next_block_pid, 0, &mut None).unwrap();
+        let found = LinearProbeHashTable::<FakeKey, FakeValue>::find_available_slot(
+            &mut bpm, &FakeKey { data: [1; 10] }, Duplicate::OfPair(&FakeValue { data: [1; 20] }), next_block_pid, 0, &mut None).unwrap();
+
+        // then
+        assert!(no_available.not_found());
+        assert!(duplicated.duplicated());
+        assert!(found.found());
+        assert_eq!(found.found_value(), Some(2));
+    }
+
+    #[test]
+    fn should_insert_one_kv_to_hashtable_with_new_block_when_meet_collapse() {
+        // given
+        let bucket_size = 16;
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the first block
+        for i in 0..block_capacity {
+            let (key, val) = build_kv(i as u64, 127);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        let (key, val) = build_kv(0, 33);
+        table.insert(&key, &val).unwrap();
+
+        // then
+        let second_block_page_id = 2;
+        let mut bpm = bpm.lock().unwrap();
+        let block_raw = bpm.fetch_page(second_block_page_id).unwrap().read().unwrap();
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
+        let (k, v) = block.get(0).unwrap();
+        assert_eq!(k.data[0], 0);
+        assert_eq!(v.data[0], 33);
+    }
+
+    #[test]
+    fn should_insert_one_kv_to_hashtable_with_exist_block_when_meet_collapse() {
+        // given
+        let bucket_size = 16;
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the first block
+        let (key, val) = build_kv(0, 123);
+        table.insert(&key, &val).unwrap();
+
+        // fill the last block
+        let last_block_base_idx = (bucket_size - 1) * block_capacity;
+        for i in 0..block_capacity {
+            let (key, val) = build_kv((last_block_base_idx + i) as u64, 127);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        let (key, val) = build_kv((last_block_base_idx + 1) as u64, 33);
+        table.insert(&key, &val).unwrap();
+
+        // then
+        let first_block_page_id = 1;
+        let mut bpm = bpm.lock().unwrap();
+        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
+        let (k, v) = block.get(1).unwrap();
+        assert_eq!(k.data[0], key.data[0]);
+        assert_eq!(k.data[1], key.data[1]);
+        assert_eq!(v.data[0], 33);
+    }
+
+    #[test]
+    fn should_not_insert_when_k_v_all_equals() {
+        // given
+        let bucket_size = 16;
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the first block
+        for i in 0..block_capacity {
+            let (key, val) = build_kv(i as u64, 127);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // fill the next block's first slot
+        let (key, val) = build_kv((block_capacity + 1) as u64, 127);
+        table.insert(&key, &val).unwrap();
+
+        // when
+        let (key, val) = build_kv(3, 127);
+
+        // then (not inserted)
+        assert!(!table.insert(&key, &val).unwrap());
+
+        // when
+        let (key, val) = build_kv((block_capacity + 1) as u64, 127);
+
+        // then (not inserted)
+        assert!(!table.insert(&key, &val).unwrap());
+    }
+
+    #[test]
+    fn should_get_kv_from_first_block() {
+        // given
+        let bucket_size = 16;
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the first block
+        for i in 0..block_capacity {
+            let (key, val) = build_kv(i as u64, i as u64);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        let (key, _) = build_kv(3, 0);
+        let res = table.get_value(&key).unwrap();
+
+        // then
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].data[0], 3);
+    }
+
+    #[test]
+    fn should_get_kvs_with_same_key() {
+        // given
+        let bucket_size = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the first block
+        let keys_num = 8;
+        for i in 0..keys_num {
+            let (key, val) = build_kv(33, i as u64);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        let (key, _) = build_kv(33, 0);
+        let res = table.get_value(&key).unwrap();
+
+        // then
+        assert_eq!(res.len(), keys_num);
+        for i in 0..keys_num {
+            assert_eq!(res[i].data[0], i as u8);
+        }
+    }
+
+    #[test]
+    fn should_get_kvs_with_same_key_across_block() {
+        // given
+        let bucket_size = 16;
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the last block
+        let last_block_base_idx = (bucket_size - 1) * block_capacity;
+        for i in 0..(block_capacity - 1) {
+            let (key, val) = build_kv((last_block_base_idx + i) as u64, 127);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        // fill the last_blk last_slot and first_blk first_slot
+        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 88);
+        table.insert(&key, &val).unwrap();
+        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 99);
+        table.insert(&key, &val).unwrap();
+
+        let res = table.get_value(&key).unwrap();
+
+        // then
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].data[0], 88);
+        assert_eq!(res[1].data[0], 99);
+    }
+
+    #[test]
+    fn should_not_get_kvs_when_not_matched() {
+        // given
+        let bucket_size = 16;
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+
+        // fill the last block
+        let last_block_base_idx = (bucket_size - 1) * block_capacity;
+        for i in 0..(block_capacity - 1) {
+            let (key, val) = build_kv((last_block_base_idx + i) as u64, 127);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // fill the last_blk last_slot and first_blk first_slot
+        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 88);
+        table.insert(&key, &val).unwrap();
+        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 99);
+        table.insert(&key, &val).unwrap();
+
+        // when
+        // slot 0 not exist, and occupied by key = (last_block_base_idx + block_capacity - 1), val = 99
+        let (key, _) = build_kv(0, 0);
+        let res = table.get_value(&key).unwrap();
+
+        // then
+        assert_eq!(res.len(), 0);
+    }
+
+    #[test]
+    fn should_get_values_past_pairs_of_other_keys() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(16, bpm.clone(), FAKE_HASH).unwrap();
+        let (key0, val0) = build_kv(0, 10);
+        let (key1, val1) = build_kv(1, 11);
+        let (_, val2) = build_kv(0, 12);
+        table.insert(&key0, &val0).unwrap();
+        table.insert(&key1, &val1).unwrap();
+
+        // when
+        table.insert(&key0, &val2).unwrap();
+
+        // then
+        let values = table.get_value(&key0).unwrap();
+        assert_eq!(values.len(), 2);
+        assert!(values[0] == val0 && values[1] == val2);
+        assert!(table.get_value(&key1).unwrap() == vec![val1]);
+    }
+
+    #[test]
+    fn should_preallocate_missing_blocks_as_one_extent() {
+        // given
+        let bucket_size = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+        let (key, val) = build_kv(0, 10);
+        table.insert(&key, &val).unwrap();
+
+        // when
+        let allocated = table.preallocate_blocks().unwrap();
+
+        // then
+        assert_eq!(allocated, bucket_size - 1);
+        let header = table.get_header().unwrap();
+        let block_pids: Vec<PageId> = (0..bucket_size).map(|idx| header.get_block_page_id(idx).unwrap().unwrap()).collect();
+        assert_eq!(block_pids, (1..=bucket_size).collect::<Vec<PageId>>());
+        assert_eq!(table.preallocate_blocks().unwrap(), 0);
+        let last_block_key = build_kv(((bucket_size - 1) * HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block()) as u64, 11);
+        table.insert(&last_block_key.0, &last_block_key.1).unwrap();
+        assert!(table.get_value(&last_block_key.0).unwrap() == vec![last_block_key.1]);
+        assert!(table.get_value(&key).unwrap() == vec![val]);
+    }
+
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct BlobValue(Vec<u8>);
+
+    impl ValueType for BlobValue {}
+
+    #[test]
+    fn should_keep_values_larger_than_a_slot_in_overflow_pages() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        let small = BlobValue(vec![1; 8]);
+        let large = BlobValue((0..5000).map(|i| i as u8).collect());
+        assert!(table.insert(&7, &small).unwrap());
+
+        // when
+        let inserted = table.insert(&7, &large).unwrap();
+
+        // then
+        assert!(inserted);
+        assert!(!table.insert(&7, &large).unwrap());
+        assert_eq!(table.get_value(&7).unwrap(), vec![small, large]);
+    }
+
+    #[test]
+    fn should_aggregate_slot_usage_of_blocks() {
+        // given
+        let bucket_size = 4;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        for k in [0, 1, 2, block_capacity as u64] {
+            let (key, val) = build_kv(k, 1);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        let stats = table.stats().unwrap();
+
+        // then
+        let mut probe_histogram = [0; PROBE_HISTOGRAM_BUCKETS];
+        probe_histogram[0] = 4;
+        assert_eq!(stats, HashTableStats {
+            allocated_blocks: 2,
+            slots: bucket_size * block_capacity,
+            occupied: 4,
+            readable: 4,
+            total_probe_length: 0,
+            max_probe_length: 0,
+            probe_histogram,
+        });
+        assert_eq!(stats.tombstones(), 0);
+        assert!((stats.fill_factor() - 4.0 / (bucket_size * block_capacity) as f64).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn should_keep_block_page_ids_past_first_header_page_in_linked_pages() {
+        // given
+        let bucket_size = 1200;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(bucket_size, bpm.clone(), FAKE_HASH).unwrap();
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block() as u64;
+        let (key, val) = build_kv(1100 * block_capacity + 3, 127);
+        let (last_key, last_val) = build_kv((bucket_size as u64) * block_capacity - 1, 128);
+
+        // when
+        table.insert(&key, &val).unwrap();
+        table.insert(&last_key, &last_val).unwrap();
+
+        // then
+        let header = table.get_header().unwrap();
+        assert_eq!(header.pages().len(), HashTableHeaderPage::pages_needed(bucket_size));
+        assert!(header.pages().len() > 1);
+        assert!(header.get_block_page_id(1100).unwrap().is_some());
+        assert!(header.get_block_page_id(0).unwrap().is_none());
+        assert_eq!(table.get_value(&key).unwrap()[0].data[0], 127);
+        assert_eq!(table.get_value(&last_key).unwrap()[0].data[0], 128);
+        assert_eq!(table.stats().unwrap().allocated_blocks, 2);
+    }
+
+    #[test]
+    fn should_double_blocks_and_keep_pairs_when_fill_factor_reached() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(1, bpm.clone(), hash).unwrap();
+        let old_header_pid = table.get_header_pid();
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let num_pairs = block_capacity * 3 / 4 + 1;
+
+        // when
+        for k in 0..num_pairs as u64 {
+            let (key, val) = build_kv(k, k + 1);
+            assert!(table.insert(&key, &val).unwrap());
+        }
+
+        // then
+        let stats = table.stats().unwrap();
+        assert_eq!(stats.slots, 2 * block_capacity);
+        assert_eq!(stats.readable, num_pairs);
+        assert_ne!(table.get_header_pid(), old_header_pid);
+        for k in 0..num_pairs as u64 {
+            let (key, val) = build_kv(k, k + 1);
+            let values = table.get_value(&key).unwrap();
+            assert_eq!(values.len(), 1);
+            assert!(values[0] == val);
+        }
+        drop(table);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(old_header_pid), None);
+    }
+
+    #[test]
+    fn should_stop_probing_after_wrapping_around_full_table() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(2, bpm.clone(), FAKE_HASH).unwrap();
+        table.set_max_fill_factor(1.0);
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        for k in 0..2 * block_capacity as u64 {
+            // every pair hashes to slot 5, probing on around the table
+            let (mut key, val) = build_kv(5, k);
+            key.data[8] = k as u8;
+            key.data[9] = (k >> 8) as u8;
+            assert!(table.insert(&key, &val).unwrap());
+        }
+
+        // when
+        let (mut missing_key, _) = build_kv(5, 0);
+        missing_key.data[9] = u8::MAX;
+        let last_k = 2 * block_capacity as u64 - 1;
+        let (mut wrapped_key, _) = build_kv(5, 0);
+        wrapped_key.data[8] = last_k as u8;
+        wrapped_key.data[9] = (last_k >> 8) as u8;
+        let missing = table.get_value(&missing_key).unwrap();
+        let wrapped = table.get_value(&wrapped_key).unwrap();
+
+        // then
+        assert!(missing.is_empty());
+        assert_eq!(wrapped.len(), 1);
+        assert!(wrapped[0] == build_kv(0, last_k).1);
+    }
+
+    #[test]
+    fn should_leave_tombstones_and_free_overflow_pages_when_removed() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        let large = BlobValue(vec![3; 5000]);
+        table.insert(&7, &BlobValue(vec![1; 8])).unwrap();
+        // pages 2 and 3 hold the overflow chain, page 1 is the block
+        table.insert(&7, &large).unwrap();
+        table.insert(&8, &BlobValue(vec![2; 8])).unwrap();
+
+        // when
+        table.remove(&7).unwrap();
+
+        // then
+        assert!(table.get_value(&7).unwrap().is_empty());
+        assert_eq!(table.get_value(&8).unwrap(), vec![BlobValue(vec![2; 8])]);
+        assert_eq!(table.stats().unwrap().tombstones(), 2);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(2), None);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(3), None);
+        assert!(table.insert(&7, &large).unwrap());
+        assert_eq!(table.get_value(&7).unwrap(), vec![large]);
+    }
+
+    #[test]
+    fn should_not_lose_updates_of_concurrent_inserts_and_removes() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(2, bpm, hash).unwrap();
+        let (stable_keys, removed_keys) = (0..100u64, 100..200u64);
+        for k in stable_keys.clone().chain(removed_keys.clone()) {
+            let (key, val) = build_kv(k, k);
+            table.insert(&key, &val).unwrap();
+        }
+
+        // when
+        let mut workers = Vec::new();
+        for t in 1..=4u64 {
+            let mut table = table.clone();
+            workers.push(thread::spawn(move || {
+                for k in t * 1000..t * 1000 + 300 {
+                    let (key, val) = build_kv(k, k);
+                    assert!(table.insert(&key, &val).unwrap());
+                }
+            }));
+        }
+        for _ in 0..2 {
+            let (mut table, stable_keys) = (table.clone(), stable_keys.clone());
+            workers.push(thread::spawn(move || {
+                for k in stable_keys {
+                    let (key, val) = build_kv(k, k);
+                    assert!(table.get_value(&key).unwrap() == vec![val]);
+                }
+            }));
+        }
+        {
+            let (mut table, removed_keys) = (table.clone(), removed_keys.clone());
+            workers.push(thread::spawn(move || {
+                for k in removed_keys {
+                    table.remove(&build_kv(k, k).0).unwrap();
+                }
+            }));
+        }
+        for worker in workers {
+            worker.join().unwrap();
         }
 
-        (key, val)
+        // then
+        for k in (1..=4u64).flat_map(|t| t * 1000..t * 1000 + 300).chain(stable_keys) {
+            let (key, val) = build_kv(k, k);
+            assert!(table.get_value(&key).unwrap() == vec![val]);
+        }
+        for k in removed_keys {
+            assert!(table.get_value(&build_kv(k, k).0).unwrap().is_empty());
+        }
+        assert_eq!(table.stats().unwrap().readable, 4 * 300 + 100);
     }
 
     #[test]
-    fn should_build_new_linear_probe_hash_table() {
+    fn should_overwrite_first_value_of_key_in_place_when_upserted() {
         // given
-        let mut bpm = BufferPoolManager::new_default(100);
-        let size: usize = 16;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        let large = BlobValue(vec![3; 5000]);
+        table.insert(&7, &large).unwrap();
+        table.insert(&7, &BlobValue(vec![1; 8])).unwrap();
 
         // when
-        let header_pid = {
-            let lpht = LinearProbeHashTable::<FakeKey, FakeValue>::new(size, &mut bpm, hash);
-            lpht.header_pid
-        };
+        let replaced = table.upsert(&7, &BlobValue(vec![2; 8])).unwrap();
+        let inserted = !table.upsert(&8, &BlobValue(vec![4; 8])).unwrap();
 
         // then
-        let page_with_lock = bpm.fetch_page(header_pid).unwrap();
-        let header_raw = page_with_lock.read().unwrap();
-        let header: HashTableHeaderPage = HashTableHeaderPage::deserialize(header_raw.get_data()).unwrap();
-
-        assert_eq!(header.get_size(), size);
-        assert_eq!(header.get_page_id(), header_raw.get_id());
+        assert!(replaced && inserted);
+        assert_eq!(table.get_value(&7).unwrap(), vec![BlobValue(vec![2; 8]), BlobValue(vec![1; 8])]);
+        assert_eq!(table.get_value(&8).unwrap(), vec![BlobValue(vec![4; 8])]);
+        assert_eq!(table.stats().unwrap().tombstones(), 0);
+        // the large value was written to pages 1 and 2, before its block
+        assert_eq!(bpm.lock().unwrap().pin_count_of(1), None);
     }
 
     #[test]
-    fn should_insert_kv_pair_to_new_block() {
+    fn should_find_key_past_pairs_of_other_keys_and_tombstones() {
         // given
-        let bucket_size = 16;
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut header = LinearProbeHashTable::<FakeKey, FakeValue>::new(bucket_size, &mut bpm, FAKE_HASH).get_header();
-
-        let new_block_pid = 1;
-        let slot_idx = 0;
-        let block_index = 0;
-        let block_offset = 21;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(16, bpm, FAKE_HASH).unwrap();
+        // every key hashes to slot 5, they differ in the last byte
+        let keys: Vec<FakeKey> = (0..4).map(|i| {
+            let (mut key, _) = build_kv(5, 0);
+            key.data[9] = i;
+            key
+        }).collect();
+        for key in &keys[..3] {
+            table.insert(key, &build_kv(0, 1).1).unwrap();
+        }
+        table.remove(&keys[1]).unwrap();
 
         // when
-        let (key, val) = build_kv(21, 127);
-        LinearProbeHashTable::insert_to_new_block(&mut bpm, &key, &val, &mut header, block_index, block_offset);
+        let found = table.contains_key(&keys[2]).unwrap();
+        let removed = table.contains_key(&keys[1]).unwrap();
+        let missing = table.contains_key(&keys[3]).unwrap();
 
         // then
-        // get bucket page id
-        assert_eq!(header.get_block_page_id(slot_idx).unwrap(), new_block_pid);
-
-        // get value from bucket
-        let block_raw = bpm.fetch_page(new_block_pid).unwrap().read().unwrap();
-        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
-        let (k, v) = block.get(block_offset);
-        assert_eq!(k.data[0], 21);
-        assert_eq!(v.data[0], 127);
+        assert!(found);
+        assert!(!removed);
+        assert!(!missing);
     }
 
     #[test]
-    fn should_insert_one_kv_to_empty_hashtable() {
+    fn should_count_live_pairs_in_header_across_inserts_removes_and_resizes() {
         // given
-        let bucket_size = 16;
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, hash);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(1, bpm, hash).unwrap();
+        assert!(table.is_empty().unwrap());
+        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block() as u64;
 
         // when
-        let (key, val) = build_kv(1, 127);
-        table.insert(&key, &val);
+        for k in 0..block_capacity {
+            let (key, val) = build_kv(k, k);
+            table.insert(&key, &val).unwrap();
+        }
+        let (key, val) = build_kv(0, 0);
+        table.insert(&key, &val).unwrap();
+        table.upsert(&key, &build_kv(0, 1).1).unwrap();
+        table.remove(&build_kv(1, 0).0).unwrap();
 
         // then
-        // calculate slot index and bucket index
-        let slot_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let slot_index = (hash(&key) % (bucket_size * slot_capacity) as u64) as usize;
-        let block_index = slot_index / slot_capacity;
-
-        // get bucket page id
-        let first_block_page_id = 1;
-        let header = table.get_header();
-        assert_eq!(header.get_block_page_id(block_index).unwrap(), first_block_page_id);
-
-        // get value from bucket
-        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
-        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
-        let (k, v) = block.get(slot_index - block_index * slot_capacity);
-        assert_eq!(k.data[0], 1);
-        assert_eq!(v.data[0], 127);
+        assert_eq!(table.len().unwrap(), block_capacity as usize - 1);
+        assert_eq!(table.len().unwrap(), table.stats().unwrap().readable);
+        assert!(!table.is_empty().unwrap());
     }
 
     #[test]
-    fn should_insert_one_kv_to_hashtable_with_same_block() {
+    fn should_release_blocks_and_overflow_pages_when_cleared_or_dropped() {
         // given
-        let bucket_size = 16;
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        let (key1, val) = build_kv(1, 127);
-        table.insert(&key1, &val);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        let block_capacity = HashTableBlockPage::<u64, BlobValue>::capacity_of_block() as u64;
+        // pages 1 and 2 hold the overflow chain, pages 3 and 4 the blocks
+        table.insert(&0, &BlobValue(vec![3; 5000])).unwrap();
+        table.insert(&block_capacity, &BlobValue(vec![1; 8])).unwrap();
+        let header_pid = table.get_header_pid();
 
         // when
-        let (key2, val) = build_kv(2, 127);
-        table.insert(&key2, &val);
+        table.clear().unwrap();
 
         // then
-        // calculate slot index and bucket index
-        let slot_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let slot_index = (FAKE_HASH(&key2) % (bucket_size * slot_capacity) as u64) as usize;
-        let block_index = slot_index / slot_capacity;
+        assert!(table.is_empty().unwrap());
+        assert!(table.get_value(&0).unwrap().is_empty());
+        assert_eq!(table.stats().unwrap().allocated_blocks, 0);
+        for pid in 1..=4 {
+            assert_eq!(bpm.lock().unwrap().pin_count_of(pid), None);
+        }
+        assert!(table.insert(&0, &BlobValue(vec![1; 8])).unwrap());
+        assert_eq!(table.len().unwrap(), 1);
 
-        // get bucket page id
-        let first_block_page_id = 1;
-        let header = table.get_header();
-        assert_eq!(header.get_block_page_id(block_index).unwrap(), first_block_page_id);
+        // when
+        table.drop_table().unwrap();
 
-        // get value from bucket
-        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
-        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
-        let (k, v) = block.get(slot_index - block_index * slot_capacity);
-        assert_eq!(k.data[0], 2);
-        assert_eq!(v.data[0], 127);
+        // then
+        assert_eq!(bpm.lock().unwrap().pin_count_of(header_pid), None);
     }
 
     #[test]
-    fn should_insert_one_kv_to_hashtable_with_same_block_meet_collapse() {
+    fn should_leave_table_empty_when_clear_fails_to_delete_a_block() {
         // given
-        let bucket_size = 16;
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        let (key1, val1) = build_kv(1, 127);
-        table.insert(&key1, &val1);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        for k in 0..10u64 {
+            table.insert(&k, &k).unwrap();
+        }
+        let header = table.header_view_of(table.get_header_pid()).unwrap();
+        let block_pid = header.get_block_page_id(0).unwrap().unwrap();
+        bpm.lock().unwrap().fetch_page(block_pid).unwrap();
 
         // when
-        let (key2, val2) = build_kv(1, 126);
-        table.insert(&key2, &val2);
+        let cleared = table.clear();
 
         // then
-        // calculate slot index and bucket index
-        let slot_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let slot_index = (FAKE_HASH(&key2) % (bucket_size * slot_capacity) as u64) as usize;
-        let block_index = slot_index / slot_capacity;
-
-        // get bucket page id
-        let first_block_page_id = 1;
-        let header = table.get_header();
-        assert_eq!(header.get_block_page_id(block_index).unwrap(), first_block_page_id);
-
-        // get value from bucket
-        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
-        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
-        let (k1, v1) = block.get(slot_index - block_index * slot_capacity);
-        assert_eq!(k1.data[0], 1);
-        assert_eq!(v1.data[0], 127);
-        let (k2, v2) = block.get((slot_index - block_index * slot_capacity) + 1);
-        assert_eq!(k2.data[0], 1);
-        assert_eq!(v2.data[0], 126);
+        assert!(cleared.is_err());
+        assert!(table.is_empty().unwrap());
+        assert_eq!(table.stats().unwrap().allocated_blocks, 0);
+        assert!(table.get_value(&3).unwrap().is_empty());
+        bpm.lock().unwrap().unpin_page(block_pid, false).unwrap();
+        assert!(table.insert(&3, &30).unwrap());
+        assert_eq!(table.get_value(&3).unwrap(), [30]);
     }
 
     #[test]
-    fn should_find_next_block_when_index_collapse() {
+    fn should_place_keys_by_seeded_hasher() {
         // given
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(16, bpm.clone(), XxKeyHasher::with_seed(7)).unwrap();
+        let other_table = LinearProbeHashTable::<FakeKey, FakeValue>::new(16, bpm, XxKeyHasher::with_seed(8)).unwrap();
+        let (key, val) = build_kv(3, 4);
 
-        // current block
-        let curr_block_pid =
-            {
-                let mut curr_block = HashTableBlockPage::<FakeKey, FakeValue>::new();
-                for i in 0..block_capacity {
-                    curr_block.insert(i, FakeKey { data: [0; 10] }, FakeValue { data: [0; 20] });
-                }
-                LinearProbeHashTable::<FakeKey, FakeValue>::update_page(&mut bpm, None, curr_block.serialize())
-            };
+        // when
+        table.insert(&key, &val).unwrap();
 
-        // next block
-        let next_block_pid = {
-            let mut next_block = HashTableBlockPage::<FakeKey, FakeValue>::new();
-            next_block.insert(0, FakeKey { data: [0; 10] }, FakeValue { data: [0; 20] });
-            next_block.insert(1, FakeKey { data: [0; 10] }, FakeValue { data: [0; 20] });
-            LinearProbeHashTable::<FakeKey, FakeValue>::update_page(&mut bpm, None, next_block.serialize())
-        };
+        // then
+        assert!(table.get_value(&key).unwrap() == vec![val]);
+        assert_ne!(table.home_slot(&key, 16), other_table.home_slot(&key, 16));
+    }
+
+    #[test]
+    fn should_insert_batch_writing_each_block_once_and_skip_duplicates() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm, |k: &u64| *k).unwrap();
+        let block_capacity = HashTableBlockPage::<u64, BlobValue>::capacity_of_block() as u64;
+        table.insert(&1, &BlobValue(vec![1])).unwrap();
+        let pairs = vec![
+            (1, BlobValue(vec![1])),
+            (1, BlobValue(vec![2])),
+            (2, BlobValue(vec![3; 5000])),
+            (block_capacity, BlobValue(vec![4])),
+            (block_capacity, BlobValue(vec![4])),
+            (block_capacity - 1, BlobValue(vec![5])),
+            // probes past the last slot of block 0, so goes in after the blocks are written
+            (block_capacity - 1, BlobValue(vec![7])),
+            (2 * block_capacity - 1, BlobValue(vec![6])),
+        ];
 
         // when
-        let no_available = LinearProbeHashTable::<FakeKey, FakeValue>::find_available_slot(
-            &mut bpm, &FakeKey { data: [1; 10] }, &FakeValue { data: [0; 20] }, curr_block_pid, 0);
-        let duplicated = LinearProbeHashTable::<FakeKey, FakeValue>::find_available_slot(
-            &mut bpm, &FakeKey { data: [0; 10] }, &FakeValue { data: [0; 20] }, next_block_pid, 0);
-        let found = LinearProbeHashTable::<FakeKey, FakeValue>::find_available_slot(
-            &mut bpm, &FakeKey { data: [1; 10] }, &FakeValue { data: [1; 20] }, next_block_pid, 0);
+        let inserted = table.insert_batch(&pairs).unwrap();
 
         // then
-        assert!(no_available.not_found());
-        assert!(duplicated.duplicated());
-        assert!(found.found());
-        assert_eq!(found.unwrap().1, 2);
+        assert_eq!(inserted, 6);
+        assert!(table.get_value(&1).unwrap() == vec![BlobValue(vec![1]), BlobValue(vec![2])]);
+        assert!(table.get_value(&2).unwrap() == vec![BlobValue(vec![3; 5000])]);
+        assert!(table.get_value(&block_capacity).unwrap() == vec![BlobValue(vec![4])]);
+        assert!(table.get_value(&(block_capacity - 1)).unwrap() == vec![BlobValue(vec![5]), BlobValue(vec![7])]);
+        assert!(table.get_value(&(2 * block_capacity - 1)).unwrap() == vec![BlobValue(vec![6])]);
+        assert_eq!(table.len().unwrap(), 7);
+        assert_eq!(table.stats().unwrap().allocated_blocks, 2);
     }
 
     #[test]
-    fn should_insert_one_kv_to_hashtable_with_new_block_when_meet_collapse() {
+    fn should_remove_only_pair_holding_value() {
         // given
-        let bucket_size = 16;
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        // fill the first block
-        for i in 0..block_capacity {
-            let (key, val) = build_kv(i as u64, 127);
-            table.insert(&key, &val);
-        }
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        table.insert(&1, &BlobValue(vec![1])).unwrap();
+        // page 1 holds the block, pages 2 and 3 the overflow chain
+        table.insert(&1, &BlobValue(vec![2; 5000])).unwrap();
+        table.insert(&1, &BlobValue(vec![3])).unwrap();
 
         // when
-        let (key, val) = build_kv(0, 33);
-        table.insert(&key, &val);
+        let removed = table.remove_kv(&1, &BlobValue(vec![2; 5000])).unwrap();
+        let removed_again = table.remove_kv(&1, &BlobValue(vec![2; 5000])).unwrap();
 
         // then
-        let second_block_page_id = 2;
-        let block_raw = bpm.fetch_page(second_block_page_id).unwrap().read().unwrap();
-        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
-        let (k, v) = block.get(0);
-        assert_eq!(k.data[0], 0);
-        assert_eq!(v.data[0], 33);
+        assert!(removed);
+        assert!(!removed_again);
+        assert!(table.get_value(&1).unwrap() == vec![BlobValue(vec![1]), BlobValue(vec![3])]);
+        assert_eq!(table.len().unwrap(), 2);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(2), None);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(3), None);
     }
 
     #[test]
-    fn should_insert_one_kv_to_hashtable_with_exist_block_when_meet_collapse() {
-        // given
-        let bucket_size = 16;
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
+    fn should_find_every_live_pair_through_random_removes_and_compactions() {
+        let block_capacity = HashTableBlockPage::<u64, u64>::capacity_of_block() as u64;
+        // keys cluster around the end of both blocks, so probe sequences cross blocks and wrap around
+        let clustered = move |k: &u64| match k % 2 {
+            0 => block_capacity - 8 + k % 16,
+            _ => 2 * block_capacity - 8 + k % 16,
+        };
 
-        // fill the first block
-        let (key, val) = build_kv(0, 123);
-        table.insert(&key, &val);
+        for seed in 0..4 {
+            // given
+            let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+            let mut table = LinearProbeHashTable::new(2, bpm, clustered).unwrap();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut model: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+
+            for step in 0..400 {
+                // when
+                let (k, v) = (rng.gen_range(0..64), rng.gen_range(0..4));
+                let values = model.entry(k).or_default();
+                match rng.gen_range(0..10) {
+                    0..=4 => {
+                        let inserted = !values.contains(&v);
+                        assert_eq!(table.insert(&k, &v).unwrap(), inserted, "seed {} step {}", seed, step);
+                        if inserted {
+                            values.push(v);
+                        }
+                    },
+                    5 | 6 => {
+                        let removed = values.contains(&v);
+                        assert_eq!(table.remove_kv(&k, &v).unwrap(), removed, "seed {} step {}", seed, step);
+                        values.retain(|value| *value != v);
+                    },
+                    7 => {
+                        table.remove(&k).unwrap();
+                        values.clear();
+                    },
+                    8 if values.len() <= 1 => {
+                        table.upsert(&k, &v).unwrap();
+                        *values = vec![v];
+                    },
+                    _ => {
+                        table.compact().unwrap();
+                    },
+                }
 
-        // fill the last block
-        let last_block_base_idx = (bucket_size - 1) * block_capacity;
-        for i in 0..block_capacity {
-            let (key, val) = build_kv((last_block_base_idx + i) as u64, 127);
-            table.insert(&key, &val);
+                // then
+                let mut found = table.get_value(&k).unwrap();
+                found.sort_unstable();
+                let mut expected = model[&k].clone();
+                expected.sort_unstable();
+                assert_eq!(found, expected, "seed {} step {} key {}", seed, step, k);
+            }
+
+            // then
+            for (k, values) in model.iter() {
+                let mut found = table.get_value(k).unwrap();
+                found.sort_unstable();
+                let mut expected = values.clone();
+                expected.sort_unstable();
+                assert_eq!(found, expected, "seed {} key {}", seed, k);
+            }
+            assert_eq!(table.len().unwrap(), model.values().map(Vec::len).sum::<usize>());
+            assert_eq!(table.len().unwrap(), table.stats().unwrap().readable);
+        }
+    }
+
+    #[test]
+    fn should_report_probe_lengths_of_pairs_away_from_home_slot() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(1, bpm, |k: &u64| *k).unwrap();
+        let block_capacity = HashTableBlockPage::<u64, u64>::capacity_of_block() as u64;
+        for v in 0..3 {
+            table.insert(&0, &v).unwrap();
         }
+        // the second pair wraps around to the first free slot, 4 slots on
+        table.insert(&(block_capacity - 1), &0).unwrap();
+        table.insert(&(block_capacity - 1), &1).unwrap();
 
         // when
-        let (key, val) = build_kv((last_block_base_idx + 1) as u64, 33);
-        table.insert(&key, &val);
+        let stats = table.stats().unwrap();
 
         // then
-        let first_block_page_id = 1;
-        let block_raw = bpm.fetch_page(first_block_page_id).unwrap().read().unwrap();
-        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(block_raw.get_data()).unwrap();
-        let (k, v) = block.get(1);
-        assert_eq!(k.data[0], key.data[0]);
-        assert_eq!(k.data[1], key.data[1]);
-        assert_eq!(v.data[0], 33);
+        assert_eq!(&stats.probe_histogram[..4], &[2, 1, 1, 1]);
+        assert_eq!(stats.max_probe_length, 4);
+        assert!((stats.avg_probe_length() - 7.0 / 5.0).abs() < f64::EPSILON);
+        assert!((stats.load_factor() - 5.0 / block_capacity as f64).abs() < f64::EPSILON);
     }
 
     #[test]
-    fn should_not_insert_when_k_v_all_equals() {
+    fn should_page_through_values_of_key_across_blocks() {
         // given
-        let bucket_size = 16;
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        // fill the first block
-        for i in 0..block_capacity {
-            let (key, val) = build_kv(i as u64, 127);
-            table.insert(&key, &val);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(2, bpm, |k: &u64| *k).unwrap();
+        let key = HashTableBlockPage::<u64, u64>::capacity_of_block() as u64 - 2;
+        for v in 0..5 {
+            table.insert(&key, &v).unwrap();
         }
-
-        // fill the next block's first slot
-        let (key, val) = build_kv((block_capacity + 1) as u64, 127);
-        table.insert(&key, &val);
+        table.insert(&(key + 1), &9).unwrap();
 
         // when
-        let (key, val) = build_kv(3, 127);
+        let (first, cursor) = table.get_value_limit(&key, 2, None).unwrap();
+        let (second, cursor) = table.get_value_limit(&key, 2, cursor).unwrap();
+        let (last, end) = table.get_value_limit(&key, 2, cursor).unwrap();
 
-        // then (not inserted)
-        assert!(!table.insert(&key, &val));
+        // then
+        assert_eq!(first, vec![0, 1]);
+        assert_eq!(second, vec![2, 3]);
+        assert_eq!((last, end), (vec![4], None));
+        assert_eq!(table.get_value_limit(&key, 5, None).unwrap(), (table.get_value(&key).unwrap(), None));
+    }
+
+    #[test]
+    fn should_not_lose_counts_of_concurrent_merges() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm, hash).unwrap();
+        table.insert(&7u64, &100u64).unwrap();
 
         // when
-        let (key, val) = build_kv((block_capacity + 1) as u64, 127);
+        let workers: Vec<_> = (0..4).map(|_| {
+            let table = table.clone();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    table.merge(&1, |count| count.map_or(1, |count| count + 1)).unwrap();
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        let merged = table.merge(&7, |v| v.unwrap() * 2).unwrap();
 
-        // then (not inserted)
-        assert!(!table.insert(&key, &val));
+        // then
+        assert_eq!(table.get_value(&1).unwrap(), vec![200]);
+        assert_eq!((merged, table.get_value(&7).unwrap()), (200, vec![200]));
+        assert_eq!(table.len().unwrap(), 2);
     }
 
     #[test]
-    fn should_get_kv_from_first_block() {
+    fn should_halve_blocks_and_free_old_pages_once_sparse_after_removes() {
         // given
-        let bucket_size = 16;
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        // fill the first block
-        for i in 0..block_capacity {
-            let (key, val) = build_kv(i as u64, i as u64);
-            table.insert(&key, &val);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(4, bpm.clone(), |k: &u64| *k).unwrap();
+        table.set_min_fill_factor(0.1);
+        let block_capacity = HashTableBlockPage::<u64, u64>::capacity_of_block() as u64;
+        for k in 0..block_capacity {
+            table.insert(&(k * 4), &k).unwrap();
         }
+        let old_header_pid = table.get_header_pid();
 
         // when
-        let (key, _) = build_kv(3, 0);
-        let res = table.get_value(&key);
+        let kept = block_capacity / 4;
+        for k in kept..block_capacity {
+            table.remove(&(k * 4)).unwrap();
+        }
 
         // then
-        assert_eq!(res.len(), 1);
-        assert_eq!(res[0].data[0], 3);
+        assert_eq!(table.stats().unwrap().slots, 2 * block_capacity as usize);
+        assert_ne!(table.get_header_pid(), old_header_pid);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(old_header_pid), None);
+        assert_eq!(table.len().unwrap(), kept as usize);
+        for k in 0..kept {
+            assert_eq!(table.get_value(&(k * 4)).unwrap(), vec![k]);
+        }
     }
 
     #[test]
-    fn should_get_kvs_with_same_key() {
+    fn should_build_table_with_options_and_refuse_second_value_of_unique_key() {
         // given
-        let bucket_size = 16;
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        // fill the first block
-        let keys_num = 8;
-        for i in 0..keys_num {
-            let (key, val) = build_kv(33, i as u64);
-            table.insert(&key, &val);
-        }
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::<u64, u64>::builder()
+            .num_buckets(2)
+            .hasher(|k: &u64| *k)
+            .max_fill_factor(1.0)
+            .duplicates(DuplicatePolicy::UniqueKeys)
+            .build(bpm)
+            .unwrap();
 
         // when
-        let (key, _) = build_kv(33, 0);
-        let res = table.get_value(&key);
+        let first = table.insert(&3, &30).unwrap();
+        let second = table.insert(&3, &31).unwrap();
+        let upserted = table.upsert(&3, &32).unwrap();
+        let batched = table.insert_batch(&[(3, 33), (4, 40), (4, 41)]).unwrap();
 
         // then
-        assert_eq!(res.len(), keys_num);
-        for i in 0..keys_num {
-            assert_eq!(res[i].data[0], i as u8);
-        }
+        assert!(first && !second && upserted);
+        assert_eq!(batched, 1);
+        assert_eq!((table.get_value(&3).unwrap(), table.get_value(&4).unwrap()), (vec![32], vec![40]));
+        assert_eq!(table.stats().unwrap().slots, 2 * HashTableBlockPage::<u64, u64>::capacity_of_block());
+        assert_eq!(table.home_slot(&3, 2), (0, 3));
     }
 
     #[test]
-    fn should_get_kvs_with_same_key_across_block() {
+    fn should_dump_occupancy_of_blocks() {
         // given
-        let bucket_size = 16;
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
-
-        // fill the last block
-        let last_block_base_idx = (bucket_size - 1) * block_capacity;
-        for i in 0..(block_capacity - 1) {
-            let (key, val) = build_kv((last_block_base_idx + i) as u64, 127);
-            table.insert(&key, &val);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(2, bpm, |k: &u64| *k).unwrap();
+        for k in [0, 1, 2, 65] {
+            table.insert(&k, &k).unwrap();
         }
+        table.remove(&1).unwrap();
 
         // when
-        // fill the last_blk last_slot and first_blk first_slot
-        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 88);
-        table.insert(&key, &val);
-        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 99);
-        table.insert(&key, &val);
-
-        let res = table.get_value(&key);
+        let dump = table.dump_occupancy().unwrap();
 
         // then
-        assert_eq!(res.len(), 2);
-        assert_eq!(res[0].data[0], 88);
-        assert_eq!(res[1].data[0], 99);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], format!("header pages {}", table.get_header_pid()));
+        assert!(lines[1].starts_with("block 0 page ") && lines[1].ends_with(": 4 occupied, 3 live"));
+        assert_eq!(lines[2], format!("  #x#{}", ".".repeat(61)));
+        assert_eq!(lines[3], format!("  .#{}", ".".repeat(62)));
+        assert_eq!(lines.last(), Some(&"block 1 unallocated"));
     }
 
     #[test]
-    fn should_not_get_kvs_when_not_matched() {
+    fn should_iterate_pairs_as_of_snapshot_while_table_changes() {
         // given
-        let bucket_size = 16;
-        let block_capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
-        let mut bpm = BufferPoolManager::new_default(100);
-        let mut table = LinearProbeHashTable::new(bucket_size, &mut bpm, FAKE_HASH);
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(100)));
+        let mut table = LinearProbeHashTable::new(2, bpm, |k: &u64| *k).unwrap();
+        for k in 0..10 {
+            table.insert(&k, &BlobValue(vec![k as u8; 4])).unwrap();
+        }
+        table.insert(&10, &BlobValue(vec![10; PAGE_SIZE])).unwrap();
 
-        // fill the last block
-        let last_block_base_idx = (bucket_size - 1) * block_capacity;
-        for i in 0..(block_capacity - 1) {
-            let (key, val) = build_kv((last_block_base_idx + i) as u64, 127);
-            table.insert(&key, &val);
+        // when
+        let snapshot = table.iter_snapshot().unwrap();
+        table.remove(&10).unwrap();
+        table.upsert(&0, &BlobValue(vec![42])).unwrap();
+        for k in 11..20 {
+            table.insert(&k, &BlobValue(vec![k as u8; 4])).unwrap();
         }
+        let mut pairs = snapshot.collect::<io::Result<Vec<_>>>().unwrap();
+        pairs.sort_by_key(|(k, _)| *k);
 
-        // fill the last_blk last_slot and first_blk first_slot
-        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 88);
-        table.insert(&key, &val);
-        let (key, val) = build_kv((last_block_base_idx + block_capacity - 1) as u64, 99);
-        table.insert(&key, &val);
+        // then
+        let mut expected: Vec<(u64, BlobValue)> = (0..10).map(|k| (k, BlobValue(vec![k as u8; 4]))).collect();
+        expected.push((10, BlobValue(vec![10; PAGE_SIZE])));
+        assert_eq!(pairs, expected);
+        assert_eq!(table.iter_snapshot().unwrap().count(), 19);
+    }
+
+    #[test]
+    fn should_return_error_instead_of_panicking_when_buffer_pool_runs_out_of_frames() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(3)));
+        let mut table = LinearProbeHashTable::new(2, bpm.clone(), |k: &u64| *k).unwrap();
+        for _ in 0..3 {
+            bpm.lock().unwrap().new_page().unwrap();
+        }
 
         // when
-        // slot 0 not exist, and occupied by key = (last_block_base_idx + block_capacity - 1), val = 99
-        let (key, _) = build_kv(0, 0);
-        let res = table.get_value(&key);
+        let err = table.insert(&1, &1u64).err().unwrap();
 
         // then
-        assert_eq!(res.len(), 0);
+        assert!(matches!(BufferPoolError::from_io(err.cause()), Some(BufferPoolError::OutOfFrames)));
     }
-}
\ No newline at end of file
+}
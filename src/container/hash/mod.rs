@@ -1,7 +1,29 @@
+use std::io;
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
 use crate::container::hash::FindSlotResult::Found;
+use crate::storage::page::hash_table_header_page::{HashTableHeaderChain, HashTableHeaderPage};
+use crate::storage::page::page::PageId;
 
+pub mod compaction;
 pub mod hash_table;
 pub mod linear_probe_hash_table;
+pub mod salvage;
+pub mod u64_table;
+
+/// Writes the header pages of a new table of `num_buckets` blocks as one extent, returning the id of the first.
+pub(crate) fn create_header_chain(bpm: &mut BufferPoolManager, num_buckets: usize) -> io::Result<PageId> {
+    let header_pids: Vec<PageId> = bpm.new_extent(HashTableHeaderPage::pages_needed(num_buckets))?.collect();
+    for header in HashTableHeaderChain::new(&header_pids, num_buckets).pages() {
+        bpm.write_encoded(Some(header.get_page_id()), header)?;
+    }
+
+    Ok(header_pids[0])
+}
+
+pub(crate) fn read_header_chain(bpm: &mut BufferPoolManager, header_pid: PageId) -> io::Result<HashTableHeaderChain<HashTableHeaderPage>> {
+    HashTableHeaderChain::load(header_pid, |pid| Ok(bpm.fetch_decoded(pid)?))
+}
 
 pub enum FindSlotResult<T> {
     NotFound,
@@ -24,10 +46,10 @@ impl<T> FindSlotResult<T> {
         matches!(self, FindSlotResult::Found(_))
     }
 
-    pub fn unwrap(self) -> T {
+    pub fn found_value(self) -> Option<T> {
         match self {
-            Found(val) => val,
-            _ => panic!("FindSlotResult cannot get available value"),
+            Found(val) => Some(val),
+            _ => None,
         }
     }
 }
\ No newline at end of file
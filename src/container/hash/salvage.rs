@@ -0,0 +1,201 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::common::hash::HashKeyType;
+use crate::common::ValueType;
+use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+use crate::storage::page::hash_table_header_page::{HashTableHeaderChain, HashTableHeaderPage};
+use crate::storage::page::overflow_page::{read_chain, OverflowPage};
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_version::{FormatVersion, CURRENT_FORMAT_VERSION, LEGACY_FORMAT_VERSION};
+use crate::storage::page::superblock::{Superblock, SUPERBLOCK_PAGE_ID};
+
+/// Page left out of a salvage, with why it could not be used.
+#[derive(Debug)]
+pub struct SkippedPage {
+    pub page_id: PageId,
+    pub error: io::Error,
+}
+
+pub struct SalvageReport<K, V> {
+    pub rows: Vec<(K, V)>,
+    pub skipped: Vec<SkippedPage>,
+}
+
+/// Last-resort, read-only access to a damaged data file.
+///
+/// Reads pages straight from the file, without the allocation bitmap or the buffer pool,
+/// and never writes, so it can run on a file the normal open path refuses.
+pub struct SalvageReader {
+    file: File,
+    format_version: FormatVersion,
+}
+
+impl SalvageReader {
+    /// An unreadable superblock is assumed to be of the current format.
+    pub fn open(file_path: &Path) -> io::Result<SalvageReader> {
+        let file = OpenOptions::new().read(true).open(file_path)?;
+        let mut reader = SalvageReader { file, format_version: CURRENT_FORMAT_VERSION };
+        reader.format_version = match reader.read_page(SUPERBLOCK_PAGE_ID).and_then(|data| Superblock::deserialize(&data)) {
+            Ok(Some(superblock)) => superblock.get_format_version(),
+            Ok(None) => LEGACY_FORMAT_VERSION,
+            Err(_) => CURRENT_FORMAT_VERSION,
+        };
+
+        Ok(reader)
+    }
+
+    pub fn format_version(&self) -> FormatVersion {
+        self.format_version
+    }
+
+    pub fn num_pages(&self) -> io::Result<usize> {
+        Ok(self.file.metadata()?.len() as usize / PAGE_SIZE)
+    }
+
+    pub fn read_page(&mut self, pid: PageId) -> io::Result<[u8; PAGE_SIZE]> {
+        let mut page_data = [0u8; PAGE_SIZE];
+        self.file.seek(SeekFrom::Start((pid * PAGE_SIZE) as u64))
+            .and_then(|_| self.file.read_exact(&mut page_data))
+            .with_context(|| ErrorContext::new("salvage.read_page").page(pid))?;
+        Ok(page_data)
+    }
+
+    /// Exports the entries of every block page of the hash table that still decodes.
+    ///
+    /// Only a damaged header fails the whole salvage, as it is the only record of where the blocks are.
+    pub fn salvage_hash_table<K, V>(&mut self, header_pid: PageId) -> io::Result<SalvageReport<K, V>>
+        where
+            K: HashKeyType + DeserializeOwned,
+            V: ValueType + DeserializeOwned,
+    {
+        let legacy = self.format_version == LEGACY_FORMAT_VERSION;
+        let header = HashTableHeaderChain::load(header_pid, |pid| self.read_page(pid)
+            .and_then(|data| match legacy {
+                true => HashTableHeaderPage::deserialize_legacy(&data),
+                false => HashTableHeaderPage::deserialize(&data),
+            }))
+            .with_context(|| ErrorContext::new("salvage.header").page(header_pid))?;
+
+        let mut report = SalvageReport { rows: Vec::new(), skipped: Vec::new() };
+        for block_idx in 0..header.get_size() {
+            let block_pid = match header.get_block_page_id(block_idx) {
+                Ok(Some(block_pid)) => block_pid,
+                _ => continue,
+            };
+
+            let block = self.read_page(block_pid).and_then(|data| match legacy {
+                true => HashTableBlockPage::<K, V>::deserialize_legacy(&data),
+                false => HashTableBlockPage::<K, V>::deserialize(&data),
+            });
+            match block.and_then(|block| occupied_entries(self, &block)) {
+                Ok(mut rows) => report.rows.append(&mut rows),
+                Err(error) => report.skipped.push(SkippedPage { page_id: block_pid, error }),
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Values moved to an overflow chain are read from it, a broken chain skips the whole block.
+fn occupied_entries<K, V>(reader: &mut SalvageReader, block: &HashTableBlockPage<K, V>) -> io::Result<Vec<(K, V)>>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    let mut rows = Vec::new();
+    for slot_idx in 0..HashTableBlockPage::<K, V>::capacity_of_block() {
+        if block.is_readable(slot_idx)? {
+            let (k, v) = block.get(slot_idx)?;
+            let v = match block.get_overflow(slot_idx)? {
+                Some(overflow_ref) => {
+                    let raw = read_chain(&overflow_ref, |page_id| reader.read_page(page_id)
+                        .and_then(|data| OverflowPage::deserialize(&data)))?;
+                    bincode::deserialize(&raw).map_err(PageError::from)?
+                },
+                None => v.clone(),
+            };
+            rows.push((k.clone(), v));
+        }
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+
+    use crate::container::hash::salvage::SalvageReader;
+    use crate::storage::disk::disk_manager::{DiskManager, FileDiskManager};
+    use crate::storage::page::hash_table_block_page::HashTableBlockPage;
+    use crate::storage::page::hash_table_header_page::HashTableHeaderPage;
+    use crate::storage::page::page::{PageId, PAGE_SIZE};
+    use crate::storage::page::page_error::PageError;
+    use crate::testkit::kv::{build_kv, FakeKey, FakeValue};
+
+    fn write_raw(dm: &mut FileDiskManager, pid: PageId, raw: Vec<u8>) {
+        let mut page_data = [0u8; PAGE_SIZE];
+        page_data[0..raw.len()].copy_from_slice(&raw);
+        dm.write_page(pid, &page_data).unwrap();
+    }
+
+    #[test]
+    fn should_export_rows_of_readable_blocks_and_skip_damaged_ones() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("damaged.data");
+        let mut dm = FileDiskManager::new(&path).unwrap();
+        let header_pid = dm.allocate_page().unwrap();
+        let mut header = HashTableHeaderPage::new(header_pid, 3);
+        let mut block_pids = Vec::new();
+        for block_idx in 0..2 {
+            let block_pid = dm.allocate_page().unwrap();
+            let mut block = HashTableBlockPage::<FakeKey, FakeValue>::new();
+            let (k1, v1) = build_kv(block_idx, 10);
+            let (k2, v2) = build_kv(block_idx, 20);
+            block.insert(0, k1, v1).unwrap();
+            block.insert(5, k2, v2).unwrap();
+            write_raw(&mut dm, block_pid, block.serialize().unwrap());
+            header.set(block_pid, block_idx as usize).unwrap();
+            block_pids.push(block_pid);
+        }
+        write_raw(&mut dm, header_pid, header.serialize().unwrap());
+        drop(dm);
+
+        // when
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start((block_pids[1] * PAGE_SIZE) as u64)).unwrap();
+        file.write_all(&[0xff; 16]).unwrap();
+        let report = SalvageReader::open(&path).unwrap().salvage_hash_table::<FakeKey, FakeValue>(header_pid).unwrap();
+
+        // then
+        assert_eq!(report.rows, vec![build_kv(0, 10), build_kv(0, 20)]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].page_id, block_pids[1]);
+        assert!(matches!(PageError::from_io(&report.skipped[0].error), Some(PageError::UnknownVersion { .. })));
+    }
+
+    #[test]
+    fn should_fail_salvage_when_header_unreadable() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("damaged.data");
+        drop(FileDiskManager::new(&path).unwrap());
+
+        // when
+        let mut reader = SalvageReader::open(&path).unwrap();
+        let result = reader.salvage_hash_table::<FakeKey, FakeValue>(reader.num_pages().unwrap());
+
+        // then
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::io;
+use std::ops::Range;
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::common::error::{DbError, ErrorContext, ResultExt};
+use crate::common::hash::KeyHasher;
+use crate::container::hash::{create_header_chain, read_header_chain};
+use crate::container::hash::hash_table::HashTable;
+use crate::storage::page::hash_table_header_page::{HashTableHeaderChain, HashTableHeaderPage};
+use crate::storage::page::page::PageId;
+use crate::storage::page::u64_block_page::{Probe, U64BlockPage, U64_BLOCK_CAPACITY};
+
+/// Cheap integer mixer, the suggested hash for `U64Table` keys.
+pub fn mix64(key: &u64) -> u64 {
+    let mut x = *key;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Where walking the probe sequence of a key stopped.
+enum WalkEnd {
+    /// A block reported the end of the sequence.
+    Ended,
+    /// The sequence reached a bucket without a block yet, starting at this slot.
+    Missing { block_idx: usize, slot_idx: usize },
+    /// Every slot was visited.
+    Full,
+}
+
+/// What a block visit found.
+struct Visit {
+    done: bool,
+    dirty: bool,
+}
+
+/// Linear probe hash table specialized for `u64` keys and values.
+///
+/// Same layout as `LinearProbeHashTable`, a header page pointing at block pages, but the blocks hold
+/// packed fixed-size slots probed in place on the buffer pool page instead of serde encoded entries.
+pub struct U64Table<'a> {
+    header_pid: PageId,
+    buffer_pool_manager: &'a mut BufferPoolManager,
+    hasher: Box<dyn KeyHasher<u64>>,
+}
+
+impl<'a> U64Table<'a> {
+    pub fn new<H: KeyHasher<u64> + 'static>(num_buckets: usize, bpm: &'a mut BufferPoolManager, hasher: H) -> io::Result<U64Table<'a>> {
+        let header_pid = create_header_chain(bpm, num_buckets)?;
+
+        Ok(U64Table {
+            header_pid,
+            buffer_pool_manager: bpm,
+            hasher: Box::new(hasher),
+        })
+    }
+
+    pub fn header_pid(&self) -> PageId {
+        self.header_pid
+    }
+
+    fn get_header(&mut self) -> io::Result<HashTableHeaderChain<HashTableHeaderPage>> {
+        let header_pid = self.header_pid;
+        read_header_chain(self.buffer_pool_manager, header_pid)
+            .with_context(|| ErrorContext::new("u64_table.get_header").page(header_pid))
+    }
+
+    /// Visits the blocks on the probe sequence of `key`, handing `visit` the slots to look at.
+    /// The start block is visited once more at the end for the slots before the start.
+    fn walk<F>(&mut self, header: &HashTableHeaderChain<HashTableHeaderPage>, key: u64, tag: &'static str, mut visit: F) -> io::Result<WalkEnd>
+        where F: FnMut(&mut [u8], Range<usize>) -> io::Result<Visit>
+    {
+        let num_slots = header.get_size() * U64_BLOCK_CAPACITY;
+        let start_slot = (self.hasher.hash(&key) % num_slots as u64) as usize;
+        let (start_block, start_offset) = (start_slot / U64_BLOCK_CAPACITY, start_slot % U64_BLOCK_CAPACITY);
+
+        for step in 0..=header.get_size() {
+            let block_idx = (start_block + step) % header.get_size();
+            let slots = match step {
+                0 => start_offset..U64_BLOCK_CAPACITY,
+                s if s == header.get_size() => 0..start_offset,
+                _ => 0..U64_BLOCK_CAPACITY,
+            };
+            let block_pid = match header.get_block_page_id(block_idx)? {
+                Some(block_pid) => block_pid,
+                None => return Ok(WalkEnd::Missing { block_idx, slot_idx: slots.start }),
+            };
+
+            let visited = {
+                let mut block_page = self.buffer_pool_manager.fetch_page_tagged(block_pid, tag)?.write().unwrap();
+                visit(block_page.get_data_mut(), slots)
+                    .with_context(|| ErrorContext::new(tag).page(block_pid))
+            };
+            let dirty = visited.as_ref().is_ok_and(|v| v.dirty);
+            self.buffer_pool_manager.unpin_page(block_pid, dirty)?;
+            if visited?.done {
+                return Ok(WalkEnd::Ended);
+            }
+        }
+
+        Ok(WalkEnd::Full)
+    }
+
+    fn insert_to_new_block(&mut self, header: &mut HashTableHeaderChain<HashTableHeaderPage>, block_idx: usize, slot_idx: usize,
+                           key: u64, value: u64) -> io::Result<()> {
+        let block_pid = {
+            let mut block_page = self.buffer_pool_manager.new_page_tagged("u64_table.new_block")?.write().unwrap();
+            U64BlockPage::init(block_page.get_data_mut())?.put(slot_idx, key, value);
+            block_page.get_id()
+        };
+        self.buffer_pool_manager.unpin_page(block_pid, true)?;
+
+        let header_page = header.set(block_pid, block_idx)?;
+        self.buffer_pool_manager.write_encoded(Some(header_page.get_page_id()), header_page)?;
+        Ok(())
+    }
+
+    /// `false` when the pair is already there, or when every slot is taken.
+    pub fn try_insert(&mut self, key: u64, value: u64) -> io::Result<bool> {
+        let mut header = self.get_header()?;
+        let mut inserted = false;
+        let end = self.walk(&header, key, "u64_table.insert", |page_data, slots| {
+            let mut block = U64BlockPage::from_page(page_data)?;
+            match block.probe(slots, key, value) {
+                Probe::Free(slot_idx) => {
+                    block.put(slot_idx, key, value);
+                    inserted = true;
+                    Ok(Visit { done: true, dirty: true })
+                },
+                Probe::Duplicated(_) => Ok(Visit { done: true, dirty: false }),
+                Probe::Exhausted => Ok(Visit { done: false, dirty: false }),
+            }
+        })?;
+
+        if let WalkEnd::Missing { block_idx, slot_idx } = end {
+            self.insert_to_new_block(&mut header, block_idx, slot_idx, key, value)?;
+            inserted = true;
+        }
+        Ok(inserted)
+    }
+
+    /// Overwrites the value of the first pair of `key` on its probe sequence, or else inserts the pair.
+    /// Returns whether a value was overwritten.
+    pub fn try_upsert(&mut self, key: u64, value: u64) -> io::Result<bool> {
+        let header = self.get_header()?;
+        let mut replaced = false;
+        self.walk(&header, key, "u64_table.upsert", |page_data, slots| {
+            let mut block = U64BlockPage::from_page(page_data)?;
+            let (slot_idx, done) = block.find_key(slots, key);
+            if let Some(slot_idx) = slot_idx {
+                block.put(slot_idx, key, value);
+                replaced = true;
+            }
+            Ok(Visit { done, dirty: replaced })
+        })?;
+
+        if !replaced {
+            self.try_insert(key, value)?;
+        }
+        Ok(replaced)
+    }
+
+    pub fn try_contains_key(&mut self, key: u64) -> io::Result<bool> {
+        let header = self.get_header()?;
+        let mut found = false;
+        self.walk(&header, key, "u64_table.contains_key", |page_data, slots| {
+            let (slot_idx, done) = U64BlockPage::from_page(&*page_data)?.find_key(slots, key);
+            found = slot_idx.is_some();
+            Ok(Visit { done, dirty: false })
+        })?;
+
+        Ok(found)
+    }
+
+    pub fn try_get_value(&mut self, key: u64) -> io::Result<Vec<u64>> {
+        let header = self.get_header()?;
+        let mut values = Vec::new();
+        self.walk(&header, key, "u64_table.get_value", |page_data, slots| {
+            let done = U64BlockPage::from_page(&*page_data)?.collect_values(slots, key, &mut values);
+            Ok(Visit { done, dirty: false })
+        })?;
+
+        Ok(values)
+    }
+
+    /// Returns how many pairs of `key` were removed.
+    pub fn try_remove(&mut self, key: u64) -> io::Result<usize> {
+        let header = self.get_header()?;
+        let mut removed = 0;
+        self.walk(&header, key, "u64_table.remove", |page_data, slots| {
+            let (removed_in_block, done) = U64BlockPage::from_page(page_data)?.remove_key(slots, key);
+            removed += removed_in_block;
+            Ok(Visit { done, dirty: removed_in_block > 0 })
+        })?;
+
+        Ok(removed)
+    }
+
+    /// Removes the pair if it is there, leaving the other values of `key` in place.
+    pub fn try_remove_kv(&mut self, key: u64, value: u64) -> io::Result<bool> {
+        let header = self.get_header()?;
+        let mut removed = false;
+        self.walk(&header, key, "u64_table.remove_kv", |page_data, slots| {
+            let mut block = U64BlockPage::from_page(page_data)?;
+            match block.probe(slots, key, value) {
+                Probe::Duplicated(slot_idx) => {
+                    block.remove(slot_idx);
+                    removed = true;
+                    Ok(Visit { done: true, dirty: true })
+                },
+                Probe::Free(_) => Ok(Visit { done: true, dirty: false }),
+                Probe::Exhausted => Ok(Visit { done: false, dirty: false }),
+            }
+        })?;
+
+        Ok(removed)
+    }
+}
+
+impl<'a> HashTable<u64, u64> for U64Table<'a> {
+    fn insert(&mut self, k: &u64, v: &u64) -> Result<bool, DbError> {
+        Ok(self.try_insert(*k, *v)?)
+    }
+
+    fn upsert(&mut self, k: &u64, v: &u64) -> Result<bool, DbError> {
+        Ok(self.try_upsert(*k, *v)?)
+    }
+
+    fn remove(&mut self, k: &u64) -> Result<(), DbError> {
+        self.try_remove(*k)?;
+        Ok(())
+    }
+
+    fn remove_kv(&mut self, k: &u64, v: &u64) -> Result<bool, DbError> {
+        Ok(self.try_remove_kv(*k, *v)?)
+    }
+
+    fn get_value(&mut self, k: &u64) -> Result<Vec<u64>, DbError> {
+        Ok(self.try_get_value(*k)?)
+    }
+
+    fn contains_key(&mut self, k: &u64) -> Result<bool, DbError> {
+        Ok(self.try_contains_key(*k)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::container::hash::hash_table::HashTable;
+    use crate::container::hash::u64_table::{mix64, U64Table};
+    use crate::storage::page::u64_block_page::U64_BLOCK_CAPACITY;
+
+    const IDENTITY: fn(&u64) -> u64 = |key: &u64| *key;
+
+    #[test]
+    fn should_insert_get_and_remove_values() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(10);
+        let mut table = U64Table::new(4, &mut bpm, mix64).unwrap();
+
+        // when
+        for k in 0..500 {
+            assert!(table.insert(&k, &(k * 10)).unwrap());
+        }
+        assert!(table.insert(&7, &71).unwrap());
+        assert!(!table.insert(&7, &70).unwrap());
+        table.remove(&8).unwrap();
+        assert!(table.upsert(&9, &91).unwrap());
+        assert!(!table.upsert(&600, &6000).unwrap());
+        assert!(table.insert(&10, &101).unwrap());
+        assert!(table.remove_kv(&10, &100).unwrap());
+        assert!(!table.remove_kv(&10, &100).unwrap());
+
+        // then
+        assert_eq!(table.get_value(&7).unwrap(), vec![70, 71]);
+        assert_eq!(table.get_value(&9).unwrap(), vec![91]);
+        assert_eq!(table.get_value(&600).unwrap(), vec![6000]);
+        assert_eq!(table.get_value(&10).unwrap(), vec![101]);
+        assert_eq!(table.get_value(&499).unwrap(), vec![4990]);
+        assert!(table.get_value(&8).unwrap().is_empty());
+        assert!(table.get_value(&500).unwrap().is_empty());
+        assert!(table.contains_key(&499).unwrap() && !table.contains_key(&8).unwrap());
+        let header_pid = table.header_pid();
+        assert_eq!(bpm.pin_count_of(header_pid), Some(0));
+    }
+
+    #[test]
+    fn should_probe_into_next_block_and_wrap_around() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(10);
+        let mut table = U64Table::new(2, &mut bpm, IDENTITY).unwrap();
+        let last_slot = 2 * U64_BLOCK_CAPACITY as u64 - 1;
+
+        // when
+        for i in 0..3 {
+            assert!(table.insert(&last_slot, &i).unwrap());
+        }
+        table.remove(&last_slot).unwrap();
+        assert!(table.insert(&(last_slot - 2), &9).unwrap());
+
+        // then
+        assert!(table.get_value(&last_slot).unwrap().is_empty());
+        assert_eq!(table.get_value(&(last_slot - 2)).unwrap(), vec![9]);
+        assert_eq!(table.try_get_value(0).unwrap(), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn should_report_full_table() {
+        // given
+        let mut bpm = BufferPoolManager::new_default(10);
+        let mut table = U64Table::new(1, &mut bpm, IDENTITY).unwrap();
+        for k in 0..U64_BLOCK_CAPACITY as u64 {
+            assert!(table.insert(&k, &k).unwrap());
+        }
+
+        // when
+        let inserted = table.try_insert(3, 4).unwrap();
+
+        // then
+        assert!(!inserted);
+        assert_eq!(table.get_value(&3).unwrap(), vec![3]);
+    }
+}
@@ -0,0 +1,917 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::{fmt, io};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::common::compare::{KeyComparator, NaturalOrder};
+use crate::container::index::index_error::IndexError;
+use crate::storage::page::b_plus_tree_meta_page::BPlusTreeMetaPage;
+use crate::storage::page::b_plus_tree_page::{BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage};
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_version::PageType;
+
+/// Ordered index of unique keys over the buffer pool, one node per page, keys ordered by a
+/// `KeyComparator` chosen when the tree is created.
+///
+/// A meta page records the root and the name of the comparator, so its page id is all that is
+/// needed to open the tree again. The root stays on its page, growing by moving its content down
+/// into new pages.
+pub struct BPlusTree<K, V> {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    meta_pid: PageId,
+    root_pid: PageId,
+    comparator: Arc<dyn KeyComparator<K>>,
+    max_entries: usize,
+    phantom: PhantomData<V>,
+}
+
+impl<K, V> BPlusTree<K, V>
+    where
+        K: Clone + Serialize + DeserializeOwned,
+        V: Clone + Serialize + DeserializeOwned,
+{
+    /// Empty tree ordered by `Ord` of the keys.
+    pub fn new(bpm: Arc<Mutex<BufferPoolManager>>) -> io::Result<BPlusTree<K, V>>
+        where K: Ord
+    {
+        BPlusTree::new_with_comparator(bpm, NaturalOrder)
+    }
+
+    /// Writes the meta page and the root of an empty tree, a single leaf.
+    pub fn new_with_comparator<C: KeyComparator<K> + 'static>(bpm: Arc<Mutex<BufferPoolManager>>, comparator: C) -> io::Result<BPlusTree<K, V>> {
+        let meta_pid = {
+            let mut bpm = bpm.lock().unwrap();
+            let root_pid = bpm.write_encoded(None, &BPlusTreePage::Leaf(BPlusTreeLeafPage::<K, V>::new()))?;
+            bpm.write_encoded(None, &BPlusTreeMetaPage::new(root_pid, comparator.name()))?
+        };
+        BPlusTree::open_with_comparator(bpm, meta_pid, comparator)
+    }
+
+    /// Tree created before ordered by `Ord` of the keys, with its meta page on page `meta_pid`.
+    pub fn open(bpm: Arc<Mutex<BufferPoolManager>>, meta_pid: PageId) -> io::Result<BPlusTree<K, V>>
+        where K: Ord
+    {
+        BPlusTree::open_with_comparator(bpm, meta_pid, NaturalOrder)
+    }
+
+    /// Fails with `IndexError::ComparatorMismatch` unless the tree was created with a comparator of the
+    /// same name as `comparator`.
+    pub fn open_with_comparator<C: KeyComparator<K> + 'static>(bpm: Arc<Mutex<BufferPoolManager>>, meta_pid: PageId, comparator: C) -> io::Result<BPlusTree<K, V>> {
+        let meta: BPlusTreeMetaPage = bpm.lock().unwrap().fetch_decoded(meta_pid)?;
+        if meta.get_comparator() != comparator.name() {
+            return Err(IndexError::ComparatorMismatch { stored: meta.get_comparator().to_string(), given: comparator.name() }.into());
+        }
+
+        Ok(BPlusTree {
+            buffer_pool_manager: bpm,
+            meta_pid,
+            root_pid: meta.get_root_page_id(),
+            comparator: Arc::new(comparator),
+            max_entries: usize::MAX,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Splits nodes holding more than `max_entries` keys even while they still fit their page, e.g. to
+    /// get a deep tree out of few keys.
+    pub fn with_max_entries(mut self, max_entries: usize) -> BPlusTree<K, V> {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn meta_page_id(&self) -> PageId {
+        self.meta_pid
+    }
+
+    pub fn root_page_id(&self) -> PageId {
+        self.root_pid
+    }
+
+    pub fn get(&self, k: &K) -> io::Result<Option<V>> {
+        let mut pid = self.root_pid;
+        loop {
+            match self.fetch(pid)? {
+                BPlusTreePage::Leaf(leaf) => return Ok(leaf.get(k, &*self.comparator).cloned()),
+                BPlusTreePage::Internal(node) => pid = node.children()[node.child_index(k, &*self.comparator)],
+            }
+        }
+    }
+
+    /// Entries with keys in `range`, from the last one down, e.g. to read the latest keys first without
+    /// collecting and reversing a whole scan. Leaves only point to the next one, so the way back goes
+    /// through their parents, kept on a stack while iterating.
+    pub fn range_rev<R: RangeBounds<K>>(&self, range: R) -> io::Result<RangeRev<'_, K, V>> {
+        let mut iter = RangeRev { tree: self, start: range.start_bound().cloned(), path: Vec::new(), entries: Vec::new(), done: false };
+        iter.descend(self.root_pid, range.end_bound())?;
+        Ok(iter)
+    }
+
+    /// Reads every page reachable from the root, checking that the keys of each node are in order and
+    /// between the separators of its parents, that no node but the root is underfull or any overfull,
+    /// that all leaves are at the same depth and chained in key order, and that no page is referred
+    /// to twice. Pages that cannot be read are reported too, instead of ending the check.
+    pub fn check_integrity(&self) -> IntegrityReport {
+        let mut walk = IntegrityWalk { visited: HashSet::new(), leaves: Vec::new(), report: IntegrityReport::default() };
+        match self.bpm().fetch_decoded::<BPlusTreeMetaPage>(self.meta_pid) {
+            Ok(meta) if meta.get_root_page_id() != self.root_pid =>
+                walk.report.issues.push(IntegrityIssue::RootMismatch { stored: meta.get_root_page_id(), opened: self.root_pid }),
+            Ok(_) => (),
+            Err(e) => walk.report.issues.push(IntegrityIssue::Unreadable { page_id: self.meta_pid, error: e.into() }),
+        }
+        self.check_node(&mut walk, self.root_pid, None, None, 1);
+
+        for (i, (page_id, found)) in walk.leaves.iter().enumerate() {
+            let expected = walk.leaves.get(i + 1).map(|(next_pid, _)| *next_pid);
+            if *found != expected {
+                walk.report.issues.push(IntegrityIssue::BrokenLeafChain { page_id: *page_id, expected, found: *found });
+            }
+        }
+        walk.report
+    }
+
+    /// Checks the subtree under page `pid`, whose keys have to lie in `[lower, upper)`.
+    fn check_node(&self, walk: &mut IntegrityWalk, pid: PageId, lower: Option<&K>, upper: Option<&K>, depth: usize) {
+        let issues = &mut walk.report.issues;
+        if !walk.visited.insert(pid) {
+            issues.push(IntegrityIssue::SharedPage { page_id: pid });
+            return;
+        }
+        walk.report.pages += 1;
+        let node = match self.fetch(pid) {
+            Ok(node) => node,
+            Err(error) => return issues.push(IntegrityIssue::Unreadable { page_id: pid, error }),
+        };
+
+        let cmp = &*self.comparator;
+        let keys: Vec<&K> = match &node {
+            BPlusTreePage::Leaf(leaf) => leaf.entries().iter().map(|(k, _)| k).collect(),
+            BPlusTreePage::Internal(node) => node.keys().iter().collect(),
+        };
+        for (key_idx, k) in keys.iter().enumerate() {
+            if key_idx > 0 && cmp.compare(keys[key_idx - 1], k) != Ordering::Less {
+                issues.push(IntegrityIssue::KeysOutOfOrder { page_id: pid, key_idx });
+            }
+            if lower.is_some_and(|lower| cmp.compare(k, lower) == Ordering::Less)
+                || upper.is_some_and(|upper| cmp.compare(k, upper) != Ordering::Less) {
+                issues.push(IntegrityIssue::KeyOutOfBounds { page_id: pid, key_idx });
+            }
+        }
+        if pid != self.root_pid && matches!(self.is_underfull(&node), Ok(true)) {
+            issues.push(IntegrityIssue::Underfull { page_id: pid });
+        }
+        if matches!(self.is_overfull(&node), Ok(true)) {
+            issues.push(IntegrityIssue::Overfull { page_id: pid });
+        }
+
+        match node {
+            BPlusTreePage::Leaf(leaf) => {
+                let report = &mut walk.report;
+                if report.leaves == 0 {
+                    report.depth = depth;
+                } else if report.depth != depth {
+                    report.issues.push(IntegrityIssue::UnevenDepth { page_id: pid, depth, expected: report.depth });
+                }
+                report.leaves += 1;
+                report.entries += leaf.len();
+                walk.leaves.push((pid, leaf.get_next_page_id()));
+            }
+            BPlusTreePage::Internal(node) => {
+                for (i, child_pid) in node.children().iter().enumerate() {
+                    let child_lower = if i == 0 { lower } else { Some(&node.keys()[i - 1]) };
+                    let child_upper = node.keys().get(i).or(upper);
+                    self.check_node(walk, *child_pid, child_lower, child_upper, depth + 1);
+                }
+            }
+        }
+    }
+
+    /// The tree in Graphviz dot format, for looking at splits and merges while debugging: a record per
+    /// page with its id and keys, an edge from each child pointer to its child, and dashed edges along
+    /// the leaf chain.
+    pub fn dump_dot(&self) -> io::Result<String>
+        where K: Debug
+    {
+        let mut lines = vec!["digraph b_plus_tree {".to_string(), "  node [shape=record];".to_string()];
+        let mut pids = vec![self.root_pid];
+        while let Some(pid) = pids.pop() {
+            match self.fetch(pid)? {
+                BPlusTreePage::Internal(node) => {
+                    let mut fields = vec![format!("page {}", pid)];
+                    for (i, key) in node.keys().iter().enumerate() {
+                        fields.push(format!("<c{}>", i));
+                        fields.push(dot_escape(&format!("{:?}", key)));
+                    }
+                    fields.push(format!("<c{}>", node.keys().len()));
+                    lines.push(format!("  page_{} [label=\"{}\"];", pid, fields.join("|")));
+                    for (i, child_pid) in node.children().iter().enumerate() {
+                        lines.push(format!("  page_{}:c{} -> page_{};", pid, i, child_pid));
+                    }
+                    pids.extend(node.children().iter().rev());
+                }
+                BPlusTreePage::Leaf(leaf) => {
+                    let fields: Vec<String> = leaf.entries().iter().map(|(k, _)| dot_escape(&format!("{:?}", k))).collect();
+                    lines.push(format!("  page_{} [label=\"leaf {}|{}\"];", pid, pid, fields.join("|")));
+                    if let Some(next_pid) = leaf.get_next_page_id() {
+                        lines.push(format!("  page_{} -> page_{} [style=dashed, constraint=false];", pid, next_pid));
+                    }
+                }
+            }
+        }
+        lines.push("}".to_string());
+
+        Ok(lines.join("\n") + "\n")
+    }
+
+    /// Returns false, leaving the tree unchanged, if `k` is already in it. A full leaf is split in
+    /// halves, adding a separator to its parent, which in turn splits if full, up to the root.
+    pub fn insert(&mut self, k: &K, v: &V) -> io::Result<bool> {
+        let size = bincode::serialized_size(&(k, v)).map_err(PageError::from)? as usize;
+        if size > BPlusTreeLeafPage::<K, V>::max_entry_size() {
+            return Err(PageError::ValueTooLarge { size, capacity: BPlusTreeLeafPage::<K, V>::max_entry_size() }.into());
+        }
+
+        let mut path = Vec::new();
+        let mut pid = self.root_pid;
+        let mut leaf = loop {
+            match self.fetch(pid)? {
+                BPlusTreePage::Leaf(leaf) => break leaf,
+                BPlusTreePage::Internal(node) => {
+                    let child_idx = node.child_index(k, &*self.comparator);
+                    let child_pid = node.children()[child_idx];
+                    path.push((pid, node, child_idx));
+                    pid = child_pid;
+                }
+            }
+        };
+        if !leaf.insert(k.clone(), v.clone(), &*self.comparator) {
+            return Ok(false);
+        }
+
+        let mut split = self.write_or_split(pid, BPlusTreePage::Leaf(leaf))?;
+        while let Some((separator, right_pid)) = split {
+            let (parent_pid, mut parent, child_idx) = path.pop().unwrap();
+            parent.insert_child(child_idx, separator, right_pid);
+            split = self.write_or_split(parent_pid, BPlusTreePage::Internal(parent))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Writes the node back to page `pid`, first splitting it if it got too large. Returns the separator
+    /// and page of the new right half for the parent to take in, unless the node was the root.
+    fn write_or_split(&self, pid: PageId, node: BPlusTreePage<K, V>) -> io::Result<Option<(K, PageId)>> {
+        if !self.is_overfull(&node)? {
+            self.write(Some(pid), &node)?;
+            return Ok(None);
+        }
+
+        let (mut left, separator, right) = BPlusTree::split(node);
+        let right_pid = self.write(None, &right)?;
+        link_to(&mut left, right_pid);
+        if pid != self.root_pid {
+            self.write(Some(pid), &left)?;
+            return Ok(Some((separator, right_pid)));
+        }
+
+        // the root keeps its page, becoming the parent of both halves
+        let left_pid = self.write(None, &left)?;
+        self.write(Some(pid), &BPlusTreePage::Internal(BPlusTreeInternalPage::new(left_pid, separator, right_pid)))?;
+        Ok(None)
+    }
+
+    /// Returns false if `k` was not in the tree. A leaf left underfull takes keys from a sibling, or
+    /// is merged with it if both fit one page, dropping a separator from their parent, which in turn
+    /// is rebalanced if left underfull. A root left with a single child takes over its content.
+    pub fn remove(&mut self, k: &K) -> io::Result<bool> {
+        let mut path = Vec::new();
+        let mut pid = self.root_pid;
+        let mut leaf = loop {
+            match self.fetch(pid)? {
+                BPlusTreePage::Leaf(leaf) => break leaf,
+                BPlusTreePage::Internal(node) => {
+                    let child_idx = node.child_index(k, &*self.comparator);
+                    let child_pid = node.children()[child_idx];
+                    path.push((pid, node, child_idx));
+                    pid = child_pid;
+                }
+            }
+        };
+        if !leaf.remove(k, &*self.comparator) {
+            return Ok(false);
+        }
+
+        let mut node = BPlusTreePage::Leaf(leaf);
+        while let Some((parent_pid, mut parent, child_idx)) = path.pop() {
+            if !self.is_underfull(&node)? {
+                self.write(Some(pid), &node)?;
+                return Ok(true);
+            }
+            self.rebalance(&mut parent, child_idx, node)?;
+            node = BPlusTreePage::Internal(parent);
+            pid = parent_pid;
+        }
+
+        match node {
+            BPlusTreePage::Internal(root) if root.keys().is_empty() => {
+                let child_pid = root.children()[0];
+                let child = self.fetch(child_pid)?;
+                self.write(Some(self.root_pid), &child)?;
+                self.free(child_pid)?;
+            }
+            root => {
+                self.write(Some(self.root_pid), &root)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Merges the underfull child `child_idx` of `parent` with a sibling, or splits their keys evenly
+    /// between both if they do not fit one node. Both are written, `parent` is left to the caller.
+    fn rebalance(&self, parent: &mut BPlusTreeInternalPage<K>, child_idx: usize, child: BPlusTreePage<K, V>) -> io::Result<()> {
+        let left_idx = child_idx.saturating_sub(1);
+        let (left_pid, right_pid) = (parent.children()[left_idx], parent.children()[left_idx + 1]);
+        let (left, right) = if child_idx == left_idx {
+            (child, self.fetch(right_pid)?)
+        } else {
+            (self.fetch(left_pid)?, child)
+        };
+
+        let merged = match (left, right) {
+            (BPlusTreePage::Leaf(mut left), BPlusTreePage::Leaf(right)) => {
+                left.merge(right);
+                BPlusTreePage::Leaf(left)
+            }
+            (BPlusTreePage::Internal(mut left), BPlusTreePage::Internal(right)) => {
+                left.merge(parent.keys()[left_idx].clone(), right);
+                BPlusTreePage::Internal(left)
+            }
+            // siblings are always at the same depth
+            _ => return Err(PageError::WrongPageType { expected: PageType::BPlusTreeLeaf, found: PageType::BPlusTreeInternal as u8 }.into()),
+        };
+        if !self.is_overfull(&merged)? {
+            self.write(Some(left_pid), &merged)?;
+            parent.remove_child(left_idx);
+            return self.free(right_pid);
+        }
+
+        let (mut left, separator, right) = BPlusTree::split(merged);
+        link_to(&mut left, right_pid);
+        self.write(Some(left_pid), &left)?;
+        self.write(Some(right_pid), &right)?;
+        parent.set_key(left_idx, separator);
+        Ok(())
+    }
+
+    /// Halves of the node, and the separator between them: the first key of the right half for leaves,
+    /// the middle key taken out of internal nodes.
+    fn split(node: BPlusTreePage<K, V>) -> (BPlusTreePage<K, V>, K, BPlusTreePage<K, V>) {
+        match node {
+            BPlusTreePage::Leaf(mut leaf) => {
+                let right = leaf.split();
+                let separator = right.entries()[0].0.clone();
+                (BPlusTreePage::Leaf(leaf), separator, BPlusTreePage::Leaf(right))
+            }
+            BPlusTreePage::Internal(mut node) => {
+                let (separator, right) = node.split();
+                (BPlusTreePage::Internal(node), separator, BPlusTreePage::Internal(right))
+            }
+        }
+    }
+
+    /// Deletes every page of the tree, its meta page included.
+    pub fn drop_tree(self) -> io::Result<()> {
+        let mut pids = vec![self.root_pid];
+        while let Some(pid) = pids.pop() {
+            if let BPlusTreePage::Internal(node) = self.fetch(pid)? {
+                pids.extend_from_slice(node.children());
+            }
+            self.free(pid)?;
+        }
+        self.free(self.meta_pid)
+    }
+
+    /// A node is underfull once below half of `max_entries` and a quarter of its page, so that neither
+    /// half of a split node is.
+    fn is_underfull(&self, node: &BPlusTreePage<K, V>) -> io::Result<bool> {
+        let (len, size) = match node {
+            BPlusTreePage::Leaf(leaf) => (leaf.len(), leaf.encoded_size()?),
+            BPlusTreePage::Internal(node) => (node.keys().len(), node.encoded_size()?),
+        };
+        Ok(len * 2 < self.max_entries && size * 4 < PAGE_SIZE)
+    }
+
+    fn is_overfull(&self, node: &BPlusTreePage<K, V>) -> io::Result<bool> {
+        match node {
+            BPlusTreePage::Leaf(leaf) => Ok(leaf.len() > self.max_entries || !leaf.fits()?),
+            BPlusTreePage::Internal(node) => Ok(node.keys().len() > self.max_entries || !node.fits()?),
+        }
+    }
+
+    fn fetch(&self, pid: PageId) -> io::Result<BPlusTreePage<K, V>> {
+        Ok(self.bpm().fetch_decoded(pid)?)
+    }
+
+    /// Writes over page `pid`, or a new page if `None`, returning the page written.
+    fn write(&self, pid: Option<PageId>, node: &BPlusTreePage<K, V>) -> io::Result<PageId> {
+        Ok(self.bpm().write_encoded(pid, node)?)
+    }
+
+    fn free(&self, pid: PageId) -> io::Result<()> {
+        self.bpm().delete_page(pid)?;
+        Ok(())
+    }
+
+    fn bpm(&self) -> MutexGuard<'_, BufferPoolManager> {
+        self.buffer_pool_manager.lock().unwrap()
+    }
+}
+
+/// Inconsistency found by `BPlusTree::check_integrity`. Keys are told by their index in the page, as
+/// they need not be printable.
+#[derive(Debug)]
+pub enum IntegrityIssue {
+    /// The meta page records another root than the tree was opened with.
+    RootMismatch { stored: PageId, opened: PageId },
+    Unreadable { page_id: PageId, error: io::Error },
+    /// Key `key_idx` is not greater than the key before it.
+    KeysOutOfOrder { page_id: PageId, key_idx: usize },
+    /// Key `key_idx` is outside the range the separators of the parents leave for the page.
+    KeyOutOfBounds { page_id: PageId, key_idx: usize },
+    Underfull { page_id: PageId },
+    Overfull { page_id: PageId },
+    /// A leaf at another depth than the first leaf, at `expected`.
+    UnevenDepth { page_id: PageId, depth: usize, expected: usize },
+    /// A page referred to as child a second time, or the root referred to as child.
+    SharedPage { page_id: PageId },
+    /// The leaf points to `found` instead of the next leaf in key order.
+    BrokenLeafChain { page_id: PageId, expected: Option<PageId>, found: Option<PageId> },
+}
+
+impl Display for IntegrityIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityIssue::RootMismatch { stored, opened } => write!(f, "Meta page records root {}, tree opened with root {}.", stored, opened),
+            IntegrityIssue::Unreadable { page_id, error } => write!(f, "Page {} cannot be read: {}.", page_id, error),
+            IntegrityIssue::KeysOutOfOrder { page_id, key_idx } => write!(f, "Key {} of page {} is out of order.", key_idx, page_id),
+            IntegrityIssue::KeyOutOfBounds { page_id, key_idx } => write!(f, "Key {} of page {} is outside the separators of its parent.", key_idx, page_id),
+            IntegrityIssue::Underfull { page_id } => write!(f, "Page {} is underfull.", page_id),
+            IntegrityIssue::Overfull { page_id } => write!(f, "Page {} is overfull.", page_id),
+            IntegrityIssue::UnevenDepth { page_id, depth, expected } => write!(f, "Leaf {} is at depth {}, expected {}.", page_id, depth, expected),
+            IntegrityIssue::SharedPage { page_id } => write!(f, "Page {} is referred to more than once.", page_id),
+            IntegrityIssue::BrokenLeafChain { page_id, expected, found } => write!(f, "Leaf {} points to {:?}, expected {:?}.", page_id, found, expected),
+        }
+    }
+}
+
+/// Outcome of `BPlusTree::check_integrity`: what was read, and every issue found on the way.
+#[derive(Debug, Default)]
+pub struct IntegrityReport {
+    pub pages: usize,
+    pub leaves: usize,
+    pub entries: usize,
+    /// Levels down to the first leaf, 1 for a root leaf.
+    pub depth: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// State of `BPlusTree::check_integrity` while going through the pages.
+struct IntegrityWalk {
+    visited: HashSet<PageId>,
+    /// Leaves in key order, with the next leaf each points to.
+    leaves: Vec<(PageId, Option<PageId>)>,
+    report: IntegrityReport,
+}
+
+/// Iterator of `BPlusTree::range_rev`, ending after the first error.
+pub struct RangeRev<'a, K, V> {
+    tree: &'a BPlusTree<K, V>,
+    start: Bound<K>,
+    /// Internal nodes down to the current leaf, with the index of the child taken in each.
+    path: Vec<(BPlusTreeInternalPage<K>, usize)>,
+    /// Entries of the current leaf not returned yet, the next one last.
+    entries: Vec<(K, V)>,
+    done: bool,
+}
+
+impl<K, V> RangeRev<'_, K, V>
+    where
+        K: Clone + Serialize + DeserializeOwned,
+        V: Clone + Serialize + DeserializeOwned,
+{
+    /// Goes down from page `pid` to the leaf holding `end`, or the last leaf if unbounded, keeping
+    /// the entries of the leaf up to `end`.
+    fn descend(&mut self, mut pid: PageId, end: Bound<&K>) -> io::Result<()> {
+        let cmp = &*self.tree.comparator;
+        loop {
+            match self.tree.fetch(pid)? {
+                BPlusTreePage::Internal(node) => {
+                    let child_idx = match end {
+                        Bound::Included(k) | Bound::Excluded(k) => node.child_index(k, cmp),
+                        Bound::Unbounded => node.children().len() - 1,
+                    };
+                    pid = node.children()[child_idx];
+                    self.path.push((node, child_idx));
+                }
+                BPlusTreePage::Leaf(leaf) => {
+                    self.entries = leaf.entries().iter()
+                        .filter(|(k, _)| match end {
+                            Bound::Included(end) => cmp.compare(k, end) != Ordering::Greater,
+                            Bound::Excluded(end) => cmp.compare(k, end) == Ordering::Less,
+                            Bound::Unbounded => true,
+                        })
+                        .cloned()
+                        .collect();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Moves to the leaf before the current one, returning false if it was the first leaf.
+    fn previous_leaf(&mut self) -> io::Result<bool> {
+        while let Some((node, child_idx)) = self.path.pop() {
+            if child_idx > 0 {
+                let pid = node.children()[child_idx - 1];
+                self.path.push((node, child_idx - 1));
+                self.descend(pid, Bound::Unbounded)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<K, V> Iterator for RangeRev<'_, K, V>
+    where
+        K: Clone + Serialize + DeserializeOwned,
+        V: Clone + Serialize + DeserializeOwned,
+{
+    type Item = io::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if let Some((k, v)) = self.entries.pop() {
+                let cmp = &*self.tree.comparator;
+                let in_range = match &self.start {
+                    Bound::Included(start) => cmp.compare(&k, start) != Ordering::Less,
+                    Bound::Excluded(start) => cmp.compare(&k, start) == Ordering::Greater,
+                    Bound::Unbounded => true,
+                };
+                if in_range {
+                    return Some(Ok((k, v)));
+                }
+                self.done = true;
+                break;
+            }
+
+            match self.previous_leaf() {
+                Ok(found) => self.done = !found,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Escapes the characters with a meaning in dot record labels.
+fn dot_escape(text: &str) -> String {
+    text.chars().fold(String::new(), |mut escaped, c| {
+        if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// Points a leaf split off on the left to its right half, on page `right_pid`.
+fn link_to<K, V>(left: &mut BPlusTreePage<K, V>, right_pid: PageId)
+    where
+        K: Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned,
+{
+    if let BPlusTreePage::Leaf(leaf) = left {
+        leaf.set_next_page_id(Some(right_pid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::sync::{Arc, Mutex};
+
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::common::compare::{CaseInsensitive, Descending};
+    use crate::container::index::index_error::IndexError;
+    use crate::container::index::b_plus_tree::{IntegrityIssue, RangeRev};
+    use crate::container::index::BPlusTree;
+    use crate::storage::page::b_plus_tree_page::BPlusTreePage;
+    use crate::storage::page::page::PageId;
+    use crate::storage::page::page_error::PageError;
+
+    /// Keys of the leaves in leaf chain order, starting from the leftmost leaf.
+    fn leaf_chain_keys(tree: &BPlusTree<u64, u64>) -> Vec<u64> {
+        let mut pid: Option<PageId> = Some(tree.root_page_id());
+        while let Some(BPlusTreePage::Internal(node)) = pid.map(|pid| tree.fetch(pid).unwrap()) {
+            pid = Some(node.children()[0]);
+        }
+
+        let mut keys = Vec::new();
+        while let Some(leaf_pid) = pid {
+            match tree.fetch(leaf_pid).unwrap() {
+                BPlusTreePage::Leaf(leaf) => {
+                    keys.extend(leaf.entries().iter().map(|(k, _)| *k));
+                    pid = leaf.get_next_page_id();
+                }
+                BPlusTreePage::Internal(_) => panic!("leaf chain reached internal page {}", leaf_pid),
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn should_find_keys_inserted_in_random_order_after_splits_and_reopen() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(8)));
+        let mut tree = BPlusTree::new(bpm.clone()).unwrap().with_max_entries(4);
+        let mut keys: Vec<u64> = (0..500).map(|k| k * 2).collect();
+        keys.shuffle(&mut StdRng::seed_from_u64(75));
+
+        // when
+        for k in keys.iter() {
+            assert!(tree.insert(k, &(k + 1)).unwrap());
+        }
+        let duplicate_inserted = tree.insert(&10, &0).unwrap();
+        let reopened = BPlusTree::<u64, u64>::open(bpm, tree.meta_page_id()).unwrap();
+
+        // then
+        assert!(!duplicate_inserted);
+        for k in 0..1000 {
+            let expected = if k % 2 == 0 { Some(k + 1) } else { None };
+            assert_eq!(reopened.get(&k).unwrap(), expected, "key {}", k);
+        }
+        assert_eq!(leaf_chain_keys(&reopened), (0..500).map(|k| k * 2).collect::<Vec<u64>>());
+    }
+
+    /// Checks key order against the bounds from the parents and node fill, returning the depth of the
+    /// subtree and collecting its pages.
+    fn check_subtree(tree: &BPlusTree<u64, u64>, pid: PageId, lower: Option<u64>, upper: Option<u64>, pages: &mut Vec<PageId>) -> usize {
+        pages.push(pid);
+        let node = tree.fetch(pid).unwrap();
+        assert!(pid == tree.root_page_id() || !tree.is_underfull(&node).unwrap(), "page {} underfull", pid);
+        assert!(!tree.is_overfull(&node).unwrap(), "page {} overfull", pid);
+        let keys: Vec<u64> = match &node {
+            BPlusTreePage::Leaf(leaf) => leaf.entries().iter().map(|(k, _)| *k).collect(),
+            BPlusTreePage::Internal(node) => node.keys().to_vec(),
+        };
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "keys of page {} out of order", pid);
+        assert!(keys.iter().all(|k| lower.is_none_or(|lower| *k >= lower) && upper.is_none_or(|upper| *k < upper)),
+                "keys of page {} out of bounds", pid);
+
+        match node {
+            BPlusTreePage::Leaf(_) => 1,
+            BPlusTreePage::Internal(node) => {
+                let depths: Vec<usize> = node.children().iter().enumerate().map(|(i, child_pid)| {
+                    let child_lower = if i == 0 { lower } else { Some(node.keys()[i - 1]) };
+                    let child_upper = node.keys().get(i).copied().or(upper);
+                    check_subtree(tree, *child_pid, child_lower, child_upper, pages)
+                }).collect();
+                assert!(depths.iter().all(|d| *d == depths[0]), "leaves under page {} at different depths", pid);
+                depths[0] + 1
+            }
+        }
+    }
+
+    #[test]
+    fn should_keep_tree_balanced_through_random_inserts_and_removes() {
+        for seed in 0..3 {
+            // given
+            let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(16)));
+            let mut tree = BPlusTree::new(bpm).unwrap().with_max_entries(4);
+            let mut model = BTreeMap::new();
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            for op in 0..3000 {
+                // when
+                let k = rng.gen_range(0..300u64);
+                if rng.gen_bool(0.55) {
+                    assert_eq!(tree.insert(&k, &(k * 3)).unwrap(), model.insert(k, k * 3).is_none());
+                } else {
+                    assert_eq!(tree.remove(&k).unwrap(), model.remove(&k).is_some());
+                }
+
+                // then
+                if op % 100 == 99 {
+                    check_subtree(&tree, tree.root_page_id(), None, None, &mut Vec::new());
+                    let report = tree.check_integrity();
+                    assert!(report.is_ok(), "seed {}: {:?}", seed, report.issues);
+                    assert_eq!(report.entries, model.len());
+                    assert_eq!(leaf_chain_keys(&tree), model.keys().copied().collect::<Vec<u64>>());
+                    for k in 0..300 {
+                        assert_eq!(tree.get(&k).unwrap(), model.get(&k).copied(), "seed {} key {}", seed, k);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_shrink_to_root_leaf_and_free_pages_once_emptied() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(1000)));
+        let mut tree = BPlusTree::new(bpm.clone()).unwrap().with_max_entries(4);
+        for k in 0..200u64 {
+            tree.insert(&k, &k).unwrap();
+        }
+        let mut pages = Vec::new();
+        let depth = check_subtree(&tree, tree.root_page_id(), None, None, &mut pages);
+
+        // when
+        for k in (0..200u64).rev() {
+            assert!(tree.remove(&k).unwrap());
+        }
+
+        // then
+        assert!(depth > 2);
+        assert!(!tree.remove(&0).unwrap());
+        assert!(matches!(tree.fetch(tree.root_page_id()).unwrap(), BPlusTreePage::Leaf(leaf) if leaf.is_empty()));
+        for pid in pages.into_iter().filter(|pid| *pid != tree.root_page_id()) {
+            assert_eq!(bpm.lock().unwrap().pin_count_of(pid), None, "page {} not freed", pid);
+        }
+    }
+
+    #[test]
+    fn should_delete_every_page_when_dropped() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(1000)));
+        let mut tree = BPlusTree::new(bpm.clone()).unwrap().with_max_entries(4);
+        for k in 0..200u64 {
+            tree.insert(&k, &k).unwrap();
+        }
+        let mut pages = vec![tree.meta_page_id()];
+        check_subtree(&tree, tree.root_page_id(), None, None, &mut pages);
+
+        // when
+        tree.drop_tree().unwrap();
+
+        // then
+        for pid in pages {
+            assert_eq!(bpm.lock().unwrap().pin_count_of(pid), None, "page {} not freed", pid);
+        }
+    }
+
+    #[test]
+    fn should_iterate_ranges_backwards_across_leaves() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(16)));
+        let mut tree = BPlusTree::new(bpm).unwrap().with_max_entries(4);
+        let mut keys: Vec<u64> = (0..300).map(|k| k * 2).collect();
+        keys.shuffle(&mut StdRng::seed_from_u64(79));
+        for k in keys.iter() {
+            tree.insert(k, &(k + 1)).unwrap();
+        }
+        let rev_keys = |iter: RangeRev<u64, u64>| iter.map(|entry| entry.unwrap().0).collect::<Vec<u64>>();
+
+        // when
+        let all = rev_keys(tree.range_rev(..).unwrap());
+        let latest: Vec<(u64, u64)> = tree.range_rev(..).unwrap().take(3).map(Result::unwrap).collect();
+        let inclusive = rev_keys(tree.range_rev(100..=200).unwrap());
+        let exclusive = rev_keys(tree.range_rev((Bound::Excluded(101), Bound::Excluded(201))).unwrap());
+        let beyond = rev_keys(tree.range_rev(1000..).unwrap());
+
+        // then
+        assert_eq!(all, (0..300).rev().map(|k| k * 2).collect::<Vec<u64>>());
+        assert_eq!(latest, [(598, 599), (596, 597), (594, 595)]);
+        assert_eq!(inclusive, (50..=100).rev().map(|k| k * 2).collect::<Vec<u64>>());
+        assert_eq!(exclusive, (51..=100).rev().map(|k| k * 2).collect::<Vec<u64>>());
+        assert!(beyond.is_empty());
+    }
+
+    #[test]
+    fn should_report_broken_leaf_chain_and_keys_out_of_bounds() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(32)));
+        let mut tree = BPlusTree::new(bpm).unwrap().with_max_entries(4);
+        for k in 0..100u64 {
+            tree.insert(&k, &k).unwrap();
+        }
+        let report = tree.check_integrity();
+        let mut root = match tree.fetch(tree.root_page_id()).unwrap() {
+            BPlusTreePage::Internal(root) => root,
+            BPlusTreePage::Leaf(_) => panic!("root of 100 keys is a leaf"),
+        };
+        let mut pid = root.children()[0];
+        let mut leftmost = loop {
+            match tree.fetch(pid).unwrap() {
+                BPlusTreePage::Internal(node) => pid = node.children()[0],
+                BPlusTreePage::Leaf(leaf) => break leaf,
+            }
+        };
+        let next_pid = leftmost.get_next_page_id();
+
+        // when
+        leftmost.set_next_page_id(None);
+        tree.write(Some(pid), &BPlusTreePage::Leaf(leftmost)).unwrap();
+        root.set_key(0, u64::MAX);
+        tree.write(Some(tree.root_page_id()), &BPlusTreePage::Internal(root)).unwrap();
+        let broken = tree.check_integrity();
+
+        // then
+        assert!(report.is_ok());
+        assert_eq!(report.entries, 100);
+        assert!(report.depth > 2 && report.pages > report.leaves);
+        assert!(!broken.is_ok());
+        assert!(broken.issues.iter().any(|issue| matches!(issue,
+            IntegrityIssue::BrokenLeafChain { page_id, expected, found: None } if *page_id == pid && *expected == next_pid)));
+        assert!(broken.issues.iter().any(|issue| matches!(issue, IntegrityIssue::KeyOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn should_dump_pages_keys_and_links_as_dot() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(16)));
+        let mut tree = BPlusTree::new(bpm).unwrap().with_max_entries(2);
+        for name in ["a", "b", "c|d"] {
+            tree.insert(&name.to_string(), &0u64).unwrap();
+        }
+        let root = match tree.fetch(tree.root_page_id()).unwrap() {
+            BPlusTreePage::Internal(root) => root,
+            BPlusTreePage::Leaf(_) => panic!("root of 3 keys is a leaf"),
+        };
+        let (left, right) = (root.children()[0], root.children()[1]);
+
+        // when
+        let dot = tree.dump_dot().unwrap();
+
+        // then
+        let root_pid = tree.root_page_id();
+        assert!(dot.starts_with("digraph b_plus_tree {\n") && dot.ends_with("}\n"));
+        assert!(dot.contains(&format!("page_{} [label=\"page {}|<c0>|\\\"b\\\"|<c1>\"];", root_pid, root_pid)));
+        assert!(dot.contains(&format!("page_{}:c0 -> page_{};", root_pid, left)));
+        assert!(dot.contains(&format!("page_{}:c1 -> page_{};", root_pid, right)));
+        assert!(dot.contains(&format!("page_{} [label=\"leaf {}|\\\"b\\\"|\\\"c\\|d\\\"\"];", right, right)));
+        assert!(dot.contains(&format!("page_{} -> page_{} [style=dashed, constraint=false];", left, right)));
+    }
+
+    #[test]
+    fn should_refuse_entry_too_large_to_split() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(8)));
+        let mut tree = BPlusTree::<u64, String>::new(bpm).unwrap();
+
+        // when
+        let err = tree.insert(&1, &"x".repeat(2000)).err().unwrap();
+
+        // then
+        assert!(matches!(PageError::from_io(&err), Some(PageError::ValueTooLarge { .. })));
+        assert_eq!(tree.get(&1).unwrap(), None);
+    }
+
+    #[test]
+    fn should_order_keys_by_comparator_and_refuse_opening_with_another_one() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(16)));
+        let mut tree = BPlusTree::new_with_comparator(bpm.clone(), Descending(CaseInsensitive)).unwrap().with_max_entries(4);
+        for name in ["delta", "Alpha", "charlie", "Bravo", "echo", "foxtrot"] {
+            tree.insert(&name.to_string(), &name.len()).unwrap();
+        }
+
+        // when
+        let duplicate_inserted = tree.insert(&"ALPHA".to_string(), &0).unwrap();
+        let reopened = BPlusTree::<String, usize>::open_with_comparator(bpm.clone(), tree.meta_page_id(), Descending(CaseInsensitive)).unwrap();
+        let err = BPlusTree::<String, usize>::open(bpm, tree.meta_page_id()).err().unwrap();
+
+        // then
+        assert!(!duplicate_inserted);
+        assert_eq!(reopened.get(&"BRAVO".to_string()).unwrap(), Some(5));
+        let mut pid = Some(reopened.root_page_id());
+        while let Some(BPlusTreePage::Internal(node)) = pid.map(|pid| reopened.fetch(pid).unwrap()) {
+            pid = Some(node.children()[0]);
+        }
+        assert!(matches!(reopened.fetch(pid.unwrap()).unwrap(), BPlusTreePage::Leaf(leaf) if leaf.entries()[0].0 == "foxtrot"));
+        assert!(matches!(IndexError::from_io(&err), Some(IndexError::ComparatorMismatch { stored, .. }) if stored == "desc(case_insensitive)"));
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::common::error::cause_of;
+
+/// Failures of opening or using an ordered index, carried inside `io::Error` so callers can match on them.
+#[derive(Debug)]
+pub enum IndexError {
+    /// The index was created ordered by another comparator than the one it is opened with.
+    ComparatorMismatch { stored: String, given: String },
+}
+
+impl IndexError {
+    pub fn from_io(err: &io::Error) -> Option<&IndexError> {
+        cause_of(err)
+    }
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::ComparatorMismatch { stored, given } =>
+                write!(f, "Index is ordered by comparator {}, cannot open it with {}.", stored, given),
+        }
+    }
+}
+
+impl error::Error for IndexError {}
+
+impl From<IndexError> for io::Error {
+    fn from(e: IndexError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod b_plus_tree;
+pub mod index_error;
+
+pub use b_plus_tree::BPlusTree;
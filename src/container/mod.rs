@@ -1 +1,2 @@
-pub mod hash;
\ No newline at end of file
+pub mod hash;
+pub mod index;
\ No newline at end of file
@@ -0,0 +1,262 @@
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::buffer::replacer::ClockReplacer;
+use crate::catalog::Catalog;
+use crate::common::hash::{hash, HashKeyType};
+use crate::common::throttle::WriteThrottle;
+use crate::common::ValueType;
+use crate::container::hash::linear_probe_hash_table::LinearProbeHashTable;
+use crate::storage::disk::disk_error::DiskError;
+use crate::storage::disk::disk_manager::{DiskManager, FileDiskManager, FileDiskOptions};
+use crate::storage::page::catalog_page::CatalogPage;
+use crate::storage::page::page::PAGE_SIZE;
+use crate::storage::page::page_serde::PageSerde;
+
+pub const DEFAULT_POOL_SIZE: usize = 64;
+const SCRATCH_NUM_BUCKETS: usize = 4;
+
+pub struct MineDbOptions {
+    pub pool_size: usize,
+    pub disk: FileDiskOptions,
+}
+
+impl Default for MineDbOptions {
+    fn default() -> Self {
+        MineDbOptions { pool_size: DEFAULT_POOL_SIZE, disk: FileDiskOptions::default() }
+    }
+}
+
+impl MineDbOptions {
+    pub fn with_pool_size(self, pool_size: usize) -> MineDbOptions {
+        MineDbOptions { pool_size, ..self }
+    }
+
+    pub fn with_disk(self, disk: FileDiskOptions) -> MineDbOptions {
+        MineDbOptions { disk, ..self }
+    }
+}
+
+/// A database in one data file. Its catalog page is referenced from the superblock, so every
+/// table and index created before is there again when the file is opened.
+///
+/// Tuples written through the catalog pass the write throttle of the database, which limits nothing
+/// until limits are set on it.
+pub struct MineDb {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    catalog: Catalog,
+    throttle: Arc<WriteThrottle>,
+}
+
+impl MineDb {
+    pub fn open(path: &Path) -> io::Result<MineDb> {
+        MineDb::open_with_options(path, MineDbOptions::default())
+    }
+
+    /// A new file gets an empty catalog. Files in the legacy format have no superblock to hold
+    /// the catalog root and are refused.
+    pub fn open_with_options(path: &Path, options: MineDbOptions) -> io::Result<MineDb> {
+        let mut fdm = FileDiskManager::new_with_options(path, options.disk)?;
+        let catalog_root = match fdm.superblock() {
+            None => return Err(DiskError::NotDatabaseFile(path.display().to_string()).into()),
+            Some(superblock) => superblock.get_catalog_root(),
+        };
+        let catalog_root = match catalog_root {
+            Some(pid) => pid,
+            None => MineDb::write_empty_catalog(&mut fdm)?,
+        };
+
+        let pool_size = options.pool_size;
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new(
+            pool_size,
+            Box::new(ClockReplacer::new(pool_size)),
+            Box::new(fdm))));
+        let mut catalog = Catalog::open(bpm.clone(), catalog_root)?;
+        let throttle = Arc::new(WriteThrottle::new());
+        catalog.set_write_throttle(throttle.clone());
+        Ok(MineDb { buffer_pool_manager: bpm, catalog, throttle })
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    pub fn catalog_mut(&mut self) -> &mut Catalog {
+        &mut self.catalog
+    }
+
+    /// Shared with the catalog, so limits set on it apply right away, e.g. through
+    /// `RuntimeConfig::bind_throttle`.
+    pub fn write_throttle(&self) -> Arc<WriteThrottle> {
+        self.throttle.clone()
+    }
+
+    pub fn buffer_pool_manager(&self) -> Arc<Mutex<BufferPoolManager>> {
+        self.buffer_pool_manager.clone()
+    }
+
+    /// Empty key-value space for one session, e.g. to stage the pairs of a request, which is not
+    /// referenced from the catalog and is deleted when the returned handle is dropped or ended.
+    ///
+    /// Its pages live in the buffer pool along with those of the tables, and only reach the data file
+    /// when evicted or flushed. A crash while the space is open leaves its pages allocated.
+    pub fn session_scratch<K, V>(&self) -> io::Result<SessionScratch<K, V>>
+        where
+            K: HashKeyType + DeserializeOwned + 'static,
+            V: ValueType + DeserializeOwned,
+    {
+        let table = LinearProbeHashTable::new(SCRATCH_NUM_BUCKETS, self.buffer_pool_manager.clone(), hash::<K>)?;
+        Ok(SessionScratch { table, ended: false })
+    }
+
+    /// Writes back every dirty page and syncs the file.
+    pub fn flush(&self) -> io::Result<()> {
+        self.buffer_pool_manager.lock().unwrap().flush_all_pages()?;
+        Ok(())
+    }
+
+    fn write_empty_catalog(fdm: &mut FileDiskManager) -> io::Result<usize> {
+        let pid = fdm.allocate_page()?;
+        let mut page_data = [0u8; PAGE_SIZE];
+        CatalogPage::new(0, Vec::new(), Vec::new()).to_page(&mut page_data)?;
+        fdm.write_page(pid, &page_data)?;
+        fdm.update_superblock(|superblock| superblock.set_catalog_root(Some(pid)))?;
+        Ok(pid)
+    }
+}
+
+impl Drop for MineDb {
+    // best effort, `flush` reports the failure to callers who need to know
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Key-value space of a session, see `MineDb::session_scratch`. Used like the hash table it derefs to.
+pub struct SessionScratch<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    table: LinearProbeHashTable<K, V>,
+    ended: bool,
+}
+
+impl<K, V> SessionScratch<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    /// Deletes the pages of the space, reporting a failure that dropping the handle would swallow.
+    pub fn end(mut self) -> io::Result<()> {
+        self.ended = true;
+        self.table.clone().drop_table()
+    }
+}
+
+impl<K, V> Deref for SessionScratch<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    type Target = LinearProbeHashTable<K, V>;
+
+    fn deref(&self) -> &LinearProbeHashTable<K, V> {
+        &self.table
+    }
+}
+
+impl<K, V> DerefMut for SessionScratch<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    fn deref_mut(&mut self) -> &mut LinearProbeHashTable<K, V> {
+        &mut self.table
+    }
+}
+
+impl<K, V> Drop for SessionScratch<K, V>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+{
+    // best effort, see `end`
+    fn drop(&mut self) {
+        if !self.ended {
+            let _ = self.table.clone().drop_table();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::catalog::IndexKind;
+    use crate::container::hash::hash_table::HashTable;
+    use crate::db::{MineDb, MineDbOptions};
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::tuple::Tuple;
+    use crate::storage::table::value::Value;
+
+    #[test]
+    fn should_recover_tables_and_indexes_when_reopened() {
+        // given
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mine.db");
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("name", DataType::Varchar(32))]);
+        let tuple = Tuple::new(&[Value::Integer(7), Value::Varchar("seven".to_string())], &schema).unwrap();
+        let key = Tuple::new(&[Value::Integer(7)], &Schema::new(vec![Column::new("id", DataType::Integer)])).unwrap();
+        let rid = {
+            let mut db = MineDb::open(&path).unwrap();
+            let oid = db.catalog_mut().create_table("users", schema.clone()).unwrap().oid();
+            db.catalog_mut().create_index("users", &["id"], IndexKind::BPlusTree).unwrap();
+            db.catalog_mut().insert_tuple(oid, &tuple).unwrap()
+        };
+
+        // when
+        let db = MineDb::open(&path).unwrap();
+
+        // then
+        let users = db.catalog().get_table("users").unwrap();
+        assert_eq!(users.schema(), &schema);
+        let tuples: Vec<_> = users.heap().iter().map(|entry| entry.unwrap()).collect();
+        assert_eq!(tuples, vec![(rid, tuple)]);
+        let indexes = db.catalog().table_indexes(users.oid());
+        assert_eq!(indexes.len(), 1);
+        assert_eq!(indexes[0].scan_key(&key).unwrap(), vec![rid]);
+    }
+
+    #[test]
+    fn should_spill_session_scratch_to_pages_and_delete_them_when_session_ends() {
+        // given
+        let dir = tempdir().unwrap();
+        let db = MineDb::open_with_options(&dir.path().join("mine.db"), MineDbOptions::default().with_pool_size(8)).unwrap();
+        let mut scratch = db.session_scratch::<u64, u64>().unwrap();
+        for k in 0..2000 {
+            scratch.insert(&k, &(k * 2)).unwrap();
+        }
+
+        // when
+        let value = scratch.get_value(&1234).unwrap();
+        let header_pid = scratch.get_header_pid();
+        scratch.end().unwrap();
+        let dropped = {
+            let scratch = db.session_scratch::<u64, u64>().unwrap();
+            scratch.get_header_pid()
+        };
+
+        // then
+        assert_eq!(value, vec![2468]);
+        assert!(db.catalog().list_tables().is_empty());
+        let bpm = db.buffer_pool_manager();
+        assert!(bpm.lock().unwrap().pin_count_of(header_pid).is_none());
+        assert!(bpm.lock().unwrap().pin_count_of(dropped).is_none());
+    }
+}
@@ -2,3 +2,8 @@ pub mod buffer;
 pub mod storage;
 pub mod container;
 pub mod common;
+pub mod maintenance;
+pub mod catalog;
+pub mod db;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
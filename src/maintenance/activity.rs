@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Counts foreground operations so background work can tell whether the system is idle.
+pub struct ActivityMonitor {
+    ops: AtomicU64,
+    last_sample: Mutex<(Instant, u64)>,
+}
+
+impl ActivityMonitor {
+    pub fn new() -> ActivityMonitor {
+        ActivityMonitor {
+            ops: AtomicU64::new(0),
+            last_sample: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    pub fn record_op(&self) {
+        self.ops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn total_ops(&self) -> u64 {
+        self.ops.load(Ordering::Relaxed)
+    }
+
+    /// Operations per second since the previous sample.
+    pub fn sample_ops_per_sec(&self, now: Instant) -> u64 {
+        let mut last = self.last_sample.lock().unwrap();
+        let total = self.total_ops();
+        let elapsed = now.saturating_duration_since(last.0).as_secs_f64();
+        let ops = total - last.1;
+        *last = (now, total);
+        if elapsed <= 0.0 {
+            return ops
+        }
+        (ops as f64 / elapsed) as u64
+    }
+}
+
+impl Default for ActivityMonitor {
+    fn default() -> Self {
+        ActivityMonitor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::maintenance::activity::ActivityMonitor;
+
+    #[test]
+    fn should_sample_ops_per_sec_since_last_sample() {
+        // given
+        let monitor = ActivityMonitor::new();
+        let start = Instant::now();
+        monitor.sample_ops_per_sec(start);
+        for _ in 0..20 {
+            monitor.record_op();
+        }
+
+        // when
+        let rate = monitor.sample_ops_per_sec(start + Duration::from_secs(2));
+
+        // then
+        assert_eq!(rate, 10);
+        assert_eq!(monitor.sample_ops_per_sec(start + Duration::from_secs(3)), 0);
+        assert_eq!(monitor.total_ops(), 20);
+    }
+}
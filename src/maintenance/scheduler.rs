@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::maintenance::activity::ActivityMonitor;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Names the options of a checkpoint task. None ships with the crate, as there is no log to trim yet.
+pub const CHECKPOINT_TASK: &str = "checkpoint";
+
+/// Background work, such as `VacuumTask`, `AnalyzeTask` or `HashTableCompactionTask`.
+pub trait MaintenanceTask: Send {
+    fn name(&self) -> &str;
+
+    fn run(&mut self) -> io::Result<()>;
+}
+
+/// Time-of-day range in UTC, measured from midnight. A window with `start > end` wraps past midnight.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScheduleWindow {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl ScheduleWindow {
+    pub fn contains(&self, time_of_day: Duration) -> bool {
+        if self.start <= self.end {
+            self.start <= time_of_day && time_of_day < self.end
+        } else {
+            self.start <= time_of_day || time_of_day < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskOptions {
+    pub enabled: bool,
+    /// Minimum time between two runs of the task.
+    pub interval: Duration,
+    /// Task only runs inside this window; `None` means any time.
+    pub window: Option<ScheduleWindow>,
+}
+
+impl Default for TaskOptions {
+    fn default() -> Self {
+        TaskOptions {
+            enabled: true,
+            interval: Duration::from_secs(60),
+            window: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceOptions {
+    /// System counts as idle while foreground ops per second stay at or below this.
+    pub idle_ops_per_sec: u64,
+    /// How often the background thread checks for idle time.
+    pub tick: Duration,
+    /// Per-task options keyed by task name; tasks without an entry use `TaskOptions::default()`.
+    pub tasks: HashMap<String, TaskOptions>,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> Self {
+        MaintenanceOptions {
+            idle_ops_per_sec: 10,
+            tick: Duration::from_secs(1),
+            tasks: HashMap::new(),
+        }
+    }
+}
+
+pub struct TaskRun {
+    pub name: String,
+    pub result: io::Result<()>,
+}
+
+struct ScheduledTask {
+    task: Box<dyn MaintenanceTask>,
+    last_run: Option<Instant>,
+}
+
+/// Shared handle on the options of a scheduler; changes apply from the next tick, also while it runs.
+#[derive(Clone)]
+pub struct MaintenanceControl {
+    options: Arc<Mutex<MaintenanceOptions>>,
+}
+
+impl MaintenanceControl {
+    pub fn options(&self) -> MaintenanceOptions {
+        self.options.lock().unwrap().clone()
+    }
+
+    pub fn set_task_options(&self, name: &str, task_options: TaskOptions) {
+        self.options.lock().unwrap().tasks.insert(name.to_string(), task_options);
+    }
+
+    pub fn set_idle_ops_per_sec(&self, idle_ops_per_sec: u64) {
+        self.options.lock().unwrap().idle_ops_per_sec = idle_ops_per_sec;
+    }
+
+    pub fn set_tick(&self, tick: Duration) {
+        self.options.lock().unwrap().tick = tick;
+    }
+}
+
+pub struct MaintenanceScheduler {
+    control: MaintenanceControl,
+    monitor: Arc<ActivityMonitor>,
+    tasks: Vec<ScheduledTask>,
+}
+
+impl MaintenanceScheduler {
+    pub fn new(options: MaintenanceOptions, monitor: Arc<ActivityMonitor>) -> MaintenanceScheduler {
+        MaintenanceScheduler {
+            control: MaintenanceControl { options: Arc::new(Mutex::new(options)) },
+            monitor,
+            tasks: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, task: Box<dyn MaintenanceTask>) {
+        self.tasks.push(ScheduledTask { task, last_run: None });
+    }
+
+    pub fn control(&self) -> MaintenanceControl {
+        self.control.clone()
+    }
+
+    pub fn set_task_options(&mut self, name: &str, task_options: TaskOptions) {
+        self.control.set_task_options(name, task_options);
+    }
+
+    /// Runs every enabled task that is due and inside its window, provided the system is idle.
+    pub fn run_idle_tasks(&mut self, now: Instant, time_of_day: Duration, ops_per_sec: u64) -> Vec<TaskRun> {
+        let options = self.control.options();
+        if ops_per_sec > options.idle_ops_per_sec {
+            return Vec::new()
+        }
+
+        let mut runs = Vec::new();
+        for scheduled in self.tasks.iter_mut() {
+            let task_options = options.tasks
+                .get(scheduled.task.name())
+                .cloned()
+                .unwrap_or_default();
+            if !task_options.enabled {
+                continue
+            }
+            if !task_options.window.is_none_or(|w| w.contains(time_of_day)) {
+                continue
+            }
+            if scheduled.last_run.is_some_and(|last| now.saturating_duration_since(last) < task_options.interval) {
+                continue
+            }
+
+            let result = scheduled.task.run();
+            scheduled.last_run = Some(now);
+            runs.push(TaskRun { name: scheduled.task.name().to_string(), result });
+        }
+        runs
+    }
+
+    /// Moves the scheduler to a background thread that checks for idle time every `tick`.
+    pub fn start(mut self) -> MaintenanceHandle {
+        let control = self.control();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let worker = thread::spawn(move || {
+            while !thread_stopped.load(Ordering::Acquire) {
+                let now = Instant::now();
+                let ops_per_sec = self.monitor.sample_ops_per_sec(now);
+                self.run_idle_tasks(now, utc_time_of_day(), ops_per_sec);
+                thread::park_timeout(self.control.options().tick);
+            }
+            self
+        });
+
+        MaintenanceHandle { stopped, worker, control }
+    }
+}
+
+pub struct MaintenanceHandle {
+    stopped: Arc<AtomicBool>,
+    worker: JoinHandle<MaintenanceScheduler>,
+    control: MaintenanceControl,
+}
+
+impl MaintenanceHandle {
+    pub fn control(&self) -> MaintenanceControl {
+        self.control.clone()
+    }
+
+    /// Waits for the running task, if any, to finish and hands the scheduler back.
+    pub fn stop(self) -> MaintenanceScheduler {
+        self.stopped.store(true, Ordering::Release);
+        self.worker.thread().unpark();
+        self.worker.join().unwrap()
+    }
+}
+
+fn utc_time_of_day() -> Duration {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Duration::from_secs(since_epoch.as_secs() % SECONDS_PER_DAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::maintenance::activity::ActivityMonitor;
+    use crate::maintenance::scheduler::*;
+
+    struct CountingTask {
+        name: &'static str,
+        runs: Arc<AtomicUsize>,
+    }
+
+    impl MaintenanceTask for CountingTask {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn run(&mut self) -> io::Result<()> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn scheduler_with(names: &[&'static str]) -> (MaintenanceScheduler, Vec<Arc<AtomicUsize>>) {
+        let mut scheduler = MaintenanceScheduler::new(MaintenanceOptions::default(), Arc::new(ActivityMonitor::new()));
+        let counters: Vec<Arc<AtomicUsize>> = names.iter().map(|_| Arc::new(AtomicUsize::new(0))).collect();
+        for (name, runs) in names.iter().zip(counters.iter()) {
+            scheduler.register(Box::new(CountingTask { name, runs: runs.clone() }));
+        }
+        (scheduler, counters)
+    }
+
+    #[test]
+    fn should_run_enabled_tasks_only_when_idle() {
+        // given
+        let (mut scheduler, counters) = scheduler_with(&["vacuum", "stats"]);
+        scheduler.set_task_options("stats", TaskOptions { enabled: false, ..TaskOptions::default() });
+        let now = Instant::now();
+
+        // when
+        let busy = scheduler.run_idle_tasks(now, Duration::ZERO, 1000);
+        let idle = scheduler.run_idle_tasks(now, Duration::ZERO, 0);
+
+        // then
+        assert!(busy.is_empty());
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].name, "vacuum");
+        assert!(idle[0].result.is_ok());
+        assert_eq!(counters[0].load(Ordering::SeqCst), 1);
+        assert_eq!(counters[1].load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn should_not_rerun_task_before_interval_passed() {
+        // given
+        let (mut scheduler, counters) = scheduler_with(&["vacuum"]);
+        scheduler.set_task_options("vacuum", TaskOptions { interval: Duration::from_secs(10), ..TaskOptions::default() });
+        let now = Instant::now();
+
+        // when
+        scheduler.run_idle_tasks(now, Duration::ZERO, 0);
+        scheduler.run_idle_tasks(now + Duration::from_secs(5), Duration::ZERO, 0);
+        scheduler.run_idle_tasks(now + Duration::from_secs(10), Duration::ZERO, 0);
+
+        // then
+        assert_eq!(counters[0].load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn should_run_task_only_inside_its_window() {
+        // given
+        let hour = |h: u64| Duration::from_secs(h * 3600);
+        let (mut scheduler, counters) = scheduler_with(&["checkpoint"]);
+        let window = ScheduleWindow { start: hour(22), end: hour(4) };
+        scheduler.set_task_options("checkpoint", TaskOptions { interval: Duration::ZERO, window: Some(window), ..TaskOptions::default() });
+        let now = Instant::now();
+
+        // when
+        scheduler.run_idle_tasks(now, hour(12), 0);
+        scheduler.run_idle_tasks(now, hour(23), 0);
+        scheduler.run_idle_tasks(now, hour(1), 0);
+
+        // then
+        assert_eq!(counters[0].load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn should_run_tasks_in_background_until_stopped() {
+        // given
+        let (mut scheduler, counters) = scheduler_with(&["vacuum"]);
+        scheduler.set_task_options("vacuum", TaskOptions { interval: Duration::ZERO, ..TaskOptions::default() });
+        scheduler.control().set_tick(Duration::from_millis(5));
+
+        // when
+        let handle = scheduler.start();
+        while counters[0].load(Ordering::SeqCst) < 2 {
+            std::thread::yield_now();
+        }
+        let scheduler = handle.stop();
+
+        // then
+        let runs = counters[0].load(Ordering::SeqCst);
+        assert!(runs >= 2);
+        assert_eq!(scheduler.tasks.len(), 1);
+    }
+}
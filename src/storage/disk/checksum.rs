@@ -0,0 +1,109 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use fasthash::xx;
+
+use crate::storage::disk::disk_error::DiskError;
+use crate::storage::page::page::PageId;
+
+/// Appended to the data file path to name the file holding its page checksums.
+pub const CHECKSUM_FILE_SUFFIX: &str = ".sum";
+/// Stored for pages without a recorded checksum, e.g. written before checksums were enabled.
+const NO_CHECKSUM: u32 = 0;
+const CHECKSUM_SIZE: usize = mem::size_of::<u32>();
+
+pub fn checksum_path(file_path: &Path) -> PathBuf {
+    let mut path = OsString::from(file_path);
+    path.push(CHECKSUM_FILE_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Never `NO_CHECKSUM`, so every page written with checksums on gets verified.
+pub fn page_checksum(page_data: &[u8]) -> u32 {
+    xx::hash32(page_data).max(1)
+}
+
+/// Page checksums kept next to the data file, one `u32` per page id.
+///
+/// All of them are held in memory, so verifying a read costs no extra IO.
+pub struct ChecksumFile {
+    file: File,
+    checksums: Vec<u32>,
+}
+
+impl ChecksumFile {
+    pub fn open(file_path: &Path) -> Result<ChecksumFile> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(checksum_path(file_path))?;
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+        let checksums = raw.chunks_exact(CHECKSUM_SIZE)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+
+        Ok(ChecksumFile { file, checksums })
+    }
+
+    pub fn record(&mut self, pid: PageId, page_data: &[u8]) -> Result<()> {
+        let checksum = page_checksum(page_data);
+        if self.checksums.len() <= pid {
+            self.checksums.resize(pid + 1, NO_CHECKSUM);
+        }
+        self.checksums[pid] = checksum;
+
+        self.file.seek(SeekFrom::Start((pid * CHECKSUM_SIZE) as u64))?;
+        self.file.write_all(&checksum.to_le_bytes())
+    }
+
+    pub fn verify(&self, pid: PageId, page_data: &[u8]) -> Result<()> {
+        let expected = self.checksums.get(pid).copied().unwrap_or(NO_CHECKSUM);
+        if expected == NO_CHECKSUM {
+            return Ok(())
+        }
+
+        let actual = page_checksum(page_data);
+        if actual != expected {
+            return Err(DiskError::ChecksumMismatch { page_id: pid, expected, actual }.into())
+        }
+
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::disk::checksum::{checksum_path, ChecksumFile};
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::page::page::PAGE_SIZE;
+
+    #[test]
+    fn should_verify_checksums_recorded_before_reopen() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("minedb.data");
+        let mut checksums = ChecksumFile::open(&path).unwrap();
+        checksums.record(3, &[1; PAGE_SIZE]).unwrap();
+        drop(checksums);
+
+        // when
+        let checksums = ChecksumFile::open(&path).unwrap();
+
+        // then
+        assert!(checksum_path(&path).exists());
+        assert!(checksums.verify(3, &[1; PAGE_SIZE]).is_ok());
+        assert!(checksums.verify(2, &[9; PAGE_SIZE]).is_ok());
+        let err = checksums.verify(3, &[2; PAGE_SIZE]).unwrap_err();
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::ChecksumMismatch { page_id: 3, .. })));
+    }
+}
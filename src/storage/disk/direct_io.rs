@@ -0,0 +1,53 @@
+use std::fs::{File, OpenOptions};
+use std::io::Result;
+use std::path::Path;
+
+use crate::storage::page::page::PAGE_SIZE;
+
+/// Buffer, offset and length alignment direct IO needs on common file systems.
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// Page buffer that can be handed to direct IO as is.
+#[repr(C, align(4096))]
+pub struct AlignedPage(pub [u8; PAGE_SIZE]);
+
+impl AlignedPage {
+    pub fn new_boxed() -> Box<AlignedPage> {
+        Box::new(AlignedPage([0; PAGE_SIZE]))
+    }
+}
+
+pub fn is_aligned(data: &[u8]) -> bool {
+    data.as_ptr() as usize & (DIRECT_IO_ALIGNMENT - 1) == 0
+}
+
+/// Opens an existing file bypassing the OS page cache: `O_DIRECT` on Linux, `F_NOCACHE` on macOS.
+#[cfg(target_os = "linux")]
+pub fn open_direct(file_path: &Path) -> Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(file_path)
+}
+
+#[cfg(target_os = "macos")]
+pub fn open_direct(file_path: &Path) -> Result<File> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(file_path)?;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(std::io::Error::last_os_error())
+    }
+    Ok(file)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn open_direct(_file_path: &Path) -> Result<File> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Direct IO is not supported on this platform."))
+}
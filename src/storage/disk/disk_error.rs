@@ -0,0 +1,72 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::common::error::cause_of;
+use crate::storage::page::page::PageId;
+
+/// Failures of disk managers, carried inside `io::Error` so callers can match on them.
+#[derive(Debug)]
+pub enum DiskError {
+    InvalidPageId(PageId),
+    PageNotAllocated(PageId),
+    ReservedPage(PageId),
+    ExceededMaxPage,
+    /// Growing would take the database past its maximum size, both in bytes.
+    QuotaExceeded { used: u64, quota: u64 },
+    WrongPageDataSize { expected: usize, actual: usize },
+    /// The page read back differs from what was written, e.g. after bit rot or a torn write.
+    ChecksumMismatch { page_id: PageId, expected: u32, actual: u32 },
+    /// The page does not authenticate: wrong key, or the ciphertext was modified.
+    DecryptionFailed(PageId),
+    /// No disk manager backend is registered under this name.
+    UnknownBackend(String),
+    /// Another process holds the lock of this data file.
+    FileLocked(String),
+    /// The file has no superblock and its first page holds data, so it is not a minedb data file.
+    NotDatabaseFile(String),
+    /// Pages cannot be moved, as structures of the file refer to them by their ids.
+    PagesReferenced,
+}
+
+impl DiskError {
+    pub fn from_io(err: &io::Error) -> Option<&DiskError> {
+        cause_of(err)
+    }
+}
+
+impl Display for DiskError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskError::InvalidPageId(_) => write!(f, "Invalid page id."),
+            DiskError::PageNotAllocated(_) => write!(f, "Page id not allocate."),
+            DiskError::ReservedPage(pid) => write!(f, "Page {} is reserved.", pid),
+            DiskError::ExceededMaxPage => write!(f, "Exceeded max page."),
+            DiskError::QuotaExceeded { used, quota } =>
+                write!(f, "Exceeded database size quota: {} of {} bytes used.", used, quota),
+            DiskError::WrongPageDataSize { expected, actual } =>
+                write!(f, "Wrong page data: size {} not equal to {}", actual, expected),
+            DiskError::ChecksumMismatch { page_id, expected, actual } =>
+                write!(f, "Page {} is corrupted: checksum {:#010x} does not match recorded {:#010x}.", page_id, actual, expected),
+            DiskError::DecryptionFailed(pid) => write!(f, "Page {} cannot be decrypted.", pid),
+            DiskError::UnknownBackend(name) => write!(f, "No disk backend named {}.", name),
+            DiskError::NotDatabaseFile(path) => write!(f, "{} is not a minedb data file.", path),
+            DiskError::FileLocked(path) => write!(f, "Data file {} is locked by another process.", path),
+            DiskError::PagesReferenced => write!(f, "Pages cannot be moved while the catalog or a hash table refers to them."),
+        }
+    }
+}
+
+impl error::Error for DiskError {}
+
+impl From<DiskError> for io::Error {
+    fn from(e: DiskError) -> Self {
+        let kind = match e {
+            DiskError::ExceededMaxPage | DiskError::QuotaExceeded { .. } => io::ErrorKind::Other,
+            DiskError::ChecksumMismatch { .. } | DiskError::DecryptionFailed(_) | DiskError::NotDatabaseFile(_) =>
+                io::ErrorKind::InvalidData,
+            DiskError::FileLocked(_) => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
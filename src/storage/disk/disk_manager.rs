@@ -1,19 +1,148 @@
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::storage::page::allocation_map_page::{AllocationMapPage, ALLOCATION_MAP_CAPACITY};
 use crate::storage::page::page::{PageId, PAGE_SIZE};
-use std::io::{Result, Error, ErrorKind, Seek, Write, SeekFrom, Read};
+use crate::storage::page::page_serde::PageSerde;
+use crate::storage::page::page_version::{FormatVersion, LEGACY_FORMAT_VERSION};
+use crate::storage::page::superblock::{Superblock, ALLOCATION_MAP_OFFSET, SUPERBLOCK_PAGE_ID};
+use crate::storage::disk::checksum::ChecksumFile;
+use crate::storage::disk::double_write::DoubleWriteBuffer;
+use crate::storage::disk::direct_io::{is_aligned, open_direct, AlignedPage};
+use crate::storage::disk::disk_error::DiskError;
+use crate::storage::disk::disk_stats::{DiskMetrics, DiskOp};
+use crate::storage::disk::platform;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Result, Seek, Write, SeekFrom, Read};
 #[cfg(test)]
 use mockall::{automock, predicate::*};
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 
 #[cfg_attr(test, automock)]
-pub trait DiskManager {
+pub trait DiskManager: Send {
     fn allocate_page(&mut self) -> Result<PageId>;
 
+    /// Managers without a physical layout to optimize ignore the strategy.
+    fn allocate_page_with(&mut self, _strategy: AllocationStrategy) -> Result<PageId> {
+        self.allocate_page()
+    }
+
+    /// Allocates `n` pages with consecutive ids, so reading them in order reads one contiguous region.
+    /// By default pages are appended one by one, failing if another allocation lands in between.
+    fn allocate_extent(&mut self, n: usize) -> Result<Range<PageId>> {
+        if n == 0 {
+            return Ok(0..0)
+        }
+
+        let first_pid = self.allocate_page_with(AllocationStrategy::Append)?;
+        for expected_pid in first_pid + 1..first_pid + n {
+            let pid = self.allocate_page_with(AllocationStrategy::Append)?;
+            if pid != expected_pid {
+                return Err(Error::other(format!("Page {} breaks the extent of {} pages from page {}.", pid, n, first_pid)))
+            }
+        }
+        Ok(first_pid..first_pid + n)
+    }
+
     fn deallocate_page(&mut self, page_id: PageId) -> Result<bool> ;
 
     fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<()>;
 
     fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> Result<()>;
+
+    /// Writes the pages in order, stopping at the first failure. Managers may coalesce runs of
+    /// consecutive page ids into one IO, so callers should pass pages sorted by id.
+    ///
+    /// The lifetimes are spelled out for `automock`, which cannot mock nested elided ones.
+    #[allow(clippy::needless_lifetimes)]
+    fn write_pages<'a>(&mut self, pages: &[(PageId, &'a [u8])]) -> Result<()> {
+        for (pid, page_data) in pages {
+            self.write_page(*pid, page_data)?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    fn read_pages<'a>(&mut self, pages: &mut [(PageId, &'a mut [u8])]) -> Result<()> {
+        for (pid, page_data) in pages.iter_mut() {
+            self.read_page(*pid, page_data)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the pages one after another into `buf`, which holds exactly `page_ids.len()` pages.
+    /// Goes through `read_pages`, so a run of consecutive ids takes a single vectored read where the
+    /// manager coalesces runs.
+    fn read_pages_into(&mut self, page_ids: &[PageId], buf: &mut [u8]) -> Result<()> {
+        if buf.len() != page_ids.len() * PAGE_SIZE {
+            return Err(DiskError::WrongPageDataSize { expected: page_ids.len() * PAGE_SIZE, actual: buf.len() }.into())
+        }
+
+        let mut pages: Vec<(PageId, &mut [u8])> = page_ids.iter().copied().zip(buf.chunks_exact_mut(PAGE_SIZE)).collect();
+        self.read_pages(&mut pages)
+    }
+
+    /// Makes every page written so far durable, as far as the manager's durability mode allows.
+    fn sync(&mut self) -> Result<()>;
+
+    /// Moves allocated pages toward the start of the storage and releases the space freed behind them.
+    /// Managers that cannot move pages move none.
+    fn defragment(&mut self) -> Result<PageRelocations> {
+        Ok(PageRelocations::default())
+    }
+}
+
+/// Old to new ids of the pages a `defragment` moved. Whoever stores page ids has to follow them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageRelocations {
+    moved: BTreeMap<PageId, PageId>,
+}
+
+impl PageRelocations {
+    pub fn insert(&mut self, old_pid: PageId, new_pid: PageId) {
+        self.moved.insert(old_pid, new_pid);
+    }
+
+    /// Where the page is now, which is where it was if it did not move.
+    pub fn resolve(&self, pid: PageId) -> PageId {
+        self.moved.get(&pid).copied().unwrap_or(pid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.moved.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PageId, PageId)> + '_ {
+        self.moved.iter().map(|(old_pid, new_pid)| (*old_pid, *new_pid))
+    }
+}
+
+/// Where `allocate_page_with` places the new page.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AllocationStrategy {
+    /// Lowest free page id.
+    FirstFit,
+    /// Page after the highest allocated one, never reusing holes.
+    Append,
+    /// Free page closest to the hint, preferring the one after it on a tie.
+    Near(PageId),
+}
+
+/// When `FileDiskManager` asks the OS to put written pages on stable storage.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Sync after every page write.
+    Always,
+    /// Sync only when `sync` is called, e.g. by `BufferPoolManager::flush_page`.
+    OnFlush,
+    /// Never sync, leaving it to the OS. Pages may be lost on crash.
+    Never,
 }
 
 const MAX_FILE_PAGES: usize = 0x1 << 16;
@@ -33,8 +162,8 @@ impl FakeDiskManager {
 
 impl DiskManager for FakeDiskManager {
     fn allocate_page(&mut self) -> Result<PageId> {
-        if self.page_counter > MAX_FILE_PAGES {
-            return Err(Error::new(ErrorKind::Other, "Exceeded max page."))
+        if self.page_counter >= MAX_FILE_PAGES {
+            return Err(DiskError::ExceededMaxPage.into())
         }
 
         let page_id_to_returned = self.page_counter;
@@ -47,290 +176,1022 @@ impl DiskManager for FakeDiskManager {
     }
 
     fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<()> {
-        if page_id > MAX_FILE_PAGES {
-            panic!("Illegal page id.")
-        }
-
-        for i in 0..PAGE_SIZE {
-            self.fake_file[i + page_id * PAGE_SIZE] = page_data[i];
-        }
+        validate_page_id(page_id)?;
+        validate_page_data_size(page_data.len())?;
 
+        let offset = page_id * PAGE_SIZE;
+        self.fake_file[offset..offset + PAGE_SIZE].copy_from_slice(page_data);
         Ok(())
     }
 
     fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> Result<()> {
-        if page_id > MAX_FILE_PAGES {
-            panic!("Illegal page id.")
+        validate_page_id(page_id)?;
+        validate_page_data_size(page_data.len())?;
+
+        let offset = page_id * PAGE_SIZE;
+        page_data.copy_from_slice(&self.fake_file[offset..offset + PAGE_SIZE]);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn validate_page_id(pid: PageId) -> Result<()> {
+    if pid >= MAX_FILE_PAGES {
+        return Err(DiskError::InvalidPageId(pid).into())
+    }
+
+    Ok(())
+}
+
+fn round_up_to_byte(num_pages: usize) -> usize {
+    num_pages.div_ceil(8).max(1) << 3
+}
+
+/// Length of the leading run of pages whose ids follow each other.
+fn consecutive_run_len<T>(pages: &[(PageId, T)]) -> usize {
+    pages.windows(2)
+        .position(|pair| pair[0].0.checked_add(1) != Some(pair[1].0))
+        .map_or(pages.len(), |last| last + 1)
+}
+
+fn consecutive_runs<T>(mut pages: &[(PageId, T)]) -> impl Iterator<Item = &[(PageId, T)]> {
+    std::iter::from_fn(move || {
+        if pages.is_empty() {
+            return None
         }
+        let (run, rest) = pages.split_at(consecutive_run_len(pages));
+        pages = rest;
+        Some(run)
+    })
+}
 
-        for i in 0..PAGE_SIZE {
-            page_data[i] = self.fake_file[i + page_id * PAGE_SIZE];
+// Superblock page holding the superblock, then as much of the allocation bitmap as fits.
+fn superblock_page(superblock: &Superblock, page_table: &[u8]) -> [u8; PAGE_SIZE] {
+    let mut page_data = [0u8; PAGE_SIZE];
+    let superblock_raw = superblock.serialize();
+    page_data[0..superblock_raw.len()].copy_from_slice(&superblock_raw);
+    let len = page_table.len().min(SUPERBLOCK_ALLOCATION_MAP_BYTES);
+    page_data[ALLOCATION_MAP_OFFSET..ALLOCATION_MAP_OFFSET + len].copy_from_slice(&page_table[..len]);
+    page_data
+}
+
+// Part of the allocation bitmap holding the slot: 0 for the superblock page, else one past the index
+// of the map page in the chain.
+fn allocation_map_part_of(slot: usize) -> usize {
+    match (slot / 8).checked_sub(SUPERBLOCK_ALLOCATION_MAP_BYTES) {
+        Some(byte) => 1 + byte / ALLOCATION_MAP_CAPACITY,
+        None => 0,
+    }
+}
+
+fn validate_page_data_size(size: usize) -> Result<()> {
+    if size != PAGE_SIZE {
+        return Err(DiskError::WrongPageDataSize { expected: PAGE_SIZE, actual: size }.into())
+    }
+
+    Ok(())
+}
+
+/// Pages the data file grows by once every page is allocated.
+pub const EXTENT_PAGES: usize = 1024;
+/// Bytes of the allocation bitmap the superblock page holds.
+const SUPERBLOCK_ALLOCATION_MAP_BYTES: usize = PAGE_SIZE - ALLOCATION_MAP_OFFSET;
+/// Default growth limit of a data file: 4 GiB.
+pub const DEFAULT_MAX_FILE_PAGES: usize = 0x1 << 20;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileDiskOptions {
+    pub sync_mode: SyncMode,
+    /// The file does not grow beyond this many pages, allocating past it fails with
+    /// `DiskError::QuotaExceeded`. Existing larger files still open.
+    pub max_pages: usize,
+    /// Bypass the OS page cache, which only duplicates what the buffer pool caches.
+    pub direct_io: bool,
+    /// Keep a checksum of every page written in a file next to the data file and verify it on read.
+    pub checksums: bool,
+    /// Stage every batch of pages in a file next to the data file before writing them in place,
+    /// so a crash cannot leave a torn page. Each write is then synced twice, whatever the sync mode.
+    pub double_write: bool,
+    /// Open a file without superblock whose first page holds data in the legacy format, instead of
+    /// refusing it as not a database file. A file whose first page is blank always opens as legacy.
+    pub legacy_format: bool,
+}
+
+impl Default for FileDiskOptions {
+    fn default() -> Self {
+        FileDiskOptions {
+            sync_mode: SyncMode::OnFlush,
+            max_pages: DEFAULT_MAX_FILE_PAGES,
+            direct_io: false,
+            checksums: true,
+            double_write: false,
+            legacy_format: false,
         }
+    }
+}
 
-        Ok(())
+impl FileDiskOptions {
+    /// Sets `max_pages` to the whole bitmap bytes of pages fitting in `max_size` bytes.
+    pub fn with_max_size(self, max_size: u64) -> FileDiskOptions {
+        let max_pages = (max_size / PAGE_SIZE as u64) as usize & !0x7;
+        FileDiskOptions { max_pages, ..self }
     }
 }
 
+/// Holds an advisory lock on its data file while open, so only one process uses it at a time.
+///
+/// Which pages are allocated is kept in a bitmap, written along with every allocation and
+/// deallocation to the superblock page and, once the file outgrows what fits there, to a chain of
+/// `AllocationMapPage`s, so it is read back as it was when the file is opened again.
 pub struct FileDiskManager {
     page_counter: PageId,
-    page_table: [u8; MAX_FILE_PAGES >> 3],
-    file: File
+    page_table: Vec<u8>,
+    // chain of map pages holding the bitmap past the superblock page
+    allocation_map_pages: Vec<PageId>,
+    max_pages: usize,
+    // `None` in the legacy format
+    superblock: Option<Superblock>,
+    sync_mode: SyncMode,
+    file: File,
+    // direct IO stages unaligned caller buffers here
+    bounce: Option<Box<AlignedPage>>,
+    checksums: Option<ChecksumFile>,
+    double_write: Option<DoubleWriteBuffer>,
+    metrics: Option<Arc<DiskMetrics>>
 }
 
 impl FileDiskManager {
-    pub fn new(file_path: &Path) -> FileDiskManager {
+    /// A new file starts with a superblock in page 0. An existing file without one is opened in the legacy format.
+    pub fn new(file_path: &Path) -> Result<FileDiskManager> {
+        FileDiskManager::new_with_options(file_path, FileDiskOptions::default())
+    }
+
+    /// The database never grows beyond `max_size` bytes.
+    pub fn with_quota(file_path: &Path, max_size: u64) -> Result<FileDiskManager> {
+        FileDiskManager::new_with_options(file_path, FileDiskOptions::default().with_max_size(max_size))
+    }
+
+    pub fn new_with_options(file_path: &Path, options: FileDiskOptions) -> Result<FileDiskManager> {
+        let max_pages = round_up_to_byte(options.max_pages);
         if !file_path.exists() {
             let mut new_file = OpenOptions::new()
                 .create_new(true)
                 .read(true)
                 .write(true)
-                .open(file_path)
-                .unwrap();
-            let empty_data = [0 as u8; PAGE_SIZE];
-            for _i in 0..MAX_FILE_PAGES {
-                new_file.write_all(&empty_data).unwrap()
-            }
-            new_file.flush().unwrap();
+                .open(file_path)?;
+            let num_pages = EXTENT_PAGES.min(max_pages);
+            let mut superblock = Superblock::new();
+            superblock.set_allocation_map_pages(num_pages);
+            // only the superblock page itself is allocated
+            new_file.write_all(&superblock_page(&superblock, &[0x1]))?;
+            // sparse where the file system supports it; pages are zeroed when allocated
+            new_file.set_len((PAGE_SIZE * num_pages) as u64)?;
+            new_file.sync_all()?;
         }
 
-        FileDiskManager {
-            page_counter: 0,
-            page_table: [0; MAX_FILE_PAGES >> 3],
-            file: OpenOptions::new()
+        let file = match options.direct_io {
+            true => open_direct(file_path)?,
+            false => OpenOptions::new()
                 .read(true)
                 .write(true)
-                .open(file_path)
-                .unwrap()
-        }
-    }
+                .open(file_path)?
+        };
 
-    fn get_free_slot(&self) -> Option<usize> {
-        let curr_slot = self.page_counter;
-        let mut curr_byte = curr_slot >> 3;
-        for _i in 0..self.page_table.len() {
-            if self.page_table[curr_byte] != 0xff {
-                break;
-            }
+        platform::lock_exclusive(&file, file_path)?;
 
-            curr_byte = (curr_byte + 1) & (self.page_table.len() - 1);
+        // the bitmap works in whole bytes, so the file always holds a multiple of 8 pages
+        let file_len = platform::file_size(&file)? as usize;
+        let num_pages = round_up_to_byte(file_len.div_ceil(PAGE_SIZE));
+        if num_pages * PAGE_SIZE > file_len {
+            file.set_len((num_pages * PAGE_SIZE) as u64)?;
         }
 
-        let free_byte = self.page_table[curr_byte] as u16;
-        let slot: usize = match !free_byte & (free_byte + 1) {
-            0x1 => 0,
-            0x2 => 1,
-            0x4 => 2,
-            0x8 => 3,
-            0x10 => 4,
-            0x20 => 5,
-            0x40 => 6,
-            0x80 => 7,
-            _ => {return None}
+        let mut fdm = FileDiskManager {
+            page_counter: 0,
+            page_table: vec![0; num_pages >> 3],
+            allocation_map_pages: Vec::new(),
+            max_pages,
+            superblock: None,
+            sync_mode: options.sync_mode,
+            file,
+            bounce: if options.direct_io { Some(AlignedPage::new_boxed()) } else { None },
+            checksums: if options.checksums { Some(ChecksumFile::open(file_path)?) } else { None },
+            double_write: if options.double_write { Some(DoubleWriteBuffer::open(file_path)?) } else { None },
+            metrics: None
         };
-        Some(curr_byte * 8 + slot as usize)
-    }
+        fdm.restore_staged_pages()?;
+        let mut superblock_data = [0 as u8; PAGE_SIZE];
+        fdm.read_at(SUPERBLOCK_PAGE_ID, &mut superblock_data)?;
+        match Superblock::deserialize(&superblock_data)? {
+            Some(superblock) => {
+                let allocation_map_pages = superblock.get_allocation_map_pages();
+                fdm.superblock = Some(superblock);
+                fdm.set_slot();
+                match allocation_map_pages {
+                    0 => fdm.recover_allocations()?,
+                    _ => fdm.read_allocation_map(&superblock_data)?,
+                }
+                if allocation_map_pages != fdm.num_pages() {
+                    fdm.persist_allocation_map()?;
+                }
+            },
+            None if options.legacy_format || superblock_data.iter().all(|b| *b == 0) => {},
+            None => return Err(DiskError::NotDatabaseFile(file_path.display().to_string()).into())
+        }
 
-    fn set_slot(&mut self) {
-        let slot_byte = self.page_counter / 8;
-        let slot_bit = self.page_counter % 8;
-        self.page_table[slot_byte] |= 0x1 << slot_bit;
+        Ok(fdm)
     }
 
-    fn clear_slot(&mut self, slot: usize) {
-        let slot_byte = slot / 8;
-        let slot_bit = slot % 8;
-        self.page_table[slot_byte] &= !(0x1 << slot_bit);
+    /// `None` for a file in the legacy format.
+    pub fn superblock(&self) -> Option<&Superblock> {
+        self.superblock.as_ref()
     }
 
-    fn validate_page_id(&self, pid: PageId) -> Result<()> {
-        if pid >= MAX_FILE_PAGES {
-            return Err(Error::new(ErrorKind::Other, "Invalid page id."))
-        }
+    /// Changes the superblock, e.g. to point it to a new root page, and makes it durable.
+    pub fn update_superblock<F: FnOnce(&mut Superblock)>(&mut self, update: F) -> Result<()> {
+        let mut superblock = match self.superblock.take() {
+            Some(superblock) => superblock,
+            None => return Err(Error::new(ErrorKind::Unsupported, "Legacy format files have no superblock."))
+        };
+        update(&mut superblock);
 
-        Ok(())
+        let superblock_data = superblock_page(&superblock, &self.page_table);
+        self.superblock = Some(superblock);
+        self.write_at(SUPERBLOCK_PAGE_ID, &superblock_data)
+            .and_then(|_| self.record_checksum(SUPERBLOCK_PAGE_ID, &superblock_data))
+            .and_then(|_| self.sync_files())
+            .with_context(|| ErrorContext::new("update_superblock").page(SUPERBLOCK_PAGE_ID))
     }
 
-    fn validate_allocation(&self, pid: PageId) -> Result<()> {
-        let slot_byte = pid / 8;
-        let slot_bit = pid % 8;
-        if (self.page_table[slot_byte] >> slot_bit) & 0x1 != 0x1 {
-            return Err(Error::new(ErrorKind::Other, "Page id not allocate."))
+    /// Reads, writes, allocations and every fsync, including the ones the sync mode does per write,
+    /// are recorded from then on.
+    pub fn set_metrics(&mut self, metrics: Arc<DiskMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    fn record_metrics(&self, op: DiskOp, pages: usize, start: Instant) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_since(op, pages, start);
         }
+    }
 
-        Ok(())
+    pub fn is_direct_io(&self) -> bool {
+        self.bounce.is_some()
     }
-}
 
-impl DiskManager for FileDiskManager {
-    fn allocate_page(&mut self) -> Result<usize> {
-        match self.get_free_slot() {
-            Some(free_slot) => {
-                self.page_counter = free_slot;
-                self.set_slot();
-                Ok(free_slot)
-            }
-            None => return Err(Error::new(ErrorKind::Other, "Exceeded max page."))
+    fn write_at(&mut self, pid: PageId, page_data: &[u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start((pid * PAGE_SIZE) as u64))?;
+        match &mut self.bounce {
+            Some(bounce) if !is_aligned(page_data) => {
+                bounce.0.copy_from_slice(page_data);
+                self.file.write_all(&bounce.0)
+            },
+            _ => self.file.write_all(page_data)
         }
     }
 
-    fn deallocate_page(&mut self, page_id: usize) -> Result<bool> {
-        self.validate_page_id(page_id)?;
-        self.clear_slot(page_id);
-        Ok(true)
+    fn read_at(&mut self, pid: PageId, page_data: &mut [u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start((pid * PAGE_SIZE) as u64))?;
+        match &mut self.bounce {
+            Some(bounce) if !is_aligned(page_data) => {
+                self.file.read_exact(&mut bounce.0)?;
+                page_data.copy_from_slice(&bounce.0);
+                Ok(())
+            },
+            _ => self.file.read_exact(page_data)
+        }
     }
 
-    fn write_page(&mut self, page_id: usize, page_data: &[u8]) -> Result<()> {
-        self.validate_page_id(page_id)?;
-        self.validate_allocation(page_id)?;
+    pub fn has_double_write(&self) -> bool {
+        self.double_write.is_some()
+    }
 
-        self.file.seek(SeekFrom::Start((page_id * PAGE_SIZE) as u64)).unwrap();
-        self.file.write_all(page_data)
+    // Writes staged pages in place again, as a crash may have torn them while they were written.
+    fn restore_staged_pages(&mut self) -> Result<()> {
+        let staged = match &mut self.double_write {
+            Some(double_write) => double_write.staged_pages()?,
+            None => return Ok(())
+        };
+        for (pid, page_data) in &staged {
+            self.write_at(*pid, page_data)
+                .and_then(|_| self.record_checksum(*pid, page_data))
+                .with_context(|| ErrorContext::new("restore_staged_page").page(*pid))?;
+        }
+        self.finish_double_write()
     }
 
-    fn read_page(&mut self, page_id: usize, page_data: &mut [u8]) -> Result<()> {
-        self.validate_page_id(page_id)?;
-        self.validate_allocation(page_id)?;
+    // Once in place, the staged pages have to be durable before the staged copies are dropped.
+    fn finish_double_write(&mut self) -> Result<()> {
+        if self.double_write.is_none() {
+            return Ok(())
+        }
+        self.sync_files()?;
+        match &mut self.double_write {
+            Some(double_write) => double_write.clear(),
+            None => Ok(())
+        }
+    }
 
-        self.file.seek(SeekFrom::Start((page_id * PAGE_SIZE) as u64)).unwrap();
-        self.file.read_exact(page_data)
+    pub fn has_checksums(&self) -> bool {
+        self.checksums.is_some()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::storage::disk::disk_manager::{DiskManager, FakeDiskManager, FileDiskManager, MAX_FILE_PAGES};
-    use crate::storage::page::page::*;
-    use std::fs::remove_file;
-    use std::path::Path;
-    use rand::Rng;
-    use std::os::macos::fs::MetadataExt;
+    /// Shared with disk managers doing their own IO on this file, like the validations.
+    pub(crate) fn record_checksum(&mut self, pid: PageId, page_data: &[u8]) -> Result<()> {
+        match &mut self.checksums {
+            Some(checksums) => checksums.record(pid, page_data),
+            None => Ok(())
+        }
+    }
 
-    #[test]
-    fn test_fake_disk_manager_can_allocate_page_id() {
-        let mut fake_disk_manager = FakeDiskManager::new();
-        let page_id_1 = fake_disk_manager.allocate_page().unwrap();
-        let page_id_2 = fake_disk_manager.allocate_page().unwrap();
-        let page_id_3 = fake_disk_manager.allocate_page().unwrap();
+    pub(crate) fn verify_checksum(&self, pid: PageId, page_data: &[u8]) -> Result<()> {
+        match &self.checksums {
+            Some(checksums) => checksums.verify(pid, page_data),
+            None => Ok(())
+        }
+    }
 
-        assert_eq!(page_id_1, 0);
-        assert_eq!(page_id_2, 1);
-        assert_eq!(page_id_3, 2);
+    /// Checksums are synced along with the pages they cover.
+    pub(crate) fn sync_files(&self) -> Result<()> {
+        let start = Instant::now();
+        self.file.sync_data()?;
+        if let Some(checksums) = &self.checksums {
+            checksums.sync()?;
+        }
+        self.record_metrics(DiskOp::Sync, 0, start);
+        Ok(())
     }
 
-    #[test]
-    fn test_fake_disk_manager_can_write_page_to_fake_disk() {
-        // given
-        let mut fake_disk_manager = FakeDiskManager::new();
-        let page_id_1 = fake_disk_manager.allocate_page().unwrap();
-        let page_id_2 = fake_disk_manager.allocate_page().unwrap();
+    // Writes pages with consecutive ids starting at `first_pid` in one vectored write.
+    fn write_run(&mut self, first_pid: PageId, run: &[(PageId, &[u8])]) -> Result<()> {
+        let mut slices: Vec<IoSlice> = run.iter().map(|(_, page_data)| IoSlice::new(page_data)).collect();
+        let mut slices = &mut slices[..];
+        self.file.seek(SeekFrom::Start((first_pid * PAGE_SIZE) as u64))?;
+        while !slices.is_empty() {
+            match self.file.write_vectored(slices) {
+                Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "Failed to write whole pages.")),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    }
 
-        let mut page = Page::new(page_id_2);
-        let page_data = page.get_data_mut();
-        for i in 0..10 {
-            page_data[i] = (0x00 + i) as u8
+    fn read_run(&mut self, first_pid: PageId, run: &mut [(PageId, &mut [u8])]) -> Result<()> {
+        let mut slices: Vec<IoSliceMut> = run.iter_mut().map(|(_, page_data)| IoSliceMut::new(page_data)).collect();
+        let mut slices = &mut slices[..];
+        self.file.seek(SeekFrom::Start((first_pid * PAGE_SIZE) as u64))?;
+        while !slices.is_empty() {
+            match self.file.read_vectored(slices) {
+                Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, "Failed to read whole pages.")),
+                Ok(n) => IoSliceMut::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
         }
+        Ok(())
+    }
 
-        // when
-        fake_disk_manager.write_page(page_id_2, page_data).unwrap();
+    pub fn num_pages(&self) -> usize {
+        self.page_table.len() << 3
+    }
 
-        // then
-        let mut data_written: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
-        fake_disk_manager.read_page(page_id_1, &mut data_written).unwrap();
-        assert_eq!(data_written[0], 0x00);
-        assert_eq!(data_written[5], 0x00);
-        assert_eq!(data_written[9], 0x00);
+    pub fn max_pages(&self) -> usize {
+        self.max_pages
+    }
 
-        fake_disk_manager.read_page(page_id_2, &mut data_written).unwrap();
-        assert_eq!(data_written[0], 0x00);
-        assert_eq!(data_written[5], 0x05);
-        assert_eq!(data_written[9], 0x09);
+    /// Maximum size of the data file in bytes.
+    pub fn quota(&self) -> u64 {
+        (self.max_pages * PAGE_SIZE) as u64
     }
 
-    const TEST_FILE_PATH: &str = "./test_storage";
-    #[test]
-    fn should_create_and_init_file_if_not_exists() {
-        let path = TEST_FILE_PATH.to_string() + "1";
-        remove_file(path.as_str()).unwrap_or(());
+    // Extends the file and the bitmap by one extent, returning the first new page left free, as the
+    // bitmap may take one for a map page.
+    fn grow(&mut self) -> Result<PageId> {
+        let num_pages = self.num_pages();
+        if num_pages >= self.max_pages {
+            return Err(DiskError::QuotaExceeded { used: (num_pages * PAGE_SIZE) as u64, quota: self.quota() }.into())
+        }
 
-        FileDiskManager::new(Path::new(path.as_str()));
+        let new_num_pages = (num_pages + EXTENT_PAGES).min(self.max_pages);
+        platform::preallocate(&self.file, (new_num_pages * PAGE_SIZE) as u64)
+            .with_context(|| ErrorContext::new("grow_file").page(num_pages))?;
+        self.page_table.resize(new_num_pages >> 3, 0);
+        self.persist_allocation_map()?;
+        (num_pages..new_num_pages)
+            .find(|slot| self.is_slot_free(*slot))
+            .ok_or_else(|| DiskError::QuotaExceeded { used: (new_num_pages * PAGE_SIZE) as u64, quota: self.quota() }.into())
+    }
 
-        let file_path = Path::new(path.as_str());
-        assert!(file_path.exists());
-        assert!(file_path.is_file());
-        assert_eq!(file_path.file_name().unwrap(), "test_storage1");
+    fn validate_page_id(&self, pid: PageId) -> Result<()> {
+        if pid >= self.num_pages() {
+            return Err(DiskError::InvalidPageId(pid).into())
+        }
 
-        let metadata = file_path.metadata().unwrap();
-        assert_eq!(metadata.st_size(), (PAGE_SIZE * MAX_FILE_PAGES) as u64);
+        Ok(())
+    }
 
-        remove_file(path.as_str()).unwrap();
+    pub fn format_version(&self) -> FormatVersion {
+        self.superblock.as_ref().map_or(LEGACY_FORMAT_VERSION, Superblock::get_format_version)
     }
 
-    #[test]
-    fn should_allocate_and_deallocate_page() {
-        let path = TEST_FILE_PATH.to_string() + "2";
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
 
-        // setup
-        let mut fdm = FileDiskManager::new(Path::new(path.as_str()));
+    fn validate_not_reserved(&self, pid: PageId) -> Result<()> {
+        if self.superblock.is_some() && (pid == SUPERBLOCK_PAGE_ID || self.allocation_map_pages.contains(&pid)) {
+            return Err(DiskError::ReservedPage(pid).into())
+        }
 
-        // first page id should be 0
-        let pid1 = fdm.allocate_page().unwrap();
-        assert_eq!(pid1, 0);
+        Ok(())
+    }
 
-        // fully allocate page to maximum
-        for _i in 0..fdm.page_table.len()*8 - 1 {
-            fdm.allocate_page().unwrap();
-        }
-        assert!(fdm.page_table.iter().all(|b| *b == 0xff));
+    fn get_free_slot(&self) -> Option<usize> {
+        let num_bytes = self.page_table.len();
+        let start_byte = self.page_counter >> 3;
+        (0..num_bytes)
+            .map(|i| (start_byte + i) % num_bytes)
+            .find(|byte| self.page_table[*byte] != 0xff)
+            .map(|byte| byte * 8 + self.page_table[byte].trailing_ones() as usize)
+    }
 
-        // should return maximum exceeded err
-        let should_err = fdm.allocate_page();
-        assert!(should_err.is_err());
-        assert_eq!(should_err.err().unwrap().to_string(), "Exceeded max page.");
+    // Files written before the allocation bitmap was kept zeroed a page when allocating and
+    // deallocating it, while a written page has at least its version byte set (or is ciphertext when
+    // encrypted above), so in those a page is allocated iff it is not all zeroes.
+    fn recover_allocations(&mut self) -> Result<()> {
+        let mut page_data = [0u8; PAGE_SIZE];
+        for slot in SUPERBLOCK_PAGE_ID + 1..self.num_pages() {
+            self.read_at(slot, &mut page_data)
+                .with_context(|| ErrorContext::new("recover_allocations").page(slot))?;
+            if page_data.iter().any(|byte| *byte != 0) {
+                self.page_counter = slot;
+                self.set_slot();
+            }
+        }
+        self.page_counter = SUPERBLOCK_PAGE_ID;
+        Ok(())
+    }
 
-        // should fail when deallocate invalid page id
-        let should_err = fdm.deallocate_page(usize::MAX);
-        assert!(should_err.is_err());
-        assert_eq!(should_err.err().unwrap().to_string(), "Invalid page id.");
+    fn release_slot(&mut self, slot: usize) -> Result<()> {
+        self.clear_slot(slot);
+        self.write_allocation_map(slot..slot + 1)
+    }
 
-        // random deallocate pages
-        let mut rng = rand::thread_rng();
-        let mut expected_page_ids: [usize; 5] = [0; 5];
-        for i in 0..expected_page_ids.len() {
-            expected_page_ids[i] = rng.gen_range(0..fdm.page_table.len());
-            fdm.deallocate_page(expected_page_ids[i]).unwrap();
+    // Reads the bitmap from the superblock page and the map pages chained from its root. Map pages
+    // past the end of the file, left from before it was truncated, are not read.
+    fn read_allocation_map(&mut self, superblock_data: &[u8]) -> Result<()> {
+        let len = self.page_table.len().min(SUPERBLOCK_ALLOCATION_MAP_BYTES);
+        self.page_table[..len].copy_from_slice(&superblock_data[ALLOCATION_MAP_OFFSET..ALLOCATION_MAP_OFFSET + len]);
 
-            let byte_index = expected_page_ids[i] >> 3;
-            let slot = expected_page_ids[i] - (byte_index << 3);
-            assert_eq!(fdm.page_table[byte_index] & 0x1 << slot, 0x0);
+        let mut next_page_id = self.superblock.as_ref().and_then(Superblock::get_allocation_map_root);
+        let mut page_data = [0u8; PAGE_SIZE];
+        while let Some(pid) = next_page_id {
+            let start = SUPERBLOCK_ALLOCATION_MAP_BYTES + self.allocation_map_pages.len() * ALLOCATION_MAP_CAPACITY;
+            let map_page = self.read_at(pid, &mut page_data)
+                .and_then(|_| self.verify_checksum(pid, &page_data))
+                .and_then(|_| AllocationMapPage::deserialize(&page_data))
+                .with_context(|| ErrorContext::new("read_allocation_map").page(pid))?;
+            if start < self.page_table.len() {
+                let len = (self.page_table.len() - start).min(ALLOCATION_MAP_CAPACITY);
+                self.page_table[start..start + len].copy_from_slice(&map_page.get_bitmap()[..len]);
+            }
+            self.allocation_map_pages.push(pid);
+            next_page_id = map_page.get_next_page_id();
         }
+        Ok(())
+    }
 
-        // re-allocate
-        let mut real_allocate_page_ids = [0 as usize; 5];
-        for i in 0..real_allocate_page_ids.len() {
-            real_allocate_page_ids[i] = fdm.allocate_page().unwrap();
+    // Chains map pages, taking the lowest free pages, until the bitmap covers the whole file, then
+    // writes all of it along with the number of pages it covers.
+    fn persist_allocation_map(&mut self) -> Result<()> {
+        if self.superblock.is_none() {
+            return Ok(())
         }
-        assert_eq!(expected_page_ids.sort(), real_allocate_page_ids.sort());
 
-        remove_file(path.as_str()).unwrap();
+        while SUPERBLOCK_ALLOCATION_MAP_BYTES + self.allocation_map_pages.len() * ALLOCATION_MAP_CAPACITY < self.page_table.len() {
+            let slot = self.find_free_slot(AllocationStrategy::FirstFit)
+                .ok_or(DiskError::ExceededMaxPage)?;
+            self.page_counter = slot;
+            self.set_slot();
+            if self.allocation_map_pages.is_empty() {
+                if let Some(superblock) = &mut self.superblock {
+                    superblock.set_allocation_map_root(Some(slot));
+                }
+            }
+            self.allocation_map_pages.push(slot);
+        }
+        self.write_allocation_map(SUPERBLOCK_ALLOCATION_MAP_BYTES * 8..self.num_pages())?;
+        let num_pages = self.num_pages();
+        self.update_superblock(|superblock| superblock.set_allocation_map_pages(num_pages))
     }
 
-    #[test]
-    fn should_write_page_data_then_read_it_out() {
-        let path = TEST_FILE_PATH.to_string() + "3";
-
-        // given
-        let mut rng = rand::thread_rng();
-        let mut data = [0 as u8; PAGE_SIZE];
-        for i in 0..PAGE_SIZE {
-            data[i] = rng.gen();
+    // Writes the parts of the bitmap holding the slots, the superblock page for the first ones. Files
+    // in the legacy format keep no bitmap.
+    fn write_allocation_map(&mut self, slots: Range<usize>) -> Result<()> {
+        if self.superblock.is_none() || slots.is_empty() {
+            return Ok(())
         }
-        let mut fdm = FileDiskManager::new(Path::new(path.as_str()));
-        let mut pid: PageId = EMPTY_PAGE.get_id();
-        for _i in 0..rng.gen_range(0..MAX_FILE_PAGES - 1) + 1 {
-            pid = fdm.allocate_page().unwrap()
+
+        let last_part = allocation_map_part_of(slots.end - 1).min(self.allocation_map_pages.len());
+        for part in allocation_map_part_of(slots.start)..=last_part {
+            let (pid, page_data) = match part {
+                0 => (SUPERBLOCK_PAGE_ID, superblock_page(self.superblock.as_ref().unwrap(), &self.page_table)),
+                _ => {
+                    let start = SUPERBLOCK_ALLOCATION_MAP_BYTES + (part - 1) * ALLOCATION_MAP_CAPACITY;
+                    let map_page = AllocationMapPage::new(self.page_table.get(start..).unwrap_or(&[]), self.allocation_map_pages.get(part).copied());
+                    let mut page_data = [0u8; PAGE_SIZE];
+                    map_page.to_page(&mut page_data)?;
+                    (self.allocation_map_pages[part - 1], page_data)
+                }
+            };
+            self.write_at(pid, &page_data)
+                .and_then(|_| self.record_checksum(pid, &page_data))
+                .with_context(|| ErrorContext::new("write_allocation_map").page(pid))?;
         }
+        Ok(())
+    }
 
-        // when
+    fn set_slot(&mut self) {
+        let slot_byte = self.page_counter / 8;
+        let slot_bit = self.page_counter % 8;
+        self.page_table[slot_byte] |= 0x1 << slot_bit;
+    }
+
+    fn is_slot_free(&self, slot: usize) -> bool {
+        (self.page_table[slot / 8] >> (slot % 8)) & 0x1 == 0
+    }
+
+    fn find_free_slot(&self, strategy: AllocationStrategy) -> Option<usize> {
+        let num_pages = self.num_pages();
+        match strategy {
+            AllocationStrategy::FirstFit => self.page_table.iter()
+                .position(|byte| *byte != 0xff)
+                .map(|byte| byte * 8 + self.page_table[byte].trailing_ones() as usize),
+            AllocationStrategy::Append => {
+                let next = self.page_table.iter()
+                    .rposition(|byte| *byte != 0)
+                    .map_or(0, |byte| byte * 8 + 8 - self.page_table[byte].leading_zeros() as usize);
+                if next < num_pages { Some(next) } else { None }
+            },
+            AllocationStrategy::Near(hint) => {
+                let hint = hint.min(num_pages - 1);
+                (0..num_pages).find_map(|distance| {
+                    hint.checked_add(distance).into_iter()
+                        .chain(hint.checked_sub(distance))
+                        .find(|slot| *slot < num_pages && self.is_slot_free(*slot))
+                })
+            }
+        }
+    }
+
+    fn find_free_run(&self, n: usize) -> Option<usize> {
+        let mut run_len = 0;
+        for slot in 0..self.num_pages() {
+            run_len = if self.is_slot_free(slot) { run_len + 1 } else { 0 };
+            if run_len == n {
+                return Some(slot + 1 - n)
+            }
+        }
+        None
+    }
+
+    fn take_slot(&mut self, slot: usize) -> Result<PageId> {
+        Ok(self.take_slots(slot..slot + 1)?.start)
+    }
+
+    // Zeroes the pages on disk before marking them allocated.
+    fn take_slots(&mut self, slots: Range<usize>) -> Result<Range<PageId>> {
+        let start = Instant::now();
+        for slot in slots.clone() {
+            self.write_at(slot, &[0; PAGE_SIZE])
+                .and_then(|_| self.record_checksum(slot, &[0; PAGE_SIZE]))
+                .with_context(|| ErrorContext::new("allocate_page").page(slot))?;
+            self.page_counter = slot;
+            self.set_slot();
+        }
+        self.write_allocation_map(slots.clone())?;
+        self.record_metrics(DiskOp::Allocate, slots.len(), start);
+        Ok(slots)
+    }
+
+    fn highest_allocated_slot(&self) -> Option<usize> {
+        self.page_table.iter()
+            .rposition(|byte| *byte != 0)
+            .map(|byte| byte * 8 + 7 - self.page_table[byte].leading_zeros() as usize)
+    }
+
+    fn clear_slot(&mut self, slot: usize) {
+        let slot_byte = slot / 8;
+        let slot_bit = slot % 8;
+        self.page_table[slot_byte] &= !(0x1 << slot_bit);
+    }
+
+    /// Checks before writing a page, shared with disk managers doing their own IO on this file.
+    pub(crate) fn validate_write(&self, pid: PageId, data_size: usize) -> Result<()> {
+        self.validate_page_id(pid)?;
+        validate_page_data_size(data_size)?;
+        self.validate_not_reserved(pid)?;
+        self.validate_allocation(pid)
+    }
+
+    pub(crate) fn validate_read(&self, pid: PageId, data_size: usize) -> Result<()> {
+        self.validate_page_id(pid)?;
+        validate_page_data_size(data_size)?;
+        self.validate_allocation(pid)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    fn validate_allocation(&self, pid: PageId) -> Result<()> {
+        let slot_byte = pid / 8;
+        let slot_bit = pid % 8;
+        if (self.page_table[slot_byte] >> slot_bit) & 0x1 != 0x1 {
+            return Err(DiskError::PageNotAllocated(pid).into())
+        }
+
+        Ok(())
+    }
+}
+
+impl DiskManager for FileDiskManager {
+    /// The page is zeroed on disk, so it never shows data of a page deallocated before.
+    fn allocate_page(&mut self) -> Result<usize> {
+        let free_slot = match self.get_free_slot() {
+            Some(free_slot) => free_slot,
+            None => self.grow()?
+        };
+        self.take_slot(free_slot)
+    }
+
+    /// Grows the file when the strategy finds no free page, so `Append` and `Near` past the end extend it.
+    fn allocate_page_with(&mut self, strategy: AllocationStrategy) -> Result<PageId> {
+        let free_slot = match self.find_free_slot(strategy) {
+            Some(free_slot) => free_slot,
+            None => self.grow()?
+        };
+        self.take_slot(free_slot)
+    }
+
+    /// Takes the lowest run of `n` free pages, growing the file until there is one.
+    fn allocate_extent(&mut self, n: usize) -> Result<Range<PageId>> {
+        if n == 0 {
+            return Ok(0..0)
+        }
+
+        let first_slot = loop {
+            if let Some(first_slot) = self.find_free_run(n) {
+                break first_slot
+            }
+            self.grow()?;
+        };
+        self.take_slots(first_slot..first_slot + n)
+    }
+
+    /// The page stays free when the file is opened again, as the bitmap is written along.
+    fn deallocate_page(&mut self, page_id: usize) -> Result<bool> {
+        self.validate_page_id(page_id)?;
+        self.validate_not_reserved(page_id)?;
+        self.release_slot(page_id)?;
+        Ok(true)
+    }
+
+    fn write_page(&mut self, page_id: usize, page_data: &[u8]) -> Result<()> {
+        if self.has_double_write() {
+            return self.write_pages(&[(page_id, page_data)])
+        }
+        self.validate_write(page_id, page_data.len())?;
+
+        let start = Instant::now();
+        self.write_at(page_id, page_data)
+            .and_then(|_| self.record_checksum(page_id, page_data))
+            .with_context(|| ErrorContext::new("write_page").page(page_id))?;
+        self.record_metrics(DiskOp::Write, 1, start);
+        match self.sync_mode {
+            SyncMode::Always => self.sync_files()
+                .with_context(|| ErrorContext::new("write_page").page(page_id)),
+            _ => Ok(())
+        }
+    }
+
+    fn read_page(&mut self, page_id: usize, page_data: &mut [u8]) -> Result<()> {
+        self.validate_read(page_id, page_data.len())?;
+
+        let start = Instant::now();
+        self.read_at(page_id, page_data)
+            .with_context(|| ErrorContext::new("read_page").page(page_id))?;
+        self.record_metrics(DiskOp::Read, 1, start);
+        self.verify_checksum(page_id, page_data)
+            .with_context(|| ErrorContext::new("read_page").page(page_id))
+    }
+
+    /// Runs of consecutive page ids go out as one vectored write, except with direct IO,
+    /// where unaligned pages have to pass the bounce buffer one by one.
+    /// With double write, the whole batch is staged first.
+    fn write_pages(&mut self, pages: &[(PageId, &[u8])]) -> Result<()> {
+        for (pid, page_data) in pages {
+            self.validate_write(*pid, page_data.len())?;
+        }
+        if let Some(double_write) = &mut self.double_write {
+            double_write.stage(pages)
+                .with_context(|| ErrorContext::new("stage_pages"))?;
+        }
+
+        let start = Instant::now();
+        let direct_io = self.is_direct_io();
+        for run in consecutive_runs(pages) {
+            let first_pid = run[0].0;
+            match direct_io {
+                true => run.iter().try_for_each(|(pid, page_data)| self.write_at(*pid, page_data)),
+                false => self.write_run(first_pid, run)
+            }.with_context(|| ErrorContext::new("write_pages").page(first_pid))?;
+            for (pid, page_data) in run {
+                self.record_checksum(*pid, page_data)
+                    .with_context(|| ErrorContext::new("write_pages").page(*pid))?;
+            }
+        }
+        self.record_metrics(DiskOp::Write, pages.len(), start);
+        match (self.has_double_write(), self.sync_mode) {
+            (true, _) => self.finish_double_write(),
+            (false, SyncMode::Always) => self.sync_files(),
+            _ => Ok(())
+        }
+    }
+
+    fn read_pages(&mut self, pages: &mut [(PageId, &mut [u8])]) -> Result<()> {
+        for (pid, page_data) in pages.iter() {
+            self.validate_read(*pid, page_data.len())?;
+        }
+        if self.is_direct_io() {
+            return pages.iter_mut().try_for_each(|(pid, page_data)| self.read_page(*pid, page_data))
+        }
+
+        let start = Instant::now();
+        let num_pages = pages.len();
+        let mut rest = pages;
+        while !rest.is_empty() {
+            let run_len = consecutive_run_len(rest);
+            let (run, tail) = rest.split_at_mut(run_len);
+            let first_pid = run[0].0;
+            self.read_run(first_pid, run)
+                .with_context(|| ErrorContext::new("read_pages").page(first_pid))?;
+            for (pid, page_data) in run.iter() {
+                self.verify_checksum(*pid, page_data)
+                    .with_context(|| ErrorContext::new("read_pages").page(*pid))?;
+            }
+            rest = tail;
+        }
+        self.record_metrics(DiskOp::Read, num_pages, start);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        match self.sync_mode {
+            SyncMode::Never => Ok(()),
+            _ => self.sync_files()
+        }
+    }
+
+    /// Copies the highest allocated page into the lowest free one until no free page is left below
+    /// an allocated one, then truncates the file after the last allocated page.
+    ///
+    /// The bitmap is only written once every copy is synced, so a crash in between leaves the pages
+    /// allocated where they were. Pages of the allocation map are not moved, so the defragment stops
+    /// at the highest one. Page ids stored inside pages are not rewritten though, so
+    /// a file whose superblock names a catalog or hash table root is refused with
+    /// `DiskError::PagesReferenced`: their pages refer to each other by id.
+    fn defragment(&mut self) -> Result<PageRelocations> {
+        if let Some(superblock) = &self.superblock {
+            if superblock.get_catalog_root().is_some() || superblock.get_hash_table_root().is_some() {
+                return Err(DiskError::PagesReferenced.into())
+            }
+        }
+
+        let mut relocations = PageRelocations::default();
+        let mut page_data = [0u8; PAGE_SIZE];
+        while let (Some(free_slot), Some(last_slot)) = (self.find_free_slot(AllocationStrategy::FirstFit), self.highest_allocated_slot()) {
+            if free_slot > last_slot || self.allocation_map_pages.contains(&last_slot) {
+                break
+            }
+
+            self.read_at(last_slot, &mut page_data)
+                .and_then(|_| self.verify_checksum(last_slot, &page_data))
+                .and_then(|_| self.write_at(free_slot, &page_data))
+                .and_then(|_| self.record_checksum(free_slot, &page_data))
+                .with_context(|| ErrorContext::new("defragment").page(last_slot))?;
+            self.page_counter = free_slot;
+            self.set_slot();
+            self.clear_slot(last_slot);
+            relocations.insert(last_slot, free_slot);
+        }
+        self.sync_files()?;
+        self.write_allocation_map(0..self.num_pages())?;
+        self.sync_files()?;
+
+        let num_pages = round_up_to_byte(self.highest_allocated_slot().map_or(1, |slot| slot + 1));
+        if num_pages < self.num_pages() {
+            self.file.set_len((num_pages * PAGE_SIZE) as u64)
+                .with_context(|| ErrorContext::new("truncate_file").page(num_pages))?;
+            self.page_table.truncate(num_pages >> 3);
+            self.page_counter = self.page_counter.min(num_pages - 1);
+            self.file.sync_all()?;
+            if self.superblock.is_some() {
+                self.update_superblock(|superblock| superblock.set_allocation_map_pages(num_pages))?;
+            }
+        }
+        Ok(relocations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::disk::disk_manager::*;
+    use crate::storage::disk::checksum::checksum_path;
+    use crate::storage::disk::direct_io::AlignedPage;
+    use crate::storage::disk::double_write::{double_write_path, DoubleWriteBuffer};
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::disk::disk_stats::DiskMetrics;
+    use crate::storage::page::page::*;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_version::{CURRENT_FORMAT_VERSION, LEGACY_FORMAT_VERSION};
+    use crate::storage::page::superblock::{Superblock, SUPERBLOCK_PAGE_ID};
+    use std::fs::{remove_file, File, OpenOptions};
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+    use std::sync::Arc;
+    use rand::Rng;
+
+    #[test]
+    fn test_fake_disk_manager_can_allocate_page_id() {
+        let mut fake_disk_manager = FakeDiskManager::new();
+        let page_id_1 = fake_disk_manager.allocate_page().unwrap();
+        let page_id_2 = fake_disk_manager.allocate_page().unwrap();
+        let page_id_3 = fake_disk_manager.allocate_page().unwrap();
+
+        assert_eq!(page_id_1, 0);
+        assert_eq!(page_id_2, 1);
+        assert_eq!(page_id_3, 2);
+    }
+
+    #[test]
+    fn test_fake_disk_manager_can_write_page_to_fake_disk() {
+        // given
+        let mut fake_disk_manager = FakeDiskManager::new();
+        let page_id_1 = fake_disk_manager.allocate_page().unwrap();
+        let page_id_2 = fake_disk_manager.allocate_page().unwrap();
+
+        let mut page = Page::new(page_id_2);
+        let page_data = page.get_data_mut();
+        for i in 0..10 {
+            page_data[i] = (0x00 + i) as u8
+        }
+
+        // when
+        fake_disk_manager.write_page(page_id_2, page_data).unwrap();
+
+        // then
+        let mut data_written: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+        fake_disk_manager.read_page(page_id_1, &mut data_written).unwrap();
+        assert_eq!(data_written[0], 0x00);
+        assert_eq!(data_written[5], 0x00);
+        assert_eq!(data_written[9], 0x00);
+
+        fake_disk_manager.read_page(page_id_2, &mut data_written).unwrap();
+        assert_eq!(data_written[0], 0x00);
+        assert_eq!(data_written[5], 0x05);
+        assert_eq!(data_written[9], 0x09);
+    }
+
+    #[test]
+    fn test_fake_disk_manager_should_fail_on_illegal_page_id() {
+        let mut fake_disk_manager = FakeDiskManager::new();
+        let mut data = [0 as u8; PAGE_SIZE];
+
+        let write_err = fake_disk_manager.write_page(MAX_FILE_PAGES, &data).unwrap_err();
+        let read_err = fake_disk_manager.read_page(usize::MAX, &mut data).unwrap_err();
+
+        assert!(matches!(DiskError::from_io(&write_err), Some(DiskError::InvalidPageId(MAX_FILE_PAGES))));
+        assert!(matches!(DiskError::from_io(&read_err), Some(DiskError::InvalidPageId(usize::MAX))));
+    }
+
+    #[test]
+    fn test_fake_disk_manager_should_fail_on_wrong_page_data_size() {
+        let mut fake_disk_manager = FakeDiskManager::new();
+        let pid = fake_disk_manager.allocate_page().unwrap();
+
+        let err = fake_disk_manager.write_page(pid, &[0; 10]).unwrap_err();
+
+        assert!(matches!(
+            DiskError::from_io(&err),
+            Some(DiskError::WrongPageDataSize { expected: PAGE_SIZE, actual: 10 })));
+    }
+
+    const TEST_FILE_PATH: &str = "./test_storage";
+
+    fn remove_data_file(path: &str) {
+        remove_file(path).unwrap_or(());
+        remove_file(checksum_path(Path::new(path))).unwrap_or(());
+        remove_file(double_write_path(Path::new(path))).unwrap_or(());
+    }
+
+    #[test]
+    fn should_create_and_init_file_if_not_exists() {
+        let path = TEST_FILE_PATH.to_string() + "1";
+        remove_data_file(path.as_str());
+
+        FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        let file_path = Path::new(path.as_str());
+        assert!(file_path.exists());
+        assert!(file_path.is_file());
+        assert_eq!(file_path.file_name().unwrap(), "test_storage1");
+
+        let metadata = file_path.metadata().unwrap();
+        assert_eq!(metadata.len(), (PAGE_SIZE * EXTENT_PAGES) as u64);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_allocate_and_deallocate_page() {
+        let path = TEST_FILE_PATH.to_string() + "2";
+
+        // setup
+        remove_data_file(path.as_str());
+        let options = FileDiskOptions { max_pages: 2 * EXTENT_PAGES, ..FileDiskOptions::default() };
+        let mut fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+
+        // first page id should be 1, page 0 is the superblock
+        let pid1 = fdm.allocate_page().unwrap();
+        assert_eq!(pid1, 1);
+
+        // fully allocate page to maximum
+        for _i in 0..fdm.max_pages() - 2 {
+            fdm.allocate_page().unwrap();
+        }
+        assert!(fdm.page_table.iter().all(|b| *b == 0xff));
+        assert_eq!(fdm.num_pages(), 2 * EXTENT_PAGES);
+
+        // should return maximum exceeded err
+        let should_err = fdm.allocate_page();
+        assert!(should_err.is_err());
+        let quota = (2 * EXTENT_PAGES * PAGE_SIZE) as u64;
+        assert!(matches!(DiskError::from_io(should_err.as_ref().unwrap_err()), Some(DiskError::QuotaExceeded { used, quota: q }) if *used == quota && *q == quota));
+
+        // should fail when deallocate invalid page id
+        let should_err = fdm.deallocate_page(usize::MAX);
+        assert!(should_err.is_err());
+        assert_eq!(should_err.err().unwrap().to_string(), "Invalid page id.");
+
+        // random deallocate pages
+        let mut rng = rand::thread_rng();
+        let mut expected_page_ids: [usize; 5] = [0; 5];
+        let distinct_page_ids = rand::seq::index::sample(&mut rng, fdm.num_pages() - 1, expected_page_ids.len());
+        for (i, expected_page_id) in expected_page_ids.iter_mut().enumerate() {
+            *expected_page_id = distinct_page_ids.index(i) + 1;
+            fdm.deallocate_page(*expected_page_id).unwrap();
+
+            let byte_index = *expected_page_id >> 3;
+            let slot = *expected_page_id - (byte_index << 3);
+            assert_eq!(fdm.page_table[byte_index] & 0x1 << slot, 0x0);
+        }
+
+        // re-allocate
+        let mut real_allocate_page_ids = [0 as usize; 5];
+        for i in 0..real_allocate_page_ids.len() {
+            real_allocate_page_ids[i] = fdm.allocate_page().unwrap();
+        }
+        assert_eq!(expected_page_ids.sort(), real_allocate_page_ids.sort());
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_write_page_data_then_read_it_out() {
+        let path = TEST_FILE_PATH.to_string() + "3";
+
+        // given
+        let mut rng = rand::thread_rng();
+        let mut data = [0 as u8; PAGE_SIZE];
+        for i in 0..PAGE_SIZE {
+            data[i] = rng.gen();
+        }
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let mut pid: PageId = INVALID_PAGE_ID;
+        for _i in 0..rng.gen_range(0..MAX_FILE_PAGES - 1) + 1 {
+            pid = fdm.allocate_page().unwrap()
+        }
+
+        // when
         fdm.write_page(pid, &data).unwrap();
 
         // then
@@ -339,5 +1200,692 @@ mod tests {
         assert_eq!(data, read_data);
 
         remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_reserve_superblock_page_in_new_file() {
+        let path = TEST_FILE_PATH.to_string() + "4";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // when
+        let deallocated = fdm.deallocate_page(SUPERBLOCK_PAGE_ID);
+        let written = fdm.write_page(SUPERBLOCK_PAGE_ID, &[0; PAGE_SIZE]);
+
+        // then
+        assert_eq!(fdm.format_version(), CURRENT_FORMAT_VERSION);
+        assert!(matches!(DiskError::from_io(&deallocated.unwrap_err()), Some(DiskError::ReservedPage(SUPERBLOCK_PAGE_ID))));
+        assert!(matches!(DiskError::from_io(&written.unwrap_err()), Some(DiskError::ReservedPage(SUPERBLOCK_PAGE_ID))));
+
+        // reopen keeps the format
+        drop(fdm);
+        let fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        assert_eq!(fdm.format_version(), CURRENT_FORMAT_VERSION);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_open_file_without_superblock_in_legacy_format() {
+        let path = TEST_FILE_PATH.to_string() + "5";
+        remove_data_file(path.as_str());
+
+        // given
+        File::create(path.as_str()).unwrap().set_len((PAGE_SIZE * MAX_FILE_PAGES) as u64).unwrap();
+
+        // when
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // then
+        assert_eq!(fdm.format_version(), LEGACY_FORMAT_VERSION);
+        assert_eq!(fdm.allocate_page().unwrap(), 0);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_fail_to_open_file_with_newer_format() {
+        let path = TEST_FILE_PATH.to_string() + "6";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut superblock_raw = Superblock::new().serialize();
+        // the format version follows the 8 byte magic
+        superblock_raw[8] = CURRENT_FORMAT_VERSION + 1;
+        let mut file = File::create(path.as_str()).unwrap();
+        file.write_all(&superblock_raw).unwrap();
+        file.set_len((PAGE_SIZE * MAX_FILE_PAGES) as u64).unwrap();
+
+        // when
+        let result = FileDiskManager::new(Path::new(path.as_str()));
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::UnknownVersion { found: 2, supported: 1 })));
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_write_and_sync_pages_in_every_sync_mode() {
+        let path = TEST_FILE_PATH.to_string() + "7";
+
+        for sync_mode in [SyncMode::Always, SyncMode::OnFlush, SyncMode::Never] {
+            // given
+            remove_data_file(path.as_str());
+            File::create(path.as_str()).unwrap().set_len((PAGE_SIZE * MAX_FILE_PAGES) as u64).unwrap();
+            let options = FileDiskOptions { sync_mode, ..FileDiskOptions::default() };
+            let mut fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+            let pid = fdm.allocate_page().unwrap();
+
+            // when
+            fdm.write_page(pid, &[7; PAGE_SIZE]).unwrap();
+            fdm.sync().unwrap();
+
+            // then
+            assert_eq!(fdm.sync_mode(), sync_mode);
+            let mut read_data = [0 as u8; PAGE_SIZE];
+            fdm.read_page(pid, &mut read_data).unwrap();
+            assert_eq!(read_data, [7; PAGE_SIZE]);
+        }
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_zero_page_data_when_page_reallocated() {
+        let path = TEST_FILE_PATH.to_string() + "8";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pid = fdm.allocate_page().unwrap();
+        fdm.write_page(pid, &[9; PAGE_SIZE]).unwrap();
+        fdm.deallocate_page(pid).unwrap();
+
+        // when
+        let reallocated = fdm.allocate_page().unwrap();
+
+        // then
+        assert_eq!(reallocated, pid);
+        let mut read_data = [1 as u8; PAGE_SIZE];
+        fdm.read_page(pid, &mut read_data).unwrap();
+        assert_eq!(read_data, [0; PAGE_SIZE]);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_recover_page_allocations_when_reopened() {
+        let path = TEST_FILE_PATH.to_string() + "25";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let kept = fdm.allocate_page().unwrap();
+        let freed = fdm.allocate_page().unwrap();
+        fdm.write_page(kept, &[9; PAGE_SIZE]).unwrap();
+        fdm.write_page(freed, &[9; PAGE_SIZE]).unwrap();
+        fdm.deallocate_page(freed).unwrap();
+        drop(fdm);
+
+        // when
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // then
+        let mut read_data = [0; PAGE_SIZE];
+        fdm.read_page(kept, &mut read_data).unwrap();
+        assert_eq!(read_data, [9; PAGE_SIZE]);
+        assert!(matches!(DiskError::from_io(&fdm.read_page(freed, &mut read_data).err().unwrap()), Some(DiskError::PageNotAllocated(_))));
+        assert_eq!(fdm.allocate_page().unwrap(), freed);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_create_new_file_without_writing_every_page() {
+        use std::os::unix::fs::MetadataExt;
+        let path = TEST_FILE_PATH.to_string() + "9";
+        remove_data_file(path.as_str());
+
+        // when
+        FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // then
+        let metadata = Path::new(path.as_str()).metadata().unwrap();
+        assert_eq!(metadata.len(), (PAGE_SIZE * EXTENT_PAGES) as u64);
+        assert!(metadata.blocks() * 512 < metadata.len());
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_grow_file_by_extent_when_all_pages_allocated() {
+        let path = TEST_FILE_PATH.to_string() + "10";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        for _i in 1..EXTENT_PAGES {
+            fdm.allocate_page().unwrap();
+        }
+        assert_eq!(fdm.num_pages(), EXTENT_PAGES);
+
+        // when
+        let pid = fdm.allocate_page().unwrap();
+        fdm.write_page(pid, &[3; PAGE_SIZE]).unwrap();
+
+        // then
+        assert_eq!(pid, EXTENT_PAGES);
+        assert_eq!(fdm.num_pages(), 2 * EXTENT_PAGES);
+        let metadata = Path::new(path.as_str()).metadata().unwrap();
+        assert_eq!(metadata.len(), (PAGE_SIZE * 2 * EXTENT_PAGES) as u64);
+
+        // reopen sees the grown file
+        drop(fdm);
+        let fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        assert_eq!(fdm.num_pages(), 2 * EXTENT_PAGES);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_round_file_up_to_whole_bitmap_byte_when_opened() {
+        let path = TEST_FILE_PATH.to_string() + "11";
+        remove_data_file(path.as_str());
+
+        // given
+        File::create(path.as_str()).unwrap().set_len((PAGE_SIZE * 3) as u64).unwrap();
+
+        // when
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // then
+        assert_eq!(fdm.num_pages(), 8);
+        for pid in 0..8 {
+            assert_eq!(fdm.allocate_page().unwrap(), pid);
+        }
+        assert_eq!(fdm.allocate_page().unwrap(), 8);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_allocate_page_by_strategy() {
+        let path = TEST_FILE_PATH.to_string() + "12";
+        remove_data_file(path.as_str());
+
+        // given pages 1..=10 allocated, then 3 and 8 freed
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        for _i in 1..=10 {
+            fdm.allocate_page().unwrap();
+        }
+        fdm.deallocate_page(3).unwrap();
+        fdm.deallocate_page(8).unwrap();
+
+        // when & then
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::Near(9)).unwrap(), 8);
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::Append).unwrap(), 11);
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::Near(100)).unwrap(), 100);
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::FirstFit).unwrap(), 3);
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::Append).unwrap(), 101);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_grow_file_when_appending_past_last_page() {
+        let path = TEST_FILE_PATH.to_string() + "13";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        fdm.allocate_page_with(AllocationStrategy::Near(EXTENT_PAGES - 1)).unwrap();
+
+        // when
+        let pid = fdm.allocate_page_with(AllocationStrategy::Append).unwrap();
+
+        // then
+        assert_eq!(pid, EXTENT_PAGES);
+        assert_eq!(fdm.num_pages(), 2 * EXTENT_PAGES);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn should_write_and_read_through_direct_io_with_any_buffer_alignment() {
+        let path = TEST_FILE_PATH.to_string() + "14";
+        remove_data_file(path.as_str());
+
+        // given
+        let options = FileDiskOptions { direct_io: true, ..FileDiskOptions::default() };
+        let mut fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+        let pid1 = fdm.allocate_page().unwrap();
+        let pid2 = fdm.allocate_page().unwrap();
+        let mut unaligned = vec![0 as u8; PAGE_SIZE + 1];
+        unaligned[1..].copy_from_slice(&[5; PAGE_SIZE]);
+        let mut aligned = AlignedPage::new_boxed();
+        aligned.0 = [6; PAGE_SIZE];
+
+        // when
+        fdm.write_page(pid1, &unaligned[1..]).unwrap();
+        fdm.write_page(pid2, &aligned.0).unwrap();
+
+        // then
+        assert!(fdm.is_direct_io());
+        let mut read_unaligned = vec![0 as u8; PAGE_SIZE + 1];
+        fdm.read_page(pid1, &mut read_unaligned[1..]).unwrap();
+        assert_eq!(&read_unaligned[1..], &[5; PAGE_SIZE][..]);
+        let mut read_aligned = AlignedPage::new_boxed();
+        fdm.read_page(pid2, &mut read_aligned.0).unwrap();
+        assert_eq!(read_aligned.0, [6; PAGE_SIZE]);
+
+        // reopen reads the superblock through direct IO too
+        drop(fdm);
+        let fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+        assert_eq!(fdm.format_version(), CURRENT_FORMAT_VERSION);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_fail_read_when_page_corrupted_on_disk() {
+        let path = TEST_FILE_PATH.to_string() + "15";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pid = fdm.allocate_page().unwrap();
+        fdm.write_page(pid, &[3; PAGE_SIZE]).unwrap();
+
+        // when
+        let mut file = OpenOptions::new().write(true).open(path.as_str()).unwrap();
+        file.seek(SeekFrom::Start((pid * PAGE_SIZE + 100) as u64)).unwrap();
+        file.write_all(&[4]).unwrap();
+        let mut read_data = [0u8; PAGE_SIZE];
+        let result = fdm.read_page(pid, &mut read_data);
+
+        // then
+        assert!(fdm.has_checksums());
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::ChecksumMismatch { page_id, .. }) if *page_id == pid));
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_not_keep_checksums_when_disabled() {
+        let path = TEST_FILE_PATH.to_string() + "16";
+        remove_data_file(path.as_str());
+
+        // given
+        let options = FileDiskOptions { checksums: false, ..FileDiskOptions::default() };
+        let mut fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+        let pid = fdm.allocate_page().unwrap();
+        fdm.write_page(pid, &[3; PAGE_SIZE]).unwrap();
+
+        // when
+        let mut file = OpenOptions::new().write(true).open(path.as_str()).unwrap();
+        file.seek(SeekFrom::Start((pid * PAGE_SIZE) as u64)).unwrap();
+        file.write_all(&[4]).unwrap();
+        let mut read_data = [0u8; PAGE_SIZE];
+        fdm.read_page(pid, &mut read_data).unwrap();
+
+        // then
+        assert!(!fdm.has_checksums());
+        assert!(!checksum_path(Path::new(path.as_str())).exists());
+        assert_eq!(read_data[0], 4);
+
+        remove_file(path.as_str()).unwrap();
+    }
+
+    #[test]
+    fn should_write_and_read_pages_in_batch() {
+        let path = TEST_FILE_PATH.to_string() + "17";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pids: Vec<PageId> = (0..5).map(|_| fdm.allocate_page().unwrap()).collect();
+        let batch = [pids[0], pids[1], pids[2], pids[4]];
+        let data: Vec<[u8; PAGE_SIZE]> = batch.iter().map(|pid| [*pid as u8; PAGE_SIZE]).collect();
+
+        // when
+        let writes: Vec<(PageId, &[u8])> = batch.iter().zip(data.iter()).map(|(pid, d)| (*pid, &d[..])).collect();
+        fdm.write_pages(&writes).unwrap();
+        let mut read_back = vec![[0u8; PAGE_SIZE]; 4];
+        let mut reads: Vec<(PageId, &mut [u8])> = batch.iter().zip(read_back.iter_mut()).map(|(pid, d)| (*pid, &mut d[..])).collect();
+        fdm.read_pages(&mut reads).unwrap();
+
+        // then
+        assert_eq!(read_back, data);
+        let mut skipped = [1u8; PAGE_SIZE];
+        fdm.read_page(pids[3], &mut skipped).unwrap();
+        assert_eq!(skipped, [0; PAGE_SIZE]);
+        let mut single = [0u8; PAGE_SIZE];
+        fdm.read_page(pids[4], &mut single).unwrap();
+        assert_eq!(single, data[3]);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_restore_torn_page_from_double_write_file_on_open() {
+        let path = TEST_FILE_PATH.to_string() + "18";
+        remove_data_file(path.as_str());
+
+        // given
+        let options = FileDiskOptions { double_write: true, ..FileDiskOptions::default() };
+        let mut fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+        let pid = fdm.allocate_page().unwrap();
+        fdm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+        drop(fdm);
+        DoubleWriteBuffer::open(Path::new(path.as_str())).unwrap().stage(&[(pid, &[2; PAGE_SIZE])]).unwrap();
+        let mut file = OpenOptions::new().read(true).write(true).open(path.as_str()).unwrap();
+        file.seek(SeekFrom::Start((pid * PAGE_SIZE) as u64)).unwrap();
+        file.write_all(&[2; PAGE_SIZE / 2]).unwrap();
+
+        // when
+        let fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+
+        // then
+        let mut page_data = [0u8; PAGE_SIZE];
+        file.seek(SeekFrom::Start((pid * PAGE_SIZE) as u64)).unwrap();
+        file.read_exact(&mut page_data).unwrap();
+        assert_eq!(page_data, [2; PAGE_SIZE]);
+        assert!(fdm.verify_checksum(pid, &page_data).is_ok());
+        assert_eq!(std::fs::metadata(double_write_path(Path::new(path.as_str()))).unwrap().len(), 0);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_record_metrics_of_every_fsync() {
+        let path = TEST_FILE_PATH.to_string() + "19";
+        remove_data_file(path.as_str());
+
+        // given
+        let options = FileDiskOptions { sync_mode: SyncMode::Always, ..FileDiskOptions::default() };
+        let mut fdm = FileDiskManager::new_with_options(Path::new(path.as_str()), options).unwrap();
+        let metrics = Arc::new(DiskMetrics::new());
+        fdm.set_metrics(metrics.clone());
+        let pid = fdm.allocate_page().unwrap();
+
+        // when
+        fdm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+        fdm.write_page(pid, &[2; PAGE_SIZE]).unwrap();
+        fdm.read_page(pid, &mut [0; PAGE_SIZE]).unwrap();
+
+        // then
+        let stats = metrics.snapshot();
+        assert_eq!(stats.allocations.ops, 1);
+        assert_eq!(stats.writes.ops, 2);
+        assert_eq!(stats.syncs.ops, 2);
+        assert_eq!(stats.reads.pages, 1);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_move_pages_to_start_and_truncate_file_when_defragment() {
+        let path = TEST_FILE_PATH.to_string() + "20";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pids: Vec<PageId> = (0..40).map(|_| fdm.allocate_page().unwrap()).collect();
+        for pid in &pids {
+            fdm.write_page(*pid, &[*pid as u8; PAGE_SIZE]).unwrap();
+        }
+        for pid in &pids[..30] {
+            fdm.deallocate_page(*pid).unwrap();
+        }
+
+        // when
+        let relocations = fdm.defragment().unwrap();
+
+        // then
+        assert_eq!(relocations.len(), 10);
+        assert_eq!(fdm.num_pages(), 16);
+        assert_eq!(File::open(path.as_str()).unwrap().metadata().unwrap().len(), (16 * PAGE_SIZE) as u64);
+        for pid in &pids[30..] {
+            let new_pid = relocations.resolve(*pid);
+            assert!(new_pid <= 10);
+            let mut page_data = [0u8; PAGE_SIZE];
+            fdm.read_page(new_pid, &mut page_data).unwrap();
+            assert_eq!(page_data, [*pid as u8; PAGE_SIZE]);
+        }
+        assert!(fdm.defragment().unwrap().is_empty());
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_refuse_to_defragment_while_superblock_names_a_root() {
+        let path = TEST_FILE_PATH.to_string() + "26";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pids: Vec<PageId> = (0..4).map(|_| fdm.allocate_page().unwrap()).collect();
+        fdm.write_page(pids[3], &[3; PAGE_SIZE]).unwrap();
+        fdm.deallocate_page(pids[0]).unwrap();
+        fdm.update_superblock(|superblock| superblock.set_catalog_root(Some(pids[1]))).unwrap();
+
+        // when
+        let err = fdm.defragment().err().unwrap();
+
+        // then
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::PagesReferenced)));
+        let mut page_data = [0u8; PAGE_SIZE];
+        fdm.read_page(pids[3], &mut page_data).unwrap();
+        assert_eq!(page_data, [3; PAGE_SIZE]);
+        assert!(matches!(DiskError::from_io(&fdm.read_page(pids[0], &mut page_data).unwrap_err()), Some(DiskError::PageNotAllocated(_))));
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_keep_allocations_of_unwritten_pages_when_reopened() {
+        let path = TEST_FILE_PATH.to_string() + "27";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let unwritten = fdm.allocate_page().unwrap();
+        let freed = fdm.allocate_page().unwrap();
+        fdm.write_page(freed, &[9; PAGE_SIZE]).unwrap();
+        fdm.deallocate_page(freed).unwrap();
+        drop(fdm);
+
+        // when
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // then
+        let mut read_data = [9; PAGE_SIZE];
+        fdm.read_page(unwritten, &mut read_data).unwrap();
+        assert_eq!(read_data, [0; PAGE_SIZE]);
+        assert_eq!(fdm.allocate_page().unwrap(), freed);
+
+        // files written before the bitmap was kept recover it from the pages written
+        fdm.write_page(freed, &[9; PAGE_SIZE]).unwrap();
+        fdm.update_superblock(|superblock| superblock.set_allocation_map_pages(0)).unwrap();
+        drop(fdm);
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        assert_eq!(fdm.superblock().unwrap().get_allocation_map_pages(), fdm.num_pages());
+        assert!(matches!(DiskError::from_io(&fdm.read_page(unwritten, &mut read_data).err().unwrap()), Some(DiskError::PageNotAllocated(_))));
+        fdm.read_page(freed, &mut read_data).unwrap();
+        assert_eq!(read_data, [9; PAGE_SIZE]);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_chain_allocation_map_pages_once_file_outgrows_superblock_page() {
+        let path = TEST_FILE_PATH.to_string() + "28";
+        remove_data_file(path.as_str());
+
+        // given
+        drop(FileDiskManager::new(Path::new(path.as_str())).unwrap());
+        let num_pages = 40 * EXTENT_PAGES;
+        OpenOptions::new().write(true).open(path.as_str()).unwrap().set_len((num_pages * PAGE_SIZE) as u64).unwrap();
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let map_page_id = fdm.superblock().unwrap().get_allocation_map_root().unwrap();
+        let far = fdm.allocate_page_with(AllocationStrategy::Near(num_pages - 2)).unwrap();
+        drop(fdm);
+
+        // when
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+
+        // then
+        assert_eq!(far, num_pages - 2);
+        fdm.read_page(far, &mut [0; PAGE_SIZE]).unwrap();
+        let written = fdm.write_page(map_page_id, &[0; PAGE_SIZE]);
+        assert!(matches!(DiskError::from_io(&written.unwrap_err()), Some(DiskError::ReservedPage(pid)) if *pid == map_page_id));
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::Near(num_pages - 2)).unwrap(), num_pages - 1);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_read_pages_into_one_buffer() {
+        let path = TEST_FILE_PATH.to_string() + "21";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pids: Vec<PageId> = (0..4).map(|_| fdm.allocate_page().unwrap()).collect();
+        for pid in &pids {
+            fdm.write_page(*pid, &[*pid as u8; PAGE_SIZE]).unwrap();
+        }
+
+        // when
+        let batch = [pids[0], pids[1], pids[3]];
+        let mut buf = vec![0u8; batch.len() * PAGE_SIZE];
+        fdm.read_pages_into(&batch, &mut buf).unwrap();
+
+        // then
+        for (pid, page_data) in batch.iter().zip(buf.chunks_exact(PAGE_SIZE)) {
+            assert_eq!(page_data, &[*pid as u8; PAGE_SIZE][..]);
+        }
+        let err = fdm.read_pages_into(&batch, &mut buf[PAGE_SIZE..]).unwrap_err();
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::WrongPageDataSize { expected, .. }) if *expected == 3 * PAGE_SIZE));
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_fail_with_quota_exceeded_when_database_reaches_max_size() {
+        let path = TEST_FILE_PATH.to_string() + "22";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::with_quota(Path::new(path.as_str()), (20 * PAGE_SIZE + 100) as u64).unwrap();
+        for _i in 1..16 {
+            fdm.allocate_page().unwrap();
+        }
+
+        // when
+        let err = fdm.allocate_page().unwrap_err();
+
+        // then
+        assert_eq!(fdm.quota(), (16 * PAGE_SIZE) as u64);
+        match DiskError::from_io(&err) {
+            Some(DiskError::QuotaExceeded { used, quota }) => assert_eq!((*used, *quota), (fdm.quota(), fdm.quota())),
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_allocate_extent_in_lowest_free_run_and_grow_for_it() {
+        let path = TEST_FILE_PATH.to_string() + "23";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let pids: Vec<PageId> = (0..8).map(|_| fdm.allocate_page().unwrap()).collect();
+        fdm.deallocate_page(pids[1]).unwrap();
+        for pid in &pids[4..7] {
+            fdm.deallocate_page(*pid).unwrap();
+        }
+
+        // when
+        let extent = fdm.allocate_extent(3).unwrap();
+        let large_extent = fdm.allocate_extent(EXTENT_PAGES).unwrap();
+
+        // then
+        assert_eq!(extent, pids[4]..pids[7]);
+        assert_eq!(large_extent, pids[7] + 1..pids[7] + 1 + EXTENT_PAGES);
+        assert_eq!(fdm.num_pages(), 2 * EXTENT_PAGES);
+        assert_eq!(fdm.allocate_page_with(AllocationStrategy::FirstFit).unwrap(), pids[1]);
+        assert!(fdm.allocate_extent(0).unwrap().is_empty());
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+
+    #[test]
+    fn should_refuse_foreign_file_and_persist_superblock_roots() {
+        let path = TEST_FILE_PATH.to_string() + "24";
+        remove_data_file(path.as_str());
+
+        // given
+        let mut file = File::create(path.as_str()).unwrap();
+        file.write_all(b"not a database").unwrap();
+        file.set_len((PAGE_SIZE * 8) as u64).unwrap();
+        drop(file);
+
+        // when
+        let refused = FileDiskManager::new(Path::new(path.as_str()));
+        let legacy = FileDiskManager::new_with_options(Path::new(path.as_str()), FileDiskOptions { legacy_format: true, ..FileDiskOptions::default() }).unwrap();
+
+        // then
+        assert!(matches!(DiskError::from_io(&refused.err().unwrap()), Some(DiskError::NotDatabaseFile(_))));
+        assert_eq!(legacy.format_version(), LEGACY_FORMAT_VERSION);
+        assert!(legacy.superblock().is_none());
+        drop(legacy);
+        remove_data_file(path.as_str());
+
+        // roots survive reopen
+        let mut fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        let root = fdm.allocate_page().unwrap();
+        fdm.update_superblock(|superblock| superblock.set_hash_table_root(Some(root))).unwrap();
+        drop(fdm);
+        let fdm = FileDiskManager::new(Path::new(path.as_str())).unwrap();
+        assert_eq!(fdm.superblock().unwrap().get_hash_table_root(), Some(root));
+        assert_eq!(fdm.superblock().unwrap().get_catalog_root(), None);
+
+        remove_file(path.as_str()).unwrap();
+        remove_data_file(path.as_str());
+    }
+}
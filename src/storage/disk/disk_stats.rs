@@ -0,0 +1,168 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Bucket `i` holds latencies below 2^i microseconds, the last one everything slower.
+pub const LATENCY_BUCKETS: usize = 24;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiskOp {
+    Read,
+    Write,
+    /// An fsync, or a `sync` call of a disk manager without finer grained metrics.
+    Sync,
+    Allocate,
+}
+
+impl DiskOp {
+    fn index(self) -> usize {
+        match self {
+            DiskOp::Read => 0,
+            DiskOp::Write => 1,
+            DiskOp::Sync => 2,
+            DiskOp::Allocate => 3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    pub fn bucket_of(latency: Duration) -> usize {
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        ((64 - micros.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1)
+    }
+
+    pub fn upper_bound(bucket: usize) -> Duration {
+        Duration::from_micros(1 << bucket)
+    }
+
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Upper bound of the bucket the `quantile` latency falls in, `None` without any sample.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None
+        }
+
+        let rank = ((count as f64 * quantile).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        self.buckets.iter()
+            .position(|n| {
+                seen += n;
+                seen >= rank
+            })
+            .map(LatencyHistogram::upper_bound)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct OpStats {
+    pub ops: u64,
+    /// A batch call is one op over several pages.
+    pub pages: u64,
+    pub latency: LatencyHistogram,
+}
+
+/// Snapshot of `DiskMetrics`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DiskStats {
+    pub reads: OpStats,
+    pub writes: OpStats,
+    pub syncs: OpStats,
+    pub allocations: OpStats,
+}
+
+#[derive(Default)]
+struct OpMetrics {
+    ops: AtomicU64,
+    pages: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl OpMetrics {
+    fn snapshot(&self) -> OpStats {
+        let mut latency = LatencyHistogram::default();
+        for (bucket, n) in latency.buckets.iter_mut().zip(self.latency.iter()) {
+            *bucket = n.load(Ordering::Relaxed);
+        }
+
+        OpStats {
+            ops: self.ops.load(Ordering::Relaxed),
+            pages: self.pages.load(Ordering::Relaxed),
+            latency,
+        }
+    }
+}
+
+/// Counts and times disk IO, shared between a disk manager and whoever reads the stats.
+///
+/// Tells whether slowness comes from the disk itself, or from the buffer pool missing too often.
+#[derive(Default)]
+pub struct DiskMetrics {
+    ops: [OpMetrics; 4],
+}
+
+impl DiskMetrics {
+    pub fn new() -> DiskMetrics {
+        DiskMetrics::default()
+    }
+
+    pub fn record(&self, op: DiskOp, pages: usize, latency: Duration) {
+        let metrics = &self.ops[op.index()];
+        metrics.ops.fetch_add(1, Ordering::Relaxed);
+        metrics.pages.fetch_add(pages as u64, Ordering::Relaxed);
+        metrics.latency[LatencyHistogram::bucket_of(latency)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_since(&self, op: DiskOp, pages: usize, start: Instant) {
+        self.record(op, pages, start.elapsed());
+    }
+
+    pub fn snapshot(&self) -> DiskStats {
+        DiskStats {
+            reads: self.ops[DiskOp::Read.index()].snapshot(),
+            writes: self.ops[DiskOp::Write.index()].snapshot(),
+            syncs: self.ops[DiskOp::Sync.index()].snapshot(),
+            allocations: self.ops[DiskOp::Allocate.index()].snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::storage::disk::disk_stats::{DiskMetrics, DiskOp, LatencyHistogram, LATENCY_BUCKETS};
+
+    #[test]
+    fn should_bucket_latencies_and_report_quantiles() {
+        // given
+        let metrics = DiskMetrics::new();
+        for _ in 0..9 {
+            metrics.record(DiskOp::Read, 1, Duration::from_micros(3));
+        }
+
+        // when
+        metrics.record(DiskOp::Read, 4, Duration::from_millis(5));
+        let stats = metrics.snapshot();
+
+        // then
+        assert_eq!(stats.reads.ops, 10);
+        assert_eq!(stats.reads.pages, 13);
+        assert_eq!(stats.reads.latency.buckets()[2], 9);
+        assert_eq!(stats.reads.latency.quantile(0.5), Some(Duration::from_micros(4)));
+        assert_eq!(stats.reads.latency.quantile(0.99), Some(Duration::from_micros(8192)));
+        assert_eq!(stats.writes.latency.quantile(0.5), None);
+        assert_eq!(LatencyHistogram::bucket_of(Duration::from_secs(3600)), LATENCY_BUCKETS - 1);
+    }
+}
@@ -0,0 +1,118 @@
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use fasthash::xx;
+
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+
+/// Appended to the data file path to name the file pages are staged in before written in place.
+pub const DOUBLE_WRITE_FILE_SUFFIX: &str = ".dw";
+/// Page id and checksum ahead of each staged page.
+const RECORD_HEADER_SIZE: usize = 8 + 4;
+const RECORD_SIZE: usize = RECORD_HEADER_SIZE + PAGE_SIZE;
+
+pub fn double_write_path(file_path: &Path) -> PathBuf {
+    let mut path = OsString::from(file_path);
+    path.push(DOUBLE_WRITE_FILE_SUFFIX);
+    PathBuf::from(path)
+}
+
+// seeded with the page id, so a record is only intact for the page it was staged for
+fn record_checksum(pid: PageId, page_data: &[u8]) -> u32 {
+    xx::hash32_with_seed(page_data, pid as u32)
+}
+
+/// Scratch file next to the data file, where a batch of pages is written and synced before any of
+/// them is written in place.
+///
+/// A crash while writing in place may tear a page, which is then restored from its intact copy here.
+/// A crash while staging tears a record instead, which fails its checksum and is left out, as its page
+/// was not touched in place yet.
+pub struct DoubleWriteBuffer {
+    file: File,
+}
+
+impl DoubleWriteBuffer {
+    pub fn open(file_path: &Path) -> Result<DoubleWriteBuffer> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(double_write_path(file_path))?;
+
+        Ok(DoubleWriteBuffer { file })
+    }
+
+    /// Writes the pages sequentially, replacing the batch staged before, and syncs them.
+    pub fn stage(&mut self, pages: &[(PageId, &[u8])]) -> Result<()> {
+        let mut batch = Vec::with_capacity(pages.len() * RECORD_SIZE);
+        for (pid, page_data) in pages {
+            batch.extend_from_slice(&(*pid as u64).to_le_bytes());
+            batch.extend_from_slice(&record_checksum(*pid, page_data).to_le_bytes());
+            batch.extend_from_slice(page_data);
+        }
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&batch)?;
+        self.file.set_len(batch.len() as u64)?;
+        self.file.sync_data()
+    }
+
+    /// Intact pages of the last staged batch, which may not have made it in place.
+    pub fn staged_pages(&mut self) -> Result<Vec<(PageId, Vec<u8>)>> {
+        let mut raw = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut raw)?;
+
+        Ok(raw.chunks_exact(RECORD_SIZE)
+            .filter_map(|record| {
+                let pid = u64::from_le_bytes(record[0..8].try_into().unwrap()) as PageId;
+                let checksum = u32::from_le_bytes(record[8..RECORD_HEADER_SIZE].try_into().unwrap());
+                let page_data = &record[RECORD_HEADER_SIZE..];
+                match record_checksum(pid, page_data) == checksum {
+                    true => Some((pid, page_data.to_vec())),
+                    false => None,
+                }
+            })
+            .collect())
+    }
+
+    /// Drops the staged batch once it is durable in place.
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.set_len(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+
+    use crate::storage::disk::double_write::{double_write_path, DoubleWriteBuffer, RECORD_SIZE};
+    use crate::storage::page::page::PAGE_SIZE;
+
+    #[test]
+    fn should_return_only_intact_pages_of_last_batch() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("minedb.data");
+        let mut double_write = DoubleWriteBuffer::open(&path).unwrap();
+        double_write.stage(&[(1, &[1; PAGE_SIZE]), (2, &[2; PAGE_SIZE]), (3, &[3; PAGE_SIZE])]).unwrap();
+        double_write.stage(&[(4, &[4; PAGE_SIZE]), (5, &[5; PAGE_SIZE])]).unwrap();
+
+        // when
+        let mut file = OpenOptions::new().write(true).open(double_write_path(&path)).unwrap();
+        file.seek(SeekFrom::Start((RECORD_SIZE + 100) as u64)).unwrap();
+        file.write_all(&[0; 8]).unwrap();
+        let staged = DoubleWriteBuffer::open(&path).unwrap().staged_pages().unwrap();
+
+        // then
+        assert_eq!(staged, vec![(4, vec![4; PAGE_SIZE])]);
+        double_write.clear().unwrap();
+        assert!(double_write.staged_pages().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce, Tag};
+
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::storage::disk::disk_error::DiskError;
+use crate::storage::disk::disk_manager::{AllocationStrategy, DiskManager};
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+
+/// AES-256 key length.
+pub const KEY_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+/// Generation handed out last, generation and tag of the contents, whether the page holds ciphertext
+/// at all, then the tag of a pending write and whether there is one.
+const SEAL_SIZE: usize = 8 + 8 + TAG_SIZE + 1 + TAG_SIZE + 1;
+/// The nonce keeps 4 bytes for the page id.
+const MAX_PAGE_ID: PageId = u32::MAX as PageId;
+
+const SEALED_GENERATION_OFFSET: usize = 8;
+const TAG_OFFSET: usize = SEALED_GENERATION_OFFSET + 8;
+const SEALED_OFFSET: usize = TAG_OFFSET + TAG_SIZE;
+const PENDING_TAG_OFFSET: usize = SEALED_OFFSET + 1;
+
+/// What decrypting one page needs besides the key.
+#[derive(Copy, Clone, Default)]
+struct Seal {
+    /// Bumped on every write, so no nonce is used twice with the same key. Made durable before the
+    /// page is written with it.
+    generation: u64,
+    /// Generation the contents were encrypted with, behind `generation` while a write is pending.
+    sealed_generation: u64,
+    tag: [u8; TAG_SIZE],
+    sealed: bool,
+    /// Tag of the write with `generation`, kept until the write is known to have reached the page.
+    pending_tag: Option<[u8; TAG_SIZE]>,
+}
+
+impl Seal {
+    fn to_bytes(self) -> [u8; SEAL_SIZE] {
+        let mut raw = [0; SEAL_SIZE];
+        raw[0..SEALED_GENERATION_OFFSET].copy_from_slice(&self.generation.to_le_bytes());
+        raw[SEALED_GENERATION_OFFSET..TAG_OFFSET].copy_from_slice(&self.sealed_generation.to_le_bytes());
+        raw[TAG_OFFSET..SEALED_OFFSET].copy_from_slice(&self.tag);
+        raw[SEALED_OFFSET] = self.sealed as u8;
+        if let Some(pending_tag) = self.pending_tag {
+            raw[PENDING_TAG_OFFSET..SEAL_SIZE - 1].copy_from_slice(&pending_tag);
+            raw[SEAL_SIZE - 1] = 1;
+        }
+        raw
+    }
+
+    fn from_bytes(raw: &[u8]) -> Seal {
+        Seal {
+            generation: u64::from_le_bytes(raw[0..SEALED_GENERATION_OFFSET].try_into().unwrap()),
+            sealed_generation: u64::from_le_bytes(raw[SEALED_GENERATION_OFFSET..TAG_OFFSET].try_into().unwrap()),
+            tag: raw[TAG_OFFSET..SEALED_OFFSET].try_into().unwrap(),
+            sealed: raw[SEALED_OFFSET] != 0,
+            pending_tag: match raw[SEAL_SIZE - 1] {
+                0 => None,
+                _ => Some(raw[PENDING_TAG_OFFSET..SEAL_SIZE - 1].try_into().unwrap()),
+            },
+        }
+    }
+}
+
+fn nonce_of(pid: PageId, generation: u64) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[0..4].copy_from_slice(&(pid as u32).to_le_bytes());
+    nonce[4..12].copy_from_slice(&generation.to_le_bytes());
+    nonce
+}
+
+/// Disk manager wrapper encrypting every page with AES-256-GCM before it reaches `D`.
+///
+/// The nonce is derived from the page id and a per-page write generation, and the page id is
+/// authenticated too, so a page copied over another one fails to decrypt. Generations and tags
+/// are kept in a seal file, as a page of ciphertext has no room left for them.
+///
+/// The generation of a write and its tag are synced to the seal file as pending before the page is
+/// written, so a crash in between cannot hand out the generation again. The seal of the previous
+/// contents stays until the write went through, and a read tries both, so a write that fails or is
+/// cut off by a crash leaves the page readable with whichever contents it holds, unless the write
+/// tore the page. That costs one sync of the seal file per `write_page`, and one per `write_pages`
+/// batch, on top of whatever the wrapped manager syncs.
+///
+/// A page without seal is only read as the zeroes it holds after allocation, anything else stored
+/// there fails to decrypt as well, so the seal file cannot be edited to pass off plaintext.
+pub struct EncryptedDiskManager<D: DiskManager> {
+    inner: D,
+    cipher: Aes256Gcm,
+    seal_file: File,
+    seals: Vec<Seal>,
+}
+
+impl<D: DiskManager> EncryptedDiskManager<D> {
+    pub fn new(inner: D, key: &[u8; KEY_SIZE], seal_path: &Path) -> Result<EncryptedDiskManager<D>> {
+        let mut seal_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(seal_path)?;
+        let mut raw = Vec::new();
+        seal_file.read_to_end(&mut raw)?;
+
+        Ok(EncryptedDiskManager {
+            inner,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            seal_file,
+            seals: raw.chunks_exact(SEAL_SIZE).map(Seal::from_bytes).collect(),
+        })
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    fn seal_of(&self, pid: PageId) -> Seal {
+        self.seals.get(pid).copied().unwrap_or_default()
+    }
+
+    fn store_seal(&mut self, pid: PageId, seal: Seal) -> Result<()> {
+        self.seal_file.seek(SeekFrom::Start((pid * SEAL_SIZE) as u64))?;
+        self.seal_file.write_all(&seal.to_bytes())?;
+
+        if self.seals.len() <= pid {
+            self.seals.resize(pid + 1, Seal::default());
+        }
+        self.seals[pid] = seal;
+        Ok(())
+    }
+
+    /// A reallocated page reads as zeroes again, but keeps its generation so nonces stay unique.
+    fn unseal_page(&mut self, pid: PageId) -> Result<()> {
+        let seal = self.seal_of(pid);
+        if seal.sealed || seal.pending_tag.is_some() {
+            self.store_seal(pid, Seal { sealed: false, pending_tag: None, ..seal })?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts the page under the next generation, storing it as pending in the seal of the page.
+    fn seal_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<[u8; PAGE_SIZE]> {
+        EncryptedDiskManager::<D>::validate_page_id(page_id)?;
+        if page_data.len() != PAGE_SIZE {
+            return Err(DiskError::WrongPageDataSize { expected: PAGE_SIZE, actual: page_data.len() }.into())
+        }
+
+        let seal = self.seal_of(page_id);
+        let generation = seal.generation + 1;
+        let mut ciphertext = [0u8; PAGE_SIZE];
+        ciphertext.copy_from_slice(page_data);
+        let tag = self.cipher
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce_of(page_id, generation)), &page_id.to_le_bytes(), &mut ciphertext)
+            .map_err(|_| std::io::Error::other("Page encryption failed."))?;
+
+        self.store_seal(page_id, Seal { generation, pending_tag: Some(tag.into()), ..seal })
+            .with_context(|| ErrorContext::new("write_page_seal").page(page_id))?;
+        Ok(ciphertext)
+    }
+
+    /// The pending write reached the page. Not synced, as the pending tag still decrypts the page.
+    fn confirm_seal(&mut self, page_id: PageId) -> Result<()> {
+        let seal = self.seal_of(page_id);
+        if let Some(tag) = seal.pending_tag {
+            self.store_seal(page_id, Seal { sealed_generation: seal.generation, tag, sealed: true, pending_tag: None, ..seal })?;
+        }
+        Ok(())
+    }
+
+    fn decrypt(&self, page_id: PageId, generation: u64, tag: &[u8; TAG_SIZE], page_data: &mut [u8]) -> bool {
+        self.cipher
+            .decrypt_in_place_detached(Nonce::from_slice(&nonce_of(page_id, generation)), &page_id.to_le_bytes(),
+                                       page_data, Tag::from_slice(tag))
+            .is_ok()
+    }
+
+    fn validate_page_id(pid: PageId) -> Result<()> {
+        if pid > MAX_PAGE_ID {
+            return Err(DiskError::InvalidPageId(pid).into())
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: DiskManager> DiskManager for EncryptedDiskManager<D> {
+    fn allocate_page(&mut self) -> Result<PageId> {
+        let pid = self.inner.allocate_page()?;
+        self.unseal_page(pid)?;
+        Ok(pid)
+    }
+
+    fn allocate_page_with(&mut self, strategy: AllocationStrategy) -> Result<PageId> {
+        let pid = self.inner.allocate_page_with(strategy)?;
+        self.unseal_page(pid)?;
+        Ok(pid)
+    }
+
+    fn allocate_extent(&mut self, n: usize) -> Result<Range<PageId>> {
+        let pids = self.inner.allocate_extent(n)?;
+        for pid in pids.clone() {
+            self.unseal_page(pid)?;
+        }
+        Ok(pids)
+    }
+
+    fn deallocate_page(&mut self, page_id: PageId) -> Result<bool> {
+        self.inner.deallocate_page(page_id)
+    }
+
+    fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<()> {
+        self.write_pages(&[(page_id, page_data)])
+    }
+
+    /// Seals the whole batch with a single sync of the seal file before handing it on in one call.
+    fn write_pages(&mut self, pages: &[(PageId, &[u8])]) -> Result<()> {
+        let mut ciphertexts = Vec::with_capacity(pages.len());
+        for (pid, page_data) in pages {
+            ciphertexts.push(self.seal_page(*pid, page_data)?);
+        }
+        self.seal_file.sync_data()
+            .with_context(|| ErrorContext::new("write_page_seal"))?;
+
+        let encrypted: Vec<(PageId, &[u8])> = pages.iter().zip(&ciphertexts)
+            .map(|((pid, _), ciphertext)| (*pid, &ciphertext[..]))
+            .collect();
+        self.inner.write_pages(&encrypted)?;
+        for (pid, _) in pages {
+            self.confirm_seal(*pid)?;
+        }
+        Ok(())
+    }
+
+    fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> Result<()> {
+        EncryptedDiskManager::<D>::validate_page_id(page_id)?;
+        self.inner.read_page(page_id, page_data)?;
+
+        // a failed decryption leaves the buffer as it was, so the pending seal gets the ciphertext too
+        let seal = self.seal_of(page_id);
+        let readable = match seal.sealed {
+            true => self.decrypt(page_id, seal.sealed_generation, &seal.tag, page_data),
+            false => page_data.iter().all(|byte| *byte == 0),
+        };
+        let readable = readable || match &seal.pending_tag {
+            Some(pending_tag) => self.decrypt(page_id, seal.generation, pending_tag, page_data),
+            None => false,
+        };
+        match readable {
+            true => Ok(()),
+            false => Err(DiskError::DecryptionFailed(page_id).into()),
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()?;
+        self.seal_file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::disk::disk_manager::{DiskManager, FakeDiskManager};
+    use crate::storage::disk::encrypted_disk_manager::{EncryptedDiskManager, Seal, KEY_SIZE};
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::testkit::fault::{FaultyDiskManager, WriteFault};
+
+    const KEY: [u8; KEY_SIZE] = [7; KEY_SIZE];
+
+    #[test]
+    fn should_store_only_ciphertext_and_read_back_plaintext() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = EncryptedDiskManager::new(FakeDiskManager::new(), &KEY, &dir.path().join("seals")).unwrap();
+        let pid = dm.allocate_page().unwrap();
+        let fresh_pid = dm.allocate_page().unwrap();
+
+        // when
+        dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+        dm.write_page(pid, &[2; PAGE_SIZE]).unwrap();
+
+        // then
+        let mut plaintext = [0u8; PAGE_SIZE];
+        dm.read_page(pid, &mut plaintext).unwrap();
+        assert_eq!(plaintext, [2; PAGE_SIZE]);
+        dm.read_page(fresh_pid, &mut plaintext).unwrap();
+        assert_eq!(plaintext, [0; PAGE_SIZE]);
+        let mut stored = [0u8; PAGE_SIZE];
+        dm.inner.read_page(pid, &mut stored).unwrap();
+        assert_ne!(stored, [2; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn should_fail_read_with_wrong_key_or_tampered_page() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let seal_path = dir.path().join("seals");
+        let mut dm = EncryptedDiskManager::new(FakeDiskManager::new(), &KEY, &seal_path).unwrap();
+        let pid = dm.allocate_page().unwrap();
+        dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+        let mut stored = [0u8; PAGE_SIZE];
+        dm.inner.read_page(pid, &mut stored).unwrap();
+
+        // when
+        let mut other_key = EncryptedDiskManager::new(FakeDiskManager::new(), &[8; KEY_SIZE], &seal_path).unwrap();
+        other_key.inner.write_page(pid, &stored).unwrap();
+        let wrong_key = other_key.read_page(pid, &mut [0u8; PAGE_SIZE]);
+        stored[10] ^= 1;
+        dm.inner.write_page(pid, &stored).unwrap();
+        let tampered = dm.read_page(pid, &mut [0u8; PAGE_SIZE]);
+
+        // then
+        assert!(matches!(DiskError::from_io(&wrong_key.unwrap_err()), Some(DiskError::DecryptionFailed(p)) if *p == pid));
+        assert!(matches!(DiskError::from_io(&tampered.unwrap_err()), Some(DiskError::DecryptionFailed(p)) if *p == pid));
+    }
+
+    #[test]
+    fn should_keep_previous_contents_readable_when_inner_write_fails() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let seal_path = dir.path().join("seals");
+        let mut dm = EncryptedDiskManager::new(FaultyDiskManager::new(FakeDiskManager::new()), &KEY, &seal_path).unwrap();
+        let pid = dm.allocate_page().unwrap();
+        let fresh_pid = dm.allocate_page().unwrap();
+        dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+        dm.inner.fail_nth_write(1, WriteFault::Fail);
+
+        // when
+        let err = dm.write_page(pid, &[2; PAGE_SIZE]);
+        dm.inner.fail_nth_write(2, WriteFault::Fail);
+        let batch_err = dm.write_pages(&[(pid, &[3; PAGE_SIZE]), (fresh_pid, &[4; PAGE_SIZE])]);
+        let reopened_generation = EncryptedDiskManager::new(FakeDiskManager::new(), &KEY, &seal_path).unwrap().seal_of(pid).generation;
+
+        // then
+        assert!(err.is_err());
+        assert!(batch_err.is_err());
+        assert_eq!(reopened_generation, 3);
+        let mut plaintext = [0u8; PAGE_SIZE];
+        dm.read_page(pid, &mut plaintext).unwrap();
+        assert_eq!(plaintext, [3; PAGE_SIZE]);
+        dm.read_page(fresh_pid, &mut plaintext).unwrap();
+        assert_eq!(plaintext, [0; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn should_read_page_written_under_pending_seal_before_crash() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = EncryptedDiskManager::new(FakeDiskManager::new(), &KEY, &dir.path().join("seals")).unwrap();
+        let pid = dm.allocate_page().unwrap();
+        dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+
+        // when
+        let ciphertext = dm.seal_page(pid, &[2; PAGE_SIZE]).unwrap();
+        dm.inner.write_page(pid, &ciphertext).unwrap();
+
+        // then
+        let mut plaintext = [0u8; PAGE_SIZE];
+        dm.read_page(pid, &mut plaintext).unwrap();
+        assert_eq!(plaintext, [2; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn should_refuse_plaintext_passed_off_as_unsealed_page() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = EncryptedDiskManager::new(FakeDiskManager::new(), &KEY, &dir.path().join("seals")).unwrap();
+        let pid = dm.allocate_page().unwrap();
+        dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+
+        // when
+        let seal = dm.seal_of(pid);
+        dm.store_seal(pid, Seal { sealed: false, ..seal }).unwrap();
+        dm.inner.write_page(pid, &[3; PAGE_SIZE]).unwrap();
+        let forged = dm.read_page(pid, &mut [0u8; PAGE_SIZE]);
+
+        // then
+        assert!(matches!(DiskError::from_io(&forged.unwrap_err()), Some(DiskError::DecryptionFailed(p)) if *p == pid));
+    }
+}
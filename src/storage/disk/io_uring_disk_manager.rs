@@ -0,0 +1,256 @@
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use io_uring::{opcode, types, IoUring};
+
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::storage::disk::direct_io::is_aligned;
+use crate::storage::disk::disk_manager::{AllocationStrategy, DiskManager, FileDiskManager, FileDiskOptions, PageRelocations, SyncMode};
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+
+pub const DEFAULT_QUEUE_DEPTH: u32 = 64;
+
+/// `FileDiskManager` doing its page IO through io_uring, so a batch of pages costs one syscall
+/// instead of a seek and a read or write per page.
+///
+/// Allocation, validation and the file layout stay with the wrapped `FileDiskManager`.
+pub struct IoUringDiskManager {
+    inner: FileDiskManager,
+    ring: IoUring,
+    queue_depth: usize,
+}
+
+impl IoUringDiskManager {
+    /// Double write is not supported, as pages go to the ring without being staged first.
+    pub fn new(file_path: &Path, options: FileDiskOptions, queue_depth: u32) -> Result<IoUringDiskManager> {
+        if options.double_write {
+            return Err(Error::new(ErrorKind::InvalidInput, "Double write is not supported with io_uring."))
+        }
+        Ok(IoUringDiskManager {
+            inner: FileDiskManager::new_with_options(file_path, options)?,
+            ring: IoUring::new(queue_depth)?,
+            queue_depth: queue_depth as usize,
+        })
+    }
+
+    // Pages go to the kernel as is, so direct IO needs buffers like `AlignedPage`.
+    fn validate_alignment(&self, data: &[u8]) -> Result<()> {
+        if self.inner.is_direct_io() && !is_aligned(data) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Direct IO needs page buffers aligned to 4096 bytes."))
+        }
+
+        Ok(())
+    }
+
+    // Every entry must complete with a full page; the first failure is reported with its page id.
+    fn submit_and_wait(&mut self, entries: &[io_uring::squeue::Entry], pids: &[PageId],
+                       operation: &'static str, short_io: ErrorKind) -> Result<()> {
+        {
+            let mut submission = self.ring.submission();
+            for entry in entries {
+                // safety: the caller keeps every buffer alive until this function returns,
+                // and it only returns once all entries completed
+                unsafe { submission.push(entry) }
+                    .map_err(|_| Error::other("io_uring submission queue is full."))?;
+            }
+        }
+        self.ring.submit_and_wait(entries.len())?;
+
+        let mut first_error = None;
+        for cqe in self.ring.completion().take(entries.len()) {
+            let pid = pids[cqe.user_data() as usize];
+            let result = match cqe.result() {
+                n if n < 0 => Err(Error::from_raw_os_error(-n)),
+                n if (n as usize) < PAGE_SIZE => Err(Error::new(short_io, "Short page IO.")),
+                _ => Ok(()),
+            };
+            if first_error.is_none() {
+                first_error = result.with_context(|| ErrorContext::new(operation).page(pid)).err();
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+}
+
+impl DiskManager for IoUringDiskManager {
+    fn allocate_page(&mut self) -> Result<PageId> {
+        self.inner.allocate_page()
+    }
+
+    fn allocate_page_with(&mut self, strategy: AllocationStrategy) -> Result<PageId> {
+        self.inner.allocate_page_with(strategy)
+    }
+
+    fn allocate_extent(&mut self, n: usize) -> Result<Range<PageId>> {
+        self.inner.allocate_extent(n)
+    }
+
+    fn deallocate_page(&mut self, page_id: PageId) -> Result<bool> {
+        self.inner.deallocate_page(page_id)
+    }
+
+    fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<()> {
+        self.write_pages(&[(page_id, page_data)])
+    }
+
+    fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> Result<()> {
+        self.read_pages(&mut [(page_id, page_data)])
+    }
+
+    /// Submits up to the queue depth of pages at once.
+    fn write_pages(&mut self, pages: &[(PageId, &[u8])]) -> Result<()> {
+        for (pid, data) in pages {
+            self.inner.validate_write(*pid, data.len())?;
+            self.validate_alignment(data)?;
+        }
+
+        let fd = types::Fd(self.inner.file().as_raw_fd());
+        for chunk in pages.chunks(self.queue_depth) {
+            let entries: Vec<_> = chunk.iter()
+                .enumerate()
+                .map(|(i, (pid, data))| {
+                    opcode::Write::new(fd, data.as_ptr(), PAGE_SIZE as u32)
+                        .offset((pid * PAGE_SIZE) as u64)
+                        .build()
+                        .user_data(i as u64)
+                })
+                .collect();
+            let pids: Vec<PageId> = chunk.iter().map(|(pid, _)| *pid).collect();
+            // buffers in `chunk` outlive the submission, which completes before returning
+            self.submit_and_wait(&entries, &pids, "write_page", ErrorKind::WriteZero)?;
+        }
+
+        for (pid, data) in pages {
+            self.inner.record_checksum(*pid, data)
+                .with_context(|| ErrorContext::new("write_page").page(*pid))?;
+        }
+        if self.inner.sync_mode() == SyncMode::Always {
+            self.inner.sync_files()?;
+        }
+        Ok(())
+    }
+
+    fn read_pages(&mut self, pages: &mut [(PageId, &mut [u8])]) -> Result<()> {
+        for (pid, data) in pages.iter() {
+            self.inner.validate_read(*pid, data.len())?;
+            self.validate_alignment(data)?;
+        }
+
+        let fd = types::Fd(self.inner.file().as_raw_fd());
+        let queue_depth = self.queue_depth;
+        for chunk in pages.chunks_mut(queue_depth) {
+            let entries: Vec<_> = chunk.iter_mut()
+                .enumerate()
+                .map(|(i, (pid, data))| {
+                    opcode::Read::new(fd, data.as_mut_ptr(), PAGE_SIZE as u32)
+                        .offset((*pid * PAGE_SIZE) as u64)
+                        .build()
+                        .user_data(i as u64)
+                })
+                .collect();
+            let pids: Vec<PageId> = chunk.iter().map(|(pid, _)| *pid).collect();
+            self.submit_and_wait(&entries, &pids, "read_page", ErrorKind::UnexpectedEof)?;
+        }
+
+        for (pid, data) in pages.iter() {
+            self.inner.verify_checksum(*pid, data)
+                .with_context(|| ErrorContext::new("read_page").page(*pid))?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()
+    }
+
+    fn defragment(&mut self) -> Result<PageRelocations> {
+        self.inner.defragment()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::disk::disk_manager::{AllocationStrategy, DiskManager, FileDiskOptions};
+    use crate::storage::disk::io_uring_disk_manager::{IoUringDiskManager, DEFAULT_QUEUE_DEPTH};
+    use crate::storage::page::page::{PageId, PAGE_SIZE};
+
+    #[test]
+    fn should_write_and_read_pages_in_batches_larger_than_queue() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = IoUringDiskManager::new(&dir.path().join("uring.data"), FileDiskOptions::default(), 4).unwrap();
+        let pids: Vec<PageId> = (0..10).map(|_| dm.allocate_page().unwrap()).collect();
+        let data: Vec<[u8; PAGE_SIZE]> = (0..10).map(|i| [i as u8 + 1; PAGE_SIZE]).collect();
+
+        // when
+        let writes: Vec<(PageId, &[u8])> = pids.iter().zip(data.iter()).map(|(p, d)| (*p, &d[..])).collect();
+        dm.write_pages(&writes).unwrap();
+        let mut read_back = vec![[0u8; PAGE_SIZE]; 10];
+        let mut reads: Vec<(PageId, &mut [u8])> = pids.iter().zip(read_back.iter_mut()).map(|(p, d)| (*p, &mut d[..])).collect();
+        dm.read_pages(&mut reads).unwrap();
+
+        // then
+        assert_eq!(read_back, data);
+    }
+
+    #[test]
+    fn should_validate_pages_before_submitting() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = IoUringDiskManager::new(&dir.path().join("uring.data"), FileDiskOptions::default(), DEFAULT_QUEUE_DEPTH).unwrap();
+        let pid = dm.allocate_page().unwrap();
+
+        // when
+        let result = dm.write_page(pid + 1, &[0; PAGE_SIZE]);
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::PageNotAllocated(p)) if *p == pid + 1));
+    }
+
+    #[test]
+    fn should_allocate_page_by_strategy_of_inner_disk_manager() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = IoUringDiskManager::new(&dir.path().join("uring.data"), FileDiskOptions::default(), DEFAULT_QUEUE_DEPTH).unwrap();
+        let pids: Vec<PageId> = (0..10).map(|_| dm.allocate_page().unwrap()).collect();
+        dm.deallocate_page(pids[2]).unwrap();
+        dm.deallocate_page(pids[7]).unwrap();
+
+        // when
+        let near = dm.allocate_page_with(AllocationStrategy::Near(pids[8])).unwrap();
+        let appended = dm.allocate_page_with(AllocationStrategy::Append).unwrap();
+        let first_fit = dm.allocate_page_with(AllocationStrategy::FirstFit).unwrap();
+
+        // then
+        assert_eq!(near, pids[7]);
+        assert_eq!(appended, pids[9] + 1);
+        assert_eq!(first_fit, pids[2]);
+    }
+
+    #[test]
+    fn should_allocate_extent_past_hole_left_by_deallocation() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = IoUringDiskManager::new(&dir.path().join("uring.data"), FileDiskOptions::default(), DEFAULT_QUEUE_DEPTH).unwrap();
+        let pids: Vec<PageId> = (0..4).map(|_| dm.allocate_page().unwrap()).collect();
+        dm.deallocate_page(pids[1]).unwrap();
+
+        // when
+        let extent = dm.allocate_extent(3).unwrap();
+
+        // then
+        assert_eq!(extent.len(), 3);
+        assert!(extent.start > pids[3]);
+        let data: Vec<[u8; PAGE_SIZE]> = (0..3).map(|i| [i as u8 + 1; PAGE_SIZE]).collect();
+        let writes: Vec<(PageId, &[u8])> = extent.clone().zip(data.iter()).map(|(p, d)| (p, &d[..])).collect();
+        dm.write_pages(&writes).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Result;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::storage::disk::disk_manager::{AllocationStrategy, DiskManager, PageRelocations};
+use crate::storage::disk::disk_stats::{DiskMetrics, DiskOp};
+use crate::storage::page::page::PageId;
+
+/// Disk manager wrapper recording every call of `D` into `DiskMetrics`.
+///
+/// Works with any backend. `FileDiskManager` can record by itself instead, which also counts the
+/// fsyncs its sync mode does on every write.
+pub struct MeteredDiskManager<D: DiskManager> {
+    inner: D,
+    metrics: Arc<DiskMetrics>,
+}
+
+impl<D: DiskManager> MeteredDiskManager<D> {
+    pub fn new(inner: D, metrics: Arc<DiskMetrics>) -> MeteredDiskManager<D> {
+        MeteredDiskManager { inner, metrics }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn metrics(&self) -> &Arc<DiskMetrics> {
+        &self.metrics
+    }
+}
+
+impl<D: DiskManager> DiskManager for MeteredDiskManager<D> {
+    fn allocate_page(&mut self) -> Result<PageId> {
+        let start = Instant::now();
+        let result = self.inner.allocate_page();
+        self.metrics.record_since(DiskOp::Allocate, 1, start);
+        result
+    }
+
+    fn allocate_page_with(&mut self, strategy: AllocationStrategy) -> Result<PageId> {
+        let start = Instant::now();
+        let result = self.inner.allocate_page_with(strategy);
+        self.metrics.record_since(DiskOp::Allocate, 1, start);
+        result
+    }
+
+    fn allocate_extent(&mut self, n: usize) -> Result<Range<PageId>> {
+        let start = Instant::now();
+        let result = self.inner.allocate_extent(n);
+        self.metrics.record_since(DiskOp::Allocate, n, start);
+        result
+    }
+
+    fn deallocate_page(&mut self, page_id: PageId) -> Result<bool> {
+        self.inner.deallocate_page(page_id)
+    }
+
+    fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.write_page(page_id, page_data);
+        self.metrics.record_since(DiskOp::Write, 1, start);
+        result
+    }
+
+    fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.read_page(page_id, page_data);
+        self.metrics.record_since(DiskOp::Read, 1, start);
+        result
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    fn write_pages<'a>(&mut self, pages: &[(PageId, &'a [u8])]) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.write_pages(pages);
+        self.metrics.record_since(DiskOp::Write, pages.len(), start);
+        result
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    fn read_pages<'a>(&mut self, pages: &mut [(PageId, &'a mut [u8])]) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.read_pages(pages);
+        self.metrics.record_since(DiskOp::Read, pages.len(), start);
+        result
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.sync();
+        self.metrics.record_since(DiskOp::Sync, 0, start);
+        result
+    }
+
+    fn defragment(&mut self) -> Result<PageRelocations> {
+        self.inner.defragment()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::storage::disk::disk_manager::{DiskManager, FakeDiskManager, FileDiskManager};
+    use crate::storage::disk::disk_stats::DiskMetrics;
+    use crate::storage::disk::metered_disk_manager::MeteredDiskManager;
+    use crate::storage::page::page::PAGE_SIZE;
+
+    #[test]
+    fn should_record_every_call_of_inner_disk_manager() {
+        // given
+        let metrics = Arc::new(DiskMetrics::new());
+        let mut dm = MeteredDiskManager::new(FakeDiskManager::new(), metrics.clone());
+        let pid = dm.allocate_page().unwrap();
+        let other_pid = dm.allocate_page().unwrap();
+
+        // when
+        dm.write_pages(&[(pid, &[1; PAGE_SIZE]), (other_pid, &[2; PAGE_SIZE])]).unwrap();
+        dm.read_page(pid, &mut [0; PAGE_SIZE]).unwrap();
+        dm.sync().unwrap();
+
+        // then
+        let stats = metrics.snapshot();
+        assert_eq!((stats.allocations.ops, stats.allocations.pages), (2, 2));
+        assert_eq!((stats.writes.ops, stats.writes.pages), (1, 2));
+        assert_eq!((stats.reads.ops, stats.reads.pages), (1, 1));
+        assert_eq!(stats.syncs.ops, 1);
+        assert_eq!(stats.reads.latency.count(), 1);
+    }
+
+    #[test]
+    fn should_defragment_inner_disk_manager() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let mut dm = MeteredDiskManager::new(FileDiskManager::new(&dir.path().join("metered.data")).unwrap(), Arc::new(DiskMetrics::new()));
+        let pids: Vec<_> = (0..2).map(|_| dm.allocate_page().unwrap()).collect();
+        dm.write_page(pids[1], &[1; PAGE_SIZE]).unwrap();
+        dm.deallocate_page(pids[0]).unwrap();
+
+        // when
+        let relocations = dm.defragment().unwrap();
+
+        // then
+        assert_eq!(relocations.resolve(pids[1]), pids[0]);
+    }
+}
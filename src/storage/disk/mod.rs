@@ -1 +1,14 @@
-pub mod disk_manager;
\ No newline at end of file
+pub mod disk_manager;
+pub mod disk_error;
+pub mod direct_io;
+pub mod platform;
+pub mod checksum;
+pub mod double_write;
+pub mod disk_stats;
+pub mod metered_disk_manager;
+pub mod object_store_disk_manager;
+pub mod registry;
+#[cfg(feature = "encryption")]
+pub mod encrypted_disk_manager;
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+pub mod io_uring_disk_manager;
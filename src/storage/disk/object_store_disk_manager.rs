@@ -0,0 +1,368 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+
+use crate::common::error::{ErrorContext, ResultExt};
+use crate::storage::disk::disk_error::DiskError;
+use crate::storage::disk::disk_manager::DiskManager;
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+
+/// Pages per segment object: 1 MiB objects.
+pub const SEGMENT_PAGES: usize = 256;
+const SEGMENT_SIZE: usize = SEGMENT_PAGES * PAGE_SIZE;
+const MANIFEST_KEY: &str = "manifest";
+
+/// Minimal client of an S3-compatible store: ranged GETs and whole object PUTs.
+pub trait ObjectStore: Send {
+    /// `None` when there is no such object. The returned bytes may stop short of the range end.
+    fn get_range(&mut self, key: &str, range: Range<usize>) -> Result<Option<Vec<u8>>>;
+
+    fn put(&mut self, key: &str, data: &[u8]) -> Result<()>;
+}
+
+struct CachedPage {
+    data: Box<[u8; PAGE_SIZE]>,
+    dirty: bool,
+}
+
+/// Disk manager keeping pages in segment objects of an object store, behind a local write-back cache.
+///
+/// A page is read with a ranged GET of its segment. Writes stay in the cache until `sync`, or until
+/// the cache is full of dirty pages, and then go out as one PUT per touched segment, since objects
+/// cannot be written in part. The manifest object records how many pages exist and which of them
+/// are deallocated, so their space is reused after reopening too.
+pub struct ObjectStoreDiskManager<S: ObjectStore> {
+    store: S,
+    prefix: String,
+    num_pages: usize,
+    free_pages: BTreeSet<PageId>,
+    cache: HashMap<PageId, CachedPage>,
+    cache_pages: usize,
+}
+
+impl<S: ObjectStore> ObjectStoreDiskManager<S> {
+    /// Objects are named after `prefix`, so one bucket can hold several databases.
+    pub fn open(mut store: S, prefix: &str, cache_pages: usize) -> Result<ObjectStoreDiskManager<S>> {
+        let manifest_key = format!("{}/{}", prefix, MANIFEST_KEY);
+        let (num_pages, num_free_pages) = match store.get_range(&manifest_key, 0..16)? {
+            // manifests written before the free pages were recorded
+            Some(raw) if raw.len() == 8 => (read_u64(&raw[0..8]), 0),
+            Some(raw) if raw.len() == 16 => (read_u64(&raw[0..8]), read_u64(&raw[8..16])),
+            Some(_) => return Err(malformed_manifest()),
+            None => (0, 0),
+        };
+        let free_pages: BTreeSet<PageId> = match num_free_pages {
+            0 => BTreeSet::new(),
+            n => {
+                let raw = store.get_range(&manifest_key, 16..16 + 8 * n)?.ok_or_else(malformed_manifest)?;
+                if raw.len() != 8 * n {
+                    return Err(malformed_manifest())
+                }
+                raw.chunks_exact(8).map(read_u64).collect()
+            }
+        };
+        if free_pages.iter().any(|pid| *pid >= num_pages) {
+            return Err(malformed_manifest())
+        }
+
+        Ok(ObjectStoreDiskManager {
+            store,
+            prefix: prefix.to_string(),
+            num_pages,
+            free_pages,
+            cache: HashMap::new(),
+            cache_pages: cache_pages.max(1),
+        })
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    fn segment_key(&self, segment: usize) -> String {
+        format!("{}/segment-{:08}", self.prefix, segment)
+    }
+
+    fn validate_allocation(&self, pid: PageId) -> Result<()> {
+        if pid >= self.num_pages {
+            return Err(DiskError::InvalidPageId(pid).into())
+        }
+        if self.free_pages.contains(&pid) {
+            return Err(DiskError::PageNotAllocated(pid).into())
+        }
+
+        Ok(())
+    }
+
+    fn fetch_page(&mut self, pid: PageId) -> Result<Box<[u8; PAGE_SIZE]>> {
+        let offset = (pid % SEGMENT_PAGES) * PAGE_SIZE;
+        let key = self.segment_key(pid / SEGMENT_PAGES);
+        let mut page_data = Box::new([0u8; PAGE_SIZE]);
+        // pages past the end of a segment object were never written
+        if let Some(raw) = self.store.get_range(&key, offset..offset + PAGE_SIZE)? {
+            page_data[..raw.len().min(PAGE_SIZE)].copy_from_slice(&raw[..raw.len().min(PAGE_SIZE)]);
+        }
+        Ok(page_data)
+    }
+
+    // Makes room for one more page, writing back dirty pages only when no clean one can go.
+    fn make_room(&mut self) -> Result<()> {
+        if self.cache.len() < self.cache_pages {
+            return Ok(())
+        }
+        if !self.cache.values().any(|page| !page.dirty) {
+            self.write_back()?;
+        }
+
+        let victim = self.cache.iter()
+            .find(|(_, page)| !page.dirty)
+            .map(|(pid, _)| *pid);
+        if let Some(victim) = victim {
+            self.cache.remove(&victim);
+        }
+        Ok(())
+    }
+
+    fn cache_page(&mut self, pid: PageId, data: Box<[u8; PAGE_SIZE]>, dirty: bool) -> Result<()> {
+        if !self.cache.contains_key(&pid) {
+            self.make_room()?;
+        }
+        self.cache.insert(pid, CachedPage { data, dirty });
+        Ok(())
+    }
+
+    // PUTs every segment holding dirty pages, merged into what the store has of it.
+    fn write_back(&mut self) -> Result<()> {
+        let dirty_segments: BTreeSet<usize> = self.cache.iter()
+            .filter(|(_, page)| page.dirty)
+            .map(|(pid, _)| pid / SEGMENT_PAGES)
+            .collect();
+
+        for segment in dirty_segments {
+            let key = self.segment_key(segment);
+            let mut segment_data = self.store.get_range(&key, 0..SEGMENT_SIZE)?.unwrap_or_default();
+            let pids: Vec<PageId> = self.cache.iter()
+                .filter(|(pid, page)| page.dirty && *pid / SEGMENT_PAGES == segment)
+                .map(|(pid, _)| *pid)
+                .collect();
+            for pid in &pids {
+                let offset = (pid % SEGMENT_PAGES) * PAGE_SIZE;
+                if segment_data.len() < offset + PAGE_SIZE {
+                    segment_data.resize(offset + PAGE_SIZE, 0);
+                }
+                segment_data[offset..offset + PAGE_SIZE].copy_from_slice(&self.cache[pid].data[..]);
+            }
+
+            self.store.put(&key, &segment_data)
+                .with_context(|| ErrorContext::new("put_segment").page(segment * SEGMENT_PAGES))?;
+            for pid in pids {
+                if let Some(page) = self.cache.get_mut(&pid) {
+                    page.dirty = false;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: ObjectStore> DiskManager for ObjectStoreDiskManager<S> {
+    /// Reuses the lowest deallocated page first. The page reads as zeroes.
+    fn allocate_page(&mut self) -> Result<PageId> {
+        match self.free_pages.iter().next().copied() {
+            Some(pid) => {
+                self.cache_page(pid, Box::new([0; PAGE_SIZE]), true)?;
+                self.free_pages.remove(&pid);
+                Ok(pid)
+            },
+            // never written, so not in any segment object yet
+            None => {
+                self.num_pages += 1;
+                Ok(self.num_pages - 1)
+            }
+        }
+    }
+
+    fn deallocate_page(&mut self, page_id: PageId) -> Result<bool> {
+        self.validate_allocation(page_id)?;
+        self.free_pages.insert(page_id);
+        Ok(true)
+    }
+
+    fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> Result<()> {
+        self.validate_allocation(page_id)?;
+        if page_data.len() != PAGE_SIZE {
+            return Err(DiskError::WrongPageDataSize { expected: PAGE_SIZE, actual: page_data.len() }.into())
+        }
+
+        match self.cache.get_mut(&page_id) {
+            Some(page) => {
+                page.data.copy_from_slice(page_data);
+                page.dirty = true;
+                Ok(())
+            },
+            None => self.cache_page(page_id, Box::new(page_data.try_into().unwrap()), true)
+        }
+    }
+
+    fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> Result<()> {
+        self.validate_allocation(page_id)?;
+        if page_data.len() != PAGE_SIZE {
+            return Err(DiskError::WrongPageDataSize { expected: PAGE_SIZE, actual: page_data.len() }.into())
+        }
+
+        if let Some(page) = self.cache.get(&page_id) {
+            page_data.copy_from_slice(&page.data[..]);
+            return Ok(())
+        }
+        let fetched = self.fetch_page(page_id)
+            .with_context(|| ErrorContext::new("get_page").page(page_id))?;
+        page_data.copy_from_slice(&fetched[..]);
+        self.cache_page(page_id, fetched, false)
+    }
+
+    /// Writes back the dirty segments, then the manifest.
+    fn sync(&mut self) -> Result<()> {
+        self.write_back()?;
+        let manifest_key = format!("{}/{}", self.prefix, MANIFEST_KEY);
+        let mut manifest = Vec::with_capacity(16 + 8 * self.free_pages.len());
+        manifest.extend_from_slice(&(self.num_pages as u64).to_le_bytes());
+        manifest.extend_from_slice(&(self.free_pages.len() as u64).to_le_bytes());
+        for pid in &self.free_pages {
+            manifest.extend_from_slice(&(*pid as u64).to_le_bytes());
+        }
+        self.store.put(&manifest_key, &manifest)
+    }
+}
+
+fn read_u64(raw: &[u8]) -> usize {
+    u64::from_le_bytes(raw.try_into().unwrap()) as usize
+}
+
+fn malformed_manifest() -> Error {
+    Error::new(ErrorKind::InvalidData, "Malformed manifest object.")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Result;
+    use std::ops::Range;
+
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::disk::disk_manager::DiskManager;
+    use crate::storage::disk::object_store_disk_manager::{ObjectStore, ObjectStoreDiskManager, SEGMENT_PAGES};
+    use crate::storage::page::page::PAGE_SIZE;
+
+    #[derive(Default)]
+    struct MemoryObjectStore {
+        objects: HashMap<String, Vec<u8>>,
+        puts: usize,
+    }
+
+    impl ObjectStore for MemoryObjectStore {
+        fn get_range(&mut self, key: &str, range: Range<usize>) -> Result<Option<Vec<u8>>> {
+            Ok(self.objects.get(key).map(|object| {
+                object[range.start.min(object.len())..range.end.min(object.len())].to_vec()
+            }))
+        }
+
+        fn put(&mut self, key: &str, data: &[u8]) -> Result<()> {
+            self.puts += 1;
+            self.objects.insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_keep_writes_in_cache_until_sync_and_read_them_after_reopen() {
+        // given
+        let mut dm = ObjectStoreDiskManager::open(MemoryObjectStore::default(), "db", 16).unwrap();
+        let pids: Vec<usize> = (0..3).map(|_| dm.allocate_page().unwrap()).collect();
+        dm.write_page(pids[0], &[1; PAGE_SIZE]).unwrap();
+        dm.write_page(pids[2], &[3; PAGE_SIZE]).unwrap();
+        assert_eq!(dm.store().puts, 0);
+
+        // when
+        dm.sync().unwrap();
+        let mut dm = ObjectStoreDiskManager::open(dm.into_store(), "db", 16).unwrap();
+
+        // then
+        assert_eq!(dm.store().puts, 2);
+        assert_eq!(dm.num_pages(), 3);
+        let mut page_data = [9u8; PAGE_SIZE];
+        dm.read_page(pids[2], &mut page_data).unwrap();
+        assert_eq!(page_data, [3; PAGE_SIZE]);
+        dm.read_page(pids[1], &mut page_data).unwrap();
+        assert_eq!(page_data, [0; PAGE_SIZE]);
+        let err = dm.read_page(3, &mut page_data).unwrap_err();
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::InvalidPageId(3))));
+    }
+
+    #[test]
+    fn should_write_back_segments_when_cache_is_full_of_dirty_pages() {
+        // given
+        let mut dm = ObjectStoreDiskManager::open(MemoryObjectStore::default(), "db", 2).unwrap();
+        for _ in 0..SEGMENT_PAGES + 1 {
+            dm.allocate_page().unwrap();
+        }
+        dm.write_page(0, &[1; PAGE_SIZE]).unwrap();
+        dm.write_page(SEGMENT_PAGES, &[2; PAGE_SIZE]).unwrap();
+
+        // when
+        dm.write_page(1, &[3; PAGE_SIZE]).unwrap();
+
+        // then
+        let store = dm.store();
+        assert_eq!(store.objects["db/segment-00000001"], vec![2; PAGE_SIZE]);
+        assert_eq!(&store.objects["db/segment-00000000"][..PAGE_SIZE], &[1; PAGE_SIZE][..]);
+        let mut page_data = [0u8; PAGE_SIZE];
+        dm.read_page(1, &mut page_data).unwrap();
+        assert_eq!(page_data, [3; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn should_reuse_page_deallocated_before_reopen() {
+        // given
+        let mut dm = ObjectStoreDiskManager::open(MemoryObjectStore::default(), "db", 16).unwrap();
+        let pids: Vec<usize> = (0..3).map(|_| dm.allocate_page().unwrap()).collect();
+        dm.write_page(pids[1], &[1; PAGE_SIZE]).unwrap();
+        dm.deallocate_page(pids[1]).unwrap();
+        dm.sync().unwrap();
+
+        // when
+        let mut dm = ObjectStoreDiskManager::open(dm.into_store(), "db", 16).unwrap();
+        let mut page_data = [0u8; PAGE_SIZE];
+        let err = dm.read_page(pids[1], &mut page_data).unwrap_err();
+        let reused = dm.allocate_page().unwrap();
+
+        // then
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::PageNotAllocated(1))));
+        assert_eq!(reused, pids[1]);
+        assert_eq!(dm.num_pages(), 3);
+        dm.read_page(reused, &mut page_data).unwrap();
+        assert_eq!(page_data, [0; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn should_open_manifest_without_free_pages() {
+        // given
+        let mut store = MemoryObjectStore::default();
+        store.put("db/manifest", &5u64.to_le_bytes()).unwrap();
+
+        // when
+        let mut dm = ObjectStoreDiskManager::open(store, "db", 16).unwrap();
+
+        // then
+        assert_eq!(dm.num_pages(), 5);
+        assert_eq!(dm.allocate_page().unwrap(), 5);
+    }
+}
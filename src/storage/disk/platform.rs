@@ -0,0 +1,85 @@
+use std::fs::{File, TryLockError};
+use std::io::Result;
+use std::path::Path;
+
+use crate::storage::disk::disk_error::DiskError;
+
+/// Size of the file in bytes.
+pub fn file_size(file: &File) -> Result<u64> {
+    Ok(file.metadata()?.len())
+}
+
+/// Grows the file to `len` bytes with the disk space reserved where the platform can, so running out of
+/// space fails here rather than on a later page write. Never shrinks the file.
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &File, len: u64) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let current_len = file_size(file)?;
+    if len <= current_len {
+        return Ok(())
+    }
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), current_len as libc::off_t, (len - current_len) as libc::off_t) } {
+        0 => Ok(()),
+        // file systems without fallocate still grow, sparse
+        libc::EOPNOTSUPP | libc::EINVAL => file.set_len(len),
+        errno => Err(std::io::Error::from_raw_os_error(errno))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(file: &File, len: u64) -> Result<()> {
+    if len <= file_size(file)? {
+        return Ok(())
+    }
+    file.set_len(len)
+}
+
+/// Takes an advisory exclusive lock on the file, held until the file is closed, so a second process
+/// cannot open the same data file. Fails at once if the file is already locked.
+pub fn lock_exclusive(file: &File, file_path: &Path) -> Result<()> {
+    match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(DiskError::FileLocked(file_path.display().to_string()).into()),
+        Err(TryLockError::Error(e)) => Err(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::disk::platform::{file_size, lock_exclusive, preallocate};
+
+    #[test]
+    fn should_preallocate_without_shrinking() {
+        // given
+        let file = tempfile::tempfile().unwrap();
+
+        // when
+        preallocate(&file, 8192).unwrap();
+        preallocate(&file, 4096).unwrap();
+
+        // then
+        assert_eq!(file_size(&file).unwrap(), 8192);
+    }
+
+    #[test]
+    fn should_refuse_second_lock_of_same_file() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("locked.data");
+        let open = || OpenOptions::new().create(true).truncate(false).read(true).write(true).open(&path).unwrap();
+        let file = open();
+        lock_exclusive(&file, &path).unwrap();
+
+        // when
+        let err = lock_exclusive(&open(), &path).unwrap_err();
+
+        // then
+        assert!(matches!(DiskError::from_io(&err), Some(DiskError::FileLocked(_))));
+        drop(file);
+        assert!(lock_exclusive(&open(), &path).is_ok());
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::io::Result;
+use std::path::Path;
+
+use crate::storage::disk::disk_error::DiskError;
+use crate::storage::disk::disk_manager::{DiskManager, FakeDiskManager, FileDiskManager, FileDiskOptions};
+
+/// Builds a disk manager over the data file at the path. Backends without a file ignore it.
+pub type BackendFactory = fn(&Path, FileDiskOptions) -> Result<Box<dyn DiskManager>>;
+
+pub const FAKE_BACKEND: &str = "fake";
+pub const FILE_BACKEND: &str = "file";
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+pub const IO_URING_BACKEND: &str = "io_uring";
+
+fn open_fake(_: &Path, _: FileDiskOptions) -> Result<Box<dyn DiskManager>> {
+    Ok(Box::new(FakeDiskManager::new()))
+}
+
+fn open_file(path: &Path, options: FileDiskOptions) -> Result<Box<dyn DiskManager>> {
+    Ok(Box::new(FileDiskManager::new_with_options(path, options)?))
+}
+
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+fn open_io_uring(path: &Path, options: FileDiskOptions) -> Result<Box<dyn DiskManager>> {
+    use crate::storage::disk::io_uring_disk_manager::{IoUringDiskManager, DEFAULT_QUEUE_DEPTH};
+    Ok(Box::new(IoUringDiskManager::new(path, options, DEFAULT_QUEUE_DEPTH)?))
+}
+
+/// Disk manager backends by name, so which one to use can come from configuration.
+pub struct BackendRegistry {
+    backends: BTreeMap<String, BackendFactory>,
+}
+
+impl BackendRegistry {
+    pub fn new() -> BackendRegistry {
+        BackendRegistry { backends: BTreeMap::new() }
+    }
+
+    /// The backends of this crate: `fake`, `file`, and `io_uring` when compiled in.
+    pub fn with_builtin() -> BackendRegistry {
+        let mut registry = BackendRegistry::new();
+        registry.register(FAKE_BACKEND, open_fake);
+        registry.register(FILE_BACKEND, open_file);
+        #[cfg(all(target_os = "linux", feature = "io_uring"))]
+        registry.register(IO_URING_BACKEND, open_io_uring);
+        registry
+    }
+
+    /// Returns the factory registered under the name before, if any.
+    pub fn register(&mut self, name: &str, factory: BackendFactory) -> Option<BackendFactory> {
+        self.backends.insert(name.to_string(), factory)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.backends.keys().map(|name| name.as_str()).collect()
+    }
+
+    pub fn open(&self, name: &str, path: &Path, options: FileDiskOptions) -> Result<Box<dyn DiskManager>> {
+        match self.backends.get(name) {
+            Some(factory) => factory(path, options),
+            None => Err(DiskError::UnknownBackend(name.to_string()).into()),
+        }
+    }
+}
+
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        BackendRegistry::with_builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::storage::disk::disk_error::DiskError;
+    use crate::storage::disk::disk_manager::FileDiskOptions;
+    use crate::storage::disk::registry::{BackendRegistry, FAKE_BACKEND, FILE_BACKEND};
+    use crate::storage::page::page::PAGE_SIZE;
+
+    #[test]
+    fn should_open_backends_by_name() {
+        // given
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("minedb.data");
+        let mut registry = BackendRegistry::with_builtin();
+        registry.register("fake_again", |path, options| BackendRegistry::with_builtin().open(FAKE_BACKEND, path, options));
+
+        // when
+        let mut file_dm = registry.open(FILE_BACKEND, &path, FileDiskOptions::default()).unwrap();
+        let mut fake_dm = registry.open("fake_again", Path::new(""), FileDiskOptions::default()).unwrap();
+        let unknown = registry.open("mmap", &path, FileDiskOptions::default());
+
+        // then
+        let pid = file_dm.allocate_page().unwrap();
+        file_dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+        assert!(path.exists());
+        assert_eq!(fake_dm.allocate_page().unwrap(), 0);
+        assert!(matches!(DiskError::from_io(&unknown.err().unwrap()), Some(DiskError::UnknownBackend(name)) if name == "mmap"));
+        assert!(registry.names().contains(&"fake_again"));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod page;
-pub mod disk;
\ No newline at end of file
+pub mod disk;
+pub mod table;
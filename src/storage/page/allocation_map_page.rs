@@ -0,0 +1,85 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+
+/// v1: version byte, LSN, page type, next page id, then the bitmap bytes.
+const ALLOCATION_MAP_PAGE_VERSION: PageVersion = 1;
+const BITMAP_OFFSET: usize = PAGE_HEADER_SIZE + mem::size_of::<u64>();
+/// Bitmap bytes one map page holds.
+pub const ALLOCATION_MAP_CAPACITY: usize = PAGE_SIZE - BITMAP_OFFSET;
+
+/// Part of the allocation bitmap of a data file past the part the superblock page holds, one bit per
+/// page, set when allocated. The pages are chained in the order of the part of the bitmap they hold.
+pub struct AllocationMapPage {
+    page_lsn: Lsn,
+    next_page_id: Option<PageId>,
+    bitmap: Vec<u8>,
+}
+
+impl AllocationMapPage {
+    /// Bitmap bytes past `ALLOCATION_MAP_CAPACITY` are left out, missing ones read as free pages.
+    pub fn new(bitmap: &[u8], next_page_id: Option<PageId>) -> AllocationMapPage {
+        let mut page_bitmap = vec![0; ALLOCATION_MAP_CAPACITY];
+        let len = bitmap.len().min(ALLOCATION_MAP_CAPACITY);
+        page_bitmap[..len].copy_from_slice(&bitmap[..len]);
+        AllocationMapPage { page_lsn: 0, next_page_id, bitmap: page_bitmap }
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_next_page_id(&self) -> Option<PageId> {
+        self.next_page_id
+    }
+
+    pub fn get_bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut res = vec![ALLOCATION_MAP_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::AllocationMap as u8);
+        res.extend_from_slice(&(self.next_page_id.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        res.extend_from_slice(&self.bitmap);
+        res
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<AllocationMapPage> {
+        match read_page_version(page_data)? {
+            ALLOCATION_MAP_PAGE_VERSION => check_page_type(page_data, PageType::AllocationMap)?,
+            version => return Err(PageError::UnknownVersion { found: version, supported: ALLOCATION_MAP_PAGE_VERSION }.into()),
+        }
+        if page_data.len() < PAGE_SIZE {
+            return Err(PageError::PageDataTooShort { expected: PAGE_SIZE, actual: page_data.len() }.into());
+        }
+
+        let next_page_id = u64::from_le_bytes(page_data[PAGE_HEADER_SIZE..BITMAP_OFFSET].try_into().unwrap()) as PageId;
+        Ok(AllocationMapPage {
+            page_lsn: read_page_lsn(page_data)?,
+            next_page_id: if next_page_id == INVALID_PAGE_ID { None } else { Some(next_page_id) },
+            bitmap: page_data[BITMAP_OFFSET..PAGE_SIZE].to_vec(),
+        })
+    }
+}
+
+impl PageSerde for AllocationMapPage {
+    const VERSION: PageVersion = ALLOCATION_MAP_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize(), page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<AllocationMapPage> {
+        AllocationMapPage::deserialize(page_data)
+    }
+}
@@ -0,0 +1,78 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use crate::storage::page::page::PageId;
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+
+/// v1: version byte, LSN, page type, root page id, then the comparator name, bincode encoded.
+const META_PAGE_VERSION: PageVersion = 1;
+const COMPARATOR_OFFSET: usize = PAGE_HEADER_SIZE + mem::size_of::<u64>();
+
+/// First page of a B+ tree, recording where its root is and the comparator its keys are ordered by.
+pub struct BPlusTreeMetaPage {
+    page_lsn: Lsn,
+    root_page_id: PageId,
+    comparator: String,
+}
+
+impl BPlusTreeMetaPage {
+    pub fn new(root_page_id: PageId, comparator: String) -> BPlusTreeMetaPage {
+        BPlusTreeMetaPage { page_lsn: 0, root_page_id, comparator }
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_root_page_id(&self) -> PageId {
+        self.root_page_id
+    }
+
+    /// Name of the `KeyComparator` the tree was created with.
+    pub fn get_comparator(&self) -> &str {
+        &self.comparator
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![META_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::BPlusTreeMeta as u8);
+        res.extend_from_slice(&(self.root_page_id as u64).to_le_bytes());
+        bincode::serialize_into(&mut res, &self.comparator).map_err(PageError::from)?;
+        Ok(res)
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<BPlusTreeMetaPage> {
+        match read_page_version(page_data)? {
+            META_PAGE_VERSION => check_page_type(page_data, PageType::BPlusTreeMeta)?,
+            version => return Err(PageError::UnknownVersion { found: version, supported: META_PAGE_VERSION }.into()),
+        }
+        if page_data.len() < COMPARATOR_OFFSET {
+            return Err(PageError::PageDataTooShort { expected: COMPARATOR_OFFSET, actual: page_data.len() }.into());
+        }
+
+        Ok(BPlusTreeMetaPage {
+            page_lsn: read_page_lsn(page_data)?,
+            root_page_id: u64::from_le_bytes(page_data[PAGE_HEADER_SIZE..COMPARATOR_OFFSET].try_into().unwrap()) as PageId,
+            comparator: bincode::deserialize(&page_data[COMPARATOR_OFFSET..]).map_err(PageError::from)?,
+        })
+    }
+}
+
+impl PageSerde for BPlusTreeMetaPage {
+    const VERSION: PageVersion = META_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<BPlusTreeMetaPage> {
+        BPlusTreeMetaPage::deserialize(page_data)
+    }
+}
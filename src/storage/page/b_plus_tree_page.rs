@@ -0,0 +1,352 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::common::compare::KeyComparator;
+use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, PAGE_TYPE_OFFSET, read_page_lsn, read_page_version};
+
+/// v1, leaf: version byte, LSN, page type, next leaf id, then the entries in key order, bincode encoded.
+/// v1, internal: version byte, LSN, page type, then the separator keys and the child ids, bincode encoded.
+const B_PLUS_TREE_PAGE_VERSION: PageVersion = 1;
+const LEAF_BODY_OFFSET: usize = PAGE_HEADER_SIZE + mem::size_of::<u64>();
+
+/// Leaf of a B+ tree, holding entries sorted by the comparator of the tree and pointing to the leaf holding the next keys.
+pub struct BPlusTreeLeafPage<K, V> {
+    page_lsn: Lsn,
+    next_page_id: Option<PageId>,
+    entries: Vec<(K, V)>,
+}
+
+impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> BPlusTreeLeafPage<K, V> {
+    pub fn new() -> BPlusTreeLeafPage<K, V> {
+        BPlusTreeLeafPage { page_lsn: 0, next_page_id: None, entries: Vec::new() }
+    }
+
+    /// Largest encoded entry accepted, small enough for any node to split into halves that fit a page.
+    pub fn max_entry_size() -> usize {
+        (PAGE_SIZE - LEAF_BODY_OFFSET) / 4
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_next_page_id(&self) -> Option<PageId> {
+        self.next_page_id
+    }
+
+    pub fn set_next_page_id(&mut self, next_page_id: Option<PageId>) {
+        self.next_page_id = next_page_id
+    }
+
+    pub fn entries(&self) -> &[(K, V)] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &K, cmp: &dyn KeyComparator<K>) -> Option<&V> {
+        self.position(key, cmp).ok().map(|idx| &self.entries[idx].1)
+    }
+
+    /// Keeps the entries in the order of `cmp`. Returns false, leaving the leaf unchanged, if `key` is already there.
+    pub fn insert(&mut self, key: K, value: V, cmp: &dyn KeyComparator<K>) -> bool {
+        match self.position(&key, cmp) {
+            Ok(_) => false,
+            Err(idx) => {
+                self.entries.insert(idx, (key, value));
+                true
+            }
+        }
+    }
+
+    /// Returns whether `key` was there.
+    pub fn remove(&mut self, key: &K, cmp: &dyn KeyComparator<K>) -> bool {
+        match self.position(key, cmp) {
+            Ok(idx) => {
+                self.entries.remove(idx);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Takes over the entries of `right`, the next leaf, and where it points to.
+    pub fn merge(&mut self, mut right: BPlusTreeLeafPage<K, V>) {
+        self.entries.append(&mut right.entries);
+        self.next_page_id = right.next_page_id;
+    }
+
+    /// Moves the upper half of the entries into a new leaf pointing where this one did. Link this leaf
+    /// to the new one once it has a page.
+    pub fn split(&mut self) -> BPlusTreeLeafPage<K, V> {
+        let upper = self.entries.split_off(self.entries.len() / 2);
+        BPlusTreeLeafPage { page_lsn: 0, next_page_id: self.next_page_id, entries: upper }
+    }
+
+    /// Bytes of the page the encoded leaf takes.
+    pub fn encoded_size(&self) -> io::Result<usize> {
+        Ok(LEAF_BODY_OFFSET + bincode::serialized_size(&self.entries).map_err(PageError::from)? as usize)
+    }
+
+    /// Whether the encoded leaf still fits into a page.
+    pub fn fits(&self) -> io::Result<bool> {
+        Ok(self.encoded_size()? <= PAGE_SIZE)
+    }
+
+    fn position(&self, key: &K, cmp: &dyn KeyComparator<K>) -> Result<usize, usize> {
+        self.entries.binary_search_by(|(k, _)| cmp.compare(k, key))
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![B_PLUS_TREE_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::BPlusTreeLeaf as u8);
+        res.extend_from_slice(&(self.next_page_id.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        bincode::serialize_into(&mut res, &self.entries).map_err(PageError::from)?;
+        Ok(res)
+    }
+
+    fn deserialize(page_data: &[u8]) -> io::Result<BPlusTreeLeafPage<K, V>> {
+        if page_data.len() < LEAF_BODY_OFFSET {
+            return Err(PageError::PageDataTooShort { expected: LEAF_BODY_OFFSET, actual: page_data.len() }.into());
+        }
+
+        let next_page_id = u64::from_le_bytes(page_data[PAGE_HEADER_SIZE..LEAF_BODY_OFFSET].try_into().unwrap()) as PageId;
+        Ok(BPlusTreeLeafPage {
+            page_lsn: read_page_lsn(page_data)?,
+            next_page_id: if next_page_id == INVALID_PAGE_ID { None } else { Some(next_page_id) },
+            entries: bincode::deserialize(&page_data[LEAF_BODY_OFFSET..]).map_err(PageError::from)?,
+        })
+    }
+}
+
+impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> Default for BPlusTreeLeafPage<K, V> {
+    fn default() -> Self {
+        BPlusTreeLeafPage::new()
+    }
+}
+
+/// Inner node of a B+ tree. Child `i` holds the keys from separator `i - 1` up to, but not
+/// including, separator `i`, so there is always one more child than separators.
+pub struct BPlusTreeInternalPage<K> {
+    page_lsn: Lsn,
+    keys: Vec<K>,
+    children: Vec<PageId>,
+}
+
+impl<K: Serialize + DeserializeOwned> BPlusTreeInternalPage<K> {
+    /// New root over two children, split at `key`.
+    pub fn new(left: PageId, key: K, right: PageId) -> BPlusTreeInternalPage<K> {
+        BPlusTreeInternalPage { page_lsn: 0, keys: vec![key], children: vec![left, right] }
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+
+    pub fn children(&self) -> &[PageId] {
+        &self.children
+    }
+
+    /// Index of the child whose keys include `key`.
+    pub fn child_index(&self, key: &K, cmp: &dyn KeyComparator<K>) -> usize {
+        match self.keys.binary_search_by(|k| cmp.compare(k, key)) {
+            Ok(idx) => idx + 1,
+            Err(idx) => idx,
+        }
+    }
+
+    /// Adds `right`, split off child `child_idx` at `key`, right after it.
+    pub fn insert_child(&mut self, child_idx: usize, key: K, right: PageId) {
+        self.keys.insert(child_idx, key);
+        self.children.insert(child_idx + 1, right);
+    }
+
+    /// Drops child `child_idx + 1`, merged into child `child_idx`, along with the separator between them.
+    pub fn remove_child(&mut self, child_idx: usize) {
+        self.keys.remove(child_idx);
+        self.children.remove(child_idx + 1);
+    }
+
+    /// Replaces the separator between child `child_idx` and the next one, e.g. once keys moved between them.
+    pub fn set_key(&mut self, child_idx: usize, key: K) {
+        self.keys[child_idx] = key;
+    }
+
+    /// Takes over the children of `right`, the next node, pulling down `separator` from their parent
+    /// to stand between the children of both.
+    pub fn merge(&mut self, separator: K, mut right: BPlusTreeInternalPage<K>) {
+        self.keys.push(separator);
+        self.keys.append(&mut right.keys);
+        self.children.append(&mut right.children);
+    }
+
+    /// Moves the upper half of the children into a new node, returning the separator between the
+    /// halves, which goes up to the parent instead of staying in either of them.
+    pub fn split(&mut self) -> (K, BPlusTreeInternalPage<K>) {
+        let mid = self.keys.len() / 2;
+        let keys = self.keys.split_off(mid + 1);
+        let children = self.children.split_off(mid + 1);
+        let separator = self.keys.pop().unwrap();
+        (separator, BPlusTreeInternalPage { page_lsn: 0, keys, children })
+    }
+
+    /// Bytes of the page the encoded node takes.
+    pub fn encoded_size(&self) -> io::Result<usize> {
+        Ok(PAGE_HEADER_SIZE + bincode::serialized_size(&(&self.keys, &self.children)).map_err(PageError::from)? as usize)
+    }
+
+    /// Whether the encoded node still fits into a page.
+    pub fn fits(&self) -> io::Result<bool> {
+        Ok(self.encoded_size()? <= PAGE_SIZE)
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![B_PLUS_TREE_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::BPlusTreeInternal as u8);
+        bincode::serialize_into(&mut res, &(&self.keys, &self.children)).map_err(PageError::from)?;
+        Ok(res)
+    }
+
+    fn deserialize(page_data: &[u8]) -> io::Result<BPlusTreeInternalPage<K>> {
+        let (keys, children): (Vec<K>, Vec<PageId>) = bincode::deserialize(&page_data[PAGE_HEADER_SIZE..])
+            .map_err(PageError::from)?;
+        if children.len() != keys.len() + 1 {
+            return Err(PageError::PageDataTooShort { expected: keys.len() + 1, actual: children.len() }.into());
+        }
+
+        Ok(BPlusTreeInternalPage { page_lsn: read_page_lsn(page_data)?, keys, children })
+    }
+}
+
+/// Node of a B+ tree, either kind decoded from the page type.
+pub enum BPlusTreePage<K, V> {
+    Leaf(BPlusTreeLeafPage<K, V>),
+    Internal(BPlusTreeInternalPage<K>),
+}
+
+impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> BPlusTreePage<K, V> {
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        match self {
+            BPlusTreePage::Leaf(leaf) => leaf.serialize(),
+            BPlusTreePage::Internal(internal) => internal.serialize(),
+        }
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<BPlusTreePage<K, V>> {
+        match read_page_version(page_data)? {
+            B_PLUS_TREE_PAGE_VERSION => (),
+            version => return Err(PageError::UnknownVersion { found: version, supported: B_PLUS_TREE_PAGE_VERSION }.into()),
+        }
+        match page_data.get(PAGE_TYPE_OFFSET) {
+            Some(found) if *found == PageType::BPlusTreeLeaf as u8 => Ok(BPlusTreePage::Leaf(BPlusTreeLeafPage::deserialize(page_data)?)),
+            Some(found) if *found == PageType::BPlusTreeInternal as u8 => Ok(BPlusTreePage::Internal(BPlusTreeInternalPage::deserialize(page_data)?)),
+            Some(found) => Err(PageError::WrongPageType { expected: PageType::BPlusTreeLeaf, found: *found }.into()),
+            None => Err(PageError::PageDataTooShort { expected: PAGE_HEADER_SIZE, actual: page_data.len() }.into()),
+        }
+    }
+}
+
+impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> PageSerde for BPlusTreePage<K, V> {
+    const VERSION: PageVersion = B_PLUS_TREE_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<BPlusTreePage<K, V>> {
+        BPlusTreePage::deserialize(page_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::compare::NaturalOrder;
+    use crate::storage::page::b_plus_tree_page::{BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage};
+    use crate::storage::page::log_page::LogPage;
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_serde::PageSerde;
+
+    #[test]
+    fn should_serialize_and_deserialize_leaf_and_internal_pages() {
+        // given
+        let mut leaf = BPlusTreeLeafPage::<u64, String>::new();
+        leaf.set_lsn(7);
+        leaf.set_next_page_id(Some(5));
+        for k in [3, 1, 2] {
+            leaf.insert(k, k.to_string(), &NaturalOrder);
+        }
+        let mut internal = BPlusTreeInternalPage::new(1, 10u64, 2);
+        internal.insert_child(1, 20, 3);
+
+        // when
+        let mut raw = vec![0; PAGE_SIZE];
+        BPlusTreePage::Leaf(leaf).to_page(&mut raw).unwrap();
+        let deser_leaf = BPlusTreePage::<u64, String>::from_page(&raw).unwrap();
+        BPlusTreePage::<u64, String>::Internal(internal).to_page(&mut raw).unwrap();
+        let deser_internal = BPlusTreePage::<u64, String>::from_page(&raw).unwrap();
+
+        // then
+        match deser_leaf {
+            BPlusTreePage::Leaf(leaf) => {
+                assert_eq!(leaf.get_lsn(), 7);
+                assert_eq!(leaf.get_next_page_id(), Some(5));
+                assert_eq!(leaf.entries(), &[(1, "1".to_string()), (2, "2".to_string()), (3, "3".to_string())]);
+            }
+            BPlusTreePage::Internal(_) => panic!("leaf decoded as internal page"),
+        }
+        match deser_internal {
+            BPlusTreePage::Internal(internal) => {
+                assert_eq!(internal.keys(), &[10, 20]);
+                assert_eq!(internal.children(), &[1, 2, 3]);
+                assert_eq!((internal.child_index(&9, &NaturalOrder), internal.child_index(&10, &NaturalOrder), internal.child_index(&25, &NaturalOrder)), (0, 1, 2));
+            }
+            BPlusTreePage::Leaf(_) => panic!("internal page decoded as leaf"),
+        }
+        let err = BPlusTreePage::<u64, u64>::from_page(&LogPage::new(1).serialize()).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::WrongPageType { .. })));
+    }
+
+    #[test]
+    fn should_split_internal_page_moving_middle_key_up() {
+        // given
+        let mut internal = BPlusTreeInternalPage::new(0, 10u64, 1);
+        for (i, k) in [20, 30, 40].iter().enumerate() {
+            internal.insert_child(i + 1, *k, i + 2);
+        }
+
+        // when
+        let (separator, right) = internal.split();
+
+        // then
+        assert_eq!(separator, 30);
+        assert_eq!((internal.keys(), internal.children()), (&[10, 20][..], &[0, 1, 2][..]));
+        assert_eq!((right.keys(), right.children()), (&[40][..], &[3, 4][..]));
+    }
+}
@@ -0,0 +1,430 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::foreign_key::ForeignKeyAction;
+use crate::catalog::index::IndexKind;
+use crate::storage::page::overflow_page::OverflowRef;
+use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+use crate::storage::table::schema::{Column, DataType, Schema};
+use crate::storage::table::value::Value;
+
+/// v8: version byte, LSN, page type, the next oid to hand out, the first page and length of the
+/// overflow chain holding the entries, an invalid page id for none, then the tables, the indexes, the
+/// statistics pages and the foreign keys, bincode encoded, unless they are in the overflow chain. v7
+/// pages had no overflow chain, v6 columns had no defaults, v5 pages had no foreign keys, v4 indexes
+/// had no primary key flag, v3 pages had no statistics, v2 tables had no schema versions, v1 pages
+/// only had the tables.
+const CATALOG_PAGE_VERSION: PageVersion = 8;
+const V7_CATALOG_PAGE_VERSION: PageVersion = 7;
+const V6_CATALOG_PAGE_VERSION: PageVersion = 6;
+const V5_CATALOG_PAGE_VERSION: PageVersion = 5;
+const V4_CATALOG_PAGE_VERSION: PageVersion = 4;
+const V3_CATALOG_PAGE_VERSION: PageVersion = 3;
+const V2_CATALOG_PAGE_VERSION: PageVersion = 2;
+const V1_CATALOG_PAGE_VERSION: PageVersion = 1;
+const TABLES_OFFSET: usize = PAGE_HEADER_SIZE + mem::size_of::<u32>();
+const OVERFLOW_LEN_OFFSET: usize = TABLES_OFFSET + mem::size_of::<u64>();
+const ENTRIES_OFFSET: usize = OVERFLOW_LEN_OFFSET + mem::size_of::<u64>();
+
+/// Table as recorded in the catalog: enough to open its heap again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub oid: u32,
+    pub name: String,
+    /// Schema of the latest version.
+    pub schema: Schema,
+    pub first_page_id: PageId,
+    /// Number of columns of each schema version, oldest first, as columns are only ever added.
+    pub column_counts: Vec<usize>,
+    /// Default of each column added after the first version, filled into older tuples on read.
+    pub defaults: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct V6Column {
+    name: String,
+    data_type: DataType,
+    nullable: bool,
+}
+
+fn v6_schema(columns: Vec<V6Column>) -> Schema {
+    Schema::new(columns.into_iter().map(|column| Column::new(&column.name, column.data_type).with_nullable(column.nullable)).collect())
+}
+
+#[derive(Deserialize)]
+struct V6CatalogEntry {
+    oid: u32,
+    name: String,
+    schema: Vec<V6Column>,
+    first_page_id: PageId,
+    column_counts: Vec<usize>,
+    defaults: Vec<Value>,
+}
+
+impl From<V6CatalogEntry> for CatalogEntry {
+    fn from(entry: V6CatalogEntry) -> Self {
+        CatalogEntry {
+            oid: entry.oid,
+            name: entry.name,
+            schema: v6_schema(entry.schema),
+            first_page_id: entry.first_page_id,
+            column_counts: entry.column_counts,
+            defaults: entry.defaults,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct V2CatalogEntry {
+    oid: u32,
+    name: String,
+    schema: Vec<V6Column>,
+    first_page_id: PageId,
+}
+
+impl From<V2CatalogEntry> for CatalogEntry {
+    fn from(entry: V2CatalogEntry) -> Self {
+        CatalogEntry {
+            oid: entry.oid,
+            column_counts: vec![entry.schema.len()],
+            name: entry.name,
+            schema: v6_schema(entry.schema),
+            first_page_id: entry.first_page_id,
+            defaults: Vec::new(),
+        }
+    }
+}
+
+/// Index as recorded in the catalog: the columns of its table it is keyed by and the page it is
+/// opened from, the meta page of a B+ tree or the first header page of a hash table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub oid: u32,
+    pub table_oid: u32,
+    pub key_columns: Vec<usize>,
+    pub kind: IndexKind,
+    pub root_page_id: PageId,
+    /// Whether the index backs the primary key of its table.
+    pub primary_key: bool,
+}
+
+#[derive(Deserialize)]
+struct V4IndexEntry {
+    oid: u32,
+    table_oid: u32,
+    key_columns: Vec<usize>,
+    kind: IndexKind,
+    root_page_id: PageId,
+}
+
+impl From<V4IndexEntry> for IndexEntry {
+    fn from(entry: V4IndexEntry) -> Self {
+        IndexEntry {
+            oid: entry.oid,
+            table_oid: entry.table_oid,
+            key_columns: entry.key_columns,
+            kind: entry.kind,
+            root_page_id: entry.root_page_id,
+            primary_key: false,
+        }
+    }
+}
+
+/// Page holding the statistics of a table, see `StatisticsPage`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatisticsEntry {
+    pub table_oid: u32,
+    pub page_id: PageId,
+}
+
+/// Foreign key as recorded in the catalog, see `ForeignKeyInfo`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignKeyEntry {
+    pub oid: u32,
+    pub table_oid: u32,
+    pub columns: Vec<usize>,
+    pub referenced_table_oid: u32,
+    pub referenced_index_oid: u32,
+    pub on_delete: ForeignKeyAction,
+    pub on_update: ForeignKeyAction,
+}
+
+/// Page of the catalog, listing every table and index of the data file. Entries that do not fit into
+/// the page are kept in an overflow chain instead, see `with_overflow`, the page only pointing to it.
+/// Encoding them into the page fails with `PageError::ValueTooLarge` otherwise.
+pub struct CatalogPage {
+    page_lsn: Lsn,
+    next_oid: u32,
+    overflow: Option<OverflowRef>,
+    tables: Vec<CatalogEntry>,
+    indexes: Vec<IndexEntry>,
+    statistics: Vec<StatisticsEntry>,
+    foreign_keys: Vec<ForeignKeyEntry>,
+}
+
+impl CatalogPage {
+    pub fn new(next_oid: u32, tables: Vec<CatalogEntry>, indexes: Vec<IndexEntry>) -> CatalogPage {
+        CatalogPage { page_lsn: 0, next_oid, overflow: None, tables, indexes, statistics: Vec::new(), foreign_keys: Vec::new() }
+    }
+
+    /// Bytes of encoded entries the page holds itself.
+    pub fn inline_capacity() -> usize {
+        PAGE_SIZE - ENTRIES_OFFSET
+    }
+
+    pub fn with_statistics(self, statistics: Vec<StatisticsEntry>) -> CatalogPage {
+        CatalogPage { statistics, ..self }
+    }
+
+    pub fn with_foreign_keys(self, foreign_keys: Vec<ForeignKeyEntry>) -> CatalogPage {
+        CatalogPage { foreign_keys, ..self }
+    }
+
+    /// The page is encoded pointing to the overflow chain, which has to hold the entries as
+    /// `encode_entries` returns them.
+    pub fn with_overflow(self, overflow: OverflowRef) -> CatalogPage {
+        CatalogPage { overflow: Some(overflow), ..self }
+    }
+
+    /// Overflow chain the entries are kept in, their bytes to be read from it and passed to
+    /// `with_overflow_entries`, as a decoded page lists none of them until then.
+    pub fn get_overflow(&self) -> Option<OverflowRef> {
+        self.overflow
+    }
+
+    pub fn with_overflow_entries(self, encoded: &[u8]) -> io::Result<CatalogPage> {
+        let (tables, indexes, statistics, foreign_keys) = bincode::deserialize(encoded).map_err(PageError::from)?;
+        Ok(CatalogPage { tables, indexes, statistics, foreign_keys, ..self })
+    }
+
+    /// Tables, indexes, statistics pages and foreign keys, encoded as the page holds them.
+    pub fn encode_entries(&self) -> io::Result<Vec<u8>> {
+        Ok(bincode::serialize(&(&self.tables, &self.indexes, &self.statistics, &self.foreign_keys)).map_err(PageError::from)?)
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_next_oid(&self) -> u32 {
+        self.next_oid
+    }
+
+    pub fn get_tables(&self) -> &[CatalogEntry] {
+        &self.tables
+    }
+
+    pub fn get_indexes(&self) -> &[IndexEntry] {
+        &self.indexes
+    }
+
+    pub fn get_statistics(&self) -> &[StatisticsEntry] {
+        &self.statistics
+    }
+
+    pub fn get_foreign_keys(&self) -> &[ForeignKeyEntry] {
+        &self.foreign_keys
+    }
+
+    pub fn into_entries(self) -> (Vec<CatalogEntry>, Vec<IndexEntry>) {
+        (self.tables, self.indexes)
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![CATALOG_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::Catalog as u8);
+        res.extend_from_slice(&self.next_oid.to_le_bytes());
+        match self.overflow {
+            Some(overflow) => {
+                res.extend_from_slice(&(overflow.page_id as u64).to_le_bytes());
+                res.extend_from_slice(&overflow.len.to_le_bytes());
+            }
+            None => {
+                res.extend_from_slice(&(INVALID_PAGE_ID as u64).to_le_bytes());
+                res.extend_from_slice(&0u64.to_le_bytes());
+                res.extend_from_slice(&self.encode_entries()?);
+            }
+        }
+        if res.len() > PAGE_SIZE {
+            return Err(PageError::ValueTooLarge { size: res.len(), capacity: PAGE_SIZE }.into());
+        }
+        Ok(res)
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<CatalogPage> {
+        let version = read_page_version(page_data)?;
+        match version {
+            V1_CATALOG_PAGE_VERSION..=CATALOG_PAGE_VERSION => check_page_type(page_data, PageType::Catalog)?,
+            version => return Err(PageError::UnknownVersion { found: version, supported: CATALOG_PAGE_VERSION }.into()),
+        }
+        let entries_offset = if version <= V7_CATALOG_PAGE_VERSION { TABLES_OFFSET } else { ENTRIES_OFFSET };
+        if page_data.len() < entries_offset {
+            return Err(PageError::PageDataTooShort { expected: entries_offset, actual: page_data.len() }.into());
+        }
+
+        let overflow_page_id = match version {
+            V1_CATALOG_PAGE_VERSION..=V7_CATALOG_PAGE_VERSION => INVALID_PAGE_ID,
+            _ => u64::from_le_bytes(page_data[TABLES_OFFSET..OVERFLOW_LEN_OFFSET].try_into().unwrap()) as PageId,
+        };
+        let overflow = match overflow_page_id {
+            INVALID_PAGE_ID => None,
+            page_id => Some(OverflowRef { page_id, len: u64::from_le_bytes(page_data[OVERFLOW_LEN_OFFSET..ENTRIES_OFFSET].try_into().unwrap()) }),
+        };
+        let encoded = &page_data[entries_offset..];
+        let (tables, indexes, statistics, foreign_keys) = match version {
+            V1_CATALOG_PAGE_VERSION => {
+                let tables: Vec<V2CatalogEntry> = bincode::deserialize(encoded).map_err(PageError::from)?;
+                (tables.into_iter().map(CatalogEntry::from).collect(), Vec::new(), Vec::new(), Vec::new())
+            }
+            V2_CATALOG_PAGE_VERSION => {
+                let (tables, indexes): (Vec<V2CatalogEntry>, Vec<V4IndexEntry>) = bincode::deserialize(encoded).map_err(PageError::from)?;
+                (tables.into_iter().map(CatalogEntry::from).collect(), indexes.into_iter().map(IndexEntry::from).collect(), Vec::new(), Vec::new())
+            }
+            V3_CATALOG_PAGE_VERSION => {
+                let (tables, indexes): (Vec<V6CatalogEntry>, Vec<V4IndexEntry>) = bincode::deserialize(encoded).map_err(PageError::from)?;
+                (tables.into_iter().map(CatalogEntry::from).collect(), indexes.into_iter().map(IndexEntry::from).collect(), Vec::new(), Vec::new())
+            }
+            V4_CATALOG_PAGE_VERSION => {
+                let (tables, indexes, statistics): (Vec<V6CatalogEntry>, Vec<V4IndexEntry>, Vec<StatisticsEntry>) =
+                    bincode::deserialize(encoded).map_err(PageError::from)?;
+                (tables.into_iter().map(CatalogEntry::from).collect(), indexes.into_iter().map(IndexEntry::from).collect(), statistics, Vec::new())
+            }
+            V5_CATALOG_PAGE_VERSION => {
+                let (tables, indexes, statistics): (Vec<V6CatalogEntry>, Vec<IndexEntry>, Vec<StatisticsEntry>) =
+                    bincode::deserialize(encoded).map_err(PageError::from)?;
+                (tables.into_iter().map(CatalogEntry::from).collect(), indexes, statistics, Vec::new())
+            }
+            V6_CATALOG_PAGE_VERSION => {
+                let (tables, indexes, statistics, foreign_keys): (Vec<V6CatalogEntry>, Vec<IndexEntry>, Vec<StatisticsEntry>, Vec<ForeignKeyEntry>) =
+                    bincode::deserialize(encoded).map_err(PageError::from)?;
+                (tables.into_iter().map(CatalogEntry::from).collect(), indexes, statistics, foreign_keys)
+            }
+            _ if overflow.is_some() => (Vec::new(), Vec::new(), Vec::new(), Vec::new()),
+            _ => bincode::deserialize(encoded).map_err(PageError::from)?,
+        };
+        Ok(CatalogPage {
+            page_lsn: read_page_lsn(page_data)?,
+            next_oid: u32::from_le_bytes(page_data[PAGE_HEADER_SIZE..TABLES_OFFSET].try_into().unwrap()),
+            overflow,
+            tables,
+            indexes,
+            statistics,
+            foreign_keys,
+        })
+    }
+}
+
+impl PageSerde for CatalogPage {
+    const VERSION: PageVersion = CATALOG_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<CatalogPage> {
+        CatalogPage::deserialize(page_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::foreign_key::ForeignKeyAction;
+    use crate::catalog::index::IndexKind;
+    use crate::storage::page::catalog_page::{CatalogEntry, CatalogPage, ForeignKeyEntry, IndexEntry, StatisticsEntry};
+    use crate::storage::page::overflow_page::OverflowRef;
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_version::PageType;
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::value::Value;
+
+    fn entry(oid: u32) -> CatalogEntry {
+        CatalogEntry {
+            oid,
+            name: format!("table_{}", oid),
+            schema: Schema::new(vec![Column::new("id", DataType::Integer), Column::new("name", DataType::Varchar(32))]),
+            first_page_id: oid as usize + 10,
+            column_counts: vec![2],
+            defaults: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn should_keep_tables_indexes_statistics_and_foreign_keys_through_serde_and_refuse_more_than_fit() {
+        // given
+        let index = IndexEntry { oid: 3, table_oid: 1, key_columns: vec![1, 0], kind: IndexKind::Hash, root_page_id: 20, primary_key: true };
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("name", DataType::Varchar(32)).with_default(Value::Varchar("?".to_string()))]);
+        let evolved = CatalogEntry { schema, column_counts: vec![1, 2], defaults: vec![Value::Varchar("n/a".to_string())], ..entry(2) };
+        let statistics = StatisticsEntry { table_oid: 2, page_id: 30 };
+        let foreign_key = ForeignKeyEntry {
+            oid: 4, table_oid: 2, columns: vec![0, 1], referenced_table_oid: 1, referenced_index_oid: 3,
+            on_delete: ForeignKeyAction::Cascade, on_update: ForeignKeyAction::Restrict,
+        };
+        let page = CatalogPage::new(5, vec![entry(1), evolved.clone()], vec![index.clone()])
+            .with_statistics(vec![statistics])
+            .with_foreign_keys(vec![foreign_key.clone()]);
+
+        // when
+        let deser_page = CatalogPage::deserialize(&page.serialize().unwrap()).unwrap();
+        let err = CatalogPage::new(1000, (0..1000).map(entry).collect(), Vec::new()).serialize().err().unwrap();
+
+        // then
+        assert_eq!(deser_page.get_next_oid(), 5);
+        assert_eq!(deser_page.get_tables(), &[entry(1), evolved]);
+        assert_eq!(deser_page.get_indexes(), &[index]);
+        assert_eq!(deser_page.get_statistics(), &[statistics]);
+        assert_eq!(deser_page.get_foreign_keys(), &[foreign_key]);
+        assert!(matches!(PageError::from_io(&err), Some(PageError::ValueTooLarge { capacity: PAGE_SIZE, .. })));
+    }
+
+    #[test]
+    fn should_point_to_overflow_chain_and_take_entries_read_from_it() {
+        // given
+        let page = CatalogPage::new(1000, (0..1000).map(entry).collect(), Vec::new());
+        let encoded = page.encode_entries().unwrap();
+        let overflow = OverflowRef { page_id: 42, len: encoded.len() as u64 };
+
+        // when
+        let deser_page = CatalogPage::deserialize(&page.with_overflow(overflow).serialize().unwrap()).unwrap();
+        let filled_page = CatalogPage::deserialize(&deser_page.serialize().unwrap()).unwrap().with_overflow_entries(&encoded).unwrap();
+
+        // then
+        assert!(encoded.len() > CatalogPage::inline_capacity());
+        assert_eq!((deser_page.get_next_oid(), deser_page.get_overflow()), (1000, Some(overflow)));
+        assert!(deser_page.get_tables().is_empty());
+        assert_eq!(filled_page.get_tables(), (0..1000).map(entry).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn should_read_v1_page_as_catalog_without_indexes() {
+        // given
+        let CatalogEntry { oid, name, schema, first_page_id, .. } = entry(1);
+        let mut raw = vec![1];
+        raw.extend_from_slice(&7u64.to_le_bytes());
+        raw.push(PageType::Catalog as u8);
+        raw.extend_from_slice(&2u32.to_le_bytes());
+        let columns: Vec<(&str, DataType, bool)> = schema.columns().iter().map(|column| (column.name(), column.data_type(), column.is_nullable())).collect();
+        bincode::serialize_into(&mut raw, &vec![(oid, name, columns, first_page_id)]).unwrap();
+        raw.resize(PAGE_SIZE, 0);
+
+        // when
+        let page = CatalogPage::deserialize(&raw).unwrap();
+
+        // then
+        assert_eq!((page.get_lsn(), page.get_next_oid()), (7, 2));
+        assert_eq!(page.get_tables(), &[entry(1)]);
+        assert!(page.get_indexes().is_empty());
+        assert!(page.get_statistics().is_empty());
+        assert!(page.get_foreign_keys().is_empty());
+    }
+}
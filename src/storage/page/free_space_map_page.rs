@@ -0,0 +1,227 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, UNTYPED_PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+
+/// v3: version byte, LSN, page type, id of the first page covered, id of the next map page, then one
+/// free space category per page.
+/// v2: version byte, LSN, page type, id of the first page covered, then one free space category per page.
+/// v1: version byte, LSN, id of the first page covered, then one free space category per page.
+const FREE_SPACE_MAP_PAGE_VERSION: PageVersion = 3;
+const V2_FREE_SPACE_MAP_PAGE_VERSION: PageVersion = 2;
+const V1_FREE_SPACE_MAP_PAGE_VERSION: PageVersion = 1;
+const FIRST_PAGE_ID_SIZE: usize = mem::size_of::<u64>();
+const NEXT_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE + FIRST_PAGE_ID_SIZE;
+const CATEGORIES_OFFSET: usize = NEXT_PAGE_ID_OFFSET + mem::size_of::<u64>();
+/// Pages one map page covers.
+pub const FREE_SPACE_MAP_CAPACITY: usize = PAGE_SIZE - CATEGORIES_OFFSET;
+/// Free bytes are kept in one byte per page, rounded down to a multiple of this.
+const CATEGORY_SIZE: usize = PAGE_SIZE / 256;
+
+/// Approximate free bytes of a run of consecutive pages, so an insert can pick a page with room
+/// without reading every page.
+///
+/// Amounts are rounded down, so a page found with room for `n` bytes always has them. The map pages of
+/// a heap are chained through their next page id.
+pub struct FreeSpaceMapPage {
+    page_lsn: Lsn,
+    first_page_id: PageId,
+    next_page_id: Option<PageId>,
+    categories: Vec<u8>,
+}
+
+impl FreeSpaceMapPage {
+    /// Covers `FREE_SPACE_MAP_CAPACITY` pages from `first_page_id` on, all of them full until set.
+    pub fn new(first_page_id: PageId) -> FreeSpaceMapPage {
+        FreeSpaceMapPage { page_lsn: 0, first_page_id, next_page_id: None, categories: vec![0; FREE_SPACE_MAP_CAPACITY] }
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_first_page_id(&self) -> PageId {
+        self.first_page_id
+    }
+
+    pub fn get_next_page_id(&self) -> Option<PageId> {
+        self.next_page_id
+    }
+
+    pub fn set_next_page_id(&mut self, next_page_id: Option<PageId>) {
+        self.next_page_id = next_page_id
+    }
+
+    pub fn covers(&self, page_id: PageId) -> bool {
+        page_id >= self.first_page_id && page_id - self.first_page_id < FREE_SPACE_MAP_CAPACITY
+    }
+
+    pub fn set_free_space(&mut self, page_id: PageId, free_bytes: usize) -> io::Result<()> {
+        let idx = self.index_of(page_id)?;
+        self.categories[idx] = (free_bytes / CATEGORY_SIZE).min(u8::MAX as usize) as u8;
+        Ok(())
+    }
+
+    pub fn get_free_space(&self, page_id: PageId) -> io::Result<usize> {
+        let idx = self.index_of(page_id)?;
+        Ok(self.categories[idx] as usize * CATEGORY_SIZE)
+    }
+
+    /// Marks every covered page full.
+    pub fn clear(&mut self) {
+        self.categories.iter_mut().for_each(|c| *c = 0);
+    }
+
+    /// First covered page with at least `bytes` free.
+    pub fn find_page_with(&self, bytes: usize) -> Option<PageId> {
+        // round up, a category holding less than asked for never matches
+        let category = bytes.div_ceil(CATEGORY_SIZE);
+        self.categories.iter()
+            .position(|c| *c as usize >= category)
+            .map(|idx| self.first_page_id + idx)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut res = vec![FREE_SPACE_MAP_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::FreeSpaceMap as u8);
+        res.extend_from_slice(&(self.first_page_id as u64).to_le_bytes());
+        res.extend_from_slice(&(self.next_page_id.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        res.extend_from_slice(&self.categories);
+        res
+    }
+
+    /// A v1 or v2 map covers more pages than fit now, which are left out as full: the map is only a
+    /// hint. Neither had a next map page.
+    pub fn deserialize(page_data: &[u8]) -> io::Result<FreeSpaceMapPage> {
+        let version = read_page_version(page_data)?;
+        let header_size = match version {
+            FREE_SPACE_MAP_PAGE_VERSION | V2_FREE_SPACE_MAP_PAGE_VERSION => {
+                check_page_type(page_data, PageType::FreeSpaceMap)?;
+                PAGE_HEADER_SIZE
+            },
+            V1_FREE_SPACE_MAP_PAGE_VERSION => UNTYPED_PAGE_HEADER_SIZE,
+            version => return Err(PageError::UnknownVersion { found: version, supported: FREE_SPACE_MAP_PAGE_VERSION }.into()),
+        };
+        if page_data.len() < PAGE_SIZE {
+            return Err(PageError::PageDataTooShort { expected: PAGE_SIZE, actual: page_data.len() }.into());
+        }
+
+        let next_page_id_offset = header_size + FIRST_PAGE_ID_SIZE;
+        let (next_page_id, categories_offset) = match version {
+            FREE_SPACE_MAP_PAGE_VERSION => {
+                let next_page_id = u64::from_le_bytes(page_data[NEXT_PAGE_ID_OFFSET..CATEGORIES_OFFSET].try_into().unwrap()) as PageId;
+                (next_page_id, CATEGORIES_OFFSET)
+            },
+            _ => (INVALID_PAGE_ID, next_page_id_offset),
+        };
+        Ok(FreeSpaceMapPage {
+            page_lsn: read_page_lsn(page_data)?,
+            first_page_id: u64::from_le_bytes(page_data[header_size..next_page_id_offset].try_into().unwrap()) as PageId,
+            next_page_id: if next_page_id == INVALID_PAGE_ID { None } else { Some(next_page_id) },
+            categories: page_data[categories_offset..categories_offset + FREE_SPACE_MAP_CAPACITY].to_vec(),
+        })
+    }
+
+    fn index_of(&self, page_id: PageId) -> io::Result<usize> {
+        if !self.covers(page_id) {
+            return Err(PageError::SlotOutOfRange {
+                slot_idx: page_id.wrapping_sub(self.first_page_id),
+                capacity: FREE_SPACE_MAP_CAPACITY,
+            }.into());
+        }
+
+        Ok(page_id - self.first_page_id)
+    }
+}
+
+impl PageSerde for FreeSpaceMapPage {
+    const VERSION: PageVersion = FREE_SPACE_MAP_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize(), page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<FreeSpaceMapPage> {
+        FreeSpaceMapPage::deserialize(page_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::free_space_map_page::{FreeSpaceMapPage, FREE_SPACE_MAP_CAPACITY};
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_serde::upgrade_page;
+    use crate::storage::page::page_version::read_page_version;
+
+    #[test]
+    fn should_find_page_with_enough_free_space() {
+        // given
+        let mut fsm = FreeSpaceMapPage::new(100);
+        fsm.set_free_space(101, 100).unwrap();
+        fsm.set_free_space(105, 2000).unwrap();
+
+        // when
+        let found = fsm.find_page_with(200);
+
+        // then
+        assert_eq!(found, Some(105));
+        assert_eq!(fsm.find_page_with(96), Some(101));
+        assert_eq!(fsm.find_page_with(100), Some(105));
+        assert_eq!(fsm.find_page_with(PAGE_SIZE), None);
+        assert_eq!(fsm.get_free_space(101).unwrap(), 96);
+        let err = fsm.set_free_space(100 + FREE_SPACE_MAP_CAPACITY, 10).unwrap_err();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::SlotOutOfRange { .. })));
+    }
+
+    #[test]
+    fn should_serialize_and_deserialize_free_space_map() {
+        // given
+        let mut fsm = FreeSpaceMapPage::new(100);
+        fsm.set_free_space(100 + FREE_SPACE_MAP_CAPACITY - 1, PAGE_SIZE).unwrap();
+        fsm.set_lsn(42);
+        fsm.set_next_page_id(Some(7));
+
+        // when
+        let raw = fsm.serialize();
+        let deser_fsm = FreeSpaceMapPage::deserialize(&raw).unwrap();
+
+        // then
+        assert_eq!(raw.len(), PAGE_SIZE);
+        assert_eq!(deser_fsm.get_lsn(), 42);
+        assert_eq!(deser_fsm.get_first_page_id(), 100);
+        assert_eq!(deser_fsm.get_next_page_id(), Some(7));
+        assert_eq!(deser_fsm.find_page_with(4000), Some(100 + FREE_SPACE_MAP_CAPACITY - 1));
+    }
+
+    #[test]
+    fn should_upgrade_v1_map_to_current_layout() {
+        // given
+        let mut v1_raw = vec![1];
+        v1_raw.extend_from_slice(&42u64.to_le_bytes());
+        v1_raw.extend_from_slice(&100u64.to_le_bytes());
+        v1_raw.resize(PAGE_SIZE, 0);
+        v1_raw[17 + 5] = 16;
+
+        // when
+        let upgraded = upgrade_page::<FreeSpaceMapPage>(&mut v1_raw).unwrap();
+
+        // then
+        assert!(upgraded);
+        assert_eq!(read_page_version(&v1_raw).unwrap(), 3);
+        assert!(!upgrade_page::<FreeSpaceMapPage>(&mut v1_raw).unwrap());
+        let fsm = FreeSpaceMapPage::deserialize(&v1_raw).unwrap();
+        assert_eq!(fsm.get_lsn(), 42);
+        assert_eq!(fsm.get_first_page_id(), 100);
+        assert_eq!(fsm.get_next_page_id(), None);
+        assert_eq!(fsm.get_free_space(105).unwrap(), 256);
+    }
+}
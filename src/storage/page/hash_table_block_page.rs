@@ -1,113 +1,690 @@
 use crate::storage::page::page::PAGE_SIZE;
 use crate::common::hash::*;
 use std::{mem, io};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
 use crate::common::ValueType;
+use crate::storage::page::overflow_page::OverflowRef;
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, PAGE_VERSION_SIZE, UNTYPED_PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
 use serde::{Serialize, Deserialize};
 use serde::de::DeserializeOwned;
 
+/// v6: version byte, LSN, page type, then occupied bits, readable bits, overflowed bits, the key filter and
+/// the mapping array.
+/// v5: version byte, LSN, page type, then occupied bits, readable bits, overflowed bits and the mapping array.
+/// v4: version byte, LSN, then occupied bits, readable bits, overflowed bits and the mapping array.
+/// v3: as v4, but the readable bits were never set, every occupied slot is live.
+/// v2: version byte, LSN, then occupied bits, readable bits and the mapping array.
+/// v1: version byte, then occupied bits, readable bits and the mapping array.
+///
+/// An occupied slot keeps probe sequences passing it intact, a readable one holds a live pair. Removing a
+/// pair clears its readable bit only, leaving a tombstone.
+///
+/// The key filter is a Bloom filter of the keys written to the block, one byte per slot. Removing a pair
+/// leaves its bits set, so the filter may only tell a key is certainly not in the block.
+const BLOCK_PAGE_VERSION: PageVersion = 6;
+const V5_BLOCK_PAGE_VERSION: PageVersion = 5;
+const V4_BLOCK_PAGE_VERSION: PageVersion = 4;
+const V3_BLOCK_PAGE_VERSION: PageVersion = 3;
+const V2_BLOCK_PAGE_VERSION: PageVersion = 2;
+const V1_BLOCK_PAGE_VERSION: PageVersion = 1;
+const BITMAPS: usize = 3;
+const V2_BITMAPS: usize = 2;
+const FILTER_HASHES: u32 = 4;
+const FILTER_SEED: u64 = 0x6b65_7966_696c_7472;
+
 #[derive(Clone, Serialize, Deserialize)]
-struct MappingType<K: HashKeyType, V: ValueType> {
-    key: K,
-    value: V,
+pub(crate) struct MappingType<K: HashKeyType, V: ValueType> {
+    pub(crate) key: K,
+    pub(crate) value: V,
+}
+
+/// Value of a slot as stored: inline, or in an overflow chain when it does not fit into the slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotValue<V> {
+    Inline(V),
+    Overflow(OverflowRef),
+}
+
+impl<V> SlotValue<V> {
+    pub fn as_ref(&self) -> SlotValue<&V> {
+        match self {
+            SlotValue::Inline(v) => SlotValue::Inline(v),
+            SlotValue::Overflow(overflow_ref) => SlotValue::Overflow(*overflow_ref),
+        }
+    }
 }
 
 pub struct HashTableBlockPage<K: HashKeyType, V: ValueType> {
+    page_lsn: Lsn,
     occupied: Vec<u8>,
     readable: Vec<u8>,
     array: Vec<MappingType<K, V>>,
+    /// Slots whose value sits in an overflow chain, their value in `array` is left default.
+    overflow_refs: BTreeMap<usize, OverflowRef>,
+    filter: Vec<u8>,
 }
 
 impl<K: HashKeyType + DeserializeOwned, V: ValueType + DeserializeOwned> HashTableBlockPage<K, V> {
     pub fn new() -> HashTableBlockPage<K, V> {
         let capacity = HashTableBlockPage::<K, V>::capacity_of_block();
         HashTableBlockPage {
-            occupied: vec![0; (capacity - 1) / 8 + 1],
-            readable: vec![0; (capacity - 1) / 8 + 1],
-            array: vec![MappingType {key: Default::default(), value: Default::default()}; capacity]
+            page_lsn: 0,
+            occupied: vec![0; bitmap_size(capacity)],
+            readable: vec![0; bitmap_size(capacity)],
+            array: vec![MappingType {key: Default::default(), value: Default::default()}; capacity],
+            overflow_refs: BTreeMap::new(),
+            filter: vec![0; filter_size(capacity)],
         }
     }
 
-    /// Size of MappingTypes in one page: size_of(MappingType) + 0.25, 0.25 = 2/8 byte = occupied bit + readable bit
+    /// Size of MappingTypes in one page: size_of(MappingType) + 3/8 byte = occupied bit + readable bit + overflowed bit,
+    /// plus one byte of key filter, leaving room for each bitmap to round up by one byte
     pub fn capacity_of_block() -> usize {
-        4 * PAGE_SIZE / (4 * mem::size_of::<MappingType<K, V>>() + 1)
+        8 * (PAGE_SIZE - PAGE_HEADER_SIZE - BITMAPS) / (8 * HashTableBlockPage::<K, V>::slot_size() + BITMAPS + 8)
+    }
+
+    /// Capacity of the layouts from v3 to v5, having three bitmaps and no key filter.
+    fn capacity_with_overflowed(body_size: usize) -> usize {
+        8 * (body_size - BITMAPS) / (8 * HashTableBlockPage::<K, V>::slot_size() + BITMAPS)
+    }
+
+    /// Bytes of a slot, where a pair is stored inline if its serialized form fits. Never less than the
+    /// in-memory size of a pair, which every slot took before `ENCODED_SIZE`, so layouts stay as they were.
+    pub fn slot_size() -> usize {
+        mem::size_of::<MappingType<K, V>>().max(K::ENCODED_SIZE + V::ENCODED_SIZE)
+    }
+
+    pub fn fits_inline(key: &K, value: &V) -> io::Result<bool> {
+        let size = bincode::serialized_size(&(key, value)).map_err(PageError::from)?;
+        Ok(size as usize <= HashTableBlockPage::<K, V>::slot_size())
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    /// Capacity of the layouts before v3, having two bitmaps only.
+    fn capacity_of(body_size: usize) -> usize {
+        4 * body_size / (4 * HashTableBlockPage::<K, V>::slot_size() + 1)
     }
 
     /// We won't directly use bincode::serialize() due to we don't want Vector's length info go into disk page
-    pub fn serialize(&self) -> Vec<u8> {
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![BLOCK_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::HashTableBlock as u8);
+        res.append(&mut self.encode(BITMAPS, &self.filter)?);
+
+        Ok(res)
+    }
+
+    /// Every slot takes `slot_size` bytes whatever the serialized size of its pair, so slots stay at fixed offsets.
+    /// The filter goes in between the bitmaps and the slots, layouts before v6 have none.
+    fn encode(&self, bitmaps: usize, filter: &[u8]) -> io::Result<Vec<u8>> {
         let mut res = self.occupied.clone();
-        res.append(&mut (self.readable.clone()));
-        for mapping_type in self.array.iter() {
-            let mut raw = bincode::serialize(mapping_type).unwrap();
-            res.append(&mut raw);
+        res.extend_from_slice(&self.readable);
+        if bitmaps == BITMAPS {
+            let mut overflowed = vec![0; self.occupied.len()];
+            for slot_idx in self.overflow_refs.keys() {
+                overflowed[slot_idx / 8] |= 0x01 << (slot_idx % 8);
+            }
+            res.append(&mut overflowed);
         }
+        res.extend_from_slice(filter);
 
-        res
+        let slot_size = HashTableBlockPage::<K, V>::slot_size();
+        for (slot_idx, mapping_type) in self.array.iter().enumerate() {
+            let start = res.len();
+            res.resize(start + slot_size, 0);
+            match self.overflow_refs.get(&slot_idx) {
+                Some(overflow_ref) => encode_slot(&mut res[start..], &(&mapping_type.key, overflow_ref))?,
+                None => encode_slot(&mut res[start..], mapping_type)?,
+            }
+        }
+
+        Ok(res)
     }
 
     pub fn deserialize(page_data: &[u8]) -> io::Result<HashTableBlockPage<K, V>> {
-        let capacity = HashTableBlockPage::<K, V>::capacity_of_block();
-        let array_bit_size = (capacity - 1) / 8 + 1;
-        let mut array = vec![MappingType {key: Default::default(), value: Default::default()}; capacity];
+        match read_page_version(page_data)? {
+            BLOCK_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableBlock)?;
+                let page_lsn = read_page_lsn(page_data)?;
+                let capacity = HashTableBlockPage::<K, V>::capacity_of_block();
+                let block = HashTableBlockPage::decode(&page_data[PAGE_HEADER_SIZE..], capacity, BITMAPS, filter_size(capacity))?;
+                Ok(HashTableBlockPage { page_lsn, ..block })
+            },
+            V5_BLOCK_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableBlock)?;
+                let page_lsn = read_page_lsn(page_data)?;
+                let block = HashTableBlockPage::decode(&page_data[PAGE_HEADER_SIZE..],
+                    HashTableBlockPage::<K, V>::capacity_with_overflowed(PAGE_SIZE - PAGE_HEADER_SIZE), BITMAPS, 0)?;
+                Ok(HashTableBlockPage { page_lsn, ..block })
+            },
+            V4_BLOCK_PAGE_VERSION => {
+                let page_lsn = read_page_lsn(page_data)?;
+                let block = HashTableBlockPage::decode(&page_data[UNTYPED_PAGE_HEADER_SIZE..],
+                    HashTableBlockPage::<K, V>::capacity_with_overflowed(PAGE_SIZE - UNTYPED_PAGE_HEADER_SIZE), BITMAPS, 0)?;
+                Ok(HashTableBlockPage { page_lsn, ..block })
+            },
+            V3_BLOCK_PAGE_VERSION => {
+                let page_lsn = read_page_lsn(page_data)?;
+                let block = HashTableBlockPage::decode(&page_data[UNTYPED_PAGE_HEADER_SIZE..],
+                    HashTableBlockPage::<K, V>::capacity_with_overflowed(PAGE_SIZE - UNTYPED_PAGE_HEADER_SIZE), BITMAPS, 0)?;
+                Ok(HashTableBlockPage { page_lsn, ..block.occupied_as_readable() })
+            },
+            V2_BLOCK_PAGE_VERSION => {
+                let page_lsn = read_page_lsn(page_data)?;
+                let block = HashTableBlockPage::decode(&page_data[UNTYPED_PAGE_HEADER_SIZE..],
+                    HashTableBlockPage::<K, V>::capacity_of(PAGE_SIZE - UNTYPED_PAGE_HEADER_SIZE), V2_BITMAPS, 0)?;
+                Ok(HashTableBlockPage { page_lsn, ..block.occupied_as_readable() })
+            },
+            V1_BLOCK_PAGE_VERSION => HashTableBlockPage::decode(
+                &page_data[PAGE_VERSION_SIZE..], HashTableBlockPage::<K, V>::capacity_of(PAGE_SIZE - PAGE_VERSION_SIZE), V2_BITMAPS, 0)
+                .map(HashTableBlockPage::occupied_as_readable),
+            version => Err(PageError::UnknownVersion { found: version, supported: BLOCK_PAGE_VERSION }.into()),
+        }
+    }
 
-        let mapping_type_size = mem::size_of::<MappingType<K, V>>();
+    /// Decodes a block page of a legacy format file, which has no version byte and so may hold more slots.
+    pub fn deserialize_legacy(page_data: &[u8]) -> io::Result<HashTableBlockPage<K, V>> {
+        HashTableBlockPage::decode(page_data, HashTableBlockPage::<K, V>::capacity_of(PAGE_SIZE), V2_BITMAPS, 0)
+            .map(HashTableBlockPage::occupied_as_readable)
+    }
+
+    /// Versions before v4 never set readable bits, nor left tombstones.
+    fn occupied_as_readable(mut self) -> HashTableBlockPage<K, V> {
+        self.readable = self.occupied.clone();
+        self.rebuild_filter();
+        self
+    }
 
-        // explain of end range <((page_data.len() - 2 * array_bit_size) / mapping_type_size) * mapping_type_size + 2 * array_bit_size>
-        // 1. <((page_data.len() - 2 * array_bit_size) / mapping_type_size)>:
-        //    largest mapping type numbers the page_data can hold
-        // 2. <* mapping_type_size>:
-        //    total size of whole mapping type
-        // 3. <+ 2 * array_bit_size>:
-        //    plus the top two bit array size
-        let data_range = 2 * array_bit_size..((page_data.len() - 2 * array_bit_size) / mapping_type_size) * mapping_type_size + 2 * array_bit_size;
-        for i in data_range.step_by(mapping_type_size) {
-            let curr_mapping_type_index = (i - 2 * array_bit_size) / mapping_type_size;
-            array[curr_mapping_type_index] = bincode::deserialize::<MappingType<K, V>>(&(page_data[i..i + mapping_type_size])).unwrap();
+    /// Stored slots beyond the current capacity must be unused, otherwise they would be lost. The stored
+    /// filter is skipped, it is rebuilt from the live keys instead, dropping the bits of removed pairs.
+    fn decode(data: &[u8], stored_capacity: usize, bitmaps: usize, stored_filter_size: usize) -> io::Result<HashTableBlockPage<K, V>> {
+        let array_bit_size = bitmap_size(stored_capacity);
+        let mapping_type_size = HashTableBlockPage::<K, V>::slot_size();
+
+        let slots_offset = bitmaps * array_bit_size + stored_filter_size;
+        let expected_size = slots_offset + stored_capacity * mapping_type_size;
+        if data.len() < expected_size {
+            return Err(PageError::PageDataTooShort { expected: expected_size, actual: data.len() }.into());
         }
 
-        Ok(HashTableBlockPage {
-            occupied: Vec::from(&page_data[0..array_bit_size]),
-            readable: Vec::from(&page_data[((capacity - 1) / 8 + 1)..2*array_bit_size]),
-            array
-        })
+        let (occupied, readable) = (&data[0..array_bit_size], &data[array_bit_size..2 * array_bit_size]);
+        let overflowed = match bitmaps {
+            BITMAPS => &data[2 * array_bit_size..3 * array_bit_size],
+            _ => &[][..],
+        };
+        let mut block = HashTableBlockPage::new();
+        let capacity = block.array.len();
+        for i in 0..stored_capacity {
+            let (byte_idx, bit) = (i / 8, 0x01 << (i % 8));
+            if i >= capacity {
+                if (occupied[byte_idx] | readable[byte_idx]) & bit != 0 {
+                    return Err(PageError::SlotOutOfRange { slot_idx: i, capacity }.into());
+                }
+                continue;
+            }
+
+            let start = slots_offset + i * mapping_type_size;
+            let slot = &data[start..start + mapping_type_size];
+            if overflowed.get(byte_idx).is_some_and(|bits| bits & bit != 0) {
+                let (key, overflow_ref) = bincode::deserialize::<(K, OverflowRef)>(slot).map_err(PageError::from)?;
+                block.array[i].key = key;
+                block.overflow_refs.insert(i, overflow_ref);
+            } else {
+                block.array[i] = bincode::deserialize::<MappingType<K, V>>(slot).map_err(PageError::from)?;
+            }
+            block.occupied[byte_idx] |= occupied[byte_idx] & bit;
+            block.readable[byte_idx] |= readable[byte_idx] & bit;
+        }
+        block.rebuild_filter();
+
+        Ok(block)
     }
 
-    pub fn insert(&mut self, slot_idx: usize, key: K, value: V) -> bool {
-        if (&self).is_occupied(slot_idx) {
-            return false;
+    fn rebuild_filter(&mut self) {
+        self.filter.fill(0);
+        for slot_idx in 0..self.array.len() {
+            if (self.readable[slot_idx / 8] >> (slot_idx % 8)) & 0x01 == 1 {
+                add_to_filter(&mut self.filter, &self.array[slot_idx].key);
+            }
         }
+    }
 
+    /// False only for a key no pair was ever written to the block for.
+    pub fn may_contain(&self, key: &K) -> bool {
+        filter_contains(&self.filter, key)
+    }
+
+    /// Takes a free slot or a tombstone, never a live pair.
+    pub fn insert(&mut self, slot_idx: usize, key: K, value: V) -> io::Result<bool> {
+        if self.is_readable(slot_idx)? {
+            return Ok(false);
+        }
+
+        add_to_filter(&mut self.filter, &key);
         self.array[slot_idx] = MappingType { key, value};
+        self.overflow_refs.remove(&slot_idx);
         self.set(slot_idx);
-        true
+        Ok(true)
     }
 
-    pub fn get(&self, slot_idx: usize) -> (&K, &V) {
+    /// Inserts a pair whose value was written to an overflow chain.
+    pub fn insert_overflowed(&mut self, slot_idx: usize, key: K, overflow_ref: OverflowRef) -> io::Result<bool> {
+        if !self.insert(slot_idx, key, Default::default())? {
+            return Ok(false);
+        }
+
+        self.overflow_refs.insert(slot_idx, overflow_ref);
+        Ok(true)
+    }
+
+    /// Turns the slot into a tombstone if it holds the given pair, keeping it occupied so probe sequences
+    /// passing it stay intact. A pair moved to an overflow chain is matched by key only, its value has to
+    /// be compared by the caller reading the chain.
+    pub fn remove(&mut self, slot_idx: usize, key: &K, value: &V) -> io::Result<bool> {
+        if !self.is_readable(slot_idx)? {
+            return Ok(false);
+        }
+
         let mapping_type = &self.array[slot_idx];
-        (&mapping_type.key, &mapping_type.value)
+        let overflowed = self.overflow_refs.contains_key(&slot_idx);
+        if &mapping_type.key != key || (!overflowed && &mapping_type.value != value) {
+            return Ok(false);
+        }
+
+        self.readable[slot_idx / 8] &= !(0x01 << (slot_idx % 8));
+        Ok(true)
     }
 
-    pub fn is_occupied(&self, slot_idx: usize) -> bool {
+    /// Drops the tombstones by moving live pairs back towards their home slot, and returns how many slots
+    /// became free. `home_slot` gives the slot a key hashes to in this block, `None` for a key hashed to an
+    /// earlier block whose probe sequence reached this one.
+    ///
+    /// Pairs only move towards the block start, and a run of occupied slots reaching the block end is kept
+    /// as it is, as probe sequences of keys in the next block may pass it.
+    pub fn compact<F: Fn(&K) -> Option<usize>>(&mut self, home_slot: F) -> usize {
+        let capacity = self.array.len();
+        let is_occupied = |bits: &[u8], i: usize| (bits[i / 8] >> (i % 8)) & 0x01 == 1;
+        let kept_run_start = (0..capacity).rev()
+            .find(|i| !is_occupied(&self.occupied, *i))
+            .map_or(0, |i| i + 1);
+        let occupied_before = (0..kept_run_start).filter(|i| is_occupied(&self.occupied, *i)).count();
+
+        let live_slots: Vec<usize> = (0..kept_run_start).filter(|i| is_occupied(&self.readable, *i)).collect();
+        self.overflow_refs.retain(|slot_idx, _| *slot_idx >= kept_run_start || live_slots.contains(slot_idx));
+        for i in 0..kept_run_start {
+            self.occupied[i / 8] &= !(0x01 << (i % 8));
+            self.readable[i / 8] &= !(0x01 << (i % 8));
+        }
+        for slot_idx in live_slots {
+            let home = home_slot(&self.array[slot_idx].key).unwrap_or(0).min(slot_idx);
+            let new_slot_idx = (home..=slot_idx).find(|i| !is_occupied(&self.occupied, *i)).unwrap();
+            self.array.swap(new_slot_idx, slot_idx);
+            if let Some(overflow_ref) = self.overflow_refs.remove(&slot_idx) {
+                self.overflow_refs.insert(new_slot_idx, overflow_ref);
+            }
+            self.set(new_slot_idx);
+        }
+
+        occupied_before - (0..kept_run_start).filter(|i| is_occupied(&self.occupied, *i)).count()
+    }
+
+    /// The value of a slot moved to an overflow chain is left default, `get_overflow` tells where it is.
+    pub fn get(&self, slot_idx: usize) -> io::Result<(&K, &V)> {
+        self.validate_slot_idx(slot_idx)?;
+        let mapping_type = &self.array[slot_idx];
+        Ok((&mapping_type.key, &mapping_type.value))
+    }
+
+    pub fn get_overflow(&self, slot_idx: usize) -> io::Result<Option<OverflowRef>> {
+        self.validate_slot_idx(slot_idx)?;
+        Ok(self.overflow_refs.get(&slot_idx).copied())
+    }
+
+    pub fn is_occupied(&self, slot_idx: usize) -> io::Result<bool> {
+        self.validate_slot_idx(slot_idx)?;
         let byte_idx = slot_idx / 8;
         let bit_idx = slot_idx % 8;
-        self.occupied[byte_idx] | (!(0x01 << bit_idx)) == 0xff
+        Ok(self.occupied[byte_idx] | (!(0x01 << bit_idx)) == 0xff)
+    }
+
+    /// Whether the slot holds a live pair. An occupied slot that is not readable is a tombstone.
+    pub fn is_readable(&self, slot_idx: usize) -> io::Result<bool> {
+        self.validate_slot_idx(slot_idx)?;
+        Ok((self.readable[slot_idx / 8] >> (slot_idx % 8)) & 0x01 == 1)
+    }
+
+    /// Slots holding a live pair or a tombstone.
+    pub fn num_occupied(&self) -> usize {
+        count_bits(&self.occupied)
+    }
+
+    pub fn num_readable(&self) -> usize {
+        count_bits(&self.readable)
+    }
+
+    /// Share of the slots occupied, tombstones included as they lengthen probe sequences just the same.
+    pub fn fill_factor(&self) -> f64 {
+        self.num_occupied() as f64 / self.array.len() as f64
+    }
+
+    fn validate_slot_idx(&self, slot_idx: usize) -> io::Result<()> {
+        if slot_idx >= self.array.len() {
+            return Err(PageError::SlotOutOfRange { slot_idx, capacity: self.array.len() }.into());
+        }
+
+        Ok(())
     }
 
     fn set(&mut self, slot_idx: usize) {
         let byte_idx = slot_idx / 8;
         let bit_idx = slot_idx % 8;
-        self.occupied[byte_idx] |= 0x01 << bit_idx
+        self.occupied[byte_idx] |= 0x01 << bit_idx;
+        self.readable[byte_idx] |= 0x01 << bit_idx
     }
 
     fn clear(&mut self, slot_idx: usize) {
         let byte_idx = slot_idx / 8;
         let bit_idx = slot_idx % 8;
-        self.occupied[byte_idx] &= !(0x01 << bit_idx)
+        self.occupied[byte_idx] &= !(0x01 << bit_idx);
+        self.readable[byte_idx] &= !(0x01 << bit_idx);
+        self.overflow_refs.remove(&slot_idx);
     }
 }
 
+impl<K: HashKeyType + DeserializeOwned, V: ValueType + DeserializeOwned> PageSerde for HashTableBlockPage<K, V> {
+    const VERSION: PageVersion = BLOCK_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<HashTableBlockPage<K, V>> {
+        HashTableBlockPage::deserialize(page_data)
+    }
+}
+
+/// Block page read and written in place on the page data, decoding only the slots asked for instead of
+/// the whole page like `HashTableBlockPage::deserialize`. Slots sit at fixed offsets, so a lookup touches
+/// the bitmaps and its own slot only.
+pub struct HashTableBlockView<K, V, D> {
+    data: D,
+    body_offset: usize,
+    capacity: usize,
+    bitmaps: usize,
+    /// Zero before v6, which has no key filter.
+    filter_size: usize,
+    /// False before v4, where every occupied slot is live.
+    tracks_readable: bool,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, D> HashTableBlockView<K, V, D>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+        D: AsRef<[u8]>,
+{
+    /// Reads every layout in place, a page of an older version keeps its own layout when written.
+    pub fn from_page(data: D) -> io::Result<HashTableBlockView<K, V, D>> {
+        let page_data = data.as_ref();
+        let version = read_page_version(page_data)?;
+        let (body_offset, capacity, bitmaps) = match version {
+            BLOCK_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableBlock)?;
+                (PAGE_HEADER_SIZE, HashTableBlockPage::<K, V>::capacity_of_block(), BITMAPS)
+            },
+            V5_BLOCK_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableBlock)?;
+                (PAGE_HEADER_SIZE, HashTableBlockPage::<K, V>::capacity_with_overflowed(PAGE_SIZE - PAGE_HEADER_SIZE), BITMAPS)
+            },
+            V4_BLOCK_PAGE_VERSION | V3_BLOCK_PAGE_VERSION => (UNTYPED_PAGE_HEADER_SIZE,
+                HashTableBlockPage::<K, V>::capacity_with_overflowed(PAGE_SIZE - UNTYPED_PAGE_HEADER_SIZE), BITMAPS),
+            V2_BLOCK_PAGE_VERSION => (UNTYPED_PAGE_HEADER_SIZE, HashTableBlockPage::<K, V>::capacity_of(PAGE_SIZE - UNTYPED_PAGE_HEADER_SIZE), V2_BITMAPS),
+            V1_BLOCK_PAGE_VERSION => (PAGE_VERSION_SIZE, HashTableBlockPage::<K, V>::capacity_of(PAGE_SIZE - PAGE_VERSION_SIZE), V2_BITMAPS),
+            version => return Err(PageError::UnknownVersion { found: version, supported: BLOCK_PAGE_VERSION }.into()),
+        };
+        let filter_size = if version == BLOCK_PAGE_VERSION { filter_size(capacity) } else { 0 };
+        let expected_size = body_offset + bitmaps * bitmap_size(capacity) + filter_size
+            + capacity * HashTableBlockPage::<K, V>::slot_size();
+        if page_data.len() < expected_size {
+            return Err(PageError::PageDataTooShort { expected: expected_size, actual: page_data.len() }.into());
+        }
+
+        let tracks_readable = version >= V4_BLOCK_PAGE_VERSION;
+        Ok(HashTableBlockView { data, body_offset, capacity, bitmaps, filter_size, tracks_readable, phantom: PhantomData })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// False only for a key no pair was ever written to the block for, so its slots need not be decoded
+    /// when looking the key up. Blocks of layouts before the filter may contain any key.
+    pub fn may_contain(&self, key: &K) -> bool {
+        self.filter_size == 0 || filter_contains(&self.data.as_ref()[self.filter_offset()..self.filter_offset() + self.filter_size], key)
+    }
+
+    fn filter_offset(&self) -> usize {
+        self.body_offset + self.bitmaps * bitmap_size(self.capacity)
+    }
+
+    pub fn is_occupied(&self, slot_idx: usize) -> io::Result<bool> {
+        self.validate_slot_idx(slot_idx)?;
+        Ok((self.data.as_ref()[self.body_offset + slot_idx / 8] >> (slot_idx % 8)) & 0x01 == 1)
+    }
+
+    pub fn is_readable(&self, slot_idx: usize) -> io::Result<bool> {
+        if !self.tracks_readable {
+            return self.is_occupied(slot_idx);
+        }
+
+        self.validate_slot_idx(slot_idx)?;
+        let readable_offset = self.body_offset + bitmap_size(self.capacity);
+        Ok((self.data.as_ref()[readable_offset + slot_idx / 8] >> (slot_idx % 8)) & 0x01 == 1)
+    }
+
+    pub fn num_occupied(&self) -> usize {
+        count_bits(&self.data.as_ref()[self.body_offset..self.body_offset + bitmap_size(self.capacity)])
+    }
+
+    pub fn num_readable(&self) -> usize {
+        if !self.tracks_readable {
+            return self.num_occupied();
+        }
+
+        let readable_offset = self.body_offset + bitmap_size(self.capacity);
+        count_bits(&self.data.as_ref()[readable_offset..readable_offset + bitmap_size(self.capacity)])
+    }
+
+    pub fn is_overflowed(&self, slot_idx: usize) -> io::Result<bool> {
+        self.validate_slot_idx(slot_idx)?;
+        if self.bitmaps != BITMAPS {
+            return Ok(false);
+        }
+
+        let overflowed_offset = self.body_offset + 2 * bitmap_size(self.capacity);
+        Ok((self.data.as_ref()[overflowed_offset + slot_idx / 8] >> (slot_idx % 8)) & 0x01 == 1)
+    }
+
+    pub fn get(&self, slot_idx: usize) -> io::Result<(K, SlotValue<V>)> {
+        let start = self.slot_offset(slot_idx);
+        let slot = &self.data.as_ref()[start..start + HashTableBlockPage::<K, V>::slot_size()];
+        if self.is_overflowed(slot_idx)? {
+            let (k, overflow_ref) = bincode::deserialize::<(K, OverflowRef)>(slot).map_err(PageError::from)?;
+            return Ok((k, SlotValue::Overflow(overflow_ref)));
+        }
+
+        let (k, v) = bincode::deserialize::<(K, V)>(slot).map_err(PageError::from)?;
+        Ok((k, SlotValue::Inline(v)))
+    }
+
+    /// Decodes the key alone, leaving the value in place.
+    pub fn get_key(&self, slot_idx: usize) -> io::Result<K> {
+        self.validate_slot_idx(slot_idx)?;
+        let start = self.slot_offset(slot_idx);
+        let slot = &self.data.as_ref()[start..start + HashTableBlockPage::<K, V>::slot_size()];
+        Ok(bincode::deserialize::<K>(slot).map_err(PageError::from)?)
+    }
+
+    fn slot_offset(&self, slot_idx: usize) -> usize {
+        self.filter_offset() + self.filter_size + slot_idx * HashTableBlockPage::<K, V>::slot_size()
+    }
+
+    fn validate_slot_idx(&self, slot_idx: usize) -> io::Result<()> {
+        if slot_idx >= self.capacity {
+            return Err(PageError::SlotOutOfRange { slot_idx, capacity: self.capacity }.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V, D> HashTableBlockView<K, V, D>
+    where
+        K: HashKeyType + DeserializeOwned,
+        V: ValueType + DeserializeOwned,
+        D: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Formats the page as an empty block of the current version.
+    pub fn init(mut data: D) -> io::Result<HashTableBlockView<K, V, D>> {
+        let empty = HashTableBlockPage::<K, V>::new().serialize()?;
+        let page_data = data.as_mut();
+        if page_data.len() < empty.len() {
+            return Err(PageError::PageDataTooShort { expected: empty.len(), actual: page_data.len() }.into());
+        }
+
+        page_data[..empty.len()].copy_from_slice(&empty);
+        HashTableBlockView::from_page(data)
+    }
+
+    /// Writes the pair into a free slot or a tombstone, like `HashTableBlockPage::insert`. A value not
+    /// fitting into the slot fails with `PageError::ValueTooLarge`, see `insert_overflowed`.
+    pub fn insert(&mut self, slot_idx: usize, key: &K, value: &V) -> io::Result<bool> {
+        if self.is_readable(slot_idx)? {
+            return Ok(false);
+        }
+
+        let start = self.slot_offset(slot_idx);
+        let page_data = self.data.as_mut();
+        encode_slot(&mut page_data[start..start + HashTableBlockPage::<K, V>::slot_size()], &(key, value))?;
+        self.mark_live(slot_idx, key, false);
+        Ok(true)
+    }
+
+    fn mark_live(&mut self, slot_idx: usize, key: &K, overflowed: bool) {
+        let (byte_idx, bit) = (slot_idx / 8, 0x01 << (slot_idx % 8));
+        let bitmap_size = bitmap_size(self.capacity);
+        let occupied_offset = self.body_offset;
+        let (readable_offset, overflowed_offset) = (occupied_offset + bitmap_size, occupied_offset + 2 * bitmap_size);
+        let (filter_offset, filter_size) = (self.filter_offset(), self.filter_size);
+        let page_data = self.data.as_mut();
+        add_to_filter(&mut page_data[filter_offset..filter_offset + filter_size], key);
+        page_data[occupied_offset + byte_idx] |= bit;
+        page_data[readable_offset + byte_idx] |= bit;
+        if self.bitmaps == BITMAPS {
+            match overflowed {
+                true => page_data[overflowed_offset + byte_idx] |= bit,
+                false => page_data[overflowed_offset + byte_idx] &= !bit,
+            }
+        }
+    }
+
+    /// Writes the key along with where its value was put. Pages of versions before overflow chains
+    /// cannot refer to one and fail with `PageError::ValueTooLarge`.
+    pub fn insert_overflowed(&mut self, slot_idx: usize, key: &K, overflow_ref: &OverflowRef) -> io::Result<bool> {
+        if self.bitmaps != BITMAPS {
+            let slot_size = HashTableBlockPage::<K, V>::slot_size();
+            return Err(PageError::ValueTooLarge { size: overflow_ref.len as usize, capacity: slot_size }.into());
+        }
+        if self.is_readable(slot_idx)? {
+            return Ok(false);
+        }
+
+        let start = self.slot_offset(slot_idx);
+        let page_data = self.data.as_mut();
+        encode_slot(&mut page_data[start..start + HashTableBlockPage::<K, V>::slot_size()], &(key, overflow_ref))?;
+        self.mark_live(slot_idx, key, true);
+        Ok(true)
+    }
+
+    /// Leaves a tombstone in the slot, whatever pair it holds. An overflowed value keeps its pages.
+    pub fn remove(&mut self, slot_idx: usize) -> io::Result<bool> {
+        if !self.is_readable(slot_idx)? {
+            return Ok(false);
+        }
+
+        let (byte_idx, bit) = (slot_idx / 8, 0x01 << (slot_idx % 8));
+        let readable_offset = self.body_offset + bitmap_size(self.capacity);
+        self.data.as_mut()[readable_offset + byte_idx] &= !bit;
+        Ok(true)
+    }
+}
+
+fn bitmap_size(capacity: usize) -> usize {
+    (capacity - 1) / 8 + 1
+}
+
+/// One byte of filter per slot, eight bits per key when the block is full.
+fn filter_size(capacity: usize) -> usize {
+    capacity
+}
+
+/// Bits of the key in a filter, by double hashing one hash of it. Seeded apart from the default table
+/// hasher, so keys placed into the same block do not share bits.
+fn filter_bits<K: HashKeyType>(filter: &[u8], key: &K) -> impl Iterator<Item = usize> {
+    let hash = XxKeyHasher::with_seed(FILTER_SEED).hash(key);
+    let (h1, h2) = (hash as u32, (hash >> 32) as u32 | 1);
+    let filter_bits = filter.len() * 8;
+    (0..FILTER_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) as usize % filter_bits)
+}
+
+fn add_to_filter<K: HashKeyType>(filter: &mut [u8], key: &K) {
+    if filter.is_empty() {
+        return;
+    }
+
+    for bit in filter_bits(filter, key).collect::<Vec<_>>() {
+        filter[bit / 8] |= 0x01 << (bit % 8);
+    }
+}
+
+fn filter_contains<K: HashKeyType>(filter: &[u8], key: &K) -> bool {
+    filter_bits(filter, key).all(|bit| (filter[bit / 8] >> (bit % 8)) & 0x01 == 1)
+}
+
+/// Bits past the capacity are never set, so whole bytes can be counted.
+fn count_bits(bitmap: &[u8]) -> usize {
+    bitmap.iter().map(|byte| byte.count_ones() as usize).sum()
+}
+
+pub(crate) fn encode_slot<T: Serialize>(slot: &mut [u8], pair: &T) -> io::Result<()> {
+    let size = bincode::serialized_size(pair).map_err(PageError::from)? as usize;
+    if size > slot.len() {
+        return Err(PageError::ValueTooLarge { size, capacity: slot.len() }.into());
+    }
+
+    bincode::serialize_into(slot, pair).map_err(PageError::from)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::storage::page::hash_table_block_page::{HashKeyType, ValueType, HashTableBlockPage};
+    use crate::storage::page::hash_table_block_page::{HashKeyType, ValueType, HashTableBlockPage, HashTableBlockView, SlotValue, BITMAPS, BLOCK_PAGE_VERSION, V2_BITMAPS, V5_BLOCK_PAGE_VERSION};
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::overflow_page::OverflowRef;
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_version::{PageType, PAGE_TYPE_OFFSET, UNTYPED_PAGE_HEADER_SIZE};
     use std::hash::Hash;
     use serde::{Serialize, Deserialize};
 
@@ -145,7 +722,8 @@ mod tests {
         let block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
         assert_eq!(block.occupied.capacity(), 17);
         assert_eq!(block.readable.capacity(), 17);
-        assert_eq!(block.array.capacity(), 135);
+        assert_eq!(block.array.capacity(), 130);
+        assert_eq!(block.filter.len(), 130);
     }
 
     #[test]
@@ -155,9 +733,9 @@ mod tests {
         block.occupied[10] = 0b0010_1000;
 
         // when
-        let is_occupied_83 = block.is_occupied(83);
-        let is_occupied_85 = block.is_occupied(85);
-        let not_occupied_86 = block.is_occupied(86);
+        let is_occupied_83 = block.is_occupied(83).unwrap();
+        let is_occupied_85 = block.is_occupied(85).unwrap();
+        let not_occupied_86 = block.is_occupied(86).unwrap();
 
         // then
         assert!(is_occupied_83);
@@ -172,7 +750,7 @@ mod tests {
         block.occupied[10] = 0b0010_1000;
 
         // when
-        assert!(!block.is_occupied(86));
+        assert!(!block.is_occupied(86).unwrap());
         block.set(86);
 
         // then
@@ -186,7 +764,7 @@ mod tests {
         block.occupied[10] = 0b0010_1000;
 
         // when
-        assert!(block.is_occupied(83));
+        assert!(block.is_occupied(83).unwrap());
         block.clear(83);
 
         // then
@@ -202,11 +780,11 @@ mod tests {
         let value = FakeValue { data: [127; 20] };
 
         // when
-        let inserted = block.insert(86, key, value);
+        let inserted = block.insert(86, key, value).unwrap();
 
         // then
         assert!(inserted);
-        assert!(block.is_occupied(86));
+        assert!(block.is_occupied(86).unwrap());
         let mapping = &block.array[86];
         assert_eq!(mapping.key.data[0], 1);
         assert_eq!(mapping.value.data[0], 127);
@@ -217,15 +795,16 @@ mod tests {
         // given
         let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
         block.occupied[10] = 0b0010_1000;
+        block.readable[10] = 0b0010_1000;
         let key = FakeKey { data: [1; 10] };
         let value = FakeValue { data: [127; 20] };
 
         // when
-        let inserted = block.insert(83, key, value);
+        let inserted = block.insert(83, key, value).unwrap();
 
         // then
         assert!(!inserted);
-        assert!(!block.is_occupied(86));
+        assert!(!block.is_occupied(86).unwrap());
     }
 
     #[test]
@@ -235,19 +814,21 @@ mod tests {
         block.occupied[10] = 0b0010_1000;
         let key = FakeKey { data: [1; 10] };
         let value = FakeValue { data: [127; 20] };
-        block.insert(86, key, value);
+        block.insert(86, key, value).unwrap();
 
         // when
-        let raw = block.serialize();
+        let raw = block.serialize().unwrap();
 
         // then
-        // array size == 135, occupied,readable size == 17
-        assert_eq!(raw[10], 0b0110_1000);
-        // array index == 86 -> real index == 17*2 + 86*30 = 2614 (MappingType first idx)
-        assert_eq!(raw[2613], 0);
-        assert_eq!(raw[2614], 1);
-        assert_eq!(raw[2623], 1);
-        assert_eq!(raw[2624], 127);
+        // array size == 130, occupied,readable,overflowed size == 17, after 1 version byte, 8 LSN bytes and the page type
+        assert_eq!(raw[0], BLOCK_PAGE_VERSION);
+        assert_eq!(raw[9], PageType::HashTableBlock as u8);
+        assert_eq!(raw[20], 0b0110_1000);
+        // array index == 86 -> real index == 10 + 17*3 + 130 filter bytes + 86*30 = 2771 (MappingType first idx)
+        assert_eq!(raw[2770], 0);
+        assert_eq!(raw[2771], 1);
+        assert_eq!(raw[2780], 1);
+        assert_eq!(raw[2781], 127);
     }
 
     #[test]
@@ -257,8 +838,8 @@ mod tests {
         block.occupied[10] = 0b0010_1000;
         let key = FakeKey { data: [1; 10] };
         let value = FakeValue { data: [127; 20] };
-        block.insert(86, key, value);
-        let raw = block.serialize();
+        block.insert(86, key, value).unwrap();
+        let raw = block.serialize().unwrap();
 
         // when
         let deser_block: HashTableBlockPage<FakeKey, FakeValue> =
@@ -268,4 +849,292 @@ mod tests {
         assert_eq!(deser_block.occupied[10], 0b0110_1000);
         assert_eq!(deser_block.array[86].key.data, [1; 10]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_fail_when_slot_out_of_range() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        let key = FakeKey { data: [1; 10] };
+        let value = FakeValue { data: [127; 20] };
+
+        // when
+        let inserted = block.insert(130, key, value);
+        let got = block.get(usize::MAX);
+        let occupied = block.is_occupied(136);
+
+        // then
+        assert!(matches!(PageError::from_io(&inserted.unwrap_err()), Some(PageError::SlotOutOfRange { slot_idx: 130, capacity: 130 })));
+        assert!(matches!(PageError::from_io(&got.err().unwrap()), Some(PageError::SlotOutOfRange { .. })));
+        assert!(occupied.is_err());
+    }
+
+    #[test]
+    fn should_fail_to_deserialize_when_page_data_too_short() {
+        // given
+        let raw = HashTableBlockPage::<FakeKey, FakeValue>::new().serialize().unwrap();
+
+        // when
+        let result = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(&raw[..100]);
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::PageDataTooShort { expected: 4081, actual: 90 })));
+    }
+
+    #[test]
+    fn should_fail_to_deserialize_unknown_version() {
+        // given
+        let block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        let mut raw = block.serialize().unwrap();
+        raw[0] = BLOCK_PAGE_VERSION + 1;
+
+        // when
+        let result = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(raw.as_slice());
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::UnknownVersion { found: 7, supported: 6 })));
+    }
+
+    #[test]
+    fn should_deserialize_legacy_block() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        block.insert(86, FakeKey { data: [1; 10] }, FakeValue { data: [127; 20] }).unwrap();
+        let mut legacy_raw = block.encode(V2_BITMAPS, &[]).unwrap();
+        legacy_raw.resize(PAGE_SIZE, 0);
+
+        // when
+        let legacy_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize_legacy(legacy_raw.as_slice()).unwrap();
+
+        // then
+        assert!(legacy_block.is_occupied(86).unwrap());
+        assert_eq!(legacy_block.get(86).unwrap().0.data, [1; 10]);
+        assert_eq!(legacy_block.get(86).unwrap().1.data, [127; 20]);
+    }
+
+    #[test]
+    fn should_keep_lsn_and_read_v1_block() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        block.insert(86, FakeKey { data: [1; 10] }, FakeValue { data: [127; 20] }).unwrap();
+        block.set_lsn(42);
+        let raw = block.serialize().unwrap();
+        let mut v1_raw = vec![1];
+        v1_raw.append(&mut block.encode(V2_BITMAPS, &[]).unwrap());
+        v1_raw.resize(PAGE_SIZE, 0);
+
+        // when
+        let deser_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(raw.as_slice()).unwrap();
+        let v1_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(v1_raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(deser_block.get_lsn(), 42);
+        assert_eq!(raw[1..PAGE_TYPE_OFFSET], 42u64.to_le_bytes());
+        assert_eq!(v1_block.get_lsn(), 0);
+        assert_eq!(v1_block.get(86).unwrap().0.data, [1; 10]);
+    }
+
+    #[test]
+    fn should_read_and_insert_slots_in_place() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        block.insert(86, FakeKey { data: [1; 10] }, FakeValue { data: [127; 20] }).unwrap();
+        let mut raw = block.serialize().unwrap();
+
+        // when
+        let inserted = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(raw.as_mut_slice()).unwrap()
+            .insert(3, &FakeKey { data: [2; 10] }, &FakeValue { data: [9; 20] }).unwrap();
+
+        // then
+        assert!(inserted);
+        let view = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(raw.as_slice()).unwrap();
+        assert_eq!(view.capacity(), HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block());
+        assert!(view.is_occupied(86).unwrap());
+        assert!(!view.is_occupied(4).unwrap());
+        assert!(matches!(view.get(86).unwrap().1, SlotValue::Inline(v) if v.data == [127; 20]));
+        let deser_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(raw.as_slice()).unwrap();
+        assert_eq!(deser_block.get(3).unwrap().0.data, [2; 10]);
+    }
+
+    #[test]
+    fn should_keep_overflow_ref_of_slot() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        let overflow_ref = OverflowRef { page_id: 12, len: 5000 };
+        block.insert_overflowed(5, FakeKey { data: [1; 10] }, overflow_ref).unwrap();
+        block.insert(6, FakeKey { data: [2; 10] }, FakeValue { data: [127; 20] }).unwrap();
+        let raw = block.serialize().unwrap();
+
+        // when
+        let deser_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(raw.as_slice()).unwrap();
+        let view = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(deser_block.get_overflow(5).unwrap(), Some(overflow_ref));
+        assert_eq!(deser_block.get(5).unwrap().0.data, [1; 10]);
+        assert_eq!(deser_block.get_overflow(6).unwrap(), None);
+        assert!(matches!(view.get(5).unwrap().1, SlotValue::Overflow(r) if r == overflow_ref));
+        assert!(!view.is_overflowed(6).unwrap());
+    }
+
+    #[test]
+    fn should_reuse_tombstone_and_read_pre_v4_slots_as_live() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        block.insert(3, FakeKey { data: [1; 10] }, FakeValue { data: [127; 20] }).unwrap();
+        block.occupied[0] |= 0b0001_0000;
+        let mut raw = block.serialize().unwrap();
+
+        // when
+        let mut view = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(raw.as_mut_slice()).unwrap();
+        let tombstone = (view.is_occupied(4).unwrap(), view.is_readable(4).unwrap());
+        let reused = view.insert(4, &FakeKey { data: [2; 10] }, &FakeValue { data: [9; 20] }).unwrap();
+        // v3 pages have no page type nor key filter, and never set the readable bits
+        let mut v3_raw = vec![3];
+        v3_raw.extend_from_slice(&0u64.to_le_bytes());
+        v3_raw.append(&mut block.encode(BITMAPS, &[]).unwrap());
+        v3_raw.resize(PAGE_SIZE, 0);
+        v3_raw[UNTYPED_PAGE_HEADER_SIZE + 17] = 0;
+
+        // then
+        assert_eq!(tombstone, (true, false));
+        assert!(reused);
+        assert!(!view.insert(3, &FakeKey { data: [2; 10] }, &FakeValue { data: [9; 20] }).unwrap());
+        let v3_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(v3_raw.as_slice()).unwrap();
+        assert!(v3_block.is_readable(3).unwrap() && v3_block.is_readable(4).unwrap());
+    }
+
+    #[test]
+    fn should_remove_only_matching_pair_and_keep_slot_occupied() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        let (key, value) = (FakeKey { data: [1; 10] }, FakeValue { data: [127; 20] });
+        block.insert(86, key.clone(), value.clone()).unwrap();
+
+        // when
+        let other_value_removed = block.remove(86, &key, &FakeValue { data: [1; 20] }).unwrap();
+        let removed = block.remove(86, &key, &value).unwrap();
+
+        // then
+        assert!(!other_value_removed);
+        assert!(removed);
+        assert!(!block.remove(86, &key, &value).unwrap());
+        assert!(block.is_occupied(86).unwrap());
+        assert!(!block.is_readable(86).unwrap());
+        let deser_block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(&block.serialize().unwrap()).unwrap();
+        assert!(deser_block.is_occupied(86).unwrap() && !deser_block.is_readable(86).unwrap());
+    }
+
+    #[test]
+    fn should_compact_tombstones_keeping_pairs_reachable() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        let capacity = HashTableBlockPage::<FakeKey, FakeValue>::capacity_of_block();
+        let key = |i: u8| FakeKey { data: [i; 10] };
+        let value = FakeValue { data: [7; 20] };
+        // keys hash to slot == data[0], key 0 comes from an earlier block
+        for (slot_idx, k) in [(0, 0), (1, 1), (2, 1), (3, 1), (4, 4), (10, 10), (capacity - 2, 200), (capacity - 1, 200)] {
+            block.insert(slot_idx, key(k as u8), value.clone()).unwrap();
+        }
+        block.insert_overflowed(5, key(4), OverflowRef { page_id: 9, len: 100 }).unwrap();
+        block.remove(1, &key(1), &value).unwrap();
+        block.remove(4, &key(4), &value).unwrap();
+        block.remove(capacity - 2, &key(200), &value).unwrap();
+
+        // when
+        let reclaimed = block.compact(|k| if k.data[0] == 0 { None } else { Some(k.data[0] as usize) });
+
+        // then
+        assert_eq!(reclaimed, 2);
+        assert_eq!([0, 1, 2, 4].map(|i| block.get(i).unwrap().0.data[0]), [0, 1, 1, 4]);
+        assert!([0, 1, 2, 4].iter().all(|i| block.is_readable(*i).unwrap()));
+        assert_eq!(block.get_overflow(4).unwrap(), Some(OverflowRef { page_id: 9, len: 100 }));
+        assert!(!block.is_occupied(3).unwrap() && !block.is_occupied(5).unwrap());
+        assert!(block.is_readable(10).unwrap());
+        // run reaching the block end kept, tombstone included
+        assert!(block.is_occupied(capacity - 2).unwrap() && !block.is_readable(capacity - 2).unwrap());
+    }
+
+    #[test]
+    fn should_count_occupied_and_readable_slots() {
+        // given
+        let mut block: HashTableBlockPage<FakeKey, FakeValue> = HashTableBlockPage::new();
+        let (key, value) = (FakeKey { data: [1; 10] }, FakeValue { data: [127; 20] });
+        for slot_idx in [3, 4, 100, 129] {
+            block.insert(slot_idx, key.clone(), value.clone()).unwrap();
+        }
+
+        // when
+        block.remove(4, &key, &value).unwrap();
+
+        // then
+        assert_eq!((block.num_occupied(), block.num_readable()), (4, 3));
+        assert!((block.fill_factor() - 4.0 / 130.0).abs() < f64::EPSILON);
+        let raw = block.serialize().unwrap();
+        let view = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(raw.as_slice()).unwrap();
+        assert_eq!((view.num_occupied(), view.num_readable()), (4, 3));
+    }
+
+    #[test]
+    fn should_rule_out_keys_never_written_to_block_but_not_for_v5_blocks() {
+        // given
+        let key = |i: u8| FakeKey { data: [i; 10] };
+        let mut raw = vec![0; PAGE_SIZE];
+        let mut view = HashTableBlockView::<FakeKey, FakeValue, _>::init(raw.as_mut_slice()).unwrap();
+        for i in 0..100 {
+            view.insert(i as usize, &key(i), &FakeValue { data: [i; 20] }).unwrap();
+        }
+        view.remove(0).unwrap();
+        // v5 pages have no key filter, their bitmaps are as large for this pair
+        let mut v5_raw = vec![V5_BLOCK_PAGE_VERSION];
+        v5_raw.extend_from_slice(&0u64.to_le_bytes());
+        v5_raw.push(PageType::HashTableBlock as u8);
+        v5_raw.append(&mut HashTableBlockPage::<FakeKey, FakeValue>::deserialize(&raw).unwrap().encode(BITMAPS, &[]).unwrap());
+        v5_raw.resize(PAGE_SIZE, 0);
+
+        // when
+        let view = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(raw.as_slice()).unwrap();
+        let v5_view = HashTableBlockView::<FakeKey, FakeValue, _>::from_page(v5_raw.as_slice()).unwrap();
+
+        // then
+        assert!((0..100).all(|i| view.may_contain(&key(i))));
+        assert!((100..=255).filter(|i| view.may_contain(&key(*i))).count() < 20);
+        assert!(v5_view.may_contain(&key(200)));
+        assert!(v5_view.get_key(7).unwrap() == key(7));
+        // decoding drops the bits of removed pairs, stale bits only ever kept a key in
+        let block = HashTableBlockPage::<FakeKey, FakeValue>::deserialize(&raw).unwrap();
+        assert!(!block.may_contain(&key(0)) && block.may_contain(&key(1)));
+    }
+
+    /// Name of at most 40 bytes, whose in-memory size says nothing of its encoding.
+    #[derive(Hash, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct NameKey(String);
+
+    impl HashKeyType for NameKey {
+        const ENCODED_SIZE: usize = 8 + 40;
+    }
+
+    #[test]
+    fn should_size_slots_by_encoded_size_of_heap_allocated_keys() {
+        // given
+        let mut raw = vec![0; PAGE_SIZE];
+        let mut view = HashTableBlockView::<NameKey, u64, _>::init(raw.as_mut_slice()).unwrap();
+        let (short_name, full_name) = (NameKey("a".to_string()), NameKey("b".repeat(40)));
+
+        // when
+        view.insert(0, &short_name, &1).unwrap();
+        view.insert(1, &full_name, &2).unwrap();
+        let too_long = view.insert(2, &NameKey("c".repeat(41)), &3).err().unwrap();
+
+        // then
+        assert_eq!(HashTableBlockPage::<NameKey, u64>::slot_size(), 8 + 40 + 8);
+        assert!(matches!(PageError::from_io(&too_long), Some(PageError::ValueTooLarge { .. })));
+        let block = HashTableBlockPage::<NameKey, u64>::deserialize(&raw).unwrap();
+        assert!(block.get(0).unwrap() == (&short_name, &1));
+        assert!(block.get(1).unwrap() == (&full_name, &2));
+        assert!(!block.is_occupied(2).unwrap());
+        let reencoded = block.serialize().unwrap();
+        assert!(raw[..reencoded.len()] == reencoded[..]);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::storage::page::page::PAGE_SIZE;
+use crate::common::hash::*;
+use std::{mem, io};
+use std::convert::TryInto;
+use crate::common::ValueType;
+use crate::storage::page::hash_table_block_page::{encode_slot, HashTableBlockPage, MappingType};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, UNTYPED_PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+use serde::de::DeserializeOwned;
+
+/// v2: version byte, LSN, page type, local depth, then occupied bits, readable bits and the mapping array.
+/// v1: version byte, LSN, local depth, then occupied bits, readable bits and the mapping array.
+const BUCKET_PAGE_VERSION: PageVersion = 2;
+const V1_BUCKET_PAGE_VERSION: PageVersion = 1;
+const LOCAL_DEPTH_SIZE: usize = mem::size_of::<u32>();
+
+/// Bucket of an extendible hash table. Unlike the linear probe block page, a key may sit in any slot of
+/// its bucket, and a full bucket is split in two by one more bit of the hash instead of probing onwards.
+///
+/// Occupied slots always form a prefix, so lookups stop at the first never used slot. Removing only
+/// clears the readable bit, leaving a tombstone the next insert reuses.
+pub struct HashTableBucketPage<K: HashKeyType, V: ValueType> {
+    page_lsn: Lsn,
+    local_depth: u32,
+    occupied: Vec<u8>,
+    readable: Vec<u8>,
+    array: Vec<MappingType<K, V>>,
+}
+
+impl<K: HashKeyType + DeserializeOwned, V: ValueType + DeserializeOwned> HashTableBucketPage<K, V> {
+    pub fn new(local_depth: u32) -> HashTableBucketPage<K, V> {
+        let capacity = HashTableBucketPage::<K, V>::capacity_of_bucket();
+        HashTableBucketPage {
+            page_lsn: 0,
+            local_depth,
+            occupied: vec![0; (capacity - 1) / 8 + 1],
+            readable: vec![0; (capacity - 1) / 8 + 1],
+            array: vec![MappingType {key: Default::default(), value: Default::default()}; capacity]
+        }
+    }
+
+    /// Size of MappingTypes in one page: size_of(MappingType) + 0.25, 0.25 = 2/8 byte = occupied bit + readable bit
+    pub fn capacity_of_bucket() -> usize {
+        HashTableBucketPage::<K, V>::capacity_of(PAGE_SIZE - PAGE_HEADER_SIZE - LOCAL_DEPTH_SIZE)
+    }
+
+    fn capacity_of(body_size: usize) -> usize {
+        4 * body_size / (4 * HashTableBlockPage::<K, V>::slot_size() + 1)
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_local_depth(&self) -> u32 {
+        self.local_depth
+    }
+
+    pub fn set_local_depth(&mut self, local_depth: u32) {
+        self.local_depth = local_depth
+    }
+
+    /// Hash bits a key of this bucket shares with all the others.
+    pub fn local_depth_mask(&self) -> u64 {
+        (1u64 << self.local_depth) - 1
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![BUCKET_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::HashTableBucket as u8);
+        res.extend_from_slice(&self.local_depth.to_le_bytes());
+        res.append(&mut self.occupied.clone());
+        res.append(&mut self.readable.clone());
+        // slots stay at fixed offsets whatever the serialized size of their pair
+        let slot_size = HashTableBlockPage::<K, V>::slot_size();
+        for mapping_type in self.array.iter() {
+            let start = res.len();
+            res.resize(start + slot_size, 0);
+            encode_slot(&mut res[start..], mapping_type)?;
+        }
+
+        Ok(res)
+    }
+
+    /// Stored slots beyond the current capacity must be unused, otherwise they would be lost.
+    pub fn deserialize(page_data: &[u8]) -> io::Result<HashTableBucketPage<K, V>> {
+        let header_size = match read_page_version(page_data)? {
+            BUCKET_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableBucket)?;
+                PAGE_HEADER_SIZE
+            },
+            V1_BUCKET_PAGE_VERSION => UNTYPED_PAGE_HEADER_SIZE,
+            version => return Err(PageError::UnknownVersion { found: version, supported: BUCKET_PAGE_VERSION }.into()),
+        };
+
+        let body_offset = header_size + LOCAL_DEPTH_SIZE;
+        let stored_capacity = HashTableBucketPage::<K, V>::capacity_of(PAGE_SIZE - body_offset);
+        let array_bit_size = (stored_capacity - 1) / 8 + 1;
+        let mapping_type_size = HashTableBlockPage::<K, V>::slot_size();
+        let expected_size = body_offset + 2 * array_bit_size + stored_capacity * mapping_type_size;
+        if page_data.len() < expected_size {
+            return Err(PageError::PageDataTooShort { expected: expected_size, actual: page_data.len() }.into());
+        }
+
+        let local_depth = u32::from_le_bytes(page_data[header_size..body_offset].try_into().unwrap());
+        let mut bucket = HashTableBucketPage::new(local_depth);
+        bucket.page_lsn = read_page_lsn(page_data)?;
+        let capacity = bucket.array.len();
+        let data = &page_data[body_offset..];
+        let (occupied, readable) = (&data[0..array_bit_size], &data[array_bit_size..2 * array_bit_size]);
+        for i in 0..stored_capacity {
+            let (byte_idx, bit) = (i / 8, 0x01 << (i % 8));
+            if i >= capacity {
+                if occupied[byte_idx] & bit != 0 {
+                    return Err(PageError::SlotOutOfRange { slot_idx: i, capacity }.into());
+                }
+                continue;
+            }
+
+            let start = 2 * array_bit_size + i * mapping_type_size;
+            bucket.array[i] = bincode::deserialize::<MappingType<K, V>>(&data[start..start + mapping_type_size])
+                .map_err(PageError::from)?;
+            bucket.occupied[byte_idx] |= occupied[byte_idx] & bit;
+            bucket.readable[byte_idx] |= readable[byte_idx] & bit;
+        }
+
+        Ok(bucket)
+    }
+
+    /// Puts the pair into the first free slot. False if the same pair is already in, or the bucket is full.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        let mut free_slot = None;
+        for i in 0..self.array.len() {
+            if !self.is_occupied(i) {
+                free_slot = free_slot.or(Some(i));
+                break;
+            }
+
+            if !self.is_readable(i) {
+                free_slot = free_slot.or(Some(i));
+            } else if self.array[i].key == key && self.array[i].value == value {
+                return false;
+            }
+        }
+
+        match free_slot {
+            Some(slot_idx) => {
+                self.array[slot_idx] = MappingType { key, value };
+                set_bit(&mut self.occupied, slot_idx);
+                set_bit(&mut self.readable, slot_idx);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Leaves a tombstone where the pair was. False if the pair is not in.
+    pub fn remove(&mut self, key: &K, value: &V) -> bool {
+        let found = self.readable_slots().find(|&i| &self.array[i].key == key && &self.array[i].value == value);
+        match found {
+            Some(slot_idx) => {
+                clear_bit(&mut self.readable, slot_idx);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn get_value(&self, key: &K) -> Vec<V> {
+        self.readable_slots()
+            .filter(|&i| &self.array[i].key == key)
+            .map(|i| self.array[i].value.clone())
+            .collect()
+    }
+
+    pub fn get(&self, slot_idx: usize) -> io::Result<(&K, &V)> {
+        self.validate_slot_idx(slot_idx)?;
+        let mapping_type = &self.array[slot_idx];
+        Ok((&mapping_type.key, &mapping_type.value))
+    }
+
+    pub fn is_occupied(&self, slot_idx: usize) -> bool {
+        is_bit_set(&self.occupied, slot_idx)
+    }
+
+    pub fn is_readable(&self, slot_idx: usize) -> bool {
+        is_bit_set(&self.readable, slot_idx)
+    }
+
+    pub fn num_readable(&self) -> usize {
+        self.readable.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.num_readable() == self.array.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_readable() == 0
+    }
+
+    /// Splits a full bucket by the next hash bit: pairs having it set move to the returned split image,
+    /// the others stay, and both get the incremented local depth. Tombstones are dropped on the way.
+    pub fn split(&mut self, hash_fn: fn(&K) -> u64) -> HashTableBucketPage<K, V> {
+        let split_bit = 1u64 << self.local_depth;
+        let local_depth = self.local_depth + 1;
+        let pairs: Vec<MappingType<K, V>> = self.readable_slots().map(|i| self.array[i].clone()).collect();
+
+        let mut image = HashTableBucketPage::new(local_depth);
+        let mut kept = HashTableBucketPage::new(local_depth);
+        kept.page_lsn = self.page_lsn;
+        for MappingType { key, value } in pairs {
+            let bucket = if hash_fn(&key) & split_bit == 0 { &mut kept } else { &mut image };
+            bucket.insert(key, value);
+        }
+
+        *self = kept;
+        image
+    }
+
+    fn readable_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.array.len())
+            .take_while(move |&i| self.is_occupied(i))
+            .filter(move |&i| self.is_readable(i))
+    }
+
+    fn validate_slot_idx(&self, slot_idx: usize) -> io::Result<()> {
+        if slot_idx >= self.array.len() {
+            return Err(PageError::SlotOutOfRange { slot_idx, capacity: self.array.len() }.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: HashKeyType + DeserializeOwned, V: ValueType + DeserializeOwned> PageSerde for HashTableBucketPage<K, V> {
+    const VERSION: PageVersion = BUCKET_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<HashTableBucketPage<K, V>> {
+        HashTableBucketPage::deserialize(page_data)
+    }
+}
+
+fn is_bit_set(bits: &[u8], idx: usize) -> bool {
+    bits[idx / 8] & (0x01 << (idx % 8)) != 0
+}
+
+fn set_bit(bits: &mut [u8], idx: usize) {
+    bits[idx / 8] |= 0x01 << (idx % 8)
+}
+
+fn clear_bit(bits: &mut [u8], idx: usize) {
+    bits[idx / 8] &= !(0x01 << (idx % 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::hash_table_bucket_page::{HashTableBucketPage, BUCKET_PAGE_VERSION};
+    use crate::storage::page::page_error::PageError;
+
+    #[test]
+    fn should_insert_get_and_remove_pairs() {
+        // given
+        let mut bucket: HashTableBucketPage<u64, u64> = HashTableBucketPage::new(0);
+        assert!(bucket.insert(1, 10));
+        assert!(bucket.insert(1, 11));
+        assert!(bucket.insert(2, 20));
+
+        // when
+        let duplicated = bucket.insert(1, 10);
+        let removed = bucket.remove(&1, &10);
+
+        // then
+        assert!(!duplicated);
+        assert!(removed);
+        assert!(!bucket.remove(&1, &10));
+        assert_eq!(bucket.get_value(&1), vec![11]);
+        assert!(bucket.is_occupied(0));
+        assert!(!bucket.is_readable(0));
+        assert_eq!(bucket.num_readable(), 2);
+        // tombstone reused
+        assert!(bucket.insert(3, 30));
+        assert_eq!(bucket.get(0).unwrap(), (&3, &30));
+    }
+
+    #[test]
+    fn should_refuse_insert_into_full_bucket() {
+        // given
+        let mut bucket: HashTableBucketPage<u64, u64> = HashTableBucketPage::new(0);
+        let capacity = HashTableBucketPage::<u64, u64>::capacity_of_bucket();
+        for i in 0..capacity as u64 {
+            assert!(bucket.insert(i, i));
+        }
+
+        // when
+        let inserted = bucket.insert(capacity as u64, 0);
+
+        // then
+        assert!(!inserted);
+        assert!(bucket.is_full());
+    }
+
+    #[test]
+    fn should_split_by_next_hash_bit() {
+        // given
+        let mut bucket: HashTableBucketPage<u64, u64> = HashTableBucketPage::new(1);
+        for i in 0..8 {
+            bucket.insert(i, i * 10);
+        }
+        bucket.remove(&4, &40);
+
+        // when
+        let image = bucket.split(|k| *k);
+
+        // then
+        assert_eq!((bucket.get_local_depth(), image.get_local_depth()), (2, 2));
+        assert_eq!(bucket.local_depth_mask(), 0b11);
+        assert_eq!(bucket.get_value(&1), vec![10]);
+        assert_eq!(bucket.get_value(&5), vec![50]);
+        assert_eq!(image.get_value(&2), vec![20]);
+        assert_eq!(image.get_value(&7), vec![70]);
+        assert!(bucket.get_value(&4).is_empty());
+        assert_eq!((bucket.num_readable(), image.num_readable()), (3, 4));
+        assert!(!bucket.is_occupied(3));
+    }
+
+    #[test]
+    fn should_serialize_and_deserialize_bucket() {
+        // given
+        let mut bucket: HashTableBucketPage<u64, u64> = HashTableBucketPage::new(3);
+        bucket.insert(1, 10);
+        bucket.insert(2, 20);
+        bucket.remove(&1, &10);
+        bucket.set_lsn(42);
+
+        // when
+        let raw = bucket.serialize().unwrap();
+        let deser_bucket = HashTableBucketPage::<u64, u64>::deserialize(raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(raw[0], BUCKET_PAGE_VERSION);
+        assert_eq!(deser_bucket.get_lsn(), 42);
+        assert_eq!(deser_bucket.get_local_depth(), 3);
+        assert!(deser_bucket.get_value(&1).is_empty());
+        assert_eq!(deser_bucket.get_value(&2), vec![20]);
+        let err = HashTableBucketPage::<u64, u64>::deserialize(&raw[..100]).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::PageDataTooShort { .. })));
+    }
+}
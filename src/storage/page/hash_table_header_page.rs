@@ -1,9 +1,34 @@
 use crate::storage::page::page::{PageId, PAGE_SIZE, INVALID_PAGE_ID};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, PAGE_VERSION_SIZE, UNTYPED_PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+use std::convert::TryInto;
 use std::{mem, io};
-use std::io::{Error, ErrorKind};
 use serde::{Serialize, Deserialize};
 
-const BLOCK_PAGE_IDS_SIZE: usize = (PAGE_SIZE - mem::size_of::<BasicInfo>()) / mem::size_of::<PageId>();
+/// v5: version byte, LSN, page type, basic info, next header page id, live pair count, then block page ids.
+/// v4: version byte, LSN, page type, basic info, next header page id, then block page ids.
+/// v3: version byte, LSN, page type, then basic info and block page ids.
+/// v2: version byte, LSN, then basic info and block page ids.
+/// v1: version byte, then basic info and block page ids.
+const HEADER_PAGE_VERSION: PageVersion = 5;
+const V4_HEADER_PAGE_VERSION: PageVersion = 4;
+const V3_HEADER_PAGE_VERSION: PageVersion = 3;
+const V2_HEADER_PAGE_VERSION: PageVersion = 2;
+const V1_HEADER_PAGE_VERSION: PageVersion = 1;
+const NEXT_HEADER_PID_SIZE: usize = mem::size_of::<u64>();
+const LEN_SIZE: usize = mem::size_of::<u64>();
+const BLOCK_PAGE_IDS_SIZE: usize =
+    (PAGE_SIZE - PAGE_HEADER_SIZE - mem::size_of::<BasicInfo>() - NEXT_HEADER_PID_SIZE - LEN_SIZE) / mem::size_of::<PageId>();
+/// Headers from before the count hold one block page id more, and keep the v4 layout when written.
+const V4_BLOCK_PAGE_IDS_SIZE: usize =
+    (PAGE_SIZE - PAGE_HEADER_SIZE - mem::size_of::<BasicInfo>() - NEXT_HEADER_PID_SIZE) / mem::size_of::<PageId>();
+/// Headers from before chains hold one block page id more, and keep the v3 layout when written.
+const V3_BLOCK_PAGE_IDS_SIZE: usize = (PAGE_SIZE - PAGE_HEADER_SIZE - mem::size_of::<BasicInfo>()) / mem::size_of::<PageId>();
+const V2_BLOCK_PAGE_IDS_SIZE: usize = (PAGE_SIZE - UNTYPED_PAGE_HEADER_SIZE - mem::size_of::<BasicInfo>()) / mem::size_of::<PageId>();
+const V1_BLOCK_PAGE_IDS_SIZE: usize = (PAGE_SIZE - PAGE_VERSION_SIZE - mem::size_of::<BasicInfo>()) / mem::size_of::<PageId>();
+/// Before versioning the basic info started at byte 0, leaving room for one more block page id.
+const LEGACY_BLOCK_PAGE_IDS_SIZE: usize = (PAGE_SIZE - mem::size_of::<BasicInfo>()) / mem::size_of::<PageId>();
 #[derive(Serialize, Deserialize)]
 struct BasicInfo {
     page_id: PageId,
@@ -11,27 +36,55 @@ struct BasicInfo {
     next_idx: usize,
 }
 
+/// One page of the header of a hash table. A table with more blocks than a page holds ids for links
+/// further header pages through `next_header_pid`, each holding the ids of the blocks following the
+/// previous page's, see `HashTableHeaderChain`. `size` counts the blocks from this page on.
+///
+/// The first page also counts the live pairs of the table, unknown for pages of the layouts before.
 pub struct HashTableHeaderPage {
+    page_lsn: Lsn,
     basic_info: BasicInfo,
-    block_page_ids: [PageId; BLOCK_PAGE_IDS_SIZE]
+    next_header_pid: Option<PageId>,
+    len: Option<u64>,
+    block_page_ids: Vec<PageId>,
 }
 
 impl HashTableHeaderPage {
     pub fn new(pid: PageId, size: usize) -> HashTableHeaderPage {
+        HashTableHeaderPage::with_capacity(pid, size, BLOCK_PAGE_IDS_SIZE)
+    }
+
+    fn with_capacity(pid: PageId, size: usize, ids_capacity: usize) -> HashTableHeaderPage {
         HashTableHeaderPage {
+            page_lsn: 0,
             basic_info: BasicInfo {
                 page_id: pid,
                 size,
                 next_idx: 0
             },
-            block_page_ids: [INVALID_PAGE_ID; BLOCK_PAGE_IDS_SIZE]
+            next_header_pid: None,
+            len: Some(0),
+            block_page_ids: vec![INVALID_PAGE_ID; ids_capacity],
         }
     }
 
+    /// Header pages a table of `size` blocks needs.
+    pub fn pages_needed(size: usize) -> usize {
+        size.div_ceil(BLOCK_PAGE_IDS_SIZE).max(1)
+    }
+
     pub fn get_page_id(&self) -> PageId {
         self.basic_info.page_id
     }
 
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
     pub fn get_size(&self) -> usize {
         self.basic_info.size
     }
@@ -40,9 +93,26 @@ impl HashTableHeaderPage {
         self.basic_info.size = size
     }
 
+    pub fn get_next_header_pid(&self) -> Option<PageId> {
+        self.next_header_pid
+    }
+
+    pub fn set_next_header_pid(&mut self, next_header_pid: Option<PageId>) {
+        self.next_header_pid = next_header_pid
+    }
+
+    pub fn get_len(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// A page keeping a layout from before the count drops it when written.
+    pub fn set_len(&mut self, len: u64) {
+        self.len = Some(len)
+    }
+
     pub fn add(&mut self, pid: PageId) -> io::Result<()> {
         if self.block_page_ids.len() == self.basic_info.next_idx + 1 {
-            return Err(Error::new(ErrorKind::Other, "Hash table header fulled."));
+            return Err(PageError::HeaderFull.into());
         }
 
         self.block_page_ids[self.basic_info.next_idx] = pid;
@@ -50,47 +120,131 @@ impl HashTableHeaderPage {
         Ok(())
     }
 
-    pub fn set(&mut self, pid: PageId, slot_id: usize) {
+    pub fn set(&mut self, pid: PageId, slot_id: usize) -> io::Result<()> {
+        self.validate_slot_idx(slot_id)?;
         self.block_page_ids[slot_id] = pid;
+        Ok(())
     }
 
-    pub fn get_block_page_id(&self, slot_idx: usize) -> Option<PageId> {
-        assert!(slot_idx < self.get_size());
+    pub fn get_block_page_id(&self, slot_idx: usize) -> io::Result<Option<PageId>> {
+        self.validate_slot_idx(slot_idx)?;
 
         let block_pid = self.block_page_ids[slot_idx];
         if block_pid == INVALID_PAGE_ID {
-            return None;
+            return Ok(None);
         }
 
-        Some(block_pid)
+        Ok(Some(block_pid))
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut basic_info_part = bincode::serialize(&self.basic_info).unwrap();
-        for pid in self.block_page_ids {
-            let mut pid_raw = bincode::serialize(&pid).unwrap();
-            basic_info_part.append(&mut pid_raw);
+    /// Slot must be inside both the bucket count and the ids this page can hold.
+    fn validate_slot_idx(&self, slot_idx: usize) -> io::Result<()> {
+        let capacity = self.get_size().min(self.block_page_ids.len());
+        if slot_idx >= capacity {
+            return Err(PageError::SlotOutOfRange { slot_idx, capacity }.into());
         }
 
-        basic_info_part
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let counted = self.block_page_ids.len() <= BLOCK_PAGE_IDS_SIZE;
+        let linked = self.block_page_ids.len() <= V4_BLOCK_PAGE_IDS_SIZE;
+        let mut res = vec![match (counted, linked) {
+            (true, _) => HEADER_PAGE_VERSION,
+            (false, true) => V4_HEADER_PAGE_VERSION,
+            (false, false) => V3_HEADER_PAGE_VERSION,
+        }];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::HashTableHeader as u8);
+        res.append(&mut bincode::serialize(&self.basic_info).map_err(PageError::from)?);
+        if linked {
+            res.extend_from_slice(&(self.next_header_pid.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        }
+        if counted {
+            res.extend_from_slice(&self.len.unwrap_or(0).to_le_bytes());
+        }
+        for pid in &self.block_page_ids {
+            let mut pid_raw = bincode::serialize(pid).map_err(PageError::from)?;
+            res.append(&mut pid_raw);
+        }
+
+        Ok(res)
     }
 
     pub fn deserialize(page_data: &[u8]) -> io::Result<HashTableHeaderPage> {
-        if page_data.len() != PAGE_SIZE {
-            return Err(Error::new(ErrorKind::Other, format!("Wrong page data: size not equal to {}", PAGE_SIZE)));
+        match read_page_version(page_data)? {
+            HEADER_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableHeader)?;
+                let page_lsn = read_page_lsn(page_data)?;
+                let header = HashTableHeaderPage::decode(&page_data[PAGE_HEADER_SIZE..], BLOCK_PAGE_IDS_SIZE, true, true)?;
+                Ok(HashTableHeaderPage { page_lsn, ..header })
+            },
+            V4_HEADER_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableHeader)?;
+                let page_lsn = read_page_lsn(page_data)?;
+                let header = HashTableHeaderPage::decode(&page_data[PAGE_HEADER_SIZE..], V4_BLOCK_PAGE_IDS_SIZE, true, false)?;
+                Ok(HashTableHeaderPage { page_lsn, ..header })
+            },
+            V3_HEADER_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableHeader)?;
+                let page_lsn = read_page_lsn(page_data)?;
+                let header = HashTableHeaderPage::decode(&page_data[PAGE_HEADER_SIZE..], V3_BLOCK_PAGE_IDS_SIZE, false, false)?;
+                Ok(HashTableHeaderPage { page_lsn, ..header })
+            },
+            V2_HEADER_PAGE_VERSION => {
+                let page_lsn = read_page_lsn(page_data)?;
+                let header = HashTableHeaderPage::decode(&page_data[UNTYPED_PAGE_HEADER_SIZE..], V2_BLOCK_PAGE_IDS_SIZE, false, false)?;
+                Ok(HashTableHeaderPage { page_lsn, ..header })
+            },
+            V1_HEADER_PAGE_VERSION => HashTableHeaderPage::decode(&page_data[PAGE_VERSION_SIZE..], V1_BLOCK_PAGE_IDS_SIZE, false, false),
+            version => Err(PageError::UnknownVersion { found: version, supported: HEADER_PAGE_VERSION }.into()),
         }
+    }
 
-        let basic_info_size = mem::size_of::<BasicInfo>();
-        let basic_info = bincode::deserialize::<BasicInfo>(&page_data[0..basic_info_size]).unwrap();
+    /// Decodes a header page of a legacy format file, which has no version byte.
+    pub fn deserialize_legacy(page_data: &[u8]) -> io::Result<HashTableHeaderPage> {
+        HashTableHeaderPage::decode(page_data, LEGACY_BLOCK_PAGE_IDS_SIZE, false, false)
+    }
 
+    /// Stored ids beyond what the layouts still written hold must be unused, otherwise they would be lost.
+    fn decode(data: &[u8], stored_ids_size: usize, linked: bool, counted: bool) -> io::Result<HashTableHeaderPage> {
+        let basic_info_size = mem::size_of::<BasicInfo>();
+        let len_offset = basic_info_size + if linked { NEXT_HEADER_PID_SIZE } else { 0 };
+        let ids_offset = len_offset + if counted { LEN_SIZE } else { 0 };
         let page_id_size = mem::size_of::<PageId>();
-        let mut block_page_ids = [INVALID_PAGE_ID; BLOCK_PAGE_IDS_SIZE];
-        for i in (basic_info_size..(page_data.len() - page_id_size)).step_by(page_id_size) {
-            block_page_ids[(i - basic_info_size) / page_id_size] = bincode::deserialize::<PageId>(&page_data[i..i+page_id_size]).unwrap();
+        let expected_size = ids_offset + stored_ids_size * page_id_size;
+        if data.len() < expected_size {
+            return Err(PageError::PageDataTooShort { expected: expected_size, actual: data.len() }.into());
+        }
+
+        let basic_info = bincode::deserialize::<BasicInfo>(&data[0..basic_info_size]).map_err(PageError::from)?;
+        let next_header_pid = match linked {
+            true => Some(u64::from_le_bytes(data[basic_info_size..len_offset].try_into().unwrap()) as PageId)
+                .filter(|pid| *pid != INVALID_PAGE_ID),
+            false => None,
+        };
+        let len = match counted {
+            true => Some(u64::from_le_bytes(data[len_offset..ids_offset].try_into().unwrap())),
+            false => None,
+        };
+
+        let mut block_page_ids = vec![INVALID_PAGE_ID; stored_ids_size.min(V3_BLOCK_PAGE_IDS_SIZE)];
+        for (i, pid_raw) in data[ids_offset..expected_size].chunks(page_id_size).enumerate() {
+            let pid = bincode::deserialize::<PageId>(pid_raw).map_err(PageError::from)?;
+            match block_page_ids.get_mut(i) {
+                Some(block_page_id) => *block_page_id = pid,
+                None if pid != INVALID_PAGE_ID =>
+                    return Err(PageError::SlotOutOfRange { slot_idx: i, capacity: V3_BLOCK_PAGE_IDS_SIZE }.into()),
+                None => {}
+            }
         }
 
         Ok(HashTableHeaderPage {
+            page_lsn: 0,
             basic_info,
+            next_header_pid,
+            len,
             block_page_ids,
         })
     }
@@ -98,12 +252,293 @@ impl HashTableHeaderPage {
     pub fn get_block_page_ids(&self) -> &[PageId] {
         &self.block_page_ids
     }
+
+    /// Follows pages moved by a defragmentation, e.g. `|pid| relocations.resolve(pid)`, for the header
+    /// itself, the next header page and its blocks. Returns whether any of them moved.
+    pub fn relocate<F: Fn(PageId) -> PageId>(&mut self, resolve: F) -> bool {
+        let mut moved = false;
+        let header_pid = resolve(self.basic_info.page_id);
+        moved |= header_pid != self.basic_info.page_id;
+        self.basic_info.page_id = header_pid;
+        if let Some(next_header_pid) = self.next_header_pid {
+            let new_pid = resolve(next_header_pid);
+            moved |= new_pid != next_header_pid;
+            self.next_header_pid = Some(new_pid);
+        }
+        for block_pid in self.block_page_ids.iter_mut().filter(|pid| **pid != INVALID_PAGE_ID) {
+            let new_pid = resolve(*block_pid);
+            moved |= new_pid != *block_pid;
+            *block_pid = new_pid;
+        }
+        moved
+    }
+}
+
+impl PageSerde for HashTableHeaderPage {
+    const VERSION: PageVersion = HEADER_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<HashTableHeaderPage> {
+        HashTableHeaderPage::deserialize(page_data)
+    }
+}
+
+/// Header page read and written in place on the page data, without decoding every block page id.
+pub struct HashTableHeaderView<D> {
+    data: D,
+    body_offset: usize,
+    len_offset: Option<usize>,
+    ids_offset: usize,
+    ids_size: usize,
+    linked: bool,
+}
+
+impl<D: AsRef<[u8]>> HashTableHeaderView<D> {
+    pub fn from_page(data: D) -> io::Result<HashTableHeaderView<D>> {
+        let page_data = data.as_ref();
+        let (body_offset, ids_size, linked, counted) = match read_page_version(page_data)? {
+            HEADER_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableHeader)?;
+                (PAGE_HEADER_SIZE, BLOCK_PAGE_IDS_SIZE, true, true)
+            },
+            V4_HEADER_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableHeader)?;
+                (PAGE_HEADER_SIZE, V4_BLOCK_PAGE_IDS_SIZE, true, false)
+            },
+            V3_HEADER_PAGE_VERSION => {
+                check_page_type(page_data, PageType::HashTableHeader)?;
+                (PAGE_HEADER_SIZE, V3_BLOCK_PAGE_IDS_SIZE, false, false)
+            },
+            V2_HEADER_PAGE_VERSION => (UNTYPED_PAGE_HEADER_SIZE, V2_BLOCK_PAGE_IDS_SIZE, false, false),
+            V1_HEADER_PAGE_VERSION => (PAGE_VERSION_SIZE, V1_BLOCK_PAGE_IDS_SIZE, false, false),
+            version => return Err(PageError::UnknownVersion { found: version, supported: HEADER_PAGE_VERSION }.into()),
+        };
+        let len_offset = body_offset + mem::size_of::<BasicInfo>() + if linked { NEXT_HEADER_PID_SIZE } else { 0 };
+        let ids_offset = len_offset + if counted { LEN_SIZE } else { 0 };
+        let len_offset = counted.then_some(len_offset);
+        let expected_size = ids_offset + ids_size * mem::size_of::<PageId>();
+        if page_data.len() < expected_size {
+            return Err(PageError::PageDataTooShort { expected: expected_size, actual: page_data.len() }.into());
+        }
+
+        Ok(HashTableHeaderView { data, body_offset, len_offset, ids_offset, ids_size, linked })
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.word(self.body_offset + mem::size_of::<PageId>()) as usize
+    }
+
+    pub fn get_next_header_pid(&self) -> Option<PageId> {
+        if !self.linked {
+            return None;
+        }
+
+        match self.word(self.body_offset + mem::size_of::<BasicInfo>()) as PageId {
+            INVALID_PAGE_ID => None,
+            next_header_pid => Some(next_header_pid),
+        }
+    }
+
+    pub fn get_len(&self) -> Option<u64> {
+        self.len_offset.map(|len_offset| self.word(len_offset))
+    }
+
+    pub fn get_block_page_id(&self, slot_idx: usize) -> io::Result<Option<PageId>> {
+        self.validate_slot_idx(slot_idx)?;
+        match self.word(self.id_offset(slot_idx)) as PageId {
+            INVALID_PAGE_ID => Ok(None),
+            block_pid => Ok(Some(block_pid)),
+        }
+    }
+
+    fn id_offset(&self, slot_idx: usize) -> usize {
+        self.ids_offset + slot_idx * mem::size_of::<PageId>()
+    }
+
+    fn word(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.data.as_ref()[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn validate_slot_idx(&self, slot_idx: usize) -> io::Result<()> {
+        let capacity = self.get_size().min(self.ids_size);
+        if slot_idx >= capacity {
+            return Err(PageError::SlotOutOfRange { slot_idx, capacity }.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: AsRef<[u8]> + AsMut<[u8]>> HashTableHeaderView<D> {
+    pub fn set(&mut self, pid: PageId, slot_idx: usize) -> io::Result<()> {
+        self.validate_slot_idx(slot_idx)?;
+        let offset = self.id_offset(slot_idx);
+        self.data.as_mut()[offset..offset + 8].copy_from_slice(&(pid as u64).to_le_bytes());
+        Ok(())
+    }
+
+    /// Returns whether the layout of the page holds the count, see `HashTableHeaderPage`.
+    pub fn set_len(&mut self, len: u64) -> bool {
+        let len_offset = match self.len_offset {
+            Some(len_offset) => len_offset,
+            None => return false,
+        };
+
+        self.data.as_mut()[len_offset..len_offset + LEN_SIZE].copy_from_slice(&len.to_le_bytes());
+        true
+    }
+}
+
+/// A header page, decoded or read in place, as one link of a `HashTableHeaderChain`.
+pub trait HeaderChainPage {
+    /// Blocks from this page on to the end of the chain.
+    fn get_size(&self) -> usize;
+
+    /// Block page ids this page holds.
+    fn ids_capacity(&self) -> usize;
+
+    fn get_block_page_id(&self, slot_idx: usize) -> io::Result<Option<PageId>>;
+
+    fn get_next_header_pid(&self) -> Option<PageId>;
+}
+
+impl HeaderChainPage for HashTableHeaderPage {
+    fn get_size(&self) -> usize {
+        HashTableHeaderPage::get_size(self)
+    }
+
+    fn ids_capacity(&self) -> usize {
+        self.block_page_ids.len()
+    }
+
+    fn get_block_page_id(&self, slot_idx: usize) -> io::Result<Option<PageId>> {
+        HashTableHeaderPage::get_block_page_id(self, slot_idx)
+    }
+
+    fn get_next_header_pid(&self) -> Option<PageId> {
+        HashTableHeaderPage::get_next_header_pid(self)
+    }
+}
+
+impl<D: AsRef<[u8]>> HeaderChainPage for HashTableHeaderView<D> {
+    fn get_size(&self) -> usize {
+        HashTableHeaderView::get_size(self)
+    }
+
+    fn ids_capacity(&self) -> usize {
+        self.ids_size
+    }
+
+    fn get_block_page_id(&self, slot_idx: usize) -> io::Result<Option<PageId>> {
+        HashTableHeaderView::get_block_page_id(self, slot_idx)
+    }
+
+    fn get_next_header_pid(&self) -> Option<PageId> {
+        HashTableHeaderView::get_next_header_pid(self)
+    }
+}
+
+/// Header pages of a hash table linked from the first one, addressed by block index across the chain.
+pub struct HashTableHeaderChain<H> {
+    page_ids: Vec<PageId>,
+    pages: Vec<H>,
+}
+
+impl HashTableHeaderChain<HashTableHeaderPage> {
+    /// Links new header pages for a table of `size` blocks, one per id of
+    /// `header_pids`, see `HashTableHeaderPage::pages_needed`.
+    pub fn new(header_pids: &[PageId], size: usize) -> HashTableHeaderChain<HashTableHeaderPage> {
+        let pages = header_pids.iter().enumerate()
+            .map(|(i, pid)| {
+                let mut page = HashTableHeaderPage::new(*pid, size.saturating_sub(i * BLOCK_PAGE_IDS_SIZE));
+                page.set_next_header_pid(header_pids.get(i + 1).copied());
+                page
+            })
+            .collect();
+        HashTableHeaderChain { page_ids: header_pids.to_vec(), pages }
+    }
+
+    /// Returns the header page the id went to, which has to be written back.
+    pub fn set(&mut self, pid: PageId, block_idx: usize) -> io::Result<&HashTableHeaderPage> {
+        let (page_idx, slot_idx) = self.locate(block_idx)?;
+        self.pages[page_idx].set(pid, slot_idx)?;
+        Ok(&self.pages[page_idx])
+    }
+}
+
+impl<H: HeaderChainPage> HashTableHeaderChain<H> {
+    /// Reads the header pages from `header_pid` on with `read_page`, as many as the size in the first
+    /// one needs, so a cycle in the links cannot loop forever.
+    pub fn load<F>(header_pid: PageId, mut read_page: F) -> io::Result<HashTableHeaderChain<H>>
+        where F: FnMut(PageId) -> io::Result<H>
+    {
+        let first = read_page(header_pid)?;
+        let size = first.get_size();
+        let mut covered = first.ids_capacity();
+        let mut next_header_pid = first.get_next_header_pid();
+        let mut page_ids = vec![header_pid];
+        let mut pages = vec![first];
+        while covered < size {
+            let pid = match next_header_pid {
+                Some(pid) => pid,
+                None => return Err(PageError::SlotOutOfRange { slot_idx: covered, capacity: covered }.into()),
+            };
+            let page = read_page(pid)?;
+            covered += page.ids_capacity();
+            next_header_pid = page.get_next_header_pid();
+            page_ids.push(pid);
+            pages.push(page);
+        }
+
+        Ok(HashTableHeaderChain { page_ids, pages })
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.pages[0].get_size()
+    }
+
+    pub fn get_block_page_id(&self, block_idx: usize) -> io::Result<Option<PageId>> {
+        let (page_idx, slot_idx) = self.locate(block_idx)?;
+        self.pages[page_idx].get_block_page_id(slot_idx)
+    }
+
+    pub fn pages(&self) -> &[H] {
+        &self.pages
+    }
+
+    pub fn page_ids(&self) -> &[PageId] {
+        &self.page_ids
+    }
+
+    /// Id of the header page holding the id of the block, and its slot there.
+    pub fn header_page_of(&self, block_idx: usize) -> io::Result<(PageId, usize)> {
+        let (page_idx, slot_idx) = self.locate(block_idx)?;
+        Ok((self.page_ids[page_idx], slot_idx))
+    }
+
+    /// Header page holding the id of the block, and its slot there.
+    fn locate(&self, block_idx: usize) -> io::Result<(usize, usize)> {
+        let mut slot_idx = block_idx;
+        for (page_idx, page) in self.pages.iter().enumerate() {
+            if slot_idx < page.ids_capacity() {
+                return Ok((page_idx, slot_idx));
+            }
+            slot_idx -= page.ids_capacity();
+        }
+
+        Err(PageError::SlotOutOfRange { slot_idx: block_idx, capacity: block_idx - slot_idx }.into())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::storage::page::hash_table_header_page::{HashTableHeaderPage, BLOCK_PAGE_IDS_SIZE};
-    use crate::storage::page::page::PageId;
+    use crate::storage::page::hash_table_header_page::{HashTableHeaderChain, HashTableHeaderPage, HashTableHeaderView, BLOCK_PAGE_IDS_SIZE, HEADER_PAGE_VERSION, LEGACY_BLOCK_PAGE_IDS_SIZE, V3_BLOCK_PAGE_IDS_SIZE, V4_BLOCK_PAGE_IDS_SIZE};
+    use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+    use crate::storage::page::page_version::{PageType, PAGE_HEADER_SIZE, PAGE_TYPE_OFFSET, UNTYPED_PAGE_HEADER_SIZE};
+    use crate::storage::page::page_error::PageError;
 
     #[test]
     fn should_construct_new_empty_head() {
@@ -118,7 +553,7 @@ mod tests {
         assert_eq!(header.get_page_id(), pid);
         assert_eq!(header.get_size(), size);
         assert_eq!(header.basic_info.next_idx, 0);
-        assert_eq!(header.block_page_ids.len(), 509); // (4096 - (64*3)/8) / 64/8
+        assert_eq!(header.block_page_ids.len(), 505); // (4096 - 10 - (64*3)/8 - 64/8 - 64/8) / 64/8
     }
 
     #[test]
@@ -178,7 +613,7 @@ mod tests {
         header.block_page_ids[1] = test_pid;
 
         // when
-        let raw = header.serialize();
+        let raw = header.serialize().unwrap();
         let deser_header = HashTableHeaderPage::deserialize(raw.as_slice()).unwrap();
 
         // then
@@ -186,4 +621,235 @@ mod tests {
         assert_eq!(deser_header.basic_info.size, size);
         assert_eq!(deser_header.block_page_ids[1], test_pid);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_keep_last_block_page_id_after_deserialize() {
+        // given
+        let mut header = HashTableHeaderPage::new(3, BLOCK_PAGE_IDS_SIZE);
+        header.set(10, BLOCK_PAGE_IDS_SIZE - 1).unwrap();
+
+        // when
+        let raw = header.serialize().unwrap();
+        let deser_header = HashTableHeaderPage::deserialize(raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(deser_header.get_block_page_id(BLOCK_PAGE_IDS_SIZE - 1).unwrap(), Some(10));
+    }
+
+    #[test]
+    fn should_fail_when_slot_out_of_range() {
+        // given
+        let mut header = HashTableHeaderPage::new(0, 8);
+
+        // when
+        let set_result = header.set(1, 8);
+        let get_result = header.get_block_page_id(usize::MAX);
+
+        // then
+        assert!(matches!(PageError::from_io(&set_result.unwrap_err()), Some(PageError::SlotOutOfRange { slot_idx: 8, capacity: 8 })));
+        assert!(get_result.is_err());
+    }
+
+    #[test]
+    fn should_fail_to_deserialize_when_page_data_too_short() {
+        let raw = HashTableHeaderPage::new(3, 16).serialize().unwrap();
+        let result = HashTableHeaderPage::deserialize(&raw[..20]);
+
+        assert!(matches!(PageError::from_io(&result.err().unwrap()), Some(PageError::PageDataTooShort { .. })));
+    }
+
+    #[test]
+    fn should_fail_to_deserialize_unknown_version() {
+        // given
+        let mut raw = HashTableHeaderPage::new(3, 16).serialize().unwrap();
+        raw[0] = HEADER_PAGE_VERSION + 1;
+
+        // when
+        let result = HashTableHeaderPage::deserialize(raw.as_slice());
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::UnknownVersion { found: 6, supported: 5 })));
+    }
+
+    #[test]
+    fn should_fail_to_decode_page_of_another_type() {
+        // given
+        let mut raw = HashTableHeaderPage::new(3, 16).serialize().unwrap();
+        raw[PAGE_TYPE_OFFSET] = PageType::HashTableBlock as u8;
+
+        // when
+        let result = HashTableHeaderPage::deserialize(raw.as_slice());
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(PageError::from_io(&err),
+            Some(PageError::WrongPageType { expected: PageType::HashTableHeader, found: 2 })));
+        let err = HashTableHeaderView::from_page(raw.as_slice()).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::WrongPageType { .. })));
+    }
+
+    #[test]
+    fn should_deserialize_legacy_header() {
+        // given
+        let basic_info: (PageId, usize, usize) = (3, 16, 1);
+        let mut raw = bincode::serialize(&basic_info).unwrap();
+        for i in 0..LEGACY_BLOCK_PAGE_IDS_SIZE {
+            let pid = if i == 0 { 10 } else { INVALID_PAGE_ID };
+            raw.append(&mut bincode::serialize(&pid).unwrap());
+        }
+
+        // when
+        let header = HashTableHeaderPage::deserialize_legacy(raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(header.get_page_id(), 3);
+        assert_eq!(header.get_size(), 16);
+        assert_eq!(header.get_block_page_id(0).unwrap(), Some(10));
+        assert_eq!(header.get_block_page_id(1).unwrap(), None);
+    }
+
+    #[test]
+    fn should_fail_to_deserialize_legacy_header_using_last_legacy_slot() {
+        // given
+        let basic_info: (PageId, usize, usize) = (3, 16, 0);
+        let mut raw = bincode::serialize(&basic_info).unwrap();
+        for i in 0..LEGACY_BLOCK_PAGE_IDS_SIZE {
+            let pid = if i == LEGACY_BLOCK_PAGE_IDS_SIZE - 1 { 10 } else { INVALID_PAGE_ID };
+            raw.append(&mut bincode::serialize(&pid).unwrap());
+        }
+
+        // when
+        let result = HashTableHeaderPage::deserialize_legacy(raw.as_slice());
+
+        // then
+        assert!(matches!(PageError::from_io(&result.err().unwrap()), Some(PageError::SlotOutOfRange { slot_idx: 508, .. })));
+    }
+
+    #[test]
+    fn should_relocate_header_and_block_page_ids() {
+        // given
+        let mut header = HashTableHeaderPage::new(9, 4);
+        header.set(7, 0).unwrap();
+        header.set(3, 2).unwrap();
+
+        // when
+        let moved = header.relocate(|pid| if pid >= 7 { pid - 6 } else { pid });
+
+        // then
+        assert!(moved);
+        assert_eq!(header.get_page_id(), 3);
+        assert_eq!(header.get_block_page_id(0).unwrap(), Some(1));
+        assert_eq!(header.get_block_page_id(1).unwrap(), None);
+        assert_eq!(header.get_block_page_id(2).unwrap(), Some(3));
+        assert!(!header.relocate(|pid| pid));
+    }
+
+    #[test]
+    fn should_keep_lsn_and_read_older_headers() {
+        // given
+        let mut header = HashTableHeaderPage::new(3, 16);
+        header.set(10, 1).unwrap();
+        header.set_lsn(42);
+        let raw = header.serialize().unwrap();
+        // layouts before chains have no next header page id, nor the count
+        let unlinked_body = [&raw[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 24], &raw[PAGE_HEADER_SIZE + 40..]].concat();
+        let mut v1_raw = vec![1];
+        v1_raw.extend_from_slice(&unlinked_body);
+        v1_raw.resize(PAGE_SIZE, 0xff);
+        let mut v2_raw = vec![2];
+        v2_raw.extend_from_slice(&raw[1..UNTYPED_PAGE_HEADER_SIZE]);
+        v2_raw.extend_from_slice(&unlinked_body);
+        v2_raw.resize(PAGE_SIZE, 0xff);
+
+        // when
+        let deser_header = HashTableHeaderPage::deserialize(raw.as_slice()).unwrap();
+        let v1_header = HashTableHeaderPage::deserialize(v1_raw.as_slice()).unwrap();
+        let v2_header = HashTableHeaderPage::deserialize(v2_raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(deser_header.get_lsn(), 42);
+        assert_eq!(v1_header.get_lsn(), 0);
+        assert_eq!(v1_header.get_block_page_id(1).unwrap(), Some(10));
+        assert_eq!(v2_header.get_lsn(), 42);
+        assert_eq!(v2_header.get_block_page_id(1).unwrap(), Some(10));
+        assert_eq!(HashTableHeaderView::from_page(v2_raw.as_slice()).unwrap().get_block_page_id(1).unwrap(), Some(10));
+    }
+
+    #[test]
+    fn should_keep_v3_layout_when_last_v3_slot_used() {
+        // given
+        let mut header = HashTableHeaderPage::new(3, V3_BLOCK_PAGE_IDS_SIZE);
+        header.set_next_header_pid(Some(4));
+        let raw = header.serialize().unwrap();
+        let mut v3_raw = [&raw[..PAGE_HEADER_SIZE + 24], &raw[PAGE_HEADER_SIZE + 40..]].concat();
+        v3_raw[0] = 3;
+        v3_raw.resize(PAGE_SIZE, 0xff);
+        let last_id_offset = PAGE_HEADER_SIZE + 24 + V4_BLOCK_PAGE_IDS_SIZE * 8;
+        v3_raw[last_id_offset..last_id_offset + 8].copy_from_slice(&11u64.to_le_bytes());
+
+        // when
+        let v3_header = HashTableHeaderPage::deserialize(v3_raw.as_slice()).unwrap();
+
+        // then
+        assert_eq!(v3_header.get_block_page_id(V4_BLOCK_PAGE_IDS_SIZE).unwrap(), Some(11));
+        assert_eq!(v3_header.get_next_header_pid(), None);
+        let rewritten = v3_header.serialize().unwrap();
+        assert_eq!(rewritten[0], 3);
+        assert_eq!(HashTableHeaderView::from_page(rewritten.as_slice()).unwrap().get_block_page_id(V4_BLOCK_PAGE_IDS_SIZE).unwrap(), Some(11));
+    }
+
+    #[test]
+    fn should_count_pairs_in_place_and_keep_v4_layout_without_count() {
+        // given
+        let mut header = HashTableHeaderPage::new(3, V4_BLOCK_PAGE_IDS_SIZE);
+        header.set(10, 1).unwrap();
+        let mut raw = header.serialize().unwrap();
+        raw.resize(PAGE_SIZE, 0xff);
+        let mut v4_raw = [&raw[..PAGE_HEADER_SIZE + 32], &raw[PAGE_HEADER_SIZE + 40..]].concat();
+        v4_raw[0] = 4;
+        v4_raw.resize(PAGE_SIZE, 0xff);
+
+        // when
+        let counted = HashTableHeaderView::from_page(raw.as_mut_slice()).unwrap().set_len(7);
+        let v4_counted = HashTableHeaderView::from_page(v4_raw.as_mut_slice()).unwrap().set_len(7);
+
+        // then
+        assert!(counted && !v4_counted);
+        assert_eq!(HashTableHeaderPage::deserialize(&raw).unwrap().get_len(), Some(7));
+        let v4_header = HashTableHeaderPage::deserialize(&v4_raw).unwrap();
+        assert_eq!(v4_header.get_len(), None);
+        assert_eq!(v4_header.get_block_page_id(1).unwrap(), Some(10));
+        let rewritten = v4_header.serialize().unwrap();
+        assert_eq!(rewritten[0], 4);
+        assert_eq!(HashTableHeaderView::from_page(rewritten.as_slice()).unwrap().get_len(), None);
+    }
+
+    #[test]
+    fn should_address_blocks_across_linked_header_pages() {
+        // given
+        let size = 2 * BLOCK_PAGE_IDS_SIZE + 10;
+        let header_pids = [7, 8, 9];
+        assert_eq!(HashTableHeaderPage::pages_needed(size), header_pids.len());
+        let mut chain = HashTableHeaderChain::new(&header_pids, size);
+        assert_eq!(chain.set(100, BLOCK_PAGE_IDS_SIZE + 1).unwrap().get_page_id(), 8);
+        assert_eq!(chain.set(200, size - 1).unwrap().get_page_id(), 9);
+        let raw: Vec<Vec<u8>> = chain.pages().iter().map(|page| page.serialize().unwrap()).collect();
+
+        // when
+        let chain = HashTableHeaderChain::load(7, |pid| HashTableHeaderView::from_page(raw[pid - 7].as_slice())).unwrap();
+
+        // then
+        assert_eq!(chain.pages().len(), 3);
+        assert_eq!(chain.get_size(), size);
+        assert_eq!(chain.get_block_page_id(BLOCK_PAGE_IDS_SIZE + 1).unwrap(), Some(100));
+        assert_eq!(chain.get_block_page_id(size - 1).unwrap(), Some(200));
+        assert_eq!(chain.get_block_page_id(0).unwrap(), None);
+        assert!(chain.get_block_page_id(size).is_err());
+        let broken = HashTableHeaderChain::load(7, |pid| match pid {
+            7 => HashTableHeaderPage::deserialize(&raw[0]),
+            _ => Ok(HashTableHeaderPage::new(pid, 0)),
+        });
+        assert!(matches!(PageError::from_io(&broken.err().unwrap()), Some(PageError::SlotOutOfRange { .. })));
+    }
+}
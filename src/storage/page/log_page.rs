@@ -0,0 +1,240 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use fasthash::xx;
+
+use crate::storage::page::page::PAGE_SIZE;
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+
+/// v1: version byte, LSN, page type, segment id, used bytes, CRC of the used bytes, then the packed records.
+const LOG_PAGE_VERSION: PageVersion = 1;
+const USED_OFFSET: usize = PAGE_HEADER_SIZE + mem::size_of::<u64>();
+const CRC_OFFSET: usize = USED_OFFSET + mem::size_of::<u32>();
+const BODY_OFFSET: usize = CRC_OFFSET + mem::size_of::<u32>();
+/// Each record is stored as its length followed by its bytes.
+const RECORD_LEN_SIZE: usize = mem::size_of::<u32>();
+
+/// One page of a log segment, holding records packed one after another. A record may start on one
+/// page and continue on the next pages of the segment, see `LogAppender`.
+pub struct LogPage {
+    page_lsn: Lsn,
+    segment_id: u64,
+    body: Vec<u8>,
+}
+
+impl LogPage {
+    pub fn new(segment_id: u64) -> LogPage {
+        LogPage { page_lsn: 0, segment_id, body: Vec::new() }
+    }
+
+    /// Bytes of records one page holds.
+    pub fn capacity() -> usize {
+        PAGE_SIZE - BODY_OFFSET
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_segment_id(&self) -> u64 {
+        self.segment_id
+    }
+
+    pub fn get_used(&self) -> usize {
+        self.body.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.body.len() == LogPage::capacity()
+    }
+
+    pub fn get_body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Appends as much of `bytes` as still fits, returning how many bytes went in.
+    pub fn append(&mut self, bytes: &[u8]) -> usize {
+        let len = bytes.len().min(LogPage::capacity() - self.body.len());
+        self.body.extend_from_slice(&bytes[..len]);
+        len
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut res = vec![LOG_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::Log as u8);
+        res.extend_from_slice(&self.segment_id.to_le_bytes());
+        res.extend_from_slice(&(self.body.len() as u32).to_le_bytes());
+        res.extend_from_slice(&xx::hash32(&self.body).to_le_bytes());
+        res.extend_from_slice(&self.body);
+        res
+    }
+
+    /// Fails on a CRC mismatch, e.g. a page torn while the log was being written.
+    pub fn deserialize(page_data: &[u8]) -> io::Result<LogPage> {
+        match read_page_version(page_data)? {
+            LOG_PAGE_VERSION => check_page_type(page_data, PageType::Log)?,
+            version => return Err(PageError::UnknownVersion { found: version, supported: LOG_PAGE_VERSION }.into()),
+        }
+        if page_data.len() < BODY_OFFSET {
+            return Err(PageError::PageDataTooShort { expected: BODY_OFFSET, actual: page_data.len() }.into());
+        }
+
+        let used = u32::from_le_bytes(page_data[USED_OFFSET..CRC_OFFSET].try_into().unwrap()) as usize;
+        if used > LogPage::capacity() {
+            return Err(PageError::ValueTooLarge { size: used, capacity: LogPage::capacity() }.into());
+        }
+        if page_data.len() < BODY_OFFSET + used {
+            return Err(PageError::PageDataTooShort { expected: BODY_OFFSET + used, actual: page_data.len() }.into());
+        }
+        let body = page_data[BODY_OFFSET..BODY_OFFSET + used].to_vec();
+        let expected = u32::from_le_bytes(page_data[CRC_OFFSET..BODY_OFFSET].try_into().unwrap());
+        let found = xx::hash32(&body);
+        if found != expected {
+            return Err(PageError::ChecksumMismatch { expected, found }.into());
+        }
+
+        Ok(LogPage {
+            page_lsn: read_page_lsn(page_data)?,
+            segment_id: u64::from_le_bytes(page_data[PAGE_HEADER_SIZE..USED_OFFSET].try_into().unwrap()),
+            body,
+        })
+    }
+}
+
+impl PageSerde for LogPage {
+    const VERSION: PageVersion = LOG_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize(), page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<LogPage> {
+        LogPage::deserialize(page_data)
+    }
+}
+
+/// Packs records into the pages of one log segment. A record that does not fit into the current page
+/// continues on the next one, so no page space is left unused.
+pub struct LogAppender {
+    segment_id: u64,
+    offset: u64,
+    full_pages: Vec<LogPage>,
+    current: LogPage,
+}
+
+impl LogAppender {
+    pub fn new(segment_id: u64) -> LogAppender {
+        LogAppender { segment_id, offset: 0, full_pages: Vec::new(), current: LogPage::new(segment_id) }
+    }
+
+    /// Returns the offset of the record in the segment.
+    pub fn append(&mut self, record: &[u8]) -> io::Result<u64> {
+        if record.len() > u32::MAX as usize {
+            return Err(PageError::ValueTooLarge { size: record.len(), capacity: u32::MAX as usize }.into());
+        }
+
+        let record_offset = self.offset;
+        self.write(&(record.len() as u32).to_le_bytes());
+        self.write(record);
+        Ok(record_offset)
+    }
+
+    /// Pages filled up since the last call, in segment order, ready to be written.
+    pub fn take_full_pages(&mut self) -> Vec<LogPage> {
+        std::mem::take(&mut self.full_pages)
+    }
+
+    /// Page the next record goes to, written again each time it is flushed before it is full.
+    pub fn current_page(&self) -> &LogPage {
+        &self.current
+    }
+
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.offset += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let written = self.current.append(bytes);
+            bytes = &bytes[written..];
+            if self.current.is_full() {
+                let full_page = std::mem::replace(&mut self.current, LogPage::new(self.segment_id));
+                self.full_pages.push(full_page);
+            }
+        }
+    }
+}
+
+/// Splits the pages of a segment, in order from its first page, back into the records appended.
+pub fn read_records(pages: &[LogPage]) -> io::Result<Vec<Vec<u8>>> {
+    let packed: Vec<u8> = pages.iter().flat_map(|page| page.get_body().iter().copied()).collect();
+    let mut records = Vec::new();
+    let mut rest = &packed[..];
+    while !rest.is_empty() {
+        if rest.len() < RECORD_LEN_SIZE {
+            return Err(PageError::PageDataTooShort { expected: RECORD_LEN_SIZE, actual: rest.len() }.into());
+        }
+        let len = u32::from_le_bytes(rest[..RECORD_LEN_SIZE].try_into().unwrap()) as usize;
+        rest = &rest[RECORD_LEN_SIZE..];
+        if rest.len() < len {
+            return Err(PageError::PageDataTooShort { expected: len, actual: rest.len() }.into());
+        }
+        records.push(rest[..len].to_vec());
+        rest = &rest[len..];
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::log_page::{read_records, LogAppender, LogPage};
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+
+    #[test]
+    fn should_serialize_log_page_and_detect_corrupted_body() {
+        // given
+        let mut page = LogPage::new(3);
+        page.set_lsn(42);
+        page.append(b"record");
+
+        // when
+        let mut raw = page.serialize();
+        let deser_page = LogPage::deserialize(&raw).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 1;
+
+        // then
+        assert_eq!(deser_page.get_lsn(), 42);
+        assert_eq!(deser_page.get_segment_id(), 3);
+        assert_eq!(deser_page.get_body(), b"record");
+        let err = LogPage::deserialize(&raw).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn should_span_records_across_pages_and_read_them_back() {
+        // given
+        let mut appender = LogAppender::new(1);
+        let large_record = vec![7; LogPage::capacity() + 100];
+
+        // when
+        let first_offset = appender.append(b"first").unwrap();
+        let large_offset = appender.append(&large_record).unwrap();
+        let last_offset = appender.append(b"last").unwrap();
+        let mut pages = appender.take_full_pages();
+        pages.push(LogPage::deserialize(&appender.current_page().serialize()).unwrap());
+
+        // then
+        assert_eq!((first_offset, large_offset), (0, 9));
+        assert_eq!(last_offset, 9 + 4 + large_record.len() as u64);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].serialize().len(), PAGE_SIZE);
+        assert_eq!(read_records(&pages).unwrap(), vec![b"first".to_vec(), large_record, b"last".to_vec()]);
+    }
+}
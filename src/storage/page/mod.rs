@@ -1,3 +1,19 @@
 pub mod page;
+pub mod page_error;
+pub mod page_latch;
+pub mod page_serde;
+pub mod page_version;
+pub mod superblock;
+pub mod allocation_map_page;
 pub mod hash_table_header_page;
-pub mod hash_table_block_page;
\ No newline at end of file
+pub mod hash_table_block_page;
+pub mod hash_table_bucket_page;
+pub mod overflow_page;
+pub mod free_space_map_page;
+pub mod u64_block_page;
+pub mod log_page;
+pub mod b_plus_tree_page;
+pub mod b_plus_tree_meta_page;
+pub mod table_page;
+pub mod catalog_page;
+pub mod statistics_page;
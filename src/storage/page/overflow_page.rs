@@ -0,0 +1,173 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, UNTYPED_PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+
+/// v2: version byte, LSN, page type, next page id, data length, then the data.
+/// v1: version byte, LSN, next page id, data length, then the data.
+const OVERFLOW_PAGE_VERSION: PageVersion = 2;
+const V1_OVERFLOW_PAGE_VERSION: PageVersion = 1;
+const DATA_LEN_OFFSET: usize = mem::size_of::<u64>();
+/// Next page id and data length, ahead of the data.
+const CHAIN_INFO_SIZE: usize = DATA_LEN_OFFSET + mem::size_of::<u32>();
+const DATA_OFFSET: usize = PAGE_HEADER_SIZE + CHAIN_INFO_SIZE;
+
+/// Stands in a slot for a value too large to be stored inline: the first page of the overflow chain
+/// holding the serialized value, and its length.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct OverflowRef {
+    pub page_id: PageId,
+    pub len: u64,
+}
+
+/// One page of a chain holding a serialized value, each page pointing to the next one.
+pub struct OverflowPage {
+    page_lsn: Lsn,
+    next_page_id: Option<PageId>,
+    data: Vec<u8>,
+}
+
+impl OverflowPage {
+    pub fn new(data: Vec<u8>, next_page_id: Option<PageId>) -> io::Result<OverflowPage> {
+        if data.len() > OverflowPage::capacity() {
+            return Err(PageError::ValueTooLarge { size: data.len(), capacity: OverflowPage::capacity() }.into());
+        }
+
+        Ok(OverflowPage { page_lsn: 0, next_page_id, data })
+    }
+
+    /// Bytes of the value one page holds.
+    pub fn capacity() -> usize {
+        PAGE_SIZE - DATA_OFFSET
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_next_page_id(&self) -> Option<PageId> {
+        self.next_page_id
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut res = vec![OVERFLOW_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::Overflow as u8);
+        res.extend_from_slice(&(self.next_page_id.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        res.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        res.extend_from_slice(&self.data);
+        res
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<OverflowPage> {
+        let body_offset = match read_page_version(page_data)? {
+            OVERFLOW_PAGE_VERSION => {
+                check_page_type(page_data, PageType::Overflow)?;
+                PAGE_HEADER_SIZE
+            },
+            V1_OVERFLOW_PAGE_VERSION => UNTYPED_PAGE_HEADER_SIZE,
+            version => return Err(PageError::UnknownVersion { found: version, supported: OVERFLOW_PAGE_VERSION }.into()),
+        };
+        let data_offset = body_offset + CHAIN_INFO_SIZE;
+        if page_data.len() < data_offset {
+            return Err(PageError::PageDataTooShort { expected: data_offset, actual: page_data.len() }.into());
+        }
+
+        let data_len_offset = body_offset + DATA_LEN_OFFSET;
+        let next_page_id = u64::from_le_bytes(page_data[body_offset..data_len_offset].try_into().unwrap()) as PageId;
+        let data_len = u32::from_le_bytes(page_data[data_len_offset..data_offset].try_into().unwrap()) as usize;
+        if data_len > PAGE_SIZE - data_offset {
+            return Err(PageError::ValueTooLarge { size: data_len, capacity: PAGE_SIZE - data_offset }.into());
+        }
+        if page_data.len() < data_offset + data_len {
+            return Err(PageError::PageDataTooShort { expected: data_offset + data_len, actual: page_data.len() }.into());
+        }
+
+        Ok(OverflowPage {
+            page_lsn: read_page_lsn(page_data)?,
+            next_page_id: if next_page_id == INVALID_PAGE_ID { None } else { Some(next_page_id) },
+            data: page_data[data_offset..data_offset + data_len].to_vec(),
+        })
+    }
+}
+
+impl PageSerde for OverflowPage {
+    const VERSION: PageVersion = OVERFLOW_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize(), page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<OverflowPage> {
+        OverflowPage::deserialize(page_data)
+    }
+}
+
+/// Collects the serialized value `overflow_ref` points to, reading each page of its chain with `read_page`.
+pub fn read_chain<F>(overflow_ref: &OverflowRef, mut read_page: F) -> io::Result<Vec<u8>>
+    where F: FnMut(PageId) -> io::Result<OverflowPage>
+{
+    let len = overflow_ref.len as usize;
+    let mut data = Vec::with_capacity(len);
+    let mut next_page_id = Some(overflow_ref.page_id);
+    // a chain never has more pages than its length needs, which also stops at a cycle
+    for _ in 0..len / OverflowPage::capacity() + 1 {
+        let page_id = match next_page_id {
+            Some(page_id) => page_id,
+            None => break,
+        };
+        let overflow_page = read_page(page_id)?;
+        data.extend_from_slice(overflow_page.get_data());
+        next_page_id = overflow_page.get_next_page_id();
+    }
+
+    if next_page_id.is_some() || data.len() > len {
+        return Err(PageError::ValueTooLarge { size: data.len(), capacity: len }.into());
+    }
+    if data.len() < len {
+        return Err(PageError::PageDataTooShort { expected: len, actual: data.len() }.into());
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::overflow_page::OverflowPage;
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+
+    #[test]
+    fn should_serialize_and_deserialize_overflow_page() {
+        // given
+        let mut page = OverflowPage::new(vec![7; 100], Some(12)).unwrap();
+        page.set_lsn(42);
+        let last_page = OverflowPage::new(vec![8; OverflowPage::capacity()], None).unwrap();
+
+        // when
+        let deser_page = OverflowPage::deserialize(&page.serialize()).unwrap();
+        let deser_last_page = OverflowPage::deserialize(&last_page.serialize()).unwrap();
+
+        // then
+        assert_eq!(deser_page.get_lsn(), 42);
+        assert_eq!(deser_page.get_next_page_id(), Some(12));
+        assert_eq!(deser_page.get_data(), &[7; 100][..]);
+        assert_eq!(last_page.serialize().len(), PAGE_SIZE);
+        assert_eq!(deser_last_page.get_next_page_id(), None);
+        let err = OverflowPage::new(vec![0; OverflowPage::capacity() + 1], None).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::ValueTooLarge { .. })));
+    }
+}
@@ -1,18 +1,19 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use crate::storage::page::page_latch::PageLatch;
+use crate::storage::page::page_version::{Lsn, PAGE_LSN_OFFSET, PAGE_TYPE_OFFSET};
+
 pub type PageId = usize;
 pub const INVALID_PAGE_ID: PageId = usize::MAX;
 pub const PAGE_SIZE: usize = 4096;
-pub const EMPTY_PAGE: Page = Page {
-    id: INVALID_PAGE_ID,
-    pin_count: 0,
-    dirty_flag: false,
-    data: [0; PAGE_SIZE]
-};
 
 pub struct Page {
     id: PageId,
     pin_count: u64,
     dirty_flag: bool,
-    data: [u8; PAGE_SIZE]
+    data: Arc<[u8; PAGE_SIZE]>,
+    latch: Arc<PageLatch>
 }
 
 impl Page {
@@ -21,16 +22,28 @@ impl Page {
             id: page_id,
             pin_count: 0,
             dirty_flag: false,
-            data: [0; PAGE_SIZE]
+            data: Arc::new([0; PAGE_SIZE]),
+            latch: Arc::new(PageLatch::new())
         }
     }
 
     pub fn get_data(&self) -> &[u8] {
-        &self.data
+        &self.data[..]
     }
 
+    /// Copies the data first if any snapshot still shares it, so snapshots never observe the write.
     pub fn get_data_mut(&mut self) -> &mut [u8] {
-        &mut self.data
+        &mut Arc::make_mut(&mut self.data)[..]
+    }
+
+    /// LSN stored after the version byte, see `page_version::PAGE_LSN_OFFSET`. Only meaningful for page
+    /// kinds keeping one, which are all but the superblock.
+    pub fn get_lsn(&self) -> Lsn {
+        Lsn::from_le_bytes(self.data[PAGE_LSN_OFFSET..PAGE_TYPE_OFFSET].try_into().unwrap())
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.get_data_mut()[PAGE_LSN_OFFSET..PAGE_TYPE_OFFSET].copy_from_slice(&lsn.to_le_bytes())
     }
 
     pub fn set_id(&mut self, pid: PageId) {
@@ -57,8 +70,28 @@ impl Page {
         self.pin_count+=1;
     }
 
-    pub fn unpin(&mut self) {
-        self.pin_count-=1;
+    /// Returns false instead of underflowing when the page is not pinned.
+    pub fn unpin(&mut self) -> bool {
+        match self.pin_count.checked_sub(1) {
+            Some(count) => {
+                self.pin_count = count;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Latch on the content of the page, kept by the frame. Take it from a pinned page and let go of
+    /// the frame lock before waiting on it.
+    pub fn latch(&self) -> Arc<PageLatch> {
+        self.latch.clone()
+    }
+
+    pub fn snapshot(&self) -> PageSnapshot {
+        PageSnapshot {
+            id: self.id,
+            data: self.data.clone()
+        }
     }
 }
 
@@ -66,4 +99,91 @@ impl Clone for Page {
     fn clone(&self) -> Self {
         Page::new(self.id)
     }
-}
\ No newline at end of file
+}
+
+/// Read-only view of a page's content at the time it was taken.
+#[derive(Clone)]
+pub struct PageSnapshot {
+    id: PageId,
+    data: Arc<[u8; PAGE_SIZE]>
+}
+
+impl AsRef<[u8]> for PageSnapshot {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[..]
+    }
+}
+
+impl PageSnapshot {
+    pub fn get_id(&self) -> PageId {
+        self.id
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::page::Page;
+
+    #[test]
+    fn should_keep_snapshot_data_when_page_modified() {
+        // given
+        let mut page = Page::new(1);
+        page.get_data_mut()[0] = 1;
+        let snapshot = page.snapshot();
+
+        // when
+        page.get_data_mut()[0] = 2;
+
+        // then
+        assert_eq!(snapshot.get_id(), 1);
+        assert_eq!(snapshot.get_data()[0], 1);
+        assert_eq!(page.get_data()[0], 2);
+    }
+
+    #[test]
+    fn should_share_data_until_page_modified() {
+        // given
+        let page = Page::new(1);
+
+        // when
+        let snapshot = page.snapshot();
+
+        // then
+        assert_eq!(snapshot.get_data().as_ptr(), page.get_data().as_ptr());
+    }
+
+    #[test]
+    fn should_not_underflow_when_unpin_unpinned_page() {
+        // given
+        let mut page = Page::new(1);
+        page.pin();
+
+        // when
+        let first = page.unpin();
+        let second = page.unpin();
+
+        // then
+        assert!(first);
+        assert!(!second);
+        assert_eq!(page.get_pin_count(), 0);
+    }
+
+    #[test]
+    fn should_keep_lsn_after_version_byte() {
+        // given
+        let mut page = Page::new(1);
+        page.get_data_mut()[0] = 2;
+
+        // when
+        page.set_lsn(42);
+
+        // then
+        assert_eq!(page.get_lsn(), 42);
+        assert_eq!(page.get_data()[0], 2);
+        assert_eq!(page.get_data()[1..9], 42u64.to_le_bytes());
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::common::error::cause_of;
+use crate::storage::page::page_version::PageType;
+
+/// Failures of page encoding and slot arithmetic, carried inside `io::Error` so callers can match on them.
+#[derive(Debug)]
+pub enum PageError {
+    SlotOutOfRange { slot_idx: usize, capacity: usize },
+    PageDataTooShort { expected: usize, actual: usize },
+    HeaderFull,
+    UnknownVersion { found: u8, supported: u8 },
+    /// The page is of another kind than it was decoded as.
+    WrongPageType { expected: PageType, found: u8 },
+    /// The data file was written with pages of another size than this build uses.
+    PageSizeMismatch { expected: usize, found: usize },
+    /// A serialized value does not fit where it has to go, inline slot or overflow page.
+    ValueTooLarge { size: usize, capacity: usize },
+    /// The page content does not match the checksum stored along with it.
+    ChecksumMismatch { expected: u32, found: u32 },
+    /// A slot no longer holds what it was found to hold, e.g. a free slot taken by the time it is written.
+    SlotChanged { slot_idx: usize },
+    Encoding(bincode::Error),
+}
+
+impl PageError {
+    pub fn from_io(err: &io::Error) -> Option<&PageError> {
+        cause_of(err)
+    }
+}
+
+impl Display for PageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::SlotOutOfRange { slot_idx, capacity } =>
+                write!(f, "Slot index {} out of range, capacity is {}.", slot_idx, capacity),
+            PageError::PageDataTooShort { expected, actual } =>
+                write!(f, "Wrong page data: size {} less than {}", actual, expected),
+            PageError::HeaderFull => write!(f, "Hash table header fulled."),
+            PageError::UnknownVersion { found, supported } =>
+                write!(f, "Unknown page format version {}, newest supported is {}.", found, supported),
+            PageError::WrongPageType { expected, found } =>
+                write!(f, "Expected a {:?} page, found page type {}.", expected, found),
+            PageError::PageSizeMismatch { expected, found } =>
+                write!(f, "Data file has {} byte pages, expected {}.", found, expected),
+            PageError::ValueTooLarge { size, capacity } =>
+                write!(f, "Value of {} bytes does not fit into {} bytes.", size, capacity),
+            PageError::ChecksumMismatch { expected, found } =>
+                write!(f, "Page checksum mismatch: expected {:#x}, found {:#x}.", expected, found),
+            PageError::SlotChanged { slot_idx } => write!(f, "Slot {} changed since it was looked up.", slot_idx),
+            PageError::Encoding(e) => write!(f, "Page encoding failed: {}", e),
+        }
+    }
+}
+
+impl error::Error for PageError {}
+
+impl From<PageError> for io::Error {
+    fn from(e: PageError) -> Self {
+        let kind = match e {
+            PageError::HeaderFull => io::ErrorKind::Other,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+impl From<bincode::Error> for PageError {
+    fn from(e: bincode::Error) -> Self {
+        PageError::Encoding(e)
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+#[derive(Default)]
+struct LatchState {
+    readers: usize,
+    upgradable: bool,
+    writer: bool,
+}
+
+/// Reader-writer latch on the content of one page, held across operations that span several pages,
+/// e.g. crabbing from one hash table block to the next. The frame lock of the buffer pool only needs
+/// to be held while reading or writing the bytes.
+///
+/// Besides shared and exclusive holders there can be one upgradable holder. It shares the page with
+/// readers and turns exclusive with `upgrade` without letting any other writer in between.
+///
+/// A latch is only meaningful while its page is pinned, the frame may hold another page after that.
+#[derive(Default)]
+pub struct PageLatch {
+    state: Mutex<LatchState>,
+    released: Condvar,
+}
+
+impl PageLatch {
+    pub fn new() -> PageLatch {
+        PageLatch::default()
+    }
+
+    pub fn read(self: &Arc<Self>) -> PageReadLatch {
+        self.acquire(|state| !state.writer, |state| state.readers += 1);
+        PageReadLatch { latch: self.clone() }
+    }
+
+    /// Waits for the other upgradable holder and any writer, but not for readers.
+    pub fn upgradable_read(self: &Arc<Self>) -> PageUpgradableLatch {
+        self.acquire(|state| !state.writer && !state.upgradable, |state| state.upgradable = true);
+        PageUpgradableLatch { latch: self.clone(), upgraded: false }
+    }
+
+    pub fn write(self: &Arc<Self>) -> PageWriteLatch {
+        self.acquire(|state| !state.writer && !state.upgradable && state.readers == 0, |state| state.writer = true);
+        PageWriteLatch { latch: self.clone() }
+    }
+
+    /// Holders of the latch in any mode.
+    pub fn holders(&self) -> usize {
+        let state = self.lock_state();
+        state.readers + state.upgradable as usize + state.writer as usize
+    }
+
+    fn acquire<C, T>(&self, can_take: C, take: T)
+        where C: Fn(&LatchState) -> bool, T: FnOnce(&mut LatchState)
+    {
+        let mut state = self.lock_state();
+        while !can_take(&state) {
+            state = self.released.wait(state).unwrap();
+        }
+        take(&mut state);
+    }
+
+    fn release<T: FnOnce(&mut LatchState)>(&self, give_back: T) {
+        give_back(&mut self.lock_state());
+        self.released.notify_all();
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, LatchState> {
+        self.state.lock().unwrap()
+    }
+}
+
+pub struct PageReadLatch {
+    latch: Arc<PageLatch>,
+}
+
+impl Drop for PageReadLatch {
+    fn drop(&mut self) {
+        self.latch.release(|state| state.readers -= 1);
+    }
+}
+
+pub struct PageUpgradableLatch {
+    latch: Arc<PageLatch>,
+    upgraded: bool,
+}
+
+impl PageUpgradableLatch {
+    /// Waits for the readers to leave. No writer can take the latch meanwhile, so what was read under
+    /// this latch still holds once upgraded.
+    pub fn upgrade(mut self) -> PageWriteLatch {
+        // the upgradable mode passes to the writer without a gap, dropping self must not release it
+        self.upgraded = true;
+        let latch = self.latch.clone();
+        latch.acquire(|state| state.readers == 0, |state| {
+            state.upgradable = false;
+            state.writer = true;
+        });
+        PageWriteLatch { latch }
+    }
+}
+
+impl Drop for PageUpgradableLatch {
+    fn drop(&mut self) {
+        if self.upgraded {
+            return;
+        }
+
+        self.latch.release(|state| state.upgradable = false);
+    }
+}
+
+pub struct PageWriteLatch {
+    latch: Arc<PageLatch>,
+}
+
+impl Drop for PageWriteLatch {
+    fn drop(&mut self) {
+        self.latch.release(|state| state.writer = false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::storage::page::page_latch::PageLatch;
+
+    #[test]
+    fn should_share_upgradable_latch_with_readers_until_upgraded() {
+        // given
+        let latch = Arc::new(PageLatch::new());
+        let upgradable = latch.upgradable_read();
+        let reader = latch.read();
+        let upgraded = Arc::new(AtomicBool::new(false));
+
+        // when
+        let upgrader = {
+            let upgraded = upgraded.clone();
+            thread::spawn(move || {
+                let writer = upgradable.upgrade();
+                upgraded.store(true, Ordering::SeqCst);
+                drop(writer);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let upgraded_while_read = upgraded.load(Ordering::SeqCst);
+        drop(reader);
+        upgrader.join().unwrap();
+
+        // then
+        assert!(!upgraded_while_read);
+        assert!(upgraded.load(Ordering::SeqCst));
+        assert_eq!(latch.holders(), 0);
+    }
+
+    #[test]
+    fn should_keep_writer_out_while_upgradable_latch_held() {
+        // given
+        let latch = Arc::new(PageLatch::new());
+        let upgradable = latch.upgradable_read();
+        let written = Arc::new(AtomicBool::new(false));
+
+        // when
+        let writer = {
+            let (latch, written) = (latch.clone(), written.clone());
+            thread::spawn(move || {
+                let _writer = latch.write();
+                written.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let written_while_upgradable = written.load(Ordering::SeqCst);
+        let upgraded = upgradable.upgrade();
+        let written_after_upgrade = written.load(Ordering::SeqCst);
+        drop(upgraded);
+        writer.join().unwrap();
+
+        // then
+        assert!(!written_while_upgradable);
+        assert!(!written_after_upgrade);
+        assert!(written.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+
+use crate::storage::page::page::PAGE_SIZE;
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_version::{read_page_version, PageVersion};
+
+/// Encoding of a page type to and from the data of a whole page, shared by every page type the buffer
+/// pool hands out, see `BufferPoolManager::fetch_decoded` and `write_encoded`.
+pub trait PageSerde: Sized {
+    /// Newest layout version of the page kind. `from_page` also reads the older ones it supports.
+    const VERSION: PageVersion;
+
+    /// Writes the page over `page_data`, zeroing whatever follows the encoded page.
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()>;
+
+    fn from_page(page_data: &[u8]) -> io::Result<Self>;
+}
+
+/// Copies a page encoded into a buffer over the page data.
+pub(crate) fn copy_to_page(raw: &[u8], page_data: &mut [u8]) -> io::Result<()> {
+    if raw.len() > PAGE_SIZE || page_data.len() < raw.len() {
+        return Err(PageError::PageDataTooShort { expected: raw.len(), actual: page_data.len().min(PAGE_SIZE) }.into());
+    }
+
+    page_data[..raw.len()].copy_from_slice(raw);
+    page_data[raw.len()..].fill(0);
+    Ok(())
+}
+
+/// Rewrites a page stored in an older layout of `T` in the current one, so later reads and writes of
+/// the page no longer go through the older layout. Returns whether the page was rewritten.
+///
+/// A page whose content does not fit the current layout, e.g. a header holding more block ids than
+/// a linked header page can, is kept in the newest layout it fits.
+pub fn upgrade_page<T: PageSerde>(page_data: &mut [u8]) -> io::Result<bool> {
+    let version = read_page_version(page_data)?;
+    if version >= T::VERSION {
+        return Ok(false);
+    }
+
+    T::from_page(page_data)?.to_page(page_data)?;
+    Ok(read_page_version(page_data)? != version)
+}
@@ -0,0 +1,79 @@
+use std::convert::TryInto;
+use std::io;
+
+use crate::storage::page::page_error::PageError;
+
+/// Layout version of a single page kind, stored in the first byte of the page.
+pub type PageVersion = u8;
+
+/// Version of the data file as a whole, stored in the superblock.
+pub type FormatVersion = u8;
+
+pub const PAGE_VERSION_SIZE: usize = 1;
+
+/// Log sequence number of the last change to a page, for comparing page state with log records.
+pub type Lsn = u64;
+
+/// Page kinds keeping an LSN store it right after their version byte.
+pub const PAGE_LSN_OFFSET: usize = PAGE_VERSION_SIZE;
+pub const PAGE_LSN_SIZE: usize = 8;
+/// The page type follows the LSN, so the LSN stays where page layouts from before page types had it.
+pub const PAGE_TYPE_OFFSET: usize = PAGE_LSN_OFFSET + PAGE_LSN_SIZE;
+pub const PAGE_TYPE_SIZE: usize = 1;
+/// Version byte, LSN and page type, ahead of the content of every page kind.
+pub const PAGE_HEADER_SIZE: usize = PAGE_TYPE_OFFSET + PAGE_TYPE_SIZE;
+/// Version byte and LSN of page layouts from before page types.
+pub const UNTYPED_PAGE_HEADER_SIZE: usize = PAGE_VERSION_SIZE + PAGE_LSN_SIZE;
+
+/// Kind of a page, stored in its header so that a page is never decoded as another kind.
+/// Values are persisted and must never be reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PageType {
+    HashTableHeader = 1,
+    HashTableBlock = 2,
+    HashTableBucket = 3,
+    Overflow = 4,
+    FreeSpaceMap = 5,
+    U64Block = 6,
+    Log = 7,
+    BPlusTreeLeaf = 8,
+    BPlusTreeInternal = 9,
+    BPlusTreeMeta = 10,
+    TablePage = 11,
+    Catalog = 12,
+    Statistics = 13,
+    AllocationMap = 14,
+}
+
+/// Files written before versioning: no superblock, and pages start directly with their content.
+pub const LEGACY_FORMAT_VERSION: FormatVersion = 0;
+/// Page 0 holds the superblock and every other page starts with its version byte.
+pub const CURRENT_FORMAT_VERSION: FormatVersion = 1;
+
+pub fn read_page_lsn(page_data: &[u8]) -> io::Result<Lsn> {
+    match page_data.get(PAGE_LSN_OFFSET..PAGE_TYPE_OFFSET) {
+        Some(raw) => Ok(Lsn::from_le_bytes(raw.try_into().unwrap())),
+        None => Err(PageError::PageDataTooShort { expected: PAGE_TYPE_OFFSET, actual: page_data.len() }.into()),
+    }
+}
+
+pub fn write_page_lsn(page_data: &mut [u8], lsn: Lsn) {
+    page_data[PAGE_LSN_OFFSET..PAGE_TYPE_OFFSET].copy_from_slice(&lsn.to_le_bytes());
+}
+
+pub fn read_page_version(page_data: &[u8]) -> io::Result<PageVersion> {
+    match page_data.first() {
+        Some(version) => Ok(*version),
+        None => Err(PageError::PageDataTooShort { expected: PAGE_VERSION_SIZE, actual: 0 }.into()),
+    }
+}
+
+/// Pages of a layout with a page type must be of the `expected` kind.
+pub fn check_page_type(page_data: &[u8], expected: PageType) -> io::Result<()> {
+    match page_data.get(PAGE_TYPE_OFFSET) {
+        Some(found) if *found == expected as u8 => Ok(()),
+        Some(found) => Err(PageError::WrongPageType { expected, found: *found }.into()),
+        None => Err(PageError::PageDataTooShort { expected: PAGE_HEADER_SIZE, actual: page_data.len() }.into()),
+    }
+}
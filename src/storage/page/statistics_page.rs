@@ -0,0 +1,77 @@
+use std::io;
+
+use crate::catalog::statistics::TableStatistics;
+use crate::storage::page::page::PAGE_SIZE;
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+
+/// v1: version byte, LSN, page type, then the statistics, bincode encoded.
+const STATISTICS_PAGE_VERSION: PageVersion = 1;
+
+/// Page holding the statistics of one table, referred to from the catalog page. The statistics have
+/// to fit into the one page, encoding more fails with `PageError::ValueTooLarge`.
+pub struct StatisticsPage {
+    page_lsn: Lsn,
+    statistics: TableStatistics,
+}
+
+impl StatisticsPage {
+    pub fn new(statistics: TableStatistics) -> StatisticsPage {
+        StatisticsPage { page_lsn: 0, statistics }
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_statistics(&self) -> &TableStatistics {
+        &self.statistics
+    }
+
+    pub fn into_statistics(self) -> TableStatistics {
+        self.statistics
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut res = vec![STATISTICS_PAGE_VERSION];
+        res.extend_from_slice(&self.page_lsn.to_le_bytes());
+        res.push(PageType::Statistics as u8);
+        bincode::serialize_into(&mut res, &self.statistics).map_err(PageError::from)?;
+        if res.len() > PAGE_SIZE {
+            return Err(PageError::ValueTooLarge { size: res.len(), capacity: PAGE_SIZE }.into());
+        }
+        Ok(res)
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<StatisticsPage> {
+        match read_page_version(page_data)? {
+            STATISTICS_PAGE_VERSION => check_page_type(page_data, PageType::Statistics)?,
+            version => return Err(PageError::UnknownVersion { found: version, supported: STATISTICS_PAGE_VERSION }.into()),
+        }
+        if page_data.len() < PAGE_HEADER_SIZE {
+            return Err(PageError::PageDataTooShort { expected: PAGE_HEADER_SIZE, actual: page_data.len() }.into());
+        }
+
+        Ok(StatisticsPage {
+            page_lsn: read_page_lsn(page_data)?,
+            statistics: bincode::deserialize(&page_data[PAGE_HEADER_SIZE..]).map_err(PageError::from)?,
+        })
+    }
+}
+
+impl PageSerde for StatisticsPage {
+    const VERSION: PageVersion = STATISTICS_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize()?, page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<StatisticsPage> {
+        StatisticsPage::deserialize(page_data)
+    }
+}
@@ -0,0 +1,215 @@
+use std::convert::TryInto;
+use std::io;
+
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_version::{CURRENT_FORMAT_VERSION, FormatVersion};
+
+pub const SUPERBLOCK_PAGE_ID: PageId = 0;
+const MAGIC: [u8; 8] = *b"MINEDB\0\0";
+const FORMAT_VERSION_OFFSET: usize = MAGIC.len();
+const PAGE_SIZE_OFFSET: usize = FORMAT_VERSION_OFFSET + 1;
+const CATALOG_ROOT_OFFSET: usize = PAGE_SIZE_OFFSET + 4;
+const HASH_TABLE_ROOT_OFFSET: usize = CATALOG_ROOT_OFFSET + 8;
+const ALLOCATION_MAP_ROOT_OFFSET: usize = HASH_TABLE_ROOT_OFFSET + 8;
+const ALLOCATION_MAP_PAGES_OFFSET: usize = ALLOCATION_MAP_ROOT_OFFSET + 8;
+const SUPERBLOCK_SIZE: usize = ALLOCATION_MAP_PAGES_OFFSET + 8;
+/// The superblock page holds the start of the allocation bitmap from here on, the rest of the bitmap
+/// is kept in a chain of `AllocationMapPage`s from the allocation map root.
+pub const ALLOCATION_MAP_OFFSET: usize = 64;
+
+/// First page of a data file: magic, the format the rest of the file is written in, the page size,
+/// the root pages structures are found from, and where the allocation bitmap of the file is kept.
+///
+/// Root ids are 0 when unset, as page 0 is always the superblock. Superblocks written before the page
+/// size was recorded read as having the current page size, and the ones written before the allocation
+/// bitmap was kept as covering no pages.
+pub struct Superblock {
+    format_version: FormatVersion,
+    page_size: usize,
+    catalog_root: Option<PageId>,
+    hash_table_root: Option<PageId>,
+    allocation_map_root: Option<PageId>,
+    allocation_map_pages: usize,
+}
+
+impl Superblock {
+    pub fn new() -> Superblock {
+        Superblock {
+            format_version: CURRENT_FORMAT_VERSION,
+            page_size: PAGE_SIZE,
+            catalog_root: None,
+            hash_table_root: None,
+            allocation_map_root: None,
+            allocation_map_pages: 0,
+        }
+    }
+
+    pub fn get_format_version(&self) -> FormatVersion {
+        self.format_version
+    }
+
+    pub fn get_page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn get_catalog_root(&self) -> Option<PageId> {
+        self.catalog_root
+    }
+
+    pub fn set_catalog_root(&mut self, pid: Option<PageId>) {
+        self.catalog_root = pid
+    }
+
+    /// Header page of the first hash table.
+    pub fn get_hash_table_root(&self) -> Option<PageId> {
+        self.hash_table_root
+    }
+
+    pub fn set_hash_table_root(&mut self, pid: Option<PageId>) {
+        self.hash_table_root = pid
+    }
+
+    /// First page of the allocation bitmap past the part kept in the superblock page.
+    pub fn get_allocation_map_root(&self) -> Option<PageId> {
+        self.allocation_map_root
+    }
+
+    pub fn set_allocation_map_root(&mut self, pid: Option<PageId>) {
+        self.allocation_map_root = pid
+    }
+
+    /// Pages of the file the allocation bitmap covers, 0 if the file has none kept yet.
+    pub fn get_allocation_map_pages(&self) -> usize {
+        self.allocation_map_pages
+    }
+
+    pub fn set_allocation_map_pages(&mut self, num_pages: usize) {
+        self.allocation_map_pages = num_pages
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut res = MAGIC.to_vec();
+        res.push(self.format_version);
+        res.extend_from_slice(&(self.page_size as u32).to_le_bytes());
+        res.extend_from_slice(&(self.catalog_root.unwrap_or(SUPERBLOCK_PAGE_ID) as u64).to_le_bytes());
+        res.extend_from_slice(&(self.hash_table_root.unwrap_or(SUPERBLOCK_PAGE_ID) as u64).to_le_bytes());
+        res.extend_from_slice(&(self.allocation_map_root.unwrap_or(SUPERBLOCK_PAGE_ID) as u64).to_le_bytes());
+        res.extend_from_slice(&(self.allocation_map_pages as u64).to_le_bytes());
+        res
+    }
+
+    /// `None` when the page carries no magic, i.e. the file was written before superblocks existed.
+    /// Fails when the file uses another page size than this build.
+    pub fn deserialize(page_data: &[u8]) -> io::Result<Option<Superblock>> {
+        if page_data.len() < SUPERBLOCK_SIZE {
+            return Err(PageError::PageDataTooShort { expected: SUPERBLOCK_SIZE, actual: page_data.len() }.into());
+        }
+
+        if page_data[0..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+
+        let format_version = page_data[FORMAT_VERSION_OFFSET];
+        if format_version > CURRENT_FORMAT_VERSION {
+            return Err(PageError::UnknownVersion { found: format_version, supported: CURRENT_FORMAT_VERSION }.into());
+        }
+
+        let page_size = match u32::from_le_bytes(page_data[PAGE_SIZE_OFFSET..CATALOG_ROOT_OFFSET].try_into().unwrap()) as usize {
+            0 => PAGE_SIZE,
+            page_size => page_size,
+        };
+        if page_size != PAGE_SIZE {
+            return Err(PageError::PageSizeMismatch { expected: PAGE_SIZE, found: page_size }.into());
+        }
+
+        Ok(Some(Superblock {
+            format_version,
+            page_size,
+            catalog_root: read_root(&page_data[CATALOG_ROOT_OFFSET..HASH_TABLE_ROOT_OFFSET]),
+            hash_table_root: read_root(&page_data[HASH_TABLE_ROOT_OFFSET..ALLOCATION_MAP_ROOT_OFFSET]),
+            allocation_map_root: read_root(&page_data[ALLOCATION_MAP_ROOT_OFFSET..ALLOCATION_MAP_PAGES_OFFSET]),
+            allocation_map_pages: u64::from_le_bytes(page_data[ALLOCATION_MAP_PAGES_OFFSET..SUPERBLOCK_SIZE].try_into().unwrap()) as usize,
+        }))
+    }
+}
+
+fn read_root(raw: &[u8]) -> Option<PageId> {
+    match u64::from_le_bytes(raw.try_into().unwrap()) as PageId {
+        SUPERBLOCK_PAGE_ID => None,
+        pid => Some(pid),
+    }
+}
+
+impl Default for Superblock {
+    fn default() -> Self {
+        Superblock::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_version::CURRENT_FORMAT_VERSION;
+    use crate::storage::page::superblock::{Superblock, FORMAT_VERSION_OFFSET, PAGE_SIZE_OFFSET};
+
+    #[test]
+    fn should_serialize_and_deserialize_superblock() {
+        // given
+        let mut page = [0u8; PAGE_SIZE];
+        let raw = Superblock::new().serialize();
+        page[0..raw.len()].copy_from_slice(&raw);
+
+        // when
+        let superblock = Superblock::deserialize(&page).unwrap();
+
+        // then
+        assert_eq!(superblock.unwrap().get_format_version(), CURRENT_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn should_return_none_when_magic_missing() {
+        let superblock = Superblock::deserialize(&[0u8; PAGE_SIZE]).unwrap();
+
+        assert!(superblock.is_none());
+    }
+
+    #[test]
+    fn should_fail_when_format_version_unknown() {
+        // given
+        let mut raw = Superblock::new().serialize();
+        raw[FORMAT_VERSION_OFFSET] = CURRENT_FORMAT_VERSION + 1;
+
+        // when
+        let result = Superblock::deserialize(&raw);
+
+        // then
+        let err = result.err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::UnknownVersion { found: 2, supported: 1 })));
+    }
+
+    #[test]
+    fn should_keep_roots_and_refuse_other_page_size() {
+        // given
+        let mut superblock = Superblock::new();
+        superblock.set_catalog_root(Some(3));
+        superblock.set_allocation_map_root(Some(5));
+        superblock.set_allocation_map_pages(1024);
+        let mut raw = superblock.serialize();
+
+        // when
+        let read_back = Superblock::deserialize(&raw).unwrap().unwrap();
+        raw[PAGE_SIZE_OFFSET..PAGE_SIZE_OFFSET + 4].copy_from_slice(&(2 * PAGE_SIZE as u32).to_le_bytes());
+        let other_page_size = Superblock::deserialize(&raw);
+
+        // then
+        assert_eq!(read_back.get_catalog_root(), Some(3));
+        assert_eq!(read_back.get_hash_table_root(), None);
+        assert_eq!(read_back.get_allocation_map_root(), Some(5));
+        assert_eq!(read_back.get_allocation_map_pages(), 1024);
+        assert_eq!(read_back.get_page_size(), PAGE_SIZE);
+        let err = other_page_size.err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::PageSizeMismatch { found, .. }) if *found == 2 * PAGE_SIZE));
+    }
+}
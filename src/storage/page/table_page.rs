@@ -0,0 +1,381 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use crate::storage::page::page::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_serde::{copy_to_page, PageSerde};
+use crate::storage::page::page_version::{Lsn, PageType, PageVersion, PAGE_HEADER_SIZE, check_page_type, read_page_lsn, read_page_version};
+use crate::storage::table::rid::Rid;
+use crate::storage::table::tuple::TupleHeader;
+
+/// v4: version byte, LSN, page type, next page id, free space map page id, slot count, then the offset,
+/// length, schema version and `TupleHeader` of the tuple of each slot, an offset of 0 marking a deleted
+/// tuple. The header is begin and end timestamp, then the rid of the previous version, an invalid page
+/// id for none. Tuples are packed at the end of the page, the tuple of the first slot last. v3 pages had
+/// no free space map page id, v2 slots had no header, their tuples read with the default one, and v1
+/// slots had no schema version either, reading as of version 0.
+const TABLE_PAGE_VERSION: PageVersion = 4;
+const V3_TABLE_PAGE_VERSION: PageVersion = 3;
+const V2_TABLE_PAGE_VERSION: PageVersion = 2;
+const V1_TABLE_PAGE_VERSION: PageVersion = 1;
+const FREE_SPACE_MAP_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE + mem::size_of::<u64>();
+const SLOT_COUNT_OFFSET: usize = FREE_SPACE_MAP_PAGE_ID_OFFSET + mem::size_of::<u64>();
+const SLOTS_OFFSET: usize = SLOT_COUNT_OFFSET + mem::size_of::<u32>();
+const V3_SLOT_COUNT_OFFSET: usize = FREE_SPACE_MAP_PAGE_ID_OFFSET;
+const HEADER_OFFSET: usize = V2_SLOT_SIZE;
+const SLOT_SIZE: usize = V2_SLOT_SIZE + 2 * mem::size_of::<u64>() + Rid::SIZE;
+const V2_SLOT_SIZE: usize = 3 * mem::size_of::<u32>();
+const V1_SLOT_SIZE: usize = 2 * mem::size_of::<u32>();
+
+/// Tuple bytes with the version of the table schema they were encoded after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Slot {
+    schema_version: u32,
+    header: TupleHeader,
+    data: Vec<u8>,
+}
+
+/// Slotted page of a table heap, pointing to the next page of the heap. A tuple is addressed by its
+/// slot, which stays the same while the tuple moves within the page. Slots of deleted tuples are only
+/// reused once dropped by `compact`, so until then a rid never points to another tuple than it was
+/// given for.
+pub struct TablePage {
+    page_lsn: Lsn,
+    next_page_id: Option<PageId>,
+    /// First page of the free space map of the heap, only kept on the first page of a heap.
+    free_space_map_page_id: Option<PageId>,
+    /// Tuple of each slot, `None` once deleted.
+    tuples: Vec<Option<Slot>>,
+}
+
+impl TablePage {
+    pub fn new() -> TablePage {
+        TablePage { page_lsn: 0, next_page_id: None, free_space_map_page_id: None, tuples: Vec::new() }
+    }
+
+    /// Largest tuple an empty page takes.
+    pub fn max_tuple_size() -> usize {
+        PAGE_SIZE - SLOTS_OFFSET - SLOT_SIZE
+    }
+
+    pub fn get_lsn(&self) -> Lsn {
+        self.page_lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.page_lsn = lsn
+    }
+
+    pub fn get_next_page_id(&self) -> Option<PageId> {
+        self.next_page_id
+    }
+
+    pub fn set_next_page_id(&mut self, next_page_id: Option<PageId>) {
+        self.next_page_id = next_page_id
+    }
+
+    pub fn get_free_space_map_page_id(&self) -> Option<PageId> {
+        self.free_space_map_page_id
+    }
+
+    pub fn set_free_space_map_page_id(&mut self, free_space_map_page_id: Option<PageId>) {
+        self.free_space_map_page_id = free_space_map_page_id
+    }
+
+    /// Bytes a tuple takes on a page along with its slot.
+    pub fn required_space(tuple_size: usize) -> usize {
+        tuple_size + SLOT_SIZE
+    }
+
+    /// Slots handed out so far, deleted ones included.
+    pub fn num_slots(&self) -> usize {
+        self.tuples.len()
+    }
+
+    /// Bytes left for tuples and their slots.
+    pub fn free_space(&self) -> usize {
+        let used: usize = self.tuples.iter().flatten().map(|slot| slot.data.len()).sum();
+        PAGE_SIZE - SLOTS_OFFSET - self.tuples.len() * SLOT_SIZE - used
+    }
+
+    /// Whether every tuple of the page was deleted.
+    pub fn is_empty(&self) -> bool {
+        self.tuples.iter().all(Option::is_none)
+    }
+
+    /// Drops the slots of deleted tuples after the last tuple left, returning how many were dropped.
+    /// Slots in between stay, as dropping them would move the tuples after them to other slots, and so
+    /// does a slot `is_referenced` tells is still referred to, along with the slots before it.
+    pub fn compact<F: Fn(u32) -> bool>(&mut self, is_referenced: F) -> usize {
+        let num_slots = self.tuples.len();
+        while let Some(None) = self.tuples.last() {
+            if is_referenced(self.tuples.len() as u32 - 1) {
+                break;
+            }
+            self.tuples.pop();
+        }
+        num_slots - self.tuples.len()
+    }
+
+    /// Returns the slot of the tuple, `None` if the page has no room left for it.
+    pub fn insert(&mut self, tuple: &[u8], schema_version: u32) -> Option<u32> {
+        self.insert_with_header(tuple, schema_version, TupleHeader::default())
+    }
+
+    pub fn insert_with_header(&mut self, tuple: &[u8], schema_version: u32, header: TupleHeader) -> Option<u32> {
+        if TablePage::required_space(tuple.len()) > self.free_space() {
+            return None;
+        }
+
+        self.tuples.push(Some(Slot { schema_version, header, data: tuple.to_vec() }));
+        Some(self.tuples.len() as u32 - 1)
+    }
+
+    /// `None` for a deleted tuple.
+    pub fn get(&self, slot: u32) -> io::Result<Option<&[u8]>> {
+        Ok(self.slot(slot)?.as_ref().map(|slot| slot.data.as_slice()))
+    }
+
+    /// Tuple with the schema version it was written with, `None` for a deleted tuple.
+    pub fn get_versioned(&self, slot: u32) -> io::Result<Option<(u32, &[u8])>> {
+        Ok(self.slot(slot)?.as_ref().map(|slot| (slot.schema_version, slot.data.as_slice())))
+    }
+
+    /// `None` for a deleted tuple.
+    pub fn get_header(&self, slot: u32) -> io::Result<Option<TupleHeader>> {
+        Ok(self.slot(slot)?.as_ref().map(|slot| slot.header))
+    }
+
+    /// Returns false, leaving the page unchanged, if the slot holds no tuple.
+    pub fn set_header(&mut self, slot: u32, header: TupleHeader) -> io::Result<bool> {
+        Ok(self.slot_mut(slot)?.as_mut().map(|slot| slot.header = header).is_some())
+    }
+
+    /// Returns whether the slot held a tuple.
+    pub fn remove(&mut self, slot: u32) -> io::Result<bool> {
+        Ok(self.slot_mut(slot)?.take().is_some())
+    }
+
+    /// Replaces the tuple of the slot, keeping its header, returning false, leaving the page unchanged,
+    /// if the slot holds no tuple or the page has no room for the new one.
+    pub fn update(&mut self, slot: u32, tuple: &[u8], schema_version: u32) -> io::Result<bool> {
+        let free_space = self.free_space();
+        let old = match self.slot_mut(slot)? {
+            Some(old) => old,
+            None => return Ok(false),
+        };
+        if tuple.len() > free_space + old.data.len() {
+            return Ok(false);
+        }
+
+        *old = Slot { schema_version, header: old.header, data: tuple.to_vec() };
+        Ok(true)
+    }
+
+    fn slot(&self, slot: u32) -> io::Result<&Option<Slot>> {
+        let capacity = self.tuples.len();
+        self.tuples.get(slot as usize)
+            .ok_or_else(|| PageError::SlotOutOfRange { slot_idx: slot as usize, capacity }.into())
+    }
+
+    fn slot_mut(&mut self, slot: u32) -> io::Result<&mut Option<Slot>> {
+        let capacity = self.tuples.len();
+        self.tuples.get_mut(slot as usize)
+            .ok_or_else(|| PageError::SlotOutOfRange { slot_idx: slot as usize, capacity }.into())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut res = vec![0; PAGE_SIZE];
+        res[0] = TABLE_PAGE_VERSION;
+        res[1..PAGE_HEADER_SIZE - 1].copy_from_slice(&self.page_lsn.to_le_bytes());
+        res[PAGE_HEADER_SIZE - 1] = PageType::TablePage as u8;
+        res[PAGE_HEADER_SIZE..FREE_SPACE_MAP_PAGE_ID_OFFSET].copy_from_slice(&(self.next_page_id.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        res[FREE_SPACE_MAP_PAGE_ID_OFFSET..SLOT_COUNT_OFFSET].copy_from_slice(&(self.free_space_map_page_id.unwrap_or(INVALID_PAGE_ID) as u64).to_le_bytes());
+        res[SLOT_COUNT_OFFSET..SLOTS_OFFSET].copy_from_slice(&(self.tuples.len() as u32).to_le_bytes());
+
+        let mut data_end = PAGE_SIZE;
+        for (slot, tuple) in self.tuples.iter().enumerate() {
+            let (offset, len, schema_version, header) = match tuple {
+                Some(Slot { schema_version, header, data }) => {
+                    data_end -= data.len();
+                    res[data_end..data_end + data.len()].copy_from_slice(data);
+                    (data_end, data.len(), *schema_version, *header)
+                }
+                None => (0, 0, 0, TupleHeader::default()),
+            };
+            let slot_offset = SLOTS_OFFSET + slot * SLOT_SIZE;
+            res[slot_offset..slot_offset + 4].copy_from_slice(&(offset as u32).to_le_bytes());
+            res[slot_offset + 4..slot_offset + 8].copy_from_slice(&(len as u32).to_le_bytes());
+            res[slot_offset + 8..slot_offset + HEADER_OFFSET].copy_from_slice(&schema_version.to_le_bytes());
+            let header_offset = slot_offset + HEADER_OFFSET;
+            res[header_offset..header_offset + 8].copy_from_slice(&header.begin_ts.to_le_bytes());
+            res[header_offset + 8..header_offset + 16].copy_from_slice(&header.end_ts.to_le_bytes());
+            let prev_version = header.prev_version.unwrap_or(Rid::new(INVALID_PAGE_ID, 0));
+            res[header_offset + 16..slot_offset + SLOT_SIZE].copy_from_slice(&prev_version.to_bytes());
+        }
+        res
+    }
+
+    pub fn deserialize(page_data: &[u8]) -> io::Result<TablePage> {
+        let version = read_page_version(page_data)?;
+        let (slot_count_offset, slot_size) = match version {
+            V1_TABLE_PAGE_VERSION => (V3_SLOT_COUNT_OFFSET, V1_SLOT_SIZE),
+            V2_TABLE_PAGE_VERSION => (V3_SLOT_COUNT_OFFSET, V2_SLOT_SIZE),
+            V3_TABLE_PAGE_VERSION => (V3_SLOT_COUNT_OFFSET, SLOT_SIZE),
+            TABLE_PAGE_VERSION => (SLOT_COUNT_OFFSET, SLOT_SIZE),
+            version => return Err(PageError::UnknownVersion { found: version, supported: TABLE_PAGE_VERSION }.into()),
+        };
+        check_page_type(page_data, PageType::TablePage)?;
+        let slots_offset = slot_count_offset + mem::size_of::<u32>();
+        if page_data.len() < slots_offset {
+            return Err(PageError::PageDataTooShort { expected: slots_offset, actual: page_data.len() }.into());
+        }
+
+        let next_page_id = u64::from_le_bytes(page_data[PAGE_HEADER_SIZE..FREE_SPACE_MAP_PAGE_ID_OFFSET].try_into().unwrap()) as PageId;
+        let free_space_map_page_id = match version {
+            TABLE_PAGE_VERSION => u64::from_le_bytes(page_data[FREE_SPACE_MAP_PAGE_ID_OFFSET..SLOT_COUNT_OFFSET].try_into().unwrap()) as PageId,
+            _ => INVALID_PAGE_ID,
+        };
+        let num_slots = u32::from_le_bytes(page_data[slot_count_offset..slots_offset].try_into().unwrap()) as usize;
+        let slots_end = slots_offset + num_slots * slot_size;
+        if page_data.len() < slots_end {
+            return Err(PageError::PageDataTooShort { expected: slots_end, actual: page_data.len() }.into());
+        }
+        let tuples = (0..num_slots).map(|slot| {
+            let slot_offset = slots_offset + slot * slot_size;
+            let offset = u32::from_le_bytes(page_data[slot_offset..slot_offset + 4].try_into().unwrap()) as usize;
+            let len = u32::from_le_bytes(page_data[slot_offset + 4..slot_offset + 8].try_into().unwrap()) as usize;
+            let schema_version = match version {
+                V1_TABLE_PAGE_VERSION => 0,
+                _ => u32::from_le_bytes(page_data[slot_offset + 8..slot_offset + HEADER_OFFSET].try_into().unwrap()),
+            };
+            let header = match version {
+                V3_TABLE_PAGE_VERSION | TABLE_PAGE_VERSION => read_header(&page_data[slot_offset + HEADER_OFFSET..slot_offset + SLOT_SIZE]),
+                _ => TupleHeader::default(),
+            };
+            match offset {
+                0 => Ok(None),
+                _ => page_data.get(offset..offset + len)
+                    .map(|data| Some(Slot { schema_version, header, data: data.to_vec() }))
+                    .ok_or_else(|| PageError::PageDataTooShort { expected: offset + len, actual: page_data.len() }.into()),
+            }
+        }).collect::<io::Result<Vec<Option<Slot>>>>()?;
+
+        Ok(TablePage {
+            page_lsn: read_page_lsn(page_data)?,
+            next_page_id: if next_page_id == INVALID_PAGE_ID { None } else { Some(next_page_id) },
+            free_space_map_page_id: if free_space_map_page_id == INVALID_PAGE_ID { None } else { Some(free_space_map_page_id) },
+            tuples,
+        })
+    }
+}
+
+fn read_header(bytes: &[u8]) -> TupleHeader {
+    let prev_version = Rid::from_bytes(bytes[16..16 + Rid::SIZE].try_into().unwrap());
+    TupleHeader {
+        begin_ts: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+        end_ts: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        prev_version: if prev_version.page_id == INVALID_PAGE_ID { None } else { Some(prev_version) },
+    }
+}
+
+impl Default for TablePage {
+    fn default() -> Self {
+        TablePage::new()
+    }
+}
+
+impl PageSerde for TablePage {
+    const VERSION: PageVersion = TABLE_PAGE_VERSION;
+
+    fn to_page(&self, page_data: &mut [u8]) -> io::Result<()> {
+        copy_to_page(&self.serialize(), page_data)
+    }
+
+    fn from_page(page_data: &[u8]) -> io::Result<TablePage> {
+        TablePage::deserialize(page_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::page::{INVALID_PAGE_ID, PAGE_SIZE};
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_version::{PageType, PAGE_HEADER_SIZE};
+    use crate::storage::page::table_page::{TablePage, SLOT_SIZE, V1_TABLE_PAGE_VERSION, V3_SLOT_COUNT_OFFSET};
+    use crate::storage::table::rid::Rid;
+    use crate::storage::table::tuple::TupleHeader;
+
+    #[test]
+    fn should_keep_slots_of_tuples_through_delete_update_and_serde() {
+        // given
+        let mut page = TablePage::new();
+        page.set_next_page_id(Some(7));
+        page.set_free_space_map_page_id(Some(9));
+        let slots: Vec<u32> = [&b"alpha"[..], b"beta", b"gamma"].iter().map(|t| page.insert(t, 0).unwrap()).collect();
+        let header = TupleHeader { begin_ts: 3, end_ts: 9, prev_version: Some(Rid::new(4, 2)) };
+
+        // when
+        page.remove(slots[1]).unwrap();
+        page.set_header(slots[0], header).unwrap();
+        let grown = page.update(slots[0], b"alpha, grown", 1).unwrap();
+        let deleted_updated = page.update(slots[1], b"x", 1).unwrap();
+        let deser_page = TablePage::deserialize(&page.serialize()).unwrap();
+
+        // then
+        assert_eq!(slots, [0, 1, 2]);
+        assert!(grown && !deleted_updated);
+        assert_eq!(deser_page.get_next_page_id(), Some(7));
+        assert_eq!(deser_page.get_free_space_map_page_id(), Some(9));
+        assert_eq!(deser_page.num_slots(), 3);
+        assert_eq!(deser_page.get_versioned(0).unwrap(), Some((1, &b"alpha, grown"[..])));
+        assert_eq!(deser_page.get_header(0).unwrap(), Some(header));
+        assert_eq!(deser_page.get_header(2).unwrap(), Some(TupleHeader::default()));
+        assert_eq!(deser_page.get(1).unwrap(), None);
+        assert_eq!(deser_page.get(2).unwrap(), Some(&b"gamma"[..]));
+        assert_eq!(deser_page.free_space(), page.free_space());
+        let err = deser_page.get(3).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::SlotOutOfRange { slot_idx: 3, capacity: 3 })));
+    }
+
+    #[test]
+    fn should_refuse_tuples_once_page_is_full() {
+        // given
+        let mut page = TablePage::new();
+        let tuple = vec![1u8; 900];
+
+        // when
+        let inserted = (0..5).filter_map(|_| page.insert(&tuple, 0)).count();
+        let largest = page.insert(&vec![2u8; page.free_space() - SLOT_SIZE], 0);
+        let grown = page.update(0, &vec![3u8; 901], 0).unwrap();
+
+        // then
+        assert_eq!(inserted, 4);
+        assert_eq!(largest, Some(4));
+        assert_eq!(page.free_space(), 0);
+        assert!(!grown);
+        assert!(TablePage::new().insert(&vec![0u8; TablePage::max_tuple_size()], 0).is_some());
+    }
+
+    #[test]
+    fn should_read_tuples_of_v1_page_as_of_schema_version_0() {
+        // given
+        let mut page_data = vec![0u8; PAGE_SIZE];
+        page_data[0] = V1_TABLE_PAGE_VERSION;
+        page_data[PAGE_HEADER_SIZE - 1] = PageType::TablePage as u8;
+        page_data[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 8].copy_from_slice(&(INVALID_PAGE_ID as u64).to_le_bytes());
+        let slots_offset = V3_SLOT_COUNT_OFFSET + 4;
+        page_data[V3_SLOT_COUNT_OFFSET..slots_offset].copy_from_slice(&1u32.to_le_bytes());
+        page_data[slots_offset..slots_offset + 4].copy_from_slice(&((PAGE_SIZE - 3) as u32).to_le_bytes());
+        page_data[slots_offset + 4..slots_offset + 8].copy_from_slice(&3u32.to_le_bytes());
+        page_data[PAGE_SIZE - 3..].copy_from_slice(b"old");
+
+        // when
+        let page = TablePage::deserialize(&page_data).unwrap();
+
+        // then
+        assert_eq!(page.get_next_page_id(), None);
+        assert_eq!(page.get_free_space_map_page_id(), None);
+        assert_eq!(page.get_versioned(0).unwrap(), Some((0, &b"old"[..])));
+    }
+}
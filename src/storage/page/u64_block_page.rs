@@ -0,0 +1,246 @@
+use std::convert::TryInto;
+use std::io;
+use std::ops::Range;
+
+use crate::storage::page::page::PAGE_SIZE;
+use crate::storage::page::page_error::PageError;
+use crate::storage::page::page_version::{PageType, PageVersion, PAGE_HEADER_SIZE, PAGE_TYPE_OFFSET, PAGE_VERSION_SIZE, check_page_type, read_page_version};
+
+/// v2: version byte, LSN, page type, occupied bits, readable bits, then packed little-endian key and value pairs.
+/// v1: version byte, occupied bits, readable bits, then packed little-endian key and value pairs.
+const U64_BLOCK_PAGE_VERSION: PageVersion = 2;
+const V1_U64_BLOCK_PAGE_VERSION: PageVersion = 1;
+const SLOT_SIZE: usize = 16;
+/// Two bits of each slot go to the bitmaps, each of which may round up by one byte.
+pub const U64_BLOCK_CAPACITY: usize = 8 * (PAGE_SIZE - PAGE_HEADER_SIZE - 2) / (8 * SLOT_SIZE + 2);
+const BITMAP_SIZE: usize = (U64_BLOCK_CAPACITY - 1) / 8 + 1;
+/// Offsets into the body, which follows the page header.
+const READABLE_OFFSET: usize = BITMAP_SIZE;
+const SLOTS_OFFSET: usize = READABLE_OFFSET + BITMAP_SIZE;
+const _: () = assert!(PAGE_HEADER_SIZE + SLOTS_OFFSET + U64_BLOCK_CAPACITY * SLOT_SIZE <= PAGE_SIZE);
+// v1 blocks hold as many slots, their body starting right after the version byte
+const _: () = assert!(U64_BLOCK_CAPACITY == 8 * (PAGE_SIZE - PAGE_VERSION_SIZE - 2) / (8 * SLOT_SIZE + 2));
+
+/// Outcome of probing a range of slots of a block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Probe {
+    /// Never used slot, where the probe sequence of a key ends.
+    Free(usize),
+    Duplicated(usize),
+    /// Every slot of the range is taken by other pairs or removed ones.
+    Exhausted,
+}
+
+/// Block of a `U64Table`, read and written in place on the page data without any decoding.
+///
+/// A removed pair keeps its slot occupied but no longer readable, so probe sequences passing it stay intact.
+pub struct U64BlockPage<D> {
+    data: D,
+    body_offset: usize,
+}
+
+impl<D: AsRef<[u8]>> U64BlockPage<D> {
+    pub fn from_page(data: D) -> io::Result<U64BlockPage<D>> {
+        let page_data = data.as_ref();
+        if page_data.len() < PAGE_SIZE {
+            return Err(PageError::PageDataTooShort { expected: PAGE_SIZE, actual: page_data.len() }.into());
+        }
+
+        let body_offset = match read_page_version(page_data)? {
+            U64_BLOCK_PAGE_VERSION => {
+                check_page_type(page_data, PageType::U64Block)?;
+                PAGE_HEADER_SIZE
+            },
+            V1_U64_BLOCK_PAGE_VERSION => PAGE_VERSION_SIZE,
+            version => return Err(PageError::UnknownVersion { found: version, supported: U64_BLOCK_PAGE_VERSION }.into()),
+        };
+        Ok(U64BlockPage { data, body_offset })
+    }
+
+    pub fn is_occupied(&self, slot_idx: usize) -> bool {
+        self.bit(0, slot_idx) == 1
+    }
+
+    pub fn is_readable(&self, slot_idx: usize) -> bool {
+        self.bit(READABLE_OFFSET, slot_idx) == 1
+    }
+
+    pub fn key(&self, slot_idx: usize) -> u64 {
+        self.word(SLOTS_OFFSET + slot_idx * SLOT_SIZE)
+    }
+
+    pub fn value(&self, slot_idx: usize) -> u64 {
+        self.word(SLOTS_OFFSET + slot_idx * SLOT_SIZE + 8)
+    }
+
+    /// Looks for where `key`, `value` would go among `slots`. The per slot test has no branches,
+    /// only the loop exit does.
+    pub fn probe(&self, slots: Range<usize>, key: u64, value: u64) -> Probe {
+        for slot_idx in slots {
+            let occupied = self.bit(0, slot_idx);
+            let readable = self.bit(READABLE_OFFSET, slot_idx);
+            let same = ((self.key(slot_idx) == key) & (self.value(slot_idx) == value)) as u8;
+            let free = occupied ^ 1;
+            if free | (readable & same) == 1 {
+                return match free {
+                    1 => Probe::Free(slot_idx),
+                    _ => Probe::Duplicated(slot_idx),
+                };
+            }
+        }
+
+        Probe::Exhausted
+    }
+
+    /// Collects values of `key` among `slots`; `true` once a free slot ends the probe sequence.
+    pub fn collect_values(&self, slots: Range<usize>, key: u64, values: &mut Vec<u64>) -> bool {
+        for slot_idx in slots {
+            if !self.is_occupied(slot_idx) {
+                return true;
+            }
+
+            if self.is_readable(slot_idx) & (self.key(slot_idx) == key) {
+                values.push(self.value(slot_idx));
+            }
+        }
+
+        false
+    }
+
+    /// First slot among `slots` holding a pair of `key`, or else whether a free slot ended the probe
+    /// sequence before it.
+    pub fn find_key(&self, slots: Range<usize>, key: u64) -> (Option<usize>, bool) {
+        for slot_idx in slots {
+            if !self.is_occupied(slot_idx) {
+                return (None, true);
+            }
+
+            if self.is_readable(slot_idx) & (self.key(slot_idx) == key) {
+                return (Some(slot_idx), true);
+            }
+        }
+
+        (None, false)
+    }
+
+    fn bit(&self, bitmap_offset: usize, slot_idx: usize) -> u8 {
+        (self.data.as_ref()[self.body_offset + bitmap_offset + slot_idx / 8] >> (slot_idx % 8)) & 0x01
+    }
+
+    fn word(&self, offset: usize) -> u64 {
+        let offset = self.body_offset + offset;
+        u64::from_le_bytes(self.data.as_ref()[offset..offset + 8].try_into().unwrap())
+    }
+}
+
+impl<D: AsRef<[u8]> + AsMut<[u8]>> U64BlockPage<D> {
+    /// Formats the page as an empty block.
+    pub fn init(mut data: D) -> io::Result<U64BlockPage<D>> {
+        let page_data = data.as_mut();
+        if page_data.len() < PAGE_SIZE {
+            return Err(PageError::PageDataTooShort { expected: PAGE_SIZE, actual: page_data.len() }.into());
+        }
+
+        page_data[..PAGE_HEADER_SIZE + SLOTS_OFFSET].fill(0);
+        page_data[0] = U64_BLOCK_PAGE_VERSION;
+        page_data[PAGE_TYPE_OFFSET] = PageType::U64Block as u8;
+        Ok(U64BlockPage { data, body_offset: PAGE_HEADER_SIZE })
+    }
+
+    pub fn put(&mut self, slot_idx: usize, key: u64, value: u64) {
+        let body_offset = self.body_offset;
+        let offset = body_offset + SLOTS_OFFSET + slot_idx * SLOT_SIZE;
+        let page_data = self.data.as_mut();
+        page_data[offset..offset + 8].copy_from_slice(&key.to_le_bytes());
+        page_data[offset + 8..offset + 16].copy_from_slice(&value.to_le_bytes());
+        page_data[body_offset + slot_idx / 8] |= 0x01 << (slot_idx % 8);
+        page_data[body_offset + READABLE_OFFSET + slot_idx / 8] |= 0x01 << (slot_idx % 8);
+    }
+
+    pub fn remove(&mut self, slot_idx: usize) {
+        let offset = self.body_offset + READABLE_OFFSET + slot_idx / 8;
+        self.data.as_mut()[offset] &= !(0x01 << (slot_idx % 8));
+    }
+
+    /// Removes every pair of `key` among `slots`, returning how many and whether a free slot ended
+    /// the probe sequence.
+    pub fn remove_key(&mut self, slots: Range<usize>, key: u64) -> (usize, bool) {
+        let mut removed = 0;
+        for slot_idx in slots {
+            if !self.is_occupied(slot_idx) {
+                return (removed, true);
+            }
+
+            if self.is_readable(slot_idx) & (self.key(slot_idx) == key) {
+                self.remove(slot_idx);
+                removed += 1;
+            }
+        }
+
+        (removed, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::storage::page::page_error::PageError;
+    use crate::storage::page::page_version::{PAGE_HEADER_SIZE, PAGE_TYPE_OFFSET, PageType};
+    use crate::storage::page::u64_block_page::{Probe, U64BlockPage, U64_BLOCK_CAPACITY, SLOTS_OFFSET, SLOT_SIZE};
+
+    #[test]
+    fn should_fit_packed_slots_into_one_page() {
+        assert_eq!(U64_BLOCK_CAPACITY, 251);
+        assert_eq!(PAGE_HEADER_SIZE + SLOTS_OFFSET + U64_BLOCK_CAPACITY * SLOT_SIZE, PAGE_SIZE - 6);
+    }
+
+    #[test]
+    fn should_probe_past_other_and_removed_pairs() {
+        // given
+        let mut data = [0xff; PAGE_SIZE];
+        let mut block = U64BlockPage::init(&mut data[..]).unwrap();
+        block.put(3, 1, 10);
+        block.put(4, 2, 20);
+        block.put(5, 1, 11);
+        block.remove(5);
+
+        // when
+        let duplicated = block.probe(3..U64_BLOCK_CAPACITY, 2, 20);
+        let free = block.probe(3..U64_BLOCK_CAPACITY, 1, 11);
+
+        // then
+        assert_eq!(duplicated, Probe::Duplicated(4));
+        assert_eq!(free, Probe::Free(6));
+        let mut values = Vec::new();
+        assert!(block.collect_values(3..U64_BLOCK_CAPACITY, 1, &mut values));
+        assert_eq!(values, vec![10]);
+        assert_eq!(block.remove_key(0..U64_BLOCK_CAPACITY, 2), (0, true));
+        assert_eq!(block.remove_key(3..5, 2), (1, false));
+        assert!(!block.is_readable(4));
+    }
+
+    #[test]
+    fn should_read_block_back_from_page_data() {
+        // given
+        let mut data = [0; PAGE_SIZE];
+        U64BlockPage::init(&mut data[..]).unwrap().put(U64_BLOCK_CAPACITY - 1, u64::MAX, 7);
+
+        // when
+        let block = U64BlockPage::from_page(&data[..]).unwrap();
+
+        // then
+        assert!(block.is_readable(U64_BLOCK_CAPACITY - 1));
+        assert_eq!(block.key(U64_BLOCK_CAPACITY - 1), u64::MAX);
+        assert_eq!(block.value(U64_BLOCK_CAPACITY - 1), 7);
+        assert_eq!(block.probe(U64_BLOCK_CAPACITY - 1..U64_BLOCK_CAPACITY, 0, 0), Probe::Exhausted);
+        let mut v1_data = vec![1];
+        v1_data.extend_from_slice(&data[PAGE_HEADER_SIZE..]);
+        v1_data.resize(PAGE_SIZE, 0);
+        assert_eq!(U64BlockPage::from_page(&v1_data[..]).unwrap().value(U64_BLOCK_CAPACITY - 1), 7);
+        let err = U64BlockPage::from_page(&[0; PAGE_SIZE][..]).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::UnknownVersion { found: 0, supported: 2 })));
+        data[PAGE_TYPE_OFFSET] = PageType::HashTableBlock as u8;
+        let err = U64BlockPage::from_page(&data[..]).err().unwrap();
+        assert!(matches!(PageError::from_io(&err), Some(PageError::WrongPageType { expected: PageType::U64Block, .. })));
+    }
+}
@@ -0,0 +1,259 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::{cmp, io, mem};
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::storage::page::overflow_page::{OverflowPage, OverflowRef};
+use crate::storage::page::page::PageId;
+use crate::storage::page::page_error::PageError;
+use crate::storage::table::table_error::TableError;
+use crate::storage::table::value::Value;
+
+/// Large objects, e.g. documents or images, each in a chain of overflow pages over the buffer pool.
+/// A tuple keeps the handle of its object, a `Value::Blob` in a `DataType::Blob` column, instead of
+/// the bytes.
+///
+/// Objects are written through a `LobWriter` and read through a `LobReader` one page at a time, so
+/// no object has to fit into memory as a whole.
+pub struct LobStore {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+}
+
+impl LobStore {
+    pub fn new(bpm: Arc<Mutex<BufferPoolManager>>) -> LobStore {
+        LobStore { buffer_pool_manager: bpm }
+    }
+
+    /// Starts a new object on a page of its own, see `LobWriter::finish`.
+    pub fn writer(&self) -> io::Result<LobWriter> {
+        let first_page_id = write_page(&self.buffer_pool_manager, None, Vec::new(), None)?;
+        Ok(LobWriter {
+            buffer_pool_manager: self.buffer_pool_manager.clone(),
+            first_page_id,
+            page_id: first_page_id,
+            buf: Vec::new(),
+            len: 0,
+            finished: false,
+        })
+    }
+
+    pub fn reader(&self, blob: &Value) -> io::Result<LobReader> {
+        let overflow_ref = overflow_ref_of(blob)?;
+        Ok(LobReader {
+            buffer_pool_manager: self.buffer_pool_manager.clone(),
+            next_page_id: Some(overflow_ref.page_id),
+            page_data: Vec::new(),
+            pos: 0,
+            len: overflow_ref.len,
+            read: 0,
+        })
+    }
+
+    /// Writes the bytes as a new object, returning its handle.
+    pub fn write(&self, data: &[u8]) -> io::Result<Value> {
+        let mut writer = self.writer()?;
+        writer.write_all(data)?;
+        writer.finish()
+    }
+
+    /// Reads the whole object into memory.
+    pub fn read(&self, blob: &Value) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(overflow_ref_of(blob)?.len as usize);
+        self.reader(blob)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Deletes the pages of the object. Its handle is left dangling, so no tuple may keep it.
+    pub fn delete(&self, blob: &Value) -> io::Result<()> {
+        let overflow_ref = overflow_ref_of(blob)?;
+        free_chain(&mut self.buffer_pool_manager.lock().unwrap(), overflow_ref.page_id, overflow_ref.len)
+    }
+}
+
+/// Writes an object a page at a time. A page is written once the next one is started, or by `finish`,
+/// which then holds the object in whole. Dropping an unfinished writer deletes the pages written.
+pub struct LobWriter {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    first_page_id: PageId,
+    page_id: PageId,
+    buf: Vec<u8>,
+    len: u64,
+    finished: bool,
+}
+
+impl LobWriter {
+    /// Writes the last page, returning the handle of the object.
+    pub fn finish(mut self) -> io::Result<Value> {
+        write_page(&self.buffer_pool_manager, Some(self.page_id), mem::take(&mut self.buf), None)?;
+        self.finished = true;
+        Ok(Value::Blob(OverflowRef { page_id: self.first_page_id, len: self.len }))
+    }
+}
+
+impl Write for LobWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.buf.len() == OverflowPage::capacity() {
+            // the full page is linked to the next one first, so that it is written only once
+            let next_page_id = write_page(&self.buffer_pool_manager, None, Vec::new(), None)?;
+            write_page(&self.buffer_pool_manager, Some(self.page_id), mem::take(&mut self.buf), Some(next_page_id))?;
+            self.page_id = next_page_id;
+        }
+
+        let n = cmp::min(buf.len(), OverflowPage::capacity() - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LobWriter {
+    // best effort, pages left behind are only lost space
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = free_chain(&mut self.buffer_pool_manager.lock().unwrap(), self.first_page_id, self.len);
+        }
+    }
+}
+
+/// Reads an object a page at a time, failing if its chain holds fewer or more bytes than its handle says.
+pub struct LobReader {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    next_page_id: Option<PageId>,
+    page_data: Vec<u8>,
+    pos: usize,
+    len: u64,
+    read: u64,
+}
+
+impl LobReader {
+    /// Bytes of the object.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for LobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.page_data.len() {
+            let next_page_id = match self.next_page_id {
+                Some(page_id) => page_id,
+                None if self.read < self.len =>
+                    return Err(PageError::PageDataTooShort { expected: self.len as usize, actual: self.read as usize }.into()),
+                None => return Ok(0),
+            };
+            let page: OverflowPage = self.buffer_pool_manager.lock().unwrap().fetch_decoded(next_page_id)?;
+            self.read += page.get_data().len() as u64;
+            if self.read > self.len {
+                return Err(PageError::ValueTooLarge { size: self.read as usize, capacity: self.len as usize }.into());
+            }
+            self.next_page_id = page.get_next_page_id();
+            self.page_data = page.get_data().to_vec();
+            self.pos = 0;
+        }
+
+        let n = cmp::min(buf.len(), self.page_data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.page_data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn overflow_ref_of(blob: &Value) -> io::Result<OverflowRef> {
+    match blob {
+        Value::Blob(overflow_ref) => Ok(*overflow_ref),
+        _ => Err(TableError::NotBlob { value: blob.to_string() }.into()),
+    }
+}
+
+fn write_page(bpm: &Mutex<BufferPoolManager>, page_id: Option<PageId>, data: Vec<u8>, next_page_id: Option<PageId>) -> io::Result<PageId> {
+    Ok(bpm.lock().unwrap().write_encoded(page_id, &OverflowPage::new(data, next_page_id)?)?)
+}
+
+// a chain never has more pages than its length needs, which also stops at a cycle
+fn free_chain(bpm: &mut BufferPoolManager, first_page_id: PageId, len: u64) -> io::Result<()> {
+    let mut next_page_id = Some(first_page_id);
+    for _ in 0..len as usize / OverflowPage::capacity() + 1 {
+        let page_id = match next_page_id {
+            Some(page_id) => page_id,
+            None => break,
+        };
+        next_page_id = bpm.fetch_decoded::<OverflowPage>(page_id)?.get_next_page_id();
+        bpm.delete_page(page_id)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::storage::page::overflow_page::OverflowPage;
+    use crate::storage::table::lob_store::LobStore;
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::table_error::TableError;
+    use crate::storage::table::tuple::Tuple;
+    use crate::storage::table::value::Value;
+
+    #[test]
+    fn should_stream_object_across_pages_and_keep_its_handle_in_a_tuple() {
+        // given
+        let store = LobStore::new(Arc::new(Mutex::new(BufferPoolManager::new_default(20))));
+        let data: Vec<u8> = (0..3 * OverflowPage::capacity() + 10).map(|i| (i % 251) as u8).collect();
+        let schema = Schema::new(vec![Column::new("id", DataType::Integer), Column::new("photo", DataType::Blob)]);
+
+        // when
+        let mut writer = store.writer().unwrap();
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        let blob = writer.finish().unwrap();
+        let tuple = Tuple::new(&[Value::Integer(1), blob.clone()], &schema).unwrap();
+        let mut reader = store.reader(&tuple.get_value(&schema, 1).unwrap()).unwrap();
+        let mut read = Vec::new();
+        let mut buf = [0; 777];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => read.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        // then
+        assert_eq!(reader.len(), data.len() as u64);
+        assert_eq!(read, data);
+        assert_eq!(store.read(&store.write(&[]).unwrap()).unwrap(), Vec::<u8>::new());
+        assert_eq!(blob.to_string(), format!("<blob of {} bytes>", data.len()));
+    }
+
+    #[test]
+    fn should_delete_pages_of_object_and_of_unfinished_writer() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(20)));
+        let store = LobStore::new(bpm.clone());
+        let blob = store.write(&vec![7; 2 * OverflowPage::capacity()]).unwrap();
+        let mut unfinished = store.writer().unwrap();
+        unfinished.write_all(&vec![8; OverflowPage::capacity() + 1]).unwrap();
+
+        // when
+        store.delete(&blob).unwrap();
+        drop(unfinished);
+        let err = store.read(&Value::Integer(1)).err().unwrap();
+
+        // then
+        assert!((0..5).all(|page_id| bpm.lock().unwrap().pin_count_of(page_id).is_none()));
+        assert!(matches!(TableError::from_io(&err), Some(TableError::NotBlob { value }) if value == "1"));
+    }
+}
@@ -0,0 +1,14 @@
+pub mod lob_store;
+pub mod rid;
+pub mod schema;
+pub mod table_error;
+pub mod table_heap;
+pub mod tuple;
+pub mod value;
+
+pub use lob_store::{LobReader, LobStore, LobWriter};
+pub use rid::Rid;
+pub use schema::{Column, DataType, Schema};
+pub use table_heap::{TableHeap, TableIterator, VacuumStats, VersionChain};
+pub use tuple::{Tuple, TupleHeader, VarcharData, VersionTs, TS_INFINITY};
+pub use value::{ArithmeticOp, CompareOp, Truth, Value};
@@ -0,0 +1,108 @@
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter};
+use std::{fmt, io, mem};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::ValueType;
+use crate::storage::page::page::PageId;
+use crate::storage::table::table_error::TableError;
+
+/// Where a tuple sits in a table heap: its page and its slot there. Indexes store it as the value
+/// of a key to point at the tuple. Ordered by page first, the order of a sequential scan.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Rid {
+    pub page_id: PageId,
+    pub slot: u32,
+}
+
+impl ValueType for Rid {
+    const ENCODED_SIZE: usize = Rid::SIZE;
+}
+
+impl Rid {
+    /// Bytes of `to_bytes`, the page id as u64 and the slot.
+    pub const SIZE: usize = mem::size_of::<u64>() + mem::size_of::<u32>();
+
+    pub fn new(page_id: PageId, slot: u32) -> Rid {
+        Rid { page_id, slot }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Rid::SIZE] {
+        let mut bytes = [0; Rid::SIZE];
+        bytes[..8].copy_from_slice(&(self.page_id as u64).to_le_bytes());
+        bytes[8..].copy_from_slice(&self.slot.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Rid::SIZE]) -> Rid {
+        Rid {
+            page_id: u64::from_le_bytes(bytes[..8].try_into().unwrap()) as PageId,
+            slot: u32::from_le_bytes(bytes[8..].try_into().unwrap()),
+        }
+    }
+
+    /// Packs the rid into one u64, page id in the upper half, for indexes with u64 values like
+    /// `U64Table`. Fails with `TableError::PageIdTooLarge` for a page id not fitting 32 bits.
+    pub fn to_u64(&self) -> io::Result<u64> {
+        if self.page_id > u32::MAX as PageId {
+            return Err(TableError::PageIdTooLarge { page_id: self.page_id }.into());
+        }
+
+        Ok(((self.page_id as u64) << 32) | self.slot as u64)
+    }
+
+    pub fn from_u64(packed: u64) -> Rid {
+        Rid { page_id: (packed >> 32) as PageId, slot: packed as u32 }
+    }
+}
+
+impl Display for Rid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.page_id, self.slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::container::hash::hash_table::HashTable;
+    use crate::container::hash::linear_probe_hash_table::LinearProbeHashTable;
+    use crate::storage::table::rid::Rid;
+    use crate::storage::table::table_error::TableError;
+
+    #[test]
+    fn should_pack_and_order_rids() {
+        // given
+        let rid = Rid::new(70_000, 3);
+
+        // when
+        let from_bytes = Rid::from_bytes(&rid.to_bytes());
+        let from_u64 = Rid::from_u64(rid.to_u64().unwrap());
+        let err = Rid::new(1 << 40, 0).to_u64().err().unwrap();
+
+        // then
+        assert_eq!((from_bytes, from_u64), (rid, rid));
+        assert!(Rid::new(1, 9) < Rid::new(2, 0) && Rid::new(2, 0) < Rid::new(2, 1));
+        assert_eq!(rid.to_string(), "(70000, 3)");
+        assert!(matches!(TableError::from_io(&err), Some(TableError::PageIdTooLarge { .. })));
+    }
+
+    #[test]
+    fn should_store_rids_as_index_values() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut index = LinearProbeHashTable::<u64, Rid>::new(2, bpm, |k: &u64| *k).unwrap();
+
+        // when
+        index.insert(&1, &Rid::new(5, 0)).unwrap();
+        index.insert(&1, &Rid::new(5, 1)).unwrap();
+        index.insert(&2, &Rid::new(6, 0)).unwrap();
+
+        // then
+        assert_eq!(index.get_value(&1).unwrap(), vec![Rid::new(5, 0), Rid::new(5, 1)]);
+        assert_eq!(index.get_value(&2).unwrap(), vec![Rid::new(6, 0)]);
+    }
+}
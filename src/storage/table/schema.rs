@@ -0,0 +1,268 @@
+use std::{io, slice};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::table::table_error::TableError;
+use crate::storage::table::tuple::Tuple;
+use crate::storage::table::value::Value;
+
+/// Type of a column. A varchar holds at most the given number of bytes of UTF-8, a decimal keeps
+/// the given number of digits after the point.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DataType {
+    Boolean,
+    Integer,
+    BigInt,
+    Varchar(u32),
+    /// Up to 18 digits in all, kept as an unscaled `i64`.
+    Decimal(u8),
+    /// Microseconds since the Unix epoch, in UTC.
+    Timestamp,
+    /// Handle of a large object kept by a `LobStore`.
+    Blob,
+}
+
+impl DataType {
+    /// Bytes the type takes in the fixed-size part of a tuple. A varchar keeps the offset and length
+    /// of its data there, the data itself follows the fixed-size part.
+    pub fn fixed_size(&self) -> usize {
+        match self {
+            DataType::Boolean => 1,
+            DataType::Integer => 4,
+            DataType::BigInt | DataType::Decimal(_) | DataType::Timestamp => 8,
+            DataType::Varchar(_) => 8,
+            DataType::Blob => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Column {
+    name: String,
+    data_type: DataType,
+    nullable: bool,
+    default: Option<Value>,
+}
+
+impl Column {
+    /// Column not taking nulls and without a default, see `with_nullable` and `with_default`.
+    pub fn new(name: &str, data_type: DataType) -> Column {
+        Column { name: name.to_string(), data_type, nullable: false, default: None }
+    }
+
+    pub fn with_nullable(mut self, nullable: bool) -> Column {
+        self.nullable = nullable;
+        self
+    }
+
+    /// Value the column takes when a tuple is made without one for it, see `Schema::fill_defaults`.
+    pub fn with_default(mut self, default: Value) -> Column {
+        self.default = Some(default);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn is_nullable(&self) -> bool {
+        self.nullable
+    }
+
+    pub fn default(&self) -> Option<&Value> {
+        self.default.as_ref()
+    }
+
+    /// The value if there is one, else the default, else a null. Fails with `TableError::MissingValue`
+    /// if that leaves a null for a column not taking nulls.
+    pub fn value_or_default(&self, value: Option<Value>) -> io::Result<Value> {
+        match value.or_else(|| self.default.clone()) {
+            Some(value) => Ok(value),
+            None if self.nullable => Ok(Value::Null),
+            None => Err(TableError::MissingValue { column: self.name.clone() }.into()),
+        }
+    }
+}
+
+/// Columns of the tuples of a table, in order, and where each field sits in a tuple: after a bitmap
+/// of the null fields, every column at a fixed offset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Column>", into = "Vec<Column>")]
+pub struct Schema {
+    columns: Vec<Column>,
+    offsets: Vec<usize>,
+    fixed_size: usize,
+}
+
+impl Schema {
+    pub fn new(columns: Vec<Column>) -> Schema {
+        let mut offset = Schema::null_bitmap_size_of(columns.len());
+        let offsets = columns.iter().map(|column| {
+            let column_offset = offset;
+            offset += column.data_type.fixed_size();
+            column_offset
+        }).collect();
+        Schema { columns, offsets, fixed_size: offset }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn column(&self, idx: usize) -> Option<&Column> {
+        self.columns.get(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    /// Offset of the field of column `idx` in a tuple.
+    pub fn offset_of(&self, idx: usize) -> usize {
+        self.offsets[idx]
+    }
+
+    /// Bytes of a tuple before its varchar data: the null bitmap and every field.
+    pub fn fixed_size(&self) -> usize {
+        self.fixed_size
+    }
+
+    /// Bytes of the largest tuple: the fixed part plus every varchar at its maximum length.
+    pub fn max_tuple_size(&self) -> usize {
+        self.fixed_size + self.columns.iter()
+            .map(|column| match column.data_type {
+                DataType::Varchar(max) => max as usize,
+                _ => 0,
+            })
+            .sum::<usize>()
+    }
+
+    /// Schema of the columns at `indices`, in that order, e.g. of the key of an index. Fails with
+    /// `TableError::ColumnOutOfRange` for an index past the columns.
+    pub fn project(&self, indices: &[usize]) -> io::Result<Schema> {
+        let columns = indices.iter()
+            .map(|idx| self.columns.get(*idx).cloned()
+                .ok_or(TableError::ColumnOutOfRange { idx: *idx, num_columns: self.columns.len() }))
+            .collect::<Result<Vec<Column>, TableError>>()?;
+        Ok(Schema::new(columns))
+    }
+
+    /// Values of a tuple given by column, with the ones missing filled in by `Column::value_or_default`.
+    pub fn fill_defaults(&self, values: Vec<Option<Value>>) -> io::Result<Vec<Value>> {
+        if values.len() != self.columns.len() {
+            return Err(TableError::ColumnCountMismatch { expected: self.columns.len(), found: values.len() }.into());
+        }
+        values.into_iter().zip(&self.columns).map(|(value, column)| column.value_or_default(value)).collect()
+    }
+
+    /// Fails with a `TableError` if the default of a column is not of its type, or is null for a column
+    /// not taking nulls, just as a tuple holding it would.
+    pub fn check_defaults(&self) -> io::Result<()> {
+        for column in &self.columns {
+            if let Some(default) = &column.default {
+                Tuple::new(slice::from_ref(default), &Schema::new(vec![column.clone()]))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn null_bitmap_size(&self) -> usize {
+        Schema::null_bitmap_size_of(self.columns.len())
+    }
+
+    fn null_bitmap_size_of(num_columns: usize) -> usize {
+        num_columns.div_ceil(8)
+    }
+}
+
+impl From<Vec<Column>> for Schema {
+    fn from(columns: Vec<Column>) -> Schema {
+        Schema::new(columns)
+    }
+}
+
+impl From<Schema> for Vec<Column> {
+    fn from(schema: Schema) -> Vec<Column> {
+        schema.columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::table_error::TableError;
+    use crate::storage::table::value::Value;
+
+    #[test]
+    fn should_lay_out_fields_after_null_bitmap_and_keep_layout_through_serde() {
+        // given
+        let columns = (0..9).map(|i| Column::new(&format!("c{}", i), DataType::Integer))
+            .chain([
+                Column::new("name", DataType::Varchar(32)).with_nullable(true),
+                Column::new("flag", DataType::Boolean).with_default(Value::Boolean(false)),
+            ])
+            .collect();
+
+        // when
+        let schema = Schema::new(columns);
+        let deser_schema: Schema = bincode::deserialize(&bincode::serialize(&schema).unwrap()).unwrap();
+
+        // then
+        assert_eq!(schema.null_bitmap_size(), 2);
+        assert_eq!((schema.offset_of(0), schema.offset_of(9), schema.offset_of(10)), (2, 38, 46));
+        assert_eq!(schema.fixed_size(), 47);
+        assert_eq!(schema.index_of("name"), Some(9));
+        assert!(schema.column(9).unwrap().is_nullable() && !schema.column(10).unwrap().is_nullable());
+        assert_eq!(deser_schema, schema);
+    }
+
+    #[test]
+    fn should_fill_missing_values_with_defaults_or_nulls() {
+        // given
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Integer),
+            Column::new("status", DataType::Varchar(8)).with_default(Value::Varchar("new".to_string())),
+            Column::new("note", DataType::Varchar(32)).with_nullable(true),
+        ]);
+        let bad_default = Schema::new(vec![Column::new("flag", DataType::Boolean).with_default(Value::Integer(1))]);
+
+        // when
+        let filled = schema.fill_defaults(vec![Some(Value::Integer(1)), None, None]).unwrap();
+        let missing = schema.fill_defaults(vec![None, Some(Value::Varchar("done".to_string())), None]).err().unwrap();
+
+        // then
+        assert_eq!(filled, [Value::Integer(1), Value::Varchar("new".to_string()), Value::Null]);
+        assert!(matches!(TableError::from_io(&missing), Some(TableError::MissingValue { column }) if column == "id"));
+        assert!(schema.check_defaults().is_ok());
+        let err = bad_default.check_defaults().err().unwrap();
+        assert!(matches!(TableError::from_io(&err), Some(TableError::TypeMismatch { column, .. }) if column == "flag"));
+    }
+
+    #[test]
+    fn should_project_columns_into_key_schema() {
+        // given
+        let schema = Schema::new(vec![Column::new("id", DataType::BigInt), Column::new("name", DataType::Varchar(32)), Column::new("age", DataType::Integer)]);
+
+        // when
+        let key_schema = schema.project(&[2, 1]).unwrap();
+        let err = schema.project(&[3]).err().unwrap();
+
+        // then
+        let names: Vec<&str> = key_schema.columns().iter().map(Column::name).collect();
+        assert_eq!(names, ["age", "name"]);
+        assert_eq!(key_schema.max_tuple_size(), 1 + 4 + 8 + 32);
+        assert!(matches!(TableError::from_io(&err), Some(TableError::ColumnOutOfRange { idx: 3, num_columns: 3 })));
+    }
+}
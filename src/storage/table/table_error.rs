@@ -0,0 +1,94 @@
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+use crate::common::error::cause_of;
+use crate::storage::page::page::PageId;
+use crate::storage::table::rid::Rid;
+use crate::storage::table::schema::DataType;
+
+/// Failures of encoding or decoding records, carried inside `io::Error` so callers can match on them.
+#[derive(Debug)]
+pub enum TableError {
+    ColumnCountMismatch { expected: usize, found: usize },
+    ColumnOutOfRange { idx: usize, num_columns: usize },
+    TypeMismatch { column: String, expected: DataType },
+    NullNotAllowed { column: String },
+    /// No value was given for a column that takes no nulls and has no default.
+    MissingValue { column: String },
+    /// A varchar longer than its column allows, in bytes.
+    ValueTooLong { column: String, len: usize, max: usize },
+    /// The tuple data ends before the fields the schema says it holds.
+    TupleTooShort { expected: usize, actual: usize },
+    InvalidUtf8 { column: String },
+    /// The varchar is stored in overflow pages, which only its table reads back.
+    VarcharOutOfLine { column: String },
+    /// A rid whose page id does not fit into the bits a packed rid keeps for it.
+    PageIdTooLarge { page_id: PageId },
+    /// A tuple larger than an empty table page takes.
+    TupleTooLarge { size: usize, max: usize },
+    /// The rid points to a deleted tuple.
+    TupleNotFound { rid: Rid },
+    /// The value has no counterpart of the type, see `Value::cast_to`.
+    InvalidCast { value: String, to: DataType },
+    NotComparable { left: String, right: String },
+    /// The operator takes no values of the types of its operands.
+    InvalidOperands { op: String, operands: String },
+    /// The result of the operator does not fit its type.
+    Overflow { op: String },
+    DivisionByZero,
+    /// The version was replaced or ended already, or begins after the timestamp it is to end at.
+    VersionConflict { rid: Rid },
+    /// A `LobStore` was handed a value other than a blob handle.
+    NotBlob { value: String },
+}
+
+impl TableError {
+    pub fn from_io(err: &io::Error) -> Option<&TableError> {
+        cause_of(err)
+    }
+}
+
+impl Display for TableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::ColumnCountMismatch { expected, found } =>
+                write!(f, "Schema has {} columns, got {} values.", expected, found),
+            TableError::ColumnOutOfRange { idx, num_columns } =>
+                write!(f, "Column {} out of range, schema has {} columns.", idx, num_columns),
+            TableError::TypeMismatch { column, expected } =>
+                write!(f, "Column {} takes values of type {:?}.", column, expected),
+            TableError::NullNotAllowed { column } => write!(f, "Column {} is not nullable.", column),
+            TableError::MissingValue { column } => write!(f, "Column {} is not nullable and has no default, a value is required.", column),
+            TableError::ValueTooLong { column, len, max } =>
+                write!(f, "Value of {} bytes is too long for column {}, at most {} allowed.", len, column, max),
+            TableError::TupleTooShort { expected, actual } =>
+                write!(f, "Tuple data too short, expected at least {} bytes, got {}.", expected, actual),
+            TableError::InvalidUtf8 { column } => write!(f, "Varchar of column {} is not valid UTF-8.", column),
+            TableError::VarcharOutOfLine { column } => write!(f, "Varchar of column {} is stored out of line.", column),
+            TableError::PageIdTooLarge { page_id } => write!(f, "Page id {} too large to pack into a rid.", page_id),
+            TableError::TupleTooLarge { size, max } =>
+                write!(f, "Tuple of {} bytes too large for a table page, at most {} allowed.", size, max),
+            TableError::TupleNotFound { rid } => write!(f, "No tuple at rid {}.", rid),
+            TableError::InvalidCast { value, to } => write!(f, "Cannot cast {} to {:?}.", value, to),
+            TableError::NotComparable { left, right } => write!(f, "Cannot compare {} with {}.", left, right),
+            TableError::InvalidOperands { op, operands } => write!(f, "Cannot apply {} to {}.", op, operands),
+            TableError::Overflow { op } => write!(f, "Result of {} out of range.", op),
+            TableError::DivisionByZero => write!(f, "Division by zero."),
+            TableError::VersionConflict { rid } => write!(f, "Version at rid {} cannot be ended.", rid),
+            TableError::NotBlob { value } => write!(f, "{} is not a blob.", value),
+        }
+    }
+}
+
+impl error::Error for TableError {}
+
+impl From<TableError> for io::Error {
+    fn from(e: TableError) -> Self {
+        let kind = match e {
+            TableError::TupleTooShort { .. } | TableError::InvalidUtf8 { .. } => io::ErrorKind::InvalidData,
+            TableError::TupleNotFound { .. } => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
@@ -0,0 +1,794 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::storage::page::free_space_map_page::{FreeSpaceMapPage, FREE_SPACE_MAP_CAPACITY};
+use crate::storage::page::overflow_page::{read_chain, OverflowPage, OverflowRef};
+use crate::storage::page::page::PageId;
+use crate::storage::page::page_serde::PageSerde;
+use crate::storage::page::table_page::TablePage;
+use crate::storage::table::rid::Rid;
+use crate::storage::table::table_error::TableError;
+use crate::storage::table::tuple::{Tuple, TupleHeader, VersionTs, TS_INFINITY};
+
+/// Tuples of a table, kept in a chain of slotted `TablePage`s over the buffer pool. A tuple is
+/// addressed by its `Rid`, which stays valid until the tuple is deleted or relocated by an update.
+///
+/// The id of the first page is all that is needed to open the heap again. New tuples go to a page the
+/// free space map knows to have room, else to the last page, a new page is linked to the chain once it
+/// is full. The map is kept in `FreeSpaceMapPage`s chained from the first page, so it outlives the
+/// heap being closed. A heap written before that has no map until it is next vacuumed.
+///
+/// Every tuple is tagged with the schema version it is written with, see `set_schema_version`, so
+/// tuples written before a column was added can be told apart.
+///
+/// Every tuple also has a `TupleHeader`, so that a tuple can be kept as a chain of versions, see
+/// `install_version` and `versions`. Each version is a tuple of the heap under its own rid.
+pub struct TableHeap {
+    buffer_pool_manager: Arc<Mutex<BufferPoolManager>>,
+    first_page_id: PageId,
+    last_page_id: PageId,
+    schema_version: u32,
+    free_space: HeapFreeSpace,
+}
+
+/// Work done by `TableHeap::vacuum`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    /// Pages of the heap before the vacuum.
+    pub pages_scanned: usize,
+    /// Pages left without tuples, unlinked from the heap and deallocated.
+    pub pages_freed: usize,
+    /// Slots of deleted tuples dropped from the pages kept.
+    pub slots_reclaimed: usize,
+}
+
+impl TableHeap {
+    /// Writes the first page of an empty heap.
+    pub fn new(bpm: Arc<Mutex<BufferPoolManager>>) -> io::Result<TableHeap> {
+        let first_page_id = bpm.lock().unwrap().write_encoded(None, &TablePage::new())?;
+        let mut free_space = HeapFreeSpace::new(first_page_id);
+        free_space.set(&mut bpm.lock().unwrap(), first_page_id, TablePage::new().free_space())?;
+        Ok(TableHeap { buffer_pool_manager: bpm, first_page_id, last_page_id: first_page_id, schema_version: 0, free_space })
+    }
+
+    /// Heap created before with its first page on page `first_page_id`, following the chain to its
+    /// last page and loading its free space map.
+    pub fn open(bpm: Arc<Mutex<BufferPoolManager>>, first_page_id: PageId) -> io::Result<TableHeap> {
+        let mut last_page_id = first_page_id;
+        let free_space = {
+            let mut bpm = bpm.lock().unwrap();
+            let first_page: TablePage = bpm.fetch_decoded(first_page_id)?;
+            let free_space = HeapFreeSpace::open(&mut bpm, first_page_id, first_page.get_free_space_map_page_id())?;
+            let mut next_page_id = first_page.get_next_page_id();
+            while let Some(page_id) = next_page_id {
+                last_page_id = page_id;
+                next_page_id = bpm.fetch_decoded::<TablePage>(page_id)?.get_next_page_id();
+            }
+            free_space
+        };
+        Ok(TableHeap { buffer_pool_manager: bpm, first_page_id, last_page_id, schema_version: 0, free_space })
+    }
+
+    pub fn first_page_id(&self) -> PageId {
+        self.first_page_id
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Version tagged on tuples inserted or updated from now on, 0 until set.
+    pub fn set_schema_version(&mut self, schema_version: u32) {
+        self.schema_version = schema_version
+    }
+
+    /// Fails with `TableError::TupleTooLarge` if the tuple does not fit into an empty page.
+    pub fn insert_tuple(&mut self, tuple: &Tuple) -> io::Result<Rid> {
+        self.insert_with_header(tuple, TupleHeader::default())
+    }
+
+    /// Inserts the first version of a tuple, visible from `begin_ts` on.
+    pub fn insert_version(&mut self, tuple: &Tuple, begin_ts: VersionTs) -> io::Result<Rid> {
+        self.insert_with_header(tuple, TupleHeader::new(begin_ts, None))
+    }
+
+    /// Inserts `tuple` as the version replacing the one at `prev`, visible from `ts` on, and ends
+    /// `prev` at `ts`. The new version is inserted first, so a failure leaves `prev` as it was. Fails
+    /// with `TableError::TupleNotFound` if `prev` was deleted, or with `VersionConflict` if it was
+    /// ended already or begins after `ts`.
+    pub fn install_version(&mut self, prev: Rid, tuple: &Tuple, ts: VersionTs) -> io::Result<Rid> {
+        let prev_header = self.endable_header(prev, ts)?;
+        let rid = self.insert_with_header(tuple, TupleHeader::new(ts, Some(prev)))?;
+        self.set_header(prev, TupleHeader { end_ts: ts, ..prev_header })?;
+        Ok(rid)
+    }
+
+    /// Ends the version at `ts` without a successor, which deletes the tuple for readers from `ts` on.
+    /// Fails like `install_version`.
+    pub fn end_version(&mut self, rid: Rid, ts: VersionTs) -> io::Result<()> {
+        let header = self.endable_header(rid, ts)?;
+        self.set_header(rid, TupleHeader { end_ts: ts, ..header })
+    }
+
+    /// `None` if the tuple was deleted.
+    pub fn get_header(&self, rid: Rid) -> io::Result<Option<TupleHeader>> {
+        let page: TablePage = self.bpm().fetch_decoded(rid.page_id)?;
+        page.get_header(rid.slot)
+    }
+
+    /// Versions of a tuple, newest first, from the one at `rid` back along the previous versions.
+    /// Stops at a version that was deleted.
+    pub fn versions(&self, rid: Rid) -> VersionChain<'_> {
+        VersionChain { heap: self, next: Some(rid) }
+    }
+
+    /// Version visible at `ts` of the tuple whose newest version is at `rid`, `None` if there is none.
+    pub fn get_visible(&self, rid: Rid, ts: VersionTs) -> io::Result<Option<(Rid, Tuple)>> {
+        for version in self.versions(rid) {
+            let (rid, header, tuple) = version?;
+            if header.is_visible_at(ts) {
+                return Ok(Some((rid, tuple)));
+            }
+            if header.begin_ts <= ts {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    fn endable_header(&self, rid: Rid, ts: VersionTs) -> io::Result<TupleHeader> {
+        let header = self.get_header(rid)?.ok_or(TableError::TupleNotFound { rid })?;
+        if header.end_ts != TS_INFINITY || ts < header.begin_ts {
+            return Err(TableError::VersionConflict { rid }.into());
+        }
+        Ok(header)
+    }
+
+    fn set_header(&mut self, rid: Rid, header: TupleHeader) -> io::Result<()> {
+        let mut bpm = self.bpm();
+        let mut page: TablePage = bpm.fetch_decoded(rid.page_id)?;
+        if !page.set_header(rid.slot, header)? {
+            return Err(TableError::TupleNotFound { rid }.into());
+        }
+        bpm.write_encoded(Some(rid.page_id), &page)?;
+        Ok(())
+    }
+
+    fn insert_with_header(&mut self, tuple: &Tuple, header: TupleHeader) -> io::Result<Rid> {
+        let max = TablePage::max_tuple_size();
+        if tuple.size() > max {
+            return Err(TableError::TupleTooLarge { size: tuple.size(), max }.into());
+        }
+
+        let mut bpm = self.buffer_pool_manager.lock().unwrap();
+        let required_space = TablePage::required_space(tuple.size());
+        if let Some(page_id) = self.free_space.find_page_with(required_space).filter(|page_id| *page_id != self.last_page_id) {
+            let mut page: TablePage = bpm.fetch_decoded(page_id)?;
+            if let Some(slot) = page.insert_with_header(tuple.data(), self.schema_version, header) {
+                bpm.write_encoded(Some(page_id), &page)?;
+                self.free_space.set(&mut bpm, page_id, page.free_space())?;
+                return Ok(Rid::new(page_id, slot));
+            }
+            self.free_space.set(&mut bpm, page_id, page.free_space())?;
+        }
+
+        let mut last_page: TablePage = bpm.fetch_decoded(self.last_page_id)?;
+        if let Some(slot) = last_page.insert_with_header(tuple.data(), self.schema_version, header) {
+            bpm.write_encoded(Some(self.last_page_id), &last_page)?;
+            self.free_space.set(&mut bpm, self.last_page_id, last_page.free_space())?;
+            return Ok(Rid::new(self.last_page_id, slot));
+        }
+
+        let mut new_page = TablePage::new();
+        let slot = new_page.insert_with_header(tuple.data(), self.schema_version, header).expect("tuple fits into an empty page");
+        let new_page_id = bpm.write_encoded(None, &new_page)?;
+        last_page.set_next_page_id(Some(new_page_id));
+        bpm.write_encoded(Some(self.last_page_id), &last_page)?;
+        self.free_space.set(&mut bpm, self.last_page_id, last_page.free_space())?;
+        self.free_space.set(&mut bpm, new_page_id, new_page.free_space())?;
+        self.last_page_id = new_page_id;
+        Ok(Rid::new(new_page_id, slot))
+    }
+
+    /// Sequential scan of the tuples in rid order, deleted ones skipped.
+    pub fn iter(&self) -> TableIterator<'_> {
+        TableIterator { heap: self, next_page_id: Some(self.first_page_id), page: None, slot: 0, ahead: VecDeque::new() }
+    }
+
+    /// `None` if the tuple was deleted.
+    pub fn get_tuple(&self, rid: Rid) -> io::Result<Option<Tuple>> {
+        Ok(self.get_versioned_tuple(rid)?.map(|(_, tuple)| tuple))
+    }
+
+    /// Tuple with the schema version it was written with, `None` if it was deleted.
+    pub fn get_versioned_tuple(&self, rid: Rid) -> io::Result<Option<(u32, Tuple)>> {
+        let page: TablePage = self.bpm().fetch_decoded(rid.page_id)?;
+        Ok(page.get_versioned(rid.slot)?.map(|(schema_version, data)| (schema_version, Tuple::from_bytes(data.to_vec()))))
+    }
+
+    /// Returns whether there was a tuple to delete.
+    pub fn delete_tuple(&mut self, rid: Rid) -> io::Result<bool> {
+        let mut bpm = self.buffer_pool_manager.lock().unwrap();
+        let mut page: TablePage = bpm.fetch_decoded(rid.page_id)?;
+        if !page.remove(rid.slot)? {
+            return Ok(false);
+        }
+        bpm.write_encoded(Some(rid.page_id), &page)?;
+        self.free_space.set(&mut bpm, rid.page_id, page.free_space())?;
+        Ok(true)
+    }
+
+    /// Replaces the tuple in place if its page has room for the new one, otherwise moves it: the new
+    /// tuple is inserted before the old one is deleted, so a failed update leaves the old tuple. Returns
+    /// the rid the tuple is at now, and fails with `TableError::TupleNotFound` if it was deleted.
+    pub fn update_tuple(&mut self, rid: Rid, tuple: &Tuple) -> io::Result<Rid> {
+        {
+            let mut bpm = self.buffer_pool_manager.lock().unwrap();
+            let mut page: TablePage = bpm.fetch_decoded(rid.page_id)?;
+            if page.get(rid.slot)?.is_none() {
+                return Err(TableError::TupleNotFound { rid }.into());
+            }
+            if page.update(rid.slot, tuple.data(), self.schema_version)? {
+                bpm.write_encoded(Some(rid.page_id), &page)?;
+                self.free_space.set(&mut bpm, rid.page_id, page.free_space())?;
+                return Ok(rid);
+            }
+        }
+
+        let new_rid = self.insert_tuple(tuple)?;
+        self.delete_tuple(rid)?;
+        Ok(new_rid)
+    }
+
+    /// Compacts every page, see `TablePage::compact`, and deallocates the pages left without slots,
+    /// but for the first page, which the heap is opened from. The free space map is built anew from
+    /// the pages kept. Rids of tuples left stay valid.
+    ///
+    /// Slots dropped are handed out again, so nothing may refer to a deleted tuple by its rid anymore,
+    /// which the indexes of a table written through the catalog never do. The slot of a deleted
+    /// version is kept though as long as a tuple left still has it as its previous version, so a
+    /// `VersionChain` stops there rather than following it to another tuple.
+    pub fn vacuum(&mut self) -> io::Result<VacuumStats> {
+        let prev_versions = self.prev_versions()?;
+        let mut bpm = self.buffer_pool_manager.lock().unwrap();
+        let mut stats = VacuumStats::default();
+        let mut free_space = Vec::new();
+        // last page kept, relinked to the page after each page deallocated
+        let mut kept: Option<(PageId, TablePage)> = None;
+        let mut next_page_id = Some(self.first_page_id);
+        while let Some(page_id) = next_page_id {
+            let mut page: TablePage = bpm.fetch_decoded(page_id)?;
+            next_page_id = page.get_next_page_id();
+            stats.pages_scanned += 1;
+
+            let slots_reclaimed = page.compact(|slot| prev_versions.contains(&Rid::new(page_id, slot)));
+            if let (0, Some((kept_page_id, kept_page))) = (page.num_slots(), kept.as_mut()) {
+                kept_page.set_next_page_id(next_page_id);
+                bpm.write_encoded(Some(*kept_page_id), kept_page)?;
+                if page_id == self.last_page_id {
+                    self.last_page_id = *kept_page_id;
+                }
+                bpm.delete_page(page_id)?;
+                stats.pages_freed += 1;
+                continue;
+            }
+
+            if slots_reclaimed > 0 {
+                bpm.write_encoded(Some(page_id), &page)?;
+                stats.slots_reclaimed += slots_reclaimed;
+            }
+            free_space.push((page_id, page.free_space()));
+            kept = Some((page_id, page));
+        }
+
+        self.free_space.rebuild(&mut bpm, &free_space)?;
+        Ok(stats)
+    }
+
+    /// Rids the tuples of the heap have as their previous version.
+    fn prev_versions(&self) -> io::Result<HashSet<Rid>> {
+        let mut bpm = self.bpm();
+        let mut prev_versions = HashSet::new();
+        let mut next_page_id = Some(self.first_page_id);
+        while let Some(page_id) = next_page_id {
+            let page: TablePage = bpm.fetch_decoded(page_id)?;
+            next_page_id = page.get_next_page_id();
+            for slot in 0..page.num_slots() as u32 {
+                if let Some(prev_version) = page.get_header(slot)?.and_then(|header| header.prev_version) {
+                    prev_versions.insert(prev_version);
+                }
+            }
+        }
+        Ok(prev_versions)
+    }
+
+    /// Writes the bytes to a new chain of overflow pages, last page first so each page is written once
+    /// along with its successor.
+    pub fn write_overflow(&mut self, data: &[u8]) -> io::Result<OverflowRef> {
+        let mut bpm = self.bpm();
+        let mut next_page_id = None;
+        for chunk in data.chunks(OverflowPage::capacity()).rev() {
+            next_page_id = Some(bpm.write_encoded(None, &OverflowPage::new(chunk.to_vec(), next_page_id)?)?);
+        }
+        let page_id = match next_page_id {
+            Some(page_id) => page_id,
+            None => bpm.write_encoded(None, &OverflowPage::new(Vec::new(), None)?)?,
+        };
+        Ok(OverflowRef { page_id, len: data.len() as u64 })
+    }
+
+    pub fn read_overflow(&self, overflow_ref: &OverflowRef) -> io::Result<Vec<u8>> {
+        let mut bpm = self.bpm();
+        read_chain(overflow_ref, |page_id| Ok(bpm.fetch_decoded(page_id)?))
+    }
+
+    /// Deletes the pages of an overflow chain no tuple refers to anymore.
+    pub fn free_overflow(&mut self, overflow_ref: &OverflowRef) -> io::Result<()> {
+        let mut bpm = self.bpm();
+        read_chain(overflow_ref, |page_id| {
+            let overflow_page = bpm.fetch_decoded(page_id)?;
+            bpm.delete_page(page_id)?;
+            Ok(overflow_page)
+        })?;
+        Ok(())
+    }
+
+    /// Deletes the pages of the heap and of its free space map. Overflow chains are left, as the tuples
+    /// referring to them may have been copied elsewhere.
+    pub fn drop_heap(self) -> io::Result<()> {
+        let mut bpm = self.bpm();
+        for (map_page_id, _) in self.free_space.map_pages.values() {
+            bpm.delete_page(*map_page_id)?;
+        }
+        let mut next_page_id = Some(self.first_page_id);
+        while let Some(page_id) = next_page_id {
+            next_page_id = bpm.fetch_decoded::<TablePage>(page_id)?.get_next_page_id();
+            bpm.delete_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    fn bpm(&self) -> MutexGuard<'_, BufferPoolManager> {
+        self.buffer_pool_manager.lock().unwrap()
+    }
+}
+
+/// Free bytes of the pages of a heap, in `FreeSpaceMapPage`s each covering the run of page ids its first
+/// page id starts. The map pages are chained from the first page of the heap, in the order they were
+/// added, and each is written as soon as a category of it changes.
+struct HeapFreeSpace {
+    heap_page_id: PageId,
+    /// Map pages with their page id, by the first page id they cover.
+    map_pages: BTreeMap<PageId, (PageId, FreeSpaceMapPage)>,
+    last_map_page_id: Option<PageId>,
+}
+
+impl HeapFreeSpace {
+    fn new(heap_page_id: PageId) -> HeapFreeSpace {
+        HeapFreeSpace { heap_page_id, map_pages: BTreeMap::new(), last_map_page_id: None }
+    }
+
+    /// Map of the heap with its first page on `heap_page_id`, read from the chain of map pages starting
+    /// at `map_page_id`.
+    fn open(bpm: &mut BufferPoolManager, heap_page_id: PageId, map_page_id: Option<PageId>) -> io::Result<HeapFreeSpace> {
+        let mut free_space = HeapFreeSpace::new(heap_page_id);
+        let mut next_page_id = map_page_id;
+        while let Some(map_page_id) = next_page_id {
+            let map_page: FreeSpaceMapPage = bpm.fetch_decoded(map_page_id)?;
+            next_page_id = map_page.get_next_page_id();
+            free_space.last_map_page_id = Some(map_page_id);
+            free_space.map_pages.insert(map_page.get_first_page_id(), (map_page_id, map_page));
+        }
+        Ok(free_space)
+    }
+
+    fn set(&mut self, bpm: &mut BufferPoolManager, page_id: PageId, free_bytes: usize) -> io::Result<()> {
+        let (map_page_id, map_page) = self.map_page_of(bpm, page_id)?;
+        let old_free_bytes = map_page.get_free_space(page_id)?;
+        map_page.set_free_space(page_id, free_bytes)?;
+        if map_page.get_free_space(page_id)? != old_free_bytes {
+            bpm.write_encoded(Some(*map_page_id), map_page)?;
+        }
+        Ok(())
+    }
+
+    /// Marks every page full but for the ones given, writing each map page once.
+    fn rebuild(&mut self, bpm: &mut BufferPoolManager, free_space: &[(PageId, usize)]) -> io::Result<()> {
+        self.map_pages.values_mut().for_each(|(_, map_page)| map_page.clear());
+        for (page_id, free_bytes) in free_space {
+            self.map_page_of(bpm, *page_id)?.1.set_free_space(*page_id, *free_bytes)?;
+        }
+        for (map_page_id, map_page) in self.map_pages.values() {
+            bpm.write_encoded(Some(*map_page_id), map_page)?;
+        }
+        Ok(())
+    }
+
+    fn find_page_with(&self, bytes: usize) -> Option<PageId> {
+        self.map_pages.values().find_map(|(_, map_page)| map_page.find_page_with(bytes))
+    }
+
+    /// Map page covering the page, written and linked to the end of the chain first if there is none.
+    fn map_page_of(&mut self, bpm: &mut BufferPoolManager, page_id: PageId) -> io::Result<&mut (PageId, FreeSpaceMapPage)> {
+        let first_page_id = page_id - page_id % FREE_SPACE_MAP_CAPACITY;
+        if !self.map_pages.contains_key(&first_page_id) {
+            let map_page = FreeSpaceMapPage::new(first_page_id);
+            let map_page_id = bpm.write_encoded(None, &map_page)?;
+            match self.last_map_page_id {
+                Some(last_map_page_id) => {
+                    let (_, last_map_page) = self.map_pages.values_mut()
+                        .find(|(id, _)| *id == last_map_page_id)
+                        .expect("last map page is loaded");
+                    last_map_page.set_next_page_id(Some(map_page_id));
+                    bpm.write_encoded(Some(last_map_page_id), last_map_page)?;
+                }
+                None => {
+                    let mut heap_page: TablePage = bpm.fetch_decoded(self.heap_page_id)?;
+                    heap_page.set_free_space_map_page_id(Some(map_page_id));
+                    bpm.write_encoded(Some(self.heap_page_id), &heap_page)?;
+                }
+            }
+            self.last_map_page_id = Some(map_page_id);
+            self.map_pages.insert(first_page_id, (map_page_id, map_page));
+        }
+        Ok(self.map_pages.get_mut(&first_page_id).unwrap())
+    }
+}
+
+/// Versions of a tuple returned by `TableHeap::versions`, each with its rid and header.
+pub struct VersionChain<'a> {
+    heap: &'a TableHeap,
+    next: Option<Rid>,
+}
+
+impl Iterator for VersionChain<'_> {
+    type Item = io::Result<(Rid, TupleHeader, Tuple)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rid = self.next.take()?;
+        let page: TablePage = match self.heap.bpm().fetch_decoded(rid.page_id) {
+            Ok(page) => page,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let (header, data) = match (page.get_header(rid.slot), page.get(rid.slot)) {
+            (Ok(Some(header)), Ok(Some(data))) => (header, data.to_vec()),
+            (Err(e), _) | (_, Err(e)) => return Some(Err(e)),
+            _ => return None,
+        };
+        self.next = header.prev_version;
+        Some(Ok((rid, header, Tuple::from_bytes(data))))
+    }
+}
+
+/// Sequential scan returned by `TableHeap::iter`. The page being read stays pinned until the scan
+/// moves on to the next one. The pages following it are hinted to the buffer pool to be read ahead, as
+/// many as its `read_ahead_depth`.
+pub struct TableIterator<'a> {
+    heap: &'a TableHeap,
+    next_page_id: Option<PageId>,
+    page: Option<(PageId, TablePage)>,
+    slot: u32,
+    // pages read ahead of the current one, in chain order
+    ahead: VecDeque<PageId>,
+}
+
+impl TableIterator<'_> {
+    /// Unpins the page read so far and pins the next one, `None` at the end of the chain.
+    fn advance(&mut self) -> io::Result<Option<()>> {
+        self.release()?;
+        let pid = match self.next_page_id.take() {
+            Some(pid) => pid,
+            None => return Ok(None),
+        };
+
+        let mut bpm = self.heap.bpm();
+        let decoded = TablePage::from_page(bpm.fetch_page_tagged(pid, "table_iter")?.read().unwrap().get_data());
+        let page = match decoded {
+            Ok(page) => page,
+            Err(e) => {
+                bpm.unpin_page(pid, false)?;
+                return Err(e);
+            }
+        };
+        self.next_page_id = page.get_next_page_id();
+        if self.ahead.pop_front() != Some(pid) {
+            self.ahead.clear();
+        }
+        while self.ahead.len() < bpm.read_ahead_depth() {
+            // the last page read ahead is in the pool already, unless evicted since
+            let following = match self.ahead.back() {
+                Some(last) => bpm.fetch_decoded::<TablePage>(*last).ok().and_then(|page| page.get_next_page_id()),
+                None => self.next_page_id,
+            };
+            match following {
+                Some(following) => {
+                    bpm.read_ahead(&[following]);
+                    self.ahead.push_back(following);
+                },
+                None => break,
+            }
+        }
+        self.page = Some((pid, page));
+        self.slot = 0;
+        Ok(Some(()))
+    }
+
+    fn release(&mut self) -> io::Result<()> {
+        if let Some((pid, _)) = self.page.take() {
+            self.heap.bpm().unpin_page(pid, false)?;
+        }
+        Ok(())
+    }
+}
+
+impl TableIterator<'_> {
+    /// Next tuple with the schema version it was written with.
+    pub fn next_versioned(&mut self) -> Option<io::Result<(Rid, u32, Tuple)>> {
+        loop {
+            if let Some((pid, page)) = &self.page {
+                while (self.slot as usize) < page.num_slots() {
+                    let slot = self.slot;
+                    self.slot += 1;
+                    match page.get_versioned(slot) {
+                        Ok(Some((schema_version, data))) =>
+                            return Some(Ok((Rid::new(*pid, slot), schema_version, Tuple::from_bytes(data.to_vec())))),
+                        Ok(None) => continue,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+            }
+
+            match self.advance() {
+                Ok(Some(())) => continue,
+                Ok(None) => return None,
+                Err(e) => {
+                    self.next_page_id = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for TableIterator<'_> {
+    type Item = io::Result<(Rid, Tuple)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_versioned().map(|entry| entry.map(|(rid, _, tuple)| (rid, tuple)))
+    }
+}
+
+impl Drop for TableIterator<'_> {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::storage::table::rid::Rid;
+    use crate::storage::table::table_error::TableError;
+    use crate::storage::table::table_heap::{TableHeap, VacuumStats};
+    use crate::storage::table::tuple::{Tuple, TupleHeader};
+
+    fn tuple_of(byte: u8, len: usize) -> Tuple {
+        Tuple::from_bytes(vec![byte; len])
+    }
+
+    #[test]
+    fn should_insert_across_pages_delete_and_reopen_heap() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm.clone()).unwrap();
+
+        // when
+        let rids: Vec<Rid> = (0..10u8).map(|i| heap.insert_tuple(&tuple_of(i, 1000)).unwrap()).collect();
+        let deleted = heap.delete_tuple(rids[3]).unwrap();
+        let deleted_again = heap.delete_tuple(rids[3]).unwrap();
+        let reopened = TableHeap::open(bpm.clone(), heap.first_page_id()).unwrap();
+
+        // then
+        assert!(deleted && !deleted_again);
+        assert_eq!(rids[0].page_id, heap.first_page_id());
+        assert_ne!(rids[0].page_id, rids[9].page_id);
+        assert_eq!(reopened.last_page_id, heap.last_page_id);
+        assert_eq!(reopened.get_tuple(rids[3]).unwrap(), None);
+        for (i, rid) in rids.iter().enumerate().filter(|(i, _)| *i != 3) {
+            assert_eq!(reopened.get_tuple(*rid).unwrap(), Some(tuple_of(i as u8, 1000)));
+        }
+        let err = heap.insert_tuple(&tuple_of(0, 5000)).err().unwrap();
+        assert!(matches!(TableError::from_io(&err), Some(TableError::TupleTooLarge { size: 5000, .. })));
+    }
+
+    #[test]
+    fn should_update_tuples_in_place_and_relocate_them_on_grow() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm).unwrap();
+        let first = heap.insert_tuple(&tuple_of(1, 1000)).unwrap();
+        let second = heap.insert_tuple(&tuple_of(2, 2000)).unwrap();
+
+        // when
+        let in_place = heap.update_tuple(first, &tuple_of(3, 1500)).unwrap();
+        let relocated = heap.update_tuple(second, &tuple_of(4, 3000)).unwrap();
+        heap.delete_tuple(first).unwrap();
+        let err = heap.update_tuple(first, &tuple_of(5, 10)).err().unwrap();
+
+        // then
+        assert_eq!(in_place, first);
+        assert_ne!(relocated.page_id, second.page_id);
+        assert_eq!(heap.get_tuple(second).unwrap(), None);
+        assert_eq!(heap.get_tuple(relocated).unwrap(), Some(tuple_of(4, 3000)));
+        assert!(matches!(TableError::from_io(&err), Some(TableError::TupleNotFound { rid }) if *rid == first));
+    }
+
+    #[test]
+    fn should_scan_live_tuples_in_rid_order_pinning_only_page_being_read() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm.clone()).unwrap();
+        let rids: Vec<Rid> = (0..10u8).map(|i| heap.insert_tuple(&tuple_of(i, 1000)).unwrap()).collect();
+        for i in [0, 4, 5, 9] {
+            heap.delete_tuple(rids[i]).unwrap();
+        }
+
+        // when
+        let mut iter = heap.iter();
+        let first = iter.next().unwrap().unwrap();
+        let first_pins = {
+            let bpm = bpm.lock().unwrap();
+            (bpm.pin_count_of(rids[0].page_id), bpm.pin_count_of(rids[9].page_id))
+        };
+        let rest: Vec<(Rid, Tuple)> = iter.by_ref().map(Result::unwrap).collect();
+        drop(iter);
+
+        // then
+        assert_eq!(first, (rids[1], tuple_of(1, 1000)));
+        assert_eq!(first_pins, (Some(1), Some(0)));
+        let expected: Vec<(Rid, Tuple)> = [2, 3, 6, 7, 8].iter().map(|i| (rids[*i], tuple_of(*i as u8, 1000))).collect();
+        assert_eq!(rest, expected);
+        for rid in &rids {
+            assert_eq!(bpm.lock().unwrap().pin_count_of(rid.page_id), Some(0));
+        }
+    }
+
+    #[test]
+    fn should_read_ahead_as_many_pages_as_read_ahead_depth() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(8)));
+        let mut heap = TableHeap::new(bpm.clone()).unwrap();
+        let rids: Vec<Rid> = (0..6u8).map(|i| heap.insert_tuple(&tuple_of(i, 3000)).unwrap()).collect();
+        let evict_heap = || {
+            let mut bpm = bpm.lock().unwrap();
+            for _ in 0..8 {
+                let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+                bpm.unpin_page(pid, false).unwrap();
+            }
+        };
+
+        // when
+        evict_heap();
+        bpm.lock().unwrap().set_read_ahead_depth(3);
+        let mut iter = heap.iter();
+        iter.next().unwrap().unwrap();
+        let cached_at_depth_3: Vec<bool> = rids.iter().map(|rid| bpm.lock().unwrap().pin_count_of(rid.page_id).is_some()).collect();
+        drop(iter);
+        evict_heap();
+        bpm.lock().unwrap().set_read_ahead_depth(0);
+        let mut iter = heap.iter();
+        iter.next().unwrap().unwrap();
+        let cached_at_depth_0: Vec<bool> = rids.iter().map(|rid| bpm.lock().unwrap().pin_count_of(rid.page_id).is_some()).collect();
+
+        // then
+        assert_eq!(cached_at_depth_3, [true, true, true, true, false, false]);
+        assert_eq!(cached_at_depth_0, [true, false, false, false, false, false]);
+    }
+
+    #[test]
+    fn should_vacuum_empty_pages_and_reuse_space_of_deleted_tuples() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm.clone()).unwrap();
+        let rids: Vec<Rid> = (0..12u8).map(|i| heap.insert_tuple(&tuple_of(i, 900)).unwrap()).collect();
+        for rid in &rids[2..] {
+            heap.delete_tuple(*rid).unwrap();
+        }
+
+        // when
+        let stats = heap.vacuum().unwrap();
+        let reused = heap.insert_tuple(&tuple_of(20, 900)).unwrap();
+        let reopened = TableHeap::open(bpm.clone(), heap.first_page_id()).unwrap();
+
+        // then
+        assert_eq!(stats, VacuumStats { pages_scanned: 3, pages_freed: 2, slots_reclaimed: 2 });
+        assert_eq!(reused, rids[2]);
+        assert_eq!(heap.last_page_id, heap.first_page_id());
+        assert_eq!(reopened.last_page_id, heap.first_page_id());
+        let scanned: Vec<Rid> = reopened.iter().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(scanned, [rids[0], rids[1], reused]);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(rids[4].page_id), None);
+        assert_eq!(bpm.lock().unwrap().pin_count_of(rids[8].page_id), None);
+    }
+
+    #[test]
+    fn should_find_free_space_of_heap_after_reopening_it() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm.clone()).unwrap();
+        let rids: Vec<Rid> = (0..12u8).map(|i| heap.insert_tuple(&tuple_of(i, 900)).unwrap()).collect();
+        heap.delete_tuple(rids[1]).unwrap();
+
+        // when
+        let mut reopened = TableHeap::open(bpm, heap.first_page_id()).unwrap();
+        let reinserted = reopened.insert_tuple(&tuple_of(20, 900)).unwrap();
+
+        // then
+        assert_eq!(reinserted.page_id, heap.first_page_id());
+        assert_eq!(reopened.get_tuple(reinserted).unwrap(), Some(tuple_of(20, 900)));
+    }
+
+    #[test]
+    fn should_keep_slot_of_deleted_version_while_its_successor_refers_to_it() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm).unwrap();
+        let first = heap.insert_version(&tuple_of(1, 2000), 10).unwrap();
+        let second = heap.install_version(first, &tuple_of(2, 3000), 20).unwrap();
+        heap.delete_tuple(first).unwrap();
+
+        // when
+        let stats = heap.vacuum().unwrap();
+        let inserted = heap.insert_tuple(&tuple_of(3, 10)).unwrap();
+
+        // then
+        assert_ne!(second.page_id, first.page_id);
+        assert_eq!(stats.slots_reclaimed, 0);
+        assert_ne!(inserted, first);
+        let versions: Vec<Rid> = heap.versions(second).map(|version| version.unwrap().0).collect();
+        assert_eq!(versions, [second]);
+    }
+
+    #[test]
+    fn should_install_versions_and_read_the_one_visible_at_a_timestamp() {
+        // given
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new_default(10)));
+        let mut heap = TableHeap::new(bpm).unwrap();
+        let first = heap.insert_version(&tuple_of(1, 10), 10).unwrap();
+        let later = heap.insert_version(&tuple_of(5, 10), 60).unwrap();
+
+        // when
+        let second = heap.install_version(first, &tuple_of(2, 10), 20).unwrap();
+        let third = heap.install_version(second, &tuple_of(3, 10), 30).unwrap();
+        heap.end_version(third, 40).unwrap();
+        let errors = [
+            heap.install_version(first, &tuple_of(4, 10), 50).err().unwrap(),
+            heap.end_version(later, 55).err().unwrap(),
+        ];
+
+        // then
+        let versions: Vec<(Rid, TupleHeader)> = heap.versions(third).map(|version| {
+            let (rid, header, _) = version.unwrap();
+            (rid, header)
+        }).collect();
+        assert_eq!(versions, [
+            (third, TupleHeader { begin_ts: 30, end_ts: 40, prev_version: Some(second) }),
+            (second, TupleHeader { begin_ts: 20, end_ts: 30, prev_version: Some(first) }),
+            (first, TupleHeader { begin_ts: 10, end_ts: 20, prev_version: None }),
+        ]);
+        let visible: Vec<Option<Rid>> = [5, 15, 20, 35, 45].iter()
+            .map(|ts| heap.get_visible(third, *ts).unwrap().map(|(rid, _)| rid))
+            .collect();
+        assert_eq!(visible, [None, Some(first), Some(second), Some(third), None]);
+        assert_eq!(heap.get_visible(third, 25).unwrap(), Some((second, tuple_of(2, 10))));
+        assert!(matches!(TableError::from_io(&errors[0]), Some(TableError::VersionConflict { rid }) if *rid == first));
+        assert!(matches!(TableError::from_io(&errors[1]), Some(TableError::VersionConflict { rid }) if *rid == later));
+        let plain = heap.insert_tuple(&tuple_of(6, 10)).unwrap();
+        assert_eq!(heap.get_header(plain).unwrap(), Some(TupleHeader::default()));
+    }
+}
+
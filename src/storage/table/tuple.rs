@@ -0,0 +1,340 @@
+use std::convert::TryInto;
+use std::{io, mem};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::hash::HashKeyType;
+use crate::storage::page::overflow_page::OverflowRef;
+use crate::storage::page::page::PageId;
+use crate::storage::table::rid::Rid;
+use crate::storage::table::schema::{DataType, Schema};
+use crate::storage::table::table_error::TableError;
+use crate::storage::table::value::Value;
+
+/// Set in the length of a varchar field whose data is an `OverflowRef` instead of the varchar itself.
+const OUT_OF_LINE: u32 = 1 << 31;
+const OVERFLOW_REF_SIZE: usize = 2 * mem::size_of::<u64>();
+
+/// Data of a varchar field: the bytes of the varchar, or where they are stored out of line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarcharData {
+    Inline(Vec<u8>),
+    OutOfLine(OverflowRef),
+}
+
+/// Timestamp a version of a tuple starts or stops being visible at, e.g. the commit timestamp of the
+/// transaction writing it.
+pub type VersionTs = u64;
+
+/// End of a version that was not replaced or deleted yet.
+pub const TS_INFINITY: VersionTs = VersionTs::MAX;
+
+/// Version of a tuple: visible from `begin_ts` up to, not including, `end_ts`, and the version it
+/// replaced, so that a reader can walk back to the version visible at its timestamp.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TupleHeader {
+    pub begin_ts: VersionTs,
+    pub end_ts: VersionTs,
+    pub prev_version: Option<Rid>,
+}
+
+impl TupleHeader {
+    pub fn new(begin_ts: VersionTs, prev_version: Option<Rid>) -> TupleHeader {
+        TupleHeader { begin_ts, end_ts: TS_INFINITY, prev_version }
+    }
+
+    pub fn is_visible_at(&self, ts: VersionTs) -> bool {
+        self.begin_ts <= ts && ts < self.end_ts
+    }
+}
+
+/// Tuples written without versions are visible from the start on, never replaced.
+impl Default for TupleHeader {
+    fn default() -> Self {
+        TupleHeader::new(0, None)
+    }
+}
+
+/// Record encoded after a `Schema`: a bitmap of the null fields, every field at the fixed offset of
+/// its column, then the data of the varchars, each referred to by offset and length from its field.
+/// Only the bytes are kept, reading them takes the schema they were written with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tuple {
+    data: Vec<u8>,
+}
+
+/// Tuples as keys of a hash index, whose slots keep keys of `Tuple::MAX_HASH_KEY_SIZE` bytes at most.
+impl HashKeyType for Tuple {
+    const ENCODED_SIZE: usize = mem::size_of::<u64>() + Tuple::MAX_HASH_KEY_SIZE;
+}
+
+impl Tuple {
+    /// Largest tuple a hash index takes as key.
+    pub const MAX_HASH_KEY_SIZE: usize = 64;
+    /// Longer varchars are moved to overflow pages when the tuple is written to a table.
+    pub const MAX_INLINE_VARCHAR_SIZE: usize = 256;
+
+    /// Fails with a `TableError` unless there is a value of the column type, or a null if the
+    /// column is nullable, for each column.
+    pub fn new(values: &[Value], schema: &Schema) -> io::Result<Tuple> {
+        if values.len() != schema.len() {
+            return Err(TableError::ColumnCountMismatch { expected: schema.len(), found: values.len() }.into());
+        }
+
+        let mut data = vec![0; schema.fixed_size()];
+        for (idx, (value, column)) in values.iter().zip(schema.columns()).enumerate() {
+            let offset = schema.offset_of(idx);
+            match (value, column.data_type()) {
+                (Value::Null, _) if column.is_nullable() => data[idx / 8] |= 0x01 << (idx % 8),
+                (Value::Null, _) => return Err(TableError::NullNotAllowed { column: column.name().to_string() }.into()),
+                (Value::Boolean(b), DataType::Boolean) => data[offset] = *b as u8,
+                (Value::Integer(i), DataType::Integer) => data[offset..offset + 4].copy_from_slice(&i.to_le_bytes()),
+                (Value::BigInt(i), DataType::BigInt) => data[offset..offset + 8].copy_from_slice(&i.to_le_bytes()),
+                (Value::Decimal(unscaled, scale), DataType::Decimal(column_scale)) if *scale == column_scale =>
+                    data[offset..offset + 8].copy_from_slice(&unscaled.to_le_bytes()),
+                (Value::Timestamp(micros), DataType::Timestamp) => data[offset..offset + 8].copy_from_slice(&micros.to_le_bytes()),
+                (Value::Blob(blob), DataType::Blob) => {
+                    data[offset..offset + 8].copy_from_slice(&(blob.page_id as u64).to_le_bytes());
+                    data[offset + 8..offset + 16].copy_from_slice(&blob.len.to_le_bytes());
+                }
+                (Value::Varchar(s), DataType::Varchar(max)) => {
+                    if s.len() > max as usize {
+                        return Err(TableError::ValueTooLong { column: column.name().to_string(), len: s.len(), max: max as usize }.into());
+                    }
+                    let start = data.len() as u32;
+                    data[offset..offset + 4].copy_from_slice(&start.to_le_bytes());
+                    data[offset + 4..offset + 8].copy_from_slice(&(s.len() as u32).to_le_bytes());
+                    data.extend_from_slice(s.as_bytes());
+                }
+                (_, expected) => return Err(TableError::TypeMismatch { column: column.name().to_string(), expected }.into()),
+            }
+        }
+
+        Ok(Tuple { data })
+    }
+
+    /// Tuple of bytes encoded before, e.g. read back from a page.
+    pub fn from_bytes(data: Vec<u8>) -> Tuple {
+        Tuple { data }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Bytes of the encoded tuple.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Decodes the field of column `idx` alone.
+    pub fn get_value(&self, schema: &Schema, idx: usize) -> io::Result<Value> {
+        let column = schema.column(idx)
+            .ok_or(TableError::ColumnOutOfRange { idx, num_columns: schema.len() })?;
+        if self.data.len() < schema.fixed_size() {
+            return Err(TableError::TupleTooShort { expected: schema.fixed_size(), actual: self.data.len() }.into());
+        }
+        if (self.data[idx / 8] >> (idx % 8)) & 0x01 == 1 {
+            return Ok(Value::Null);
+        }
+
+        let offset = schema.offset_of(idx);
+        let field = &self.data[offset..offset + column.data_type().fixed_size()];
+        Ok(match column.data_type() {
+            DataType::Boolean => Value::Boolean(field[0] != 0),
+            DataType::Integer => Value::Integer(i32::from_le_bytes(field.try_into().unwrap())),
+            DataType::BigInt => Value::BigInt(i64::from_le_bytes(field.try_into().unwrap())),
+            DataType::Decimal(scale) => Value::Decimal(i64::from_le_bytes(field.try_into().unwrap()), scale),
+            DataType::Timestamp => Value::Timestamp(i64::from_le_bytes(field.try_into().unwrap())),
+            DataType::Blob => Value::Blob(OverflowRef {
+                page_id: u64::from_le_bytes(field[0..8].try_into().unwrap()) as PageId,
+                len: u64::from_le_bytes(field[8..16].try_into().unwrap()),
+            }),
+            DataType::Varchar(_) => match self.varchar_field(offset)? {
+                (bytes, false) => {
+                    let s = String::from_utf8(bytes.to_vec())
+                        .map_err(|_| TableError::InvalidUtf8 { column: column.name().to_string() })?;
+                    Value::Varchar(s)
+                }
+                (_, true) => return Err(TableError::VarcharOutOfLine { column: column.name().to_string() }.into()),
+            },
+        })
+    }
+
+    /// Data of the varchar of column `idx`, `None` if the field is null or not a varchar.
+    pub fn varchar_data(&self, schema: &Schema, idx: usize) -> io::Result<Option<VarcharData>> {
+        let column = schema.column(idx)
+            .ok_or(TableError::ColumnOutOfRange { idx, num_columns: schema.len() })?;
+        if self.data.len() < schema.fixed_size() {
+            return Err(TableError::TupleTooShort { expected: schema.fixed_size(), actual: self.data.len() }.into());
+        }
+        if !matches!(column.data_type(), DataType::Varchar(_)) || (self.data[idx / 8] >> (idx % 8)) & 0x01 == 1 {
+            return Ok(None);
+        }
+
+        Ok(Some(match self.varchar_field(schema.offset_of(idx))? {
+            (bytes, false) => VarcharData::Inline(bytes.to_vec()),
+            (bytes, true) => VarcharData::OutOfLine(OverflowRef {
+                page_id: u64::from_le_bytes(bytes[0..8].try_into().unwrap()) as PageId,
+                len: u64::from_le_bytes(bytes[8..OVERFLOW_REF_SIZE].try_into().unwrap()),
+            }),
+        }))
+    }
+
+    /// Tuple with the data of the varchar of column `idx` replaced, a non-null varchar. The data of every
+    /// varchar is laid out again after the fixed-size part.
+    pub fn with_varchar_data(&self, schema: &Schema, idx: usize, varchar_data: VarcharData) -> io::Result<Tuple> {
+        if self.data.len() < schema.fixed_size() {
+            return Err(TableError::TupleTooShort { expected: schema.fixed_size(), actual: self.data.len() }.into());
+        }
+
+        let mut replacement = Some(varchar_data);
+        let mut data = self.data[..schema.fixed_size()].to_vec();
+        for column_idx in 0..schema.len() {
+            let field = match self.varchar_data(schema, column_idx)? {
+                Some(_) if column_idx == idx => replacement.take().unwrap(),
+                Some(field) => field,
+                None => continue,
+            };
+            let start = data.len() as u32;
+            let len = match field {
+                VarcharData::Inline(bytes) => {
+                    data.extend_from_slice(&bytes);
+                    bytes.len() as u32
+                }
+                VarcharData::OutOfLine(overflow_ref) => {
+                    data.extend_from_slice(&(overflow_ref.page_id as u64).to_le_bytes());
+                    data.extend_from_slice(&overflow_ref.len.to_le_bytes());
+                    OUT_OF_LINE | OVERFLOW_REF_SIZE as u32
+                }
+            };
+            let offset = schema.offset_of(column_idx);
+            data[offset..offset + 4].copy_from_slice(&start.to_le_bytes());
+            data[offset + 4..offset + 8].copy_from_slice(&len.to_le_bytes());
+        }
+        Ok(Tuple { data })
+    }
+
+    // bytes a varchar field refers to, and whether they are an `OverflowRef`
+    fn varchar_field(&self, offset: usize) -> io::Result<(&[u8], bool)> {
+        let start = u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(self.data[offset + 4..offset + 8].try_into().unwrap());
+        let end = start + (len & !OUT_OF_LINE) as usize;
+        let bytes = self.data.get(start..end)
+            .ok_or(TableError::TupleTooShort { expected: end, actual: self.data.len() })?;
+        Ok((bytes, len & OUT_OF_LINE != 0))
+    }
+
+    /// Whether any field is null, read from the null bitmap alone.
+    pub fn has_null(&self, schema: &Schema) -> bool {
+        (0..schema.len()).any(|idx| self.data.get(idx / 8).is_some_and(|byte| (byte >> (idx % 8)) & 0x01 == 1))
+    }
+
+    pub fn values(&self, schema: &Schema) -> io::Result<Vec<Value>> {
+        (0..schema.len()).map(|idx| self.get_value(schema, idx)).collect()
+    }
+
+    /// Fails with a `TableError` unless the tuple is one `Tuple::new` would make for the schema: its
+    /// fields decode and are within their columns, with no null in a column not taking nulls.
+    pub fn check(&self, schema: &Schema) -> io::Result<()> {
+        Tuple::new(&self.values(schema)?, schema).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::table::schema::{Column, DataType, Schema};
+    use crate::storage::table::table_error::TableError;
+    use crate::storage::page::overflow_page::OverflowRef;
+    use crate::storage::table::tuple::{Tuple, VarcharData};
+    use crate::storage::table::value::Value;
+
+    fn user_schema() -> Schema {
+        Schema::new(vec![
+            Column::new("id", DataType::BigInt),
+            Column::new("name", DataType::Varchar(16)),
+            Column::new("email", DataType::Varchar(32)).with_nullable(true),
+            Column::new("age", DataType::Integer).with_nullable(true),
+            Column::new("active", DataType::Boolean),
+        ])
+    }
+
+    #[test]
+    fn should_encode_fixed_fields_inline_and_varchars_after_them() {
+        // given
+        let schema = user_schema();
+        let values = vec![Value::BigInt(-7), Value::Varchar("alice".to_string()), Value::Null, Value::Integer(42), Value::Boolean(true)];
+
+        // when
+        let tuple = Tuple::new(&values, &schema).unwrap();
+        let read_back = Tuple::from_bytes(tuple.data().to_vec());
+
+        // then
+        assert_eq!(tuple.size(), schema.fixed_size() + "alice".len());
+        assert_eq!(read_back.values(&schema).unwrap(), values);
+        assert_eq!(read_back.get_value(&schema, 1).unwrap(), Value::Varchar("alice".to_string()));
+        assert_eq!(read_back.get_value(&schema, 2).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn should_encode_decimals_at_the_scale_of_their_column_and_timestamps() {
+        // given
+        let schema = Schema::new(vec![Column::new("price", DataType::Decimal(2)), Column::new("sold_at", DataType::Timestamp)]);
+        let values = vec![Value::Decimal(-1999, 2), Value::Timestamp(1_700_000_000_000_000)];
+
+        // when
+        let tuple = Tuple::new(&values, &schema).unwrap();
+        let err = Tuple::new(&[Value::Decimal(-1999, 3), Value::Timestamp(0)], &schema).err().unwrap();
+
+        // then
+        assert_eq!(tuple.values(&schema).unwrap(), values);
+        assert!(matches!(TableError::from_io(&err), Some(TableError::TypeMismatch { expected: DataType::Decimal(2), .. })));
+    }
+
+    #[test]
+    fn should_refuse_values_not_matching_schema_and_truncated_tuples() {
+        // given
+        let schema = user_schema();
+        let row = |name: Value, age: Value| vec![Value::BigInt(1), name, Value::Null, age, Value::Boolean(false)];
+
+        // when
+        let errors = [
+            Tuple::new(&row(Value::Varchar("bob".to_string()), Value::BigInt(3)), &schema).err().unwrap(),
+            Tuple::new(&row(Value::Null, Value::Null), &schema).err().unwrap(),
+            Tuple::new(&row(Value::Varchar("x".repeat(17)), Value::Null), &schema).err().unwrap(),
+            Tuple::new(&[Value::BigInt(1)], &schema).err().unwrap(),
+        ];
+        let tuple = Tuple::new(&row(Value::Varchar("bob".to_string()), Value::Null), &schema).unwrap();
+        let truncated = Tuple::from_bytes(tuple.data()[..tuple.size() - 1].to_vec());
+
+        // then
+        assert!(matches!(TableError::from_io(&errors[0]), Some(TableError::TypeMismatch { column, expected: DataType::Integer }) if column == "age"));
+        assert!(matches!(TableError::from_io(&errors[1]), Some(TableError::NullNotAllowed { column }) if column == "name"));
+        assert!(matches!(TableError::from_io(&errors[2]), Some(TableError::ValueTooLong { len: 17, max: 16, .. })));
+        assert!(matches!(TableError::from_io(&errors[3]), Some(TableError::ColumnCountMismatch { expected: 5, found: 1 })));
+        assert_eq!(truncated.get_value(&schema, 0).unwrap(), Value::BigInt(1));
+        let err = truncated.get_value(&schema, 1).err().unwrap();
+        assert!(matches!(TableError::from_io(&err), Some(TableError::TupleTooShort { .. })));
+    }
+
+    #[test]
+    fn should_replace_varchar_data_with_overflow_ref_and_back() {
+        // given
+        let schema = user_schema();
+        let values = vec![Value::BigInt(1), Value::Varchar("bob".to_string()), Value::Varchar("bob@mine.db".to_string()), Value::Null, Value::Boolean(true)];
+        let tuple = Tuple::new(&values, &schema).unwrap();
+        let overflow_ref = OverflowRef { page_id: 9, len: 11 };
+
+        // when
+        let out_of_line = tuple.with_varchar_data(&schema, 2, VarcharData::OutOfLine(overflow_ref)).unwrap();
+        let inline = out_of_line.with_varchar_data(&schema, 2, VarcharData::Inline(b"bob@mine.db".to_vec())).unwrap();
+
+        // then
+        assert_eq!(out_of_line.varchar_data(&schema, 2).unwrap(), Some(VarcharData::OutOfLine(overflow_ref)));
+        assert_eq!(out_of_line.varchar_data(&schema, 1).unwrap(), Some(VarcharData::Inline(b"bob".to_vec())));
+        assert_eq!(out_of_line.varchar_data(&schema, 3).unwrap(), None);
+        let err = out_of_line.get_value(&schema, 2).err().unwrap();
+        assert!(matches!(TableError::from_io(&err), Some(TableError::VarcharOutOfLine { column }) if column == "email"));
+        assert_eq!(inline, tuple);
+    }
+}
@@ -0,0 +1,556 @@
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter};
+use std::ops::Not;
+use std::{fmt, io, iter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::page::overflow_page::OverflowRef;
+use crate::storage::table::schema::DataType;
+use crate::storage::table::table_error::TableError;
+
+/// Most digits after the point a decimal keeps, as many as an `i64` holds in all.
+pub const MAX_DECIMAL_SCALE: u8 = 18;
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// Value of a field, of the type of its column, or null. Values of a column are ordered by their
+/// content, nulls first; `compare` orders values of different numeric types as well.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i32),
+    BigInt(i64),
+    Varchar(String),
+    /// Unscaled value and scale: `Decimal(1234, 2)` is 12.34.
+    Decimal(i64, u8),
+    /// Microseconds since the Unix epoch, in UTC.
+    Timestamp(i64),
+    /// Handle of a large object, its bytes are read and written through a `LobStore`.
+    Blob(OverflowRef),
+}
+
+/// Truth value of a predicate in three-valued logic: comparing with a null is `Unknown`, as a null
+/// stands for a value that is missing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+impl Truth {
+    pub fn and(self, other: Truth) -> Truth {
+        match (self, other) {
+            (Truth::False, _) | (_, Truth::False) => Truth::False,
+            (Truth::True, Truth::True) => Truth::True,
+            _ => Truth::Unknown,
+        }
+    }
+
+    pub fn or(self, other: Truth) -> Truth {
+        match (self, other) {
+            (Truth::True, _) | (_, Truth::True) => Truth::True,
+            (Truth::False, Truth::False) => Truth::False,
+            _ => Truth::Unknown,
+        }
+    }
+
+    /// Whether a row passes a filter of the predicate: unknown filters it out like false.
+    pub fn is_true(self) -> bool {
+        self == Truth::True
+    }
+}
+
+impl Not for Truth {
+    type Output = Truth;
+
+    fn not(self) -> Truth {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Truth {
+    fn from(b: bool) -> Self {
+        if b { Truth::True } else { Truth::False }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl Display for ArithmeticOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Sub => "-",
+            ArithmeticOp::Mul => "*",
+            ArithmeticOp::Div => "/",
+            ArithmeticOp::Mod => "%",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Compares the values by `op` the way a predicate does: `Unknown` if either is null, otherwise
+    /// as ordered by `compare`.
+    pub fn evaluate(&self, op: CompareOp, other: &Value) -> io::Result<Truth> {
+        if self.is_null() || other.is_null() {
+            return Ok(Truth::Unknown)
+        }
+
+        let ordering = self.compare(other)?;
+        Ok(Truth::from(match op {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::NotEq => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::LtEq => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::GtEq => ordering != Ordering::Less,
+        }))
+    }
+
+    /// Applies `op` the way an expression does, null if either value is null. Numbers of any type mix:
+    /// two integers give an integer, a decimal gives a decimal, anything else a big int. Decimals keep
+    /// the larger scale of the two, products the sum of both up to `MAX_DECIMAL_SCALE`, and division
+    /// truncates towards zero. A timestamp moves by a number of microseconds, and two timestamps
+    /// subtract to the big int of microseconds between them.
+    ///
+    /// Fails with `TableError::Overflow` if the result does not fit its type, `DivisionByZero`, or
+    /// `InvalidOperands` for values of other types.
+    pub fn arithmetic(&self, op: ArithmeticOp, other: &Value) -> io::Result<Value> {
+        let invalid = || TableError::InvalidOperands { op: op.to_string(), operands: format!("{}, {}", self, other) };
+        let overflow = || TableError::Overflow { op: op.to_string() };
+        let result = match (self, op, other) {
+            (Value::Null, _, _) | (_, _, Value::Null) => return Ok(Value::Null),
+            (Value::Timestamp(a), ArithmeticOp::Sub, Value::Timestamp(b)) => a.checked_sub(*b).map(Value::BigInt),
+            (Value::Timestamp(t), ArithmeticOp::Add, micros) | (micros, ArithmeticOp::Add, Value::Timestamp(t)) =>
+                t.checked_add(micros.as_micros().ok_or_else(invalid)?).map(Value::Timestamp),
+            (Value::Timestamp(t), ArithmeticOp::Sub, micros) =>
+                t.checked_sub(micros.as_micros().ok_or_else(invalid)?).map(Value::Timestamp),
+            _ => {
+                let ((a, a_scale), (b, b_scale)) = self.as_decimal().zip(other.as_decimal()).ok_or_else(invalid)?;
+                if b == 0 && matches!(op, ArithmeticOp::Div | ArithmeticOp::Mod) {
+                    return Err(TableError::DivisionByZero.into());
+                }
+                let (result, scale) = decimal_arithmetic(op, a as i128, a_scale, b as i128, b_scale).ok_or_else(overflow)?;
+                match (self, other) {
+                    (Value::Integer(_), Value::Integer(_)) => result.try_into().ok().map(Value::Integer),
+                    (Value::Decimal(..), _) | (_, Value::Decimal(..)) => result.try_into().ok().map(|unscaled| Value::Decimal(unscaled, scale)),
+                    _ => result.try_into().ok().map(Value::BigInt),
+                }
+            }
+        };
+        result.ok_or_else(|| overflow().into())
+    }
+
+    /// Concatenates two varchars, null if either is null.
+    pub fn concat(&self, other: &Value) -> io::Result<Value> {
+        match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+            (Value::Varchar(a), Value::Varchar(b)) => Ok(Value::Varchar(format!("{}{}", a, b))),
+            _ => Err(TableError::InvalidOperands { op: "||".to_string(), operands: format!("{}, {}", self, other) }.into()),
+        }
+    }
+
+    /// Characters, not bytes, of a varchar, null for null.
+    pub fn char_length(&self) -> io::Result<Value> {
+        match self {
+            Value::Null => Ok(Value::Null),
+            Value::Varchar(s) => Ok(Value::BigInt(s.chars().count() as i64)),
+            _ => Err(TableError::InvalidOperands { op: "char_length".to_string(), operands: self.to_string() }.into()),
+        }
+    }
+
+    /// Matches a varchar against a `LIKE` pattern, where `%` stands for any run of characters and `_`
+    /// for any one character. `Unknown` if either is null.
+    pub fn like(&self, pattern: &Value) -> io::Result<Truth> {
+        match (self, pattern) {
+            (Value::Null, _) | (_, Value::Null) => Ok(Truth::Unknown),
+            (Value::Varchar(s), Value::Varchar(pattern)) =>
+                Ok(Truth::from(like_matches(&s.chars().collect::<Vec<char>>(), &pattern.chars().collect::<Vec<char>>()))),
+            _ => Err(TableError::InvalidOperands { op: "LIKE".to_string(), operands: format!("{}, {}", self, pattern) }.into()),
+        }
+    }
+
+    /// Orders numbers of any type by their numeric value, and other values against values of their
+    /// own type only, failing with `TableError::NotComparable` otherwise. Nulls come first, which is
+    /// where sorting puts them; predicates use `evaluate` instead.
+    pub fn compare(&self, other: &Value) -> io::Result<Ordering> {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ok(Ordering::Equal),
+            (Value::Null, _) => Ok(Ordering::Less),
+            (_, Value::Null) => Ok(Ordering::Greater),
+            (Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
+            (Value::Varchar(a), Value::Varchar(b)) => Ok(a.cmp(b)),
+            (Value::Timestamp(a), Value::Timestamp(b)) => Ok(a.cmp(b)),
+            _ => match (self.as_decimal(), other.as_decimal()) {
+                (Some((a, a_scale)), Some((b, b_scale))) => {
+                    let scale = a_scale.max(b_scale);
+                    Ok(widen(a, a_scale, scale).cmp(&widen(b, b_scale, scale)))
+                }
+                _ => Err(TableError::NotComparable { left: self.to_string(), right: other.to_string() }.into()),
+            },
+        }
+    }
+
+    /// Converts to a value of type `to`, failing with `TableError::InvalidCast` if there is none:
+    /// - null stays null, whatever the type;
+    /// - numbers convert into each other, going to a smaller scale truncates towards zero, while
+    ///   overflowing fails;
+    /// - timestamps and big ints convert into each other as microseconds since the epoch;
+    /// - every value has a varchar of its text if that fits, and varchars of such text parse back,
+    ///   timestamps as `YYYY-MM-DD[ HH:MM:SS[.ffffff]]`;
+    /// - a blob handle casts to a blob only.
+    pub fn cast_to(&self, to: DataType) -> io::Result<Value> {
+        let cast = match (self, to) {
+            (Value::Null, _) => Some(Value::Null),
+            (Value::Boolean(b), DataType::Boolean) => Some(Value::Boolean(*b)),
+            (Value::Timestamp(t), DataType::Timestamp) | (Value::BigInt(t), DataType::Timestamp) => Some(Value::Timestamp(*t)),
+            (Value::Timestamp(t), DataType::BigInt) => Some(Value::BigInt(*t)),
+            (Value::Blob(blob), DataType::Blob) => Some(Value::Blob(*blob)),
+            (Value::Blob(_), _) => None,
+            (Value::Varchar(s), DataType::Boolean) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" => Some(Value::Boolean(true)),
+                "false" => Some(Value::Boolean(false)),
+                _ => None,
+            },
+            (Value::Varchar(s), DataType::Integer) => s.trim().parse().ok().map(Value::Integer),
+            (Value::Varchar(s), DataType::BigInt) => s.trim().parse().ok().map(Value::BigInt),
+            (Value::Varchar(s), DataType::Decimal(scale)) => parse_decimal(s.trim(), scale).map(|unscaled| Value::Decimal(unscaled, scale)),
+            (Value::Varchar(s), DataType::Timestamp) => parse_timestamp(s.trim()).map(Value::Timestamp),
+            (value, DataType::Varchar(max)) => Some(value.to_string()).filter(|s| s.len() <= max as usize).map(Value::Varchar),
+            (value, DataType::Integer) => value.as_decimal()
+                .and_then(|(unscaled, scale)| rescale(unscaled, scale, 0))
+                .and_then(|i| i.try_into().ok())
+                .map(Value::Integer),
+            (value, DataType::BigInt) => value.as_decimal()
+                .and_then(|(unscaled, scale)| rescale(unscaled, scale, 0))
+                .map(Value::BigInt),
+            (value, DataType::Decimal(to_scale)) => value.as_decimal()
+                .and_then(|(unscaled, scale)| rescale(unscaled, scale, to_scale))
+                .map(|unscaled| Value::Decimal(unscaled, to_scale)),
+            _ => None,
+        };
+        cast.ok_or_else(|| TableError::InvalidCast { value: self.to_string(), to }.into())
+    }
+
+    fn as_micros(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i as i64),
+            Value::BigInt(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    // numbers as an unscaled value and its scale
+    fn as_decimal(&self) -> Option<(i64, u8)> {
+        match self {
+            Value::Integer(i) => Some((*i as i64, 0)),
+            Value::BigInt(i) => Some((*i, 0)),
+            Value::Decimal(unscaled, scale) => Some((*unscaled, *scale)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
+            Value::Varchar(s) => write!(f, "{}", s),
+            Value::Blob(blob) => write!(f, "<blob of {} bytes>", blob.len),
+            Value::Decimal(unscaled, 0) => write!(f, "{}", unscaled),
+            Value::Decimal(unscaled, scale) => {
+                let digits = format!("{:0>width$}", unscaled.unsigned_abs(), width = *scale as usize + 1);
+                let (integer, fraction) = digits.split_at(digits.len() - *scale as usize);
+                write!(f, "{}{}.{}", if *unscaled < 0 { "-" } else { "" }, integer, fraction)
+            }
+            Value::Timestamp(micros) => {
+                let (year, month, day) = civil_from_days(micros.div_euclid(MICROS_PER_DAY));
+                let micros_of_day = micros.rem_euclid(MICROS_PER_DAY);
+                let seconds = micros_of_day / MICROS_PER_SECOND;
+                write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)?;
+                match micros_of_day % MICROS_PER_SECOND {
+                    0 => Ok(()),
+                    fraction => write!(f, ".{:06}", fraction),
+                }
+            }
+        }
+    }
+}
+
+// only compared, so saturating keeps the order of scales too large for an i64
+fn widen(unscaled: i64, scale: u8, to_scale: u8) -> i128 {
+    (unscaled as i128).saturating_mul(10i128.saturating_pow((to_scale - scale) as u32))
+}
+
+fn rescale(unscaled: i64, scale: u8, to_scale: u8) -> Option<i64> {
+    if to_scale >= scale {
+        10i64.checked_pow((to_scale - scale) as u32).and_then(|factor| unscaled.checked_mul(factor))
+    } else {
+        Some(10i64.checked_pow((scale - to_scale) as u32).map_or(0, |factor| unscaled / factor))
+    }
+}
+
+// `None` on overflow, the divisor is not zero
+fn decimal_arithmetic(op: ArithmeticOp, a: i128, a_scale: u8, b: i128, b_scale: u8) -> Option<(i128, u8)> {
+    let pow = |exp: u8| 10i128.checked_pow(exp as u32);
+    let scale = a_scale.max(b_scale);
+    let (a_wide, b_wide) = (a.checked_mul(pow(scale - a_scale)?)?, b.checked_mul(pow(scale - b_scale)?)?);
+    match op {
+        ArithmeticOp::Add => Some((a_wide.checked_add(b_wide)?, scale)),
+        ArithmeticOp::Sub => Some((a_wide.checked_sub(b_wide)?, scale)),
+        ArithmeticOp::Mod => Some((a_wide.checked_rem(b_wide)?, scale)),
+        ArithmeticOp::Div => Some((a_wide.checked_mul(pow(scale)?)?.checked_div(b_wide)?, scale)),
+        ArithmeticOp::Mul => {
+            let (product, product_scale) = (a.checked_mul(b)?, a_scale.checked_add(b_scale)?);
+            match product_scale.checked_sub(MAX_DECIMAL_SCALE) {
+                Some(excess) if excess > 0 => Some((product / pow(excess)?, MAX_DECIMAL_SCALE)),
+                _ => Some((product, product_scale)),
+            }
+        }
+    }
+}
+
+// backtracks to the last `%` on a mismatch, which is enough as a later `%` matches whatever an earlier one would
+fn like_matches(text: &[char], pattern: &[char]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    let mut last_percent: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '%' {
+            last_percent = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+            t += 1;
+            p += 1;
+        } else if let Some((percent, matched_from)) = last_percent {
+            last_percent = Some((percent, matched_from + 1));
+            p = percent + 1;
+            t = matched_from + 1;
+        } else {
+            return false
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+fn parse_decimal(s: &str, scale: u8) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None
+    }
+
+    let fraction: String = fraction.chars().chain(iter::repeat('0')).take(scale as usize).collect();
+    let unscaled: i64 = format!("{}{}", integer, fraction).parse().ok()?;
+    Some(if negative { -unscaled } else { unscaled })
+}
+
+fn parse_timestamp(s: &str) -> Option<i64> {
+    let (date, time) = s.split_once([' ', 'T']).unwrap_or((s, "00:00:00"));
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let date: Vec<i64> = date.split('-').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let time: Vec<i64> = time.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    if date.len() != 3 || time.len() != 3 || fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None
+    }
+
+    let (year, month, day) = (date[0], date[1], date[2]);
+    let (hour, minute, second) = (time[0], time[1], time[2]);
+    let next_month = if month == 12 { days_from_civil(year + 1, 1, 1) } else { days_from_civil(year, month + 1, 1) };
+    let days_in_month = next_month - days_from_civil(year, month, 1);
+    if !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day)
+        || !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+        return None
+    }
+
+    let micros: i64 = format!("{:0<6}", fraction).parse().ok()?;
+    days_from_civil(year, month, day).checked_mul(MICROS_PER_DAY)?
+        .checked_add(((hour * 60 + minute) * 60 + second) * MICROS_PER_SECOND + micros)
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, after Howard Hinnant's
+// `days_from_civil`; `civil_from_days` is its inverse.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::storage::table::schema::DataType;
+    use crate::storage::table::table_error::TableError;
+    use crate::storage::table::value::{ArithmeticOp, CompareOp, Truth, Value};
+
+    #[test]
+    fn should_cast_values_between_types() {
+        // given
+        let price = Value::Decimal(-12_345, 3);
+        let launch = Value::Varchar("2024-02-29 13:05:09.25".to_string());
+
+        // when
+        let timestamp = launch.cast_to(DataType::Timestamp).unwrap();
+
+        // then
+        assert_eq!(price.to_string(), "-12.345");
+        assert_eq!(price.cast_to(DataType::Decimal(1)).unwrap(), Value::Decimal(-123, 1));
+        assert_eq!(price.cast_to(DataType::Integer).unwrap(), Value::Integer(-12));
+        assert_eq!(Value::Integer(7).cast_to(DataType::Decimal(2)).unwrap(), Value::Decimal(700, 2));
+        assert_eq!(Value::Varchar(" 0.5".to_string()).cast_to(DataType::Decimal(3)).unwrap(), Value::Decimal(500, 3));
+        assert_eq!(Value::Varchar("TRUE".to_string()).cast_to(DataType::Boolean).unwrap(), Value::Boolean(true));
+        assert_eq!(Value::Null.cast_to(DataType::BigInt).unwrap(), Value::Null);
+        assert_eq!(timestamp, Value::Timestamp(1_709_211_909_250_000));
+        assert_eq!(timestamp.to_string(), "2024-02-29 13:05:09.250000");
+        assert_eq!(timestamp.cast_to(DataType::Varchar(32)).unwrap().cast_to(DataType::Timestamp).unwrap(), timestamp);
+        assert_eq!(Value::Timestamp(-1).to_string(), "1969-12-31 23:59:59.999999");
+        let errors = [
+            Value::BigInt(i64::MAX).cast_to(DataType::Integer).err().unwrap(),
+            Value::Varchar("2023-02-29".to_string()).cast_to(DataType::Timestamp).err().unwrap(),
+            Value::BigInt(12_345).cast_to(DataType::Varchar(4)).err().unwrap(),
+            Value::Boolean(true).cast_to(DataType::Integer).err().unwrap(),
+        ];
+        for err in errors {
+            assert!(matches!(TableError::from_io(&err), Some(TableError::InvalidCast { .. })));
+        }
+    }
+
+    #[test]
+    fn should_compare_numbers_across_types_and_scales() {
+        // given
+        let half = Value::Decimal(5, 1);
+        let one = Value::Decimal(100, 2);
+
+        // when
+        let ordered = [
+            half.compare(&Value::Integer(1)).unwrap(),
+            one.compare(&Value::BigInt(1)).unwrap(),
+            Value::Integer(-3).compare(&Value::Decimal(-29, 1)).unwrap(),
+            Value::Null.compare(&half).unwrap(),
+        ];
+        let err = Value::Varchar("1".to_string()).compare(&Value::Integer(1)).err().unwrap();
+
+        // then
+        assert_eq!(ordered, [Ordering::Less, Ordering::Equal, Ordering::Less, Ordering::Less]);
+        assert!(matches!(TableError::from_io(&err), Some(TableError::NotComparable { .. })));
+    }
+
+    #[test]
+    fn should_evaluate_comparisons_with_null_as_unknown() {
+        // given
+        let one = Value::Integer(1);
+
+        // when
+        let truths = [
+            one.evaluate(CompareOp::LtEq, &Value::Decimal(15, 1)).unwrap(),
+            one.evaluate(CompareOp::NotEq, &Value::BigInt(1)).unwrap(),
+            one.evaluate(CompareOp::Eq, &Value::Null).unwrap(),
+            Value::Null.evaluate(CompareOp::Eq, &Value::Null).unwrap(),
+        ];
+
+        // then
+        assert_eq!(truths, [Truth::True, Truth::False, Truth::Unknown, Truth::Unknown]);
+        assert_eq!(Truth::Unknown.and(Truth::False), Truth::False);
+        assert_eq!(Truth::Unknown.and(Truth::True), Truth::Unknown);
+        assert_eq!(Truth::Unknown.or(Truth::True), Truth::True);
+        assert_eq!(!Truth::Unknown, Truth::Unknown);
+        assert!(!Truth::Unknown.is_true());
+    }
+
+    #[test]
+    fn should_do_checked_arithmetic_across_numeric_types() {
+        // given
+        let price = Value::Decimal(1999, 2);
+
+        // when
+        let results = [
+            Value::Integer(7).arithmetic(ArithmeticOp::Div, &Value::Integer(-2)).unwrap(),
+            Value::Integer(7).arithmetic(ArithmeticOp::Mod, &Value::BigInt(4)).unwrap(),
+            price.arithmetic(ArithmeticOp::Add, &Value::Integer(1)).unwrap(),
+            price.arithmetic(ArithmeticOp::Mul, &Value::Decimal(15, 1)).unwrap(),
+            Value::Integer(1).arithmetic(ArithmeticOp::Div, &Value::Decimal(300, 2)).unwrap(),
+            Value::Timestamp(5_000_000).arithmetic(ArithmeticOp::Sub, &Value::Timestamp(2_000_000)).unwrap(),
+            Value::Null.arithmetic(ArithmeticOp::Add, &price).unwrap(),
+        ];
+        let errors = [
+            Value::Integer(i32::MAX).arithmetic(ArithmeticOp::Add, &Value::Integer(1)).err().unwrap(),
+            Value::BigInt(1).arithmetic(ArithmeticOp::Div, &Value::Decimal(0, 2)).err().unwrap(),
+            Value::Varchar("1".to_string()).arithmetic(ArithmeticOp::Add, &Value::Integer(1)).err().unwrap(),
+        ];
+
+        // then
+        assert_eq!(results, [Value::Integer(-3), Value::BigInt(3), Value::Decimal(2099, 2), Value::Decimal(29985, 3),
+            Value::Decimal(33, 2), Value::BigInt(3_000_000), Value::Null]);
+        assert!(matches!(TableError::from_io(&errors[0]), Some(TableError::Overflow { op }) if op == "+"));
+        assert!(matches!(TableError::from_io(&errors[1]), Some(TableError::DivisionByZero)));
+        assert!(matches!(TableError::from_io(&errors[2]), Some(TableError::InvalidOperands { .. })));
+    }
+
+    #[test]
+    fn should_concat_measure_and_match_varchars() {
+        // given
+        let name = Value::Varchar("héloïse".to_string());
+
+        // when
+        let matches = ["h%se", "h_lo%", "%ï%", "%lo", "h_se"].map(|pattern| name.like(&Value::Varchar(pattern.to_string())).unwrap());
+
+        // then
+        assert_eq!(matches, [Truth::True, Truth::True, Truth::True, Truth::False, Truth::False]);
+        assert_eq!(name.like(&Value::Null).unwrap(), Truth::Unknown);
+        assert_eq!(name.char_length().unwrap(), Value::BigInt(7));
+        assert_eq!(name.concat(&Value::Varchar("!".to_string())).unwrap(), Value::Varchar("héloïse!".to_string()));
+        assert_eq!(name.concat(&Value::Null).unwrap(), Value::Null);
+        assert!(name.concat(&Value::Integer(1)).is_err());
+    }
+}
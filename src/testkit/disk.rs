@@ -0,0 +1,80 @@
+use std::io;
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+
+use crate::storage::disk::disk_manager::{DiskManager, FileDiskManager};
+use crate::storage::page::page::PageId;
+
+const DATA_FILE_NAME: &str = "minedb.data";
+
+/// `FileDiskManager` backed by a data file in a temporary directory, removed when dropped.
+pub struct TempFileDiskManager {
+    inner: FileDiskManager,
+    dir: TempDir,
+}
+
+impl TempFileDiskManager {
+    pub fn new() -> io::Result<TempFileDiskManager> {
+        let dir = tempfile::tempdir()?;
+        let inner = FileDiskManager::new(&dir.path().join(DATA_FILE_NAME))?;
+        Ok(TempFileDiskManager { inner, dir })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.path().join(DATA_FILE_NAME)
+    }
+}
+
+impl DiskManager for TempFileDiskManager {
+    fn allocate_page(&mut self) -> io::Result<PageId> {
+        self.inner.allocate_page()
+    }
+
+    fn deallocate_page(&mut self, page_id: PageId) -> io::Result<bool> {
+        self.inner.deallocate_page(page_id)
+    }
+
+    fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> io::Result<()> {
+        self.inner.write_page(page_id, page_data)
+    }
+
+    fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> io::Result<()> {
+        self.inner.read_page(page_id, page_data)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.inner.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::disk::disk_manager::DiskManager;
+    use crate::testkit::disk::TempFileDiskManager;
+    use crate::testkit::invariant::assert_disk_roundtrip;
+
+    #[test]
+    fn should_remove_data_file_when_dropped() {
+        // given
+        let mut dm = TempFileDiskManager::new().unwrap();
+        let path = dm.path();
+        assert!(path.exists());
+
+        // when
+        assert_disk_roundtrip(&mut dm, 3);
+        drop(dm);
+
+        // then
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn should_not_share_data_file_between_instances() {
+        let dm1 = TempFileDiskManager::new().unwrap();
+        let mut dm2 = TempFileDiskManager::new().unwrap();
+
+        assert_ne!(dm1.path(), dm2.path());
+        assert_eq!(dm2.allocate_page().unwrap(), 1);
+    }
+}
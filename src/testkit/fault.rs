@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+
+use crate::storage::disk::disk_manager::{AllocationStrategy, DiskManager, PageRelocations};
+use crate::storage::page::page::{PageId, PAGE_SIZE};
+
+/// What happens to a write picked by `FaultyDiskManager::fail_nth_write`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WriteFault {
+    /// The write fails without touching the page.
+    Fail,
+    /// Only the first bytes reach the page, the rest keeps its old content, and the write fails.
+    /// The wrapped manager stores the torn page as a whole, so checksums it keeps match it.
+    Torn { written: usize },
+    /// Panics before writing, like a crash at that point.
+    Crash,
+}
+
+/// Disk manager wrapper injecting faults into writes and syncs of `D`, so error and recovery paths
+/// can be tested without a mock written per case.
+///
+/// Writes are counted from 1, with every page of a batch write counting as one.
+pub struct FaultyDiskManager<D: DiskManager> {
+    inner: D,
+    writes: usize,
+    write_faults: BTreeMap<usize, WriteFault>,
+    fail_syncs: bool,
+}
+
+impl<D: DiskManager> FaultyDiskManager<D> {
+    pub fn new(inner: D) -> FaultyDiskManager<D> {
+        FaultyDiskManager {
+            inner,
+            writes: 0,
+            write_faults: BTreeMap::new(),
+            fail_syncs: false,
+        }
+    }
+
+    /// The `nth` write from now on, 1 being the next one, hits `fault`.
+    pub fn fail_nth_write(&mut self, nth: usize, fault: WriteFault) -> &mut FaultyDiskManager<D> {
+        self.write_faults.insert(self.writes + nth, fault);
+        self
+    }
+
+    pub fn fail_syncs(&mut self, fail: bool) -> &mut FaultyDiskManager<D> {
+        self.fail_syncs = fail;
+        self
+    }
+
+    /// Writes seen so far, faulty ones included.
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn write_torn(&mut self, page_id: PageId, page_data: &[u8], written: usize) -> io::Result<()> {
+        let written = written.min(page_data.len());
+        let mut torn = [0u8; PAGE_SIZE];
+        self.inner.read_page(page_id, &mut torn)?;
+        torn[..written].copy_from_slice(&page_data[..written]);
+        self.inner.write_page(page_id, &torn)?;
+        Err(io::Error::new(io::ErrorKind::WriteZero, format!("Injected torn write of page {}.", page_id)))
+    }
+}
+
+impl<D: DiskManager> DiskManager for FaultyDiskManager<D> {
+    fn allocate_page(&mut self) -> io::Result<PageId> {
+        self.inner.allocate_page()
+    }
+
+    fn allocate_page_with(&mut self, strategy: AllocationStrategy) -> io::Result<PageId> {
+        self.inner.allocate_page_with(strategy)
+    }
+
+    fn allocate_extent(&mut self, n: usize) -> io::Result<Range<PageId>> {
+        self.inner.allocate_extent(n)
+    }
+
+    fn deallocate_page(&mut self, page_id: PageId) -> io::Result<bool> {
+        self.inner.deallocate_page(page_id)
+    }
+
+    fn write_page(&mut self, page_id: PageId, page_data: &[u8]) -> io::Result<()> {
+        self.writes += 1;
+        match self.write_faults.remove(&self.writes) {
+            None => self.inner.write_page(page_id, page_data),
+            Some(WriteFault::Fail) => Err(io::Error::other(format!("Injected failure writing page {}.", page_id))),
+            Some(WriteFault::Torn { written }) => self.write_torn(page_id, page_data, written),
+            Some(WriteFault::Crash) => panic!("Injected crash before writing page {}.", page_id),
+        }
+    }
+
+    fn read_page(&mut self, page_id: PageId, page_data: &mut [u8]) -> io::Result<()> {
+        self.inner.read_page(page_id, page_data)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        if self.fail_syncs {
+            return Err(io::Error::other("Injected sync failure."))
+        }
+        self.inner.sync()
+    }
+
+    fn defragment(&mut self) -> io::Result<PageRelocations> {
+        self.inner.defragment()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer::replacer::ClockReplacer;
+    use crate::storage::disk::disk_manager::{DiskManager, FakeDiskManager};
+    use crate::storage::page::page::PAGE_SIZE;
+    use crate::testkit::fault::{FaultyDiskManager, WriteFault};
+
+    #[test]
+    fn should_fail_and_tear_picked_writes_only() {
+        // given
+        let mut dm = FaultyDiskManager::new(FakeDiskManager::new());
+        let pid = dm.allocate_page().unwrap();
+        dm.fail_nth_write(2, WriteFault::Fail)
+            .fail_nth_write(3, WriteFault::Torn { written: 10 });
+
+        // when
+        let first = dm.write_page(pid, &[1; PAGE_SIZE]);
+        let failed = dm.write_page(pid, &[2; PAGE_SIZE]);
+        let torn = dm.write_page(pid, &[3; PAGE_SIZE]);
+
+        // then
+        assert!(first.is_ok());
+        assert!(failed.is_err());
+        assert_eq!(torn.unwrap_err().kind(), std::io::ErrorKind::WriteZero);
+        let mut page_data = [0u8; PAGE_SIZE];
+        dm.read_page(pid, &mut page_data).unwrap();
+        assert_eq!(page_data[..10], [3; 10]);
+        assert_eq!(page_data[10..], [1; PAGE_SIZE - 10]);
+        assert!(dm.write_page(pid, &[4; PAGE_SIZE]).is_ok());
+        assert_eq!(dm.writes(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Injected crash")]
+    fn should_panic_at_crash_point() {
+        let mut dm = FaultyDiskManager::new(FakeDiskManager::new());
+        let pid = dm.allocate_page().unwrap();
+        dm.fail_nth_write(1, WriteFault::Crash);
+
+        dm.write_page(pid, &[1; PAGE_SIZE]).unwrap();
+    }
+
+    #[test]
+    fn should_keep_page_dirty_in_pool_when_flush_fails() {
+        // given
+        let mut dm = FaultyDiskManager::new(FakeDiskManager::new());
+        dm.fail_nth_write(1, WriteFault::Fail);
+        let mut bpm = BufferPoolManager::new(2, Box::new(ClockReplacer::new(2)), Box::new(dm));
+        let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+        bpm.unpin_page(pid, true).unwrap();
+
+        // when
+        let failed = bpm.flush_page(pid);
+
+        // then
+        assert!(failed.is_err());
+        assert!(bpm.fetch_page(pid).unwrap().read().unwrap().is_dirty());
+        bpm.unpin_page(pid, false).unwrap();
+        assert!(bpm.flush_page(pid).is_ok());
+    }
+}
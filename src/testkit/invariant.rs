@@ -0,0 +1,55 @@
+use std::fmt::Debug;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::common::hash::HashKeyType;
+use crate::common::ValueType;
+use crate::container::hash::hash_table::HashTable;
+use crate::storage::disk::disk_manager::DiskManager;
+use crate::storage::page::page::PAGE_SIZE;
+
+/// Panics unless every pair can be read back from the table.
+pub fn assert_all_findable<K, V, T>(table: &mut T, pairs: &[(K, V)])
+    where
+        K: HashKeyType + Debug,
+        V: ValueType + Debug,
+        T: HashTable<K, V>,
+{
+    for (k, v) in pairs {
+        let values = table.get_value(k).unwrap();
+        assert!(values.contains(v), "value {:?} of key {:?} not found, got {:?}", v, k, values);
+    }
+}
+
+/// Panics if any of the keys still has a value in the table.
+pub fn assert_none_findable<K, V, T>(table: &mut T, keys: &[K])
+    where
+        K: HashKeyType + Debug,
+        V: ValueType + Debug,
+        T: HashTable<K, V>,
+{
+    for k in keys {
+        let values = table.get_value(k).unwrap();
+        assert!(values.is_empty(), "key {:?} should be absent, got {:?}", k, values);
+    }
+}
+
+/// Allocates `num_pages` pages, fills them with distinct data and panics unless each reads back unchanged.
+pub fn assert_disk_roundtrip(dm: &mut dyn DiskManager, num_pages: usize) {
+    let mut rng = StdRng::seed_from_u64(num_pages as u64);
+    let mut written = Vec::with_capacity(num_pages);
+    for _ in 0..num_pages {
+        let pid = dm.allocate_page().unwrap();
+        let mut data = [0u8; PAGE_SIZE];
+        rng.fill(&mut data[..]);
+        dm.write_page(pid, &data).unwrap();
+        written.push((pid, data));
+    }
+
+    let mut read = [0u8; PAGE_SIZE];
+    for (pid, data) in written.iter() {
+        dm.read_page(*pid, &mut read).unwrap();
+        assert!(read[..] == data[..], "page {} read back different data", pid);
+    }
+}
@@ -0,0 +1,91 @@
+use std::convert::TryInto;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+use crate::common::hash::HashKeyType;
+use crate::common::ValueType;
+
+#[derive(Hash, Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FakeKey {
+    pub data: [u8; 10],
+}
+
+impl HashKeyType for FakeKey {}
+
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FakeValue {
+    pub data: [u8; 20],
+}
+
+impl ValueType for FakeValue {}
+
+/// Hash that returns the number a key was built from, so tests control which slot a key lands in.
+pub fn identity_hash(key: &FakeKey) -> u64 {
+    u64::from_le_bytes(key.data[0..8].try_into().unwrap())
+}
+
+/// Key and value holding the little-endian bytes of `k` and `v`.
+pub fn build_kv(k: u64, v: u64) -> (FakeKey, FakeValue) {
+    let mut key = FakeKey::default();
+    key.data[0..8].copy_from_slice(&k.to_le_bytes());
+
+    let mut val = FakeValue::default();
+    val.data[0..8].copy_from_slice(&v.to_le_bytes());
+
+    (key, val)
+}
+
+/// Seeded source of key-value pairs, so a failing test replays the same data.
+pub struct KvGenerator {
+    rng: StdRng,
+}
+
+impl KvGenerator {
+    pub fn new(seed: u64) -> KvGenerator {
+        KvGenerator {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn next_kv(&mut self) -> (FakeKey, FakeValue) {
+        build_kv(self.rng.gen(), self.rng.gen())
+    }
+
+    /// `n` pairs whose keys are drawn from `0..key_range`, so a small range forces collisions.
+    pub fn kvs_in_range(&mut self, n: usize, key_range: u64) -> Vec<(FakeKey, FakeValue)> {
+        (0..n)
+            .map(|_| build_kv(self.rng.gen_range(0..key_range), self.rng.gen()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testkit::kv::{build_kv, identity_hash, KvGenerator};
+
+    #[test]
+    fn should_hash_key_to_the_number_it_was_built_from() {
+        let (key, val) = build_kv(300, 7);
+
+        assert_eq!(identity_hash(&key), 300);
+        assert_eq!(val.data[0], 7);
+    }
+
+    #[test]
+    fn should_generate_same_kvs_for_same_seed() {
+        let mut gen1 = KvGenerator::new(42);
+        let mut gen2 = KvGenerator::new(42);
+
+        assert_eq!(gen1.kvs_in_range(16, 4), gen2.kvs_in_range(16, 4));
+        assert_eq!(gen1.next_kv(), gen2.next_kv());
+    }
+
+    #[test]
+    fn should_generate_keys_in_range() {
+        let kvs = KvGenerator::new(7).kvs_in_range(100, 3);
+
+        assert!(kvs.iter().all(|(k, _)| identity_hash(k) < 3));
+    }
+}
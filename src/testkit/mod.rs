@@ -0,0 +1,8 @@
+//! Fixtures for writing tests against minedb.
+//!
+//! Compiled for this crate's own tests and, for downstream crates, behind the `testkit` feature.
+pub mod disk;
+pub mod pool;
+pub mod kv;
+pub mod invariant;
+pub mod fault;
@@ -0,0 +1,40 @@
+use std::io;
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::buffer::replacer::ClockReplacer;
+use crate::storage::disk::disk_manager::FakeDiskManager;
+use crate::testkit::disk::TempFileDiskManager;
+
+/// Small enough that eviction paths are exercised by a handful of pages.
+pub const SMALL_POOL_SIZE: usize = 8;
+
+/// In-memory pool: page ids are handed out sequentially from 0, so tests can rely on them.
+pub fn small_pool(pool_size: usize) -> BufferPoolManager {
+    BufferPoolManager::new(
+        pool_size,
+        Box::new(ClockReplacer::new(pool_size)),
+        Box::new(FakeDiskManager::new()))
+}
+
+/// Pool over a temporary data file, which lives as long as the pool does.
+pub fn file_backed_pool(pool_size: usize) -> io::Result<BufferPoolManager> {
+    Ok(BufferPoolManager::new(
+        pool_size,
+        Box::new(ClockReplacer::new(pool_size)),
+        Box::new(TempFileDiskManager::new()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testkit::pool::{small_pool, SMALL_POOL_SIZE};
+
+    #[test]
+    fn should_allocate_sequential_page_ids() {
+        let mut bpm = small_pool(SMALL_POOL_SIZE);
+
+        for expected in 0..SMALL_POOL_SIZE {
+            let pid = bpm.new_page().unwrap().read().unwrap().get_id();
+            assert_eq!(pid, expected);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Drives the example applications end to end, through the same functions their `main` calls.
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use minedb::buffer::buffer_pool_manager::BufferPoolManager;
+use minedb::buffer::replacer::ClockReplacer;
+use minedb::storage::disk::disk_manager::FileDiskManager;
+
+#[path = "../examples/url_shortener.rs"]
+#[allow(dead_code)]
+mod url_shortener;
+
+#[path = "../examples/analytics.rs"]
+#[allow(dead_code)]
+mod analytics;
+
+#[path = "../examples/crash_recovery.rs"]
+#[allow(dead_code)]
+mod crash_recovery;
+
+/// Set for the writer process `should_recover_flushed_rows_after_writer_is_killed` spawns.
+const CRASH_PATH_VAR: &str = "MINEDB_CRASH_RECOVERY_PATH";
+
+#[test]
+fn should_shorten_urls_and_expand_their_codes() {
+    // given
+    let dir = tempfile::tempdir().unwrap();
+    let disk_manager = FileDiskManager::new(&dir.path().join("urls.data")).unwrap();
+    let bpm = BufferPoolManager::new(16, Box::new(ClockReplacer::new(16)), Box::new(disk_manager));
+    let mut shortener = url_shortener::Shortener::new(Arc::new(Mutex::new(bpm))).unwrap();
+    let urls: Vec<String> = (0..50).map(|i| format!("https://minedb.dev/{}", i)).collect();
+
+    // when
+    let codes: Vec<String> = urls.iter().map(|url| shortener.shorten(url).unwrap()).collect();
+
+    // then
+    for (url, code) in urls.iter().zip(codes.iter()) {
+        assert_eq!(shortener.expand(code).unwrap().as_ref(), Some(url));
+    }
+}
+
+#[test]
+fn should_report_views_and_revenue_per_page() {
+    // given
+    let dir = tempfile::tempdir().unwrap();
+
+    // when
+    let report = analytics::run(&dir.path().join("analytics.db"), 500).unwrap();
+
+    // then
+    assert_eq!(report.events, 500);
+    assert_eq!(report.pages.len(), analytics::PAGES.len());
+    assert_eq!(report.pages.values().map(|summary| summary.views).sum::<u64>(), 500);
+}
+
+#[test]
+fn should_recover_flushed_rows_after_writer_is_killed() {
+    // given
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("crash.db");
+    let mut writer = Command::new(env::current_exe().unwrap());
+    writer.args(["crash_recovery_writer", "--exact", "--ignored", "--nocapture"]).env(CRASH_PATH_VAR, &path);
+
+    // when
+    let recovery = crash_recovery::crash_and_recover(writer, &path, 300).unwrap();
+
+    // then
+    assert!(recovery.flushed >= 300);
+    assert!(recovery.recovered >= recovery.flushed);
+    assert_eq!(recovery.recovered % crash_recovery::BATCH_ROWS, 0);
+}
+
+/// Writer process of `should_recover_flushed_rows_after_writer_is_killed`, doing nothing when run on its own.
+#[test]
+#[ignore]
+fn crash_recovery_writer() {
+    if let Some(path) = env::var_os(CRASH_PATH_VAR) {
+        crash_recovery::write_until_killed(Path::new(&path)).unwrap();
+    }
+}